@@ -0,0 +1,41 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Embed the git commit and build time, reported by the `/version` endpoint
+///
+/// `GIT_SHA` overrides the commit for builds without a `.git` directory (e.g. Docker
+/// contexts), `SOURCE_DATE_EPOCH` the build time for reproducible builds.
+fn main() {
+  println!("cargo:rerun-if-env-changed=GIT_SHA");
+  println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+  println!("cargo:rerun-if-changed=.git/HEAD");
+  println!("cargo:rerun-if-changed=.git/refs");
+
+  let git_sha = std::env::var("GIT_SHA")
+    .ok()
+    .filter(|sha| !sha.is_empty())
+    .or_else(|| {
+      let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+      output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    })
+    .unwrap_or_else(|| "unknown".to_string());
+
+  let build_epoch = std::env::var("SOURCE_DATE_EPOCH")
+    .ok()
+    .and_then(|epoch| epoch.parse::<u64>().ok())
+    .unwrap_or_else(|| {
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+    });
+
+  println!("cargo:rustc-env=NX_CACHE_GIT_SHA={}", git_sha);
+  println!("cargo:rustc-env=NX_CACHE_BUILD_EPOCH={}", build_epoch);
+}
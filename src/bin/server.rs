@@ -1,8 +1,11 @@
 use clap::Parser;
 use nx_cache_server::domain::config::Config;
 use nx_cache_server::infra::multi_storage::MultiStorageRouter;
-use nx_cache_server::server::run_server;
+use nx_cache_server::server::recent_errors::{RecentErrors, RecentErrorsLayer};
+use nx_cache_server::server::ServerBuilder;
 use std::path::PathBuf;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 
 #[derive(Parser)]
 #[command(name = "nx-cache-server")]
@@ -18,6 +21,48 @@ struct Cli {
 
   #[arg(long, env = "DEBUG", help = "Enable debug logging")]
   debug: bool,
+
+  #[arg(
+    long,
+    help = "Compare configured namespaces with the prefixes present in the buckets, print the findings and exit"
+  )]
+  audit_namespaces: bool,
+
+  #[cfg(feature = "tokio-console")]
+  #[arg(
+    long,
+    env = "TOKIO_CONSOLE",
+    help = "Serve tokio-console instrumentation (default address 127.0.0.1:6669)"
+  )]
+  tokio_console: bool,
+}
+
+/// Initialize logging, optionally alongside the tokio-console instrumentation layer
+///
+/// Errors are also fed into the ring buffer served at `/admin/errors`.
+fn init_tracing(cli: &Cli) {
+  let level = if cli.debug {
+    LevelFilter::DEBUG
+  } else {
+    LevelFilter::INFO
+  };
+  let recent_errors = RecentErrorsLayer::new(RecentErrors::global().clone());
+
+  #[cfg(feature = "tokio-console")]
+  if cli.tokio_console {
+    tracing_subscriber::registry()
+      .with(console_subscriber::spawn())
+      .with(tracing_subscriber::fmt::layer().with_filter(level))
+      .with(recent_errors)
+      .init();
+    tracing::info!("tokio-console instrumentation enabled");
+    return;
+  }
+
+  tracing_subscriber::registry()
+    .with(tracing_subscriber::fmt::layer().with_filter(level))
+    .with(recent_errors)
+    .init();
 }
 
 #[tokio::main]
@@ -25,13 +70,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let cli = Cli::parse();
 
   // Initialize logging
-  if cli.debug {
-    tracing_subscriber::fmt()
-      .with_max_level(tracing::Level::DEBUG)
-      .init();
-  } else {
-    tracing_subscriber::fmt::init();
-  }
+  init_tracing(&cli);
 
   tracing::info!("Loading configuration from: {}", cli.config_file.display());
 
@@ -104,9 +143,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     std::process::exit(1);
   }
 
+  if cli.audit_namespaces {
+    let findings = storage.audit_namespaces(&resolved_config).await;
+    for finding in &findings {
+      println!("{}", finding);
+    }
+    println!("{} finding(s)", findings.len());
+    std::process::exit(if findings.is_empty() { 0 } else { 2 });
+  }
+
   // Run server
   tracing::info!("Server starting on port {}", resolved_config.port);
-  if let Err(e) = run_server(storage, &resolved_config).await {
+  let server = ServerBuilder::new(storage, &resolved_config).with_config_file(&cli.config_file);
+  if let Err(e) = server.run().await {
     eprintln!();
     eprintln!("Server error: {}", e);
     std::process::exit(1);
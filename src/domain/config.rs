@@ -1,10 +1,14 @@
 use base64::engine::general_purpose;
 use base64::Engine as _;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
+use crate::domain::http_date;
+use crate::domain::migration::{self, KeyStyle};
+use crate::domain::token_hash;
+
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
   #[error("Failed to read config file: {0}")]
@@ -19,6 +23,8 @@ pub enum ConfigError {
   Validation(String),
   #[error("Environment variable not found: {0}")]
   EnvVarNotFound(String),
+  #[error("Configuration migration error: {0}")]
+  Migration(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -29,126 +35,1486 @@ pub enum SseType {
   SseC,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum KmsContext {
-  Map(HashMap<String, String>),
-  JsonString(String),
+/// S3 storage class for uploaded objects
+///
+/// Archive classes (GLACIER, DEEP_ARCHIVE) are not offered: their objects cannot be read back
+/// without a restore, which makes them useless for a cache.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StorageClass {
+  Standard,
+  StandardIa,
+  OnezoneIa,
+  IntelligentTiering,
+  ReducedRedundancy,
+  GlacierIr,
+  ExpressOnezone,
+}
+
+impl StorageClass {
+  /// Value of the `x-amz-storage-class` header
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::Standard => "STANDARD",
+      Self::StandardIa => "STANDARD_IA",
+      Self::OnezoneIa => "ONEZONE_IA",
+      Self::IntelligentTiering => "INTELLIGENT_TIERING",
+      Self::ReducedRedundancy => "REDUCED_REDUNDANCY",
+      Self::GlacierIr => "GLACIER_IR",
+      Self::ExpressOnezone => "EXPRESS_ONEZONE",
+    }
+  }
+}
+
+/// S3 `x-amz-server-side-encryption` values accepted as a shorthand for the `sse` block
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ServerSideEncryption {
+  #[serde(rename = "AES256")]
+  Aes256,
+  #[serde(rename = "aws:kms")]
+  AwsKms,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KmsContext {
+  Map(HashMap<String, String>),
+  JsonString(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SseConfig {
+  #[serde(rename = "type")]
+  pub sse_type: SseType,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kms_key_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kms_key_id_env: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kms_context: Option<KmsContext>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kms_context_env: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub customer_key_base64: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub customer_key_base64_env: Option<String>,
+}
+
+/// Adaptive (AIMD) concurrency limits for storage operations on a bucket
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConcurrencyConfig {
+  /// Concurrency limit at startup
+  #[serde(default = "default_initial_limit")]
+  pub initial_limit: usize,
+
+  /// Lower bound the limit never shrinks below
+  #[serde(default = "default_min_limit")]
+  pub min_limit: usize,
+
+  /// Upper bound the limit never grows beyond
+  #[serde(default = "default_max_limit")]
+  pub max_limit: usize,
+
+  /// Operations slower than this (in milliseconds) shrink the limit
+  #[serde(default = "default_latency_threshold_ms")]
+  pub latency_threshold_ms: u64,
+
+  /// Multiplier applied to the limit on slow or failed operations
+  #[serde(default = "default_backoff_ratio")]
+  pub backoff_ratio: f64,
+}
+
+fn default_initial_limit() -> usize {
+  20
+}
+
+fn default_min_limit() -> usize {
+  1
+}
+
+fn default_max_limit() -> usize {
+  200
+}
+
+fn default_latency_threshold_ms() -> u64 {
+  1000
+}
+
+fn default_backoff_ratio() -> f64 {
+  0.9
+}
+
+/// Local disk tier in front of a bucket: writes land on disk and are uploaded in the background
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalTierConfig {
+  /// Directory holding the tier's files
+  pub path: String,
+
+  /// Keep files on disk after they were uploaded so reads are served locally (defaults to true)
+  #[serde(default = "default_true")]
+  pub retain_after_upload: bool,
+}
+
+fn default_true() -> bool {
+  true
+}
+
+/// Spill large upload bodies to a temporary file instead of buffering them in memory
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SpillConfig {
+  /// Bodies larger than this, or of unknown length, are written to disk first (defaults to 8 MiB)
+  #[serde(default = "default_spill_threshold_bytes")]
+  pub threshold_bytes: u64,
+
+  /// Directory for the temporary files (optional, the system temp directory if not set)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub path: Option<String>,
+}
+
+fn default_spill_threshold_bytes() -> u64 {
+  8 * 1024 * 1024
+}
+
+impl Default for SpillConfig {
+  fn default() -> Self {
+    Self {
+      threshold_bytes: default_spill_threshold_bytes(),
+      path: None,
+    }
+  }
+}
+
+/// Download large objects as parallel ranged GETs stitched back together in order
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RangedReadsConfig {
+  /// Size of each ranged GET (defaults to 8 MiB); smaller objects are fetched in one request
+  #[serde(default = "default_ranged_part_size_bytes")]
+  pub part_size_bytes: u64,
+
+  /// Ranges fetched ahead of the client at the same time (defaults to 4)
+  #[serde(default = "default_ranged_concurrency")]
+  pub concurrency: usize,
+}
+
+/// Smaller parts spend more time on request latency than they save
+const MIN_RANGED_PART_SIZE_BYTES: u64 = 1024 * 1024;
+
+fn default_ranged_part_size_bytes() -> u64 {
+  8 * 1024 * 1024
+}
+
+/// Run a bucket's storage operations on a dedicated tokio runtime
+///
+/// A backend with slow or blocking SDK calls then only occupies its own worker threads
+/// instead of starving the handlers serving other buckets.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IsolationConfig {
+  /// Worker threads of the bucket's runtime (defaults to 2)
+  #[serde(default = "default_isolation_worker_threads")]
+  pub worker_threads: usize,
+}
+
+fn default_isolation_worker_threads() -> usize {
+  2
+}
+
+fn default_ranged_concurrency() -> usize {
+  4
+}
+
+/// Per-stage timeouts applied during an orderly shutdown
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShutdownConfig {
+  /// Maximum time (in seconds) to wait for pending background storage work
+  #[serde(default = "default_drain_timeout_seconds")]
+  pub drain_timeout_seconds: u64,
+
+  /// Maximum time (in seconds) to wait while flushing in-memory stats
+  #[serde(default = "default_flush_timeout_seconds")]
+  pub flush_timeout_seconds: u64,
+}
+
+impl Default for ShutdownConfig {
+  fn default() -> Self {
+    Self {
+      drain_timeout_seconds: default_drain_timeout_seconds(),
+      flush_timeout_seconds: default_flush_timeout_seconds(),
+    }
+  }
+}
+
+fn default_drain_timeout_seconds() -> u64 {
+  60
+}
+
+fn default_flush_timeout_seconds() -> u64 {
+  5
+}
+
+/// `Cache-Control` sent with downloaded artifacts, which never change once stored
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheControlConfig {
+  /// Seconds clients and proxies may reuse a downloaded artifact (0 disables the header)
+  #[serde(default = "default_cache_max_age_seconds")]
+  pub max_age_seconds: u64,
+
+  /// Mark downloads `public` instead of `private`, letting shared caches such as CDNs store
+  /// them; only enable when the shared cache checks the token itself
+  #[serde(default)]
+  pub public: bool,
+}
+
+impl Default for CacheControlConfig {
+  fn default() -> Self {
+    Self {
+      max_age_seconds: default_cache_max_age_seconds(),
+      public: false,
+    }
+  }
+}
+
+fn default_cache_max_age_seconds() -> u64 {
+  31_536_000
+}
+
+/// Read-only mirror for compiler caches (ccache, sccache) served under `/mirror/{key}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MirrorConfig {
+  /// Bucket the mirror reads from; may be shared with Nx tokens
+  pub bucket: String,
+
+  /// Key prefix inside the bucket
+  #[serde(default)]
+  pub prefix: String,
+
+  /// Require a service access token (defaults to true); only disable on private networks
+  #[serde(default = "default_true")]
+  pub require_auth: bool,
+
+  /// How long (in seconds) a miss is remembered before the bucket is asked again
+  #[serde(default = "default_negative_cache_ttl_seconds")]
+  pub negative_cache_ttl_seconds: u64,
+
+  /// Maximum number of remembered misses
+  #[serde(default = "default_negative_cache_max_entries")]
+  pub negative_cache_max_entries: usize,
+}
+
+fn default_negative_cache_ttl_seconds() -> u64 {
+  300
+}
+
+fn default_negative_cache_max_entries() -> usize {
+  100_000
+}
+
+/// Server replicas that hashes are spread across with rendezvous hashing
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AffinityConfig {
+  /// Base URLs of all replicas, including this one
+  pub peers: Vec<String>,
+}
+
+/// Task metadata (duration, project, target) recorded alongside artifacts for build analytics
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskMetadataConfig {
+  /// Largest accepted metadata document in bytes (defaults to 16 KiB)
+  #[serde(default = "default_task_metadata_max_bytes")]
+  pub max_bytes: usize,
+
+  /// Most records returned by one admin query (defaults to 1000)
+  #[serde(default = "default_task_metadata_max_results")]
+  pub max_results: usize,
+}
+
+fn default_task_metadata_max_bytes() -> usize {
+  16 * 1024
+}
+
+fn default_task_metadata_max_results() -> usize {
+  1000
+}
+
+/// Resumable uploads following the tus protocol
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TusConfig {
+  /// Directory for partial uploads (optional, `nx-cache-tus` in the system temp directory if
+  /// not set)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub path: Option<String>,
+
+  /// Largest accepted upload in bytes (defaults to 10 GiB)
+  #[serde(default = "default_tus_max_size_bytes")]
+  pub max_size_bytes: u64,
+
+  /// Seconds an unfinished upload is kept after its last change (defaults to 24 hours)
+  #[serde(default = "default_tus_expiration_seconds")]
+  pub expiration_seconds: u64,
+}
+
+fn default_tus_max_size_bytes() -> u64 {
+  10 * 1024 * 1024 * 1024
+}
+
+fn default_tus_expiration_seconds() -> u64 {
+  24 * 60 * 60
+}
+
+/// gRPC listener serving the Bazel remote execution API's caching services
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GrpcConfig {
+  /// Port of the gRPC listener (defaults to 9092)
+  #[serde(default = "default_grpc_port")]
+  pub port: u16,
+}
+
+fn default_grpc_port() -> u16 {
+  9092
+}
+
+/// Verification of JWT bearer tokens, which are mapped to a service token by their claims
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JwtConfig {
+  /// Shared secret of HS256, HS384 or HS512 signed tokens
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub secret: Option<String>,
+
+  /// Environment variable name holding the shared secret
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub secret_env: Option<String>,
+
+  /// URL of the JSON Web Key Set of RS256, PS256 or ES256 signed tokens
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub jwks_url: Option<String>,
+
+  /// Required `iss` claim (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub issuer: Option<String>,
+
+  /// Required `aud` claim (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub audience: Option<String>,
+
+  /// Clock skew in seconds tolerated when checking `exp` and `nbf`
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+
+  /// How long (in seconds) keys fetched from `jwksUrl` are cached
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+
+  /// Claim rules in order; the first one matching decides the service token
+  pub rules: Vec<JwtRuleConfig>,
+}
+
+fn default_jwt_leeway_seconds() -> u64 {
+  60
+}
+
+fn default_jwks_cache_seconds() -> u64 {
+  3600
+}
+
+/// Verification of OIDC ID tokens from an identity provider found through issuer discovery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcConfig {
+  /// Issuer URL; keys are found through `{issuer}/.well-known/openid-configuration`
+  pub issuer: String,
+
+  /// Required `aud` claim, usually the client ID registered with the provider
+  pub audience: String,
+
+  /// Clock skew in seconds tolerated when checking `exp` and `nbf`
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+
+  /// How long (in seconds) the provider's keys are cached
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+
+  /// Claim rules in order; the first one matching decides the service token
+  pub rules: Vec<JwtRuleConfig>,
+}
+
+/// Verification of GitHub Actions OIDC tokens, so workflows need no stored secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubActionsConfig {
+  /// Token issuer, to be changed only for GitHub Enterprise Server
+  #[serde(default = "default_github_actions_issuer")]
+  pub issuer: String,
+
+  /// Required `aud` claim, as requested by the workflow (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub audience: Option<String>,
+
+  /// Clock skew in seconds tolerated when checking `exp` and `nbf`
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+
+  /// How long (in seconds) GitHub's keys are cached
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+
+  /// Repository rules in order; the first one matching decides the service token
+  pub rules: Vec<GithubActionsRuleConfig>,
+}
+
+fn default_github_actions_issuer() -> String {
+  "https://token.actions.githubusercontent.com".to_string()
+}
+
+/// Service token the workflows of a repository get, optionally only for some refs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubActionsRuleConfig {
+  /// Name of the service token whose bucket, prefix and permissions apply
+  pub token: String,
+
+  /// Repository as `owner/name`; may contain `*` wildcards
+  pub repository: String,
+
+  /// Git ref the workflow runs for, e.g. `refs/heads/main` (any when absent)
+  #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+  pub git_ref: Option<String>,
+
+  /// Further claims the token must carry, e.g. `environment` or `event_name`
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub claims: BTreeMap<String, String>,
+}
+
+impl GithubActionsConfig {
+  /// The rules as claim rules of the generic JWT verifier
+  pub fn jwt_rules(&self) -> Vec<JwtRuleConfig> {
+    self
+      .rules
+      .iter()
+      .map(|rule| {
+        let mut claims = rule.claims.clone();
+        claims.insert("repository".to_string(), rule.repository.clone());
+        if let Some(git_ref) = &rule.git_ref {
+          claims.insert("ref".to_string(), git_ref.clone());
+        }
+        JwtRuleConfig {
+          token: rule.token.clone(),
+          claims,
+        }
+      })
+      .collect()
+  }
+}
+
+/// Verification of GitLab CI job JWTs, so pipelines need no token in their CI variables
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitlabConfig {
+  /// URL of the GitLab instance, which issues and signs the job tokens
+  #[serde(default = "default_gitlab_url")]
+  pub url: String,
+
+  /// Required `aud` claim, as configured in the job's `id_tokens` (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub audience: Option<String>,
+
+  /// Clock skew in seconds tolerated when checking `exp` and `nbf`
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+
+  /// How long (in seconds) the instance's keys are cached
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+
+  /// Project rules in order; the first one matching decides the service token
+  pub rules: Vec<GitlabRuleConfig>,
+}
+
+fn default_gitlab_url() -> String {
+  "https://gitlab.com".to_string()
+}
+
+/// Service token the jobs of a project get, optionally only for some refs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitlabRuleConfig {
+  /// Name of the service token whose bucket, prefix and permissions apply
+  pub token: String,
+
+  /// Project as `group/project`; may contain `*` wildcards
+  pub project_path: String,
+
+  /// Branch or tag the job runs for, e.g. `main` (any when absent)
+  #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+  pub git_ref: Option<String>,
+
+  /// Further claims the token must carry, e.g. `ref_protected` or `environment`
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub claims: BTreeMap<String, String>,
+}
+
+impl GitlabConfig {
+  /// The rules as claim rules of the generic JWT verifier
+  pub fn jwt_rules(&self) -> Vec<JwtRuleConfig> {
+    self
+      .rules
+      .iter()
+      .map(|rule| {
+        let mut claims = rule.claims.clone();
+        claims.insert("project_path".to_string(), rule.project_path.clone());
+        if let Some(git_ref) = &rule.git_ref {
+          claims.insert("ref".to_string(), git_ref.clone());
+        }
+        JwtRuleConfig {
+          token: rule.token.clone(),
+          claims,
+        }
+      })
+      .collect()
+  }
+}
+
+/// Issuer of the tokens the server mints itself
+pub const MINTED_TOKEN_ISSUER: &str = "nx-cache-server";
+
+/// Admin endpoint minting short-lived tokens derived from a service token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenMintingConfig {
+  /// Secret used to sign minted tokens
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signing_key: Option<String>,
+
+  /// Environment variable name holding the signing secret
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signing_key_env: Option<String>,
+
+  /// Lifetime (in seconds) of minted tokens when the request names none
+  #[serde(default = "default_minted_ttl_seconds")]
+  pub default_ttl_seconds: u64,
+
+  /// Longest lifetime (in seconds) a minted token can be given
+  #[serde(default = "default_minted_max_ttl_seconds")]
+  pub max_ttl_seconds: u64,
+}
+
+fn default_minted_ttl_seconds() -> u64 {
+  3600
+}
+
+fn default_minted_max_ttl_seconds() -> u64 {
+  86400
+}
+
+/// Admin endpoint revoking tokens at runtime, with the revocations kept in a file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRevocationConfig {
+  /// JSON file the revoked token names are stored in, created on the first revocation
+  pub path: String,
+}
+
+/// Service token a JWT stands for if it carries the given claims
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JwtRuleConfig {
+  /// Name of the service token whose bucket, prefix and permissions apply
+  pub token: String,
+
+  /// Claims the JWT must carry, by name; values may contain `*` wildcards
+  #[serde(default)]
+  pub claims: BTreeMap<String, String>,
+}
+
+/// On-the-fly `Content-Encoding` of downloads for clients that accept it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportCompressionConfig {
+  /// Objects smaller than this are sent uncompressed (defaults to 1 KiB)
+  #[serde(default = "default_transport_min_size_bytes")]
+  pub min_size_bytes: u64,
+
+  /// zstd level (1-22, defaults to 3)
+  #[serde(default = "default_compression_level")]
+  pub zstd_level: i32,
+
+  /// gzip level (1-9, defaults to 6)
+  #[serde(default = "default_transport_gzip_level")]
+  pub gzip_level: i32,
+}
+
+fn default_transport_min_size_bytes() -> u64 {
+  1024
+}
+
+fn default_transport_gzip_level() -> i32 {
+  6
+}
+
+impl TransportCompressionConfig {
+  /// Configured level for a codec
+  pub fn level(&self, codec: Codec) -> i32 {
+    match codec {
+      Codec::Zstd => self.zstd_level,
+      Codec::Gzip => self.gzip_level,
+    }
+  }
+}
+
+/// Characters accepted in cache keys
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashCharset {
+  /// Letters, digits, `-` and `_`
+  Alphanumeric,
+  /// Hex digits only
+  Hex,
+  /// ASCII letters, digits, `-`, `_`, `.` and `/`, without empty, `.` or `..` segments
+  Path,
+}
+
+/// Key format accepted on one protocol surface; unset fields keep the surface's built-in rule
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HashPolicyConfig {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub charset: Option<HashCharset>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_length: Option<usize>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_length: Option<usize>,
+
+  /// Regular expression the whole key must match, on top of the charset and length rules
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub pattern: Option<String>,
+}
+
+/// Key validation per protocol surface (Nx, Please, Bazel, Gradle and the compiler cache
+/// mirror)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HashValidationConfig {
+  /// `/v1/cache/{hash}` and the other Nx routes taking a hash
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub nx: Option<HashPolicyConfig>,
+
+  /// `/please/{key}`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub please: Option<HashPolicyConfig>,
+
+  /// `/ac/{hash}` and `/cas/{hash}`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub bazel: Option<HashPolicyConfig>,
+
+  /// `/gradle/{key}`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub gradle: Option<HashPolicyConfig>,
+
+  /// `/ccache/{key}`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ccache: Option<HashPolicyConfig>,
+
+  /// `/v8/artifacts/{hash}` and the Turborepo artifact query
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub turbo: Option<HashPolicyConfig>,
+
+  /// `/mirror/{key}`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mirror: Option<HashPolicyConfig>,
+}
+
+impl HashValidationConfig {
+  /// Configured policies with the name of their surface
+  pub fn policies(&self) -> impl Iterator<Item = (&'static str, &HashPolicyConfig)> {
+    [
+      ("nx", &self.nx),
+      ("please", &self.please),
+      ("bazel", &self.bazel),
+      ("gradle", &self.gradle),
+      ("ccache", &self.ccache),
+      ("turbo", &self.turbo),
+      ("mirror", &self.mirror),
+    ]
+    .into_iter()
+    .filter_map(|(surface, policy)| policy.as_ref().map(|policy| (surface, policy)))
+  }
+}
+
+/// Longest key any policy may accept, the S3 object key limit
+pub const MAX_HASH_POLICY_LENGTH: usize = 1024;
+
+/// Bulk download manifests with server-signed URLs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestConfig {
+  /// Secret used to sign download URLs
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signing_key: Option<String>,
+
+  /// Environment variable name holding the signing secret
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signing_key_env: Option<String>,
+
+  /// How long (in seconds) signed URLs stay valid
+  #[serde(default = "default_manifest_url_ttl_seconds")]
+  pub url_ttl_seconds: u64,
+
+  /// Maximum number of hashes accepted in a single manifest request
+  #[serde(default = "default_manifest_max_hashes")]
+  pub max_hashes: usize,
+
+  /// Externally reachable base URL used for signed URLs; relative URLs are returned when unset
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub public_url: Option<String>,
+}
+
+fn default_manifest_url_ttl_seconds() -> u64 {
+  900
+}
+
+fn default_manifest_max_hashes() -> usize {
+  1000
+}
+
+/// Storage backend implementation used for a bucket
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendType {
+  /// Amazon S3 or any S3-compatible service
+  #[default]
+  S3,
+  /// MinIO (S3 API with path-style addressing)
+  Minio,
+  /// Google Cloud Storage through its S3-compatible XML API (HMAC keys)
+  Gcs,
+  /// Local filesystem directory
+  Fs,
+}
+
+/// S3-compatible service whose known quirks are applied to an `s3` bucket
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+  Wasabi,
+  Digitalocean,
+  Hetzner,
+  Backblaze,
+  R2,
+  Garage,
+  Seaweedfs,
+}
+
+/// Settings known to work with a [`Provider`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderPreset {
+  /// Region used when the bucket sets none
+  pub default_region: Option<&'static str>,
+  /// Endpoint used when the bucket sets none, `{region}` replaced by the region
+  pub endpoint_template: Option<&'static str>,
+  pub force_path_style: bool,
+  pub conditional_writes: bool,
+  pub object_tagging: bool,
+}
+
+impl Provider {
+  pub fn preset(self) -> ProviderPreset {
+    match self {
+      Self::Wasabi => ProviderPreset {
+        default_region: Some("us-east-1"),
+        endpoint_template: Some("https://s3.{region}.wasabisys.com"),
+        force_path_style: false,
+        conditional_writes: false,
+        object_tagging: true,
+      },
+      Self::Digitalocean => ProviderPreset {
+        default_region: Some("nyc3"),
+        endpoint_template: Some("https://{region}.digitaloceanspaces.com"),
+        force_path_style: false,
+        conditional_writes: false,
+        object_tagging: false,
+      },
+      Self::Hetzner => ProviderPreset {
+        default_region: Some("fsn1"),
+        endpoint_template: Some("https://{region}.your-objectstorage.com"),
+        force_path_style: false,
+        conditional_writes: false,
+        object_tagging: false,
+      },
+      Self::Backblaze => ProviderPreset {
+        default_region: None,
+        endpoint_template: Some("https://s3.{region}.backblazeb2.com"),
+        force_path_style: false,
+        conditional_writes: false,
+        object_tagging: false,
+      },
+      // The endpoint contains the account ID, so it has to be configured
+      Self::R2 => ProviderPreset {
+        default_region: Some("auto"),
+        endpoint_template: None,
+        force_path_style: true,
+        conditional_writes: true,
+        object_tagging: false,
+      },
+      Self::Garage => ProviderPreset {
+        default_region: Some("garage"),
+        endpoint_template: None,
+        force_path_style: true,
+        conditional_writes: false,
+        object_tagging: false,
+      },
+      Self::Seaweedfs => ProviderPreset {
+        default_region: Some("us-east-1"),
+        endpoint_template: None,
+        force_path_style: true,
+        conditional_writes: false,
+        object_tagging: true,
+      },
+    }
+  }
+}
+
+/// Availability Zone ID of an S3 Express One Zone directory bucket
+///
+/// Directory bucket names end in `--<zone-id>--x-s3`, e.g. `nx-cache--usw2-az1--x-s3`.
+pub fn s3_express_zone(bucket_name: &str) -> Option<&str> {
+  let (base, zone) = bucket_name.strip_suffix("--x-s3")?.rsplit_once("--")?;
+  (!base.is_empty() && !zone.is_empty()).then_some(zone)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketConfig {
+  /// Unique name for this bucket configuration
+  pub name: String,
+
+  /// Storage backend type (optional, defaults to s3)
+  #[serde(rename = "type", default)]
+  pub backend: BackendType,
+
+  /// S3-compatible service whose known-good settings are applied (optional)
+  ///
+  /// Fills in the region and endpoint when they are not set, forces path-style addressing
+  /// where required, and turns off conditional writes and object tagging where unsupported.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub provider: Option<Provider>,
+
+  /// S3 bucket name
+  pub bucket_name: String,
+
+  /// Directory holding the objects (required for `fs` buckets)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub path: Option<String>,
+
+  /// AWS Access Key ID (optional - auto-discovered if not provided)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub access_key_id: Option<String>,
+
+  /// Environment variable name holding the AWS Access Key ID
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub access_key_id_env: Option<String>,
+
+  /// AWS Secret Access Key (optional - auto-discovered if not provided)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub secret_access_key: Option<String>,
+
+  /// Environment variable name holding the AWS Secret Access Key
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub secret_access_key_env: Option<String>,
+
+  /// AWS Session Token (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub session_token: Option<String>,
+
+  /// Environment variable name holding the AWS Session Token
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub session_token_env: Option<String>,
+
+  /// IAM role assumed through STS with the bucket's credentials (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub role_arn: Option<String>,
+
+  /// External ID required by the role's trust policy (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub external_id: Option<String>,
+
+  /// Environment variable name holding the external ID
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub external_id_env: Option<String>,
+
+  /// AWS Region (optional - auto-discovered if not provided)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub region: Option<String>,
+
+  /// Custom S3 endpoint URL (for MinIO, etc.)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub endpoint_url: Option<String>,
+
+  /// TLS CA certificate bundle path for custom endpoints
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tls_ca_file: Option<String>,
+
+  /// Environment variable name holding TLS CA certificate bundle path
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tls_ca_file_env: Option<String>,
+
+  /// Disable TLS certificate verification (use with care)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub insecure_tls: Option<bool>,
+
+  /// Environment variable name holding TLS verification flag
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub insecure_tls_env: Option<String>,
+
+  /// Force path-style addressing (required for MinIO and some S3-compatible services)
+  #[serde(default)]
+  pub force_path_style: bool,
+
+  /// Server-side encryption configuration (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sse: Option<SseConfig>,
+
+  /// Shorthand for `sse` using the S3 header value (`AES256` or `aws:kms`)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub server_side_encryption: Option<ServerSideEncryption>,
+
+  /// Shorthand for `sse` with `type: sseKms`; implies `serverSideEncryption: aws:kms`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sse_kms_key_id: Option<String>,
+
+  /// Storage operation timeouts
+  #[serde(default)]
+  pub timeouts: TimeoutsConfig,
+
+  /// Retry policy for transient storage errors
+  #[serde(default)]
+  pub retry: RetryConfig,
+
+  /// Client-side AES-256-GCM encryption (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub encryption: Option<EncryptionConfig>,
+
+  /// Transparent compression (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub compression: Option<CompressionConfig>,
+
+  /// Adaptive concurrency limiting for storage operations (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub concurrency: Option<ConcurrencyConfig>,
+
+  /// Local disk write-through tier (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub local_tier: Option<LocalTierConfig>,
+
+  /// Spill large uploads to disk before sending them to the bucket (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub spill: Option<SpillConfig>,
+
+  /// Fetch large downloads as parallel ranged GETs (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ranged_reads: Option<RangedReadsConfig>,
+
+  /// Run this bucket's storage operations on a dedicated runtime (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub isolation: Option<IsolationConfig>,
+
+  /// Name of another bucket to fail over to while this one is returning errors (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub fallback_bucket: Option<String>,
+
+  /// Use `If-None-Match: *` conditional writes instead of checking existence before uploading
+  /// (defaults to true; disable for S3-compatible services without conditional write support)
+  #[serde(default = "default_true")]
+  pub conditional_writes: bool,
+
+  /// Tag uploaded objects with the uploading token and its prefix (defaults to true; disable
+  /// for S3-compatible services without object tagging support)
+  #[serde(default = "default_true")]
+  pub object_tagging: bool,
+
+  /// S3 storage class set on uploaded objects (optional, bucket default if not set)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub storage_class: Option<StorageClass>,
+
+  /// Scheduled provider maintenance during which the server throttles itself (optional)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub maintenance_windows: Vec<MaintenanceWindowConfig>,
+}
+
+fn default_timeout() -> u64 {
+  30
+}
+
+/// Timeouts for storage operations on a bucket
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeoutsConfig {
+  /// Storage operation timeout in seconds
+  #[serde(default = "default_timeout")]
+  pub operation: u64,
+}
+
+impl Default for TimeoutsConfig {
+  fn default() -> Self {
+    Self {
+      operation: default_timeout(),
+    }
+  }
+}
+
+/// Compression codec of a stored object, named like its `Content-Encoding` token
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+  #[default]
+  Zstd,
+  Gzip,
+}
+
+impl Codec {
+  /// Value of the `Content-Encoding` header
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::Zstd => "zstd",
+      Self::Gzip => "gzip",
+    }
+  }
+
+  /// Valid compression levels
+  pub fn levels(self) -> std::ops::RangeInclusive<i32> {
+    match self {
+      Self::Zstd => 1..=22,
+      Self::Gzip => 1..=9,
+    }
+  }
+}
+
+/// Transparent compression applied before objects are written to the bucket
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CompressionConfig {
+  /// Codec used for new objects (defaults to zstd)
+  #[serde(default)]
+  pub codec: Codec,
+
+  /// Compression level (zstd 1-22, gzip 1-9)
+  #[serde(default = "default_compression_level")]
+  pub level: i32,
+}
+
+fn default_compression_level() -> i32 {
+  3
+}
+
+impl Default for CompressionConfig {
+  fn default() -> Self {
+    Self {
+      codec: Codec::default(),
+      level: default_compression_level(),
+    }
+  }
+}
+
+/// Day of the week a maintenance window starts on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+  Mon,
+  Tue,
+  Wed,
+  Thu,
+  Fri,
+  Sat,
+  Sun,
+}
+
+impl Weekday {
+  /// Days since Monday
+  pub fn index(self) -> u32 {
+    self as u32
+  }
+}
+
+/// A recurring window (UTC) in which the bucket's provider is expected to be degraded
+///
+/// Windows ending before they start wrap past midnight; `days` refers to the start day.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceWindowConfig {
+  /// Days the window starts on (every day if empty)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub days: Vec<Weekday>,
+  /// Start time as HH:MM (UTC)
+  pub start: String,
+  /// End time as HH:MM (UTC)
+  pub end: String,
+  /// Maximum concurrent storage operations on the bucket during the window (defaults to 2)
+  #[serde(default = "default_maintenance_max_concurrency")]
+  pub max_concurrency: usize,
+}
+
+fn default_maintenance_max_concurrency() -> usize {
+  2
+}
+
+impl MaintenanceWindowConfig {
+  /// Start and end as minutes after midnight, `None` if either is not a valid HH:MM
+  pub fn minutes(&self) -> Option<(u32, u32)> {
+    Some((
+      parse_time_of_day(&self.start)?,
+      parse_time_of_day(&self.end)?,
+    ))
+  }
+}
+
+/// Parse `HH:MM` into minutes after midnight
+fn parse_time_of_day(value: &str) -> Option<u32> {
+  let (hours, minutes) = value.split_once(':')?;
+  if hours.len() != 2 || minutes.len() != 2 {
+    return None;
+  }
+  let hours: u32 = hours.parse().ok()?;
+  let minutes: u32 = minutes.parse().ok()?;
+  (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Client-side encryption applied before objects are written to the bucket
+///
+/// The key is a base64-encoded 256-bit AES key, given inline, via an environment variable
+/// or read from a file (e.g. a mounted secret).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionConfig {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub key_base64: Option<String>,
+
+  /// Environment variable name holding the base64 key
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub key_base64_env: Option<String>,
+
+  /// File containing the base64 key
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub key_file: Option<String>,
+}
+
+/// Class of transient storage error that may be retried
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum RetryableError {
+  /// Connection refused, reset or closed before the response was complete
+  Connection,
+  /// Request or response timed out
+  Timeout,
+  /// 5xx responses such as InternalError or ServiceUnavailable
+  Server,
+  /// Rate limiting such as SlowDown or 429
+  Throttling,
+}
+
+/// Retry policy for storage reads (exists and get) and existence checks before writes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryConfig {
+  /// Total attempts including the first one (1 disables retries)
+  #[serde(default = "default_retry_max_attempts")]
+  pub max_attempts: u32,
+
+  /// Delay before the first retry in milliseconds
+  #[serde(default = "default_retry_initial_backoff_ms")]
+  pub initial_backoff_ms: u64,
+
+  /// Upper bound for the delay between attempts in milliseconds
+  #[serde(default = "default_retry_max_backoff_ms")]
+  pub max_backoff_ms: u64,
+
+  /// Factor the delay grows by after every retry
+  #[serde(default = "default_retry_backoff_multiplier")]
+  pub backoff_multiplier: f64,
+
+  /// Random extra delay as a fraction of the backoff (0.0 - 1.0)
+  #[serde(default = "default_retry_jitter")]
+  pub jitter: f64,
+
+  /// Error classes that are retried
+  #[serde(default = "default_retry_on")]
+  pub retry_on: Vec<RetryableError>,
+}
+
+impl Default for RetryConfig {
+  fn default() -> Self {
+    Self {
+      max_attempts: default_retry_max_attempts(),
+      initial_backoff_ms: default_retry_initial_backoff_ms(),
+      max_backoff_ms: default_retry_max_backoff_ms(),
+      backoff_multiplier: default_retry_backoff_multiplier(),
+      jitter: default_retry_jitter(),
+      retry_on: default_retry_on(),
+    }
+  }
+}
+
+fn default_retry_max_attempts() -> u32 {
+  3
+}
+
+fn default_retry_initial_backoff_ms() -> u64 {
+  100
+}
+
+fn default_retry_max_backoff_ms() -> u64 {
+  2000
+}
+
+fn default_retry_backoff_multiplier() -> f64 {
+  3.0
+}
+
+fn default_retry_jitter() -> f64 {
+  0.2
+}
+
+fn default_retry_on() -> Vec<RetryableError> {
+  vec![
+    RetryableError::Connection,
+    RetryableError::Timeout,
+    RetryableError::Server,
+    RetryableError::Throttling,
+  ]
+}
+
+/// How writes are copied to a token's replica buckets
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplicationMode {
+  /// The PUT only succeeds once every replica holds the object
+  Sync,
+  /// Replicas are written in the background after the PUT succeeded
+  #[default]
+  Async,
+}
+
+/// Which cache operations a token may perform
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Permissions {
+  /// Downloads and existence checks only, e.g. for developer machines
+  Read,
+  /// Uploads only
+  Write,
+  #[default]
+  ReadWrite,
+}
+
+impl Permissions {
+  pub fn can_read(self) -> bool {
+    self != Self::Write
+  }
+
+  pub fn can_write(self) -> bool {
+    self != Self::Read
+  }
+}
+
+/// How cache object keys are laid out below a token's prefix
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyLayout {
+  /// `{prefix}/{hash}`
+  #[default]
+  Plain,
+  /// `{prefix}/{hash}.tar.gz`, as written by Nx Powerpack's `@nx/powerpack-s3-cache`
+  Powerpack,
+}
+
+impl KeyLayout {
+  /// Object name for a cache hash under this layout
+  pub fn object_name(self, hash: &str) -> String {
+    match self {
+      Self::Plain => hash.to_string(),
+      Self::Powerpack => format!("{}.tar.gz", hash),
+    }
+  }
 }
 
+/// Another cache server that a token's reads are pulled through on a local miss
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct SseConfig {
-  #[serde(rename = "type")]
-  pub sse_type: SseType,
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub kms_key_id: Option<String>,
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub kms_key_id_env: Option<String>,
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub kms_context: Option<KmsContext>,
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub kms_context_env: Option<String>,
+pub struct UpstreamConfig {
+  /// Base URL of the upstream nx-cache-server or Nx Cloud-compatible endpoint
+  pub url: String,
+
+  /// Bearer token sent to the upstream
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub customer_key_base64: Option<String>,
+  pub access_token: Option<String>,
+
+  /// Environment variable name holding the upstream token
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub customer_key_base64_env: Option<String>,
+  pub access_token_env: Option<String>,
+
+  /// Store artifacts fetched from the upstream in the token's own namespace
+  #[serde(default = "default_true")]
+  pub persist: bool,
+
+  /// How long (in seconds) to wait for the upstream to start answering
+  #[serde(default = "default_upstream_timeout_seconds")]
+  pub timeout_seconds: u64,
+}
+
+fn default_upstream_timeout_seconds() -> u64 {
+  10
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct BucketConfig {
-  /// Unique name for this bucket configuration
+pub struct ServiceAccessTokenConfig {
+  /// Unique name for this service token
   pub name: String,
 
-  /// S3 bucket name
-  pub bucket_name: String,
+  /// Reference to the bucket name this token uses
+  pub bucket: String,
 
-  /// AWS Access Key ID (optional - auto-discovered if not provided)
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub access_key_id: Option<String>,
+  /// Prefix path in the bucket (e.g., "/ci", "/team1")
+  #[serde(default)]
+  pub prefix: String,
 
-  /// Environment variable name holding the AWS Access Key ID
+  /// Bearer token for authentication
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub access_key_id_env: Option<String>,
+  pub access_token: Option<String>,
 
-  /// AWS Secret Access Key (optional - auto-discovered if not provided)
+  /// Environment variable name holding the access token
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub secret_access_key: Option<String>,
+  pub access_token_env: Option<String>,
 
-  /// Environment variable name holding the AWS Secret Access Key
+  /// argon2 or bcrypt hash of the secret, instead of the token itself; clients send the token
+  /// as `<name>.<secret>`
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub secret_access_key_env: Option<String>,
+  pub access_token_hash: Option<String>,
 
-  /// AWS Session Token (optional)
+  /// Token replaced by the current one, still accepted until `previousAccessTokenValidUntil`
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub session_token: Option<String>,
+  pub previous_access_token: Option<String>,
 
-  /// Environment variable name holding the AWS Session Token
+  /// Environment variable name holding the previous access token
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub session_token_env: Option<String>,
+  pub previous_access_token_env: Option<String>,
 
-  /// AWS Region (optional - auto-discovered if not provided)
+  /// RFC 3339 time from which the previous token is rejected (required with a previous token)
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub region: Option<String>,
+  pub previous_access_token_valid_until: Option<String>,
 
-  /// Custom S3 endpoint URL (for MinIO, etc.)
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub endpoint_url: Option<String>,
+  /// Whether the token may download (`read`), upload (`write`) or both (`read-write`, the
+  /// default)
+  #[serde(default)]
+  pub permissions: Permissions,
 
-  /// TLS CA certificate bundle path for custom endpoints
+  /// RFC 3339 time after which the token is rejected (optional, never expires if not set)
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub tls_ca_file: Option<String>,
+  pub expires_at: Option<String>,
 
-  /// Environment variable name holding TLS CA certificate bundle path
+  /// Maximum bytes this token may download per UTC day (optional, unlimited if not set)
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub tls_ca_file_env: Option<String>,
+  pub egress_daily_limit_bytes: Option<u64>,
 
-  /// Disable TLS certificate verification (use with care)
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub insecure_tls: Option<bool>,
+  /// Percentage of the daily egress limit after which responses carry a quota warning header
+  #[serde(default = "default_quota_warning_percent")]
+  pub quota_warning_percent: u8,
 
-  /// Environment variable name holding TLS verification flag
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub insecure_tls_env: Option<String>,
+  /// Additional buckets every write is copied to (optional)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub replica_buckets: Vec<String>,
 
-  /// Force path-style addressing (required for MinIO and some S3-compatible services)
+  /// Whether replica writes are awaited (`sync`) or best-effort in the background (`async`)
   #[serde(default)]
-  pub force_path_style: bool,
+  pub replication: ReplicationMode,
 
-  /// Server-side encryption configuration (optional)
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub sse: Option<SseConfig>,
+  /// Object key layout (`plain` or `powerpack`, defaults to plain)
+  #[serde(default)]
+  pub key_layout: KeyLayout,
 
-  /// S3 operation timeout in seconds
-  #[serde(default = "default_timeout")]
-  pub timeout: u64,
-}
+  /// Former prefixes that reads fall back to when the object is missing under `prefix`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub legacy_prefixes: Vec<String>,
 
-fn default_timeout() -> u64 {
-  30
-}
+  /// Copy objects found under a legacy prefix to the current prefix in the background
+  #[serde(default)]
+  pub legacy_copy_forward: bool,
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ServiceAccessTokenConfig {
-  /// Unique name for this service token
-  pub name: String,
+  /// Tokens of the same bucket whose namespaces reads fall back to after the legacy prefixes
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub namespace_aliases: Vec<String>,
 
-  /// Reference to the bucket name this token uses
-  pub bucket: String,
+  /// Allow this token to export usage accounting for all tokens
+  #[serde(default)]
+  pub accounting_admin: bool,
 
-  /// Prefix path in the bucket (e.g., "/ci", "/team1")
+  /// Accept a per-platform `variant` stored alongside the plain hash
   #[serde(default)]
-  pub prefix: String,
+  pub variants: bool,
 
-  /// Bearer token for authentication
+  /// Allow this token to use the `/admin` endpoints
+  #[serde(default)]
+  pub admin: bool,
+
+  /// Let uploads replace an existing hash instead of answering 409, e.g. to re-publish a
+  /// corrupted artifact
+  #[serde(default)]
+  pub allow_overwrite: bool,
+
+  /// Request header the token is also accepted in, for clients that cannot set
+  /// `Authorization` (optional)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub auth_header: Option<String>,
+
+  /// Retention in seconds recorded on uploads that do not request one (optional)
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub access_token: Option<String>,
+  pub default_ttl_seconds: Option<u64>,
 
-  /// Environment variable name holding the access token
+  /// Upper bound for the retention clients request via `x-nx-cache-ttl`; without it the
+  /// header is ignored (optional)
   #[serde(skip_serializing_if = "Option::is_none")]
-  pub access_token_env: Option<String>,
+  pub max_ttl_seconds: Option<u64>,
+
+  /// Share of a saturated bucket's concurrency relative to other tokens (defaults to 1)
+  #[serde(default = "default_token_weight")]
+  pub weight: u32,
+
+  /// Cost allocation tags (e.g. `team`, `project`) set on every object the token stores
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub cost_tags: BTreeMap<String, String>,
+
+  /// Notice for this token's clients, replacing the global `notice` (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub notice: Option<String>,
+
+  /// Cache that Nx artifact reads are pulled through on a local miss (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub upstream: Option<UpstreamConfig>,
+}
+
+/// Object tags the server sets itself, which cost allocation tags cannot override
+const RESERVED_TAG_KEYS: &[&str] = &["token", "namespace", "run-id", "ttl-days"];
+
+/// S3 allows 10 tags per object, of which the server may use up to 4
+const MAX_COST_TAGS: usize = 10 - RESERVED_TAG_KEYS.len();
+
+fn default_quota_warning_percent() -> u8 {
+  80
+}
+
+fn default_token_weight() -> u32 {
+  1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -167,6 +1533,117 @@ pub struct Config {
   /// Enable debug logging
   #[serde(default)]
   pub debug: bool,
+
+  /// Shutdown stage timeouts
+  #[serde(default)]
+  pub shutdown: ShutdownConfig,
+
+  /// `Cache-Control` header of artifact downloads
+  #[serde(default)]
+  pub cache_control: CacheControlConfig,
+
+  /// Collapse duplicate slashes and strip trailing slashes from request paths before routing
+  #[serde(default)]
+  pub normalize_paths: bool,
+
+  /// Compare configured namespaces with the prefixes present in the buckets at startup and log
+  /// orphaned and empty ones
+  #[serde(default)]
+  pub audit_namespaces: bool,
+
+  /// Answer the Nx cache endpoints only with the status codes and error bodies of the Nx
+  /// remote cache spec, folding this server's extension statuses into them
+  #[serde(default)]
+  pub strict_nx_spec: bool,
+
+  /// Answer errors with a JSON body carrying a stable machine-readable code instead of plain
+  /// text; the Nx cache endpoints keep the spec's bodies in strict Nx spec mode
+  #[serde(default)]
+  pub json_errors: bool,
+
+  /// Accept zero-byte uploads to the Nx cache endpoint, which are rejected by default since an
+  /// empty artifact almost always comes from a broken client
+  #[serde(default)]
+  pub allow_empty_uploads: bool,
+
+  /// Path prefix every route is mounted under (e.g. `/nx-cache`), for serving behind a shared
+  /// ingress path without a rewriting proxy (optional)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub base_path: Option<String>,
+
+  /// Seconds a download of an object that is still being uploaded waits for the upload to
+  /// finish instead of returning a miss (0 disables waiting)
+  #[serde(default)]
+  pub in_flight_wait_seconds: u64,
+
+  /// Number of recent errors kept for `/admin/errors`
+  #[serde(default = "default_recent_errors_capacity")]
+  pub recent_errors_capacity: usize,
+
+  /// Read-only compiler cache mirror (disabled when absent)
+  #[serde(default)]
+  pub mirror: Option<MirrorConfig>,
+
+  /// Bulk download manifest endpoint (disabled when absent)
+  #[serde(default)]
+  pub manifest: Option<ManifestConfig>,
+
+  /// Rendezvous hashing hints for replicas sharing a local disk tier (disabled when absent)
+  #[serde(default)]
+  pub affinity: Option<AffinityConfig>,
+
+  /// Task metadata endpoint and admin queries (disabled when absent)
+  #[serde(default)]
+  pub task_metadata: Option<TaskMetadataConfig>,
+
+  /// Compress downloads for clients sending `Accept-Encoding` (disabled when absent)
+  #[serde(default)]
+  pub transport_compression: Option<TransportCompressionConfig>,
+
+  /// tus resumable uploads at `/v1/tus` (disabled when absent)
+  #[serde(default)]
+  pub tus: Option<TusConfig>,
+
+  /// gRPC remote cache for Bazel and Buck2 on a separate port (disabled when absent)
+  #[serde(default)]
+  pub grpc: Option<GrpcConfig>,
+
+  /// JWT bearer tokens mapped to service tokens by their claims (disabled when absent)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub jwt: Option<JwtConfig>,
+
+  /// OIDC ID tokens mapped to service tokens by their claims (disabled when absent)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub oidc: Option<OidcConfig>,
+
+  /// GitHub Actions OIDC tokens mapped to service tokens by repository (disabled when absent)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub github_actions: Option<GithubActionsConfig>,
+
+  /// GitLab CI job JWTs mapped to service tokens by project (disabled when absent)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub gitlab: Option<GitlabConfig>,
+
+  /// Admin endpoint minting short-lived derived tokens (disabled when absent)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub token_minting: Option<TokenMintingConfig>,
+
+  /// Admin endpoint revoking tokens at runtime (disabled when absent)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub token_revocation: Option<TokenRevocationConfig>,
+
+  /// Key validation rules per protocol surface (built-in rules when absent)
+  #[serde(default)]
+  pub hash_validation: HashValidationConfig,
+
+  /// Notice sent to every client in the `x-nx-cache-notice` header, e.g. about an upcoming
+  /// migration (optional)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub notice: Option<String>,
+}
+
+fn default_recent_errors_capacity() -> usize {
+  100
 }
 
 fn default_port() -> u16 {
@@ -184,11 +1661,8 @@ impl Config {
       .map(|ext| ext.to_ascii_lowercase());
 
     let config: Config = match extension.as_deref() {
-      Some("yaml") | Some("yml") => serde_yml::from_str(&content)?,
-      Some("toml") => {
-        let toml_config: TomlConfig = toml::from_str(&content)?;
-        toml_config.into()
-      },
+      Some("yaml") | Some("yml") => Self::from_yaml_str(&content)?,
+      Some("toml") => Self::from_toml_str(&content)?,
       Some(other) => return Err(ConfigError::UnsupportedFormat(other.to_string())),
       None => {
         return Err(ConfigError::UnsupportedFormat(
@@ -201,6 +1675,28 @@ impl Config {
     Ok(config)
   }
 
+  /// Parse YAML configuration, migrating older schema versions to the current one
+  pub fn from_yaml_str(content: &str) -> Result<Self, ConfigError> {
+    let mut value: serde_json::Value = serde_yml::from_str(content)?;
+    Self::log_deprecations(migration::migrate(&mut value, KeyStyle::CamelCase)?);
+    Ok(serde_yml::from_value(serde_yml::to_value(value)?)?)
+  }
+
+  /// Parse TOML configuration, migrating older schema versions to the current one
+  pub fn from_toml_str(content: &str) -> Result<Self, ConfigError> {
+    let mut value: serde_json::Value = toml::from_str(content)?;
+    Self::log_deprecations(migration::migrate(&mut value, KeyStyle::SnakeCase)?);
+    let value = toml::Value::try_from(value).map_err(|e| ConfigError::Migration(e.to_string()))?;
+    let toml_config: TomlConfig = value.try_into()?;
+    Ok(toml_config.into())
+  }
+
+  fn log_deprecations(deprecations: Vec<String>) {
+    for deprecation in deprecations {
+      tracing::warn!("Deprecated configuration: {}", deprecation);
+    }
+  }
+
   /// Validate the configuration
   pub fn validate(&self) -> Result<(), ConfigError> {
     // Validate we have at least one bucket
@@ -226,10 +1722,238 @@ impl Config {
       }
       if !bucket_names.insert(&bucket.name) {
         return Err(ConfigError::Validation(format!(
-          "Duplicate bucket name: {}",
+          "Duplicate bucket name: {}",
+          bucket.name
+        )));
+      }
+      if let Some(concurrency) = &bucket.concurrency {
+        if concurrency.min_limit == 0 || concurrency.min_limit > concurrency.max_limit {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': concurrency.minLimit must be between 1 and maxLimit",
+            bucket.name
+          )));
+        }
+        if !(concurrency.backoff_ratio > 0.0 && concurrency.backoff_ratio < 1.0) {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': concurrency.backoffRatio must be between 0 and 1",
+            bucket.name
+          )));
+        }
+      }
+      if bucket.retry.max_attempts == 0 {
+        return Err(ConfigError::Validation(format!(
+          "Bucket '{}': retry.maxAttempts must be at least 1",
+          bucket.name
+        )));
+      }
+      if bucket.retry.backoff_multiplier < 1.0 || !(0.0..=1.0).contains(&bucket.retry.jitter) {
+        return Err(ConfigError::Validation(format!(
+          "Bucket '{}': retry.backoffMultiplier must be at least 1 and retry.jitter between 0 and 1",
+          bucket.name
+        )));
+      }
+      if let Some(encryption) = &bucket.encryption {
+        let sources = [
+          encryption.key_base64.is_some(),
+          encryption.key_base64_env.is_some(),
+          encryption.key_file.is_some(),
+        ];
+        if sources.iter().filter(|set| **set).count() != 1 {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': encryption needs exactly one of keyBase64, keyBase64Env or keyFile",
+            bucket.name
+          )));
+        }
+      }
+      for window in &bucket.maintenance_windows {
+        match window.minutes() {
+          None => {
+            return Err(ConfigError::Validation(format!(
+              "Bucket '{}': maintenanceWindows start and end must be HH:MM",
+              bucket.name
+            )));
+          },
+          Some((start, end)) if start == end => {
+            return Err(ConfigError::Validation(format!(
+              "Bucket '{}': maintenanceWindows start and end must differ",
+              bucket.name
+            )));
+          },
+          Some(_) => {},
+        }
+        if window.max_concurrency == 0 {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': maintenanceWindows maxConcurrency must be at least 1",
+            bucket.name
+          )));
+        }
+      }
+
+      if let Some(compression) = &bucket.compression {
+        let levels = compression.codec.levels();
+        if !levels.contains(&compression.level) {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': compression.level must be between {} and {} for {}",
+            bucket.name,
+            levels.start(),
+            levels.end(),
+            compression.codec.as_str()
+          )));
+        }
+      }
+      if let Some(local_tier) = &bucket.local_tier {
+        if local_tier.path.trim().is_empty() {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': localTier.path cannot be empty",
+            bucket.name
+          )));
+        }
+      }
+      if let Some(spill) = &bucket.spill {
+        if spill.path.as_deref().is_some_and(|p| p.trim().is_empty()) {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': spill.path cannot be empty",
+            bucket.name
+          )));
+        }
+      }
+      if let Some(ranged_reads) = &bucket.ranged_reads {
+        if ranged_reads.part_size_bytes < MIN_RANGED_PART_SIZE_BYTES
+          || ranged_reads.concurrency == 0
+        {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': rangedReads.partSizeBytes must be at least 1 MiB and rangedReads.concurrency greater than 0",
+            bucket.name
+          )));
+        }
+      }
+      if bucket
+        .isolation
+        .as_ref()
+        .is_some_and(|isolation| isolation.worker_threads == 0)
+      {
+        return Err(ConfigError::Validation(format!(
+          "Bucket '{}': isolation.workerThreads must be greater than 0",
+          bucket.name
+        )));
+      }
+    }
+
+    // Validate backend-specific settings
+    for bucket in &self.buckets {
+      if bucket.backend == BackendType::Fs {
+        if bucket.path.as_deref().is_none_or(|p| p.trim().is_empty()) {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': path is required for type fs",
+            bucket.name
+          )));
+        }
+        if bucket.sse.is_some()
+          || bucket.server_side_encryption.is_some()
+          || bucket.sse_kms_key_id.is_some()
+        {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': sse is not supported for type fs",
+            bucket.name
+          )));
+        }
+        if bucket.storage_class.is_some() {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': storageClass is not supported for type fs",
+            bucket.name
+          )));
+        }
+      }
+      if let Some(role_arn) = &bucket.role_arn {
+        if bucket.backend != BackendType::S3 {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': roleArn is only supported for type s3",
+            bucket.name
+          )));
+        }
+        if !role_arn.starts_with("arn:") {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': roleArn must be an ARN (arn:aws:iam::<account>:role/<name>)",
+            bucket.name
+          )));
+        }
+      } else if bucket.external_id.is_some() || bucket.external_id_env.is_some() {
+        return Err(ConfigError::Validation(format!(
+          "Bucket '{}': externalId requires roleArn",
           bucket.name
         )));
       }
+      if let Some(provider) = bucket.provider {
+        if bucket.backend != BackendType::S3 {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': provider is only supported for type s3",
+            bucket.name
+          )));
+        }
+        let preset = provider.preset();
+        if bucket.endpoint_url.is_none() {
+          if preset.endpoint_template.is_none() {
+            return Err(ConfigError::Validation(format!(
+              "Bucket '{}': endpointUrl is required for provider {:?}",
+              bucket.name, provider
+            )));
+          }
+          if bucket.region.is_none() && preset.default_region.is_none() {
+            return Err(ConfigError::Validation(format!(
+              "Bucket '{}': region is required for provider {:?}",
+              bucket.name, provider
+            )));
+          }
+        }
+      }
+      if bucket.backend == BackendType::S3 && s3_express_zone(&bucket.bucket_name).is_some() {
+        let unsupported = if bucket.provider.is_some() {
+          Some("provider")
+        } else if bucket.force_path_style {
+          Some("forcePathStyle")
+        } else if Self::effective_sse(bucket)?.is_some() {
+          Some("sse")
+        } else if bucket.ranged_reads.is_some() {
+          Some("rangedReads")
+        } else if bucket
+          .storage_class
+          .is_some_and(|class| class != StorageClass::ExpressOnezone)
+        {
+          Some("storageClass other than EXPRESS_ONEZONE")
+        } else {
+          None
+        };
+        if let Some(option) = unsupported {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': {} is not supported for S3 Express directory buckets",
+            bucket.name, option
+          )));
+        }
+        if bucket.region.is_none() {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}': region is required for S3 Express directory buckets",
+            bucket.name
+          )));
+        }
+      }
+    }
+
+    // Validate fallback bucket references
+    for bucket in &self.buckets {
+      if let Some(fallback) = &bucket.fallback_bucket {
+        if fallback == &bucket.name {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}' cannot be its own fallbackBucket",
+            bucket.name
+          )));
+        }
+        if !bucket_names.contains(fallback) {
+          return Err(ConfigError::Validation(format!(
+            "Bucket '{}' references non-existent fallbackBucket '{}'",
+            bucket.name, fallback
+          )));
+        }
+      }
     }
 
     // Validate we have at least one service token
@@ -262,15 +1986,252 @@ impl Config {
         )));
       }
 
-      // Validate token is provided via value or env var
-      if token.access_token.is_none() && token.access_token_env.is_none() {
+      if !(1..=100).contains(&token.quota_warning_percent) {
+        return Err(ConfigError::Validation(format!(
+          "Service token '{}': quotaWarningPercent must be between 1 and 100",
+          token.name
+        )));
+      }
+
+      if token.default_ttl_seconds == Some(0) || token.max_ttl_seconds == Some(0) {
+        return Err(ConfigError::Validation(format!(
+          "Service token '{}': defaultTtlSeconds and maxTtlSeconds must be greater than 0",
+          token.name
+        )));
+      }
+      if let (Some(default), Some(max)) = (token.default_ttl_seconds, token.max_ttl_seconds) {
+        if default > max {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': defaultTtlSeconds must not exceed maxTtlSeconds",
+            token.name
+          )));
+        }
+      }
+      if token.cost_tags.len() > MAX_COST_TAGS {
+        return Err(ConfigError::Validation(format!(
+          "Service token '{}': at most {} costTags are supported",
+          token.name, MAX_COST_TAGS
+        )));
+      }
+      for key in token.cost_tags.keys() {
+        let valid = !key.is_empty()
+          && key.len() <= 128
+          && !key.starts_with("aws:")
+          && !RESERVED_TAG_KEYS.contains(&key.as_str())
+          && key.chars().all(|c| {
+            c.is_ascii_alphanumeric()
+              || matches!(c, ' ' | '+' | '-' | '=' | '.' | '_' | ':' | '/' | '@')
+          });
+        if !valid {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': invalid costTags key '{}'",
+            token.name, key
+          )));
+        }
+      }
+      if token.previous_access_token.is_some() || token.previous_access_token_env.is_some() {
+        if token
+          .previous_access_token_valid_until
+          .as_deref()
+          .and_then(http_date::parse_rfc3339)
+          .is_none()
+        {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': previousAccessTokenValidUntil must be an RFC 3339 timestamp",
+            token.name
+          )));
+        }
+        if token.previous_access_token.is_some()
+          && token.previous_access_token == token.access_token
+        {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': previousAccessToken must differ from accessToken",
+            token.name
+          )));
+        }
+      }
+      if let Some(expires_at) = &token.expires_at {
+        if http_date::parse_rfc3339(expires_at).is_none() {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': expiresAt '{}' is not an RFC 3339 timestamp",
+            token.name, expires_at
+          )));
+        }
+      }
+      if token.weight == 0 {
+        return Err(ConfigError::Validation(format!(
+          "Service token '{}': weight must be at least 1",
+          token.name
+        )));
+      }
+      if let Some(header) = &token.auth_header {
+        let valid = !header.is_empty()
+          && !header.eq_ignore_ascii_case("authorization")
+          && header
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        if !valid {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': authHeader '{}' must be a header name other than Authorization",
+            token.name, header
+          )));
+        }
+      }
+      if let Some(upstream) = &token.upstream {
+        if !upstream.url.starts_with("http://") && !upstream.url.starts_with("https://") {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': upstream url '{}' must start with http:// or https://",
+            token.name, upstream.url
+          )));
+        }
+        if upstream.timeout_seconds == 0 {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': upstream timeoutSeconds must be at least 1",
+            token.name
+          )));
+        }
+      }
+
+      for legacy in &token.legacy_prefixes {
+        if Self::normalize_prefix(legacy) == Self::normalize_prefix(&token.prefix) {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}' lists its own prefix '{}' in legacyPrefixes",
+            token.name, legacy
+          )));
+        }
+      }
+
+      for alias in &token.namespace_aliases {
+        let Some(target) = self
+          .service_access_tokens
+          .iter()
+          .find(|other| &other.name == alias)
+        else {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}' references non-existent token '{}' in namespaceAliases",
+            token.name, alias
+          )));
+        };
+        if target.name == token.name || target.bucket != token.bucket {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}': namespaceAliases entry '{}' must be another token of bucket '{}'",
+            token.name, alias, token.bucket
+          )));
+        }
+      }
+
+      for replica in &token.replica_buckets {
+        if !bucket_names.contains(replica) {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}' references non-existent replica bucket '{}'",
+            token.name, replica
+          )));
+        }
+        if replica == &token.bucket {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}' lists its primary bucket '{}' as a replica",
+            token.name, replica
+          )));
+        }
+      }
+
+      // Validate token is provided via value, env var or hash, unless only JWTs reach it
+      if let Some(hash) = &token.access_token_hash {
+        if token.access_token.is_some() || token.access_token_env.is_some() {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}' must not have accessTokenHash together with accessToken or accessTokenEnv",
+            token.name
+          )));
+        }
+        // Hashed tokens are sent as `<name>.<secret>`, so the name must not contain the separator
+        if token.name.contains('.') {
+          return Err(ConfigError::Validation(format!(
+            "Service token '{}' with accessTokenHash must not contain '.' in its name",
+            token.name
+          )));
+        }
+        token_hash::validate(hash).map_err(|e| {
+          ConfigError::Validation(format!(
+            "Service token '{}' accessTokenHash: {}",
+            token.name, e
+          ))
+        })?;
+      } else if token.access_token.is_none()
+        && token.access_token_env.is_none()
+        && !self.jwt_only(&token.name)
+      {
         return Err(ConfigError::Validation(format!(
-          "Service token '{}' must have either accessToken or accessTokenEnv",
+          "Service token '{}' must have either accessToken, accessTokenEnv or accessTokenHash",
           token.name
         )));
       }
     }
 
+    if let Some(jwt) = &self.jwt {
+      let secret = jwt.secret.is_some() || jwt.secret_env.is_some();
+      if secret == jwt.jwks_url.is_some() {
+        return Err(ConfigError::Validation(
+          "jwt must have either secret/secretEnv or jwksUrl".to_string(),
+        ));
+      }
+      self.validate_jwt_rules("jwt", &jwt.rules)?;
+    }
+
+    if let Some(oidc) = &self.oidc {
+      if !oidc.issuer.starts_with("https://") && !oidc.issuer.starts_with("http://") {
+        return Err(ConfigError::Validation(format!(
+          "oidc issuer '{}' must be an http(s) URL",
+          oidc.issuer
+        )));
+      }
+      if oidc.audience.is_empty() {
+        return Err(ConfigError::Validation(
+          "oidc audience must not be empty".to_string(),
+        ));
+      }
+      self.validate_jwt_rules("oidc", &oidc.rules)?;
+    }
+
+    if let Some(github) = &self.github_actions {
+      if !github.issuer.starts_with("https://") {
+        return Err(ConfigError::Validation(format!(
+          "githubActions issuer '{}' must be an https URL",
+          github.issuer
+        )));
+      }
+      if let Some(rule) = github
+        .rules
+        .iter()
+        .find(|rule| !rule.repository.contains('/'))
+      {
+        return Err(ConfigError::Validation(format!(
+          "githubActions rule repository '{}' must be 'owner/name'",
+          rule.repository
+        )));
+      }
+      self.validate_jwt_rules("githubActions", &github.jwt_rules())?;
+    }
+
+    if let Some(gitlab) = &self.gitlab {
+      if !gitlab.url.starts_with("https://") && !gitlab.url.starts_with("http://") {
+        return Err(ConfigError::Validation(format!(
+          "gitlab url '{}' must be an http(s) URL",
+          gitlab.url
+        )));
+      }
+      if let Some(rule) = gitlab
+        .rules
+        .iter()
+        .find(|rule| !rule.project_path.contains('/'))
+      {
+        return Err(ConfigError::Validation(format!(
+          "gitlab rule projectPath '{}' must be 'group/project'",
+          rule.project_path
+        )));
+      }
+      self.validate_jwt_rules("gitlab", &gitlab.jwt_rules())?;
+    }
+
     // Validate port
     if self.port == 0 {
       return Err(ConfigError::Validation(
@@ -278,6 +2239,171 @@ impl Config {
       ));
     }
 
+    if self.shutdown.drain_timeout_seconds == 0 {
+      return Err(ConfigError::Validation(
+        "shutdown.drainTimeoutSeconds must be greater than 0".to_string(),
+      ));
+    }
+
+    if let Some(mirror) = &self.mirror {
+      if !bucket_names.contains(&mirror.bucket) {
+        return Err(ConfigError::Validation(format!(
+          "mirror.bucket references unknown bucket '{}'",
+          mirror.bucket
+        )));
+      }
+      if mirror.negative_cache_max_entries == 0 {
+        return Err(ConfigError::Validation(
+          "mirror.negativeCacheMaxEntries must be greater than 0".to_string(),
+        ));
+      }
+    }
+
+    if let Some(manifest) = &self.manifest {
+      if manifest.signing_key.is_none() && manifest.signing_key_env.is_none() {
+        return Err(ConfigError::Validation(
+          "manifest must have either signingKey or signingKeyEnv".to_string(),
+        ));
+      }
+      if manifest.url_ttl_seconds == 0 || manifest.max_hashes == 0 {
+        return Err(ConfigError::Validation(
+          "manifest.urlTtlSeconds and manifest.maxHashes must be greater than 0".to_string(),
+        ));
+      }
+    }
+
+    if let Some(minting) = &self.token_minting {
+      if minting.signing_key.is_none() && minting.signing_key_env.is_none() {
+        return Err(ConfigError::Validation(
+          "tokenMinting must have either signingKey or signingKeyEnv".to_string(),
+        ));
+      }
+      if minting.default_ttl_seconds == 0 || minting.default_ttl_seconds > minting.max_ttl_seconds {
+        return Err(ConfigError::Validation(
+          "tokenMinting.defaultTtlSeconds must be between 1 and maxTtlSeconds".to_string(),
+        ));
+      }
+    }
+
+    if let Some(revocation) = &self.token_revocation {
+      if revocation.path.trim().is_empty() {
+        return Err(ConfigError::Validation(
+          "tokenRevocation.path cannot be empty".to_string(),
+        ));
+      }
+    }
+
+    if let Some(affinity) = &self.affinity {
+      if affinity.peers.is_empty() {
+        return Err(ConfigError::Validation(
+          "affinity.peers must list at least one peer".to_string(),
+        ));
+      }
+      let mut peers = std::collections::HashSet::new();
+      for peer in &affinity.peers {
+        if peer.trim().is_empty() || !peers.insert(peer.trim_end_matches('/')) {
+          return Err(ConfigError::Validation(format!(
+            "affinity.peers contains an empty or duplicate peer '{}'",
+            peer
+          )));
+        }
+      }
+    }
+
+    if let Some(task_metadata) = &self.task_metadata {
+      if task_metadata.max_bytes == 0 || task_metadata.max_results == 0 {
+        return Err(ConfigError::Validation(
+          "taskMetadata.maxBytes and taskMetadata.maxResults must be greater than 0".to_string(),
+        ));
+      }
+    }
+
+    if let Some(tus) = &self.tus {
+      if tus.path.as_deref().is_some_and(|p| p.trim().is_empty()) {
+        return Err(ConfigError::Validation(
+          "tus.path cannot be empty".to_string(),
+        ));
+      }
+      if tus.max_size_bytes == 0 || tus.expiration_seconds == 0 {
+        return Err(ConfigError::Validation(
+          "tus.maxSizeBytes and tus.expirationSeconds must be greater than 0".to_string(),
+        ));
+      }
+    }
+
+    if let Some(grpc) = &self.grpc {
+      if grpc.port == 0 || grpc.port == self.port {
+        return Err(ConfigError::Validation(
+          "grpc.port must be greater than 0 and differ from port".to_string(),
+        ));
+      }
+    }
+
+    if let Some(transport) = &self.transport_compression {
+      for codec in [Codec::Zstd, Codec::Gzip] {
+        let levels = codec.levels();
+        if !levels.contains(&transport.level(codec)) {
+          return Err(ConfigError::Validation(format!(
+            "transportCompression.{}Level must be between {} and {}",
+            codec.as_str(),
+            levels.start(),
+            levels.end()
+          )));
+        }
+      }
+    }
+
+    for (surface, policy) in self.hash_validation.policies() {
+      let min_length = policy.min_length.unwrap_or(1);
+      let max_length = policy.max_length.unwrap_or(MAX_HASH_POLICY_LENGTH);
+      if min_length == 0 || min_length > max_length || max_length > MAX_HASH_POLICY_LENGTH {
+        return Err(ConfigError::Validation(format!(
+          "hashValidation.{}: lengths must satisfy 1 <= minLength <= maxLength <= {}",
+          surface, MAX_HASH_POLICY_LENGTH
+        )));
+      }
+      if let Some(pattern) = &policy.pattern {
+        if let Err(e) = regex::Regex::new(pattern) {
+          return Err(ConfigError::Validation(format!(
+            "hashValidation.{}: invalid pattern '{}': {}",
+            surface, pattern, e
+          )));
+        }
+      }
+    }
+
+    // Notices are sent as a header value
+    let notices = self
+      .service_access_tokens
+      .iter()
+      .filter_map(|token| token.notice.as_ref())
+      .chain(&self.notice);
+    for notice in notices {
+      if notice.trim().is_empty() || !notice.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        return Err(ConfigError::Validation(format!(
+          "notice '{}' must be non-empty printable ASCII on a single line",
+          notice
+        )));
+      }
+    }
+
+    // Nested routers need a literal, non-root prefix
+    if let Some(base_path) = &self.base_path {
+      let valid = base_path.len() > 1
+        && base_path.starts_with('/')
+        && !base_path.ends_with('/')
+        && !base_path.contains("//")
+        && base_path
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || "/-_.~".contains(c));
+      if !valid {
+        return Err(ConfigError::Validation(format!(
+          "basePath '{}' must start with '/', not end with '/' and only contain letters, digits, '-', '_', '.' and '~'",
+          base_path
+        )));
+      }
+    }
+
     Ok(())
   }
 
@@ -312,8 +2438,15 @@ impl Config {
         _ => {},
       }
 
-      let sse = match &bucket.sse {
-        Some(sse) => Some(Self::resolve_sse(&bucket.name, sse)?),
+      let external_id = Self::resolve_optional_env(&bucket.external_id, &bucket.external_id_env)?;
+
+      let sse = match Self::effective_sse(bucket)? {
+        Some(sse) => Some(Self::resolve_sse(&bucket.name, &sse)?),
+        None => None,
+      };
+
+      let encryption_key = match &bucket.encryption {
+        Some(encryption) => Some(Self::resolve_encryption_key(&bucket.name, encryption)?),
         None => None,
       };
 
@@ -324,35 +2457,282 @@ impl Config {
         &format!("Bucket '{}': insecureTls", bucket.name),
       )?;
 
+      // Provider presets only ever turn on path style and turn off unsupported features
+      let preset = bucket.provider.map(Provider::preset);
+      let region = bucket.region.clone().or_else(|| {
+        preset
+          .and_then(|preset| preset.default_region)
+          .map(str::to_string)
+      });
+      // Directory buckets are served from a zonal endpoint named after their zone
+      let express_zone = (bucket.backend == BackendType::S3)
+        .then(|| s3_express_zone(&bucket.bucket_name))
+        .flatten();
+      let endpoint_url = bucket.endpoint_url.clone().or_else(|| match express_zone {
+        Some(zone) => Some(format!(
+          "https://s3express-{}.{}.amazonaws.com",
+          zone,
+          region.as_deref()?
+        )),
+        None => {
+          let template = preset?.endpoint_template?;
+          Some(template.replace("{region}", region.as_deref()?))
+        },
+      });
+
       resolved_buckets.push(ResolvedBucketConfig {
         name: bucket.name.clone(),
+        backend: bucket.backend,
         bucket_name: bucket.bucket_name.clone(),
+        path: bucket.path.clone(),
         access_key_id,
         secret_access_key,
         session_token,
-        region: bucket.region.clone(),
-        endpoint_url: bucket.endpoint_url.clone(),
+        role_arn: bucket.role_arn.clone(),
+        external_id,
+        region,
+        endpoint_url,
         tls_ca_file,
         insecure_tls,
-        force_path_style: bucket.force_path_style,
+        force_path_style: bucket.force_path_style
+          || preset.is_some_and(|preset| preset.force_path_style),
         sse,
-        timeout: bucket.timeout,
+        timeout: bucket.timeouts.operation,
+        retry: bucket.retry.clone(),
+        encryption_key,
+        compression: bucket.compression.clone(),
+        concurrency: bucket.concurrency.clone(),
+        local_tier: bucket.local_tier.clone(),
+        spill: bucket.spill.clone(),
+        ranged_reads: bucket.ranged_reads.clone(),
+        isolation: bucket.isolation.clone(),
+        fallback_bucket: bucket.fallback_bucket.clone(),
+        conditional_writes: bucket.conditional_writes
+          && preset.is_none_or(|preset| preset.conditional_writes),
+        object_tagging: bucket.object_tagging
+          && preset.is_none_or(|preset| preset.object_tagging)
+          && express_zone.is_none(),
+        storage_class: bucket.storage_class,
+        maintenance_windows: bucket.maintenance_windows.clone(),
       });
     }
 
     let mut resolved_tokens = Vec::new();
     for token in &self.service_access_tokens {
-      let access_token = Self::resolve_required_env(
-        &token.access_token,
-        &token.access_token_env,
-        &format!("Service token '{}' accessToken", token.name),
-      )?;
+      let access_token = if token.access_token.is_none()
+        && token.access_token_env.is_none()
+        && (token.access_token_hash.is_some() || self.jwt_only(&token.name))
+      {
+        Self::random_secret()
+      } else {
+        Self::resolve_required_env(
+          &token.access_token,
+          &token.access_token_env,
+          &format!("Service token '{}' accessToken", token.name),
+        )?
+      };
+      let upstream = match &token.upstream {
+        Some(upstream) => Some(ResolvedUpstreamConfig {
+          url: upstream.url.trim_end_matches('/').to_string(),
+          access_token: Self::resolve_required_env(
+            &upstream.access_token,
+            &upstream.access_token_env,
+            &format!("Service token '{}' upstream accessToken", token.name),
+          )?,
+          persist: upstream.persist,
+          timeout_seconds: upstream.timeout_seconds,
+        }),
+        None => None,
+      };
+
+      let previous_access_token =
+        if token.previous_access_token.is_some() || token.previous_access_token_env.is_some() {
+          Some(PreviousAccessToken {
+            access_token: Self::resolve_required_env(
+              &token.previous_access_token,
+              &token.previous_access_token_env,
+              &format!("Service token '{}' previousAccessToken", token.name),
+            )?,
+            valid_until: token
+              .previous_access_token_valid_until
+              .as_deref()
+              .and_then(http_date::parse_rfc3339)
+              .ok_or_else(|| {
+                ConfigError::Validation(format!(
+                  "Service token '{}': previousAccessTokenValidUntil must be an RFC 3339 timestamp",
+                  token.name
+                ))
+              })?,
+          })
+        } else {
+          None
+        };
 
       resolved_tokens.push(ResolvedServiceAccessToken {
         name: token.name.clone(),
         bucket: token.bucket.clone(),
         prefix: Self::normalize_prefix(&token.prefix),
         access_token,
+        access_token_hash: token.access_token_hash.clone(),
+        jwt_only: token.access_token.is_none()
+          && token.access_token_env.is_none()
+          && token.access_token_hash.is_none()
+          && self.jwt_only(&token.name),
+        previous_access_token,
+        permissions: token.permissions,
+        expires_at: match &token.expires_at {
+          Some(expires_at) => Some(http_date::parse_rfc3339(expires_at).ok_or_else(|| {
+            ConfigError::Validation(format!(
+              "Service token '{}': expiresAt '{}' is not an RFC 3339 timestamp",
+              token.name, expires_at
+            ))
+          })?),
+          None => None,
+        },
+        egress_daily_limit_bytes: token.egress_daily_limit_bytes,
+        quota_warning_percent: token.quota_warning_percent,
+        replica_buckets: token.replica_buckets.clone(),
+        replication: token.replication,
+        key_layout: token.key_layout,
+        legacy_prefixes: token
+          .legacy_prefixes
+          .iter()
+          .map(|prefix| Self::normalize_prefix(prefix))
+          .collect(),
+        legacy_copy_forward: token.legacy_copy_forward,
+        namespace_aliases: token
+          .namespace_aliases
+          .iter()
+          .filter_map(|alias| {
+            self
+              .service_access_tokens
+              .iter()
+              .find(|other| &other.name == alias)
+          })
+          .map(|other| NamespaceAlias {
+            token: other.name.clone(),
+            prefix: Self::normalize_prefix(&other.prefix),
+            key_layout: other.key_layout,
+          })
+          .collect(),
+        accounting_admin: token.accounting_admin,
+        variants: token.variants,
+        admin: token.admin,
+        allow_overwrite: token.allow_overwrite,
+        auth_header: token
+          .auth_header
+          .as_ref()
+          .map(|header| header.to_ascii_lowercase()),
+        default_ttl_seconds: token.default_ttl_seconds,
+        max_ttl_seconds: token.max_ttl_seconds,
+        weight: token.weight,
+        cost_tags: token.cost_tags.clone(),
+        notice: token.notice.clone().or_else(|| self.notice.clone()),
+        upstream,
+      });
+    }
+
+    let manifest = match &self.manifest {
+      Some(manifest) => Some(ResolvedManifestConfig {
+        signing_key: Self::resolve_required_env(
+          &manifest.signing_key,
+          &manifest.signing_key_env,
+          "manifest signingKey",
+        )?,
+        url_ttl_seconds: manifest.url_ttl_seconds,
+        max_hashes: manifest.max_hashes,
+        public_url: manifest
+          .public_url
+          .as_ref()
+          .map(|url| url.trim_end_matches('/').to_string())
+          .or_else(|| self.base_path.clone()),
+      }),
+      None => None,
+    };
+
+    let mut jwt_providers = Vec::new();
+    if let Some(jwt) = &self.jwt {
+      jwt_providers.push(ResolvedJwtConfig {
+        provider: "jwt".to_string(),
+        keys: match &jwt.jwks_url {
+          Some(url) => JwtKeySource::JwksUrl(url.clone()),
+          None => JwtKeySource::Secret(Self::resolve_required_env(
+            &jwt.secret,
+            &jwt.secret_env,
+            "jwt secret",
+          )?),
+        },
+        issuer: jwt.issuer.clone(),
+        audience: jwt.audience.clone(),
+        leeway_seconds: jwt.leeway_seconds,
+        jwks_cache_seconds: jwt.jwks_cache_seconds,
+        subject_is_token: false,
+        rules: jwt.rules.clone(),
+      });
+    }
+    if let Some(oidc) = &self.oidc {
+      let issuer = oidc.issuer.trim_end_matches('/').to_string();
+      jwt_providers.push(ResolvedJwtConfig {
+        provider: "oidc".to_string(),
+        keys: JwtKeySource::Discovery(issuer.clone()),
+        issuer: Some(issuer),
+        audience: Some(oidc.audience.clone()),
+        leeway_seconds: oidc.leeway_seconds,
+        jwks_cache_seconds: oidc.jwks_cache_seconds,
+        subject_is_token: false,
+        rules: oidc.rules.clone(),
+      });
+    }
+    if let Some(github) = &self.github_actions {
+      let issuer = github.issuer.trim_end_matches('/').to_string();
+      jwt_providers.push(ResolvedJwtConfig {
+        provider: "githubActions".to_string(),
+        keys: JwtKeySource::Discovery(issuer.clone()),
+        issuer: Some(issuer),
+        audience: github.audience.clone(),
+        leeway_seconds: github.leeway_seconds,
+        jwks_cache_seconds: github.jwks_cache_seconds,
+        subject_is_token: false,
+        rules: github.jwt_rules(),
+      });
+    }
+    if let Some(gitlab) = &self.gitlab {
+      let issuer = gitlab.url.trim_end_matches('/').to_string();
+      jwt_providers.push(ResolvedJwtConfig {
+        provider: "gitlab".to_string(),
+        keys: JwtKeySource::Discovery(issuer.clone()),
+        issuer: Some(issuer),
+        audience: gitlab.audience.clone(),
+        leeway_seconds: gitlab.leeway_seconds,
+        jwks_cache_seconds: gitlab.jwks_cache_seconds,
+        subject_is_token: false,
+        rules: gitlab.jwt_rules(),
+      });
+    }
+    let token_minting = match &self.token_minting {
+      Some(minting) => Some(ResolvedTokenMintingConfig {
+        signing_key: Self::resolve_required_env(
+          &minting.signing_key,
+          &minting.signing_key_env,
+          "tokenMinting signingKey",
+        )?,
+        default_ttl_seconds: minting.default_ttl_seconds,
+        max_ttl_seconds: minting.max_ttl_seconds,
+      }),
+      None => None,
+    };
+    // Minted tokens name the token they are derived from as their subject, looked up when
+    // verifying so tokens added by a reload can be minted too
+    if let Some(minting) = &token_minting {
+      jwt_providers.push(ResolvedJwtConfig {
+        provider: "tokenMinting".to_string(),
+        keys: JwtKeySource::Secret(minting.signing_key.clone()),
+        issuer: Some(MINTED_TOKEN_ISSUER.to_string()),
+        audience: None,
+        leeway_seconds: 0,
+        jwks_cache_seconds: 0,
+        subject_is_token: true,
+        rules: Vec::new(),
       });
     }
 
@@ -361,9 +2741,78 @@ impl Config {
       service_access_tokens: resolved_tokens,
       port: self.port,
       debug: self.debug,
+      shutdown: self.shutdown.clone(),
+      cache_control: self.cache_control.clone(),
+      normalize_paths: self.normalize_paths,
+      audit_namespaces: self.audit_namespaces,
+      strict_nx_spec: self.strict_nx_spec,
+      json_errors: self.json_errors,
+      allow_empty_uploads: self.allow_empty_uploads,
+      base_path: self.base_path.clone(),
+      in_flight_wait_seconds: self.in_flight_wait_seconds,
+      recent_errors_capacity: self.recent_errors_capacity,
+      mirror: self.mirror.as_ref().map(|mirror| MirrorConfig {
+        prefix: Self::normalize_prefix(&mirror.prefix),
+        ..mirror.clone()
+      }),
+      manifest,
+      affinity: self.affinity.clone(),
+      task_metadata: self.task_metadata.clone(),
+      transport_compression: self.transport_compression.clone(),
+      tus: self.tus.clone(),
+      grpc: self.grpc.clone(),
+      jwt: jwt_providers,
+      token_minting,
+      token_revocation: self.token_revocation.clone(),
+      hash_validation: self.hash_validation.clone(),
     })
   }
 
+  /// Check that a section has rules and that each of them names a configured token
+  fn validate_jwt_rules(&self, section: &str, rules: &[JwtRuleConfig]) -> Result<(), ConfigError> {
+    if rules.is_empty() {
+      return Err(ConfigError::Validation(format!(
+        "{} must have at least one rule",
+        section
+      )));
+    }
+    for rule in rules {
+      if !self
+        .service_access_tokens
+        .iter()
+        .any(|token| token.name == rule.token)
+      {
+        return Err(ConfigError::Validation(format!(
+          "{} rule references non-existent token '{}'",
+          section, rule.token
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  /// Whether a JWT rule maps to the token, which then needs no access token of its own
+  fn jwt_only(&self, token_name: &str) -> bool {
+    let jwt = self.jwt.iter().flat_map(|jwt| &jwt.rules);
+    let oidc = self.oidc.iter().flat_map(|oidc| &oidc.rules);
+    let github = self.github_actions.iter().flat_map(|github| &github.rules);
+    let gitlab = self.gitlab.iter().flat_map(|gitlab| &gitlab.rules);
+    jwt
+      .chain(oidc)
+      .map(|rule| &rule.token)
+      .chain(github.map(|rule| &rule.token))
+      .chain(gitlab.map(|rule| &rule.token))
+      .any(|token| token == token_name)
+  }
+
+  /// Unguessable internal key of a token reached through a hash or JWTs only
+  fn random_secret() -> String {
+    let mut bytes = [0u8; 32];
+    ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes)
+      .expect("system random number generator failed");
+    hex::encode(bytes)
+  }
+
   /// Resolve an optional field that can be a value or env var reference
   fn resolve_optional_env(
     value: &Option<String>,
@@ -521,6 +2970,76 @@ impl Config {
     })
   }
 
+  fn resolve_encryption_key(
+    bucket_name: &str,
+    encryption: &EncryptionConfig,
+  ) -> Result<Vec<u8>, ConfigError> {
+    let key_b64 = match &encryption.key_file {
+      Some(path) => fs::read_to_string(path).map_err(|e| {
+        ConfigError::Validation(format!(
+          "Bucket '{}': failed to read encryption.keyFile '{}': {}",
+          bucket_name, path, e
+        ))
+      })?,
+      None => Self::resolve_required_env(
+        &encryption.key_base64,
+        &encryption.key_base64_env,
+        &format!("Bucket '{}': encryption.keyBase64", bucket_name),
+      )?,
+    };
+
+    let key = general_purpose::STANDARD
+      .decode(key_b64.trim())
+      .map_err(|_| {
+        ConfigError::Validation(format!(
+          "Bucket '{}': encryption key must be valid base64",
+          bucket_name
+        ))
+      })?;
+    if key.len() != 32 {
+      return Err(ConfigError::Validation(format!(
+        "Bucket '{}': encryption key must decode to 32 bytes",
+        bucket_name
+      )));
+    }
+    Ok(key)
+  }
+
+  /// The `sse` block, or one built from the `serverSideEncryption`/`sseKmsKeyId` shorthand
+  fn effective_sse(bucket: &BucketConfig) -> Result<Option<SseConfig>, ConfigError> {
+    let shorthand = bucket.server_side_encryption.is_some() || bucket.sse_kms_key_id.is_some();
+    if !shorthand {
+      return Ok(bucket.sse.clone());
+    }
+    if bucket.sse.is_some() {
+      return Err(ConfigError::Validation(format!(
+        "Bucket '{}': use either sse or serverSideEncryption/sseKmsKeyId, not both",
+        bucket.name
+      )));
+    }
+
+    let sse_type = match bucket.server_side_encryption {
+      Some(ServerSideEncryption::Aes256) if bucket.sse_kms_key_id.is_some() => {
+        return Err(ConfigError::Validation(format!(
+          "Bucket '{}': sseKmsKeyId requires serverSideEncryption aws:kms",
+          bucket.name
+        )));
+      },
+      Some(ServerSideEncryption::Aes256) => SseType::SseS3,
+      Some(ServerSideEncryption::AwsKms) | None => SseType::SseKms,
+    };
+
+    Ok(Some(SseConfig {
+      sse_type,
+      kms_key_id: bucket.sse_kms_key_id.clone(),
+      kms_key_id_env: None,
+      kms_context: None,
+      kms_context_env: None,
+      customer_key_base64: None,
+      customer_key_base64_env: None,
+    }))
+  }
+
   fn resolve_sse(bucket_name: &str, sse: &SseConfig) -> Result<ResolvedSseConfig, ConfigError> {
     match &sse.sse_type {
       SseType::SseS3 => {
@@ -588,95 +3107,683 @@ impl Config {
     }
   }
 
-  /// Normalize prefix to ensure it starts with / and doesn't end with /
-  fn normalize_prefix(prefix: &str) -> String {
-    let trimmed = prefix.trim();
-    if trimmed.is_empty() {
-      return String::new();
+  /// Normalize prefix to ensure it starts with / and doesn't end with /
+  fn normalize_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim();
+    if trimmed.is_empty() {
+      return String::new();
+    }
+
+    let mut normalized = if !trimmed.starts_with('/') {
+      format!("/{}", trimmed)
+    } else {
+      trimmed.to_string()
+    };
+
+    // Remove trailing slash
+    if normalized.len() > 1 && normalized.ends_with('/') {
+      normalized.pop();
+    }
+
+    normalized
+  }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TomlSseType {
+  SseS3,
+  SseKms,
+  SseC,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlSseConfig {
+  #[serde(rename = "type")]
+  pub sse_type: TomlSseType,
+  pub kms_key_id: Option<String>,
+  pub kms_key_id_env: Option<String>,
+  pub kms_context: Option<KmsContext>,
+  pub kms_context_env: Option<String>,
+  pub customer_key_base64: Option<String>,
+  pub customer_key_base64_env: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlConcurrencyConfig {
+  #[serde(default = "default_initial_limit")]
+  pub initial_limit: usize,
+  #[serde(default = "default_min_limit")]
+  pub min_limit: usize,
+  #[serde(default = "default_max_limit")]
+  pub max_limit: usize,
+  #[serde(default = "default_latency_threshold_ms")]
+  pub latency_threshold_ms: u64,
+  #[serde(default = "default_backoff_ratio")]
+  pub backoff_ratio: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlLocalTierConfig {
+  pub path: String,
+  #[serde(default = "default_true")]
+  pub retain_after_upload: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlSpillConfig {
+  #[serde(default = "default_spill_threshold_bytes")]
+  pub threshold_bytes: u64,
+  pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlRangedReadsConfig {
+  #[serde(default = "default_ranged_part_size_bytes")]
+  pub part_size_bytes: u64,
+  #[serde(default = "default_ranged_concurrency")]
+  pub concurrency: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlIsolationConfig {
+  #[serde(default = "default_isolation_worker_threads")]
+  pub worker_threads: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlShutdownConfig {
+  #[serde(default = "default_drain_timeout_seconds")]
+  pub drain_timeout_seconds: u64,
+  #[serde(default = "default_flush_timeout_seconds")]
+  pub flush_timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlCacheControlConfig {
+  #[serde(default = "default_cache_max_age_seconds")]
+  pub max_age_seconds: u64,
+  #[serde(default)]
+  pub public: bool,
+}
+
+impl Default for TomlCacheControlConfig {
+  fn default() -> Self {
+    Self {
+      max_age_seconds: default_cache_max_age_seconds(),
+      public: false,
+    }
+  }
+}
+
+impl Default for TomlShutdownConfig {
+  fn default() -> Self {
+    Self {
+      drain_timeout_seconds: default_drain_timeout_seconds(),
+      flush_timeout_seconds: default_flush_timeout_seconds(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlEncryptionConfig {
+  pub key_base64: Option<String>,
+  pub key_base64_env: Option<String>,
+  pub key_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlRetryConfig {
+  #[serde(default = "default_retry_max_attempts")]
+  pub max_attempts: u32,
+  #[serde(default = "default_retry_initial_backoff_ms")]
+  pub initial_backoff_ms: u64,
+  #[serde(default = "default_retry_max_backoff_ms")]
+  pub max_backoff_ms: u64,
+  #[serde(default = "default_retry_backoff_multiplier")]
+  pub backoff_multiplier: f64,
+  #[serde(default = "default_retry_jitter")]
+  pub jitter: f64,
+  #[serde(default = "default_retry_on")]
+  pub retry_on: Vec<RetryableError>,
+}
+
+impl Default for TomlRetryConfig {
+  fn default() -> Self {
+    RetryConfig::default().into()
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlManifestConfig {
+  pub signing_key: Option<String>,
+  pub signing_key_env: Option<String>,
+  #[serde(default = "default_manifest_url_ttl_seconds")]
+  pub url_ttl_seconds: u64,
+  #[serde(default = "default_manifest_max_hashes")]
+  pub max_hashes: usize,
+  pub public_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlTokenMintingConfig {
+  pub signing_key: Option<String>,
+  pub signing_key_env: Option<String>,
+  #[serde(default = "default_minted_ttl_seconds")]
+  pub default_ttl_seconds: u64,
+  #[serde(default = "default_minted_max_ttl_seconds")]
+  pub max_ttl_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlTokenRevocationConfig {
+  pub path: String,
+}
+
+impl From<TomlTokenRevocationConfig> for TokenRevocationConfig {
+  fn from(value: TomlTokenRevocationConfig) -> Self {
+    Self { path: value.path }
+  }
+}
+
+impl From<TomlTokenMintingConfig> for TokenMintingConfig {
+  fn from(value: TomlTokenMintingConfig) -> Self {
+    Self {
+      signing_key: value.signing_key,
+      signing_key_env: value.signing_key_env,
+      default_ttl_seconds: value.default_ttl_seconds,
+      max_ttl_seconds: value.max_ttl_seconds,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlMirrorConfig {
+  pub bucket: String,
+  #[serde(default)]
+  pub prefix: String,
+  #[serde(default = "default_true")]
+  pub require_auth: bool,
+  #[serde(default = "default_negative_cache_ttl_seconds")]
+  pub negative_cache_ttl_seconds: u64,
+  #[serde(default = "default_negative_cache_max_entries")]
+  pub negative_cache_max_entries: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlBucketConfig {
+  pub name: String,
+  #[serde(rename = "type", default)]
+  pub backend: BackendType,
+  pub provider: Option<Provider>,
+  pub bucket_name: String,
+  pub path: Option<String>,
+  pub access_key_id: Option<String>,
+  pub access_key_id_env: Option<String>,
+  pub secret_access_key: Option<String>,
+  pub secret_access_key_env: Option<String>,
+  pub session_token: Option<String>,
+  pub session_token_env: Option<String>,
+  pub role_arn: Option<String>,
+  pub external_id: Option<String>,
+  pub external_id_env: Option<String>,
+  pub region: Option<String>,
+  pub endpoint_url: Option<String>,
+  pub tls_ca_file: Option<String>,
+  pub tls_ca_file_env: Option<String>,
+  pub insecure_tls: Option<bool>,
+  pub insecure_tls_env: Option<String>,
+  #[serde(default)]
+  pub force_path_style: bool,
+  pub sse: Option<TomlSseConfig>,
+  pub server_side_encryption: Option<ServerSideEncryption>,
+  pub sse_kms_key_id: Option<String>,
+  #[serde(default)]
+  pub timeouts: TimeoutsConfig,
+  #[serde(default)]
+  pub retry: TomlRetryConfig,
+  pub encryption: Option<TomlEncryptionConfig>,
+  pub compression: Option<CompressionConfig>,
+  pub concurrency: Option<TomlConcurrencyConfig>,
+  pub local_tier: Option<TomlLocalTierConfig>,
+  pub spill: Option<TomlSpillConfig>,
+  pub ranged_reads: Option<TomlRangedReadsConfig>,
+  pub isolation: Option<TomlIsolationConfig>,
+  pub fallback_bucket: Option<String>,
+  #[serde(default = "default_true")]
+  pub conditional_writes: bool,
+  #[serde(default = "default_true")]
+  pub object_tagging: bool,
+  pub storage_class: Option<StorageClass>,
+  #[serde(default)]
+  pub maintenance_windows: Vec<TomlMaintenanceWindowConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlMaintenanceWindowConfig {
+  #[serde(default)]
+  pub days: Vec<Weekday>,
+  pub start: String,
+  pub end: String,
+  #[serde(default = "default_maintenance_max_concurrency")]
+  pub max_concurrency: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlServiceAccessTokenConfig {
+  pub name: String,
+  pub bucket: String,
+  #[serde(default)]
+  pub prefix: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub access_token: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub access_token_env: Option<String>,
+  pub access_token_hash: Option<String>,
+  pub previous_access_token: Option<String>,
+  pub previous_access_token_env: Option<String>,
+  pub previous_access_token_valid_until: Option<String>,
+  #[serde(default)]
+  pub permissions: Permissions,
+  pub expires_at: Option<String>,
+  pub egress_daily_limit_bytes: Option<u64>,
+  #[serde(default = "default_quota_warning_percent")]
+  pub quota_warning_percent: u8,
+  #[serde(default)]
+  pub replica_buckets: Vec<String>,
+  #[serde(default)]
+  pub replication: ReplicationMode,
+  #[serde(default)]
+  pub key_layout: KeyLayout,
+  #[serde(default)]
+  pub legacy_prefixes: Vec<String>,
+  #[serde(default)]
+  pub legacy_copy_forward: bool,
+  #[serde(default)]
+  pub namespace_aliases: Vec<String>,
+  #[serde(default)]
+  pub accounting_admin: bool,
+  #[serde(default)]
+  pub variants: bool,
+  #[serde(default)]
+  pub admin: bool,
+  #[serde(default)]
+  pub allow_overwrite: bool,
+  pub auth_header: Option<String>,
+  pub default_ttl_seconds: Option<u64>,
+  pub max_ttl_seconds: Option<u64>,
+  #[serde(default = "default_token_weight")]
+  pub weight: u32,
+  #[serde(default)]
+  pub cost_tags: BTreeMap<String, String>,
+  pub notice: Option<String>,
+  pub upstream: Option<TomlUpstreamConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlUpstreamConfig {
+  pub url: String,
+  pub access_token: Option<String>,
+  pub access_token_env: Option<String>,
+  #[serde(default = "default_true")]
+  pub persist: bool,
+  #[serde(default = "default_upstream_timeout_seconds")]
+  pub timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlConfig {
+  pub buckets: Vec<TomlBucketConfig>,
+  pub service_access_tokens: Vec<TomlServiceAccessTokenConfig>,
+  #[serde(default = "default_port")]
+  pub port: u16,
+  #[serde(default)]
+  pub debug: bool,
+  #[serde(default)]
+  pub shutdown: TomlShutdownConfig,
+  #[serde(default)]
+  pub cache_control: TomlCacheControlConfig,
+  #[serde(default)]
+  pub normalize_paths: bool,
+  #[serde(default)]
+  pub audit_namespaces: bool,
+  #[serde(default)]
+  pub strict_nx_spec: bool,
+  #[serde(default)]
+  pub json_errors: bool,
+  #[serde(default)]
+  pub allow_empty_uploads: bool,
+  pub base_path: Option<String>,
+  #[serde(default)]
+  pub in_flight_wait_seconds: u64,
+  #[serde(default = "default_recent_errors_capacity")]
+  pub recent_errors_capacity: usize,
+  pub mirror: Option<TomlMirrorConfig>,
+  pub manifest: Option<TomlManifestConfig>,
+  pub affinity: Option<AffinityConfig>,
+  pub task_metadata: Option<TomlTaskMetadataConfig>,
+  pub transport_compression: Option<TomlTransportCompressionConfig>,
+  pub tus: Option<TomlTusConfig>,
+  pub grpc: Option<TomlGrpcConfig>,
+  pub jwt: Option<TomlJwtConfig>,
+  pub oidc: Option<TomlOidcConfig>,
+  pub github_actions: Option<TomlGithubActionsConfig>,
+  pub gitlab: Option<TomlGitlabConfig>,
+  pub token_minting: Option<TomlTokenMintingConfig>,
+  pub token_revocation: Option<TomlTokenRevocationConfig>,
+  #[serde(default)]
+  pub hash_validation: TomlHashValidationConfig,
+  pub notice: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlHashPolicyConfig {
+  pub charset: Option<HashCharset>,
+  pub min_length: Option<usize>,
+  pub max_length: Option<usize>,
+  pub pattern: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlHashValidationConfig {
+  pub nx: Option<TomlHashPolicyConfig>,
+  pub please: Option<TomlHashPolicyConfig>,
+  pub bazel: Option<TomlHashPolicyConfig>,
+  pub gradle: Option<TomlHashPolicyConfig>,
+  pub ccache: Option<TomlHashPolicyConfig>,
+  pub turbo: Option<TomlHashPolicyConfig>,
+  pub mirror: Option<TomlHashPolicyConfig>,
+}
+
+impl From<TomlHashPolicyConfig> for HashPolicyConfig {
+  fn from(value: TomlHashPolicyConfig) -> Self {
+    Self {
+      charset: value.charset,
+      min_length: value.min_length,
+      max_length: value.max_length,
+      pattern: value.pattern,
+    }
+  }
+}
+
+impl From<TomlHashValidationConfig> for HashValidationConfig {
+  fn from(value: TomlHashValidationConfig) -> Self {
+    Self {
+      nx: value.nx.map(HashPolicyConfig::from),
+      please: value.please.map(HashPolicyConfig::from),
+      bazel: value.bazel.map(HashPolicyConfig::from),
+      gradle: value.gradle.map(HashPolicyConfig::from),
+      ccache: value.ccache.map(HashPolicyConfig::from),
+      turbo: value.turbo.map(HashPolicyConfig::from),
+      mirror: value.mirror.map(HashPolicyConfig::from),
     }
+  }
+}
 
-    let mut normalized = if !trimmed.starts_with('/') {
-      format!("/{}", trimmed)
-    } else {
-      trimmed.to_string()
-    };
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlTaskMetadataConfig {
+  #[serde(default = "default_task_metadata_max_bytes")]
+  pub max_bytes: usize,
+  #[serde(default = "default_task_metadata_max_results")]
+  pub max_results: usize,
+}
 
-    // Remove trailing slash
-    if normalized.len() > 1 && normalized.ends_with('/') {
-      normalized.pop();
+impl From<TomlTaskMetadataConfig> for TaskMetadataConfig {
+  fn from(value: TomlTaskMetadataConfig) -> Self {
+    Self {
+      max_bytes: value.max_bytes,
+      max_results: value.max_results,
     }
+  }
+}
 
-    normalized
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlTusConfig {
+  pub path: Option<String>,
+  #[serde(default = "default_tus_max_size_bytes")]
+  pub max_size_bytes: u64,
+  #[serde(default = "default_tus_expiration_seconds")]
+  pub expiration_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlGrpcConfig {
+  #[serde(default = "default_grpc_port")]
+  pub port: u16,
+}
+
+impl From<TomlGrpcConfig> for GrpcConfig {
+  fn from(value: TomlGrpcConfig) -> Self {
+    Self { port: value.port }
   }
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub enum TomlSseType {
-  SseS3,
-  SseKms,
-  SseC,
+pub struct TomlJwtConfig {
+  pub secret: Option<String>,
+  pub secret_env: Option<String>,
+  pub jwks_url: Option<String>,
+  pub issuer: Option<String>,
+  pub audience: Option<String>,
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+  pub rules: Vec<TomlJwtRuleConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub struct TomlSseConfig {
-  #[serde(rename = "type")]
-  pub sse_type: TomlSseType,
-  pub kms_key_id: Option<String>,
-  pub kms_key_id_env: Option<String>,
-  pub kms_context: Option<KmsContext>,
-  pub kms_context_env: Option<String>,
-  pub customer_key_base64: Option<String>,
-  pub customer_key_base64_env: Option<String>,
+pub struct TomlOidcConfig {
+  pub issuer: String,
+  pub audience: String,
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+  pub rules: Vec<TomlJwtRuleConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub struct TomlBucketConfig {
-  pub name: String,
-  pub bucket_name: String,
-  pub access_key_id: Option<String>,
-  pub access_key_id_env: Option<String>,
-  pub secret_access_key: Option<String>,
-  pub secret_access_key_env: Option<String>,
-  pub session_token: Option<String>,
-  pub session_token_env: Option<String>,
-  pub region: Option<String>,
-  pub endpoint_url: Option<String>,
-  pub tls_ca_file: Option<String>,
-  pub tls_ca_file_env: Option<String>,
-  pub insecure_tls: Option<bool>,
-  pub insecure_tls_env: Option<String>,
+pub struct TomlGithubActionsConfig {
+  #[serde(default = "default_github_actions_issuer")]
+  pub issuer: String,
+  pub audience: Option<String>,
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+  pub rules: Vec<TomlGithubActionsRuleConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlGithubActionsRuleConfig {
+  pub token: String,
+  pub repository: String,
+  #[serde(rename = "ref")]
+  pub git_ref: Option<String>,
   #[serde(default)]
-  pub force_path_style: bool,
-  pub sse: Option<TomlSseConfig>,
-  #[serde(default = "default_timeout")]
-  pub timeout: u64,
+  pub claims: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub struct TomlServiceAccessTokenConfig {
-  pub name: String,
-  pub bucket: String,
+pub struct TomlGitlabConfig {
+  #[serde(default = "default_gitlab_url")]
+  pub url: String,
+  pub audience: Option<String>,
+  #[serde(default = "default_jwt_leeway_seconds")]
+  pub leeway_seconds: u64,
+  #[serde(default = "default_jwks_cache_seconds")]
+  pub jwks_cache_seconds: u64,
+  pub rules: Vec<TomlGitlabRuleConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlGitlabRuleConfig {
+  pub token: String,
+  pub project_path: String,
+  #[serde(rename = "ref")]
+  pub git_ref: Option<String>,
   #[serde(default)]
-  pub prefix: String,
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub access_token: Option<String>,
-  #[serde(skip_serializing_if = "Option::is_none")]
-  pub access_token_env: Option<String>,
+  pub claims: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub struct TomlConfig {
-  pub buckets: Vec<TomlBucketConfig>,
-  pub service_access_tokens: Vec<TomlServiceAccessTokenConfig>,
-  #[serde(default = "default_port")]
-  pub port: u16,
+pub struct TomlJwtRuleConfig {
+  pub token: String,
   #[serde(default)]
-  pub debug: bool,
+  pub claims: BTreeMap<String, String>,
+}
+
+impl From<TomlJwtConfig> for JwtConfig {
+  fn from(value: TomlJwtConfig) -> Self {
+    Self {
+      secret: value.secret,
+      secret_env: value.secret_env,
+      jwks_url: value.jwks_url,
+      issuer: value.issuer,
+      audience: value.audience,
+      leeway_seconds: value.leeway_seconds,
+      jwks_cache_seconds: value.jwks_cache_seconds,
+      rules: value.rules.into_iter().map(JwtRuleConfig::from).collect(),
+    }
+  }
+}
+
+impl From<TomlOidcConfig> for OidcConfig {
+  fn from(value: TomlOidcConfig) -> Self {
+    Self {
+      issuer: value.issuer,
+      audience: value.audience,
+      leeway_seconds: value.leeway_seconds,
+      jwks_cache_seconds: value.jwks_cache_seconds,
+      rules: value.rules.into_iter().map(JwtRuleConfig::from).collect(),
+    }
+  }
+}
+
+impl From<TomlGithubActionsConfig> for GithubActionsConfig {
+  fn from(value: TomlGithubActionsConfig) -> Self {
+    Self {
+      issuer: value.issuer,
+      audience: value.audience,
+      leeway_seconds: value.leeway_seconds,
+      jwks_cache_seconds: value.jwks_cache_seconds,
+      rules: value
+        .rules
+        .into_iter()
+        .map(GithubActionsRuleConfig::from)
+        .collect(),
+    }
+  }
+}
+
+impl From<TomlGithubActionsRuleConfig> for GithubActionsRuleConfig {
+  fn from(value: TomlGithubActionsRuleConfig) -> Self {
+    Self {
+      token: value.token,
+      repository: value.repository,
+      git_ref: value.git_ref,
+      claims: value.claims,
+    }
+  }
+}
+
+impl From<TomlGitlabConfig> for GitlabConfig {
+  fn from(value: TomlGitlabConfig) -> Self {
+    Self {
+      url: value.url,
+      audience: value.audience,
+      leeway_seconds: value.leeway_seconds,
+      jwks_cache_seconds: value.jwks_cache_seconds,
+      rules: value
+        .rules
+        .into_iter()
+        .map(GitlabRuleConfig::from)
+        .collect(),
+    }
+  }
+}
+
+impl From<TomlGitlabRuleConfig> for GitlabRuleConfig {
+  fn from(value: TomlGitlabRuleConfig) -> Self {
+    Self {
+      token: value.token,
+      project_path: value.project_path,
+      git_ref: value.git_ref,
+      claims: value.claims,
+    }
+  }
+}
+
+impl From<TomlJwtRuleConfig> for JwtRuleConfig {
+  fn from(value: TomlJwtRuleConfig) -> Self {
+    Self {
+      token: value.token,
+      claims: value.claims,
+    }
+  }
+}
+
+impl From<TomlTusConfig> for TusConfig {
+  fn from(value: TomlTusConfig) -> Self {
+    Self {
+      path: value.path,
+      max_size_bytes: value.max_size_bytes,
+      expiration_seconds: value.expiration_seconds,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TomlTransportCompressionConfig {
+  #[serde(default = "default_transport_min_size_bytes")]
+  pub min_size_bytes: u64,
+  #[serde(default = "default_compression_level")]
+  pub zstd_level: i32,
+  #[serde(default = "default_transport_gzip_level")]
+  pub gzip_level: i32,
+}
+
+impl From<TomlTransportCompressionConfig> for TransportCompressionConfig {
+  fn from(value: TomlTransportCompressionConfig) -> Self {
+    Self {
+      min_size_bytes: value.min_size_bytes,
+      zstd_level: value.zstd_level,
+      gzip_level: value.gzip_level,
+    }
+  }
 }
 
 impl From<TomlSseType> for SseType {
@@ -703,17 +3810,148 @@ impl From<TomlSseConfig> for SseConfig {
   }
 }
 
+impl From<TomlConcurrencyConfig> for ConcurrencyConfig {
+  fn from(value: TomlConcurrencyConfig) -> Self {
+    Self {
+      initial_limit: value.initial_limit,
+      min_limit: value.min_limit,
+      max_limit: value.max_limit,
+      latency_threshold_ms: value.latency_threshold_ms,
+      backoff_ratio: value.backoff_ratio,
+    }
+  }
+}
+
+impl From<TomlLocalTierConfig> for LocalTierConfig {
+  fn from(value: TomlLocalTierConfig) -> Self {
+    Self {
+      path: value.path,
+      retain_after_upload: value.retain_after_upload,
+    }
+  }
+}
+
+impl From<TomlSpillConfig> for SpillConfig {
+  fn from(value: TomlSpillConfig) -> Self {
+    Self {
+      threshold_bytes: value.threshold_bytes,
+      path: value.path,
+    }
+  }
+}
+
+impl From<TomlRangedReadsConfig> for RangedReadsConfig {
+  fn from(value: TomlRangedReadsConfig) -> Self {
+    Self {
+      part_size_bytes: value.part_size_bytes,
+      concurrency: value.concurrency,
+    }
+  }
+}
+
+impl From<TomlIsolationConfig> for IsolationConfig {
+  fn from(value: TomlIsolationConfig) -> Self {
+    Self {
+      worker_threads: value.worker_threads,
+    }
+  }
+}
+
+impl From<TomlCacheControlConfig> for CacheControlConfig {
+  fn from(value: TomlCacheControlConfig) -> Self {
+    Self {
+      max_age_seconds: value.max_age_seconds,
+      public: value.public,
+    }
+  }
+}
+
+impl From<TomlShutdownConfig> for ShutdownConfig {
+  fn from(value: TomlShutdownConfig) -> Self {
+    Self {
+      drain_timeout_seconds: value.drain_timeout_seconds,
+      flush_timeout_seconds: value.flush_timeout_seconds,
+    }
+  }
+}
+
+impl From<TomlEncryptionConfig> for EncryptionConfig {
+  fn from(value: TomlEncryptionConfig) -> Self {
+    Self {
+      key_base64: value.key_base64,
+      key_base64_env: value.key_base64_env,
+      key_file: value.key_file,
+    }
+  }
+}
+
+impl From<TomlRetryConfig> for RetryConfig {
+  fn from(value: TomlRetryConfig) -> Self {
+    Self {
+      max_attempts: value.max_attempts,
+      initial_backoff_ms: value.initial_backoff_ms,
+      max_backoff_ms: value.max_backoff_ms,
+      backoff_multiplier: value.backoff_multiplier,
+      jitter: value.jitter,
+      retry_on: value.retry_on,
+    }
+  }
+}
+
+impl From<RetryConfig> for TomlRetryConfig {
+  fn from(value: RetryConfig) -> Self {
+    Self {
+      max_attempts: value.max_attempts,
+      initial_backoff_ms: value.initial_backoff_ms,
+      max_backoff_ms: value.max_backoff_ms,
+      backoff_multiplier: value.backoff_multiplier,
+      jitter: value.jitter,
+      retry_on: value.retry_on,
+    }
+  }
+}
+
+impl From<TomlManifestConfig> for ManifestConfig {
+  fn from(value: TomlManifestConfig) -> Self {
+    Self {
+      signing_key: value.signing_key,
+      signing_key_env: value.signing_key_env,
+      url_ttl_seconds: value.url_ttl_seconds,
+      max_hashes: value.max_hashes,
+      public_url: value.public_url,
+    }
+  }
+}
+
+impl From<TomlMirrorConfig> for MirrorConfig {
+  fn from(value: TomlMirrorConfig) -> Self {
+    Self {
+      bucket: value.bucket,
+      prefix: value.prefix,
+      require_auth: value.require_auth,
+      negative_cache_ttl_seconds: value.negative_cache_ttl_seconds,
+      negative_cache_max_entries: value.negative_cache_max_entries,
+    }
+  }
+}
+
 impl From<TomlBucketConfig> for BucketConfig {
   fn from(value: TomlBucketConfig) -> Self {
     Self {
       name: value.name,
+      backend: value.backend,
+      provider: value.provider,
       bucket_name: value.bucket_name,
+      path: value.path,
       access_key_id: value.access_key_id,
       access_key_id_env: value.access_key_id_env,
       secret_access_key: value.secret_access_key,
       secret_access_key_env: value.secret_access_key_env,
       session_token: value.session_token,
       session_token_env: value.session_token_env,
+      role_arn: value.role_arn,
+      external_id: value.external_id,
+      external_id_env: value.external_id_env,
       region: value.region,
       endpoint_url: value.endpoint_url,
       tls_ca_file: value.tls_ca_file,
@@ -722,7 +3960,37 @@ impl From<TomlBucketConfig> for BucketConfig {
       insecure_tls_env: value.insecure_tls_env,
       force_path_style: value.force_path_style,
       sse: value.sse.map(SseConfig::from),
-      timeout: value.timeout,
+      server_side_encryption: value.server_side_encryption,
+      sse_kms_key_id: value.sse_kms_key_id,
+      timeouts: value.timeouts,
+      retry: value.retry.into(),
+      encryption: value.encryption.map(EncryptionConfig::from),
+      compression: value.compression,
+      concurrency: value.concurrency.map(ConcurrencyConfig::from),
+      local_tier: value.local_tier.map(LocalTierConfig::from),
+      spill: value.spill.map(SpillConfig::from),
+      ranged_reads: value.ranged_reads.map(RangedReadsConfig::from),
+      isolation: value.isolation.map(IsolationConfig::from),
+      fallback_bucket: value.fallback_bucket,
+      conditional_writes: value.conditional_writes,
+      object_tagging: value.object_tagging,
+      storage_class: value.storage_class,
+      maintenance_windows: value
+        .maintenance_windows
+        .into_iter()
+        .map(MaintenanceWindowConfig::from)
+        .collect(),
+    }
+  }
+}
+
+impl From<TomlMaintenanceWindowConfig> for MaintenanceWindowConfig {
+  fn from(value: TomlMaintenanceWindowConfig) -> Self {
+    Self {
+      days: value.days,
+      start: value.start,
+      end: value.end,
+      max_concurrency: value.max_concurrency,
     }
   }
 }
@@ -735,6 +4003,43 @@ impl From<TomlServiceAccessTokenConfig> for ServiceAccessTokenConfig {
       prefix: value.prefix,
       access_token: value.access_token,
       access_token_env: value.access_token_env,
+      access_token_hash: value.access_token_hash,
+      previous_access_token: value.previous_access_token,
+      previous_access_token_env: value.previous_access_token_env,
+      previous_access_token_valid_until: value.previous_access_token_valid_until,
+      permissions: value.permissions,
+      expires_at: value.expires_at,
+      egress_daily_limit_bytes: value.egress_daily_limit_bytes,
+      quota_warning_percent: value.quota_warning_percent,
+      replica_buckets: value.replica_buckets,
+      replication: value.replication,
+      key_layout: value.key_layout,
+      legacy_prefixes: value.legacy_prefixes,
+      legacy_copy_forward: value.legacy_copy_forward,
+      namespace_aliases: value.namespace_aliases,
+      accounting_admin: value.accounting_admin,
+      variants: value.variants,
+      admin: value.admin,
+      allow_overwrite: value.allow_overwrite,
+      auth_header: value.auth_header,
+      default_ttl_seconds: value.default_ttl_seconds,
+      max_ttl_seconds: value.max_ttl_seconds,
+      weight: value.weight,
+      cost_tags: value.cost_tags,
+      notice: value.notice,
+      upstream: value.upstream.map(UpstreamConfig::from),
+    }
+  }
+}
+
+impl From<TomlUpstreamConfig> for UpstreamConfig {
+  fn from(value: TomlUpstreamConfig) -> Self {
+    Self {
+      url: value.url,
+      access_token: value.access_token,
+      access_token_env: value.access_token_env,
+      persist: value.persist,
+      timeout_seconds: value.timeout_seconds,
     }
   }
 }
@@ -750,6 +4055,33 @@ impl From<TomlConfig> for Config {
         .collect(),
       port: value.port,
       debug: value.debug,
+      shutdown: value.shutdown.into(),
+      cache_control: value.cache_control.into(),
+      normalize_paths: value.normalize_paths,
+      audit_namespaces: value.audit_namespaces,
+      strict_nx_spec: value.strict_nx_spec,
+      json_errors: value.json_errors,
+      allow_empty_uploads: value.allow_empty_uploads,
+      base_path: value.base_path,
+      in_flight_wait_seconds: value.in_flight_wait_seconds,
+      recent_errors_capacity: value.recent_errors_capacity,
+      mirror: value.mirror.map(MirrorConfig::from),
+      manifest: value.manifest.map(ManifestConfig::from),
+      affinity: value.affinity,
+      task_metadata: value.task_metadata.map(TaskMetadataConfig::from),
+      transport_compression: value
+        .transport_compression
+        .map(TransportCompressionConfig::from),
+      tus: value.tus.map(TusConfig::from),
+      grpc: value.grpc.map(GrpcConfig::from),
+      jwt: value.jwt.map(JwtConfig::from),
+      oidc: value.oidc.map(OidcConfig::from),
+      github_actions: value.github_actions.map(GithubActionsConfig::from),
+      gitlab: value.gitlab.map(GitlabConfig::from),
+      token_minting: value.token_minting.map(TokenMintingConfig::from),
+      token_revocation: value.token_revocation.map(TokenRevocationConfig::from),
+      hash_validation: value.hash_validation.into(),
+      notice: value.notice,
     }
   }
 }
@@ -761,6 +4093,69 @@ pub struct ResolvedConfig {
   pub service_access_tokens: Vec<ResolvedServiceAccessToken>,
   pub port: u16,
   pub debug: bool,
+  pub shutdown: ShutdownConfig,
+  pub cache_control: CacheControlConfig,
+  pub normalize_paths: bool,
+  pub audit_namespaces: bool,
+  pub strict_nx_spec: bool,
+  pub json_errors: bool,
+  pub allow_empty_uploads: bool,
+  /// Route prefix, without a trailing slash
+  pub base_path: Option<String>,
+  pub in_flight_wait_seconds: u64,
+  pub recent_errors_capacity: usize,
+  pub mirror: Option<MirrorConfig>,
+  pub manifest: Option<ResolvedManifestConfig>,
+  pub affinity: Option<AffinityConfig>,
+  pub task_metadata: Option<TaskMetadataConfig>,
+  pub transport_compression: Option<TransportCompressionConfig>,
+  pub tus: Option<TusConfig>,
+  pub grpc: Option<GrpcConfig>,
+  /// JWT verifiers of the jwt, oidc, githubActions and gitlab sections and of minted tokens,
+  /// in that order
+  pub jwt: Vec<ResolvedJwtConfig>,
+  pub token_minting: Option<ResolvedTokenMintingConfig>,
+  pub token_revocation: Option<TokenRevocationConfig>,
+  pub hash_validation: HashValidationConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedJwtConfig {
+  /// Section the verifier was configured in, used in logs
+  pub provider: String,
+  pub keys: JwtKeySource,
+  pub issuer: Option<String>,
+  pub audience: Option<String>,
+  pub leeway_seconds: u64,
+  pub jwks_cache_seconds: u64,
+  /// The `sub` claim names the service token, as in minted tokens, instead of rules mapping
+  /// claims to one
+  pub subject_is_token: bool,
+  pub rules: Vec<JwtRuleConfig>,
+}
+
+/// Where the keys verifying JWT signatures come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum JwtKeySource {
+  Secret(String),
+  JwksUrl(String),
+  /// Keys at the `jwks_uri` of the issuer's OpenID provider configuration
+  Discovery(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedTokenMintingConfig {
+  pub signing_key: String,
+  pub default_ttl_seconds: u64,
+  pub max_ttl_seconds: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedManifestConfig {
+  pub signing_key: String,
+  pub url_ttl_seconds: u64,
+  pub max_hashes: usize,
+  pub public_url: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -778,10 +4173,15 @@ pub enum ResolvedSseConfig {
 #[derive(Debug, Clone)]
 pub struct ResolvedBucketConfig {
   pub name: String,
+  pub backend: BackendType,
   pub bucket_name: String,
+  pub path: Option<String>,
   pub access_key_id: Option<String>,
   pub secret_access_key: Option<String>,
   pub session_token: Option<String>,
+  /// IAM role assumed with the credentials above
+  pub role_arn: Option<String>,
+  pub external_id: Option<String>,
   pub region: Option<String>,
   pub endpoint_url: Option<String>,
   pub tls_ca_file: Option<String>,
@@ -789,14 +4189,191 @@ pub struct ResolvedBucketConfig {
   pub force_path_style: bool,
   pub sse: Option<ResolvedSseConfig>,
   pub timeout: u64,
+  pub retry: RetryConfig,
+  /// 256-bit client-side encryption key
+  pub encryption_key: Option<Vec<u8>>,
+  pub compression: Option<CompressionConfig>,
+  pub concurrency: Option<ConcurrencyConfig>,
+  pub local_tier: Option<LocalTierConfig>,
+  pub spill: Option<SpillConfig>,
+  pub ranged_reads: Option<RangedReadsConfig>,
+  pub isolation: Option<IsolationConfig>,
+  pub fallback_bucket: Option<String>,
+  pub conditional_writes: bool,
+  pub object_tagging: bool,
+  pub storage_class: Option<StorageClass>,
+  pub maintenance_windows: Vec<MaintenanceWindowConfig>,
+}
+
+/// A bucket with the same defaults as an otherwise empty `buckets` entry
+impl Default for ResolvedBucketConfig {
+  fn default() -> Self {
+    Self {
+      name: String::new(),
+      backend: BackendType::default(),
+      bucket_name: String::new(),
+      path: None,
+      access_key_id: None,
+      secret_access_key: None,
+      session_token: None,
+      role_arn: None,
+      external_id: None,
+      region: None,
+      endpoint_url: None,
+      tls_ca_file: None,
+      insecure_tls: None,
+      force_path_style: false,
+      sse: None,
+      timeout: default_timeout(),
+      retry: RetryConfig::default(),
+      encryption_key: None,
+      compression: None,
+      concurrency: None,
+      local_tier: None,
+      spill: None,
+      ranged_reads: None,
+      isolation: None,
+      fallback_bucket: None,
+      conditional_writes: true,
+      object_tagging: true,
+      storage_class: None,
+      maintenance_windows: Vec::new(),
+    }
+  }
+}
+
+/// Namespace of another token that reads fall back to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceAlias {
+  pub token: String,
+  pub prefix: String,
+  pub key_layout: KeyLayout,
+}
+
+/// Access token a token was rotated away from, accepted until `valid_until`
+#[derive(Debug, Clone)]
+pub struct PreviousAccessToken {
+  pub access_token: String,
+  /// Unix time in seconds from which the previous token is rejected
+  pub valid_until: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedServiceAccessToken {
+  pub name: String,
+  pub bucket: String,
+  pub prefix: String,
+  /// Internal key the token is stored under; random if the token is configured as a hash
+  pub access_token: String,
+  pub access_token_hash: Option<String>,
+  /// Reached through JWTs only, so `access_token` is random rather than a configured secret
+  pub jwt_only: bool,
+  pub previous_access_token: Option<PreviousAccessToken>,
+  pub permissions: Permissions,
+  /// Unix time in seconds from which the token is rejected
+  pub expires_at: Option<u64>,
+  pub egress_daily_limit_bytes: Option<u64>,
+  pub quota_warning_percent: u8,
+  pub replica_buckets: Vec<String>,
+  pub replication: ReplicationMode,
+  pub key_layout: KeyLayout,
+  pub legacy_prefixes: Vec<String>,
+  pub legacy_copy_forward: bool,
+  /// Namespaces of aliased tokens, in configured order
+  pub namespace_aliases: Vec<NamespaceAlias>,
+  pub accounting_admin: bool,
+  pub variants: bool,
+  pub admin: bool,
+  pub allow_overwrite: bool,
+  /// Lowercase name of the extra header the token is accepted in
+  pub auth_header: Option<String>,
+  pub default_ttl_seconds: Option<u64>,
+  pub max_ttl_seconds: Option<u64>,
+  pub weight: u32,
+  pub cost_tags: BTreeMap<String, String>,
+  /// Token notice, or the global notice if the token sets none
+  pub notice: Option<String>,
+  pub upstream: Option<ResolvedUpstreamConfig>,
+}
+
+/// A token with the same defaults as an otherwise empty `serviceAccessTokens` entry
+impl Default for ResolvedServiceAccessToken {
+  fn default() -> Self {
+    Self {
+      name: String::new(),
+      bucket: String::new(),
+      prefix: String::new(),
+      access_token: String::new(),
+      access_token_hash: None,
+      jwt_only: false,
+      previous_access_token: None,
+      permissions: Permissions::default(),
+      expires_at: None,
+      egress_daily_limit_bytes: None,
+      quota_warning_percent: default_quota_warning_percent(),
+      replica_buckets: Vec::new(),
+      replication: ReplicationMode::default(),
+      key_layout: KeyLayout::default(),
+      legacy_prefixes: Vec::new(),
+      legacy_copy_forward: false,
+      namespace_aliases: Vec::new(),
+      accounting_admin: false,
+      variants: false,
+      admin: false,
+      allow_overwrite: false,
+      auth_header: None,
+      default_ttl_seconds: None,
+      max_ttl_seconds: None,
+      weight: default_token_weight(),
+      cost_tags: BTreeMap::new(),
+      notice: None,
+      upstream: None,
+    }
+  }
 }
 
 #[derive(Debug, Clone)]
-pub struct ResolvedServiceAccessToken {
-  pub name: String,
-  pub bucket: String,
-  pub prefix: String,
+pub struct ResolvedUpstreamConfig {
+  /// Base URL without a trailing slash
+  pub url: String,
   pub access_token: String,
+  pub persist: bool,
+  pub timeout_seconds: u64,
+}
+
+impl ResolvedServiceAccessToken {
+  /// Retention for an upload: the requested TTL capped at `max_ttl_seconds`, else the default
+  ///
+  /// Requests are ignored for tokens without a maximum.
+  pub fn effective_ttl(&self, requested: Option<u64>) -> Option<u64> {
+    match (requested, self.max_ttl_seconds) {
+      (Some(requested), Some(max)) => Some(requested.min(max)),
+      _ => self.default_ttl_seconds,
+    }
+  }
+
+  /// Whether the token has expired at Unix time `now`
+  pub fn is_expired_at(&self, now: u64) -> bool {
+    self.expires_at.is_some_and(|expires_at| now >= expires_at)
+  }
+
+  /// Names of what the token may do, for auditing access
+  pub fn scopes(&self) -> Vec<&'static str> {
+    let mut scopes = Vec::new();
+    for (granted, scope) in [
+      (self.permissions.can_read(), "read"),
+      (self.permissions.can_write(), "write"),
+      (self.allow_overwrite, "overwrite"),
+      (self.variants, "variants"),
+      (self.accounting_admin, "accounting"),
+      (self.admin, "admin"),
+    ] {
+      if granted {
+        scopes.push(scope);
+      }
+    }
+    scopes
+  }
 }
 
 impl ResolvedConfig {
@@ -849,9 +4426,59 @@ mod tests {
         prefix: "/ci".to_string(),
         access_token: Some("token".to_string()),
         access_token_env: None,
+        egress_daily_limit_bytes: None,
+        replica_buckets: vec![],
+        replication: ReplicationMode::Async,
+        key_layout: KeyLayout::Plain,
+        legacy_prefixes: vec![],
+        legacy_copy_forward: false,
+        quota_warning_percent: 80,
+        accounting_admin: false,
+        variants: false,
+        admin: false,
+        default_ttl_seconds: None,
+        max_ttl_seconds: None,
+        weight: 1,
+        cost_tags: Default::default(),
+        notice: None,
+        namespace_aliases: vec![],
+        allow_overwrite: false,
+        auth_header: None,
+        upstream: None,
+        permissions: Default::default(),
+        expires_at: None,
+        access_token_hash: None,
+        previous_access_token: None,
+        previous_access_token_env: None,
+        previous_access_token_valid_until: None,
       }],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
     };
 
     assert!(config.validate().is_err());
@@ -877,11 +4504,57 @@ mod tests {
         insecure_tls_env: None,
         force_path_style: false,
         sse: None,
-        timeout: 30,
+        timeouts: TimeoutsConfig::default(),
+        concurrency: None,
+        local_tier: None,
+        fallback_bucket: None,
+        backend: BackendType::S3,
+        path: None,
+        conditional_writes: true,
+        retry: RetryConfig::default(),
+        encryption: None,
+        server_side_encryption: None,
+        sse_kms_key_id: None,
+        compression: None,
+        storage_class: None,
+        maintenance_windows: Vec::new(),
+        object_tagging: true,
+        spill: None,
+        provider: None,
+        role_arn: None,
+        external_id: None,
+        external_id_env: None,
+        ranged_reads: None,
+        isolation: None,
       }],
       service_access_tokens: vec![],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
     };
 
     assert!(config.validate().is_err());
@@ -908,7 +4581,28 @@ mod tests {
           insecure_tls_env: None,
           force_path_style: false,
           sse: None,
-          timeout: 30,
+          timeouts: TimeoutsConfig::default(),
+          concurrency: None,
+          local_tier: None,
+          fallback_bucket: None,
+          backend: BackendType::S3,
+          path: None,
+          conditional_writes: true,
+          retry: RetryConfig::default(),
+          encryption: None,
+          server_side_encryption: None,
+          sse_kms_key_id: None,
+          compression: None,
+          storage_class: None,
+          maintenance_windows: Vec::new(),
+          object_tagging: true,
+          spill: None,
+          provider: None,
+          role_arn: None,
+          external_id: None,
+          external_id_env: None,
+          ranged_reads: None,
+          isolation: None,
         },
         BucketConfig {
           name: "bucket1".to_string(),
@@ -927,7 +4621,28 @@ mod tests {
           insecure_tls_env: None,
           force_path_style: false,
           sse: None,
-          timeout: 30,
+          timeouts: TimeoutsConfig::default(),
+          concurrency: None,
+          local_tier: None,
+          fallback_bucket: None,
+          backend: BackendType::S3,
+          path: None,
+          conditional_writes: true,
+          retry: RetryConfig::default(),
+          encryption: None,
+          server_side_encryption: None,
+          sse_kms_key_id: None,
+          compression: None,
+          storage_class: None,
+          maintenance_windows: Vec::new(),
+          object_tagging: true,
+          spill: None,
+          provider: None,
+          role_arn: None,
+          external_id: None,
+          external_id_env: None,
+          ranged_reads: None,
+          isolation: None,
         },
       ],
       service_access_tokens: vec![ServiceAccessTokenConfig {
@@ -936,9 +4651,59 @@ mod tests {
         prefix: "/ci".to_string(),
         access_token: Some("token".to_string()),
         access_token_env: None,
+        egress_daily_limit_bytes: None,
+        replica_buckets: vec![],
+        replication: ReplicationMode::Async,
+        key_layout: KeyLayout::Plain,
+        legacy_prefixes: vec![],
+        legacy_copy_forward: false,
+        quota_warning_percent: 80,
+        accounting_admin: false,
+        variants: false,
+        admin: false,
+        default_ttl_seconds: None,
+        max_ttl_seconds: None,
+        weight: 1,
+        cost_tags: Default::default(),
+        notice: None,
+        namespace_aliases: vec![],
+        allow_overwrite: false,
+        auth_header: None,
+        upstream: None,
+        permissions: Default::default(),
+        expires_at: None,
+        access_token_hash: None,
+        previous_access_token: None,
+        previous_access_token_env: None,
+        previous_access_token_valid_until: None,
       }],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
     };
 
     assert!(config.validate().is_err());
@@ -964,7 +4729,28 @@ mod tests {
         insecure_tls_env: None,
         force_path_style: false,
         sse: None,
-        timeout: 30,
+        timeouts: TimeoutsConfig::default(),
+        concurrency: None,
+        local_tier: None,
+        fallback_bucket: None,
+        backend: BackendType::S3,
+        path: None,
+        conditional_writes: true,
+        retry: RetryConfig::default(),
+        encryption: None,
+        server_side_encryption: None,
+        sse_kms_key_id: None,
+        compression: None,
+        storage_class: None,
+        maintenance_windows: Vec::new(),
+        object_tagging: true,
+        spill: None,
+        provider: None,
+        role_arn: None,
+        external_id: None,
+        external_id_env: None,
+        ranged_reads: None,
+        isolation: None,
       }],
       service_access_tokens: vec![ServiceAccessTokenConfig {
         name: "test".to_string(),
@@ -972,11 +4758,1079 @@ mod tests {
         prefix: "/ci".to_string(),
         access_token: Some("token".to_string()),
         access_token_env: None,
+        egress_daily_limit_bytes: None,
+        replica_buckets: vec![],
+        replication: ReplicationMode::Async,
+        key_layout: KeyLayout::Plain,
+        legacy_prefixes: vec![],
+        legacy_copy_forward: false,
+        quota_warning_percent: 80,
+        accounting_admin: false,
+        variants: false,
+        admin: false,
+        default_ttl_seconds: None,
+        max_ttl_seconds: None,
+        weight: 1,
+        cost_tags: Default::default(),
+        notice: None,
+        namespace_aliases: vec![],
+        allow_overwrite: false,
+        auth_header: None,
+        upstream: None,
+        permissions: Default::default(),
+        expires_at: None,
+        access_token_hash: None,
+        previous_access_token: None,
+        previous_access_token_env: None,
+        previous_access_token_valid_until: None,
       }],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
+    };
+
+    assert!(config.validate().is_err());
+  }
+
+  #[test]
+  fn test_validation_fallback_bucket() {
+    let yaml = r#"
+buckets:
+  - name: primary
+    bucketName: primary-bucket
+    fallbackBucket: FALLBACK
+  - name: secondary
+    bucketName: secondary-bucket
+serviceAccessTokens:
+  - name: ci
+    bucket: primary
+    prefix: /ci
+    accessToken: token
+"#;
+
+    let config: Config = serde_yml::from_str(&yaml.replace("FALLBACK", "secondary")).unwrap();
+    assert!(config.validate().is_ok());
+
+    let config: Config = serde_yml::from_str(&yaml.replace("FALLBACK", "primary")).unwrap();
+    assert!(config.validate().is_err());
+
+    let config: Config = serde_yml::from_str(&yaml.replace("FALLBACK", "missing")).unwrap();
+    assert!(config.validate().is_err());
+  }
+
+  #[test]
+  fn test_backend_type() {
+    let yaml = r#"
+buckets:
+  - name: default
+    bucketName: s3-bucket
+  - name: local
+    type: fs
+    bucketName: local
+    path: /var/cache/nx
+serviceAccessTokens:
+  - name: ci
+    bucket: local
+    prefix: /ci
+    accessToken: token
+"#;
+
+    let config: Config = serde_yml::from_str(yaml).unwrap();
+    assert_eq!(config.buckets[0].backend, BackendType::S3);
+    assert_eq!(config.buckets[1].backend, BackendType::Fs);
+    assert!(config.validate().is_ok());
+
+    let config: Config =
+      serde_yml::from_str(&yaml.replace("    path: /var/cache/nx\n", "")).unwrap();
+    assert!(config.validate().is_err());
+  }
+
+  #[test]
+  fn test_legacy_timeout_is_migrated() {
+    let yaml = r#"
+buckets:
+  - name: bucket1
+    bucketName: my-bucket
+    timeout: 45
+serviceAccessTokens:
+  - name: ci
+    bucket: bucket1
+    prefix: /ci
+    accessToken: token
+"#;
+    let config = Config::from_yaml_str(yaml).unwrap();
+    assert_eq!(config.buckets[0].timeouts.operation, 45);
+
+    let toml = r#"
+[[buckets]]
+name = "bucket1"
+bucket_name = "my-bucket"
+timeout = 45
+
+[[service_access_tokens]]
+name = "ci"
+bucket = "bucket1"
+prefix = "/ci"
+access_token = "token"
+"#;
+    let config = Config::from_toml_str(toml).unwrap();
+    assert_eq!(config.buckets[0].timeouts.operation, 45);
+  }
+
+  #[test]
+  fn test_shutdown_defaults() {
+    let config: Config = serde_yml::from_str(
+      r#"
+buckets: []
+serviceAccessTokens: []
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.shutdown, ShutdownConfig::default());
+    assert_eq!(config.shutdown.drain_timeout_seconds, 60);
+    assert_eq!(config.shutdown.flush_timeout_seconds, 5);
+  }
+
+  #[test]
+  fn test_cache_control() {
+    let config: Config = serde_yml::from_str(
+      r#"
+buckets: []
+serviceAccessTokens: []
+cacheControl:
+  public: true
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.cache_control.max_age_seconds, 31_536_000);
+    assert!(config.cache_control.public);
+  }
+
+  #[test]
+  fn test_mirror_defaults_and_prefix() {
+    let config = Config::from_yaml_str(
+      r#"
+apiVersion: v2
+buckets:
+  - name: main
+    bucketName: cache
+serviceAccessTokens: []
+mirror:
+  bucket: main
+  prefix: ccache/
+"#,
+    )
+    .unwrap();
+
+    let mirror = config.mirror.as_ref().unwrap();
+    assert!(mirror.require_auth);
+    assert_eq!(mirror.negative_cache_ttl_seconds, 300);
+    assert_eq!(mirror.negative_cache_max_entries, 100_000);
+
+    let resolved = config.resolve_env_vars().unwrap();
+    assert_eq!(resolved.mirror.unwrap().prefix, "/ccache");
+  }
+
+  #[test]
+  fn test_sse_shorthand() {
+    let yaml = |sse: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\n{}serviceAccessTokens: []\n",
+        sse
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("    sseKmsKeyId: alias/nx\n")).unwrap();
+    let resolved = config.resolve_env_vars().unwrap();
+    assert!(matches!(
+      resolved.buckets[0].sse,
+      Some(ResolvedSseConfig::SseKms { ref key_id, context: None }) if key_id == "alias/nx"
+    ));
+
+    let config = Config::from_yaml_str(&yaml("    serverSideEncryption: AES256\n")).unwrap();
+    let resolved = config.resolve_env_vars().unwrap();
+    assert!(matches!(
+      resolved.buckets[0].sse,
+      Some(ResolvedSseConfig::SseS3)
+    ));
+
+    let config = Config::from_yaml_str(&yaml(
+      "    serverSideEncryption: AES256\n    sseKmsKeyId: alias/nx\n",
+    ))
+    .unwrap();
+    assert!(config.resolve_env_vars().is_err());
+
+    let config = Config::from_yaml_str(&yaml(
+      "    sseKmsKeyId: alias/nx\n    sse:\n      type: sseS3\n",
+    ))
+    .unwrap();
+    assert!(config.resolve_env_vars().is_err());
+  }
+
+  #[test]
+  fn test_storage_class() {
+    let yaml = |class: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\n    storageClass: {}\nserviceAccessTokens: []\n",
+        class
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("STANDARD_IA")).unwrap();
+    assert_eq!(
+      config.buckets[0].storage_class,
+      Some(StorageClass::StandardIa)
+    );
+    assert_eq!(
+      StorageClass::IntelligentTiering.as_str(),
+      "INTELLIGENT_TIERING"
+    );
+    assert!(Config::from_yaml_str(&yaml("GLACIER")).is_err());
+  }
+
+  #[test]
+  fn test_provider_presets() {
+    let yaml = |bucket: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\n    accessKeyId: key\n    secretAccessKey: secret\n{}serviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\n",
+        bucket
+      )
+    };
+
+    let config =
+      Config::from_yaml_str(&yaml("    provider: wasabi\n    region: eu-central-1\n")).unwrap();
+    assert!(config.validate().is_ok());
+    let bucket = &config.resolve_env_vars().unwrap().buckets[0];
+    assert_eq!(
+      bucket.endpoint_url.as_deref(),
+      Some("https://s3.eu-central-1.wasabisys.com")
+    );
+    assert!(!bucket.conditional_writes);
+    assert!(bucket.object_tagging);
+
+    let config = Config::from_yaml_str(&yaml(
+      "    provider: garage\n    endpointUrl: http://garage:3900\n",
+    ))
+    .unwrap();
+    let bucket = &config.resolve_env_vars().unwrap().buckets[0];
+    assert_eq!(bucket.region.as_deref(), Some("garage"));
+    assert_eq!(bucket.endpoint_url.as_deref(), Some("http://garage:3900"));
+    assert!(bucket.force_path_style);
+    assert!(!bucket.object_tagging);
+
+    // Self-hosted providers need an endpoint, Backblaze a region
+    assert!(Config::from_yaml_str(&yaml("    provider: seaweedfs\n"))
+      .unwrap()
+      .validate()
+      .is_err());
+    assert!(Config::from_yaml_str(&yaml("    provider: backblaze\n"))
+      .unwrap()
+      .validate()
+      .is_err());
+    assert!(
+      Config::from_yaml_str(&yaml("    provider: r2\n    type: fs\n    path: /tmp\n"))
+        .unwrap()
+        .validate()
+        .is_err()
+    );
+  }
+
+  #[test]
+  fn test_token_notice_replaces_global_notice() {
+    let config = Config::from_yaml_str(
+      "buckets:\n  - name: main\n    bucketName: cache\nnotice: Cache moves to cache.example.com on 2026-11-01\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: ci\n  - name: legacy\n    bucket: main\n    accessToken: legacy\n    notice: This token expires on 2026-10-31\n",
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    assert_eq!(
+      resolved.service_access_tokens[0].notice.as_deref(),
+      Some("Cache moves to cache.example.com on 2026-11-01")
+    );
+    assert_eq!(
+      resolved.service_access_tokens[1].notice.as_deref(),
+      Some("This token expires on 2026-10-31")
+    );
+
+    let mut config = config;
+    config.notice = Some("line one\nline two".to_string());
+    assert!(config.validate().is_err());
+  }
+
+  #[test]
+  fn test_transport_compression() {
+    let yaml = |section: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\ntransportCompression:\n{}",
+        section
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("  minSizeBytes: 4096\n")).unwrap();
+    assert!(config.validate().is_ok());
+    let transport = config.transport_compression.unwrap();
+    assert_eq!(transport.min_size_bytes, 4096);
+    assert_eq!(transport.level(Codec::Zstd), 3);
+    assert_eq!(transport.level(Codec::Gzip), 6);
+
+    for invalid in ["  zstdLevel: 23\n", "  gzipLevel: 0\n"] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_base_path() {
+    let yaml = |base_path: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\nbasePath: \"{}\"\nmanifest:\n  signingKey: key\n",
+        base_path
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("/nx-cache")).unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    assert_eq!(resolved.base_path.as_deref(), Some("/nx-cache"));
+    // Relative signed URLs have to carry the prefix as well
+    assert_eq!(
+      resolved.manifest.unwrap().public_url.as_deref(),
+      Some("/nx-cache")
+    );
+
+    for invalid in ["/", "nx-cache", "/nx-cache/", "/nx//cache", "/{hash}"] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_grpc() {
+    let yaml = |grpc: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\ngrpc: {}\n",
+        grpc
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("{}")).unwrap();
+    assert!(config.validate().is_ok());
+    assert_eq!(config.resolve_env_vars().unwrap().grpc.unwrap().port, 9092);
+
+    for invalid in ["{ port: 0 }", "{ port: 3000 }"] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_auth_header() {
+    let yaml = |header: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\n    authHeader: {}\n",
+        header
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("X-Nx-Token")).unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    assert_eq!(
+      resolved.service_access_tokens[0].auth_header.as_deref(),
+      Some("x-nx-token")
+    );
+
+    for invalid in ["Authorization", "\"x nx token\"", "\"\""] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_expires_at() {
+    let yaml = |expires_at: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\n    expiresAt: \"{}\"\n",
+        expires_at
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("2026-10-31T00:00:00+01:00")).unwrap();
+    assert!(config.validate().is_ok());
+    let token = &config.resolve_env_vars().unwrap().service_access_tokens[0];
+    assert_eq!(token.expires_at, Some(1_793_401_200));
+    assert!(!token.is_expired_at(1_793_401_199));
+    assert!(token.is_expired_at(1_793_401_200));
+
+    let config = Config::from_yaml_str(&yaml("next week")).unwrap();
+    assert!(config.validate().is_err());
+    assert!(config.resolve_env_vars().is_err());
+  }
+
+  #[test]
+  fn test_access_token_hash() {
+    let yaml = |token: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n{}",
+        token
+      )
+    };
+    let hash = "$2b$04$MYqOTFfLvwfSJWgJ0dzm5O7ymYLYjKHqKsfIdXIfiLJzZgl4Hq7aq";
+
+    let config =
+      Config::from_yaml_str(&yaml(&format!("    accessTokenHash: \"{}\"\n", hash))).unwrap();
+    assert!(config.validate().is_ok());
+    let token = &config.resolve_env_vars().unwrap().service_access_tokens[0];
+    assert_eq!(token.access_token_hash.as_deref(), Some(hash));
+    // The internal key is random rather than anything derived from the hash
+    assert_eq!(token.access_token.len(), 64);
+
+    for invalid in [
+      format!(
+        "    accessToken: secret\n    accessTokenHash: \"{}\"\n",
+        hash
+      ),
+      "    accessTokenHash: secret\n".to_string(),
+      String::new(),
+    ] {
+      assert!(Config::from_yaml_str(&yaml(&invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+    let dotted =
+      yaml(&format!("    accessTokenHash: \"{}\"\n", hash)).replace("name: ci", "name: ci.main");
+    assert!(Config::from_yaml_str(&dotted).unwrap().validate().is_err());
+  }
+
+  #[test]
+  fn test_previous_access_token() {
+    let yaml = |previous: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: current\n{}",
+        previous
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml(
+      "    previousAccessToken: old\n    previousAccessTokenValidUntil: \"2026-10-31T23:59:59Z\"\n",
+    ))
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let token = &config.resolve_env_vars().unwrap().service_access_tokens[0];
+    let previous = token.previous_access_token.as_ref().unwrap();
+    assert_eq!(previous.access_token, "old");
+    assert_eq!(
+      previous.valid_until,
+      http_date::parse_rfc3339("2026-10-31T23:59:59Z").unwrap()
+    );
+
+    let config = Config::from_yaml_str(&yaml("")).unwrap();
+    let token = &config.resolve_env_vars().unwrap().service_access_tokens[0];
+    assert!(token.previous_access_token.is_none());
+
+    for invalid in [
+      "    previousAccessToken: old\n",
+      "    previousAccessToken: old\n    previousAccessTokenValidUntil: tomorrow\n",
+      "    previousAccessToken: current\n    previousAccessTokenValidUntil: \"2026-10-31T23:59:59Z\"\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_upstream() {
+    let yaml = |upstream: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: edge\n    bucket: main\n    accessToken: secret\n    upstream:\n{}",
+        upstream
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml(
+      "      url: https://cache.example.com/\n      accessToken: central\n",
+    ))
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    let upstream = resolved.service_access_tokens[0].upstream.as_ref().unwrap();
+    assert_eq!(upstream.url, "https://cache.example.com");
+    assert_eq!(upstream.access_token, "central");
+    assert!(upstream.persist);
+    assert_eq!(upstream.timeout_seconds, 10);
+
+    let missing_token =
+      Config::from_yaml_str(&yaml("      url: https://cache.example.com\n")).unwrap();
+    assert!(missing_token.resolve_env_vars().is_err());
+    for invalid in [
+      "      url: cache.example.com\n      accessToken: central\n",
+      "      url: https://cache.example.com\n      accessToken: central\n      timeoutSeconds: 0\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_jwt() {
+    let yaml = |jwt: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\njwt:\n{}",
+        jwt
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml(
+      "  jwksUrl: https://ci.example.com/jwks\n  audience: nx-cache\n  rules:\n    - token: ci\n      claims:\n        ref: refs/heads/*\n",
+    ))
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    let jwt = &resolved.jwt[0];
+    assert_eq!(
+      jwt.keys,
+      JwtKeySource::JwksUrl("https://ci.example.com/jwks".to_string())
+    );
+    assert_eq!(jwt.leeway_seconds, 60);
+    assert_eq!(jwt.rules[0].claims["ref"], "refs/heads/*");
+    // Reached only through JWTs, so the token gets an unguessable internal key
+    assert_eq!(resolved.service_access_tokens[0].access_token.len(), 64);
+
+    for invalid in [
+      "  rules:\n    - token: ci\n",
+      "  secret: s\n  jwksUrl: https://ci.example.com/jwks\n  rules:\n    - token: ci\n",
+      "  secret: s\n  rules: []\n",
+      "  secret: s\n  rules:\n    - token: missing\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_oidc() {
+    let yaml = |oidc: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: developers\n    bucket: main\n    permissions: read\noidc:\n{}",
+        oidc
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml(
+      "  issuer: https://login.example.com/\n  audience: nx-cache\n  rules:\n    - token: developers\n      claims:\n        sub: \"*@example.com\"\n",
+    ))
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    let oidc = &resolved.jwt[0];
+    assert_eq!(oidc.provider, "oidc");
+    assert_eq!(
+      oidc.keys,
+      JwtKeySource::Discovery("https://login.example.com".to_string())
+    );
+    assert_eq!(oidc.issuer.as_deref(), Some("https://login.example.com"));
+    assert_eq!(oidc.audience.as_deref(), Some("nx-cache"));
+
+    for invalid in [
+      "  issuer: login.example.com\n  audience: nx-cache\n  rules:\n    - token: developers\n",
+      "  issuer: https://login.example.com\n  audience: \"\"\n  rules:\n    - token: developers\n",
+      "  issuer: https://login.example.com\n  audience: nx-cache\n  rules: []\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_github_actions() {
+    let yaml = |github: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: web-main\n    bucket: main\n  - name: web-prs\n    bucket: main\n    permissions: read\ngithubActions:\n{}",
+        github
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml(
+      "  audience: nx-cache\n  rules:\n    - token: web-main\n      repository: acme/web\n      ref: refs/heads/main\n    - token: web-prs\n      repository: acme/web\n      claims:\n        event_name: pull_request\n",
+    ))
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    let github = &resolved.jwt[0];
+    assert_eq!(
+      github.keys,
+      JwtKeySource::Discovery("https://token.actions.githubusercontent.com".to_string())
+    );
+    assert_eq!(github.audience.as_deref(), Some("nx-cache"));
+    assert_eq!(github.rules[0].claims["repository"], "acme/web");
+    assert_eq!(github.rules[0].claims["ref"], "refs/heads/main");
+    assert!(!github.rules[1].claims.contains_key("ref"));
+    assert_eq!(github.rules[1].claims["event_name"], "pull_request");
+
+    for invalid in [
+      "  rules:\n    - token: web-main\n      repository: web\n",
+      "  issuer: http://github.example.com/_services/token\n  rules:\n    - token: web-main\n      repository: acme/web\n",
+      "  rules:\n    - token: missing\n      repository: acme/web\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_gitlab() {
+    let yaml = |gitlab: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: platform\n    bucket: main\ngitlab:\n{}",
+        gitlab
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml(
+      "  url: https://gitlab.example.com/\n  rules:\n    - token: platform\n      projectPath: acme/*\n      ref: main\n      claims:\n        ref_protected: \"true\"\n",
+    ))
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    let gitlab = &resolved.jwt[0];
+    assert_eq!(gitlab.provider, "gitlab");
+    assert_eq!(gitlab.issuer.as_deref(), Some("https://gitlab.example.com"));
+    assert_eq!(gitlab.audience, None);
+    assert_eq!(gitlab.rules[0].claims["project_path"], "acme/*");
+    assert_eq!(gitlab.rules[0].claims["ref"], "main");
+    assert_eq!(gitlab.rules[0].claims["ref_protected"], "true");
+
+    let config = Config::from_yaml_str(&yaml(
+      "  rules:\n    - token: platform\n      projectPath: acme/web\n",
+    ))
+    .unwrap();
+    let resolved = config.resolve_env_vars().unwrap();
+    assert_eq!(
+      resolved.jwt[0].keys,
+      JwtKeySource::Discovery("https://gitlab.com".to_string())
+    );
+
+    for invalid in [
+      "  rules:\n    - token: platform\n      projectPath: web\n",
+      "  url: gitlab.example.com\n  rules:\n    - token: platform\n      projectPath: acme/web\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_resolved_defaults_match_empty_entries() {
+    let yaml = "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\n";
+    let resolved = Config::from_yaml_str(yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let bucket = &resolved.buckets[0];
+    let default = ResolvedBucketConfig::default();
+    assert_eq!(bucket.backend, default.backend);
+    assert_eq!(bucket.force_path_style, default.force_path_style);
+    assert_eq!(bucket.timeout, default.timeout);
+    assert_eq!(bucket.retry, default.retry);
+    assert_eq!(bucket.conditional_writes, default.conditional_writes);
+    assert_eq!(bucket.object_tagging, default.object_tagging);
+    assert!(bucket.concurrency.is_none() && default.concurrency.is_none());
+    assert!(bucket.maintenance_windows.is_empty() && default.maintenance_windows.is_empty());
+
+    let token = &resolved.service_access_tokens[0];
+    let default = ResolvedServiceAccessToken::default();
+    assert_eq!(token.prefix, default.prefix);
+    assert_eq!(token.access_token_hash, default.access_token_hash);
+    assert_eq!(token.jwt_only, default.jwt_only);
+    assert_eq!(token.permissions, default.permissions);
+    assert_eq!(token.quota_warning_percent, default.quota_warning_percent);
+    assert_eq!(token.replication, default.replication);
+    assert_eq!(token.key_layout, default.key_layout);
+    assert_eq!(token.admin, default.admin);
+    assert_eq!(token.allow_overwrite, default.allow_overwrite);
+    assert_eq!(token.weight, default.weight);
+  }
+
+  #[test]
+  fn test_token_minting() {
+    let yaml = |minting: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\ntokenMinting:\n{}",
+        minting
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("  signingKey: key\n")).unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    let minting = resolved.token_minting.as_ref().unwrap();
+    assert_eq!(minting.default_ttl_seconds, 3600);
+    assert_eq!(minting.max_ttl_seconds, 86400);
+    let provider = &resolved.jwt[0];
+    assert_eq!(provider.issuer.as_deref(), Some(MINTED_TOKEN_ISSUER));
+    assert!(provider.subject_is_token);
+    assert!(provider.rules.is_empty());
+
+    for invalid in [
+      "  defaultTtlSeconds: 60\n",
+      "  signingKey: key\n  defaultTtlSeconds: 0\n",
+      "  signingKey: key\n  maxTtlSeconds: 60\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_token_revocation() {
+    let yaml = |path: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\ntokenRevocation:\n  path: \"{}\"\n",
+        path
+      )
     };
 
+    let config = Config::from_yaml_str(&yaml("/var/lib/nx-cache/revoked.json")).unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    assert_eq!(
+      resolved.token_revocation.unwrap().path,
+      "/var/lib/nx-cache/revoked.json"
+    );
+
+    assert!(Config::from_yaml_str(&yaml(" "))
+      .unwrap()
+      .validate()
+      .is_err());
+  }
+
+  #[test]
+  fn test_hash_validation() {
+    let yaml = |policy: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\nhashValidation:\n  please:\n{}",
+        policy
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("    charset: hex\n    maxLength: 64\n")).unwrap();
+    assert!(config.validate().is_ok());
+    let please = config.hash_validation.please.as_ref().unwrap();
+    assert_eq!(please.charset, Some(HashCharset::Hex));
+    assert_eq!(please.max_length, Some(64));
+    assert!(config.hash_validation.nx.is_none());
+
+    for invalid in [
+      "    minLength: 0\n",
+      "    minLength: 10\n    maxLength: 5\n",
+      "    maxLength: 2048\n",
+      "    pattern: \"[a-z\"\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_namespace_aliases() {
+    let yaml = |alias: &str, bucket: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\n  - name: other\n    bucketName: other\nserviceAccessTokens:\n  - name: monorepo\n    bucket: {}\n    prefix: /monorepo\n    accessToken: a\n  - name: web\n    bucket: main\n    prefix: /web\n    accessToken: b\n    namespaceAliases: [{}]\n",
+        bucket, alias
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("monorepo", "main")).unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+    assert!(resolved.service_access_tokens[0]
+      .namespace_aliases
+      .is_empty());
+    assert_eq!(
+      resolved.service_access_tokens[1].namespace_aliases,
+      vec![NamespaceAlias {
+        token: "monorepo".to_string(),
+        prefix: "/monorepo".to_string(),
+        key_layout: KeyLayout::Plain,
+      }]
+    );
+
+    for (alias, bucket) in [("web", "main"), ("missing", "main"), ("monorepo", "other")] {
+      assert!(Config::from_yaml_str(&yaml(alias, bucket))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_cost_tags() {
+    let yaml = |tags: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\nserviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\n    costTags:\n{}",
+        tags
+      )
+    };
+
+    let config =
+      Config::from_yaml_str(&yaml("      team: platform\n      project: web\n")).unwrap();
+    assert!(config.validate().is_ok());
+    let token = &config.resolve_env_vars().unwrap().service_access_tokens[0];
+    assert_eq!(
+      token.cost_tags.get("team").map(String::as_str),
+      Some("platform")
+    );
+
+    for invalid in [
+      "      namespace: x\n",
+      "      aws:team: x\n",
+      "      \"a,b\": x\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_s3_express_directory_buckets() {
+    assert_eq!(
+      s3_express_zone("nx-cache--usw2-az1--x-s3"),
+      Some("usw2-az1")
+    );
+    assert_eq!(s3_express_zone("nx-cache"), None);
+    assert_eq!(s3_express_zone("--x-s3"), None);
+
+    let yaml = |bucket: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: nx-cache--usw2-az1--x-s3\n    accessKeyId: key\n    secretAccessKey: secret\n{}serviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\n",
+        bucket
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("    region: us-west-2\n")).unwrap();
+    assert!(config.validate().is_ok());
+    let bucket = &config.resolve_env_vars().unwrap().buckets[0];
+    assert_eq!(
+      bucket.endpoint_url.as_deref(),
+      Some("https://s3express-usw2-az1.us-west-2.amazonaws.com")
+    );
+    assert!(!bucket.object_tagging);
+
+    assert!(Config::from_yaml_str(&yaml(""))
+      .unwrap()
+      .validate()
+      .is_err());
+    assert!(
+      Config::from_yaml_str(&yaml("    region: us-west-2\n    forcePathStyle: true\n"))
+        .unwrap()
+        .validate()
+        .is_err()
+    );
+  }
+
+  #[test]
+  fn test_assume_role() {
+    let yaml = |bucket: &str| {
+      format!(
+        "buckets:\n  - name: main\n    bucketName: cache\n    accessKeyId: key\n    secretAccessKey: secret\n{}serviceAccessTokens:\n  - name: ci\n    bucket: main\n    accessToken: secret\n",
+        bucket
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml(
+      "    roleArn: arn:aws:iam::123456789012:role/nx-cache\n    externalId: nx\n",
+    ))
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let bucket = &config.resolve_env_vars().unwrap().buckets[0];
+    assert_eq!(
+      bucket.role_arn.as_deref(),
+      Some("arn:aws:iam::123456789012:role/nx-cache")
+    );
+    assert_eq!(bucket.external_id.as_deref(), Some("nx"));
+
+    for invalid in [
+      "    externalId: nx\n",
+      "    roleArn: nx-cache\n",
+      "    roleArn: arn:aws:iam::123456789012:role/nx-cache\n    type: gcs\n",
+    ] {
+      assert!(Config::from_yaml_str(&yaml(invalid))
+        .unwrap()
+        .validate()
+        .is_err());
+    }
+  }
+
+  #[test]
+  fn test_effective_ttl() {
+    let config = Config::from_yaml_str(
+      r#"
+buckets:
+  - name: main
+    bucketName: cache
+serviceAccessTokens:
+  - name: release
+    bucket: main
+    accessToken: release-secret
+    defaultTtlSeconds: 86400
+    maxTtlSeconds: 2592000
+  - name: pr
+    bucket: main
+    accessToken: pr-secret
+    defaultTtlSeconds: 86400
+"#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+    let resolved = config.resolve_env_vars().unwrap();
+
+    let release = resolved.find_service_token("release-secret").unwrap();
+    assert_eq!(release.effective_ttl(None), Some(86400));
+    assert_eq!(release.effective_ttl(Some(604800)), Some(604800));
+    assert_eq!(release.effective_ttl(Some(u64::MAX)), Some(2592000));
+
+    let pr = resolved.find_service_token("pr-secret").unwrap();
+    assert_eq!(pr.effective_ttl(Some(604800)), Some(86400));
+  }
+
+  #[test]
+  fn test_scopes() {
+    let config = Config::from_yaml_str(
+      r#"
+buckets:
+  - name: main
+    bucketName: cache
+serviceAccessTokens:
+  - name: ci
+    bucket: main
+    accessToken: ci-secret
+  - name: ops
+    bucket: main
+    accessToken: ops-secret
+    admin: true
+    allowOverwrite: true
+  - name: dev
+    bucket: main
+    accessToken: dev-secret
+    permissions: read
+"#,
+    )
+    .unwrap();
+    let resolved = config.resolve_env_vars().unwrap();
+
+    let ci = resolved.find_service_token("ci-secret").unwrap();
+    assert_eq!(ci.scopes(), vec!["read", "write"]);
+    let ops = resolved.find_service_token("ops-secret").unwrap();
+    assert_eq!(ops.scopes(), vec!["read", "write", "overwrite", "admin"]);
+    let dev = resolved.find_service_token("dev-secret").unwrap();
+    assert_eq!(dev.permissions, Permissions::Read);
+    assert_eq!(dev.scopes(), vec!["read"]);
+  }
+
+  #[test]
+  fn test_maintenance_windows() {
+    let yaml = |start: &str| {
+      format!(
+        r#"
+buckets:
+  - name: main
+    bucketName: cache
+    maintenanceWindows:
+      - days: [sat, sun]
+        start: "{}"
+        end: "04:00"
+serviceAccessTokens:
+  - name: ci
+    bucket: main
+    accessToken: secret
+"#,
+        start
+      )
+    };
+
+    let config = Config::from_yaml_str(&yaml("22:30")).unwrap();
+    let window = &config.buckets[0].maintenance_windows[0];
+    assert_eq!(window.days, vec![Weekday::Sat, Weekday::Sun]);
+    assert_eq!(window.minutes(), Some((22 * 60 + 30, 4 * 60)));
+    assert_eq!(window.max_concurrency, 2);
+    assert!(config.validate().is_ok());
+
+    for start in ["24:00", "2:00", "04:00"] {
+      let config = Config::from_yaml_str(&yaml(start)).unwrap();
+      assert!(config.validate().is_err(), "{}", start);
+    }
+  }
+
+  #[test]
+  fn test_mirror_unknown_bucket() {
+    let config = Config::from_yaml_str(
+      r#"
+buckets:
+  - name: main
+    bucketName: cache
+serviceAccessTokens:
+  - name: ci
+    bucket: main
+    accessToken: secret
+mirror:
+  bucket: other
+"#,
+    )
+    .unwrap();
+
     assert!(config.validate().is_err());
   }
 
@@ -1000,7 +5854,28 @@ mod tests {
         insecure_tls_env: None,
         force_path_style: false,
         sse: None,
-        timeout: 30,
+        timeouts: TimeoutsConfig::default(),
+        concurrency: None,
+        local_tier: None,
+        fallback_bucket: None,
+        backend: BackendType::S3,
+        path: None,
+        conditional_writes: true,
+        retry: RetryConfig::default(),
+        encryption: None,
+        server_side_encryption: None,
+        sse_kms_key_id: None,
+        compression: None,
+        storage_class: None,
+        maintenance_windows: Vec::new(),
+        object_tagging: true,
+        spill: None,
+        provider: None,
+        role_arn: None,
+        external_id: None,
+        external_id_env: None,
+        ranged_reads: None,
+        isolation: None,
       }],
       service_access_tokens: vec![ServiceAccessTokenConfig {
         name: "test".to_string(),
@@ -1008,9 +5883,59 @@ mod tests {
         prefix: "/ci".to_string(),
         access_token: Some("token".to_string()),
         access_token_env: None,
+        egress_daily_limit_bytes: None,
+        replica_buckets: vec![],
+        replication: ReplicationMode::Async,
+        key_layout: KeyLayout::Plain,
+        legacy_prefixes: vec![],
+        legacy_copy_forward: false,
+        quota_warning_percent: 80,
+        accounting_admin: false,
+        variants: false,
+        admin: false,
+        default_ttl_seconds: None,
+        max_ttl_seconds: None,
+        weight: 1,
+        cost_tags: Default::default(),
+        notice: None,
+        namespace_aliases: vec![],
+        allow_overwrite: false,
+        auth_header: None,
+        upstream: None,
+        permissions: Default::default(),
+        expires_at: None,
+        access_token_hash: None,
+        previous_access_token: None,
+        previous_access_token_env: None,
+        previous_access_token_valid_until: None,
       }],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
     };
 
     assert!(config.validate().is_ok());
@@ -1044,7 +5969,28 @@ mod tests {
           customer_key_base64: None,
           customer_key_base64_env: None,
         }),
-        timeout: 30,
+        timeouts: TimeoutsConfig::default(),
+        concurrency: None,
+        local_tier: None,
+        fallback_bucket: None,
+        backend: BackendType::S3,
+        path: None,
+        conditional_writes: true,
+        retry: RetryConfig::default(),
+        encryption: None,
+        server_side_encryption: None,
+        sse_kms_key_id: None,
+        compression: None,
+        storage_class: None,
+        maintenance_windows: Vec::new(),
+        object_tagging: true,
+        spill: None,
+        provider: None,
+        role_arn: None,
+        external_id: None,
+        external_id_env: None,
+        ranged_reads: None,
+        isolation: None,
       }],
       service_access_tokens: vec![ServiceAccessTokenConfig {
         name: "test".to_string(),
@@ -1052,9 +5998,59 @@ mod tests {
         prefix: "/ci".to_string(),
         access_token: Some("token".to_string()),
         access_token_env: None,
+        egress_daily_limit_bytes: None,
+        replica_buckets: vec![],
+        replication: ReplicationMode::Async,
+        key_layout: KeyLayout::Plain,
+        legacy_prefixes: vec![],
+        legacy_copy_forward: false,
+        quota_warning_percent: 80,
+        accounting_admin: false,
+        variants: false,
+        admin: false,
+        default_ttl_seconds: None,
+        max_ttl_seconds: None,
+        weight: 1,
+        cost_tags: Default::default(),
+        notice: None,
+        namespace_aliases: vec![],
+        allow_overwrite: false,
+        auth_header: None,
+        upstream: None,
+        permissions: Default::default(),
+        expires_at: None,
+        access_token_hash: None,
+        previous_access_token: None,
+        previous_access_token_env: None,
+        previous_access_token_valid_until: None,
       }],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
     };
 
     let err = config
@@ -1092,7 +6088,28 @@ mod tests {
           customer_key_base64: Some(short_key_b64),
           customer_key_base64_env: None,
         }),
-        timeout: 30,
+        timeouts: TimeoutsConfig::default(),
+        concurrency: None,
+        local_tier: None,
+        fallback_bucket: None,
+        backend: BackendType::S3,
+        path: None,
+        conditional_writes: true,
+        retry: RetryConfig::default(),
+        encryption: None,
+        server_side_encryption: None,
+        sse_kms_key_id: None,
+        compression: None,
+        storage_class: None,
+        maintenance_windows: Vec::new(),
+        object_tagging: true,
+        spill: None,
+        provider: None,
+        role_arn: None,
+        external_id: None,
+        external_id_env: None,
+        ranged_reads: None,
+        isolation: None,
       }],
       service_access_tokens: vec![ServiceAccessTokenConfig {
         name: "test".to_string(),
@@ -1100,9 +6117,59 @@ mod tests {
         prefix: "/ci".to_string(),
         access_token: Some("token".to_string()),
         access_token_env: None,
+        egress_daily_limit_bytes: None,
+        replica_buckets: vec![],
+        replication: ReplicationMode::Async,
+        key_layout: KeyLayout::Plain,
+        legacy_prefixes: vec![],
+        legacy_copy_forward: false,
+        quota_warning_percent: 80,
+        accounting_admin: false,
+        variants: false,
+        admin: false,
+        default_ttl_seconds: None,
+        max_ttl_seconds: None,
+        weight: 1,
+        cost_tags: Default::default(),
+        notice: None,
+        namespace_aliases: vec![],
+        allow_overwrite: false,
+        auth_header: None,
+        upstream: None,
+        permissions: Default::default(),
+        expires_at: None,
+        access_token_hash: None,
+        previous_access_token: None,
+        previous_access_token_env: None,
+        previous_access_token_valid_until: None,
       }],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
     };
 
     let err = config
@@ -1144,7 +6211,28 @@ mod tests {
           customer_key_base64: None,
           customer_key_base64_env: Some(key_env.to_string()),
         }),
-        timeout: 30,
+        timeouts: TimeoutsConfig::default(),
+        concurrency: None,
+        local_tier: None,
+        fallback_bucket: None,
+        backend: BackendType::S3,
+        path: None,
+        conditional_writes: true,
+        retry: RetryConfig::default(),
+        encryption: None,
+        server_side_encryption: None,
+        sse_kms_key_id: None,
+        compression: None,
+        storage_class: None,
+        maintenance_windows: Vec::new(),
+        object_tagging: true,
+        spill: None,
+        provider: None,
+        role_arn: None,
+        external_id: None,
+        external_id_env: None,
+        ranged_reads: None,
+        isolation: None,
       }],
       service_access_tokens: vec![ServiceAccessTokenConfig {
         name: "test".to_string(),
@@ -1152,9 +6240,59 @@ mod tests {
         prefix: "/ci".to_string(),
         access_token: Some("token".to_string()),
         access_token_env: None,
+        egress_daily_limit_bytes: None,
+        replica_buckets: vec![],
+        replication: ReplicationMode::Async,
+        key_layout: KeyLayout::Plain,
+        legacy_prefixes: vec![],
+        legacy_copy_forward: false,
+        quota_warning_percent: 80,
+        accounting_admin: false,
+        variants: false,
+        admin: false,
+        default_ttl_seconds: None,
+        max_ttl_seconds: None,
+        weight: 1,
+        cost_tags: Default::default(),
+        notice: None,
+        namespace_aliases: vec![],
+        allow_overwrite: false,
+        auth_header: None,
+        upstream: None,
+        permissions: Default::default(),
+        expires_at: None,
+        access_token_hash: None,
+        previous_access_token: None,
+        previous_access_token_env: None,
+        previous_access_token_valid_until: None,
       }],
       port: 3000,
       debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+      notice: None,
+      jwt: None,
+      oidc: None,
+      github_actions: None,
+      gitlab: None,
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
     };
 
     let resolved = config.resolve_env_vars().expect("Expected resolved config");
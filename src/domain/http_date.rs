@@ -0,0 +1,159 @@
+//! IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`) as used by `Last-Modified` and friends,
+//! and RFC 3339 timestamps (`1994-11-06T08:49:37Z`) as used in the configuration
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Format a Unix time in seconds as an HTTP date
+pub fn format(unix_secs: u64) -> String {
+  let days = unix_secs / 86_400;
+  let seconds = unix_secs % 86_400;
+  let (year, month, day) = civil_from_days(days as i64);
+  format!(
+    "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+    WEEKDAYS[(days % 7) as usize],
+    day,
+    MONTHS[(month - 1) as usize],
+    year,
+    seconds / 3600,
+    seconds / 60 % 60,
+    seconds % 60
+  )
+}
+
+/// Parse an HTTP date into a Unix time in seconds; the weekday is not checked
+pub fn parse(value: &str) -> Option<u64> {
+  let (_, rest) = value.trim().split_once(", ")?;
+  let mut parts = rest.split(' ');
+  let day: i64 = parts.next()?.parse().ok()?;
+  let month = parts.next()?;
+  let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+  let year: i64 = parts.next()?.parse().ok()?;
+  let mut time = parts.next()?.split(':');
+  let hours: u64 = time.next()?.parse().ok()?;
+  let minutes: u64 = time.next()?.parse().ok()?;
+  let seconds: u64 = time.next()?.parse().ok()?;
+  if parts.next()? != "GMT" || hours > 23 || minutes > 59 || seconds > 60 {
+    return None;
+  }
+  let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+  Some(days * 86_400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Format a Unix time in seconds as an RFC 3339 timestamp in UTC
+pub fn format_rfc3339(unix_secs: u64) -> String {
+  let seconds = unix_secs % 86_400;
+  let (year, month, day) = civil_from_days((unix_secs / 86_400) as i64);
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+    year,
+    month,
+    day,
+    seconds / 3600,
+    seconds / 60 % 60,
+    seconds % 60
+  )
+}
+
+/// Parse an RFC 3339 timestamp into a Unix time in seconds, dropping fractional seconds
+///
+/// Times before the Unix epoch are rejected.
+pub fn parse_rfc3339(value: &str) -> Option<u64> {
+  let value = value.trim();
+  let (date, time) = value.split_once(['T', 't', ' '])?;
+  let mut date = date.split('-');
+  let year: i64 = date.next().filter(|part| part.len() == 4)?.parse().ok()?;
+  let month: i64 = date.next().filter(|part| part.len() == 2)?.parse().ok()?;
+  let day: i64 = date.next().filter(|part| part.len() == 2)?.parse().ok()?;
+  if date.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+    return None;
+  }
+
+  let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+    Some(time) => (time, 0),
+    None => {
+      let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+      let (hours, minutes) = offset[1..].split_once(':')?;
+      let seconds = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+      (
+        time,
+        if offset.starts_with('-') {
+          -seconds
+        } else {
+          seconds
+        },
+      )
+    },
+  };
+  let time = time.split_once('.').map_or(time, |(time, _)| time);
+  let mut time = time.split(':');
+  let hours: i64 = time.next()?.parse().ok()?;
+  let minutes: i64 = time.next()?.parse().ok()?;
+  let seconds: i64 = time.next()?.parse().ok()?;
+  if time.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+    return None;
+  }
+  let local = days_from_civil(year, month, day) * 86_400 + hours * 3600 + minutes * 60 + seconds;
+  u64::try_from(local - offset).ok()
+}
+
+// Howard Hinnant's civil calendar algorithms
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let yoe = year.rem_euclid(400);
+  let mp = if month > 2 { month - 3 } else { month + 9 };
+  let doy = (153 * mp + 2) / 5 + day - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_trip() {
+    assert_eq!(format(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784_111_777));
+    assert_eq!(parse(&format(1_760_601_612)), Some(1_760_601_612));
+    assert_eq!(parse("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+  }
+
+  #[test]
+  fn test_rfc3339() {
+    assert_eq!(format_rfc3339(784_111_777), "1994-11-06T08:49:37Z");
+    assert_eq!(parse_rfc3339("1994-11-06T08:49:37Z"), Some(784_111_777));
+    assert_eq!(parse_rfc3339("1994-11-06T08:49:37.250Z"), Some(784_111_777));
+    assert_eq!(
+      parse_rfc3339("1994-11-06T10:49:37+02:00"),
+      Some(784_111_777)
+    );
+    assert_eq!(
+      parse_rfc3339("1994-11-06T03:49:37-05:00"),
+      Some(784_111_777)
+    );
+    for invalid in [
+      "1994-11-06",
+      "1994-11-06T08:49:37",
+      "1994-13-06T08:49:37Z",
+      "1969-12-31T23:59:59Z",
+    ] {
+      assert_eq!(parse_rfc3339(invalid), None, "{}", invalid);
+    }
+  }
+}
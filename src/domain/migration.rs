@@ -0,0 +1,178 @@
+use serde_json::{Map, Value};
+
+use crate::domain::config::ConfigError;
+
+/// Schema version written by current releases
+pub const CURRENT_API_VERSION: u32 = 2;
+
+/// Schema version assumed for files without an `apiVersion`
+const LEGACY_API_VERSION: u32 = 1;
+
+/// Key casing of the config file being migrated (YAML is camelCase, TOML snake_case)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStyle {
+  CamelCase,
+  SnakeCase,
+}
+
+impl KeyStyle {
+  fn key(self, camel: &'static str, snake: &'static str) -> &'static str {
+    match self {
+      Self::CamelCase => camel,
+      Self::SnakeCase => snake,
+    }
+  }
+}
+
+/// A single schema upgrade from `to - 1` to `to`
+struct Migration {
+  to: u32,
+  apply: fn(&mut Map<String, Value>, KeyStyle, &mut Vec<String>),
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+  to: 2,
+  apply: bucket_timeout_to_timeouts,
+}];
+
+/// Upgrade a parsed config document to the current schema in place.
+///
+/// Returns a deprecation message for every field that was rewritten.
+pub fn migrate(document: &mut Value, style: KeyStyle) -> Result<Vec<String>, ConfigError> {
+  let Some(root) = document.as_object_mut() else {
+    // Not a mapping; let deserialization report the error
+    return Ok(Vec::new());
+  };
+
+  let version_key = style.key("apiVersion", "api_version");
+  let version = match root.get(version_key) {
+    None => LEGACY_API_VERSION,
+    Some(value) => parse_version(value)
+      .ok_or_else(|| ConfigError::Migration(format!("Invalid {}: {}", version_key, value)))?,
+  };
+
+  if version > CURRENT_API_VERSION {
+    return Err(ConfigError::Migration(format!(
+      "{} v{} is newer than the supported v{}",
+      version_key, version, CURRENT_API_VERSION
+    )));
+  }
+
+  let mut deprecations = Vec::new();
+  for migration in MIGRATIONS.iter().filter(|m| m.to > version) {
+    (migration.apply)(root, style, &mut deprecations);
+  }
+
+  if version < CURRENT_API_VERSION {
+    deprecations.push(format!(
+      "{} v{} is deprecated, set {}: v{} after updating the fields above",
+      version_key, version, version_key, CURRENT_API_VERSION
+    ));
+  }
+  root.insert(
+    version_key.to_string(),
+    Value::String(format!("v{}", CURRENT_API_VERSION)),
+  );
+
+  Ok(deprecations)
+}
+
+/// Accept `v2`, `"2"` and `2`
+fn parse_version(value: &Value) -> Option<u32> {
+  match value {
+    Value::Number(number) => number.as_u64().and_then(|n| u32::try_from(n).ok()),
+    Value::String(text) => text.strip_prefix('v').unwrap_or(text).parse().ok(),
+    _ => None,
+  }
+}
+
+/// v1 -> v2: `buckets[].timeout` moved to `buckets[].timeouts.operation`
+fn bucket_timeout_to_timeouts(
+  root: &mut Map<String, Value>,
+  _style: KeyStyle,
+  deprecations: &mut Vec<String>,
+) {
+  let Some(buckets) = root.get_mut("buckets").and_then(Value::as_array_mut) else {
+    return;
+  };
+
+  for (index, bucket) in buckets.iter_mut().enumerate() {
+    let Some(bucket) = bucket.as_object_mut() else {
+      continue;
+    };
+    let Some(timeout) = bucket.remove("timeout") else {
+      continue;
+    };
+
+    let timeouts = bucket
+      .entry("timeouts")
+      .or_insert_with(|| Value::Object(Map::new()));
+    match timeouts.as_object_mut() {
+      Some(timeouts) if timeouts.contains_key("operation") => {
+        deprecations.push(format!(
+          "buckets[{}].timeout is ignored because buckets[{}].timeouts.operation is set",
+          index, index
+        ));
+      },
+      Some(timeouts) => {
+        timeouts.insert("operation".to_string(), timeout);
+        deprecations.push(format!(
+          "buckets[{}].timeout is deprecated, use buckets[{}].timeouts.operation",
+          index, index
+        ));
+      },
+      None => {},
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_legacy_timeout_is_migrated() {
+    let mut document = json!({
+      "buckets": [{ "name": "b", "timeout": 60 }]
+    });
+
+    let deprecations = migrate(&mut document, KeyStyle::CamelCase).unwrap();
+
+    assert_eq!(document["buckets"][0]["timeouts"]["operation"], 60);
+    assert!(document["buckets"][0].get("timeout").is_none());
+    assert_eq!(document["apiVersion"], "v2");
+    assert_eq!(deprecations.len(), 2);
+  }
+
+  #[test]
+  fn test_explicit_operation_timeout_wins() {
+    let mut document = json!({
+      "buckets": [{ "name": "b", "timeout": 60, "timeouts": { "operation": 10 } }]
+    });
+
+    migrate(&mut document, KeyStyle::CamelCase).unwrap();
+
+    assert_eq!(document["buckets"][0]["timeouts"]["operation"], 10);
+  }
+
+  #[test]
+  fn test_current_version_is_untouched() {
+    let mut document = json!({
+      "api_version": "v2",
+      "buckets": [{ "name": "b", "timeouts": { "operation": 10 } }]
+    });
+    let expected = document.clone();
+
+    let deprecations = migrate(&mut document, KeyStyle::SnakeCase).unwrap();
+
+    assert!(deprecations.is_empty());
+    assert_eq!(document, expected);
+  }
+
+  #[test]
+  fn test_newer_version_is_rejected() {
+    let mut document = json!({ "apiVersion": "v99" });
+    assert!(migrate(&mut document, KeyStyle::CamelCase).is_err());
+  }
+}
@@ -1,2 +1,5 @@
 pub mod config;
+pub mod http_date;
+pub mod migration;
 pub mod storage;
+pub mod token_hash;
@@ -1,5 +1,7 @@
 use async_trait::async_trait;
 use thiserror::Error;
+
+use crate::domain::config::Codec;
 use tokio::io::AsyncRead;
 use tokio_util::io::ReaderStream;
 
@@ -13,6 +15,72 @@ pub enum StorageError {
   OperationFailed,
 }
 
+/// Boxed reader used to pass object data to and from storage providers
+pub type DynAsyncRead = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Wrap any reader into the boxed stream accepted by [`StorageProvider::store`]
+pub fn boxed_reader_stream(
+  reader: impl AsyncRead + Send + Unpin + 'static,
+) -> ReaderStream<DynAsyncRead> {
+  ReaderStream::new(Box::new(reader))
+}
+
+/// Descriptive metadata attached to an object when it is stored
+///
+/// Backends that cannot store metadata ignore it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectMetadata {
+  /// Name of the service token that uploaded the object
+  pub token: String,
+  /// Key prefix of that token
+  pub namespace: String,
+  /// Unix time in seconds of the upload
+  pub uploaded_at: u64,
+  /// Size as sent by the client, before compression or encryption
+  pub content_length: Option<u64>,
+  /// Requested retention in seconds from the upload time
+  pub ttl_seconds: Option<u64>,
+  /// CI run that produced the object
+  pub run_id: Option<String>,
+  /// Codec the stored bytes are compressed with
+  pub content_encoding: Option<Codec>,
+  /// Cost allocation tags of the uploading token
+  pub cost_tags: Vec<(String, String)>,
+  /// Replace an existing object in one unconditional write instead of failing with
+  /// `AlreadyExists`
+  pub overwrite: bool,
+}
+
+/// Size and timestamps of a stored object, as a HEAD request reports them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjectStat {
+  /// Size in bytes of the decoded object, `None` if it is only known after reading it
+  pub size: Option<u64>,
+  /// Unix time in seconds of the last write to the object
+  pub last_modified: Option<u64>,
+  /// Unix time in seconds of the upload, from the object's metadata
+  pub created_at: Option<u64>,
+}
+
+/// One page of a listing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListPage {
+  pub keys: Vec<String>,
+  /// Opaque cursor to pass back for the next page, `None` on the last page
+  pub next: Option<String>,
+}
+
+/// Number and size of the objects below a prefix
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrefixUsage {
+  pub objects: u64,
+  /// Stored bytes, after compression or encryption
+  pub bytes: u64,
+}
+
+/// Object storage backend
+///
+/// The trait is object safe so heterogeneous providers can be held as `Arc<dyn StorageProvider>`.
 #[async_trait]
 pub trait StorageProvider: Send + Sync + 'static {
   /// Check if an object exists at the given hash key
@@ -24,11 +92,120 @@ pub trait StorageProvider: Send + Sync + 'static {
   async fn store(
     &self,
     hash: &str,
-    data: ReaderStream<impl AsyncRead + Send + Unpin + 'static>,
+    data: ReaderStream<DynAsyncRead>,
     content_length: Option<u64>,
   ) -> Result<(), StorageError>;
 
+  /// Store data like [`StorageProvider::store`], attaching descriptive metadata
+  /// The default implementation drops the metadata.
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    _metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    self.store(hash, data, content_length).await
+  }
+
   /// Retrieve object as a stream from storage
   /// Returns NotFound error if object doesn't exist
-  async fn retrieve(&self, hash: &str) -> Result<Box<dyn AsyncRead + Send + Unpin>, StorageError>;
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError>;
+
+  /// Retrieve an object still compressed if it is stored with one of the `accepted` codecs
+  /// Returns the codec the reader yields, `None` for the decoded object. The default
+  /// implementation always decodes.
+  async fn retrieve_encoded(
+    &self,
+    hash: &str,
+    _accepted: &[Codec],
+  ) -> Result<(DynAsyncRead, Option<Codec>), StorageError> {
+    Ok((self.retrieve(hash).await?, None))
+  }
+
+  /// Size of the object in bytes
+  /// Returns NotFound error if object doesn't exist. The default implementation reads the
+  /// whole object, providers that can look up the size cheaply should override it.
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    let mut reader = self.retrieve(hash).await?;
+    tokio::io::copy(&mut reader, &mut tokio::io::sink())
+      .await
+      .map_err(|_| StorageError::OperationFailed)
+  }
+
+  /// Size and timestamps of the object
+  /// Returns NotFound error if object doesn't exist. The default implementation only knows
+  /// the size; providers that can look up timestamps should override it.
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    Ok(ObjectStat {
+      size: Some(self.size(hash).await?),
+      ..ObjectStat::default()
+    })
+  }
+
+  /// Keys of all objects whose key starts with `prefix`
+  /// Providers that cannot enumerate objects keep the default, which fails.
+  async fn list(&self, _prefix: &str) -> Result<Vec<String>, StorageError> {
+    Err(StorageError::OperationFailed)
+  }
+
+  /// Up to `limit` keys starting with `prefix`, continuing after a previous page's cursor
+  /// The default implementation pages through [`StorageProvider::list`] with the last key as
+  /// cursor; providers with native pagination should override it.
+  async fn list_page(
+    &self,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    let limit = limit.max(1);
+    let mut keys = self.list(prefix).await?;
+    keys.sort();
+    keys.retain(|key| cursor.is_none_or(|cursor| key.as_str() > cursor));
+    let next = (keys.len() > limit).then(|| keys[limit - 1].clone());
+    keys.truncate(limit);
+    Ok(ListPage { keys, next })
+  }
+
+  /// Number and stored size of the objects whose key starts with `prefix`
+  /// The default implementation looks up the size of every listed key; providers whose
+  /// listings carry object sizes should override it.
+  async fn usage(&self, prefix: &str) -> Result<PrefixUsage, StorageError> {
+    let mut usage = PrefixUsage::default();
+    for key in self.list(prefix).await? {
+      usage.objects += 1;
+      usage.bytes += self.size(&key).await?;
+    }
+    Ok(usage)
+  }
+
+  /// Immediate child prefixes of `prefix` (ending in `/`), like a delimiter listing
+  /// The default implementation derives them from [`StorageProvider::list`]; providers that
+  /// can ask the backend for common prefixes should override it.
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let mut prefixes: Vec<String> = self
+      .list(prefix)
+      .await?
+      .iter()
+      .filter_map(|key| {
+        let rest = key.strip_prefix(prefix)?;
+        let end = rest.find('/')?;
+        Some(format!("{}{}", prefix, &rest[..=end]))
+      })
+      .collect();
+    prefixes.sort();
+    prefixes.dedup();
+    Ok(prefixes)
+  }
+
+  /// Delete the object at the given hash key; deleting a missing object succeeds
+  /// Providers that cannot delete objects keep the default, which fails.
+  async fn delete(&self, _hash: &str) -> Result<(), StorageError> {
+    Err(StorageError::OperationFailed)
+  }
+
+  /// Verify the backend is reachable and usable, called once at startup
+  async fn test_connection(&self) -> Result<(), StorageError> {
+    Ok(())
+  }
 }
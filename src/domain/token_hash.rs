@@ -0,0 +1,72 @@
+//! Access token hashes in the configuration: argon2 in PHC string format
+//! (`$argon2id$v=19$m=19456,t=2,p=1$...`) and bcrypt (`$2b$12$...`)
+
+use argon2::password_hash::{PasswordHash, PasswordVerifier};
+use argon2::Argon2;
+use std::str::FromStr;
+
+/// Check that `hash` is an argon2 or bcrypt hash this server can verify against
+pub fn validate(hash: &str) -> Result<(), String> {
+  if hash.starts_with("$argon2") {
+    let parsed = PasswordHash::new(hash).map_err(|e| format!("invalid argon2 hash: {}", e))?;
+    if parsed.hash.is_none() || parsed.salt.is_none() {
+      return Err("invalid argon2 hash: missing salt or hash".to_string());
+    }
+    argon2::Params::try_from(&parsed)
+      .map(|_| ())
+      .map_err(|e| format!("invalid argon2 hash: {}", e))
+  } else if is_bcrypt(hash) {
+    bcrypt::HashParts::from_str(hash)
+      .map(|_| ())
+      .map_err(|e| format!("invalid bcrypt hash: {}", e))
+  } else {
+    Err("expected an argon2 ($argon2id$...) or bcrypt ($2b$...) hash".to_string())
+  }
+}
+
+/// Whether `token` hashes to `hash`; deliberately slow, so callers run it off the runtime
+pub fn verify(hash: &str, token: &str) -> bool {
+  if hash.starts_with("$argon2") {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+      Argon2::default()
+        .verify_password(token.as_bytes(), &hash)
+        .is_ok()
+    })
+  } else if is_bcrypt(hash) {
+    bcrypt::verify(token, hash).unwrap_or(false)
+  } else {
+    false
+  }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+  ["$2a$", "$2b$", "$2x$", "$2y$"]
+    .iter()
+    .any(|prefix| hash.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use argon2::password_hash::{PasswordHasher, SaltString};
+
+  #[test]
+  fn test_verify() {
+    let salt = SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+    let argon2 = Argon2::default()
+      .hash_password(b"secret", &salt)
+      .unwrap()
+      .to_string();
+    let bcrypt = bcrypt::hash("secret", 4).unwrap();
+
+    for hash in [&argon2, &bcrypt] {
+      assert_eq!(validate(hash), Ok(()));
+      assert!(verify(hash, "secret"));
+      assert!(!verify(hash, "other"));
+    }
+    assert!(validate("secret").is_err());
+    assert!(validate("$argon2id$broken").is_err());
+    assert!(validate("$2b$12$short").is_err());
+    assert!(!verify("secret", "secret"));
+  }
+}
@@ -0,0 +1,364 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::domain::config::ConcurrencyConfig;
+
+/// AIMD (additive increase, multiplicative decrease) concurrency limiter
+///
+/// The limit grows by roughly one slot per window of fast, successful operations
+/// and shrinks multiplicatively whenever an operation fails or exceeds the latency
+/// threshold, so storage concurrency follows what the backend can currently sustain.
+///
+/// While every slot is taken, waiting operations are granted slots by start-time fair
+/// queuing across tenants rather than in arrival order: each tenant's next request is
+/// tagged one cost unit (scaled down by its weight) after its previous one, and the
+/// smallest tag goes first. A tenant with a burst of a thousand queued requests therefore
+/// cannot hold back another tenant's handful by more than one request each.
+pub struct AdaptiveLimiter {
+  state: Mutex<LimiterState>,
+  notify: Notify,
+  min_limit: f64,
+  max_limit: f64,
+  latency_threshold: Duration,
+  backoff_ratio: f64,
+}
+
+/// Virtual time a request of a tenant with weight 1 costs
+const REQUEST_COST: u64 = 1 << 20;
+
+#[derive(Debug)]
+struct LimiterState {
+  limit: f64,
+  in_flight: usize,
+  /// Start tag of the most recently granted request
+  virtual_time: u64,
+  /// Tag after each tenant's latest request, where its next request starts
+  next_start: HashMap<Arc<str>, u64>,
+  /// Waiting requests as (start tag, arrival ticket), served smallest first
+  waiting: BTreeSet<(u64, u64)>,
+  next_ticket: u64,
+}
+
+impl LimiterState {
+  fn has_slot(&self) -> bool {
+    (self.in_flight as f64) < self.limit.floor()
+  }
+}
+
+/// Who an operation is queued for while the limiter is saturated
+#[derive(Debug, Clone)]
+pub struct Tenant {
+  name: Arc<str>,
+  weight: u32,
+}
+
+impl Tenant {
+  pub fn new(name: &str, weight: u32) -> Self {
+    Self {
+      name: Arc::from(name),
+      weight: weight.max(1),
+    }
+  }
+}
+
+impl Default for Tenant {
+  /// Shared tenant for work not attributed to a token
+  fn default() -> Self {
+    Self::new("", 1)
+  }
+}
+
+/// A queued request; leaves the queue when dropped before it was granted a slot
+struct Waiter<'a> {
+  limiter: &'a AdaptiveLimiter,
+  key: (u64, u64),
+  granted: bool,
+}
+
+impl Drop for Waiter<'_> {
+  fn drop(&mut self) {
+    if !self.granted {
+      let mut state = self.limiter.state.lock().unwrap_or_else(|e| e.into_inner());
+      state.waiting.remove(&self.key);
+      drop(state);
+      // The next request in line may be able to go now
+      self.limiter.notify.notify_waiters();
+    }
+  }
+}
+
+/// Outcome of a limited operation, used to adjust the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+  Success,
+  Failure,
+}
+
+impl AdaptiveLimiter {
+  pub fn new(config: &ConcurrencyConfig) -> Self {
+    let min_limit = config.min_limit.max(1) as f64;
+    let max_limit = (config.max_limit as f64).max(min_limit);
+    let initial = (config.initial_limit as f64).clamp(min_limit, max_limit);
+
+    Self {
+      state: Mutex::new(LimiterState {
+        limit: initial,
+        in_flight: 0,
+        virtual_time: 0,
+        next_start: HashMap::new(),
+        waiting: BTreeSet::new(),
+        next_ticket: 0,
+      }),
+      notify: Notify::new(),
+      min_limit,
+      max_limit,
+      latency_threshold: Duration::from_millis(config.latency_threshold_ms),
+      backoff_ratio: config.backoff_ratio.clamp(0.1, 0.99),
+    }
+  }
+
+  /// Wait until a concurrency slot is available, queued for the shared tenant
+  pub async fn acquire(self: &Arc<Self>) -> LimiterPermit {
+    self.acquire_for(&Tenant::default()).await
+  }
+
+  /// Wait until a concurrency slot is available, queued fairly against other tenants
+  pub async fn acquire_for(self: &Arc<Self>, tenant: &Tenant) -> LimiterPermit {
+    let key = {
+      let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+      let virtual_time = state.virtual_time;
+      let start = state
+        .next_start
+        .get(&tenant.name)
+        .map_or(virtual_time, |next| (*next).max(virtual_time));
+      state.next_start.insert(
+        tenant.name.clone(),
+        start + REQUEST_COST / u64::from(tenant.weight),
+      );
+      let ticket = state.next_ticket;
+      state.next_ticket += 1;
+      state.waiting.insert((start, ticket));
+      (start, ticket)
+    };
+    let mut waiter = Waiter {
+      limiter: self,
+      key,
+      granted: false,
+    };
+
+    loop {
+      let notified = self.notify.notified();
+      {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.has_slot() && state.waiting.first() == Some(&key) {
+          state.waiting.remove(&key);
+          state.in_flight += 1;
+          state.virtual_time = state.virtual_time.max(key.0);
+          waiter.granted = true;
+          let wake_next = state.has_slot() && !state.waiting.is_empty();
+          drop(state);
+          if wake_next {
+            self.notify.notify_waiters();
+          }
+          return LimiterPermit {
+            limiter: self.clone(),
+            started: Instant::now(),
+            released: false,
+          };
+        }
+      }
+      notified.await;
+    }
+  }
+
+  /// Number of operations waiting for a slot
+  pub fn queued(&self) -> usize {
+    let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.waiting.len()
+  }
+
+  /// Current concurrency limit (rounded down)
+  pub fn limit(&self) -> usize {
+    let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.limit.floor() as usize
+  }
+
+  /// Number of operations currently holding a slot
+  pub fn in_flight(&self) -> usize {
+    let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.in_flight
+  }
+
+  fn release(&self, sample: Option<(Duration, Outcome)>) {
+    {
+      let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+      let in_flight = state.in_flight;
+      state.in_flight = in_flight.saturating_sub(1);
+
+      if let Some((latency, outcome)) = sample {
+        if outcome == Outcome::Failure || latency > self.latency_threshold {
+          state.limit = (state.limit * self.backoff_ratio).max(self.min_limit);
+          tracing::debug!(
+            "Adaptive limiter backing off to {:.1} (latency {:?}, outcome {:?})",
+            state.limit,
+            latency,
+            outcome
+          );
+        } else if (in_flight as f64) * 2.0 >= state.limit {
+          // Only grow while the current limit is actually being used
+          state.limit = (state.limit + 1.0 / state.limit).min(self.max_limit);
+        }
+      }
+    }
+    self.notify.notify_waiters();
+  }
+}
+
+/// A held concurrency slot; released on `complete` or drop
+pub struct LimiterPermit {
+  limiter: Arc<AdaptiveLimiter>,
+  started: Instant,
+  released: bool,
+}
+
+impl LimiterPermit {
+  /// Release the slot and feed the observed latency and outcome into the limiter
+  pub fn complete(mut self, outcome: Outcome) {
+    self.released = true;
+    self
+      .limiter
+      .release(Some((self.started.elapsed(), outcome)));
+  }
+}
+
+impl Drop for LimiterPermit {
+  fn drop(&mut self) {
+    if !self.released {
+      // Cancelled operations release their slot without adjusting the limit
+      self.limiter.release(None);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config() -> ConcurrencyConfig {
+    ConcurrencyConfig {
+      initial_limit: 10,
+      min_limit: 2,
+      max_limit: 20,
+      latency_threshold_ms: 100,
+      backoff_ratio: 0.5,
+    }
+  }
+
+  #[tokio::test]
+  async fn test_failure_shrinks_limit() {
+    let limiter = Arc::new(AdaptiveLimiter::new(&config()));
+    let permit = limiter.acquire().await;
+    permit.complete(Outcome::Failure);
+    assert_eq!(limiter.limit(), 5);
+    assert_eq!(limiter.in_flight(), 0);
+  }
+
+  #[tokio::test]
+  async fn test_limit_never_below_minimum() {
+    let limiter = Arc::new(AdaptiveLimiter::new(&config()));
+    for _ in 0..10 {
+      let permit = limiter.acquire().await;
+      permit.complete(Outcome::Failure);
+    }
+    assert_eq!(limiter.limit(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_success_under_load_grows_limit() {
+    let limiter = Arc::new(AdaptiveLimiter::new(&config()));
+    let mut permits = Vec::new();
+    for _ in 0..10 {
+      permits.push(limiter.acquire().await);
+    }
+    for permit in permits {
+      permit.complete(Outcome::Success);
+    }
+    assert!(limiter.limit() >= 10);
+    assert!(limiter.limit() <= 20);
+  }
+
+  #[tokio::test]
+  async fn test_dropped_permit_releases_slot() {
+    let limiter = Arc::new(AdaptiveLimiter::new(&config()));
+    let permit = limiter.acquire().await;
+    assert_eq!(limiter.in_flight(), 1);
+    drop(permit);
+    assert_eq!(limiter.in_flight(), 0);
+    assert_eq!(limiter.limit(), 10);
+  }
+
+  fn single_slot() -> Arc<AdaptiveLimiter> {
+    Arc::new(AdaptiveLimiter::new(&ConcurrencyConfig {
+      initial_limit: 1,
+      min_limit: 1,
+      max_limit: 1,
+      latency_threshold_ms: 60_000,
+      backoff_ratio: 0.5,
+    }))
+  }
+
+  #[tokio::test]
+  async fn test_saturated_limiter_interleaves_tenants() {
+    let limiter = single_slot();
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let held = limiter.acquire().await;
+
+    let spawn = |name: &'static str| {
+      let limiter = limiter.clone();
+      let order = order.clone();
+      tokio::spawn(async move {
+        let permit = limiter.acquire_for(&Tenant::new(name, 1)).await;
+        order.lock().unwrap().push(name);
+        permit.complete(Outcome::Success);
+      })
+    };
+    let mut tasks: Vec<_> = (0..5).map(|_| spawn("monorepo")).collect();
+    tokio::task::yield_now().await;
+    tasks.push(spawn("small"));
+    tokio::task::yield_now().await;
+    assert_eq!(limiter.queued(), 6);
+
+    drop(held);
+    for task in tasks {
+      task.await.unwrap();
+    }
+    let order = order.lock().unwrap();
+    let small = order.iter().position(|name| *name == "small").unwrap();
+    assert!(
+      small <= 1,
+      "small tenant served at {} in {:?}",
+      small,
+      order
+    );
+  }
+
+  #[tokio::test]
+  async fn test_cancelled_waiter_leaves_queue() {
+    let limiter = single_slot();
+    let held = limiter.acquire().await;
+    let waiting = tokio::spawn({
+      let limiter = limiter.clone();
+      async move { limiter.acquire().await }
+    });
+    tokio::task::yield_now().await;
+    assert_eq!(limiter.queued(), 1);
+
+    waiting.abort();
+    let _ = waiting.await;
+    assert_eq!(limiter.queued(), 0);
+    drop(held);
+    drop(limiter.acquire().await);
+  }
+}
@@ -0,0 +1,268 @@
+use minio::s3::creds::{Credentials, Provider};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+use crate::domain::{config::ResolvedBucketConfig, storage::StorageError};
+use crate::infra::nx_cache_store::percent_encode;
+use crate::infra::sigv4::{self, SignedRequest, Signer};
+
+/// Lifetime requested for assumed role credentials
+const SESSION_DURATION: Duration = Duration::from_secs(3600);
+
+/// Credentials are refreshed once this much of their lifetime has passed
+const REFRESH_AFTER: Duration = Duration::from_secs(SESSION_DURATION.as_secs() * 3 / 4);
+
+/// Delay before retrying a failed refresh while the current credentials are still valid
+const REFRESH_RETRY: Duration = Duration::from_secs(30);
+
+const SESSION_NAME: &str = "nx-cache-server";
+
+/// Region signing requests to the global STS endpoint
+const GLOBAL_REGION: &str = "us-east-1";
+
+/// Assumes a bucket's IAM role through STS, signing with the bucket's own credentials
+pub struct AssumeRole {
+  client: reqwest::Client,
+  endpoint: String,
+  region: String,
+  role_arn: String,
+  external_id: Option<String>,
+  access_key: String,
+  secret_key: String,
+  session_token: Option<String>,
+}
+
+/// Credentials of an assumed role, kept fresh by a background task
+///
+/// The task stops once the last clone of the provider is dropped.
+#[derive(Clone)]
+pub struct AssumeRoleProvider {
+  role_arn: String,
+  credentials: Arc<RwLock<Credentials>>,
+}
+
+impl std::fmt::Debug for AssumeRoleProvider {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("AssumeRoleProvider")
+      .field("role_arn", &self.role_arn)
+      .finish_non_exhaustive()
+  }
+}
+
+impl Provider for AssumeRoleProvider {
+  fn fetch(&self) -> Credentials {
+    self
+      .credentials
+      .read()
+      .unwrap_or_else(|e| e.into_inner())
+      .clone()
+  }
+}
+
+impl AssumeRole {
+  /// Role assumption for a bucket, if it sets `roleArn`
+  pub fn from_resolved_bucket(
+    bucket_config: &ResolvedBucketConfig,
+  ) -> Result<Option<Self>, StorageError> {
+    let Some(role_arn) = &bucket_config.role_arn else {
+      return Ok(None);
+    };
+    let (Some(access_key), Some(secret_key)) = (
+      &bucket_config.access_key_id,
+      &bucket_config.secret_access_key,
+    ) else {
+      tracing::error!(
+        "Bucket '{}': credentials are required to assume role {}",
+        bucket_config.name,
+        role_arn
+      );
+      return Err(StorageError::OperationFailed);
+    };
+
+    let (endpoint, region) = match &bucket_config.region {
+      Some(region) => (
+        format!("https://sts.{}.amazonaws.com/", region),
+        region.clone(),
+      ),
+      None => (
+        "https://sts.amazonaws.com/".to_string(),
+        GLOBAL_REGION.to_string(),
+      ),
+    };
+    let client = reqwest::Client::builder()
+      .timeout(Duration::from_secs(bucket_config.timeout))
+      .build()
+      .map_err(|e| {
+        tracing::error!("Failed to create STS client: {}", e);
+        StorageError::OperationFailed
+      })?;
+
+    Ok(Some(Self {
+      client,
+      endpoint,
+      region,
+      role_arn: role_arn.clone(),
+      external_id: bucket_config.external_id.clone(),
+      access_key: access_key.clone(),
+      secret_key: secret_key.clone(),
+      session_token: bucket_config.session_token.clone(),
+    }))
+  }
+
+  /// Assume the role once, then keep refreshing the credentials in the background
+  pub async fn into_provider(self) -> Result<AssumeRoleProvider, StorageError> {
+    let credentials = Arc::new(RwLock::new(self.assume().await?));
+    tracing::info!("Assumed role {}", self.role_arn);
+
+    let provider = AssumeRoleProvider {
+      role_arn: self.role_arn.clone(),
+      credentials: credentials.clone(),
+    };
+    tokio::spawn(self.refresh(Arc::downgrade(&credentials)));
+    Ok(provider)
+  }
+
+  async fn refresh(self, credentials: Weak<RwLock<Credentials>>) {
+    let mut delay = REFRESH_AFTER;
+    loop {
+      tokio::time::sleep(delay).await;
+      let Some(credentials) = credentials.upgrade() else {
+        return;
+      };
+      delay = match self.assume().await {
+        Ok(fresh) => {
+          *credentials.write().unwrap_or_else(|e| e.into_inner()) = fresh;
+          tracing::debug!("Refreshed credentials for role {}", self.role_arn);
+          REFRESH_AFTER
+        },
+        Err(_) => {
+          tracing::warn!(
+            "Failed to refresh credentials for role {}, retrying",
+            self.role_arn
+          );
+          REFRESH_RETRY
+        },
+      };
+    }
+  }
+
+  fn body(&self) -> String {
+    let mut params = vec![
+      ("Action", "AssumeRole".to_string()),
+      ("DurationSeconds", SESSION_DURATION.as_secs().to_string()),
+      ("RoleArn", self.role_arn.clone()),
+      ("RoleSessionName", SESSION_NAME.to_string()),
+      ("Version", "2011-06-15".to_string()),
+    ];
+    if let Some(external_id) = &self.external_id {
+      params.push(("ExternalId", external_id.clone()));
+    }
+    params
+      .iter()
+      .map(|(name, value)| format!("{}={}", name, percent_encode(value)))
+      .collect::<Vec<_>>()
+      .join("&")
+  }
+
+  async fn assume(&self) -> Result<Credentials, StorageError> {
+    let body = self.body();
+    let host = self
+      .endpoint
+      .trim_start_matches("https://")
+      .trim_end_matches('/');
+    let timestamp = sigv4::timestamp_now();
+
+    let mut headers = vec![
+      (
+        "content-type",
+        "application/x-www-form-urlencoded; charset=utf-8".to_string(),
+      ),
+      ("host", host.to_string()),
+      ("x-amz-date", timestamp.clone()),
+    ];
+    if let Some(token) = &self.session_token {
+      headers.push(("x-amz-security-token", token.clone()));
+    }
+    let signer = Signer {
+      access_key: &self.access_key,
+      secret_key: &self.secret_key,
+      region: &self.region,
+      service: "sts",
+    };
+    let authorization = signer.authorization(
+      &timestamp,
+      &SignedRequest {
+        method: "POST",
+        path: "/",
+        query: "",
+        headers: &headers,
+        payload_hash: &sigv4::payload_hash(body.as_bytes()),
+      },
+    );
+
+    let mut request = self
+      .client
+      .post(&self.endpoint)
+      .header("authorization", authorization)
+      .body(body);
+    for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+      request = request.header(*name, value);
+    }
+    let response = request.send().await.map_err(|e| {
+      tracing::error!("STS request for role {} failed: {}", self.role_arn, e);
+      StorageError::OperationFailed
+    })?;
+    let status = response.status();
+    let text = response.text().await.map_err(|e| {
+      tracing::error!("Failed to read STS response: {}", e);
+      StorageError::OperationFailed
+    })?;
+    if !status.is_success() {
+      tracing::error!(
+        "Failed to assume role {} ({}): {}",
+        self.role_arn,
+        status,
+        xml_value(&text, "Message").unwrap_or(&text)
+      );
+      return Err(StorageError::OperationFailed);
+    }
+
+    match (
+      xml_value(&text, "AccessKeyId"),
+      xml_value(&text, "SecretAccessKey"),
+      xml_value(&text, "SessionToken"),
+    ) {
+      (Some(access_key), Some(secret_key), Some(session_token)) => Ok(Credentials {
+        access_key: access_key.to_string(),
+        secret_key: secret_key.to_string(),
+        session_token: Some(session_token.to_string()),
+      }),
+      _ => {
+        tracing::error!("STS response for role {} has no credentials", self.role_arn);
+        Err(StorageError::OperationFailed)
+      },
+    }
+  }
+}
+
+/// Text of the first `<tag>` element, enough for flat AWS API responses
+pub(crate) fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+  let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+  let end = start + xml[start..].find(&format!("</{}>", tag))?;
+  Some(xml[start..end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_xml_value() {
+    let xml = "<AssumeRoleResponse><AssumeRoleResult><Credentials>\
+      <AccessKeyId>ASIA123</AccessKeyId><SessionToken>token</SessionToken>\
+      </Credentials></AssumeRoleResult></AssumeRoleResponse>";
+    assert_eq!(xml_value(xml, "AccessKeyId"), Some("ASIA123"));
+    assert_eq!(xml_value(xml, "SessionToken"), Some("token"));
+    assert_eq!(xml_value(xml, "SecretAccessKey"), None);
+  }
+}
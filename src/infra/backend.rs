@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use crate::domain::{
+  config::{s3_express_zone, BackendType, ResolvedBucketConfig},
+  storage::{StorageError, StorageProvider},
+};
+use crate::infra::fs_storage::FsStorage;
+use crate::infra::nx_cache_store::{ClientPool, NxCacheStorage};
+use crate::infra::s3_express::S3ExpressStorage;
+
+/// Endpoint of the S3-compatible Google Cloud Storage XML API
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// Instantiate the storage provider selected by a bucket's `type`
+///
+/// S3-compatible buckets take their client from `clients`, shared with earlier buckets that
+/// have the same endpoint and credentials.
+pub async fn from_resolved_bucket(
+  bucket_config: &ResolvedBucketConfig,
+  clients: &mut ClientPool,
+) -> Result<Arc<dyn StorageProvider>, StorageError> {
+  match bucket_config.backend {
+    BackendType::S3 if s3_express_zone(&bucket_config.bucket_name).is_some() => Ok(Arc::new(
+      S3ExpressStorage::from_resolved_bucket(bucket_config).await?,
+    )),
+    BackendType::S3 => Ok(Arc::new(
+      NxCacheStorage::from_resolved_bucket_with_pool(bucket_config, clients).await?,
+    )),
+    BackendType::Minio => {
+      let mut bucket_config = bucket_config.clone();
+      bucket_config.force_path_style = true;
+      Ok(Arc::new(
+        NxCacheStorage::from_resolved_bucket_with_pool(&bucket_config, clients).await?,
+      ))
+    },
+    BackendType::Gcs => {
+      let mut bucket_config = bucket_config.clone();
+      bucket_config
+        .endpoint_url
+        .get_or_insert_with(|| GCS_ENDPOINT.to_string());
+      Ok(Arc::new(
+        NxCacheStorage::from_resolved_bucket_with_pool(&bucket_config, clients).await?,
+      ))
+    },
+    BackendType::Fs => {
+      let path = bucket_config.path.as_deref().ok_or_else(|| {
+        tracing::error!(
+          "Bucket '{}': path is required for type fs",
+          bucket_config.name
+        );
+        StorageError::OperationFailed
+      })?;
+      Ok(Arc::new(FsStorage::new(path).await?))
+    },
+  }
+}
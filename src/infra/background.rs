@@ -0,0 +1,58 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Tracks fire-and-forget storage work (write-behind uploads, async replication)
+/// so it can be awaited before the process exits
+#[derive(Debug, Default)]
+pub struct BackgroundTasks {
+  pending: AtomicUsize,
+  idle: Notify,
+}
+
+/// Decrements the pending counter even if the task panics
+struct PendingGuard(Arc<BackgroundTasks>);
+
+impl Drop for PendingGuard {
+  fn drop(&mut self) {
+    if self.0.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+      self.0.idle.notify_waiters();
+    }
+  }
+}
+
+impl BackgroundTasks {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Spawn a tracked task on the current runtime
+  pub fn spawn<F>(self: &Arc<Self>, task: F)
+  where
+    F: Future<Output = ()> + Send + 'static,
+  {
+    self.pending.fetch_add(1, Ordering::AcqRel);
+    let guard = PendingGuard(self.clone());
+    tokio::spawn(async move {
+      let _guard = guard;
+      task.await;
+    });
+  }
+
+  /// Number of tasks that have not finished yet
+  pub fn pending(&self) -> usize {
+    self.pending.load(Ordering::Acquire)
+  }
+
+  /// Wait until all tracked tasks have finished
+  pub async fn wait_idle(&self) {
+    loop {
+      let notified = self.idle.notified();
+      if self.pending() == 0 {
+        return;
+      }
+      notified.await;
+    }
+  }
+}
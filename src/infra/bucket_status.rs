@@ -0,0 +1,242 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_util::io::ReaderStream;
+
+use crate::domain::config::{BackendType, Codec, ResolvedBucketConfig};
+use crate::domain::storage::{
+  DynAsyncRead, ListPage, ObjectMetadata, ObjectStat, PrefixUsage, StorageError, StorageProvider,
+};
+
+/// How often the connectivity of every bucket is probed in the background
+pub const BUCKET_STATUS_INTERVAL: Duration = Duration::from_secs(30);
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+/// Result of the most recent connectivity test of a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Probe {
+  pub reachable: bool,
+  /// Unix time of the test
+  pub checked_at: u64,
+}
+
+/// Outcome of the operations on one bucket, shared by its [`StatusStorage`]
+#[derive(Debug, Default)]
+pub struct BucketStatus {
+  /// Unix time of the last successful operation, 0 if there was none yet
+  last_success: AtomicU64,
+  /// Unix time of the last failed operation, 0 if there was none yet
+  last_failure: AtomicU64,
+  probe: Mutex<Option<Probe>>,
+}
+
+impl BucketStatus {
+  /// NotFound and AlreadyExists are answers from a working backend and count as successes
+  pub fn record<T>(&self, result: &Result<T, StorageError>) {
+    let field = match result {
+      Err(StorageError::OperationFailed) => &self.last_failure,
+      _ => &self.last_success,
+    };
+    field.store(now(), Ordering::Relaxed);
+  }
+
+  pub fn record_probe(&self, reachable: bool) {
+    let probe = Probe {
+      reachable,
+      checked_at: now(),
+    };
+    *self.probe.lock().unwrap_or_else(|e| e.into_inner()) = Some(probe);
+  }
+
+  pub fn probe(&self) -> Option<Probe> {
+    *self.probe.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  pub fn last_success(&self) -> Option<u64> {
+    Some(self.last_success.load(Ordering::Relaxed)).filter(|&at| at > 0)
+  }
+
+  pub fn last_failure(&self) -> Option<u64> {
+    Some(self.last_failure.load(Ordering::Relaxed)).filter(|&at| at > 0)
+  }
+}
+
+/// Non-secret settings of a bucket, shown by the verbose health endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketSummary {
+  pub backend: BackendType,
+  pub bucket_name: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub region: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub fallback_bucket: Option<String>,
+  pub encrypted: bool,
+  pub compressed: bool,
+  pub local_tier: bool,
+  pub maintenance_windows: usize,
+}
+
+impl From<&ResolvedBucketConfig> for BucketSummary {
+  fn from(config: &ResolvedBucketConfig) -> Self {
+    Self {
+      backend: config.backend,
+      bucket_name: config.bucket_name.clone(),
+      region: config.region.clone(),
+      fallback_bucket: config.fallback_bucket.clone(),
+      encrypted: config.encryption_key.is_some(),
+      compressed: config.compression.is_some(),
+      local_tier: config.local_tier.is_some(),
+      maintenance_windows: config.maintenance_windows.len(),
+    }
+  }
+}
+
+/// Status of one bucket as reported by `/healthz?verbose=1`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketStatusReport {
+  pub name: String,
+  /// Latest background connectivity test, absent until the first one finished
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub probe: Option<Probe>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub last_success_at: Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub last_failure_at: Option<u64>,
+  pub config: BucketSummary,
+}
+
+/// Storage wrapper recording when operations on a bucket last succeeded and failed
+pub struct StatusStorage {
+  inner: Arc<dyn StorageProvider>,
+  status: Arc<BucketStatus>,
+}
+
+impl StatusStorage {
+  pub fn new(inner: Arc<dyn StorageProvider>, status: Arc<BucketStatus>) -> Self {
+    Self { inner, status }
+  }
+
+  fn track<T>(&self, result: Result<T, StorageError>) -> Result<T, StorageError> {
+    self.status.record(&result);
+    result
+  }
+}
+
+#[async_trait]
+impl StorageProvider for StatusStorage {
+  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
+    self.track(self.inner.exists(hash).await)
+  }
+
+  async fn store(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+  ) -> Result<(), StorageError> {
+    self.track(self.inner.store(hash, data, content_length).await)
+  }
+
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    self.track(
+      self
+        .inner
+        .store_with_metadata(hash, data, content_length, metadata)
+        .await,
+    )
+  }
+
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError> {
+    self.track(self.inner.retrieve(hash).await)
+  }
+
+  async fn retrieve_encoded(
+    &self,
+    hash: &str,
+    accepted: &[Codec],
+  ) -> Result<(DynAsyncRead, Option<Codec>), StorageError> {
+    self.track(self.inner.retrieve_encoded(hash, accepted).await)
+  }
+
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    self.track(self.inner.size(hash).await)
+  }
+
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    self.track(self.inner.stat(hash).await)
+  }
+
+  async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.track(self.inner.list(prefix).await)
+  }
+
+  async fn list_page(
+    &self,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    self.track(self.inner.list_page(prefix, cursor, limit).await)
+  }
+
+  async fn usage(&self, prefix: &str) -> Result<PrefixUsage, StorageError> {
+    self.track(self.inner.usage(prefix).await)
+  }
+
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.track(self.inner.list_prefixes(prefix).await)
+  }
+
+  async fn delete(&self, hash: &str) -> Result<(), StorageError> {
+    self.track(self.inner.delete(hash).await)
+  }
+
+  async fn test_connection(&self) -> Result<(), StorageError> {
+    let result = self.inner.test_connection().await;
+    self.status.record_probe(result.is_ok());
+    result
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::infra::fs_storage::FsStorage;
+
+  #[tokio::test]
+  async fn test_records_operations_and_probes() {
+    let root = tempfile::tempdir().unwrap();
+    let status = Arc::new(BucketStatus::default());
+    let storage = StatusStorage::new(
+      Arc::new(FsStorage::new(root.path()).await.unwrap()),
+      status.clone(),
+    );
+    assert_eq!(status.last_success(), None);
+    assert_eq!(status.probe(), None);
+
+    // A miss is an answer from a working backend
+    assert!(!storage.exists("abc").await.unwrap());
+    assert!(status.last_success().is_some());
+    assert_eq!(status.last_failure(), None);
+
+    storage.test_connection().await.unwrap();
+    assert!(status.probe().unwrap().reachable);
+  }
+}
@@ -0,0 +1,425 @@
+use async_compression::tokio::bufread::{GzipDecoder, GzipEncoder, ZstdDecoder, ZstdEncoder};
+use async_compression::Level;
+use async_trait::async_trait;
+use std::io::{self, Cursor};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader, ReadBuf};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::domain::config::Codec;
+use crate::domain::storage::{
+  boxed_reader_stream, DynAsyncRead, ListPage, ObjectMetadata, ObjectStat, PrefixUsage,
+  StorageError, StorageProvider,
+};
+
+/// Marks zstd objects written by this wrapper (format version 1)
+const ZSTD_MAGIC: &[u8; 4] = b"NXZ1";
+/// Marks gzip objects written by this wrapper (format version 1)
+const GZIP_MAGIC: &[u8; 4] = b"NXG1";
+/// Magic followed by the uncompressed length (u64 BE)
+const HEADER_LEN: usize = ZSTD_MAGIC.len() + 8;
+/// Stored length when the client did not announce the object size
+const UNKNOWN_LEN: u64 = u64::MAX;
+
+fn magic(codec: Codec) -> &'static [u8; 4] {
+  match codec {
+    Codec::Zstd => ZSTD_MAGIC,
+    Codec::Gzip => GZIP_MAGIC,
+  }
+}
+
+/// Decompress a stored object's body following its header
+fn decoder(codec: Codec, reader: DynAsyncRead) -> DynAsyncRead {
+  match codec {
+    Codec::Zstd => Box::new(ZstdDecoder::new(BufReader::new(reader))),
+    Codec::Gzip => Box::new(GzipDecoder::new(BufReader::new(reader))),
+  }
+}
+
+/// Decompress a stored object, failing with `InvalidData` unless it decodes to the length
+/// recorded in its header
+fn verified_decoder(codec: Codec, plain_len: u64, reader: DynAsyncRead) -> DynAsyncRead {
+  let decoded = decoder(codec, reader);
+  if plain_len == UNKNOWN_LEN {
+    return decoded;
+  }
+  Box::new(LengthCheck {
+    inner: decoded,
+    expected: plain_len,
+    received: 0,
+  })
+}
+
+struct LengthCheck {
+  inner: DynAsyncRead,
+  expected: u64,
+  received: u64,
+}
+
+impl AsyncRead for LengthCheck {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    let before = buf.filled().len();
+    match Pin::new(&mut this.inner).poll_read(cx, buf) {
+      Poll::Ready(Ok(())) => {
+        let read = (buf.filled().len() - before) as u64;
+        this.received += read;
+        let at_end = read == 0 && buf.remaining() > 0;
+        if this.received > this.expected || (at_end && this.received != this.expected) {
+          return Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+              "decompressed object is {} bytes, expected {}",
+              this.received, this.expected
+            ),
+          )));
+        }
+        Poll::Ready(Ok(()))
+      },
+      other => other,
+    }
+  }
+}
+
+/// Storage wrapper that compresses objects before they reach the inner provider
+///
+/// Compressed objects start with a small header holding a per-codec marker and the original
+/// length, so objects written with another codec stay readable after the codec is changed.
+/// Objects without a marker, e.g. written before compression was enabled, are served as is.
+pub struct CompressedStorage {
+  inner: Arc<dyn StorageProvider>,
+  codec: Codec,
+  level: i32,
+}
+
+/// An object's leading bytes, split by whether they carry the compression header
+enum Header {
+  Compressed { codec: Codec, plain_len: u64 },
+  Plain(Vec<u8>),
+}
+
+impl CompressedStorage {
+  pub fn new(inner: Arc<dyn StorageProvider>, codec: Codec, level: i32) -> Self {
+    Self {
+      inner,
+      codec,
+      level,
+    }
+  }
+
+  /// Compress an upload behind a header recording its original length
+  fn compress(
+    &self,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+  ) -> ReaderStream<DynAsyncRead> {
+    let mut header = magic(self.codec).to_vec();
+    header.extend_from_slice(&content_length.unwrap_or(UNKNOWN_LEN).to_be_bytes());
+
+    let reader = StreamReader::new(data);
+    let level = Level::Precise(self.level);
+    let encoder: DynAsyncRead = match self.codec {
+      Codec::Zstd => Box::new(ZstdEncoder::with_quality(reader, level)),
+      Codec::Gzip => Box::new(GzipEncoder::with_quality(reader, level)),
+    };
+    boxed_reader_stream(Cursor::new(header).chain(encoder))
+  }
+
+  /// Read up to the header length from an object, stopping early only at end of stream
+  async fn read_header(reader: &mut DynAsyncRead) -> Result<Header, StorageError> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    reader
+      .take(HEADER_LEN as u64)
+      .read_to_end(&mut header)
+      .await
+      .map_err(|e| {
+        tracing::error!("Failed to read object header: {}", e);
+        StorageError::OperationFailed
+      })?;
+
+    let codec = [Codec::Zstd, Codec::Gzip]
+      .into_iter()
+      .find(|codec| header.starts_with(magic(*codec)));
+    match codec {
+      Some(codec) if header.len() == HEADER_LEN => {
+        let mut plain_len = [0u8; 8];
+        plain_len.copy_from_slice(&header[ZSTD_MAGIC.len()..]);
+        Ok(Header::Compressed {
+          codec,
+          plain_len: u64::from_be_bytes(plain_len),
+        })
+      },
+      _ => Ok(Header::Plain(header)),
+    }
+  }
+}
+
+#[async_trait]
+impl StorageProvider for CompressedStorage {
+  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
+    self.inner.exists(hash).await
+  }
+
+  async fn store(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+  ) -> Result<(), StorageError> {
+    // The compressed size is only known once the upload has finished
+    let compressed = self.compress(data, content_length);
+    self.inner.store(hash, compressed, None).await
+  }
+
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    let compressed = self.compress(data, content_length);
+    let metadata = ObjectMetadata {
+      content_encoding: Some(self.codec),
+      ..metadata.clone()
+    };
+    self
+      .inner
+      .store_with_metadata(hash, compressed, None, &metadata)
+      .await
+  }
+
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError> {
+    let mut reader = self.inner.retrieve(hash).await?;
+
+    match Self::read_header(&mut reader).await? {
+      Header::Compressed { codec, plain_len } => Ok(verified_decoder(codec, plain_len, reader)),
+      Header::Plain(prefix) => Ok(Box::new(Cursor::new(prefix).chain(reader))),
+    }
+  }
+
+  async fn retrieve_encoded(
+    &self,
+    hash: &str,
+    accepted: &[Codec],
+  ) -> Result<(DynAsyncRead, Option<Codec>), StorageError> {
+    let mut reader = self.inner.retrieve(hash).await?;
+
+    match Self::read_header(&mut reader).await? {
+      // Hand out the stored bytes without decompressing them
+      Header::Compressed { codec, .. } if accepted.contains(&codec) => Ok((reader, Some(codec))),
+      Header::Compressed { codec, plain_len } => {
+        Ok((verified_decoder(codec, plain_len, reader), None))
+      },
+      Header::Plain(prefix) => Ok((Box::new(Cursor::new(prefix).chain(reader)), None)),
+    }
+  }
+
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    let mut reader = self.inner.retrieve(hash).await?;
+
+    match Self::read_header(&mut reader).await? {
+      Header::Compressed { plain_len, .. } if plain_len != UNKNOWN_LEN => Ok(plain_len),
+      Header::Compressed { codec, .. } => {
+        let mut decoder = decoder(codec, reader);
+        tokio::io::copy(&mut decoder, &mut tokio::io::sink())
+          .await
+          .map_err(|_| StorageError::OperationFailed)
+      },
+      Header::Plain(_) => self.inner.size(hash).await,
+    }
+  }
+
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    // The inner stat has the stored size, the decoded one is in the header if it was known
+    let (stat, reader) = tokio::join!(self.inner.stat(hash), self.inner.retrieve(hash));
+    let stat = stat?;
+    let size = match Self::read_header(&mut reader?).await? {
+      Header::Compressed { plain_len, .. } => (plain_len != UNKNOWN_LEN).then_some(plain_len),
+      Header::Plain(_) => stat.size,
+    };
+    Ok(ObjectStat { size, ..stat })
+  }
+
+  async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.inner.list(prefix).await
+  }
+
+  async fn list_page(
+    &self,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    self.inner.list_page(prefix, cursor, limit).await
+  }
+
+  async fn usage(&self, prefix: &str) -> Result<PrefixUsage, StorageError> {
+    self.inner.usage(prefix).await
+  }
+
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.inner.list_prefixes(prefix).await
+  }
+
+  async fn delete(&self, hash: &str) -> Result<(), StorageError> {
+    self.inner.delete(hash).await
+  }
+
+  async fn test_connection(&self) -> Result<(), StorageError> {
+    self.inner.test_connection().await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::infra::fs_storage::FsStorage;
+
+  async fn storage_with(root: &std::path::Path, codec: Codec) -> CompressedStorage {
+    let inner: Arc<dyn StorageProvider> = Arc::new(FsStorage::new(root).await.unwrap());
+    CompressedStorage::new(inner, codec, 3)
+  }
+
+  async fn storage(root: &std::path::Path) -> CompressedStorage {
+    storage_with(root, Codec::Zstd).await
+  }
+
+  async fn read_all(storage: &CompressedStorage, hash: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut reader = storage.retrieve(hash).await.unwrap();
+    reader.read_to_end(&mut data).await.unwrap();
+    data
+  }
+
+  #[tokio::test]
+  async fn test_roundtrip_compresses() {
+    let root = tempfile::tempdir().unwrap();
+    let storage = storage(root.path()).await;
+    let data = b"nx build output ".repeat(4096);
+
+    storage
+      .store(
+        "abc",
+        boxed_reader_stream(Cursor::new(data.clone())),
+        Some(data.len() as u64),
+      )
+      .await
+      .unwrap();
+
+    let on_disk = std::fs::read(root.path().join("abc")).unwrap();
+    assert!(on_disk.starts_with(ZSTD_MAGIC));
+    assert!(on_disk.len() < data.len() / 10);
+    assert_eq!(read_all(&storage, "abc").await, data);
+    assert_eq!(storage.size("abc").await.unwrap(), data.len() as u64);
+  }
+
+  #[tokio::test]
+  async fn test_unknown_length_is_counted() {
+    let root = tempfile::tempdir().unwrap();
+    let storage = storage(root.path()).await;
+
+    storage
+      .store(
+        "abc",
+        boxed_reader_stream(Cursor::new(b"artifact".to_vec())),
+        None,
+      )
+      .await
+      .unwrap();
+
+    assert_eq!(storage.size("abc").await.unwrap(), 8);
+  }
+
+  #[tokio::test]
+  async fn test_retrieve_encoded_negotiates_codec() {
+    let root = tempfile::tempdir().unwrap();
+    let data = b"nx build output ".repeat(1024);
+    storage(root.path())
+      .await
+      .store(
+        "old",
+        boxed_reader_stream(Cursor::new(data.clone())),
+        Some(data.len() as u64),
+      )
+      .await
+      .unwrap();
+
+    // Switching codecs keeps objects written with the previous one readable
+    let storage = storage_with(root.path(), Codec::Gzip).await;
+    storage
+      .store("new", boxed_reader_stream(Cursor::new(data.clone())), None)
+      .await
+      .unwrap();
+    assert!(std::fs::read(root.path().join("new"))
+      .unwrap()
+      .starts_with(GZIP_MAGIC));
+    assert_eq!(read_all(&storage, "old").await, data);
+
+    let (reader, codec) = storage
+      .retrieve_encoded("new", &[Codec::Gzip])
+      .await
+      .unwrap();
+    assert_eq!(codec, Some(Codec::Gzip));
+    let mut decoded = Vec::new();
+    GzipDecoder::new(BufReader::new(reader))
+      .read_to_end(&mut decoded)
+      .await
+      .unwrap();
+    assert_eq!(decoded, data);
+
+    let (mut reader, codec) = storage
+      .retrieve_encoded("old", &[Codec::Gzip])
+      .await
+      .unwrap();
+    assert_eq!(codec, None);
+    let mut plain = Vec::new();
+    reader.read_to_end(&mut plain).await.unwrap();
+    assert_eq!(plain, data);
+  }
+
+  #[tokio::test]
+  async fn test_length_mismatch_is_invalid_data() {
+    let root = tempfile::tempdir().unwrap();
+    let storage = storage(root.path()).await;
+    let data = b"nx build output ".repeat(64);
+    storage
+      .store(
+        "abc",
+        boxed_reader_stream(Cursor::new(data.clone())),
+        Some(data.len() as u64),
+      )
+      .await
+      .unwrap();
+
+    let path = root.path().join("abc");
+    let mut stored = std::fs::read(&path).unwrap();
+    stored[HEADER_LEN - 1] ^= 1;
+    std::fs::write(&path, stored).unwrap();
+
+    let mut reader = storage.retrieve("abc").await.unwrap();
+    let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[tokio::test]
+  async fn test_uncompressed_objects_pass_through() {
+    let root = tempfile::tempdir().unwrap();
+    let storage = storage(root.path()).await;
+    std::fs::write(root.path().join("short"), b"abc").unwrap();
+    std::fs::write(root.path().join("long"), b"an uncompressed artifact").unwrap();
+
+    assert_eq!(read_all(&storage, "short").await, b"abc");
+    assert_eq!(
+      read_all(&storage, "long").await,
+      b"an uncompressed artifact"
+    );
+    assert_eq!(storage.size("long").await.unwrap(), 24);
+  }
+}
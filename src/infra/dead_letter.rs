@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory below a token's prefix holding its dead letters
+pub const DEAD_LETTERS_DIR: &str = ".dead-letters";
+
+/// Attempts made at an asynchronous delivery before it is dead-lettered
+pub const MAX_DELIVERY_ATTEMPTS: usize = 3;
+
+/// Background work that gave up after its last attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeadLetterKind {
+  /// Asynchronous copy of an object to a replica bucket
+  Replication,
+}
+
+/// A permanently failed delivery, kept so it can be inspected and retried
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+  pub id: String,
+  pub kind: DeadLetterKind,
+  /// Object key in the token's bucket
+  pub key: String,
+  /// Bucket the object was to be delivered to
+  pub target_bucket: String,
+  pub content_length: Option<u64>,
+  /// Unix time of the last failed attempt
+  pub failed_at: u64,
+  pub attempts: usize,
+  pub error: String,
+}
+
+impl DeadLetter {
+  pub fn replication(
+    key: &str,
+    target_bucket: &str,
+    content_length: Option<u64>,
+    attempts: usize,
+    error: String,
+  ) -> Self {
+    let failed_at = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or(0);
+    // Repeated failures of one delivery overwrite the same record
+    let digest = Sha256::digest(format!("{}\n{}", target_bucket, key));
+    Self {
+      id: hex::encode(&digest[..12]),
+      kind: DeadLetterKind::Replication,
+      key: key.to_string(),
+      target_bucket: target_bucket.to_string(),
+      content_length,
+      failed_at,
+      attempts,
+      error,
+    }
+  }
+}
+
+/// Object name of a dead letter below the token's prefix
+pub fn object_name(id: &str) -> String {
+  format!("{}/{}.json", DEAD_LETTERS_DIR, id)
+}
+
+/// Dead letter IDs are hex digests; anything else cannot name a record
+pub fn is_valid_id(id: &str) -> bool {
+  !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_id_is_stable_per_delivery() {
+    let first = DeadLetter::replication("/ci/abc", "replica", Some(3), 3, "boom".to_string());
+    let again = DeadLetter::replication("/ci/abc", "replica", None, 3, "boom".to_string());
+    let other = DeadLetter::replication("/ci/abc", "backup", None, 3, "boom".to_string());
+    assert_eq!(first.id, again.id);
+    assert_ne!(first.id, other.id);
+    assert!(is_valid_id(&first.id));
+    assert!(!is_valid_id("../abc"));
+
+    let json = serde_json::to_value(&first).unwrap();
+    assert_eq!(json["kind"], "replication");
+    assert_eq!(json["targetBucket"], "replica");
+  }
+}
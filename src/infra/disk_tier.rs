@@ -0,0 +1,287 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tokio_util::io::ReaderStream;
+
+use crate::domain::storage::{ObjectStat, StorageError};
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Local disk tier that accepts writes immediately and serves reads ahead of the bucket
+#[derive(Debug, Clone)]
+pub struct DiskTier {
+  root: PathBuf,
+}
+
+impl DiskTier {
+  /// Create the tier, making sure the root directory exists
+  pub async fn new(root: impl Into<PathBuf>) -> Result<Self, StorageError> {
+    let root = root.into();
+    fs::create_dir_all(&root).await.map_err(|e| {
+      tracing::error!(
+        "Failed to create local tier directory '{}': {}",
+        root.display(),
+        e
+      );
+      StorageError::OperationFailed
+    })?;
+    Ok(Self { root })
+  }
+
+  /// Map an object key to a path below the tier root, rejecting traversal
+  fn path_for(&self, key: &str) -> Result<PathBuf, StorageError> {
+    let relative = Path::new(key);
+    if relative
+      .components()
+      .any(|c| !matches!(c, Component::Normal(_)))
+    {
+      tracing::error!("Refusing unsafe local tier key: {}", key);
+      return Err(StorageError::OperationFailed);
+    }
+    Ok(self.root.join(relative))
+  }
+
+  pub async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+    let path = self.path_for(key)?;
+    fs::try_exists(&path).await.map_err(|e| {
+      tracing::error!(
+        "Failed to check local tier file '{}': {}",
+        path.display(),
+        e
+      );
+      StorageError::OperationFailed
+    })
+  }
+
+  /// Open a stored object, returning `None` if the tier doesn't hold it
+  pub async fn open(&self, key: &str) -> Result<Option<File>, StorageError> {
+    let path = self.path_for(key)?;
+    match File::open(&path).await {
+      Ok(file) => Ok(Some(file)),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(e) => {
+        tracing::error!("Failed to open local tier file '{}': {}", path.display(), e);
+        Err(StorageError::OperationFailed)
+      },
+    }
+  }
+
+  /// Size of a stored object, returning `None` if the tier doesn't hold it
+  pub async fn size(&self, key: &str) -> Result<Option<u64>, StorageError> {
+    Ok(self.stat(key).await?.and_then(|stat| stat.size))
+  }
+
+  /// Size and file times of a stored object, returning `None` if the tier doesn't hold it
+  pub async fn stat(&self, key: &str) -> Result<Option<ObjectStat>, StorageError> {
+    let path = self.path_for(key)?;
+    let unix_secs = |time: std::io::Result<SystemTime>| {
+      time
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+    };
+    match fs::metadata(&path).await {
+      Ok(metadata) => Ok(Some(ObjectStat {
+        size: Some(metadata.len()),
+        last_modified: unix_secs(metadata.modified()),
+        created_at: unix_secs(metadata.created()),
+      })),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(e) => {
+        tracing::error!("Failed to stat local tier file '{}': {}", path.display(), e);
+        Err(StorageError::OperationFailed)
+      },
+    }
+  }
+
+  /// Keys of stored objects starting with `prefix`, skipping in-progress temp files
+  ///
+  /// Only the directory holding the last path segment of `prefix` is scanned.
+  pub async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.entries(prefix, false).await
+  }
+
+  /// Keys of stored objects starting with `prefix`, descending into subdirectories
+  pub async fn list_recursive(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let mut keys = Vec::new();
+    let mut pending = vec![prefix.to_string()];
+    while let Some(prefix) = pending.pop() {
+      keys.extend(self.entries(&prefix, false).await?);
+      pending.extend(self.list_dirs(&prefix).await?);
+    }
+    keys.sort();
+    Ok(keys)
+  }
+
+  /// Subdirectories starting with `prefix`, as keys ending in `/`
+  pub async fn list_dirs(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let dirs = self.entries(prefix, true).await?;
+    Ok(dirs.into_iter().map(|dir| format!("{}/", dir)).collect())
+  }
+
+  async fn entries(&self, prefix: &str, dirs: bool) -> Result<Vec<String>, StorageError> {
+    let (dir, name_prefix) = match prefix.rsplit_once('/') {
+      Some((dir, name)) => (Some(dir), name),
+      None => (None, prefix),
+    };
+    let path = match dir {
+      Some(dir) => self.path_for(dir)?,
+      None => self.root.clone(),
+    };
+
+    let mut entries = match fs::read_dir(&path).await {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => {
+        tracing::error!(
+          "Failed to list local tier directory '{}': {}",
+          path.display(),
+          e
+        );
+        return Err(StorageError::OperationFailed);
+      },
+    };
+
+    let mut keys = Vec::new();
+    loop {
+      let entry = match entries.next_entry().await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => break,
+        Err(e) => {
+          tracing::error!(
+            "Failed to list local tier directory '{}': {}",
+            path.display(),
+            e
+          );
+          return Err(StorageError::OperationFailed);
+        },
+      };
+      let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+        continue;
+      };
+      let is_temp = Path::new(&name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.starts_with("tmp-"));
+      let wanted = entry
+        .file_type()
+        .await
+        .is_ok_and(|t| if dirs { t.is_dir() } else { t.is_file() });
+      if name.starts_with(name_prefix) && wanted && !is_temp {
+        keys.push(match dir {
+          Some(dir) => format!("{}/{}", dir, name),
+          None => name,
+        });
+      }
+    }
+    keys.sort();
+    Ok(keys)
+  }
+
+  /// Write a stream to the tier atomically (temp file + rename) and return its size
+  pub async fn write(
+    &self,
+    key: &str,
+    data: ReaderStream<impl AsyncRead + Send + Unpin + 'static>,
+  ) -> Result<u64, StorageError> {
+    self.write_to(key, data, false).await
+  }
+
+  /// Write a stream like [`DiskTier::write`], replacing an existing file in the rename
+  ///
+  /// Readers see the old file until the new one is complete.
+  pub async fn replace(
+    &self,
+    key: &str,
+    data: ReaderStream<impl AsyncRead + Send + Unpin + 'static>,
+  ) -> Result<u64, StorageError> {
+    self.write_to(key, data, true).await
+  }
+
+  async fn write_to(
+    &self,
+    key: &str,
+    mut data: ReaderStream<impl AsyncRead + Send + Unpin + 'static>,
+    replace: bool,
+  ) -> Result<u64, StorageError> {
+    let path = self.path_for(key)?;
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).await.map_err(|e| {
+        tracing::error!("Failed to create local tier directory: {}", e);
+        StorageError::OperationFailed
+      })?;
+    }
+
+    let temp_path = path.with_extension(format!(
+      "tmp-{}-{}",
+      std::process::id(),
+      TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = Self::write_file(&temp_path, &mut data).await;
+    let size = match result {
+      Ok(size) => size,
+      Err(e) => {
+        let _ = fs::remove_file(&temp_path).await;
+        tracing::error!(
+          "Failed to write local tier file '{}': {}",
+          path.display(),
+          e
+        );
+        return Err(StorageError::OperationFailed);
+      },
+    };
+
+    if !replace && fs::try_exists(&path).await.unwrap_or(false) {
+      let _ = fs::remove_file(&temp_path).await;
+      return Err(StorageError::AlreadyExists);
+    }
+
+    fs::rename(&temp_path, &path).await.map_err(|e| {
+      tracing::error!(
+        "Failed to finalize local tier file '{}': {}",
+        path.display(),
+        e
+      );
+      StorageError::OperationFailed
+    })?;
+
+    Ok(size)
+  }
+
+  async fn write_file(
+    path: &Path,
+    data: &mut ReaderStream<impl AsyncRead + Send + Unpin + 'static>,
+  ) -> std::io::Result<u64> {
+    let mut file = File::create(path).await?;
+    let mut size = 0u64;
+    while let Some(chunk) = data.next().await {
+      let chunk = chunk?;
+      size += chunk.len() as u64;
+      file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+    Ok(size)
+  }
+
+  /// Remove a stored object; missing files are not an error
+  pub async fn remove(&self, key: &str) -> Result<(), StorageError> {
+    let path = self.path_for(key)?;
+    match fs::remove_file(&path).await {
+      Ok(()) => Ok(()),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+      Err(e) => {
+        tracing::error!(
+          "Failed to remove local tier file '{}': {}",
+          path.display(),
+          e
+        );
+        Err(StorageError::OperationFailed)
+      },
+    }
+  }
+}
@@ -0,0 +1,497 @@
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures_util::stream::{self, Stream};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::domain::storage::{
+  boxed_reader_stream, DynAsyncRead, ListPage, ObjectMetadata, ObjectStat, PrefixUsage,
+  StorageError, StorageProvider,
+};
+
+/// Identifies objects written by this wrapper (format version 2)
+const MAGIC: &[u8; 4] = b"NXE2";
+const SALT_LEN: usize = 32;
+const HEADER_LEN: u64 = (MAGIC.len() + SALT_LEN) as u64;
+/// Plaintext bytes per encrypted chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+/// Per chunk: final flag (1) + ciphertext length (4) + tag
+const CHUNK_OVERHEAD: u64 = (1 + 4 + TAG_LEN) as u64;
+const KEY_INFO: &[u8] = b"nx-cache-server object key v2";
+
+/// Storage wrapper that encrypts objects with AES-256-GCM before they reach the inner provider
+///
+/// Every object gets its own key, derived with HKDF from the master key, a random salt stored
+/// in the object header and the object's key. The plaintext is sealed in 64 KiB chunks whose
+/// nonce and associated data hold the chunk counter and a final-chunk flag, the associated
+/// data also the object's key, so reordered, dropped or truncated chunks and objects moved
+/// to another key fail to open.
+pub struct EncryptedStorage {
+  inner: Arc<dyn StorageProvider>,
+  master_key: Vec<u8>,
+  rng: SystemRandom,
+}
+
+impl EncryptedStorage {
+  pub fn new(inner: Arc<dyn StorageProvider>, master_key: &[u8]) -> Result<Self, StorageError> {
+    if master_key.len() != AES_256_GCM.key_len() {
+      tracing::error!("Encryption key must be {} bytes", AES_256_GCM.key_len());
+      return Err(StorageError::OperationFailed);
+    }
+    Ok(Self {
+      inner,
+      master_key: master_key.to_vec(),
+      rng: SystemRandom::new(),
+    })
+  }
+
+  fn object_key(&self, salt: &[u8], hash: &str) -> Result<LessSafeKey, StorageError> {
+    let prk = Salt::new(HKDF_SHA256, salt).extract(&self.master_key);
+    let info = [KEY_INFO, hash.as_bytes()];
+    let okm = prk
+      .expand(&info, &AES_256_GCM)
+      .map_err(|_| StorageError::OperationFailed)?;
+    Ok(LessSafeKey::new(UnboundKey::from(okm)))
+  }
+
+  /// Encrypt an upload to `hash` under a fresh per-object key
+  fn seal(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+  ) -> Result<ReaderStream<DynAsyncRead>, StorageError> {
+    let mut salt = [0u8; SALT_LEN];
+    self.rng.fill(&mut salt).map_err(|_| {
+      tracing::error!("Failed to generate encryption salt");
+      StorageError::OperationFailed
+    })?;
+    let key = self.object_key(&salt, hash)?;
+
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&salt);
+    let sealed = StreamReader::new(Box::pin(seal_stream(data, key, hash, header)));
+    Ok(boxed_reader_stream(sealed))
+  }
+}
+
+/// Encrypted size of a plaintext of `plain_len` bytes
+fn encrypted_len(plain_len: u64) -> u64 {
+  let chunks = plain_len / CHUNK_SIZE as u64 + 1;
+  HEADER_LEN + plain_len + chunks * CHUNK_OVERHEAD
+}
+
+/// Plaintext size of an encrypted object of `encrypted_len` bytes
+fn plaintext_len(encrypted_len: u64) -> Option<u64> {
+  let body = encrypted_len.checked_sub(HEADER_LEN)?;
+  let full_chunk = CHUNK_SIZE as u64 + CHUNK_OVERHEAD;
+  let full_chunks = body / full_chunk;
+  let last = (body % full_chunk).checked_sub(CHUNK_OVERHEAD)?;
+  Some(full_chunks * CHUNK_SIZE as u64 + last)
+}
+
+fn chunk_nonce(counter: u32, last: bool) -> Nonce {
+  let mut nonce = [0u8; NONCE_LEN];
+  nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+  nonce[11] = u8::from(last);
+  Nonce::assume_unique_for_key(nonce)
+}
+
+/// Associated data of a chunk: the object's key, the chunk counter and the final-chunk flag
+fn chunk_aad(hash: &str, counter: u32, last: bool) -> Aad<Vec<u8>> {
+  let mut aad = Vec::with_capacity(hash.len() + 5);
+  aad.extend_from_slice(hash.as_bytes());
+  aad.extend_from_slice(&counter.to_be_bytes());
+  aad.push(u8::from(last));
+  Aad::from(aad)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Read up to `limit` bytes, stopping early only at end of stream
+async fn read_chunk(
+  reader: &mut (impl tokio::io::AsyncRead + Unpin),
+  limit: usize,
+) -> io::Result<Vec<u8>> {
+  let mut chunk = Vec::with_capacity(limit + TAG_LEN);
+  reader.take(limit as u64).read_to_end(&mut chunk).await?;
+  Ok(chunk)
+}
+
+/// Encrypt a plaintext stream into header + framed chunks
+fn seal_stream(
+  data: ReaderStream<DynAsyncRead>,
+  key: LessSafeKey,
+  hash: &str,
+  header: Vec<u8>,
+) -> impl Stream<Item = io::Result<Bytes>> + Send {
+  struct State {
+    reader: StreamReader<ReaderStream<DynAsyncRead>, Bytes>,
+    key: LessSafeKey,
+    hash: String,
+    header: Option<Vec<u8>>,
+    counter: u32,
+    finished: bool,
+  }
+
+  let state = State {
+    reader: StreamReader::new(data),
+    key,
+    hash: hash.to_string(),
+    header: Some(header),
+    counter: 0,
+    finished: false,
+  };
+
+  stream::try_unfold(state, |mut state| async move {
+    if let Some(header) = state.header.take() {
+      return Ok(Some((Bytes::from(header), state)));
+    }
+    if state.finished {
+      return Ok(None);
+    }
+
+    let mut chunk = read_chunk(&mut state.reader, CHUNK_SIZE).await?;
+    let last = chunk.len() < CHUNK_SIZE;
+    let aad = chunk_aad(&state.hash, state.counter, last);
+    state
+      .key
+      .seal_in_place_append_tag(chunk_nonce(state.counter, last), aad, &mut chunk)
+      .map_err(|_| invalid_data("failed to encrypt chunk"))?;
+    state.counter = state
+      .counter
+      .checked_add(1)
+      .ok_or_else(|| invalid_data("object too large to encrypt"))?;
+    state.finished = last;
+
+    let mut frame = Vec::with_capacity(5 + chunk.len());
+    frame.push(u8::from(last));
+    frame.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&chunk);
+    Ok(Some((Bytes::from(frame), state)))
+  })
+}
+
+/// Decrypt framed chunks (after the header has been consumed) into a plaintext stream
+fn open_stream(
+  reader: DynAsyncRead,
+  key: LessSafeKey,
+  hash: &str,
+) -> impl Stream<Item = io::Result<Bytes>> + Send {
+  struct State {
+    reader: DynAsyncRead,
+    key: LessSafeKey,
+    hash: String,
+    counter: u32,
+    finished: bool,
+  }
+
+  let state = State {
+    reader,
+    key,
+    hash: hash.to_string(),
+    counter: 0,
+    finished: false,
+  };
+
+  stream::try_unfold(state, |mut state| async move {
+    if state.finished {
+      return Ok(None);
+    }
+
+    let mut frame_header = [0u8; 5];
+    state
+      .reader
+      .read_exact(&mut frame_header)
+      .await
+      .map_err(|_| invalid_data("encrypted object is truncated"))?;
+    let last = match frame_header[0] {
+      0 => false,
+      1 => true,
+      _ => return Err(invalid_data("invalid encrypted chunk header")),
+    };
+    let len = u32::from_be_bytes([
+      frame_header[1],
+      frame_header[2],
+      frame_header[3],
+      frame_header[4],
+    ]) as usize;
+    if !(TAG_LEN..=CHUNK_SIZE + TAG_LEN).contains(&len) {
+      return Err(invalid_data("invalid encrypted chunk length"));
+    }
+
+    let mut chunk = vec![0u8; len];
+    state.reader.read_exact(&mut chunk).await?;
+    let aad = chunk_aad(&state.hash, state.counter, last);
+    let plaintext_len = state
+      .key
+      .open_in_place(chunk_nonce(state.counter, last), aad, &mut chunk)
+      .map_err(|_| invalid_data("encrypted chunk failed authentication"))?
+      .len();
+    chunk.truncate(plaintext_len);
+    state.counter = state
+      .counter
+      .checked_add(1)
+      .ok_or_else(|| invalid_data("encrypted object has too many chunks"))?;
+    state.finished = last;
+    Ok(Some((Bytes::from(chunk), state)))
+  })
+}
+
+#[async_trait]
+impl StorageProvider for EncryptedStorage {
+  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
+    self.inner.exists(hash).await
+  }
+
+  async fn store(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+  ) -> Result<(), StorageError> {
+    let sealed = self.seal(hash, data)?;
+    self
+      .inner
+      .store(hash, sealed, content_length.map(encrypted_len))
+      .await
+  }
+
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    let sealed = self.seal(hash, data)?;
+    self
+      .inner
+      .store_with_metadata(hash, sealed, content_length.map(encrypted_len), metadata)
+      .await
+  }
+
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError> {
+    let mut reader = self.inner.retrieve(hash).await?;
+
+    let mut header = [0u8; HEADER_LEN as usize];
+    reader.read_exact(&mut header).await.map_err(|e| {
+      tracing::warn!("Encrypted object {} has no valid header: {}", hash, e);
+      StorageError::NotFound
+    })?;
+    if &header[..MAGIC.len()] != MAGIC {
+      // Written before encryption was enabled or in an older format; treat as a miss rather
+      // than serving garbage
+      tracing::warn!(
+        "Object {} is not encrypted in the current format, treating it as missing",
+        hash
+      );
+      return Err(StorageError::NotFound);
+    }
+    let key = self.object_key(&header[MAGIC.len()..], hash)?;
+
+    Ok(Box::new(StreamReader::new(Box::pin(open_stream(
+      reader, key, hash,
+    )))))
+  }
+
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    let size = self.inner.size(hash).await?;
+    plaintext_len(size).ok_or(StorageError::NotFound)
+  }
+
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    let stat = self.inner.stat(hash).await?;
+    Ok(ObjectStat {
+      size: stat
+        .size
+        .map(|size| plaintext_len(size).ok_or(StorageError::NotFound))
+        .transpose()?,
+      ..stat
+    })
+  }
+
+  async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.inner.list(prefix).await
+  }
+
+  async fn list_page(
+    &self,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    self.inner.list_page(prefix, cursor, limit).await
+  }
+
+  async fn usage(&self, prefix: &str) -> Result<PrefixUsage, StorageError> {
+    self.inner.usage(prefix).await
+  }
+
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.inner.list_prefixes(prefix).await
+  }
+
+  async fn delete(&self, hash: &str) -> Result<(), StorageError> {
+    self.inner.delete(hash).await
+  }
+
+  async fn test_connection(&self) -> Result<(), StorageError> {
+    self.inner.test_connection().await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::infra::fs_storage::FsStorage;
+
+  async fn storage(root: &std::path::Path) -> EncryptedStorage {
+    let inner: Arc<dyn StorageProvider> = Arc::new(FsStorage::new(root).await.unwrap());
+    EncryptedStorage::new(inner, &[7u8; 32]).unwrap()
+  }
+
+  async fn roundtrip(size: usize) {
+    let root = tempfile::tempdir().unwrap();
+    let storage = storage(root.path()).await;
+    let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+
+    storage
+      .store(
+        "abc",
+        boxed_reader_stream(std::io::Cursor::new(data.clone())),
+        Some(size as u64),
+      )
+      .await
+      .unwrap();
+
+    let on_disk = std::fs::read(root.path().join("abc")).unwrap();
+    assert_eq!(on_disk.len() as u64, encrypted_len(size as u64));
+    assert_eq!(storage.size("abc").await.unwrap(), size as u64);
+
+    let mut plaintext = Vec::new();
+    let mut reader = storage.retrieve("abc").await.unwrap();
+    reader.read_to_end(&mut plaintext).await.unwrap();
+    assert_eq!(plaintext, data);
+  }
+
+  #[tokio::test]
+  async fn test_roundtrip_sizes() {
+    roundtrip(0).await;
+    roundtrip(10).await;
+    roundtrip(CHUNK_SIZE).await;
+    roundtrip(CHUNK_SIZE * 2 + 17).await;
+  }
+
+  #[tokio::test]
+  async fn test_tampered_object_fails() {
+    let root = tempfile::tempdir().unwrap();
+    let storage = storage(root.path()).await;
+    storage
+      .store(
+        "abc",
+        boxed_reader_stream(std::io::Cursor::new(b"artifact".to_vec())),
+        None,
+      )
+      .await
+      .unwrap();
+
+    let path = root.path().join("abc");
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&path, bytes).unwrap();
+
+    let mut plaintext = Vec::new();
+    let mut reader = storage.retrieve("abc").await.unwrap();
+    assert!(reader.read_to_end(&mut plaintext).await.is_err());
+  }
+
+  /// Store the same three-chunk plaintext as `abc` and `def`, tamper with the files and read
+  /// `abc` back
+  async fn store_and_read_back(tamper: impl FnOnce(&std::path::Path)) -> io::Result<Vec<u8>> {
+    let root = tempfile::tempdir().unwrap();
+    let storage = storage(root.path()).await;
+    let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 17).map(|i| (i % 251) as u8).collect();
+    for hash in ["abc", "def"] {
+      storage
+        .store(
+          hash,
+          boxed_reader_stream(std::io::Cursor::new(data.clone())),
+          None,
+        )
+        .await
+        .unwrap();
+    }
+    tamper(root.path());
+
+    let mut plaintext = Vec::new();
+    let mut reader = storage.retrieve("abc").await.unwrap();
+    reader.read_to_end(&mut plaintext).await.map(|_| plaintext)
+  }
+
+  #[tokio::test]
+  async fn test_truncated_and_reordered_chunks_fail() {
+    let frame = CHUNK_SIZE + CHUNK_OVERHEAD as usize;
+    let header = HEADER_LEN as usize;
+
+    // Cut after a full chunk, where the next frame would start
+    let truncate = |root: &std::path::Path| {
+      let path = root.join("abc");
+      let bytes = std::fs::read(&path).unwrap();
+      std::fs::write(&path, &bytes[..header + frame]).unwrap();
+    };
+    assert!(store_and_read_back(truncate).await.is_err());
+
+    let reorder = |root: &std::path::Path| {
+      let path = root.join("abc");
+      let mut bytes = std::fs::read(&path).unwrap();
+      let (first, second) = bytes[header..header + 2 * frame].split_at_mut(frame);
+      first.swap_with_slice(second);
+      std::fs::write(&path, bytes).unwrap();
+    };
+    assert!(store_and_read_back(reorder).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_object_moved_to_another_key_fails() {
+    // Same plaintext under two keys; the object of one does not open under the other
+    let swap = |root: &std::path::Path| {
+      std::fs::rename(root.join("def"), root.join("abc")).unwrap();
+    };
+    assert!(store_and_read_back(swap).await.is_err());
+
+    // Only the first chunk moved, with the salt it was sealed under
+    let splice = |root: &std::path::Path| {
+      let chunk_end = HEADER_LEN as usize + CHUNK_SIZE + CHUNK_OVERHEAD as usize;
+      let other = std::fs::read(root.join("def")).unwrap();
+      let mut bytes = std::fs::read(root.join("abc")).unwrap();
+      bytes[..chunk_end].copy_from_slice(&other[..chunk_end]);
+      std::fs::write(root.join("abc"), bytes).unwrap();
+    };
+    assert!(store_and_read_back(splice).await.is_err());
+
+    // Untouched objects still open
+    assert!(store_and_read_back(|_| {}).await.is_ok());
+  }
+
+  #[test]
+  fn test_plaintext_len_inverts_encrypted_len() {
+    for size in [
+      0,
+      1,
+      CHUNK_SIZE as u64 - 1,
+      CHUNK_SIZE as u64,
+      10 * CHUNK_SIZE as u64 + 5,
+    ] {
+      assert_eq!(plaintext_len(encrypted_len(size)), Some(size));
+    }
+  }
+}
@@ -0,0 +1,132 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive failures after which a bucket is taken out of rotation
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+/// How long an unhealthy bucket is skipped before it is probed again
+pub const DEFAULT_RECOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Health-based circuit breaker for a primary bucket with a fallback
+///
+/// After `failure_threshold` consecutive failures the circuit opens and requests go
+/// straight to the fallback. Once `recovery_interval` has passed the primary is tried
+/// again; a success closes the circuit, another failure re-opens it.
+#[derive(Debug)]
+pub struct BucketHealth {
+  state: Mutex<HealthState>,
+  failure_threshold: u32,
+  recovery_interval: Duration,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+  consecutive_failures: u32,
+  open_until: Option<Instant>,
+}
+
+impl Default for BucketHealth {
+  fn default() -> Self {
+    Self::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_RECOVERY_INTERVAL)
+  }
+}
+
+impl BucketHealth {
+  pub fn new(failure_threshold: u32, recovery_interval: Duration) -> Self {
+    Self {
+      state: Mutex::new(HealthState::default()),
+      failure_threshold: failure_threshold.max(1),
+      recovery_interval,
+    }
+  }
+
+  /// Whether requests should currently be sent to the primary
+  pub fn is_available(&self) -> bool {
+    let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    match state.open_until {
+      Some(until) => Instant::now() >= until,
+      None => true,
+    }
+  }
+
+  /// Time until an open circuit lets requests through to the primary again
+  pub fn reopens_in(&self) -> Option<Duration> {
+    let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    state
+      .open_until
+      .map(|until| until.saturating_duration_since(Instant::now()))
+      .filter(|remaining| !remaining.is_zero())
+  }
+
+  /// Whether the primary has not failed since its last success
+  pub fn is_healthy(&self) -> bool {
+    let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.consecutive_failures == 0
+  }
+
+  /// Record a successful primary operation, closing the circuit
+  pub fn record_success(&self) -> bool {
+    let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    let recovered = state.open_until.is_some();
+    state.consecutive_failures = 0;
+    state.open_until = None;
+    recovered
+  }
+
+  /// Record a failed primary operation; returns true if this opened the circuit
+  pub fn record_failure(&self) -> bool {
+    let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+    if state.consecutive_failures >= self.failure_threshold {
+      let was_closed = state.open_until.is_none();
+      state.open_until = Some(Instant::now() + self.recovery_interval);
+      return was_closed;
+    }
+    false
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_opens_after_threshold() {
+    let health = BucketHealth::new(2, Duration::from_secs(60));
+    assert!(!health.record_failure());
+    assert!(health.is_available());
+    assert!(health.record_failure());
+    assert!(!health.is_available());
+    assert!(!health.is_healthy());
+  }
+
+  #[test]
+  fn test_probe_after_recovery_interval() {
+    let health = BucketHealth::new(1, Duration::ZERO);
+    health.record_failure();
+    // Interval elapsed: primary is probed again
+    assert!(health.is_available());
+    assert!(health.record_success());
+    assert!(health.is_healthy());
+  }
+
+  #[test]
+  fn test_reopens_in() {
+    let health = BucketHealth::new(1, Duration::from_secs(60));
+    assert_eq!(health.reopens_in(), None);
+    health.record_failure();
+    let remaining = health.reopens_in().unwrap();
+    assert!(remaining > Duration::from_secs(59) && remaining <= Duration::from_secs(60));
+    health.record_success();
+    assert_eq!(health.reopens_in(), None);
+  }
+
+  #[test]
+  fn test_success_resets_failures() {
+    let health = BucketHealth::new(2, Duration::from_secs(60));
+    health.record_failure();
+    assert!(!health.record_success());
+    health.record_failure();
+    assert!(health.is_available());
+  }
+}
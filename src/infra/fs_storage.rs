@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio_util::io::ReaderStream;
+
+use crate::domain::storage::{
+  DynAsyncRead, ObjectMetadata, ObjectStat, StorageError, StorageProvider,
+};
+use crate::infra::disk_tier::DiskTier;
+
+/// Storage provider keeping objects as files below a local directory
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+  root: PathBuf,
+  disk: DiskTier,
+}
+
+impl FsStorage {
+  /// Create the storage, making sure the root directory exists
+  pub async fn new(root: impl Into<PathBuf>) -> Result<Self, StorageError> {
+    let root = root.into();
+    let disk = DiskTier::new(root.clone()).await?;
+    Ok(Self { root, disk })
+  }
+}
+
+#[async_trait]
+impl StorageProvider for FsStorage {
+  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
+    self.disk.exists(hash).await
+  }
+
+  async fn store(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    _content_length: Option<u64>,
+  ) -> Result<(), StorageError> {
+    if self.disk.exists(hash).await? {
+      return Err(StorageError::AlreadyExists);
+    }
+    self.disk.write(hash, data).await.map(|_| ())
+  }
+
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    if metadata.overwrite {
+      self.disk.replace(hash, data).await.map(|_| ())
+    } else {
+      self.store(hash, data, content_length).await
+    }
+  }
+
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError> {
+    match self.disk.open(hash).await? {
+      Some(file) => Ok(Box::new(file)),
+      None => Err(StorageError::NotFound),
+    }
+  }
+
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    self.disk.size(hash).await?.ok_or(StorageError::NotFound)
+  }
+
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    self.disk.stat(hash).await?.ok_or(StorageError::NotFound)
+  }
+
+  async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.disk.list_recursive(prefix).await
+  }
+
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    self.disk.list_dirs(prefix).await
+  }
+
+  async fn delete(&self, hash: &str) -> Result<(), StorageError> {
+    self.disk.remove(hash).await
+  }
+
+  /// Verify the root directory exists and is writable
+  async fn test_connection(&self) -> Result<(), StorageError> {
+    let metadata = tokio::fs::metadata(&self.root).await.map_err(|e| {
+      tracing::error!(
+        "Failed to access directory '{}': {}",
+        self.root.display(),
+        e
+      );
+      StorageError::OperationFailed
+    })?;
+
+    if !metadata.is_dir() || metadata.permissions().readonly() {
+      tracing::error!("'{}' is not a writable directory", self.root.display());
+      return Err(StorageError::OperationFailed);
+    }
+
+    tracing::info!("Successfully opened directory: {}", self.root.display());
+    Ok(())
+  }
+}
@@ -0,0 +1,298 @@
+use async_trait::async_trait;
+use futures_util::stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::domain::config::{Codec, IsolationConfig};
+use crate::domain::storage::{
+  DynAsyncRead, ListPage, ObjectMetadata, ObjectStat, PrefixUsage, StorageError, StorageProvider,
+};
+
+/// Chunks of a download buffered between the bucket's runtime and the reading client
+const BRIDGE_CHUNKS: usize = 4;
+
+/// Storage wrapper running every operation of a bucket on its own tokio runtime
+///
+/// Downloads are read on the bucket's runtime as well and handed over through a channel,
+/// so decoding and backend I/O stay off the runtime serving requests.
+pub struct IsolatedStorage {
+  inner: Arc<dyn StorageProvider>,
+  runtime: Option<Runtime>,
+}
+
+/// Task on the bucket's runtime, aborted when the request awaiting it is dropped
+struct Isolated<T>(JoinHandle<T>);
+
+impl<T> Future for Isolated<T> {
+  type Output = Result<T, StorageError>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    Pin::new(&mut self.0).poll(cx).map_err(|e| {
+      tracing::error!("Isolated storage operation failed: {}", e);
+      StorageError::OperationFailed
+    })
+  }
+}
+
+impl<T> Drop for Isolated<T> {
+  fn drop(&mut self) {
+    self.0.abort();
+  }
+}
+
+impl IsolatedStorage {
+  pub fn new(
+    inner: Arc<dyn StorageProvider>,
+    bucket: &str,
+    config: &IsolationConfig,
+  ) -> Result<Self, StorageError> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+      .worker_threads(config.worker_threads)
+      .thread_name(format!("nx-cache-{}", bucket))
+      .enable_all()
+      .build()
+      .map_err(|e| {
+        tracing::error!(
+          "Bucket '{}': failed to start isolated runtime: {}",
+          bucket,
+          e
+        );
+        StorageError::OperationFailed
+      })?;
+    Ok(Self {
+      inner,
+      runtime: Some(runtime),
+    })
+  }
+
+  fn handle(&self) -> &Handle {
+    self
+      .runtime
+      .as_ref()
+      .expect("runtime is only taken on drop")
+      .handle()
+  }
+
+  /// Run an operation of the wrapped storage on the bucket's runtime
+  async fn run<T, F, Fut>(&self, operation: F) -> Result<T, StorageError>
+  where
+    T: Send + 'static,
+    F: FnOnce(Arc<dyn StorageProvider>) -> Fut,
+    Fut: Future<Output = Result<T, StorageError>> + Send + 'static,
+  {
+    Isolated(self.handle().spawn(operation(self.inner.clone()))).await?
+  }
+
+  /// Keep reading a download on the bucket's runtime, handing chunks to the caller
+  fn bridge(&self, reader: DynAsyncRead) -> DynAsyncRead {
+    let (sender, receiver) = mpsc::channel(BRIDGE_CHUNKS);
+    self.handle().spawn(async move {
+      let mut chunks = ReaderStream::new(reader);
+      while let Some(chunk) = chunks.next().await {
+        if sender.send(chunk).await.is_err() {
+          return;
+        }
+      }
+    });
+    let chunks = stream::unfold(receiver, |mut receiver| async move {
+      receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    Box::new(StreamReader::new(Box::pin(chunks)))
+  }
+}
+
+impl Drop for IsolatedStorage {
+  fn drop(&mut self) {
+    // Dropping a runtime blocks, which panics inside the server's own runtime
+    if let Some(runtime) = self.runtime.take() {
+      runtime.shutdown_background();
+    }
+  }
+}
+
+#[async_trait]
+impl StorageProvider for IsolatedStorage {
+  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
+    let hash = hash.to_string();
+    self
+      .run(|inner| async move { inner.exists(&hash).await })
+      .await
+  }
+
+  async fn store(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+  ) -> Result<(), StorageError> {
+    let hash = hash.to_string();
+    self
+      .run(|inner| async move { inner.store(&hash, data, content_length).await })
+      .await
+  }
+
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    let hash = hash.to_string();
+    let metadata = metadata.clone();
+    self
+      .run(|inner| async move {
+        inner
+          .store_with_metadata(&hash, data, content_length, &metadata)
+          .await
+      })
+      .await
+  }
+
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError> {
+    let hash = hash.to_string();
+    let reader = self
+      .run(|inner| async move { inner.retrieve(&hash).await })
+      .await?;
+    Ok(self.bridge(reader))
+  }
+
+  async fn retrieve_encoded(
+    &self,
+    hash: &str,
+    accepted: &[Codec],
+  ) -> Result<(DynAsyncRead, Option<Codec>), StorageError> {
+    let hash = hash.to_string();
+    let accepted = accepted.to_vec();
+    let (reader, codec) = self
+      .run(|inner| async move { inner.retrieve_encoded(&hash, &accepted).await })
+      .await?;
+    Ok((self.bridge(reader), codec))
+  }
+
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    let hash = hash.to_string();
+    self
+      .run(|inner| async move { inner.size(&hash).await })
+      .await
+  }
+
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    let hash = hash.to_string();
+    self
+      .run(|inner| async move { inner.stat(&hash).await })
+      .await
+  }
+
+  async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let prefix = prefix.to_string();
+    self
+      .run(|inner| async move { inner.list(&prefix).await })
+      .await
+  }
+
+  async fn list_page(
+    &self,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    let prefix = prefix.to_string();
+    let cursor = cursor.map(str::to_string);
+    self
+      .run(|inner| async move { inner.list_page(&prefix, cursor.as_deref(), limit).await })
+      .await
+  }
+
+  async fn usage(&self, prefix: &str) -> Result<PrefixUsage, StorageError> {
+    let prefix = prefix.to_string();
+    self
+      .run(|inner| async move { inner.usage(&prefix).await })
+      .await
+  }
+
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let prefix = prefix.to_string();
+    self
+      .run(|inner| async move { inner.list_prefixes(&prefix).await })
+      .await
+  }
+
+  async fn delete(&self, hash: &str) -> Result<(), StorageError> {
+    let hash = hash.to_string();
+    self
+      .run(|inner| async move { inner.delete(&hash).await })
+      .await
+  }
+
+  async fn test_connection(&self) -> Result<(), StorageError> {
+    self
+      .run(|inner| async move { inner.test_connection().await })
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io;
+  use tokio::io::AsyncReadExt;
+
+  /// Serves one object whose read fails as corrupted, checking it runs on the bucket's runtime
+  struct ThreadCheck;
+
+  fn on_bucket_runtime() -> bool {
+    std::thread::current()
+      .name()
+      .is_some_and(|name| name == "nx-cache-heavy")
+  }
+
+  #[async_trait]
+  impl StorageProvider for ThreadCheck {
+    async fn exists(&self, _hash: &str) -> Result<bool, StorageError> {
+      Ok(on_bucket_runtime())
+    }
+
+    async fn store(
+      &self,
+      _hash: &str,
+      _data: ReaderStream<DynAsyncRead>,
+      _content_length: Option<u64>,
+    ) -> Result<(), StorageError> {
+      Ok(())
+    }
+
+    async fn retrieve(&self, _hash: &str) -> Result<DynAsyncRead, StorageError> {
+      assert!(on_bucket_runtime());
+      Ok(Box::new(StreamReader::new(tokio_stream::iter(vec![
+        Ok(bytes::Bytes::from_static(b"ok")),
+        Err(io::Error::new(io::ErrorKind::InvalidData, "bad tag")),
+      ]))))
+    }
+  }
+
+  #[tokio::test]
+  async fn test_operations_run_on_bucket_runtime() {
+    let storage = IsolatedStorage::new(
+      Arc::new(ThreadCheck),
+      "heavy",
+      &IsolationConfig { worker_threads: 1 },
+    )
+    .unwrap();
+    assert!(storage.exists("abc").await.unwrap());
+    assert!(!on_bucket_runtime());
+
+    let mut reader = storage.retrieve("abc").await.unwrap();
+    let mut data = Vec::new();
+    let err = reader.read_to_end(&mut data).await.unwrap_err();
+    assert_eq!(data, b"ok");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+  }
+}
@@ -0,0 +1,247 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::io::ReaderStream;
+
+use crate::domain::config::{Codec, MaintenanceWindowConfig};
+use crate::domain::storage::{
+  DynAsyncRead, ListPage, ObjectMetadata, ObjectStat, PrefixUsage, StorageError, StorageProvider,
+};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// A parsed maintenance window with its concurrency slots
+#[derive(Debug)]
+struct Window {
+  /// Start days as days since Monday, every day if empty
+  days: Vec<u32>,
+  /// Minutes after midnight (UTC)
+  start: u32,
+  end: u32,
+  slots: Arc<Semaphore>,
+}
+
+impl Window {
+  fn starts_on(&self, weekday: u32) -> bool {
+    self.days.is_empty() || self.days.contains(&weekday)
+  }
+
+  fn contains(&self, weekday: u32, minute: u32) -> bool {
+    if self.start < self.end {
+      self.starts_on(weekday) && (self.start..self.end).contains(&minute)
+    } else {
+      // Wraps past midnight: the tail end belongs to the previous day's window
+      (self.starts_on(weekday) && minute >= self.start)
+        || (self.starts_on((weekday + 6) % 7) && minute < self.end)
+    }
+  }
+}
+
+/// Recurring maintenance windows of a bucket's storage provider
+#[derive(Debug)]
+pub struct MaintenanceSchedule {
+  windows: Vec<Window>,
+}
+
+impl MaintenanceSchedule {
+  /// Build a schedule from validated window configs, skipping windows with invalid times
+  pub fn new(windows: &[MaintenanceWindowConfig]) -> Self {
+    let windows = windows
+      .iter()
+      .filter_map(|window| {
+        let (start, end) = window.minutes()?;
+        Some(Window {
+          days: window.days.iter().map(|day| day.index()).collect(),
+          start,
+          end,
+          slots: Arc::new(Semaphore::new(window.max_concurrency.max(1))),
+        })
+      })
+      .collect();
+    Self { windows }
+  }
+
+  /// Whether a window is active right now
+  pub fn is_active(&self) -> bool {
+    self.active_at(now_secs()).is_some()
+  }
+
+  fn active_at(&self, unix_secs: u64) -> Option<&Window> {
+    let days = unix_secs / SECONDS_PER_DAY;
+    // 1970-01-01 was a Thursday
+    let weekday = ((days + 3) % 7) as u32;
+    let minute = ((unix_secs % SECONDS_PER_DAY) / 60) as u32;
+    self
+      .windows
+      .iter()
+      .find(|window| window.contains(weekday, minute))
+  }
+
+  /// Wait for a concurrency slot if a window is active, holding it until the permit is dropped
+  async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+    let window = self.active_at(now_secs())?;
+    window.slots.clone().acquire_owned().await.ok()
+  }
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+/// Storage wrapper capping concurrent operations while a maintenance window is active
+///
+/// Only the call itself holds a slot; streaming a retrieved object's body does not.
+pub struct MaintenanceStorage {
+  inner: Arc<dyn StorageProvider>,
+  schedule: Arc<MaintenanceSchedule>,
+}
+
+impl MaintenanceStorage {
+  pub fn new(inner: Arc<dyn StorageProvider>, schedule: Arc<MaintenanceSchedule>) -> Self {
+    Self { inner, schedule }
+  }
+}
+
+#[async_trait]
+impl StorageProvider for MaintenanceStorage {
+  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.exists(hash).await
+  }
+
+  async fn store(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+  ) -> Result<(), StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.store(hash, data, content_length).await
+  }
+
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self
+      .inner
+      .store_with_metadata(hash, data, content_length, metadata)
+      .await
+  }
+
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.retrieve(hash).await
+  }
+
+  async fn retrieve_encoded(
+    &self,
+    hash: &str,
+    accepted: &[Codec],
+  ) -> Result<(DynAsyncRead, Option<Codec>), StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.retrieve_encoded(hash, accepted).await
+  }
+
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.size(hash).await
+  }
+
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.stat(hash).await
+  }
+
+  async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.list(prefix).await
+  }
+
+  async fn list_page(
+    &self,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.list_page(prefix, cursor, limit).await
+  }
+
+  async fn usage(&self, prefix: &str) -> Result<PrefixUsage, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.usage(prefix).await
+  }
+
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.list_prefixes(prefix).await
+  }
+
+  async fn delete(&self, hash: &str) -> Result<(), StorageError> {
+    let _slot = self.schedule.acquire().await;
+    self.inner.delete(hash).await
+  }
+
+  async fn test_connection(&self) -> Result<(), StorageError> {
+    self.inner.test_connection().await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Weekday;
+
+  fn window(days: Vec<Weekday>, start: &str, end: &str) -> MaintenanceWindowConfig {
+    MaintenanceWindowConfig {
+      days,
+      start: start.to_string(),
+      end: end.to_string(),
+      max_concurrency: 1,
+    }
+  }
+
+  /// Unix time for the given day after 1970-01-05 (a Monday) and time of day
+  fn at(days_after_monday: u64, hours: u64, minutes: u64) -> u64 {
+    (4 + days_after_monday) * SECONDS_PER_DAY + hours * 3600 + minutes * 60
+  }
+
+  #[test]
+  fn test_window_on_selected_days() {
+    let schedule = MaintenanceSchedule::new(&[window(vec![Weekday::Tue], "02:00", "04:00")]);
+
+    assert!(schedule.active_at(at(1, 2, 0)).is_some());
+    assert!(schedule.active_at(at(1, 3, 59)).is_some());
+    assert!(schedule.active_at(at(1, 4, 0)).is_none());
+    assert!(schedule.active_at(at(0, 3, 0)).is_none());
+    assert!(schedule.active_at(at(8, 3, 0)).is_some());
+  }
+
+  #[test]
+  fn test_window_wrapping_midnight() {
+    let schedule = MaintenanceSchedule::new(&[window(vec![Weekday::Sun], "23:00", "01:00")]);
+
+    assert!(schedule.active_at(at(6, 23, 30)).is_some());
+    assert!(schedule.active_at(at(7, 0, 30)).is_some());
+    assert!(schedule.active_at(at(7, 1, 0)).is_none());
+    assert!(schedule.active_at(at(0, 23, 30)).is_none());
+  }
+
+  #[tokio::test]
+  async fn test_active_window_limits_concurrency() {
+    let schedule = MaintenanceSchedule::new(&[window(Vec::new(), "00:00", "23:59")]);
+    let window = schedule.active_at(at(0, 12, 0)).unwrap();
+
+    let _permit = window.slots.clone().acquire_owned().await.unwrap();
+    assert!(window.slots.clone().try_acquire_owned().is_err());
+  }
+}
@@ -1,2 +1,21 @@
+pub mod adaptive_limiter;
+pub mod assume_role;
+pub mod backend;
+pub mod background;
+pub mod bucket_status;
+pub mod compressed_storage;
+pub mod dead_letter;
+pub mod disk_tier;
+pub mod encrypted_storage;
+pub mod failover;
+pub mod fs_storage;
+pub mod isolated_storage;
+pub mod maintenance;
 pub mod multi_storage;
+pub mod namespace_audit;
 pub mod nx_cache_store;
+pub mod repair;
+pub mod retry;
+pub mod s3_express;
+pub mod sigv4;
+pub mod spill;
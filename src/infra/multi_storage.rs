@@ -1,34 +1,324 @@
 use async_trait::async_trait;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::future::Future;
+use std::io::Cursor;
 use std::sync::Arc;
-use tokio::io::AsyncRead;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
 use tokio_util::io::ReaderStream;
 
 use crate::domain::{
-  config::{ResolvedConfig, ResolvedServiceAccessToken},
-  storage::{StorageError, StorageProvider},
+  config::{Codec, ReplicationMode, ResolvedConfig, ResolvedServiceAccessToken},
+  storage::{
+    boxed_reader_stream, DynAsyncRead, ListPage, ObjectMetadata, ObjectStat, PrefixUsage,
+    StorageError, StorageProvider,
+  },
 };
-use crate::infra::nx_cache_store::NxCacheStorage;
+use crate::infra::adaptive_limiter::{AdaptiveLimiter, Outcome, Tenant};
+use crate::infra::backend;
+use crate::infra::background::BackgroundTasks;
+use crate::infra::bucket_status::{BucketStatus, BucketStatusReport, BucketSummary, StatusStorage};
+use crate::infra::compressed_storage::CompressedStorage;
+use crate::infra::dead_letter::{self, DeadLetter, DeadLetterKind, MAX_DELIVERY_ATTEMPTS};
+use crate::infra::disk_tier::DiskTier;
+use crate::infra::encrypted_storage::EncryptedStorage;
+use crate::infra::failover::{BucketHealth, DEFAULT_RECOVERY_INTERVAL};
+use crate::infra::isolated_storage::IsolatedStorage;
+use crate::infra::maintenance::{MaintenanceSchedule, MaintenanceStorage};
+use crate::infra::namespace_audit::{self, NamespaceFinding};
+use crate::infra::nx_cache_store::ClientPool;
+use crate::infra::repair::{self, CorruptionWatch, RepairCounts, Repairs};
 
 /// Storage router that manages multiple S3 buckets and routes requests
 /// based on access tokens and their associated prefixes
 #[derive(Clone)]
 pub struct MultiStorageRouter {
   /// Map of bucket name to storage instance
-  storages: Arc<HashMap<String, Arc<NxCacheStorage>>>,
+  storages: Arc<HashMap<String, Arc<dyn StorageProvider>>>,
   /// Map of access token to service configuration
   token_map: Arc<HashMap<String, ResolvedServiceAccessToken>>,
+  /// Map of bucket name to adaptive concurrency limiter (only for buckets that configure one)
+  limiters: Arc<HashMap<String, Arc<AdaptiveLimiter>>>,
+  /// Map of bucket name to local disk tier (only for buckets that configure one)
+  tiers: Arc<HashMap<String, LocalTier>>,
+  /// Map of bucket name to the fallback bucket it fails over to (only for buckets that configure one)
+  failovers: Arc<HashMap<String, Failover>>,
+  /// Map of bucket name to its maintenance windows (only for buckets that configure some)
+  maintenance: Arc<HashMap<String, Arc<MaintenanceSchedule>>>,
+  /// Write-behind uploads and other work that outlives the request
+  background: Arc<BackgroundTasks>,
+  /// Corrupted primary copies found on reads and their repairs from replicas
+  repairs: Arc<Repairs>,
+  /// Map of bucket name to the outcome of its operations and connectivity tests
+  statuses: Arc<HashMap<String, Arc<BucketStatus>>>,
+  /// Map of bucket name to its non-secret settings
+  summaries: Arc<HashMap<String, BucketSummary>>,
+}
+
+/// Per-upload options supplied by the client
+#[derive(Debug, Clone, Default)]
+pub struct UploadOptions {
+  /// Requested retention in seconds, bounded by the token's `max_ttl_seconds`
+  pub ttl_seconds: Option<u64>,
+  /// CI run the upload belongs to, so the run's artifacts can be purged together
+  pub run_id: Option<String>,
+  /// Replace an existing object, which would otherwise be rejected as `AlreadyExists`
+  pub overwrite: bool,
+}
+
+/// Directory below a token's prefix holding one empty marker object per artifact of a run
+const RUNS_DIR: &str = ".runs";
+
+/// Keys listed and deleted per step of a namespace purge
+const PURGE_PAGE_SIZE: usize = 1000;
+
+/// How long a readiness probe waits for a bucket before counting it as unreachable
+const READINESS_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether a bucket tokens are bound to can currently serve requests
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketReadiness {
+  pub bucket: String,
+  /// Whether the bucket answered the connectivity probe
+  pub reachable: bool,
+  /// Whether requests are routed to the fallback bucket (only for buckets with one)
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_open: Option<bool>,
+  pub ready: bool,
+}
+
+async fn probe_bucket(storage: &dyn StorageProvider) -> bool {
+  matches!(
+    tokio::time::timeout(READINESS_PROBE_TIMEOUT, storage.test_connection()).await,
+    Ok(Ok(()))
+  )
+}
+
+/// Fallback bucket used while a primary bucket is unhealthy
+#[derive(Clone)]
+struct Failover {
+  bucket: String,
+  storage: Arc<dyn StorageProvider>,
+  health: Arc<BucketHealth>,
+}
+
+#[derive(Clone)]
+struct LocalTier {
+  disk: DiskTier,
+  retain_after_upload: bool,
+}
+
+/// Where replication reads a freshly written object from
+#[derive(Clone)]
+struct ReplicaSource {
+  tier: Option<LocalTier>,
+  storage: Arc<dyn StorageProvider>,
+}
+
+impl ReplicaSource {
+  async fn open(&self, key: &str) -> Result<DynAsyncRead, StorageError> {
+    if let Some(tier) = &self.tier {
+      if let Some(file) = tier.disk.open(key).await? {
+        return Ok(Box::new(file));
+      }
+    }
+    self.storage.retrieve(key).await
+  }
+}
+
+/// A replica bucket a write is copied to
+#[derive(Clone)]
+struct ReplicaTarget {
+  bucket: String,
+  storage: Arc<dyn StorageProvider>,
+  limiter: Option<Arc<AdaptiveLimiter>>,
+  tenant: Tenant,
+}
+
+/// Copy one object from its source to a replica bucket
+async fn copy_to_replica(
+  source: &ReplicaSource,
+  target: &ReplicaTarget,
+  key: &str,
+  content_length: Option<u64>,
+  metadata: &ObjectMetadata,
+) -> Result<(), StorageError> {
+  let reader = source.open(key).await?;
+  let result = run_limited(
+    target.limiter.as_ref(),
+    &target.tenant,
+    target
+      .storage
+      .store_with_metadata(key, ReaderStream::new(reader), content_length, metadata),
+  )
+  .await;
+
+  match result {
+    Ok(()) | Err(StorageError::AlreadyExists) => {
+      tracing::debug!("Replicated {} to bucket {}", key, target.bucket);
+      Ok(())
+    },
+    Err(err) => Err(err),
+  }
+}
+
+/// Write a dead letter below a token's prefix, replacing an earlier record of the same delivery
+/// in one unconditional write
+async fn store_dead_letter(storage: &Arc<dyn StorageProvider>, prefix: &str, letter: &DeadLetter) {
+  let key = MultiStorageRouter::build_key(prefix, &dead_letter::object_name(&letter.id));
+  let Ok(body) = serde_json::to_vec(letter) else {
+    return;
+  };
+  let length = body.len() as u64;
+  let metadata = ObjectMetadata {
+    uploaded_at: letter.failed_at,
+    content_length: Some(length),
+    overwrite: true,
+    ..Default::default()
+  };
+
+  let result = storage
+    .store_with_metadata(
+      &key,
+      boxed_reader_stream(Cursor::new(body)),
+      Some(length),
+      &metadata,
+    )
+    .await;
+  if let Err(err) = result {
+    tracing::error!("Failed to record dead letter for {}: {}", letter.key, err);
+  }
+}
+
+/// Run a storage operation under an adaptive concurrency limiter, if any, queued for `tenant`
+async fn run_limited<T, F>(
+  limiter: Option<&Arc<AdaptiveLimiter>>,
+  tenant: &Tenant,
+  operation: F,
+) -> Result<T, StorageError>
+where
+  F: Future<Output = Result<T, StorageError>>,
+{
+  match limiter {
+    Some(limiter) => {
+      let permit = limiter.acquire_for(tenant).await;
+      let result = operation.await;
+      // NotFound and AlreadyExists are healthy backend answers, only failures back off
+      let outcome = match result {
+        Err(StorageError::OperationFailed) => Outcome::Failure,
+        _ => Outcome::Success,
+      };
+      permit.complete(outcome);
+      result
+    },
+    None => operation.await,
+  }
 }
 
 impl MultiStorageRouter {
   /// Create a new multi-storage router from resolved configuration
   pub async fn from_config(config: &ResolvedConfig) -> Result<Self, StorageError> {
+    Self::from_config_with_providers(config, HashMap::new()).await
+  }
+
+  /// Create a router, using the given providers for the buckets they are registered under
+  ///
+  /// Buckets without a registered provider are instantiated from their configured `type`.
+  /// This lets embedders plug in custom `StorageProvider` implementations at runtime.
+  pub async fn from_config_with_providers(
+    config: &ResolvedConfig,
+    mut providers: HashMap<String, Arc<dyn StorageProvider>>,
+  ) -> Result<Self, StorageError> {
     let mut storages = HashMap::new();
+    let mut limiters = HashMap::new();
+    let mut tiers = HashMap::new();
+    let mut maintenance = HashMap::new();
+    let mut statuses = HashMap::new();
+    let mut summaries = HashMap::new();
+    let mut clients = ClientPool::default();
 
     // Initialize storage for each bucket
     for bucket_config in &config.buckets {
-      let storage = NxCacheStorage::from_resolved_bucket(bucket_config).await?;
-      storages.insert(bucket_config.name.clone(), Arc::new(storage));
+      let mut storage = match providers.remove(&bucket_config.name) {
+        Some(provider) => provider,
+        None => {
+          // Connections of an isolated bucket must be driven by its own runtime
+          let mut own_clients = ClientPool::default();
+          let clients = match bucket_config.isolation {
+            Some(_) => &mut own_clients,
+            None => &mut clients,
+          };
+          backend::from_resolved_bucket(bucket_config, clients).await?
+        },
+      };
+      if let Some(key) = &bucket_config.encryption_key {
+        storage = Arc::new(EncryptedStorage::new(storage, key)?);
+      }
+      // Compress before encrypting; ciphertext does not compress
+      if let Some(compression) = &bucket_config.compression {
+        storage = Arc::new(CompressedStorage::new(
+          storage,
+          compression.codec,
+          compression.level,
+        ));
+      }
+      if let Some(isolation) = &bucket_config.isolation {
+        storage = Arc::new(IsolatedStorage::new(
+          storage,
+          &bucket_config.name,
+          isolation,
+        )?);
+      }
+      if !bucket_config.maintenance_windows.is_empty() {
+        let schedule = Arc::new(MaintenanceSchedule::new(&bucket_config.maintenance_windows));
+        storage = Arc::new(MaintenanceStorage::new(storage, schedule.clone()));
+        maintenance.insert(bucket_config.name.clone(), schedule);
+      }
+      let status = Arc::new(BucketStatus::default());
+      storage = Arc::new(StatusStorage::new(storage, status.clone()));
+      statuses.insert(bucket_config.name.clone(), status);
+      summaries.insert(
+        bucket_config.name.clone(),
+        BucketSummary::from(bucket_config),
+      );
+      storages.insert(bucket_config.name.clone(), storage);
+
+      if let Some(concurrency) = &bucket_config.concurrency {
+        limiters.insert(
+          bucket_config.name.clone(),
+          Arc::new(AdaptiveLimiter::new(concurrency)),
+        );
+      }
+
+      if let Some(local_tier) = &bucket_config.local_tier {
+        tiers.insert(
+          bucket_config.name.clone(),
+          LocalTier {
+            disk: DiskTier::new(&local_tier.path).await?,
+            retain_after_upload: local_tier.retain_after_upload,
+          },
+        );
+      }
+    }
+
+    let mut failovers = HashMap::new();
+    for bucket_config in &config.buckets {
+      if let Some(fallback) = &bucket_config.fallback_bucket {
+        let storage = storages
+          .get(fallback)
+          .cloned()
+          .ok_or(StorageError::OperationFailed)?;
+        failovers.insert(
+          bucket_config.name.clone(),
+          Failover {
+            bucket: fallback.clone(),
+            storage,
+            health: Arc::new(BucketHealth::default()),
+          },
+        );
+      }
     }
 
     let token_map = config.build_token_registry();
@@ -36,6 +326,14 @@ impl MultiStorageRouter {
     Ok(Self {
       storages: Arc::new(storages),
       token_map: Arc::new(token_map),
+      limiters: Arc::new(limiters),
+      tiers: Arc::new(tiers),
+      failovers: Arc::new(failovers),
+      maintenance: Arc::new(maintenance),
+      background: Arc::new(BackgroundTasks::new()),
+      repairs: Arc::new(Repairs::default()),
+      statuses: Arc::new(statuses),
+      summaries: Arc::new(summaries),
     })
   }
 
@@ -53,8 +351,49 @@ impl MultiStorageRouter {
     Ok(())
   }
 
-  /// Get storage and prefix for a given access token
-  fn resolve_storage(&self, token: &str) -> Result<(Arc<NxCacheStorage>, String), StorageError> {
+  /// Test connectivity to every bucket without failing, refreshing the cached results
+  /// reported by [`MultiStorageRouter::bucket_statuses`]
+  pub async fn probe_buckets(&self) {
+    let probes = self
+      .storages
+      .iter()
+      .map(|(bucket_name, storage)| async move {
+        if let Err(err) = storage.test_connection().await {
+          tracing::warn!(
+            "Connectivity test of bucket {} failed: {}",
+            bucket_name,
+            err
+          );
+        }
+      });
+    futures_util::future::join_all(probes).await;
+  }
+
+  /// Cached connectivity, last operation times and settings of every bucket, by name
+  pub fn bucket_statuses(&self) -> Vec<BucketStatusReport> {
+    let mut reports: Vec<BucketStatusReport> = self
+      .statuses
+      .iter()
+      .filter_map(|(name, status)| {
+        Some(BucketStatusReport {
+          name: name.clone(),
+          probe: status.probe(),
+          last_success_at: status.last_success(),
+          last_failure_at: status.last_failure(),
+          config: self.summaries.get(name)?.clone(),
+        })
+      })
+      .collect();
+    reports.sort_by(|a, b| a.name.cmp(&b.name));
+    reports
+  }
+
+  /// Get storage and object key for a given access token and hash
+  fn resolve_storage(
+    &self,
+    token: &str,
+    hash: &str,
+  ) -> Result<(Arc<dyn StorageProvider>, String), StorageError> {
     let service_config = self
       .token_map
       .get(token)
@@ -65,7 +404,209 @@ impl MultiStorageRouter {
       .get(&service_config.bucket)
       .ok_or(StorageError::OperationFailed)?;
 
-    Ok((storage.clone(), service_config.prefix.clone()))
+    let object_name = service_config.key_layout.object_name(hash);
+    let key = Self::build_key(&service_config.prefix, &object_name);
+
+    Ok((storage.clone(), key))
+  }
+
+  /// Get the bucket name a token is bound to
+  fn bucket_for(&self, token: &str) -> Option<&str> {
+    self
+      .token_map
+      .get(token)
+      .map(|service| service.bucket.as_str())
+  }
+
+  fn limiter_for(&self, token: &str) -> Option<&Arc<AdaptiveLimiter>> {
+    self
+      .bucket_for(token)
+      .and_then(|bucket| self.limiters.get(bucket))
+  }
+
+  /// Fair queuing identity of a token's operations on a saturated bucket
+  fn tenant_for(&self, token: &str) -> Tenant {
+    self
+      .token_map
+      .get(token)
+      .map(|service| Tenant::new(&service.name, service.weight))
+      .unwrap_or_default()
+  }
+
+  fn tier_for(&self, token: &str) -> Option<&LocalTier> {
+    self
+      .bucket_for(token)
+      .and_then(|bucket| self.tiers.get(bucket))
+  }
+
+  fn failover_for(&self, token: &str) -> Option<&Failover> {
+    self
+      .bucket_for(token)
+      .and_then(|bucket| self.failovers.get(bucket))
+  }
+
+  /// Whether the token's bucket is inside a scheduled maintenance window
+  fn in_maintenance(&self, token: &str) -> bool {
+    self
+      .bucket_for(token)
+      .and_then(|bucket| self.maintenance.get(bucket))
+      .is_some_and(|schedule| schedule.is_active())
+  }
+
+  /// Whether a bucket is currently inside one of its maintenance windows
+  pub fn is_bucket_in_maintenance(&self, bucket: &str) -> Option<bool> {
+    self
+      .maintenance
+      .get(bucket)
+      .map(|schedule| schedule.is_active())
+  }
+
+  /// Whether a bucket with a fallback is currently considered healthy
+  pub fn is_bucket_healthy(&self, bucket: &str) -> Option<bool> {
+    self
+      .failovers
+      .get(bucket)
+      .map(|failover| failover.health.is_healthy())
+  }
+
+  /// How long clients should wait before retrying after a backend outage
+  ///
+  /// The longest time any open circuit stays open, or the default recovery interval when no
+  /// circuit is open, since an unreachable bucket without a fallback has none to go by.
+  pub fn retry_after(&self) -> Duration {
+    self
+      .failovers
+      .values()
+      .filter_map(|failover| failover.health.reopens_in())
+      .max()
+      .unwrap_or(DEFAULT_RECOVERY_INTERVAL)
+  }
+
+  /// Probe every bucket a token is bound to, for the readiness endpoint
+  ///
+  /// A bucket is ready when it answers and its circuit is closed, or when its circuit is
+  /// open (or it does not answer) but the fallback bucket it fails over to answers.
+  pub async fn readiness(&self) -> Vec<BucketReadiness> {
+    let mut buckets: Vec<&str> = self
+      .token_map
+      .values()
+      .map(|service| service.bucket.as_str())
+      .collect();
+    buckets.sort_unstable();
+    buckets.dedup();
+
+    let probes = buckets.into_iter().filter_map(|bucket| {
+      let storage = self.storages.get(bucket)?.clone();
+      let failover = self.failovers.get(bucket).cloned();
+      Some(async move {
+        let reachable = probe_bucket(storage.as_ref()).await;
+        let circuit_open = failover
+          .as_ref()
+          .map(|failover| !failover.health.is_available());
+        let ready = match &failover {
+          Some(failover) if circuit_open == Some(true) || !reachable => {
+            probe_bucket(failover.storage.as_ref()).await
+          },
+          _ => reachable,
+        };
+        BucketReadiness {
+          bucket: bucket.to_string(),
+          reachable,
+          circuit_open,
+          ready,
+        }
+      })
+    });
+    futures_util::future::join_all(probes).await
+  }
+
+  fn record_primary_result<T>(
+    &self,
+    token: &str,
+    failover: &Failover,
+    result: &Result<T, StorageError>,
+  ) {
+    let primary = self.bucket_for(token).unwrap_or_default();
+    match result {
+      Err(StorageError::OperationFailed) => {
+        if failover.health.record_failure() {
+          tracing::warn!(
+            "Bucket {} marked unhealthy, failing over to {}",
+            primary,
+            failover.bucket
+          );
+        }
+      },
+      _ => {
+        if failover.health.record_success() {
+          tracing::info!(
+            "Bucket {} recovered, routing back from {}",
+            primary,
+            failover.bucket
+          );
+        }
+      },
+    }
+  }
+
+  /// Run a read against the token's bucket, failing over to its fallback bucket if configured
+  ///
+  /// Misses on the primary are also looked up in the fallback, since objects written
+  /// while the primary was unavailable only exist there. During a maintenance window
+  /// the fallback is asked first and the primary only for what the fallback lacks.
+  async fn read_with_failover<T, F, Fut>(
+    &self,
+    token: &str,
+    primary: Arc<dyn StorageProvider>,
+    read: F,
+  ) -> Result<T, StorageError>
+  where
+    F: Fn(Arc<dyn StorageProvider>) -> Fut,
+    Fut: Future<Output = Result<T, StorageError>>,
+  {
+    let tenant = self.tenant_for(token);
+    let Some(failover) = self.failover_for(token) else {
+      return run_limited(self.limiter_for(token), &tenant, read(primary)).await;
+    };
+    let fallback_limiter = self.limiters.get(&failover.bucket);
+
+    if !failover.health.is_available() {
+      return run_limited(fallback_limiter, &tenant, read(failover.storage.clone())).await;
+    }
+
+    if self.in_maintenance(token) {
+      match run_limited(fallback_limiter, &tenant, read(failover.storage.clone())).await {
+        Err(StorageError::NotFound | StorageError::OperationFailed) => {},
+        result => return result,
+      }
+      return run_limited(self.limiter_for(token), &tenant, read(primary)).await;
+    }
+
+    let result = run_limited(self.limiter_for(token), &tenant, read(primary)).await;
+    self.record_primary_result(token, failover, &result);
+
+    match result {
+      Err(StorageError::OperationFailed) => {
+        run_limited(fallback_limiter, &tenant, read(failover.storage.clone())).await
+      },
+      Err(StorageError::NotFound) => {
+        match run_limited(fallback_limiter, &tenant, read(failover.storage.clone())).await {
+          Err(StorageError::OperationFailed) => Err(StorageError::NotFound),
+          result => result,
+        }
+      },
+      result => result,
+    }
+  }
+
+  /// Tracker for background work (write-behind uploads, async replication) that must finish before shutdown
+  pub fn background_tasks(&self) -> Arc<BackgroundTasks> {
+    self.background.clone()
+  }
+
+  /// Get the current concurrency limit for a bucket, if adaptive limiting is enabled
+  pub fn concurrency_limit(&self, bucket: &str) -> Option<usize> {
+    self.limiters.get(bucket).map(|limiter| limiter.limit())
   }
 
   /// Build the full key with prefix
@@ -81,9 +622,56 @@ impl MultiStorageRouter {
 
   /// Check if object exists for the given token and hash
   pub async fn exists_with_token(&self, token: &str, hash: &str) -> Result<bool, StorageError> {
-    let (storage, prefix) = self.resolve_storage(token)?;
-    let key = Self::build_key(&prefix, hash);
-    storage.exists(&key).await
+    let (storage, key) = self.resolve_storage(token, hash)?;
+
+    if let Some(tier) = self.tier_for(token) {
+      if tier.disk.exists(&key).await? {
+        return Ok(true);
+      }
+    }
+
+    let exists = |storage: Arc<dyn StorageProvider>| {
+      let key = key.clone();
+      async move {
+        // Report misses as NotFound so they are also checked against a fallback bucket
+        match storage.exists(&key).await {
+          Ok(false) => Err(StorageError::NotFound),
+          result => result,
+        }
+      }
+    };
+
+    match self
+      .read_with_failover(token, storage.clone(), exists)
+      .await
+    {
+      Err(StorageError::NotFound) => {
+        let exists = |storage: Arc<dyn StorageProvider>, key: String| async move {
+          match storage.exists(&key).await {
+            Ok(false) => Err(StorageError::NotFound),
+            result => result,
+          }
+        };
+        let legacy = self.read_legacy(token, &storage, hash, exists).await?;
+        Ok(legacy.is_some())
+      },
+      Err(StorageError::OperationFailed) => {
+        for (bucket, replica) in self.replicas_for(token) {
+          match replica.exists(&key).await {
+            Ok(exists) => {
+              tracing::warn!(
+                "Primary bucket failed, answered exists from replica {}",
+                bucket
+              );
+              return Ok(exists);
+            },
+            Err(_) => continue,
+          }
+        }
+        Err(StorageError::OperationFailed)
+      },
+      result => result,
+    }
   }
 
   /// Store object for the given token and hash
@@ -91,12 +679,620 @@ impl MultiStorageRouter {
     &self,
     token: &str,
     hash: &str,
-    data: ReaderStream<impl AsyncRead + Send + Unpin + 'static>,
+    data: ReaderStream<DynAsyncRead>,
     content_length: Option<u64>,
   ) -> Result<(), StorageError> {
-    let (storage, prefix) = self.resolve_storage(token)?;
-    let key = Self::build_key(&prefix, hash);
-    storage.store(&key, data, content_length).await
+    self
+      .store_with_options(token, hash, data, content_length, UploadOptions::default())
+      .await
+  }
+
+  /// Store data for the given token and hash with client-supplied upload options
+  pub async fn store_with_options(
+    &self,
+    token: &str,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    options: UploadOptions,
+  ) -> Result<(), StorageError> {
+    let (storage, key) = self.resolve_storage(token, hash)?;
+    let metadata = self.object_metadata(token, content_length, &options);
+
+    // Writes go to the fallback only while the primary is out of rotation; a failed
+    // upload cannot be replayed since the request body has already been consumed.
+    let failover = self.failover_for(token);
+    let (storage, limiter, on_primary) = match failover {
+      Some(failover) if !failover.health.is_available() => (
+        failover.storage.clone(),
+        self.limiters.get(&failover.bucket),
+        false,
+      ),
+      _ => (storage, self.limiter_for(token), true),
+    };
+
+    if let Some(tier) = self.tier_for(token) {
+      let size = if options.overwrite {
+        tier.disk.replace(&key, data).await?
+      } else {
+        tier.disk.write(&key, data).await?
+      };
+      let metadata = ObjectMetadata {
+        content_length: Some(size),
+        ..metadata
+      };
+      self.upload_from_tier(
+        tier.clone(),
+        storage.clone(),
+        limiter.cloned(),
+        self.tenant_for(token),
+        key.clone(),
+        metadata.clone(),
+      );
+      self.record_run(token, hash, &options).await;
+      return self
+        .replicate(token, storage, &key, Some(size), metadata)
+        .await;
+    }
+
+    let result = run_limited(
+      limiter,
+      &self.tenant_for(token),
+      storage.store_with_metadata(&key, data, content_length, &metadata),
+    )
+    .await;
+    if let (Some(failover), true) = (failover, on_primary) {
+      self.record_primary_result(token, failover, &result);
+    }
+    result?;
+
+    self.record_run(token, hash, &options).await;
+    self
+      .replicate(token, storage, &key, content_length, metadata)
+      .await
+  }
+
+  /// Marker prefix listing the artifacts a token uploaded for a run
+  fn run_prefix(service: &ResolvedServiceAccessToken, run_id: &str) -> String {
+    Self::build_key(&service.prefix, &format!("{}/{}/", RUNS_DIR, run_id))
+  }
+
+  /// Remember that `hash` belongs to the upload's run
+  ///
+  /// A failed marker write is logged but does not fail the upload; the artifact is then
+  /// only missed by a later purge of the run.
+  async fn record_run(&self, token: &str, hash: &str, options: &UploadOptions) {
+    let Some(run_id) = &options.run_id else {
+      return;
+    };
+    let Some(service) = self.token_map.get(token) else {
+      return;
+    };
+    let Some(storage) = self.storages.get(&service.bucket) else {
+      return;
+    };
+
+    let marker = format!("{}{}", Self::run_prefix(service, run_id), hash);
+    let empty = boxed_reader_stream(std::io::Cursor::new(Vec::new()));
+    match run_limited(
+      self.limiter_for(token),
+      &self.tenant_for(token),
+      storage.store(&marker, empty, Some(0)),
+    )
+    .await
+    {
+      Ok(()) | Err(StorageError::AlreadyExists) => {},
+      Err(err) => tracing::warn!("Failed to record run {} for {}: {}", run_id, hash, err),
+    }
+  }
+
+  /// Delete one object of the token from its bucket, fallback, local tier and replicas
+  ///
+  /// Deleting a missing object succeeds; replica failures are logged like in
+  /// [`Self::purge_run`].
+  pub async fn delete_with_token(&self, token: &str, hash: &str) -> Result<(), StorageError> {
+    let (storage, key) = self.resolve_storage(token, hash)?;
+
+    match run_limited(
+      self.limiter_for(token),
+      &self.tenant_for(token),
+      storage.delete(&key),
+    )
+    .await
+    {
+      Ok(()) | Err(StorageError::NotFound) => {},
+      Err(err) => return Err(err),
+    }
+    if let Some(failover) = self.failover_for(token) {
+      failover.storage.delete(&key).await?;
+    }
+    if let Some(tier) = self.tier_for(token) {
+      tier.disk.remove(&key).await?;
+    }
+    for (bucket, replica) in self.replicas_for(token) {
+      if let Err(err) = replica.delete(&key).await {
+        tracing::warn!("Failed to delete {} from replica {}: {}", key, bucket, err);
+      }
+    }
+    Ok(())
+  }
+
+  /// Delete every artifact the token uploaded for a run, returning how many were deleted
+  ///
+  /// Objects are removed from the bucket, its fallback, the local tier and the token's
+  /// replicas; replica failures are logged and do not stop the purge.
+  pub async fn purge_run(&self, token: &str, run_id: &str) -> Result<usize, StorageError> {
+    let service = self
+      .token_map
+      .get(token)
+      .ok_or(StorageError::OperationFailed)?;
+    let storage = self
+      .storages
+      .get(&service.bucket)
+      .ok_or(StorageError::OperationFailed)?;
+    let limiter = self.limiter_for(token);
+    let tenant = self.tenant_for(token);
+
+    let prefix = Self::run_prefix(service, run_id);
+    let markers = run_limited(limiter, &tenant, storage.list(&prefix)).await?;
+
+    let mut deleted = 0;
+    for marker in markers {
+      let Some(hash) = marker.strip_prefix(&prefix) else {
+        continue;
+      };
+      let (_, key) = self.resolve_storage(token, hash)?;
+
+      run_limited(limiter, &tenant, storage.delete(&key)).await?;
+      if let Some(failover) = self.failover_for(token) {
+        failover.storage.delete(&key).await?;
+      }
+      if let Some(tier) = self.tier_for(token) {
+        tier.disk.remove(&key).await?;
+      }
+      for (bucket, replica) in self.replicas_for(token) {
+        if let Err(err) = replica.delete(&key).await {
+          tracing::warn!("Failed to delete {} from replica {}: {}", key, bucket, err);
+        }
+      }
+      run_limited(limiter, &tenant, storage.delete(&marker)).await?;
+      deleted += 1;
+    }
+
+    tracing::info!(
+      "Purged {} artifacts of run {} for token {}",
+      deleted,
+      run_id,
+      service.name
+    );
+    Ok(deleted)
+  }
+
+  /// Delete every object under the token's prefix, returning how many were deleted
+  ///
+  /// Besides artifacts this removes run markers, task metadata and anything else below the
+  /// prefix. Keys listed in the bucket are also removed from its fallback and the local tier,
+  /// and objects held only by the tier are removed afterwards; replica failures are logged
+  /// and do not stop the purge. `progress` is called with the running count after each page.
+  /// Tokens without a prefix share the bucket root and are refused.
+  pub async fn purge_namespace(
+    &self,
+    token: &str,
+    mut progress: impl FnMut(usize),
+  ) -> Result<usize, StorageError> {
+    let service = self
+      .token_map
+      .get(token)
+      .ok_or(StorageError::OperationFailed)?;
+    let base = Self::build_key(&service.prefix, "");
+    if base.is_empty() || base == "/" {
+      tracing::error!("Refusing to purge token {} without a prefix", service.name);
+      return Err(StorageError::OperationFailed);
+    }
+    let storage = self
+      .storages
+      .get(&service.bucket)
+      .ok_or(StorageError::OperationFailed)?;
+    let limiter = self.limiter_for(token);
+    let tenant = self.tenant_for(token);
+
+    let mut deleted = 0;
+    let mut cursor: Option<String> = None;
+    loop {
+      let page = run_limited(
+        limiter,
+        &tenant,
+        storage.list_page(&base, cursor.as_deref(), PURGE_PAGE_SIZE),
+      )
+      .await?;
+      for key in &page.keys {
+        run_limited(limiter, &tenant, storage.delete(key)).await?;
+        if let Some(failover) = self.failover_for(token) {
+          failover.storage.delete(key).await?;
+        }
+        if let Some(tier) = self.tier_for(token) {
+          tier.disk.remove(key).await?;
+        }
+        for (bucket, replica) in self.replicas_for(token) {
+          if let Err(err) = replica.delete(key).await {
+            tracing::warn!("Failed to delete {} from replica {}: {}", key, bucket, err);
+          }
+        }
+        deleted += 1;
+      }
+      progress(deleted);
+      match page.next {
+        Some(next) => cursor = Some(next),
+        None => break,
+      }
+    }
+
+    if let Some(tier) = self.tier_for(token) {
+      let keys = tier.disk.list_recursive(&base).await?;
+      if !keys.is_empty() {
+        for key in &keys {
+          tier.disk.remove(key).await?;
+        }
+        deleted += keys.len();
+        progress(deleted);
+      }
+    }
+
+    tracing::info!(
+      "Purged {} objects under the prefix of token {}",
+      deleted,
+      service.name
+    );
+    Ok(deleted)
+  }
+
+  /// Number and stored size of the objects under the token's prefix
+  ///
+  /// Counts the primary bucket only, including run markers and task metadata. A token without
+  /// a prefix reports the whole bucket.
+  pub async fn namespace_usage(&self, token: &str) -> Result<PrefixUsage, StorageError> {
+    let service = self
+      .token_map
+      .get(token)
+      .ok_or(StorageError::OperationFailed)?;
+    let storage = self
+      .storages
+      .get(&service.bucket)
+      .ok_or(StorageError::OperationFailed)?;
+
+    let base = Self::build_key(&service.prefix, "");
+    run_limited(
+      self.limiter_for(token),
+      &self.tenant_for(token),
+      storage.usage(&base),
+    )
+    .await
+  }
+
+  /// Metadata attached to objects uploaded with the given token
+  fn object_metadata(
+    &self,
+    token: &str,
+    content_length: Option<u64>,
+    options: &UploadOptions,
+  ) -> ObjectMetadata {
+    let service = self.token_map.get(token);
+    ObjectMetadata {
+      token: service.map(|s| s.name.clone()).unwrap_or_default(),
+      namespace: service.map(|s| s.prefix.clone()).unwrap_or_default(),
+      uploaded_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0),
+      content_length,
+      ttl_seconds: service.and_then(|s| s.effective_ttl(options.ttl_seconds)),
+      run_id: options.run_id.clone(),
+      content_encoding: None,
+      cost_tags: service
+        .map(|s| s.cost_tags.clone().into_iter().collect())
+        .unwrap_or_default(),
+      overwrite: options.overwrite,
+    }
+  }
+
+  /// Copy a freshly written object to the token's replica buckets
+  async fn replicate(
+    &self,
+    token: &str,
+    primary: Arc<dyn StorageProvider>,
+    key: &str,
+    content_length: Option<u64>,
+    metadata: ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    let service = match self.token_map.get(token) {
+      Some(service) if !service.replica_buckets.is_empty() => service,
+      _ => return Ok(()),
+    };
+
+    let source = ReplicaSource {
+      tier: self.tier_for(token).cloned(),
+      storage: primary,
+    };
+    let targets: Vec<ReplicaTarget> = service
+      .replica_buckets
+      .iter()
+      .filter_map(|bucket| {
+        self.storages.get(bucket).map(|storage| ReplicaTarget {
+          bucket: bucket.clone(),
+          storage: storage.clone(),
+          limiter: self.limiters.get(bucket).cloned(),
+          tenant: self.tenant_for(token),
+        })
+      })
+      .collect();
+
+    match service.replication {
+      ReplicationMode::Sync => {
+        for target in &targets {
+          copy_to_replica(&source, target, key, content_length, &metadata)
+            .await
+            .inspect_err(|err| {
+              tracing::error!(
+                "Synchronous replication of {} to bucket {} failed: {}",
+                key,
+                target.bucket,
+                err
+              );
+            })?;
+        }
+      },
+      ReplicationMode::Async => {
+        let dead_letters = self.storages.get(&service.bucket).cloned();
+        for target in targets {
+          let source = source.clone();
+          let key = key.to_string();
+          let metadata = metadata.clone();
+          let dead_letters = dead_letters.clone();
+          let prefix = service.prefix.clone();
+          self.background.spawn(async move {
+            for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+              match copy_to_replica(&source, &target, &key, content_length, &metadata).await {
+                Ok(()) => return,
+                Err(err) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                  tracing::warn!(
+                    "Asynchronous replication of {} to bucket {} failed (attempt {}/{}): {}",
+                    key,
+                    target.bucket,
+                    attempt,
+                    MAX_DELIVERY_ATTEMPTS,
+                    err
+                  );
+                  tokio::time::sleep(std::time::Duration::from_millis(500 * attempt as u64)).await;
+                },
+                Err(err) => {
+                  tracing::error!(
+                    "Asynchronous replication of {} to bucket {} failed, dead-lettered: {}",
+                    key,
+                    target.bucket,
+                    err
+                  );
+                  let letter = DeadLetter::replication(
+                    &key,
+                    &target.bucket,
+                    content_length,
+                    attempt,
+                    err.to_string(),
+                  );
+                  if let Some(storage) = &dead_letters {
+                    store_dead_letter(storage, &prefix, &letter).await;
+                  }
+                },
+              }
+            }
+          });
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Bucket storage holding a token's dead letters, with the token
+  fn dead_letter_storage(
+    &self,
+    token: &str,
+  ) -> Result<(&ResolvedServiceAccessToken, &Arc<dyn StorageProvider>), StorageError> {
+    let service = self
+      .token_map
+      .get(token)
+      .ok_or(StorageError::OperationFailed)?;
+    let storage = self
+      .storages
+      .get(&service.bucket)
+      .ok_or(StorageError::OperationFailed)?;
+    Ok((service, storage))
+  }
+
+  async fn read_dead_letter(
+    storage: &Arc<dyn StorageProvider>,
+    key: &str,
+  ) -> Result<DeadLetter, StorageError> {
+    let mut reader = storage.retrieve(key).await?;
+    let mut data = Vec::new();
+    reader
+      .read_to_end(&mut data)
+      .await
+      .map_err(|_| StorageError::OperationFailed)?;
+    serde_json::from_slice(&data).map_err(|e| {
+      tracing::warn!("Unreadable dead letter {}: {}", key, e);
+      StorageError::OperationFailed
+    })
+  }
+
+  /// Deliveries of the token that failed permanently, most recent failure first
+  pub async fn dead_letters(&self, token: &str) -> Result<Vec<DeadLetter>, StorageError> {
+    let (service, storage) = self.dead_letter_storage(token)?;
+    let prefix = Self::build_key(
+      &service.prefix,
+      &format!("{}/", dead_letter::DEAD_LETTERS_DIR),
+    );
+    let keys = run_limited(
+      self.limiter_for(token),
+      &self.tenant_for(token),
+      storage.list(&prefix),
+    )
+    .await?;
+
+    let mut letters = Vec::new();
+    for key in keys.iter().filter(|key| key.ends_with(".json")) {
+      match Self::read_dead_letter(storage, key).await {
+        Ok(letter) => letters.push(letter),
+        Err(StorageError::NotFound) => {},
+        Err(err) => tracing::warn!("Skipping dead letter {}: {}", key, err),
+      }
+    }
+    letters.sort_by_key(|letter| std::cmp::Reverse(letter.failed_at));
+    Ok(letters)
+  }
+
+  /// Run a dead-lettered delivery again, removing the record once it succeeds
+  pub async fn retry_dead_letter(&self, token: &str, id: &str) -> Result<(), StorageError> {
+    if !dead_letter::is_valid_id(id) {
+      return Err(StorageError::NotFound);
+    }
+    let (service, storage) = self.dead_letter_storage(token)?;
+    let key = Self::build_key(&service.prefix, &dead_letter::object_name(id));
+    let letter = Self::read_dead_letter(storage, &key).await?;
+
+    match letter.kind {
+      DeadLetterKind::Replication => {
+        let target_storage = self
+          .storages
+          .get(&letter.target_bucket)
+          .ok_or(StorageError::OperationFailed)?;
+        let source = ReplicaSource {
+          tier: self.tier_for(token).cloned(),
+          storage: storage.clone(),
+        };
+        let target = ReplicaTarget {
+          bucket: letter.target_bucket.clone(),
+          storage: target_storage.clone(),
+          limiter: self.limiters.get(&letter.target_bucket).cloned(),
+          tenant: self.tenant_for(token),
+        };
+        let metadata =
+          self.object_metadata(token, letter.content_length, &UploadOptions::default());
+        copy_to_replica(
+          &source,
+          &target,
+          &letter.key,
+          letter.content_length,
+          &metadata,
+        )
+        .await?;
+      },
+    }
+
+    tracing::info!(
+      "Retried dead letter {} ({} to bucket {})",
+      id,
+      letter.key,
+      letter.target_bucket
+    );
+    storage.delete(&key).await
+  }
+
+  /// Drop a dead letter without retrying it
+  pub async fn discard_dead_letter(&self, token: &str, id: &str) -> Result<(), StorageError> {
+    if !dead_letter::is_valid_id(id) {
+      return Err(StorageError::NotFound);
+    }
+    let (service, storage) = self.dead_letter_storage(token)?;
+    let key = Self::build_key(&service.prefix, &dead_letter::object_name(id));
+    if !storage.exists(&key).await? {
+      return Err(StorageError::NotFound);
+    }
+    storage.delete(&key).await
+  }
+
+  /// Replica storages for a token, in configured order
+  fn replicas_for(&self, token: &str) -> Vec<(String, Arc<dyn StorageProvider>)> {
+    self
+      .token_map
+      .get(token)
+      .map(|service| {
+        service
+          .replica_buckets
+          .iter()
+          .filter_map(|bucket| {
+            self
+              .storages
+              .get(bucket)
+              .map(|storage| (bucket.clone(), storage.clone()))
+          })
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+
+  /// Upload a file written to the local tier to its bucket in the background
+  fn upload_from_tier(
+    &self,
+    tier: LocalTier,
+    storage: Arc<dyn StorageProvider>,
+    limiter: Option<Arc<AdaptiveLimiter>>,
+    tenant: Tenant,
+    key: String,
+    metadata: ObjectMetadata,
+  ) {
+    const MAX_ATTEMPTS: usize = 3;
+
+    self.background.spawn(async move {
+      for attempt in 1..=MAX_ATTEMPTS {
+        let file = match tier.disk.open(&key).await {
+          Ok(Some(file)) => file,
+          Ok(None) => {
+            tracing::error!("Local tier file vanished before upload: {}", key);
+            return;
+          },
+          Err(_) => return,
+        };
+
+        let result = run_limited(
+          limiter.as_ref(),
+          &tenant,
+          storage.store_with_metadata(
+            &key,
+            boxed_reader_stream(file),
+            metadata.content_length,
+            &metadata,
+          ),
+        )
+        .await;
+
+        match result {
+          Ok(()) | Err(StorageError::AlreadyExists) => {
+            tracing::debug!("Uploaded local tier object to bucket: {}", key);
+            if !tier.retain_after_upload {
+              let _ = tier.disk.remove(&key).await;
+            }
+            return;
+          },
+          Err(err) if attempt < MAX_ATTEMPTS => {
+            tracing::warn!(
+              "Upload from local tier failed (attempt {}/{}): {}: {}",
+              attempt,
+              MAX_ATTEMPTS,
+              key,
+              err
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(500 * attempt as u64)).await;
+          },
+          Err(err) => {
+            tracing::error!(
+              "Upload from local tier failed, object only on local disk: {}: {}",
+              key,
+              err
+            );
+          },
+        }
+      }
+    });
   }
 
   /// Retrieve object for the given token and hash
@@ -104,10 +1300,480 @@ impl MultiStorageRouter {
     &self,
     token: &str,
     hash: &str,
-  ) -> Result<Box<dyn AsyncRead + Send + Unpin>, StorageError> {
-    let (storage, prefix) = self.resolve_storage(token)?;
-    let key = Self::build_key(&prefix, hash);
-    storage.retrieve(&key).await
+  ) -> Result<DynAsyncRead, StorageError> {
+    let (reader, _) = self.retrieve_encoded_with_token(token, hash, &[]).await?;
+    Ok(reader)
+  }
+
+  /// Retrieve an object for the given token, still compressed if stored with an `accepted`
+  /// codec; returns the codec the reader yields, `None` for the decoded object
+  pub async fn retrieve_encoded_with_token(
+    &self,
+    token: &str,
+    hash: &str,
+    accepted: &[Codec],
+  ) -> Result<(DynAsyncRead, Option<Codec>), StorageError> {
+    let (storage, key) = self.resolve_storage(token, hash)?;
+
+    if let Some(tier) = self.tier_for(token) {
+      if let Some(file) = tier.disk.open(&key).await? {
+        return Ok((Box::new(file), None));
+      }
+    }
+
+    if self.in_maintenance(token) {
+      for (bucket, replica) in self.replicas_for(token) {
+        if let Ok(reader) = replica.retrieve_encoded(&key, accepted).await {
+          tracing::debug!(
+            "Primary bucket in maintenance, serving {} from replica {}",
+            key,
+            bucket
+          );
+          return Ok(reader);
+        }
+      }
+    }
+
+    let retrieve = |storage: Arc<dyn StorageProvider>| {
+      let key = key.clone();
+      async move { storage.retrieve_encoded(&key, accepted).await }
+    };
+
+    let result = match self
+      .read_with_failover(token, storage.clone(), retrieve)
+      .await
+    {
+      Err(StorageError::OperationFailed) => {
+        for (bucket, replica) in self.replicas_for(token) {
+          if let Ok(reader) = replica.retrieve_encoded(&key, accepted).await {
+            tracing::warn!(
+              "Primary bucket failed, serving {} from replica {}",
+              key,
+              bucket
+            );
+            return Ok(reader);
+          }
+        }
+        Err(StorageError::OperationFailed)
+      },
+      result => result,
+    };
+
+    match result {
+      Ok((reader, codec)) => {
+        self
+          .verify_read(token, storage, &key, reader, codec, accepted)
+          .await
+      },
+      Err(StorageError::NotFound) => {
+        let retrieve = |storage: Arc<dyn StorageProvider>, key: String| async move {
+          storage.retrieve_encoded(&key, accepted).await
+        };
+        match self.read_legacy(token, &storage, hash, retrieve).await? {
+          Some((legacy_key, reader)) => {
+            self.copy_forward(token, &storage, legacy_key, key);
+            Ok(reader)
+          },
+          None => Err(StorageError::NotFound),
+        }
+      },
+      result => result,
+    }
+  }
+
+  /// Check the start of a read for corruption if the token has replicas to repair from
+  ///
+  /// A copy found corrupted before anything reached the client is replaced by a replica's
+  /// copy; corruption found later fails the read. Either way the primary is repaired.
+  async fn verify_read(
+    &self,
+    token: &str,
+    primary: Arc<dyn StorageProvider>,
+    key: &str,
+    mut reader: DynAsyncRead,
+    codec: Option<Codec>,
+    accepted: &[Codec],
+  ) -> Result<(DynAsyncRead, Option<Codec>), StorageError> {
+    let replicas = self.replicas_for(token);
+    if replicas.is_empty() {
+      return Ok((reader, codec));
+    }
+
+    match repair::read_prefix(&mut reader).await {
+      Ok(prefix) => {
+        let router = self.clone();
+        let token = token.to_string();
+        let key = key.to_string();
+        let reader = CorruptionWatch::new(Cursor::new(prefix).chain(reader), move || {
+          tracing::warn!("Corrupted object {} found while streaming it", key);
+          router.repair(&token, primary, &key);
+        });
+        Ok((Box::new(reader), codec))
+      },
+      Err(err) if repair::is_corruption(&err) => {
+        tracing::warn!("Corrupted object {}: {}", key, err);
+        self.repair(token, primary, key);
+        for (bucket, replica) in replicas {
+          if let Ok(reader) = replica.retrieve_encoded(key, accepted).await {
+            tracing::warn!("Serving {} from replica {}", key, bucket);
+            return Ok(reader);
+          }
+        }
+        Err(StorageError::OperationFailed)
+      },
+      Err(err) => {
+        tracing::error!("Failed to read {}: {}", key, err);
+        Err(StorageError::OperationFailed)
+      },
+    }
+  }
+
+  /// Replace a corrupted copy in the token's bucket with a replica's copy in the background
+  fn repair(&self, token: &str, primary: Arc<dyn StorageProvider>, key: &str) {
+    let Some(guard) = self.repairs.begin(key) else {
+      return;
+    };
+    let target = ReplicaTarget {
+      bucket: self.bucket_for(token).unwrap_or_default().to_string(),
+      storage: primary,
+      limiter: self.limiter_for(token).cloned(),
+      tenant: self.tenant_for(token),
+    };
+    let replicas = self.replicas_for(token);
+    let metadata = self.object_metadata(token, None, &UploadOptions::default());
+    let repairs = self.repairs.clone();
+    let key = key.to_string();
+
+    self.background.spawn(async move {
+      let _guard = guard;
+      match target.storage.delete(&key).await {
+        Ok(()) | Err(StorageError::NotFound) => {},
+        Err(err) => {
+          tracing::error!("Failed to delete corrupted object {}: {}", key, err);
+          repairs.record_failed();
+          return;
+        },
+      }
+      for (bucket, replica) in replicas {
+        let source = ReplicaSource {
+          tier: None,
+          storage: replica,
+        };
+        match copy_to_replica(&source, &target, &key, None, &metadata).await {
+          Ok(()) => {
+            tracing::info!(
+              "Repaired {} in bucket {} from replica {}",
+              key,
+              target.bucket,
+              bucket
+            );
+            repairs.record_repaired();
+            return;
+          },
+          Err(err) => {
+            tracing::warn!("Failed to repair {} from replica {}: {}", key, bucket, err)
+          },
+        }
+      }
+      tracing::error!("Failed to repair {}: no replica holds a readable copy", key);
+      repairs.record_failed();
+    });
+  }
+
+  /// Corrupted reads detected and repaired since startup
+  pub fn repair_counts(&self) -> RepairCounts {
+    self.repairs.counts()
+  }
+
+  /// Object keys under the token's legacy prefixes, then its namespace aliases, in configured order
+  fn legacy_keys(&self, token: &str, hash: &str) -> Vec<String> {
+    self
+      .token_map
+      .get(token)
+      .map(|service| {
+        let object_name = service.key_layout.object_name(hash);
+        let aliases = service
+          .namespace_aliases
+          .iter()
+          .map(|alias| Self::build_key(&alias.prefix, &alias.key_layout.object_name(hash)));
+        service
+          .legacy_prefixes
+          .iter()
+          .map(|prefix| Self::build_key(prefix, &object_name))
+          .chain(aliases)
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+
+  /// Run a read against the token's legacy prefixes, returning the first key that hits
+  async fn read_legacy<T, F, Fut>(
+    &self,
+    token: &str,
+    storage: &Arc<dyn StorageProvider>,
+    hash: &str,
+    read: F,
+  ) -> Result<Option<(String, T)>, StorageError>
+  where
+    F: Fn(Arc<dyn StorageProvider>, String) -> Fut,
+    Fut: Future<Output = Result<T, StorageError>>,
+  {
+    for legacy_key in self.legacy_keys(token, hash) {
+      let result = run_limited(
+        self.limiter_for(token),
+        &self.tenant_for(token),
+        read(storage.clone(), legacy_key.clone()),
+      )
+      .await;
+      match result {
+        Ok(value) => {
+          tracing::debug!("Found {} under legacy key {}", hash, legacy_key);
+          return Ok(Some((legacy_key, value)));
+        },
+        Err(StorageError::NotFound) => continue,
+        Err(err) => return Err(err),
+      }
+    }
+    Ok(None)
+  }
+
+  /// Copy an object found under a legacy prefix to its current key in the background
+  fn copy_forward(
+    &self,
+    token: &str,
+    storage: &Arc<dyn StorageProvider>,
+    legacy_key: String,
+    key: String,
+  ) {
+    if !self
+      .token_map
+      .get(token)
+      .is_some_and(|service| service.legacy_copy_forward)
+    {
+      return;
+    }
+
+    let storage = storage.clone();
+    let limiter = self.limiter_for(token).cloned();
+    let tenant = self.tenant_for(token);
+    self.background.spawn(async move {
+      let copy = async {
+        let size = storage.size(&legacy_key).await.ok();
+        let reader = storage.retrieve(&legacy_key).await?;
+        storage.store(&key, ReaderStream::new(reader), size).await
+      };
+      match run_limited(limiter.as_ref(), &tenant, copy).await {
+        Ok(()) | Err(StorageError::AlreadyExists) => {
+          tracing::debug!("Copied legacy object {} forward to {}", legacy_key, key);
+        },
+        Err(err) => {
+          tracing::warn!(
+            "Copying legacy object {} forward to {} failed: {}",
+            legacy_key,
+            key,
+            err
+          );
+        },
+      }
+    });
+  }
+
+  /// Size of the object for the given token and hash
+  pub async fn size_with_token(&self, token: &str, hash: &str) -> Result<u64, StorageError> {
+    let (storage, key) = self.resolve_storage(token, hash)?;
+
+    if let Some(tier) = self.tier_for(token) {
+      if let Some(size) = tier.disk.size(&key).await? {
+        return Ok(size);
+      }
+    }
+
+    let size = |storage: Arc<dyn StorageProvider>| {
+      let key = key.clone();
+      async move { storage.size(&key).await }
+    };
+    match self.read_with_failover(token, storage.clone(), size).await {
+      Err(StorageError::NotFound) => {
+        let size =
+          |storage: Arc<dyn StorageProvider>, key: String| async move { storage.size(&key).await };
+        match self.read_legacy(token, &storage, hash, size).await? {
+          Some((_, size)) => Ok(size),
+          None => Err(StorageError::NotFound),
+        }
+      },
+      result => result,
+    }
+  }
+
+  /// Size and timestamps of an object, looked up where a read would find it
+  pub async fn stat_with_token(&self, token: &str, hash: &str) -> Result<ObjectStat, StorageError> {
+    let (storage, key) = self.resolve_storage(token, hash)?;
+
+    if let Some(tier) = self.tier_for(token) {
+      if let Some(stat) = tier.disk.stat(&key).await? {
+        return Ok(stat);
+      }
+    }
+
+    let stat = |storage: Arc<dyn StorageProvider>| {
+      let key = key.clone();
+      async move { storage.stat(&key).await }
+    };
+    match self.read_with_failover(token, storage.clone(), stat).await {
+      Err(StorageError::NotFound) => {
+        let stat =
+          |storage: Arc<dyn StorageProvider>, key: String| async move { storage.stat(&key).await };
+        match self.read_legacy(token, &storage, hash, stat).await? {
+          Some((_, stat)) => Ok(stat),
+          None => Err(StorageError::NotFound),
+        }
+      },
+      result => result,
+    }
+  }
+
+  /// Object names of the token starting with `name_prefix`, in the form passed to `*_with_token`
+  ///
+  /// Lists the primary bucket and, if configured, the local tier; sorted and deduplicated.
+  pub async fn list_with_token(
+    &self,
+    token: &str,
+    name_prefix: &str,
+  ) -> Result<Vec<String>, StorageError> {
+    let service = self
+      .token_map
+      .get(token)
+      .ok_or(StorageError::OperationFailed)?;
+    let storage = self
+      .storages
+      .get(&service.bucket)
+      .ok_or(StorageError::OperationFailed)?;
+
+    let base = Self::build_key(&service.prefix, "");
+    let key_prefix = format!("{}{}", base, name_prefix);
+    let mut keys = run_limited(
+      self.limiter_for(token),
+      &self.tenant_for(token),
+      storage.list(&key_prefix),
+    )
+    .await?;
+    if let Some(tier) = self.tier_for(token) {
+      keys.extend(tier.disk.list(&key_prefix).await?);
+    }
+
+    let suffix = service.key_layout.object_name("");
+    let mut names: Vec<String> = keys
+      .iter()
+      .filter_map(|key| key.strip_prefix(&base)?.strip_suffix(&suffix))
+      .map(str::to_string)
+      .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+  }
+
+  /// One page of the artifacts stored under the token's prefix, for inspection by admins
+  ///
+  /// Lists the primary bucket only. Names are in the form passed to `*_with_token`; run
+  /// markers, task metadata and other entries below `.`-directories are left out, so a page
+  /// may hold fewer than `limit` names while a cursor for the next page is still returned.
+  pub async fn list_artifacts(
+    &self,
+    token: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    let service = self
+      .token_map
+      .get(token)
+      .ok_or(StorageError::OperationFailed)?;
+    let storage = self
+      .storages
+      .get(&service.bucket)
+      .ok_or(StorageError::OperationFailed)?;
+
+    let base = Self::build_key(&service.prefix, "");
+    let page = run_limited(
+      self.limiter_for(token),
+      &self.tenant_for(token),
+      storage.list_page(&base, cursor, limit),
+    )
+    .await?;
+
+    let suffix = service.key_layout.object_name("");
+    let keys = page
+      .keys
+      .iter()
+      .filter_map(|key| key.strip_prefix(&base)?.strip_suffix(&suffix))
+      .filter(|name| !name.split('/').any(|segment| segment.starts_with('.')))
+      .map(str::to_string)
+      .collect();
+    Ok(ListPage {
+      keys,
+      next: page.next,
+    })
+  }
+
+  /// Find a token's configuration by its name
+  pub fn find_token_by_name(&self, name: &str) -> Option<&ResolvedServiceAccessToken> {
+    self.token_map.values().find(|token| token.name == name)
+  }
+
+  /// Compare the configured namespaces with the prefixes present in every bucket
+  ///
+  /// Buckets whose backend cannot list prefixes are skipped with a warning.
+  pub async fn audit_namespaces(&self, config: &ResolvedConfig) -> Vec<NamespaceFinding> {
+    let mut findings = Vec::new();
+    for (bucket, namespaces) in namespace_audit::known_namespaces(config) {
+      let Some(storage) = self.storages.get(&bucket) else {
+        continue;
+      };
+      match namespace_audit::audit_bucket(&bucket, storage.as_ref(), &namespaces).await {
+        Ok(found) => findings.extend(found),
+        Err(err) => tracing::warn!("Namespace audit skipped bucket {}: {}", bucket, err),
+      }
+    }
+    findings
+  }
+
+  /// Check an object in a bucket directly, bypassing token routing (used by the read-only mirror)
+  pub async fn exists_in_bucket(
+    &self,
+    bucket: &str,
+    prefix: &str,
+    name: &str,
+  ) -> Result<bool, StorageError> {
+    let storage = self
+      .storages
+      .get(bucket)
+      .ok_or(StorageError::OperationFailed)?;
+    let key = Self::build_key(prefix, name);
+    run_limited(
+      self.limiters.get(bucket),
+      &Tenant::default(),
+      storage.exists(&key),
+    )
+    .await
+  }
+
+  /// Retrieve an object from a bucket directly, bypassing token routing
+  pub async fn retrieve_from_bucket(
+    &self,
+    bucket: &str,
+    prefix: &str,
+    name: &str,
+  ) -> Result<DynAsyncRead, StorageError> {
+    let storage = self
+      .storages
+      .get(bucket)
+      .ok_or(StorageError::OperationFailed)?;
+    let key = Self::build_key(prefix, name);
+    run_limited(
+      self.limiters.get(bucket),
+      &Tenant::default(),
+      storage.retrieve(&key),
+    )
+    .await
   }
 
   /// Get the service configuration for a token
@@ -120,6 +1786,11 @@ impl MultiStorageRouter {
     self.token_map.keys()
   }
 
+  /// Configurations of all tokens
+  pub fn token_configs(&self) -> impl Iterator<Item = &ResolvedServiceAccessToken> {
+    self.token_map.values()
+  }
+
   /// Get token names
   pub fn token_names(&self) -> impl Iterator<Item = &String> {
     self.token_map.values().map(|t| &t.name)
@@ -139,13 +1810,13 @@ impl StorageProvider for MultiStorageRouter {
   async fn store(
     &self,
     _hash: &str,
-    _data: ReaderStream<impl AsyncRead + Send + Unpin>,
+    _data: ReaderStream<DynAsyncRead>,
     _content_length: Option<u64>,
   ) -> Result<(), StorageError> {
     Err(StorageError::OperationFailed)
   }
 
-  async fn retrieve(&self, _hash: &str) -> Result<Box<dyn AsyncRead + Send + Unpin>, StorageError> {
+  async fn retrieve(&self, _hash: &str) -> Result<DynAsyncRead, StorageError> {
     Err(StorageError::OperationFailed)
   }
 }
@@ -153,6 +1824,456 @@ impl StorageProvider for MultiStorageRouter {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::domain::config::{
+    BackendType, CacheControlConfig, CompressionConfig, KeyLayout, NamespaceAlias,
+    ResolvedBucketConfig, ShutdownConfig,
+  };
+
+  fn fs_config(root: &std::path::Path, token: ResolvedServiceAccessToken) -> ResolvedConfig {
+    ResolvedConfig {
+      buckets: vec![ResolvedBucketConfig {
+        name: "local".to_string(),
+        backend: BackendType::Fs,
+        bucket_name: "local".to_string(),
+        path: Some(root.display().to_string()),
+        ..Default::default()
+      }],
+      service_access_tokens: vec![token],
+      port: 3000,
+      debug: false,
+      shutdown: ShutdownConfig::default(),
+      cache_control: CacheControlConfig::default(),
+      normalize_paths: false,
+      in_flight_wait_seconds: 0,
+      recent_errors_capacity: 100,
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      jwt: Vec::new(),
+      token_minting: None,
+      token_revocation: None,
+      hash_validation: Default::default(),
+      audit_namespaces: false,
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
+      transport_compression: None,
+      tus: None,
+      grpc: None,
+    }
+  }
+
+  fn token(
+    prefix: &str,
+    legacy_prefixes: Vec<String>,
+    copy_forward: bool,
+  ) -> ResolvedServiceAccessToken {
+    ResolvedServiceAccessToken {
+      name: "team".to_string(),
+      bucket: "local".to_string(),
+      prefix: prefix.to_string(),
+      access_token: "secret".to_string(),
+      legacy_prefixes,
+      legacy_copy_forward: copy_forward,
+      ..Default::default()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_legacy_prefix_fallback_and_copy_forward() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("old")).unwrap();
+    std::fs::write(root.path().join("old/abc123"), b"artifact").unwrap();
+
+    let config = fs_config(root.path(), token("/new", vec!["/old".to_string()], true));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    assert!(router.exists_with_token("secret", "abc123").await.unwrap());
+    assert_eq!(router.size_with_token("secret", "abc123").await.unwrap(), 8);
+    let stat = router.stat_with_token("secret", "abc123").await.unwrap();
+    assert_eq!(stat.size, Some(8));
+    assert!(stat.last_modified.is_some());
+
+    let mut body = Vec::new();
+    let mut reader = router
+      .retrieve_with_token("secret", "abc123")
+      .await
+      .unwrap();
+    reader.read_to_end(&mut body).await.unwrap();
+    assert_eq!(body, b"artifact");
+
+    router.background_tasks().wait_idle().await;
+    assert!(root.path().join("new/abc123").is_file());
+  }
+
+  #[tokio::test]
+  async fn test_namespace_alias_fallback() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("monorepo")).unwrap();
+    std::fs::write(root.path().join("monorepo/abc123"), b"artifact").unwrap();
+
+    let mut service = token("/web", vec![], false);
+    service.namespace_aliases = vec![NamespaceAlias {
+      token: "monorepo".to_string(),
+      prefix: "/monorepo".to_string(),
+      key_layout: KeyLayout::Plain,
+    }];
+    let config = fs_config(root.path(), service);
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    assert!(router.exists_with_token("secret", "abc123").await.unwrap());
+    assert!(!router.exists_with_token("secret", "def456").await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_legacy_prefix_miss() {
+    let root = tempfile::tempdir().unwrap();
+    let config = fs_config(root.path(), token("/new", vec!["/old".to_string()], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    assert!(!router.exists_with_token("secret", "abc123").await.unwrap());
+    assert!(matches!(
+      router.retrieve_with_token("secret", "abc123").await,
+      Err(StorageError::NotFound)
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_list_with_token() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("ci")).unwrap();
+    for name in ["abc~linux-x64", "abc~darwin-arm64", "abcd~linux-x64", "abc"] {
+      std::fs::write(root.path().join("ci").join(name), b"artifact").unwrap();
+    }
+    std::fs::write(root.path().join("ci/abc~linux.tmp-1-0"), b"partial").unwrap();
+
+    let config = fs_config(root.path(), token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    assert_eq!(
+      router.list_with_token("secret", "abc~").await.unwrap(),
+      vec!["abc~darwin-arm64", "abc~linux-x64"]
+    );
+    assert!(router
+      .list_with_token("secret", "missing~")
+      .await
+      .unwrap()
+      .is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_list_artifacts_in_pages() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("ci/.runs/run-1")).unwrap();
+    for name in ["a", "b~linux-x64", "c", ".runs/run-1/a"] {
+      std::fs::write(root.path().join("ci").join(name), b"artifact").unwrap();
+    }
+
+    let config = fs_config(root.path(), token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    // The run marker sorts first and takes a slot of the first page
+    let first = router.list_artifacts("secret", None, 2).await.unwrap();
+    assert_eq!(first.keys, vec!["a"]);
+    let second = router
+      .list_artifacts("secret", first.next.as_deref(), 2)
+      .await
+      .unwrap();
+    assert_eq!(second.keys, vec!["b~linux-x64", "c"]);
+    assert!(second.next.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_readiness_follows_bucket_connectivity() {
+    let root = tempfile::tempdir().unwrap();
+    let data = root.path().join("data");
+    std::fs::create_dir_all(&data).unwrap();
+    let config = fs_config(&data, token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    let report = router.readiness().await;
+    assert_eq!(
+      report,
+      vec![BucketReadiness {
+        bucket: "local".to_string(),
+        reachable: true,
+        circuit_open: None,
+        ready: true,
+      }]
+    );
+
+    std::fs::remove_dir_all(&data).unwrap();
+    let report = router.readiness().await;
+    assert!(!report[0].reachable);
+    assert!(!report[0].ready);
+  }
+
+  #[tokio::test]
+  async fn test_purge_run() {
+    let root = tempfile::tempdir().unwrap();
+    let config = fs_config(root.path(), token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    for (hash, run_id) in [
+      ("abc", Some("run-1")),
+      ("def", Some("run-2")),
+      ("ghi", None),
+    ] {
+      let options = UploadOptions {
+        run_id: run_id.map(str::to_string),
+        ..Default::default()
+      };
+      let data = boxed_reader_stream(std::io::Cursor::new(b"artifact".to_vec()));
+      router
+        .store_with_options("secret", hash, data, Some(8), options)
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(router.purge_run("secret", "run-1").await.unwrap(), 1);
+    assert!(!router.exists_with_token("secret", "abc").await.unwrap());
+    assert!(router.exists_with_token("secret", "def").await.unwrap());
+    assert!(router.exists_with_token("secret", "ghi").await.unwrap());
+    assert_eq!(router.purge_run("secret", "run-1").await.unwrap(), 0);
+  }
+
+  #[tokio::test]
+  async fn test_delete_with_token() {
+    let root = tempfile::tempdir().unwrap();
+    let config = fs_config(root.path(), token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    let data = boxed_reader_stream(std::io::Cursor::new(b"artifact".to_vec()));
+    router
+      .store_with_token("secret", "ab/cdef", data, Some(8))
+      .await
+      .unwrap();
+    router.delete_with_token("secret", "ab/cdef").await.unwrap();
+    assert!(!router.exists_with_token("secret", "ab/cdef").await.unwrap());
+    router.delete_with_token("secret", "ab/cdef").await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_purge_namespace() {
+    let root = tempfile::tempdir().unwrap();
+    let config = fs_config(root.path(), token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    for hash in ["abc", "def"] {
+      let options = UploadOptions {
+        run_id: Some("run-1".to_string()),
+        ..Default::default()
+      };
+      let data = boxed_reader_stream(std::io::Cursor::new(b"artifact".to_vec()));
+      router
+        .store_with_options("secret", hash, data, Some(8), options)
+        .await
+        .unwrap();
+    }
+
+    let mut reported = Vec::new();
+    let deleted = router
+      .purge_namespace("secret", |deleted| reported.push(deleted))
+      .await
+      .unwrap();
+    // Both artifacts and their run markers
+    assert_eq!(deleted, 4);
+    assert_eq!(reported, vec![4]);
+    assert!(!router.exists_with_token("secret", "abc").await.unwrap());
+    assert!(router
+      .list_with_token("secret", "")
+      .await
+      .unwrap()
+      .is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_namespace_usage() {
+    let root = tempfile::tempdir().unwrap();
+    let config = fs_config(root.path(), token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+    assert_eq!(
+      router.namespace_usage("secret").await.unwrap(),
+      PrefixUsage::default()
+    );
+
+    for (hash, data) in [("abc", b"artifact".to_vec()), ("def", b"other".to_vec())] {
+      let len = data.len() as u64;
+      let data = boxed_reader_stream(std::io::Cursor::new(data));
+      router
+        .store_with_token("secret", hash, data, Some(len))
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(
+      router.namespace_usage("secret").await.unwrap(),
+      PrefixUsage {
+        objects: 2,
+        bytes: 13,
+      }
+    );
+  }
+
+  #[tokio::test]
+  async fn test_overwrite_replaces_existing_object() {
+    let root = tempfile::tempdir().unwrap();
+    let config = fs_config(root.path(), token("/ci", vec![], false));
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    let store = |body: &'static [u8], overwrite: bool| {
+      let router = router.clone();
+      async move {
+        let data = boxed_reader_stream(std::io::Cursor::new(body.to_vec()));
+        let options = UploadOptions {
+          overwrite,
+          ..Default::default()
+        };
+        router
+          .store_with_options("secret", "abc", data, Some(body.len() as u64), options)
+          .await
+      }
+    };
+    store(b"corrupted", false).await.unwrap();
+    assert!(matches!(
+      store(b"artifact", false).await,
+      Err(StorageError::AlreadyExists)
+    ));
+    store(b"artifact", true).await.unwrap();
+
+    let read = || {
+      let router = router.clone();
+      async move {
+        let mut body = Vec::new();
+        let mut reader = router.retrieve_with_token("secret", "abc").await.unwrap();
+        reader.read_to_end(&mut body).await.unwrap();
+        body
+      }
+    };
+    assert_eq!(read().await, b"artifact");
+
+    // An overwrite whose body is cut off keeps the existing artifact
+    let aborted = tokio_util::io::StreamReader::new(futures_util::stream::iter(vec![
+      Ok(bytes::Bytes::from_static(b"part")),
+      Err(std::io::Error::other("connection reset")),
+    ]));
+    let options = UploadOptions {
+      overwrite: true,
+      ..Default::default()
+    };
+    assert!(router
+      .store_with_options("secret", "abc", boxed_reader_stream(aborted), None, options)
+      .await
+      .is_err());
+    assert_eq!(read().await, b"artifact");
+  }
+
+  #[tokio::test]
+  async fn test_corrupted_object_is_served_from_replica_and_repaired() {
+    let root = tempfile::tempdir().unwrap();
+    let mut token = token("/ci", vec![], false);
+    token.replica_buckets = vec!["replica".to_string()];
+    token.replication = ReplicationMode::Sync;
+    let mut config = fs_config(&root.path().join("primary"), token);
+    let mut replica = config.buckets[0].clone();
+    replica.name = "replica".to_string();
+    replica.path = Some(root.path().join("replica").display().to_string());
+    config.buckets[0].compression = Some(CompressionConfig {
+      codec: Codec::Zstd,
+      level: 3,
+    });
+    config.buckets.push(replica);
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    let data = boxed_reader_stream(std::io::Cursor::new(b"artifact".to_vec()));
+    router
+      .store_with_token("secret", "abc", data, Some(8))
+      .await
+      .unwrap();
+
+    // Make the header's recorded length disagree with the compressed body
+    let stored = root.path().join("primary/ci/abc");
+    let mut bytes = std::fs::read(&stored).unwrap();
+    bytes[11] += 1;
+    std::fs::write(&stored, bytes).unwrap();
+
+    let read = |router: MultiStorageRouter| async move {
+      let mut body = Vec::new();
+      let mut reader = router.retrieve_with_token("secret", "abc").await.unwrap();
+      reader.read_to_end(&mut body).await.unwrap();
+      body
+    };
+    assert_eq!(read(router.clone()).await, b"artifact");
+
+    router.background_tasks().wait_idle().await;
+    let counts = router.repair_counts();
+    assert_eq!((counts.detected, counts.repaired, counts.failed), (1, 1, 0));
+    assert_eq!(read(router.clone()).await, b"artifact");
+    assert_eq!(router.repair_counts().detected, 1);
+  }
+
+  #[tokio::test]
+  async fn test_failed_async_replication_is_dead_lettered_and_retried() {
+    let root = tempfile::tempdir().unwrap();
+    let mut token = token("/ci", vec![], false);
+    token.replica_buckets = vec!["replica".to_string()];
+    let mut config = fs_config(&root.path().join("primary"), token);
+    let mut replica = config.buckets[0].clone();
+    replica.name = "replica".to_string();
+    replica.path = Some(root.path().join("replica").display().to_string());
+    config.buckets.push(replica);
+    let router = MultiStorageRouter::from_config(&config).await.unwrap();
+
+    // A file where the replica's prefix directory belongs makes every copy fail
+    std::fs::write(root.path().join("replica/ci"), b"").unwrap();
+    let data = boxed_reader_stream(std::io::Cursor::new(b"artifact".to_vec()));
+    router
+      .store_with_token("secret", "abc", data, Some(8))
+      .await
+      .unwrap();
+    router.background_tasks().wait_idle().await;
+
+    let letters = router.dead_letters("secret").await.unwrap();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].key, "ci/abc");
+    assert_eq!(letters[0].target_bucket, "replica");
+    assert_eq!(letters[0].attempts, MAX_DELIVERY_ATTEMPTS);
+
+    std::fs::remove_file(root.path().join("replica/ci")).unwrap();
+    router
+      .retry_dead_letter("secret", &letters[0].id)
+      .await
+      .unwrap();
+    assert!(root.path().join("replica/ci/abc").is_file());
+    assert!(router.dead_letters("secret").await.unwrap().is_empty());
+    assert!(matches!(
+      router.discard_dead_letter("secret", &letters[0].id).await,
+      Err(StorageError::NotFound)
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_dead_letter_is_replaced_in_place() {
+    let root = tempfile::tempdir().unwrap();
+    let storage: Arc<dyn StorageProvider> = Arc::new(
+      crate::infra::fs_storage::FsStorage::new(root.path())
+        .await
+        .unwrap(),
+    );
+    let mut letter = DeadLetter::replication("ci/abc", "replica", Some(8), 1, "down".to_string());
+    store_dead_letter(&storage, "/ci", &letter).await;
+
+    letter.attempts = 2;
+    letter.error = "still down".to_string();
+    store_dead_letter(&storage, "/ci", &letter).await;
+    let key = MultiStorageRouter::build_key("/ci", &dead_letter::object_name(&letter.id));
+    let stored = MultiStorageRouter::read_dead_letter(&storage, &key)
+      .await
+      .unwrap();
+    assert_eq!((stored.attempts, stored.error.as_str()), (2, "still down"));
+  }
 
   #[test]
   fn test_build_key_with_prefix() {
@@ -166,6 +2287,13 @@ mod tests {
     assert_eq!(key, "abc123");
   }
 
+  #[test]
+  fn test_build_key_powerpack_layout() {
+    let object_name = KeyLayout::Powerpack.object_name("abc123");
+    let key = MultiStorageRouter::build_key("/ci", &object_name);
+    assert_eq!(key, "ci/abc123.tar.gz");
+  }
+
   #[test]
   fn test_build_key_with_nested_prefix() {
     let key = MultiStorageRouter::build_key("/team1/subteam", "abc123");
@@ -0,0 +1,208 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::domain::config::ResolvedConfig;
+use crate::domain::storage::{StorageError, StorageProvider};
+
+/// Mismatch between the configured namespaces and the prefixes present in a bucket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum NamespaceFinding {
+  /// Data under a prefix that no token, legacy prefix, alias or mirror refers to
+  Orphaned { bucket: String, prefix: String },
+  /// A token's namespace that holds no objects
+  Empty {
+    bucket: String,
+    token: String,
+    prefix: String,
+  },
+}
+
+impl fmt::Display for NamespaceFinding {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Orphaned { bucket, prefix } => write!(
+        f,
+        "bucket '{}': prefix '{}' holds data but no token uses it",
+        bucket, prefix
+      ),
+      Self::Empty {
+        bucket,
+        token,
+        prefix,
+      } => write!(
+        f,
+        "bucket '{}': namespace '{}' of token '{}' is empty",
+        bucket, prefix, token
+      ),
+    }
+  }
+}
+
+/// Prefixes the configuration expects in one bucket
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BucketNamespaces {
+  /// Every prefix something reads or writes, without leading or trailing `/`
+  pub known: Vec<String>,
+  /// Token names with the prefix they write to, checked for being empty
+  pub tokens: Vec<(String, String)>,
+}
+
+fn key_prefix(prefix: &str) -> String {
+  prefix.trim_matches('/').to_string()
+}
+
+/// Namespaces per bucket name, covering replicas, fallback buckets and the mirror
+pub fn known_namespaces(config: &ResolvedConfig) -> BTreeMap<String, BucketNamespaces> {
+  let mut buckets: BTreeMap<String, BucketNamespaces> = config
+    .buckets
+    .iter()
+    .map(|bucket| (bucket.name.clone(), BucketNamespaces::default()))
+    .collect();
+  let fallbacks: HashMap<&str, &str> = config
+    .buckets
+    .iter()
+    .filter_map(|bucket| {
+      bucket
+        .fallback_bucket
+        .as_deref()
+        .map(|fallback| (bucket.name.as_str(), fallback))
+    })
+    .collect();
+
+  for token in &config.service_access_tokens {
+    let prefixes: Vec<String> = std::iter::once(&token.prefix)
+      .chain(&token.legacy_prefixes)
+      .map(|prefix| key_prefix(prefix))
+      .collect();
+    let copies = std::iter::once(token.bucket.as_str())
+      .chain(token.replica_buckets.iter().map(String::as_str))
+      .chain(fallbacks.get(token.bucket.as_str()).copied());
+    for bucket in copies {
+      if let Some(namespaces) = buckets.get_mut(bucket) {
+        namespaces.known.extend(prefixes.iter().cloned());
+      }
+    }
+    if let Some(namespaces) = buckets.get_mut(&token.bucket) {
+      namespaces
+        .tokens
+        .push((token.name.clone(), key_prefix(&token.prefix)));
+    }
+  }
+
+  if let Some(mirror) = &config.mirror {
+    if let Some(namespaces) = buckets.get_mut(&mirror.bucket) {
+      namespaces.known.push(key_prefix(&mirror.prefix));
+    }
+  }
+  buckets
+}
+
+/// Compare a bucket's prefixes with the namespaces configured for it
+///
+/// Only directories that lead to a known prefix are descended into, so the audit lists a
+/// handful of levels rather than the whole bucket.
+pub async fn audit_bucket(
+  bucket: &str,
+  storage: &dyn StorageProvider,
+  namespaces: &BucketNamespaces,
+) -> Result<Vec<NamespaceFinding>, StorageError> {
+  let mut findings = Vec::new();
+  let mut listings: HashMap<String, Vec<String>> = HashMap::new();
+  // A token owning the whole bucket leaves nothing to be orphaned
+  let whole_bucket = namespaces.known.iter().any(String::is_empty);
+
+  let mut pending = vec![String::new()];
+  while let Some(dir) = pending.pop() {
+    let children = storage.list_prefixes(&dir).await?;
+    for child in &children {
+      let name = child.trim_end_matches('/');
+      let nested = format!("{}/", name);
+      if whole_bucket || namespaces.known.iter().any(|known| known == name) {
+        continue;
+      }
+      if namespaces
+        .known
+        .iter()
+        .any(|known| known.starts_with(&nested))
+      {
+        pending.push(child.clone());
+      } else {
+        findings.push(NamespaceFinding::Orphaned {
+          bucket: bucket.to_string(),
+          prefix: format!("/{}", name),
+        });
+      }
+    }
+    listings.insert(dir, children);
+  }
+
+  for (token, prefix) in &namespaces.tokens {
+    if prefix.is_empty() {
+      continue;
+    }
+    let parent = match prefix.rfind('/') {
+      Some(end) => prefix[..=end].to_string(),
+      None => String::new(),
+    };
+    let children = match listings.get(&parent) {
+      Some(children) => children.clone(),
+      None => storage.list_prefixes(&parent).await?,
+    };
+    let dir = format!("{}/", prefix);
+    if !children.contains(&dir) {
+      findings.push(NamespaceFinding::Empty {
+        bucket: bucket.to_string(),
+        token: token.clone(),
+        prefix: format!("/{}", prefix),
+      });
+    }
+  }
+  Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::infra::fs_storage::FsStorage;
+
+  #[tokio::test]
+  async fn test_audit_reports_orphaned_and_empty_namespaces() {
+    let root = tempfile::tempdir().unwrap();
+    for key in ["team/ci/abc", "team/old/abc", "legacy/abc", "scratch/abc"] {
+      let path = root.path().join(key);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(path, b"artifact").unwrap();
+    }
+    let storage = FsStorage::new(root.path()).await.unwrap();
+    let namespaces = BucketNamespaces {
+      known: vec!["team/ci".into(), "team/web".into(), "legacy".into()],
+      tokens: vec![
+        ("ci".into(), "team/ci".into()),
+        ("web".into(), "team/web".into()),
+      ],
+    };
+
+    let mut findings = audit_bucket("main", &storage, &namespaces).await.unwrap();
+    findings.sort_by_key(|finding| finding.to_string());
+    assert_eq!(
+      findings,
+      vec![
+        NamespaceFinding::Empty {
+          bucket: "main".into(),
+          token: "web".into(),
+          prefix: "/team/web".into(),
+        },
+        NamespaceFinding::Orphaned {
+          bucket: "main".into(),
+          prefix: "/scratch".into(),
+        },
+        NamespaceFinding::Orphaned {
+          bucket: "main".into(),
+          prefix: "/team/old".into(),
+        },
+      ]
+    );
+  }
+}
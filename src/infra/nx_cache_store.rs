@@ -1,22 +1,51 @@
 use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
 use minio::s3::builders::ObjectContent;
 use minio::s3::creds::StaticProvider;
+use minio::s3::error::{Error, NetworkError, S3ServerError};
 use minio::s3::http::BaseUrl;
+use minio::s3::minio_error_response::MinioErrorCode;
+use minio::s3::multimap_ext::{Multimap, MultimapExt};
+use minio::s3::response_traits::HasS3Fields;
 use minio::s3::sse::{Sse, SseCustomerKey, SseKms, SseS3};
-use minio::s3::types::{Region, S3Api};
+use minio::s3::types::{Region, S3Api, ToStream};
 use minio::s3::MinioClient;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::io::AsyncRead;
+use tokio::sync::mpsc;
 use tokio::time::sleep;
 use tokio_util::io::{ReaderStream, StreamReader};
 
 use crate::domain::{
-  config::{ResolvedBucketConfig, ResolvedSseConfig},
-  storage::{StorageError, StorageProvider},
+  config::{RangedReadsConfig, ResolvedBucketConfig, ResolvedSseConfig, SpillConfig, StorageClass},
+  http_date,
+  storage::{
+    DynAsyncRead, ListPage, ObjectMetadata, ObjectStat, PrefixUsage, StorageError, StorageProvider,
+  },
 };
+use crate::infra::assume_role::AssumeRole;
+use crate::infra::retry::RetryPolicy;
+use crate::infra::spill::Spill;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Size and timestamps from the headers of a HEAD (or stat) response
+///
+/// The upload time comes from the `uploaded-at` metadata written with the object.
+pub(crate) fn object_stat(size: u64, headers: &reqwest::header::HeaderMap) -> ObjectStat {
+  let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+  ObjectStat {
+    size: Some(size),
+    last_modified: header("last-modified").and_then(http_date::parse),
+    created_at: header("x-amz-meta-uploaded-at").and_then(|value| value.parse().ok()),
+  }
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
 
 #[derive(Clone)]
 pub struct NxCacheStorage {
@@ -24,6 +53,12 @@ pub struct NxCacheStorage {
   bucket_name: String,
   sse: Option<Arc<dyn Sse>>,
   sse_customer_key: Option<SseCustomerKey>,
+  conditional_writes: bool,
+  object_tagging: bool,
+  storage_class: Option<StorageClass>,
+  retry: RetryPolicy,
+  spill: Option<Spill>,
+  ranged_reads: Option<RangedReadsConfig>,
 }
 
 impl NxCacheStorage {
@@ -40,34 +75,72 @@ impl NxCacheStorage {
       || (message.contains("badrequest") && message.contains("sse"))
   }
 
-  fn is_retryable_error(error_message: &str) -> bool {
-    let message = error_message.to_ascii_lowercase();
-    message.contains("incompletemessage")
-      || message.contains("timed out")
-      || message.contains("timeout")
-      || message.contains("connection reset")
-      || message.contains("connection closed")
-      || message.contains("broken pipe")
-      || message.contains("sendrequest")
-  }
-
-  fn retry_delay(attempt: usize) -> Duration {
-    let base_ms = match attempt {
-      1 => 100,
-      2 => 300,
-      _ => 900,
-    };
-    let jitter_ms = SystemTime::now()
-      .duration_since(UNIX_EPOCH)
-      .map(|duration| (duration.subsec_nanos() % 50) as u64)
-      .unwrap_or(0);
-    Duration::from_millis(base_ms + jitter_ms)
+  /// A conditional write lost against an existing object (412), or against a
+  /// concurrent conditional write of the same key (409)
+  ///
+  /// Decided by the S3 error code or, for responses without a body, the HTTP status, never by
+  /// the message text, which may contain keys or request ids with "412" in them.
+  fn is_precondition_failed(error: &Error) -> bool {
+    match error {
+      Error::S3Server(S3ServerError::S3Error(response)) => match response.code() {
+        MinioErrorCode::OtherError(code) => {
+          code.eq_ignore_ascii_case("PreconditionFailed")
+            || code.eq_ignore_ascii_case("ConditionalRequestConflict")
+        },
+        _ => false,
+      },
+      Error::S3Server(S3ServerError::InvalidServerResponse {
+        http_status_code, ..
+      }) => *http_status_code == 412,
+      Error::S3Server(S3ServerError::HttpError(status, _)) => *status == 412,
+      Error::Network(NetworkError::ServerError(status)) => *status == 412,
+      _ => false,
+    }
   }
 
   /// Create NxCacheStorage from a resolved bucket configuration
   pub async fn from_resolved_bucket(
     bucket_config: &ResolvedBucketConfig,
   ) -> Result<Self, StorageError> {
+    Self::from_resolved_bucket_with_pool(bucket_config, &mut ClientPool::default()).await
+  }
+
+  /// Create NxCacheStorage, reusing a client from `pool` if an earlier bucket has the same
+  /// endpoint, region and credentials
+  pub async fn from_resolved_bucket_with_pool(
+    bucket_config: &ResolvedBucketConfig,
+    pool: &mut ClientPool,
+  ) -> Result<Self, StorageError> {
+    let (sse, sse_customer_key) = match &bucket_config.sse {
+      None => (None, None),
+      Some(ResolvedSseConfig::SseS3) => (Some(Arc::new(SseS3::new()) as Arc<dyn Sse>), None),
+      Some(ResolvedSseConfig::SseKms { key_id, context }) => {
+        let sse = SseKms::new(key_id, context.as_deref());
+        (Some(Arc::new(sse) as Arc<dyn Sse>), None)
+      },
+      Some(ResolvedSseConfig::SseC { key }) => {
+        let ssec = SseCustomerKey::new(key);
+        let sse: Arc<dyn Sse> = Arc::new(ssec.clone());
+        (Some(sse), Some(ssec))
+      },
+    };
+
+    Ok(Self {
+      client: pool.client(bucket_config).await?,
+      bucket_name: bucket_config.bucket_name.clone(),
+      sse,
+      sse_customer_key,
+      conditional_writes: bucket_config.conditional_writes,
+      object_tagging: bucket_config.object_tagging,
+      storage_class: bucket_config.storage_class,
+      retry: RetryPolicy::new(bucket_config.retry.clone()),
+      spill: bucket_config.spill.as_ref().map(Spill::new),
+      ranged_reads: bucket_config.ranged_reads.clone(),
+    })
+  }
+
+  /// MinIO client for a bucket's endpoint and credentials
+  async fn client(bucket_config: &ResolvedBucketConfig) -> Result<MinioClient, StorageError> {
     let endpoint = bucket_config
       .endpoint_url
       .as_ref()
@@ -101,146 +174,45 @@ impl NxCacheStorage {
       StorageError::OperationFailed
     })?;
 
-    let static_provider = StaticProvider::new(access_key, secret_key, None);
-
-    let (sse, sse_customer_key) = match &bucket_config.sse {
-      None => (None, None),
-      Some(ResolvedSseConfig::SseS3) => (Some(Arc::new(SseS3::new()) as Arc<dyn Sse>), None),
-      Some(ResolvedSseConfig::SseKms { key_id, context }) => {
-        let sse = SseKms::new(key_id, context.as_deref());
-        (Some(Arc::new(sse) as Arc<dyn Sse>), None)
-      },
-      Some(ResolvedSseConfig::SseC { key }) => {
-        let ssec = SseCustomerKey::new(key);
-        let sse: Arc<dyn Sse> = Arc::new(ssec.clone());
-        (Some(sse), Some(ssec))
-      },
-    };
+    let static_provider = StaticProvider::new(
+      access_key,
+      secret_key,
+      bucket_config.session_token.as_deref(),
+    );
+    let assume_role = AssumeRole::from_resolved_bucket(bucket_config)?;
 
-    let ssl_cert_file = bucket_config
-      .tls_ca_file
-      .as_ref()
-      .map(|value| value.trim().to_string())
-      .filter(|value| !value.is_empty())
-      .map(PathBuf::from)
-      .filter(|path| path.is_file())
-      .or_else(|| {
-        std::env::var("SSL_CERT_FILE")
-          .ok()
-          .map(|value| value.trim().to_string())
-          .filter(|value| !value.is_empty())
-          .map(PathBuf::from)
-          .filter(|path| path.is_file())
-      });
-
-    let ignore_cert_check = bucket_config.insecure_tls.or_else(|| {
-      std::env::var("NX_CACHE_SERVER_INSECURE_TLS")
-        .ok()
-        .map(|value| value.trim().to_ascii_lowercase())
-        .and_then(|value| match value.as_str() {
-          "1" | "true" | "yes" | "y" => Some(true),
-          "0" | "false" | "no" | "n" => Some(false),
-          _ => None,
-        })
-    });
+    let ssl_cert_file = ssl_cert_file(bucket_config);
+    let ignore_cert_check = ignore_cert_check(bucket_config);
 
-    let client = MinioClient::new(
-      base_url,
-      Some(static_provider),
-      ssl_cert_file.as_deref(),
-      ignore_cert_check,
-    )
+    match assume_role {
+      Some(assume_role) => MinioClient::new(
+        base_url,
+        Some(assume_role.into_provider().await?),
+        ssl_cert_file.as_deref(),
+        ignore_cert_check,
+      ),
+      None => MinioClient::new(
+        base_url,
+        Some(static_provider),
+        ssl_cert_file.as_deref(),
+        ignore_cert_check,
+      ),
+    }
     .map_err(|e| {
       tracing::error!("Failed to create MinIO client: {:?}", e);
       StorageError::OperationFailed
-    })?;
-
-    Ok(Self {
-      client,
-      bucket_name: bucket_config.bucket_name.clone(),
-      sse,
-      sse_customer_key,
     })
   }
-}
-
-#[async_trait]
-impl StorageProvider for NxCacheStorage {
-  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
-    match self
-      .client
-      .stat_object(&self.bucket_name, hash)
-      .map_err(|e| {
-        tracing::error!("MinIO stat_object builder error: {:?}", e);
-        StorageError::OperationFailed
-      })?
-      .ssec(self.sse_customer_key.clone())
-      .build()
-      .send()
-      .await
-    {
-      Ok(_) => Ok(true),
-      Err(e) => {
-        let err_msg = e.to_string();
-        // MinIO returns 404 for non-existent objects
-        if Self::is_not_found_error(&err_msg) {
-          Ok(false)
-        } else if self.sse_customer_key.is_some() && Self::is_sse_c_key_mismatch(&err_msg) {
-          tracing::debug!(
-            "MinIO stat_object failed with SSE-C (key mismatch), treating as exists: {:?}",
-            e
-          );
-          Ok(true)
-        } else {
-          tracing::error!("MinIO stat_object failed: {:?}", e);
-          Err(StorageError::OperationFailed)
-        }
-      },
-    }
-  }
 
-  async fn store(
+  /// GET an object, or the `(offset, length)` range of it, retrying transient errors
+  async fn get_stream(
     &self,
     hash: &str,
-    data: ReaderStream<impl AsyncRead + Send + Unpin + 'static>,
-    content_length: Option<u64>,
-  ) -> Result<(), StorageError> {
-    if self.exists(hash).await? {
-      return Err(StorageError::AlreadyExists);
-    }
+    range: Option<(u64, u64)>,
+  ) -> Result<ByteStream, StorageError> {
+    let max_attempts = self.retry.max_attempts();
 
-    let content = ObjectContent::new_from_stream(data, content_length);
-    let sse_enabled = self.sse.is_some();
-    let sse_customer_key_enabled = self.sse_customer_key.is_some();
-
-    self
-      .client
-      .put_object_content(&self.bucket_name, hash, content)
-      .map_err(|e| {
-        tracing::error!("MinIO put_object_content builder error: {:?}", e);
-        StorageError::OperationFailed
-      })?
-      .sse(self.sse.clone())
-      .build()
-      .send()
-      .await
-      .map_err(|e| {
-        tracing::error!(
-          "MinIO put_object_content failed (sse_enabled={}, sse_customer_key_enabled={}): {:?}",
-          sse_enabled,
-          sse_customer_key_enabled,
-          e
-        );
-        StorageError::OperationFailed
-      })?;
-
-    Ok(())
-  }
-
-  async fn retrieve(&self, hash: &str) -> Result<Box<dyn AsyncRead + Send + Unpin>, StorageError> {
-    const MAX_ATTEMPTS: usize = 3;
-
-    for attempt in 1..=MAX_ATTEMPTS {
+    for attempt in 1..=max_attempts {
       let response = match self
         .client
         .get_object(&self.bucket_name, hash)
@@ -249,6 +221,8 @@ impl StorageProvider for NxCacheStorage {
           StorageError::OperationFailed
         })?
         .ssec(self.sse_customer_key.clone())
+        .offset(range.map(|(offset, _)| offset))
+        .length(range.map(|(_, length)| length))
         .build()
         .send()
         .await
@@ -260,12 +234,11 @@ impl StorageProvider for NxCacheStorage {
             return Err(StorageError::NotFound);
           }
 
-          if Self::is_retryable_error(&err_msg) && attempt < MAX_ATTEMPTS {
-            let delay = Self::retry_delay(attempt);
+          if let Some(delay) = self.retry.retry_after(&err_msg, attempt) {
             tracing::debug!(
               "MinIO get_object transient error, retrying (attempt {}/{}, delay {:?}): {:?}",
               attempt,
-              MAX_ATTEMPTS,
+              max_attempts,
               delay,
               e
             );
@@ -282,12 +255,11 @@ impl StorageProvider for NxCacheStorage {
         Ok(c) => c,
         Err(e) => {
           let err_msg = e.to_string();
-          if Self::is_retryable_error(&err_msg) && attempt < MAX_ATTEMPTS {
-            let delay = Self::retry_delay(attempt);
+          if let Some(delay) = self.retry.retry_after(&err_msg, attempt) {
             tracing::debug!(
               "MinIO content error, retrying (attempt {}/{}, delay {:?}): {:?}",
               attempt,
-              MAX_ATTEMPTS,
+              max_attempts,
               delay,
               e
             );
@@ -303,12 +275,11 @@ impl StorageProvider for NxCacheStorage {
         Ok((stream, size)) => (stream, size),
         Err(e) => {
           let err_msg = e.to_string();
-          if Self::is_retryable_error(&err_msg) && attempt < MAX_ATTEMPTS {
-            let delay = Self::retry_delay(attempt);
+          if let Some(delay) = self.retry.retry_after(&err_msg, attempt) {
             tracing::debug!(
               "MinIO stream transient error, retrying (attempt {}/{}, delay {:?}): {:?}",
               attempt,
-              MAX_ATTEMPTS,
+              max_attempts,
               delay,
               e
             );
@@ -321,18 +292,607 @@ impl StorageProvider for NxCacheStorage {
         },
       };
 
-      let reader = StreamReader::new(stream);
-      return Ok(Box::new(reader));
+      return Ok(Box::pin(stream));
     }
 
     Err(StorageError::OperationFailed)
   }
+
+  /// Read a whole range into memory
+  async fn read_range(&self, hash: &str, range: (u64, u64)) -> std::io::Result<Bytes> {
+    let mut stream = self
+      .get_stream(hash, Some(range))
+      .await
+      .map_err(std::io::Error::other)?;
+    let mut part = Vec::with_capacity(range.1 as usize);
+    while let Some(chunk) = stream.next().await {
+      part.extend_from_slice(&chunk?);
+    }
+    Ok(Bytes::from(part))
+  }
+
+  /// Stream the first range right away while the rest are fetched in parallel behind it
+  async fn retrieve_ranged(
+    &self,
+    hash: &str,
+    ranged_reads: &RangedReadsConfig,
+  ) -> Result<DynAsyncRead, StorageError> {
+    let part_size = ranged_reads.part_size_bytes;
+    let (first, size) = tokio::join!(self.get_stream(hash, Some((0, part_size))), self.size(hash));
+    let size = size?;
+    // A range of an empty object is not satisfiable
+    if size == 0 {
+      return Ok(Box::new(tokio::io::empty()));
+    }
+    let first = first?;
+    if size <= part_size {
+      return Ok(Box::new(StreamReader::new(first)));
+    }
+
+    let (sender, receiver) = mpsc::channel(ranged_reads.concurrency);
+    let storage = self.clone();
+    let hash = hash.to_string();
+    let concurrency = ranged_reads.concurrency;
+    tokio::spawn(async move {
+      let mut parts = stream::iter(part_ranges(size, part_size).skip(1))
+        .map(|range| storage.read_range(&hash, range))
+        .buffered(concurrency);
+      while let Some(part) = parts.next().await {
+        let failed = part.is_err();
+        // Stops once the client goes away
+        if sender.send(part).await.is_err() || failed {
+          return;
+        }
+      }
+    });
+
+    let rest = stream::unfold(receiver, |mut receiver| async move {
+      receiver.recv().await.map(|part| (part, receiver))
+    });
+    Ok(Box::new(StreamReader::new(first.chain(Box::pin(rest)))))
+  }
+
+  /// `x-amz-meta-*` headers and, if enabled, the `x-amz-tagging` header for an upload
+  pub(crate) fn metadata_headers(
+    metadata: &ObjectMetadata,
+    tagging: bool,
+  ) -> Vec<(String, String)> {
+    let mut headers = vec![
+      (
+        "x-amz-meta-token".to_string(),
+        percent_encode(&metadata.token),
+      ),
+      (
+        "x-amz-meta-namespace".to_string(),
+        percent_encode(&metadata.namespace),
+      ),
+      (
+        "x-amz-meta-uploaded-at".to_string(),
+        metadata.uploaded_at.to_string(),
+      ),
+    ];
+    if let Some(content_length) = metadata.content_length {
+      headers.push((
+        "x-amz-meta-content-length".to_string(),
+        content_length.to_string(),
+      ));
+    }
+    if let Some(run_id) = &metadata.run_id {
+      headers.push(("x-amz-meta-run-id".to_string(), percent_encode(run_id)));
+    }
+    // Not sent as `Content-Encoding`: the stored bytes carry the compression header
+    if let Some(codec) = metadata.content_encoding {
+      headers.push((
+        "x-amz-meta-content-encoding".to_string(),
+        codec.as_str().to_string(),
+      ));
+    }
+    if let Some(ttl) = metadata.ttl_seconds {
+      headers.push(("x-amz-meta-ttl-seconds".to_string(), ttl.to_string()));
+      headers.push((
+        "x-amz-meta-expires-at".to_string(),
+        metadata.uploaded_at.saturating_add(ttl).to_string(),
+      ));
+    }
+    if tagging {
+      let mut tags = format!(
+        "token={}&namespace={}",
+        percent_encode(&tag_value(&metadata.token)),
+        percent_encode(&tag_value(&metadata.namespace))
+      );
+      if let Some(run_id) = &metadata.run_id {
+        tags.push_str(&format!("&run-id={}", percent_encode(&tag_value(run_id))));
+      }
+      // Lifecycle rules expire in whole days
+      if let Some(ttl) = metadata.ttl_seconds {
+        tags.push_str(&format!("&ttl-days={}", ttl.div_ceil(SECONDS_PER_DAY)));
+      }
+      for (key, value) in &metadata.cost_tags {
+        tags.push_str(&format!(
+          "&{}={}",
+          percent_encode(key),
+          percent_encode(&tag_value(value))
+        ));
+      }
+      headers.push(("x-amz-tagging".to_string(), tags));
+    }
+    headers
+  }
+
+  async fn put(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: Option<&ObjectMetadata>,
+  ) -> Result<(), StorageError> {
+    // Without conditional writes, two concurrent PUTs of the same hash can both pass this check.
+    // The upload itself is not retried: the body is streamed once and cannot be replayed.
+    // An overwrite is a single unconditional PUT, so the existing object stays readable
+    // until the new one replaces it
+    let overwrite = metadata.is_some_and(|metadata| metadata.overwrite);
+    let mut headers = Multimap::new();
+    if !overwrite {
+      if self.conditional_writes {
+        headers.add("If-None-Match", "*");
+      } else if self.exists(hash).await? {
+        return Err(StorageError::AlreadyExists);
+      }
+    }
+    if let Some(storage_class) = self.storage_class {
+      headers.add("x-amz-storage-class", storage_class.as_str());
+    }
+    if let Some(metadata) = metadata {
+      for (name, value) in Self::metadata_headers(metadata, self.object_tagging) {
+        headers.add(name, value);
+      }
+    }
+    let extra_headers = (!headers.is_empty()).then_some(headers);
+
+    // The spilled file must outlive the upload; it is removed when dropped
+    let (content, _spilled) = match (&self.spill, content_length) {
+      // Without a size the client would buffer the largest possible parts, and some
+      // S3-compatible services reject such uploads, so chunked bodies get a size first
+      (spill, None) => {
+        let sized = match spill {
+          Some(spill) => spill.sized(data).await?,
+          None => Spill::new(&SpillConfig::default()).sized(data).await?,
+        };
+        let content = ObjectContent::new_from_stream(sized.data, Some(sized.size));
+        (content, sized.spilled)
+      },
+      (Some(spill), Some(_)) if spill.applies_to(content_length) => {
+        let spilled = spill.write(data).await?;
+        let content = ObjectContent::new_from_stream(spilled.open().await?, Some(spilled.size()));
+        (content, Some(spilled))
+      },
+      _ => (ObjectContent::new_from_stream(data, content_length), None),
+    };
+    let sse_enabled = self.sse.is_some();
+    let sse_customer_key_enabled = self.sse_customer_key.is_some();
+
+    let result = self
+      .client
+      .put_object_content(&self.bucket_name, hash, content)
+      .map_err(|e| {
+        tracing::error!("MinIO put_object_content builder error: {:?}", e);
+        StorageError::OperationFailed
+      })?
+      .sse(self.sse.clone())
+      .extra_headers(extra_headers)
+      .build()
+      .send()
+      .await;
+
+    match result {
+      Ok(_) => Ok(()),
+      Err(e) if self.conditional_writes && Self::is_precondition_failed(&e) => {
+        tracing::debug!("Conditional put rejected, object already exists: {}", hash);
+        Err(StorageError::AlreadyExists)
+      },
+      Err(e) => {
+        tracing::error!(
+          "MinIO put_object_content failed (sse_enabled={}, sse_customer_key_enabled={}): {:?}",
+          sse_enabled,
+          sse_customer_key_enabled,
+          e
+        );
+        Err(StorageError::OperationFailed)
+      },
+    }
+  }
 }
 
-impl NxCacheStorage {
+/// MinIO clients shared by buckets with the same endpoint, region and credentials
+///
+/// Every client keeps its own connection pool, so configs with many buckets on one endpoint
+/// would otherwise open and keep alive connections per bucket.
+#[derive(Default)]
+pub struct ClientPool {
+  clients: HashMap<ClientKey, MinioClient>,
+}
+
+/// Everything a MinIO client is built from; the bucket name is passed per request
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct ClientKey {
+  endpoint: Option<String>,
+  region: Option<String>,
+  force_path_style: bool,
+  access_key_id: Option<String>,
+  secret_access_key: Option<String>,
+  session_token: Option<String>,
+  role_arn: Option<String>,
+  external_id: Option<String>,
+  ssl_cert_file: Option<PathBuf>,
+  ignore_cert_check: Option<bool>,
+}
+
+impl ClientKey {
+  fn new(bucket_config: &ResolvedBucketConfig) -> Self {
+    Self {
+      endpoint: bucket_config.endpoint_url.clone(),
+      region: bucket_config.region.clone(),
+      force_path_style: bucket_config.force_path_style,
+      access_key_id: bucket_config.access_key_id.clone(),
+      secret_access_key: bucket_config.secret_access_key.clone(),
+      session_token: bucket_config.session_token.clone(),
+      role_arn: bucket_config.role_arn.clone(),
+      external_id: bucket_config.external_id.clone(),
+      ssl_cert_file: ssl_cert_file(bucket_config),
+      ignore_cert_check: ignore_cert_check(bucket_config),
+    }
+  }
+}
+
+impl ClientPool {
+  /// Client for a bucket, created on first use of its endpoint and credentials
+  pub async fn client(
+    &mut self,
+    bucket_config: &ResolvedBucketConfig,
+  ) -> Result<MinioClient, StorageError> {
+    let key = ClientKey::new(bucket_config);
+    if let Some(client) = self.clients.get(&key) {
+      tracing::debug!(
+        "Bucket '{}' shares the S3 client of an earlier bucket",
+        bucket_config.name
+      );
+      return Ok(client.clone());
+    }
+    let client = NxCacheStorage::client(bucket_config).await?;
+    self.clients.insert(key, client.clone());
+    Ok(client)
+  }
+}
+
+/// CA bundle trusted for the bucket's endpoint, falling back to `SSL_CERT_FILE`
+pub(crate) fn ssl_cert_file(bucket_config: &ResolvedBucketConfig) -> Option<PathBuf> {
+  bucket_config
+    .tls_ca_file
+    .as_ref()
+    .map(|value| value.trim().to_string())
+    .filter(|value| !value.is_empty())
+    .map(PathBuf::from)
+    .filter(|path| path.is_file())
+    .or_else(|| {
+      std::env::var("SSL_CERT_FILE")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+    })
+}
+
+/// Whether to skip certificate checks, falling back to `NX_CACHE_SERVER_INSECURE_TLS`
+pub(crate) fn ignore_cert_check(bucket_config: &ResolvedBucketConfig) -> Option<bool> {
+  bucket_config.insecure_tls.or_else(|| {
+    std::env::var("NX_CACHE_SERVER_INSECURE_TLS")
+      .ok()
+      .map(|value| value.trim().to_ascii_lowercase())
+      .and_then(|value| match value.as_str() {
+        "1" | "true" | "yes" | "y" => Some(true),
+        "0" | "false" | "no" | "n" => Some(false),
+        _ => None,
+      })
+  })
+}
+
+/// `(offset, length)` of each part of an object split into `part_size` ranges
+fn part_ranges(size: u64, part_size: u64) -> impl Iterator<Item = (u64, u64)> {
+  (0..size)
+    .step_by(part_size as usize)
+    .map(move |offset| (offset, part_size.min(size - offset)))
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+pub(crate) fn percent_encode(value: &str) -> String {
+  let mut encoded = String::with_capacity(value.len());
+  for byte in value.bytes() {
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+        encoded.push(byte as char)
+      },
+      _ => encoded.push_str(&format!("%{:02X}", byte)),
+    }
+  }
+  encoded
+}
+
+/// Replace characters S3 does not accept in tag values, truncating to the 256 character limit
+fn tag_value(value: &str) -> String {
+  value
+    .chars()
+    .map(|c| match c {
+      c if c.is_ascii_alphanumeric() => c,
+      ' ' | '+' | '-' | '=' | '.' | '_' | ':' | '/' | '@' => c,
+      _ => '_',
+    })
+    .take(256)
+    .collect()
+}
+
+#[async_trait]
+impl StorageProvider for NxCacheStorage {
+  async fn exists(&self, hash: &str) -> Result<bool, StorageError> {
+    let max_attempts = self.retry.max_attempts();
+
+    for attempt in 1..=max_attempts {
+      let result = self
+        .client
+        .stat_object(&self.bucket_name, hash)
+        .map_err(|e| {
+          tracing::error!("MinIO stat_object builder error: {:?}", e);
+          StorageError::OperationFailed
+        })?
+        .ssec(self.sse_customer_key.clone())
+        .build()
+        .send()
+        .await;
+
+      let e = match result {
+        Ok(_) => return Ok(true),
+        Err(e) => e,
+      };
+      let err_msg = e.to_string();
+      // MinIO returns 404 for non-existent objects
+      if Self::is_not_found_error(&err_msg) {
+        return Ok(false);
+      }
+      if self.sse_customer_key.is_some() && Self::is_sse_c_key_mismatch(&err_msg) {
+        tracing::debug!(
+          "MinIO stat_object failed with SSE-C (key mismatch), treating as exists: {:?}",
+          e
+        );
+        return Ok(true);
+      }
+      if let Some(delay) = self.retry.retry_after(&err_msg, attempt) {
+        tracing::debug!(
+          "MinIO stat_object transient error, retrying (attempt {}/{}, delay {:?}): {:?}",
+          attempt,
+          max_attempts,
+          delay,
+          e
+        );
+        sleep(delay).await;
+        continue;
+      }
+
+      tracing::error!("MinIO stat_object failed: {:?}", e);
+      return Err(StorageError::OperationFailed);
+    }
+
+    Err(StorageError::OperationFailed)
+  }
+
+  async fn store(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+  ) -> Result<(), StorageError> {
+    self.put(hash, data, content_length, None).await
+  }
+
+  async fn store_with_metadata(
+    &self,
+    hash: &str,
+    data: ReaderStream<DynAsyncRead>,
+    content_length: Option<u64>,
+    metadata: &ObjectMetadata,
+  ) -> Result<(), StorageError> {
+    self.put(hash, data, content_length, Some(metadata)).await
+  }
+
+  async fn retrieve(&self, hash: &str) -> Result<DynAsyncRead, StorageError> {
+    match &self.ranged_reads {
+      Some(ranged_reads) => self.retrieve_ranged(hash, ranged_reads).await,
+      None => Ok(Box::new(StreamReader::new(
+        self.get_stream(hash, None).await?,
+      ))),
+    }
+  }
+
+  async fn size(&self, hash: &str) -> Result<u64, StorageError> {
+    self
+      .stat(hash)
+      .await?
+      .size
+      .ok_or(StorageError::OperationFailed)
+  }
+
+  async fn stat(&self, hash: &str) -> Result<ObjectStat, StorageError> {
+    let response = self
+      .client
+      .stat_object(&self.bucket_name, hash)
+      .map_err(|e| {
+        tracing::error!("MinIO stat_object builder error: {:?}", e);
+        StorageError::OperationFailed
+      })?
+      .ssec(self.sse_customer_key.clone())
+      .build()
+      .send()
+      .await
+      .map_err(|e| {
+        if Self::is_not_found_error(&e.to_string()) {
+          StorageError::NotFound
+        } else {
+          tracing::error!("MinIO stat_object failed: {:?}", e);
+          StorageError::OperationFailed
+        }
+      })?;
+
+    let size = response.size().map_err(|e| {
+      tracing::error!("MinIO stat_object returned no object size: {:?}", e);
+      StorageError::OperationFailed
+    })?;
+    Ok(object_stat(size, response.headers()))
+  }
+
+  async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    let mut pages = self
+      .client
+      .list_objects(&self.bucket_name)
+      .map_err(|e| {
+        tracing::error!("MinIO list_objects builder error: {:?}", e);
+        StorageError::OperationFailed
+      })?
+      .prefix(Some(prefix.to_string()))
+      .recursive(true)
+      .build()
+      .to_stream()
+      .await;
+
+    let mut keys = Vec::new();
+    while let Some(page) = pages.next().await {
+      let page = page.map_err(|e| {
+        tracing::error!("MinIO list_objects failed: {:?}", e);
+        StorageError::OperationFailed
+      })?;
+      keys.extend(page.contents.into_iter().map(|entry| entry.name));
+    }
+    Ok(keys)
+  }
+
+  async fn list_page(
+    &self,
+    prefix: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> Result<ListPage, StorageError> {
+    // The cursor is the ListObjectsV2 continuation token; only the first page is fetched
+    let mut pages = self
+      .client
+      .list_objects(&self.bucket_name)
+      .map_err(|e| {
+        tracing::error!("MinIO list_objects builder error: {:?}", e);
+        StorageError::OperationFailed
+      })?
+      .prefix(Some(prefix.to_string()))
+      .recursive(true)
+      .max_keys(Some(limit.clamp(1, 1000) as u16))
+      .continuation_token(cursor.map(str::to_string))
+      .build()
+      .to_stream()
+      .await;
+
+    match pages.next().await {
+      Some(page) => {
+        let page = page.map_err(|e| {
+          tracing::error!("MinIO list_objects failed: {:?}", e);
+          StorageError::OperationFailed
+        })?;
+        Ok(ListPage {
+          keys: page.contents.into_iter().map(|entry| entry.name).collect(),
+          next: page.next_continuation_token,
+        })
+      },
+      None => Ok(ListPage::default()),
+    }
+  }
+
+  async fn usage(&self, prefix: &str) -> Result<PrefixUsage, StorageError> {
+    // ListObjectsV2 reports the size of every entry, so no object needs a HEAD request
+    let mut pages = self
+      .client
+      .list_objects(&self.bucket_name)
+      .map_err(|e| {
+        tracing::error!("MinIO list_objects builder error: {:?}", e);
+        StorageError::OperationFailed
+      })?
+      .prefix(Some(prefix.to_string()))
+      .recursive(true)
+      .build()
+      .to_stream()
+      .await;
+
+    let mut usage = PrefixUsage::default();
+    while let Some(page) = pages.next().await {
+      let page = page.map_err(|e| {
+        tracing::error!("MinIO list_objects failed: {:?}", e);
+        StorageError::OperationFailed
+      })?;
+      for entry in page.contents {
+        usage.objects += 1;
+        usage.bytes += entry.size.unwrap_or(0);
+      }
+    }
+    Ok(usage)
+  }
+
+  async fn list_prefixes(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+    // Non-recursive listings return the common prefixes below `prefix` as entries
+    let mut pages = self
+      .client
+      .list_objects(&self.bucket_name)
+      .map_err(|e| {
+        tracing::error!("MinIO list_objects builder error: {:?}", e);
+        StorageError::OperationFailed
+      })?
+      .prefix(Some(prefix.to_string()))
+      .recursive(false)
+      .build()
+      .to_stream()
+      .await;
+
+    let mut prefixes = Vec::new();
+    while let Some(page) = pages.next().await {
+      let page = page.map_err(|e| {
+        tracing::error!("MinIO list_objects failed: {:?}", e);
+        StorageError::OperationFailed
+      })?;
+      prefixes.extend(
+        page
+          .contents
+          .into_iter()
+          .filter(|entry| entry.is_prefix)
+          .map(|entry| entry.name),
+      );
+    }
+    Ok(prefixes)
+  }
+
+  async fn delete(&self, hash: &str) -> Result<(), StorageError> {
+    // S3 reports success for keys that do not exist
+    self
+      .client
+      .delete_object(&self.bucket_name, hash)
+      .map_err(|e| {
+        tracing::error!("MinIO delete_object builder error: {:?}", e);
+        StorageError::OperationFailed
+      })?
+      .build()
+      .send()
+      .await
+      .map(|_| ())
+      .map_err(|e| {
+        tracing::error!("MinIO delete_object failed: {:?}", e);
+        StorageError::OperationFailed
+      })
+  }
+
   /// Test bucket connectivity by checking if bucket exists
   /// This verifies that credentials are valid and the bucket is accessible
-  pub async fn test_connection(&self) -> Result<(), StorageError> {
+  async fn test_connection(&self) -> Result<(), StorageError> {
     tracing::debug!("Testing connection to bucket: {}", self.bucket_name);
 
     // Check if bucket exists
@@ -364,3 +924,141 @@ impl NxCacheStorage {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Codec;
+  use minio::s3::minio_error_response::MinioErrorResponse;
+
+  fn bucket(name: &str, secret: &str) -> ResolvedBucketConfig {
+    let yaml = format!(
+      "buckets:\n  - name: {}\n    bucketName: {}\n    endpointUrl: http://localhost:9000\n    region: us-east-1\n    accessKeyId: key\n    secretAccessKey: {}\nserviceAccessTokens: []\n",
+      name, name, secret
+    );
+    crate::domain::config::Config::from_yaml_str(&yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap()
+      .buckets
+      .remove(0)
+  }
+
+  #[tokio::test]
+  async fn test_client_pool_shares_clients_per_credentials() {
+    let mut pool = ClientPool::default();
+    pool.client(&bucket("a", "secret")).await.unwrap();
+    pool.client(&bucket("b", "secret")).await.unwrap();
+    assert_eq!(pool.clients.len(), 1);
+
+    pool.client(&bucket("c", "other")).await.unwrap();
+    assert_eq!(pool.clients.len(), 2);
+  }
+
+  #[test]
+  fn test_is_precondition_failed() {
+    let s3_error = |code: MinioErrorCode, message: &str| {
+      let response = MinioErrorResponse::new(
+        Default::default(),
+        code,
+        Some(message.to_string()),
+        "/cache/4120412".to_string(),
+        "412412".to_string(),
+        String::new(),
+        None,
+        None,
+      );
+      Error::S3Server(S3ServerError::S3Error(Box::new(response)))
+    };
+
+    assert!(NxCacheStorage::is_precondition_failed(&s3_error(
+      MinioErrorCode::OtherError("preconditionfailed".to_string()),
+      "At least one of the pre-conditions you specified did not hold"
+    )));
+    assert!(NxCacheStorage::is_precondition_failed(&s3_error(
+      MinioErrorCode::OtherError("conditionalrequestconflict".to_string()),
+      "conflict"
+    )));
+    assert!(NxCacheStorage::is_precondition_failed(&Error::Network(
+      NetworkError::ServerError(412)
+    )));
+    // "412" in keys, request ids or messages of other errors is not a lost conditional write
+    assert!(!NxCacheStorage::is_precondition_failed(&s3_error(
+      MinioErrorCode::OtherError("internalerror".to_string()),
+      "We encountered an internal error uploading 412abc"
+    )));
+    assert!(!NxCacheStorage::is_precondition_failed(&s3_error(
+      MinioErrorCode::AccessDenied,
+      "412"
+    )));
+    assert!(!NxCacheStorage::is_precondition_failed(&Error::Network(
+      NetworkError::ServerError(500)
+    )));
+  }
+
+  #[test]
+  fn test_metadata_headers() {
+    let metadata = ObjectMetadata {
+      token: "team a/ci".to_string(),
+      namespace: "nx/team-a".to_string(),
+      uploaded_at: 1_700_000_000,
+      content_length: Some(42),
+      ttl_seconds: None,
+      run_id: Some("1234-1".to_string()),
+      content_encoding: Some(Codec::Gzip),
+      cost_tags: vec![("team".to_string(), "platform".to_string())],
+      overwrite: false,
+    };
+
+    let headers = NxCacheStorage::metadata_headers(&metadata, true);
+    let header = |name: &str| {
+      headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+    };
+    assert_eq!(header("x-amz-meta-token"), Some("team%20a%2Fci"));
+    assert_eq!(header("x-amz-meta-uploaded-at"), Some("1700000000"));
+    assert_eq!(header("x-amz-meta-content-length"), Some("42"));
+    assert_eq!(header("x-amz-meta-content-encoding"), Some("gzip"));
+    assert_eq!(
+      header("x-amz-tagging"),
+      Some("token=team%20a%2Fci&namespace=nx%2Fteam-a&run-id=1234-1&team=platform")
+    );
+
+    let headers = NxCacheStorage::metadata_headers(&metadata, false);
+    assert!(headers.iter().all(|(name, _)| name != "x-amz-tagging"));
+  }
+
+  #[test]
+  fn test_ttl_headers() {
+    let metadata = ObjectMetadata {
+      token: "release".to_string(),
+      uploaded_at: 1_000,
+      ttl_seconds: Some(SECONDS_PER_DAY + 1),
+      ..Default::default()
+    };
+
+    let headers = NxCacheStorage::metadata_headers(&metadata, true);
+    assert!(headers.contains(&("x-amz-meta-expires-at".to_string(), "87401".to_string())));
+    assert!(headers.contains(&(
+      "x-amz-tagging".to_string(),
+      "token=release&namespace=&ttl-days=2".to_string()
+    )));
+  }
+
+  #[test]
+  fn test_part_ranges() {
+    assert_eq!(
+      part_ranges(10, 4).collect::<Vec<_>>(),
+      vec![(0, 4), (4, 4), (8, 2)]
+    );
+    assert_eq!(part_ranges(8, 4).collect::<Vec<_>>(), vec![(0, 4), (4, 4)]);
+    assert_eq!(part_ranges(0, 4).count(), 0);
+  }
+
+  #[test]
+  fn test_tag_value_replaces_unsupported_characters() {
+    assert_eq!(tag_value("ci#1 (main)"), "ci_1 _main_");
+  }
+}
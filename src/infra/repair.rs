@@ -0,0 +1,169 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::domain::storage::DynAsyncRead;
+
+/// Bytes read from a primary copy before it is handed to the client
+///
+/// Spans the first chunk of an encrypted object, so a bad authentication tag and small
+/// corrupted objects are caught while a replica can still be served instead.
+pub const VERIFY_PREFIX_BYTES: u64 = 64 * 1024;
+
+/// Whether a read failed because the stored bytes are corrupted rather than unreachable
+pub fn is_corruption(err: &io::Error) -> bool {
+  err.kind() == io::ErrorKind::InvalidData
+}
+
+/// Read up to [`VERIFY_PREFIX_BYTES`] from an object
+pub async fn read_prefix(reader: &mut DynAsyncRead) -> io::Result<Vec<u8>> {
+  let mut prefix = Vec::new();
+  reader
+    .take(VERIFY_PREFIX_BYTES)
+    .read_to_end(&mut prefix)
+    .await?;
+  Ok(prefix)
+}
+
+/// Counters of corrupted reads and the repairs they triggered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairCounts {
+  /// Reads of a primary copy that turned out to be corrupted
+  pub detected: u64,
+  /// Primary copies replaced from a replica
+  pub repaired: u64,
+  /// Repairs that found no readable replica copy
+  pub failed: u64,
+}
+
+/// Repair bookkeeping shared by all requests
+#[derive(Debug, Default)]
+pub struct Repairs {
+  detected: AtomicU64,
+  repaired: AtomicU64,
+  failed: AtomicU64,
+  in_progress: Mutex<HashSet<String>>,
+}
+
+/// Marks a key as being repaired until dropped
+pub struct RepairGuard {
+  repairs: Arc<Repairs>,
+  key: String,
+}
+
+impl Drop for RepairGuard {
+  fn drop(&mut self) {
+    self
+      .repairs
+      .in_progress
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .remove(&self.key);
+  }
+}
+
+impl Repairs {
+  /// Count a corrupted read and claim its repair, unless one is already running for the key
+  pub fn begin(self: &Arc<Self>, key: &str) -> Option<RepairGuard> {
+    self.detected.fetch_add(1, Ordering::Relaxed);
+    let mut in_progress = self.in_progress.lock().unwrap_or_else(|e| e.into_inner());
+    in_progress.insert(key.to_string()).then(|| RepairGuard {
+      repairs: self.clone(),
+      key: key.to_string(),
+    })
+  }
+
+  pub fn record_repaired(&self) {
+    self.repaired.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn record_failed(&self) {
+    self.failed.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn counts(&self) -> RepairCounts {
+    RepairCounts {
+      detected: self.detected.load(Ordering::Relaxed),
+      repaired: self.repaired.load(Ordering::Relaxed),
+      failed: self.failed.load(Ordering::Relaxed),
+    }
+  }
+}
+
+/// Reader calling a hook the first time the wrapped reader reports corruption
+///
+/// Corruption found after the first bytes reached the client cannot be hidden from it,
+/// but the hook still repairs the stored copy for later reads.
+pub struct CorruptionWatch<R> {
+  inner: R,
+  on_corruption: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<R> CorruptionWatch<R> {
+  pub fn new(inner: R, on_corruption: impl FnOnce() + Send + 'static) -> Self {
+    Self {
+      inner,
+      on_corruption: Some(Box::new(on_corruption)),
+    }
+  }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CorruptionWatch<R> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+    if let Poll::Ready(Err(err)) = &result {
+      if is_corruption(err) {
+        if let Some(on_corruption) = this.on_corruption.take() {
+          on_corruption();
+        }
+      }
+    }
+    result
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::AtomicBool;
+
+  #[test]
+  fn test_concurrent_repairs_of_a_key_are_deduplicated() {
+    let repairs = Arc::new(Repairs::default());
+    let guard = repairs.begin("abc");
+    assert!(guard.is_some());
+    assert!(repairs.begin("abc").is_none());
+    assert!(repairs.begin("def").is_some());
+
+    drop(guard);
+    assert!(repairs.begin("abc").is_some());
+    assert_eq!(repairs.counts().detected, 4);
+  }
+
+  #[tokio::test]
+  async fn test_watch_reports_corruption_once() {
+    let corrupted = tokio_util::io::StreamReader::new(tokio_stream::iter(vec![
+      Ok(bytes::Bytes::from_static(b"ok")),
+      Err(io::Error::new(io::ErrorKind::InvalidData, "bad tag")),
+    ]));
+    let reported = Arc::new(AtomicBool::new(false));
+    let flag = reported.clone();
+    let mut reader = CorruptionWatch::new(corrupted, move || flag.store(true, Ordering::SeqCst));
+
+    let mut data = Vec::new();
+    assert!(reader.read_to_end(&mut data).await.is_err());
+    assert_eq!(data, b"ok");
+    assert!(reported.load(Ordering::SeqCst));
+  }
+}
@@ -0,0 +1,135 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::domain::config::{RetryConfig, RetryableError};
+
+/// Decides whether and when a failed storage request is retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  config: RetryConfig,
+}
+
+impl RetryPolicy {
+  pub fn new(config: RetryConfig) -> Self {
+    Self { config }
+  }
+
+  /// Total attempts including the first one
+  pub fn max_attempts(&self) -> u32 {
+    self.config.max_attempts.max(1)
+  }
+
+  /// Delay before the next attempt, or `None` if the error is final
+  ///
+  /// `attempt` is the 1-based number of the attempt that just failed.
+  pub fn retry_after(&self, error_message: &str, attempt: u32) -> Option<Duration> {
+    if attempt >= self.max_attempts() {
+      return None;
+    }
+    let class = classify(error_message)?;
+    if !self.config.retry_on.contains(&class) {
+      return None;
+    }
+    Some(self.backoff(attempt, jitter_fraction()))
+  }
+
+  fn backoff(&self, attempt: u32, random: f64) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(32) as i32;
+    let base = (self.config.initial_backoff_ms as f64
+      * self.config.backoff_multiplier.powi(exponent))
+    .min(self.config.max_backoff_ms as f64);
+    let jitter = base * self.config.jitter * random;
+    Duration::from_millis((base + jitter) as u64)
+  }
+}
+
+/// Classify a storage error message into a retryable error class
+pub fn classify(error_message: &str) -> Option<RetryableError> {
+  let message = error_message.to_ascii_lowercase();
+  if message.contains("slowdown")
+    || message.contains("throttl")
+    || message.contains("too many requests")
+    || message.contains("requestlimitexceeded")
+  {
+    Some(RetryableError::Throttling)
+  } else if message.contains("timed out") || message.contains("timeout") {
+    Some(RetryableError::Timeout)
+  } else if message.contains("incompletemessage")
+    || message.contains("connection reset")
+    || message.contains("connection closed")
+    || message.contains("connection refused")
+    || message.contains("broken pipe")
+    || message.contains("sendrequest")
+  {
+    Some(RetryableError::Connection)
+  } else if message.contains("internalerror")
+    || message.contains("internal server error")
+    || message.contains("serviceunavailable")
+    || message.contains("service unavailable")
+    || message.contains("bad gateway")
+    || message.contains("gateway timeout")
+  {
+    Some(RetryableError::Server)
+  } else {
+    None
+  }
+}
+
+/// Pseudo-random value in [0, 1) from the clock; good enough to spread out retries
+fn jitter_fraction() -> f64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| (duration.subsec_nanos() % 1000) as f64 / 1000.0)
+    .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn policy() -> RetryPolicy {
+    RetryPolicy::new(RetryConfig {
+      max_attempts: 4,
+      initial_backoff_ms: 100,
+      max_backoff_ms: 500,
+      backoff_multiplier: 3.0,
+      jitter: 0.5,
+      retry_on: vec![RetryableError::Connection, RetryableError::Server],
+    })
+  }
+
+  #[test]
+  fn test_backoff_grows_and_is_capped() {
+    let policy = policy();
+    assert_eq!(policy.backoff(1, 0.0), Duration::from_millis(100));
+    assert_eq!(policy.backoff(2, 0.0), Duration::from_millis(300));
+    assert_eq!(policy.backoff(3, 0.0), Duration::from_millis(500));
+    assert_eq!(policy.backoff(1, 1.0), Duration::from_millis(150));
+  }
+
+  #[test]
+  fn test_retry_after_respects_classes_and_attempts() {
+    let policy = policy();
+    assert!(policy.retry_after("connection reset by peer", 1).is_some());
+    assert!(policy
+      .retry_after("S3 error: ServiceUnavailable", 3)
+      .is_some());
+    assert!(policy
+      .retry_after("S3 error: ServiceUnavailable", 4)
+      .is_none());
+    // Timeouts are not in retry_on for this policy
+    assert!(policy.retry_after("operation timed out", 1).is_none());
+    assert!(policy.retry_after("NoSuchKey", 1).is_none());
+  }
+
+  #[test]
+  fn test_classify() {
+    assert_eq!(classify("SlowDown"), Some(RetryableError::Throttling));
+    assert_eq!(classify("request timed out"), Some(RetryableError::Timeout));
+    assert_eq!(
+      classify("hyper::Error(IncompleteMessage)"),
+      Some(RetryableError::Connection)
+    );
+    assert_eq!(classify("InternalError"), Some(RetryableError::Server));
+    assert_eq!(classify("AccessDenied"), None);
+  }
+}
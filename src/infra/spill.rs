@@ -0,0 +1,220 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::domain::config::SpillConfig;
+use crate::domain::storage::{boxed_reader_stream, DynAsyncRead, StorageError};
+
+static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Decides which upload bodies are written to disk before they are sent to a bucket
+///
+/// The S3 client buffers a whole part in memory, and a body of unknown length is split into
+/// the largest parts. Spilling such bodies first bounds memory under concurrent large uploads.
+#[derive(Debug, Clone)]
+pub struct Spill {
+  threshold_bytes: u64,
+  dir: PathBuf,
+}
+
+impl Spill {
+  pub fn new(config: &SpillConfig) -> Self {
+    let dir = config
+      .path
+      .as_deref()
+      .map(PathBuf::from)
+      .unwrap_or_else(std::env::temp_dir);
+    Self {
+      threshold_bytes: config.threshold_bytes,
+      dir,
+    }
+  }
+
+  /// Whether a body with the declared length should be spilled
+  pub fn applies_to(&self, content_length: Option<u64>) -> bool {
+    content_length.is_none_or(|length| length > self.threshold_bytes)
+  }
+
+  /// Determine the size of a body of unknown length, e.g. from a chunked upload
+  ///
+  /// The body is held in memory up to the threshold and spilled to disk once it grows past it.
+  pub async fn sized(
+    &self,
+    mut data: ReaderStream<DynAsyncRead>,
+  ) -> Result<SizedBody, StorageError> {
+    let mut buffered = Vec::new();
+    while let Some(chunk) = data.next().await {
+      let chunk = chunk.map_err(|e| {
+        tracing::error!("Failed to read upload body: {}", e);
+        StorageError::OperationFailed
+      })?;
+      buffered.extend_from_slice(&chunk);
+      if buffered.len() as u64 > self.threshold_bytes {
+        let rest = Cursor::new(buffered).chain(StreamReader::new(data));
+        let spilled = self.write(boxed_reader_stream(rest)).await?;
+        return Ok(SizedBody {
+          data: spilled.open().await?,
+          size: spilled.size(),
+          spilled: Some(spilled),
+        });
+      }
+    }
+    Ok(SizedBody {
+      size: buffered.len() as u64,
+      data: boxed_reader_stream(Cursor::new(buffered)),
+      spilled: None,
+    })
+  }
+
+  /// Write a body to a new temporary file
+  pub async fn write(
+    &self,
+    mut data: ReaderStream<DynAsyncRead>,
+  ) -> Result<SpillFile, StorageError> {
+    tokio::fs::create_dir_all(&self.dir).await.map_err(|e| {
+      tracing::error!(
+        "Failed to create spill directory '{}': {}",
+        self.dir.display(),
+        e
+      );
+      StorageError::OperationFailed
+    })?;
+
+    // Created before writing so a failed write still removes the file on drop
+    let mut spilled = SpillFile {
+      path: self.dir.join(format!(
+        "nx-cache-spill-{}-{}",
+        std::process::id(),
+        SPILL_COUNTER.fetch_add(1, Ordering::Relaxed)
+      )),
+      size: 0,
+    };
+    let result: std::io::Result<()> = async {
+      let mut file = File::create(&spilled.path).await?;
+      while let Some(chunk) = data.next().await {
+        let chunk = chunk?;
+        spilled.size += chunk.len() as u64;
+        file.write_all(&chunk).await?;
+      }
+      file.flush().await
+    }
+    .await;
+
+    result.map_err(|e| {
+      tracing::error!(
+        "Failed to spill upload to '{}': {}",
+        spilled.path.display(),
+        e
+      );
+      StorageError::OperationFailed
+    })?;
+    Ok(spilled)
+  }
+}
+
+/// A spilled upload body, removed from disk when dropped
+#[derive(Debug)]
+pub struct SpillFile {
+  path: PathBuf,
+  size: u64,
+}
+
+impl SpillFile {
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  /// Stream the spilled body back from disk
+  pub async fn open(&self) -> Result<ReaderStream<DynAsyncRead>, StorageError> {
+    let file = File::open(&self.path).await.map_err(|e| {
+      tracing::error!(
+        "Failed to open spilled upload '{}': {}",
+        self.path.display(),
+        e
+      );
+      StorageError::OperationFailed
+    })?;
+    Ok(boxed_reader_stream(file))
+  }
+}
+
+/// A body whose size is known, read from memory or from a spilled file
+pub struct SizedBody {
+  pub data: ReaderStream<DynAsyncRead>,
+  pub size: u64,
+  /// Must outlive the upload of `data`; the file is removed when dropped
+  pub spilled: Option<SpillFile>,
+}
+
+impl Drop for SpillFile {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_file(&self.path);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn spill(dir: &std::path::Path) -> Spill {
+    Spill::new(&SpillConfig {
+      threshold_bytes: 4,
+      path: Some(dir.display().to_string()),
+    })
+  }
+
+  #[test]
+  fn test_applies_above_threshold_or_unknown_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let spill = spill(dir.path());
+
+    assert!(!spill.applies_to(Some(4)));
+    assert!(spill.applies_to(Some(5)));
+    assert!(spill.applies_to(None));
+  }
+
+  #[tokio::test]
+  async fn test_spilled_file_is_removed_on_drop() {
+    let dir = tempfile::tempdir().unwrap();
+    let spill = spill(dir.path());
+
+    let data = boxed_reader_stream(std::io::Cursor::new(b"hello world".to_vec()));
+    let spilled = spill.write(data).await.unwrap();
+    assert_eq!(spilled.size(), 11);
+
+    let mut body = Vec::new();
+    let mut stream = spilled.open().await.unwrap();
+    while let Some(chunk) = stream.next().await {
+      body.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(body, b"hello world");
+
+    drop(spilled);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+  }
+
+  #[tokio::test]
+  async fn test_sized_buffers_up_to_threshold() {
+    let dir = tempfile::tempdir().unwrap();
+    let spill = spill(dir.path());
+
+    let data = boxed_reader_stream(std::io::Cursor::new(b"tiny".to_vec()));
+    let body = spill.sized(data).await.unwrap();
+    assert_eq!(body.size, 4);
+    assert!(body.spilled.is_none());
+
+    let data = boxed_reader_stream(std::io::Cursor::new(b"hello world".to_vec()));
+    let mut body = spill.sized(data).await.unwrap();
+    assert_eq!(body.size, 11);
+    assert!(body.spilled.is_some());
+    let mut read = Vec::new();
+    while let Some(chunk) = body.data.next().await {
+      read.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(read, b"hello world");
+  }
+}
@@ -0,0 +1,243 @@
+use crate::server::egress::{current_day, format_day};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Number of UTC days of usage kept for export
+pub const ACCOUNTING_RETENTION_DAYS: u64 = 90;
+
+/// Counters for one token on one UTC day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DailyUsage {
+  /// Download requests, including misses and requests rejected by the egress limit
+  pub requests: u64,
+  /// Download requests that found the object
+  pub hits: u64,
+  /// Bytes served
+  pub bytes: u64,
+  /// Uploads stored
+  pub uploads: u64,
+  /// Bytes received with stored uploads
+  pub bytes_received: u64,
+  /// Requests rejected because the token had expired
+  pub expired_rejections: u64,
+}
+
+/// One exported accounting row
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct UsageRow {
+  /// UTC date formatted as YYYY-MM-DD
+  pub date: String,
+  pub token: String,
+  pub requests: u64,
+  pub hits: u64,
+  pub bytes: u64,
+}
+
+/// Usage of one token summed over the retained days
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotals {
+  /// First UTC day with recorded usage, formatted as YYYY-MM-DD
+  pub since: Option<String>,
+  pub requests: u64,
+  pub hits: u64,
+  /// Share of download requests that found the object, 0 without requests
+  pub hit_rate: f64,
+  pub bytes_served: u64,
+  pub uploads: u64,
+  pub bytes_received: u64,
+  pub expired_rejections: u64,
+}
+
+/// Export formats for accounting data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+  Csv,
+  Json,
+}
+
+impl ExportFormat {
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "csv" => Some(Self::Csv),
+      "json" => Some(Self::Json),
+      _ => None,
+    }
+  }
+}
+
+/// Per token, per UTC day usage accounting kept in memory for export
+#[derive(Debug, Default)]
+pub struct UsageAccounting {
+  usage: Mutex<BTreeMap<(u64, String), DailyUsage>>,
+}
+
+impl UsageAccounting {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Count a download request, `hit` being whether the object was found
+  pub fn record_request(&self, token_name: &str, hit: bool) {
+    self.update(token_name, current_day(), |usage| {
+      usage.requests += 1;
+      usage.hits += u64::from(hit);
+    });
+  }
+
+  /// Count bytes served to the given token
+  pub fn record_bytes(&self, token_name: &str, bytes: u64) {
+    self.update(token_name, current_day(), |usage| usage.bytes += bytes);
+  }
+
+  /// Count a stored upload of `bytes` bytes
+  pub fn record_upload(&self, token_name: &str, bytes: u64) {
+    self.update(token_name, current_day(), |usage| {
+      usage.uploads += 1;
+      usage.bytes_received += bytes;
+    });
+  }
+
+  /// Count a request rejected because the token had expired
+  pub fn record_expired(&self, token_name: &str) {
+    self.update(token_name, current_day(), |usage| {
+      usage.expired_rejections += 1
+    });
+  }
+
+  fn update(&self, token_name: &str, day: u64, apply: impl FnOnce(&mut DailyUsage)) {
+    let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+    apply(usage.entry((day, token_name.to_string())).or_default());
+
+    let oldest = day.saturating_sub(ACCOUNTING_RETENTION_DAYS - 1);
+    usage.retain(|(d, _), _| *d >= oldest);
+  }
+
+  /// Rows ordered by date then token, optionally limited to a single token
+  pub fn rows(&self, token_name: Option<&str>) -> Vec<UsageRow> {
+    let usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+    usage
+      .iter()
+      .filter(|((_, token), _)| token_name.is_none_or(|name| name == token))
+      .map(|((day, token), usage)| UsageRow {
+        date: format_day(*day),
+        token: token.clone(),
+        requests: usage.requests,
+        hits: usage.hits,
+        bytes: usage.bytes,
+      })
+      .collect()
+  }
+
+  /// Usage of a token summed over the retained days
+  pub fn totals(&self, token_name: &str) -> UsageTotals {
+    let usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+    let mut totals = UsageTotals::default();
+    for ((day, _), usage) in usage.iter().filter(|((_, token), _)| token == token_name) {
+      totals.since.get_or_insert_with(|| format_day(*day));
+      totals.requests += usage.requests;
+      totals.hits += usage.hits;
+      totals.bytes_served += usage.bytes;
+      totals.uploads += usage.uploads;
+      totals.bytes_received += usage.bytes_received;
+      totals.expired_rejections += usage.expired_rejections;
+    }
+    if totals.requests > 0 {
+      totals.hit_rate = totals.hits as f64 / totals.requests as f64;
+    }
+    totals
+  }
+}
+
+/// Render rows as CSV with a `date,token,requests,hits,bytes` header
+pub fn to_csv(rows: &[UsageRow]) -> String {
+  let mut csv = String::from("date,token,requests,hits,bytes\n");
+  for row in rows {
+    csv.push_str(&format!(
+      "{},{},{},{},{}\n",
+      row.date,
+      csv_field(&row.token),
+      row.requests,
+      row.hits,
+      row.bytes
+    ));
+  }
+  csv
+}
+
+/// Quote a field when it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+  if value.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_counters_and_retention() {
+    let accounting = UsageAccounting::new();
+    accounting.update("ci", 10, |u| u.requests += 1);
+    accounting.update("ci", 11, |u| u.hits += 1);
+    accounting.update("dev", 11, |u| u.bytes += 5);
+
+    assert_eq!(accounting.rows(None).len(), 3);
+    assert_eq!(accounting.rows(Some("dev")).len(), 1);
+
+    accounting.update("ci", 10 + ACCOUNTING_RETENTION_DAYS, |u| u.requests += 1);
+    let rows = accounting.rows(Some("ci"));
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].date, format_day(11));
+  }
+
+  #[test]
+  fn test_totals() {
+    let accounting = UsageAccounting::new();
+    accounting.update("ci", 10, |u| {
+      u.requests += 3;
+      u.hits += 1;
+    });
+    accounting.update("ci", 11, |u| {
+      u.requests += 1;
+      u.hits += 1;
+      u.bytes += 7;
+    });
+    accounting.update("dev", 11, |u| u.requests += 5);
+    accounting.update("ci", 11, |u| {
+      u.uploads += 1;
+      u.bytes_received += 20;
+    });
+    accounting.update("ci", 11, |u| u.expired_rejections += 1);
+
+    let totals = accounting.totals("ci");
+    assert_eq!(totals.since.as_deref(), Some(format_day(10).as_str()));
+    assert_eq!(totals.requests, 4);
+    assert_eq!(totals.hits, 2);
+    assert_eq!(totals.hit_rate, 0.5);
+    assert_eq!(totals.bytes_served, 7);
+    assert_eq!(totals.uploads, 1);
+    assert_eq!(totals.bytes_received, 20);
+    assert_eq!(totals.expired_rejections, 1);
+    assert_eq!(accounting.totals("unused"), UsageTotals::default());
+  }
+
+  #[test]
+  fn test_csv_quotes_token_names() {
+    let rows = vec![UsageRow {
+      date: "2024-01-01".to_string(),
+      token: "team \"a\", ci".to_string(),
+      requests: 3,
+      hits: 2,
+      bytes: 100,
+    }];
+    assert_eq!(
+      to_csv(&rows),
+      "date,token,requests,hits,bytes\n2024-01-01,\"team \"\"a\"\", ci\",3,2,100\n"
+    );
+  }
+}
@@ -0,0 +1,160 @@
+use crate::domain::config::AffinityConfig;
+use crate::server::{error::ServerError, AppState};
+use axum::{
+  extract::{Path, Request, State},
+  http::HeaderValue,
+  middleware::Next,
+  response::Response,
+  routing::get,
+  Json, Router,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Response header naming the replica whose local disk tier should hold a hash
+pub const AFFINITY_HEADER: &str = "x-nx-cache-affinity";
+
+/// Rendezvous (highest random weight) hashing over the configured replicas
+///
+/// Every replica ranks the peers the same way for a hash, so a load balancer or a syncing
+/// client following the hints sends all requests for that hash to the same replica.
+/// Adding or removing a peer only moves the hashes that peer owns.
+#[derive(Debug, Clone)]
+pub struct Affinity {
+  peers: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AffinityHint {
+  pub hash: String,
+  /// Replica that should serve the hash
+  pub owner: String,
+  /// All replicas, most preferred first, for falling back when the owner is down
+  pub peers: Vec<String>,
+}
+
+impl Affinity {
+  pub fn new(config: &AffinityConfig) -> Self {
+    let peers = config
+      .peers
+      .iter()
+      .map(|peer| peer.trim().trim_end_matches('/').to_string())
+      .collect();
+    Self { peers }
+  }
+
+  fn score(peer: &str, hash: &str) -> u64 {
+    let digest = Sha256::new()
+      .chain_update(peer.as_bytes())
+      .chain_update(b"\n")
+      .chain_update(hash.as_bytes())
+      .finalize();
+    let mut score = [0u8; 8];
+    score.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(score)
+  }
+
+  /// Peers ordered by preference for a hash
+  pub fn rank(&self, hash: &str) -> Vec<&str> {
+    let mut ranked: Vec<(u64, &str)> = self
+      .peers
+      .iter()
+      .map(|peer| (Self::score(peer, hash), peer.as_str()))
+      .collect();
+    ranked.sort_by(|a, b| b.cmp(a));
+    ranked.into_iter().map(|(_, peer)| peer).collect()
+  }
+
+  /// Preferred peer for a hash
+  pub fn owner(&self, hash: &str) -> Option<&str> {
+    self
+      .peers
+      .iter()
+      .max_by_key(|peer| (Self::score(peer, hash), peer.as_str()))
+      .map(String::as_str)
+  }
+}
+
+/// Token-protected route returning the affinity hint for a hash (`GET /v1/affinity/{hash}`)
+pub fn affinity_routes() -> Router<AppState> {
+  Router::new().route("/v1/affinity/{hash}", get(affinity_hint))
+}
+
+pub async fn affinity_hint(
+  Path(hash): Path<String>,
+  State(state): State<AppState>,
+) -> Result<Json<AffinityHint>, ServerError> {
+  state.hash_policies.nx.validate(&hash)?;
+  let affinity = state.affinity.as_ref().ok_or(ServerError::BadRequest)?;
+
+  let peers: Vec<String> = affinity
+    .rank(&hash)
+    .into_iter()
+    .map(str::to_string)
+    .collect();
+  let owner = peers.first().cloned().ok_or(ServerError::BadRequest)?;
+  Ok(Json(AffinityHint { hash, owner, peers }))
+}
+
+/// Add the [`AFFINITY_HEADER`] to responses for `/v1/cache/{hash}`
+pub async fn affinity_middleware(
+  State(state): State<AppState>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let hash = request
+    .uri()
+    .path()
+    .strip_prefix("/v1/cache/")
+    .map(|rest| rest.split('/').next().unwrap_or_default().to_string());
+
+  let mut response = next.run(request).await;
+  if let (Some(affinity), Some(hash)) = (&state.affinity, hash) {
+    let owner = affinity
+      .owner(&hash)
+      .and_then(|owner| HeaderValue::from_str(owner).ok());
+    if let Some(owner) = owner {
+      response.headers_mut().insert(AFFINITY_HEADER, owner);
+    }
+  }
+  response
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn affinity(peers: &[&str]) -> Affinity {
+    Affinity::new(&AffinityConfig {
+      peers: peers.iter().map(|peer| peer.to_string()).collect(),
+    })
+  }
+
+  #[test]
+  fn test_owner_is_first_ranked_peer() {
+    let affinity = affinity(&["http://a:3000", "http://b:3000/", "http://c:3000"]);
+    for hash in ["abc", "def", "0123456789"] {
+      let ranked = affinity.rank(hash);
+      assert_eq!(ranked.len(), 3);
+      assert_eq!(affinity.owner(hash), Some(ranked[0]));
+    }
+    assert!(affinity.rank("abc").contains(&"http://b:3000"));
+  }
+
+  #[test]
+  fn test_removing_a_peer_only_moves_its_hashes() {
+    let all = affinity(&["http://a:3000", "http://b:3000", "http://c:3000"]);
+    let without_c = affinity(&["http://a:3000", "http://b:3000"]);
+
+    let hashes: Vec<String> = (0..200).map(|i| format!("hash{}", i)).collect();
+    for hash in &hashes {
+      let owner = all.owner(hash).unwrap();
+      if owner != "http://c:3000" {
+        assert_eq!(without_c.owner(hash), Some(owner));
+      }
+    }
+    assert!(hashes
+      .iter()
+      .any(|hash| all.owner(hash) == Some("http://c:3000")));
+  }
+}
@@ -1,7 +1,196 @@
+use crate::domain::config::{
+  AffinityConfig, CacheControlConfig, MirrorConfig, ResolvedJwtConfig, ResolvedManifestConfig,
+  ResolvedTokenMintingConfig, TaskMetadataConfig, TransportCompressionConfig, TusConfig,
+};
 use crate::infra::multi_storage::MultiStorageRouter;
+use crate::server::accounting::UsageAccounting;
+use crate::server::affinity::Affinity;
+use crate::server::egress::EgressTracker;
+use crate::server::hashed_tokens::HashedTokens;
+use crate::server::in_flight::InFlightUploads;
+use crate::server::jwt::JwtAuth;
+use crate::server::manifest::ManifestSigner;
+use crate::server::mirror::Mirror;
+use crate::server::recent_errors::RecentErrors;
+use crate::server::revoked_tokens::RevokedTokens;
+use crate::server::tus::TusUploads;
+use crate::server::validation::HashPolicies;
+use axum::http::HeaderValue;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct AppState {
   pub storage: Arc<MultiStorageRouter>,
+  pub egress: Arc<EgressTracker>,
+  pub accounting: Arc<UsageAccounting>,
+  pub uploads: Arc<InFlightUploads>,
+  pub hashed_tokens: Arc<HashedTokens>,
+  pub errors: Arc<RecentErrors>,
+  pub mirror: Option<Arc<Mirror>>,
+  pub manifest: Option<Arc<ManifestSigner>>,
+  pub affinity: Option<Arc<Affinity>>,
+  pub task_metadata: Option<Arc<TaskMetadataConfig>>,
+  pub transport_compression: Option<Arc<TransportCompressionConfig>>,
+  pub tus: Option<Arc<TusUploads>>,
+  pub jwt: Option<Arc<JwtAuth>>,
+  pub token_minting: Option<Arc<ResolvedTokenMintingConfig>>,
+  pub revoked_tokens: Option<Arc<RevokedTokens>>,
+  pub hash_policies: Arc<HashPolicies>,
+  /// Answer the Nx cache endpoints only as the Nx remote cache spec describes them
+  pub strict_nx_spec: bool,
+  /// Answer errors as JSON with machine-readable codes
+  pub json_errors: bool,
+  /// Accept zero-byte uploads to the Nx cache endpoint
+  pub allow_empty_uploads: bool,
+  /// Prefix every route is mounted under
+  pub base_path: Option<String>,
+  /// `Cache-Control` sent with artifact downloads, none when disabled
+  pub cache_control: Option<HeaderValue>,
+}
+
+impl AppState {
+  /// Create application state around a storage router with fresh runtime trackers
+  pub fn new(storage: MultiStorageRouter) -> Self {
+    Self {
+      storage: Arc::new(storage),
+      egress: Arc::new(EgressTracker::new()),
+      accounting: Arc::new(UsageAccounting::new()),
+      uploads: Arc::new(InFlightUploads::default()),
+      hashed_tokens: Arc::new(HashedTokens::default()),
+      errors: RecentErrors::global().clone(),
+      mirror: None,
+      manifest: None,
+      affinity: None,
+      task_metadata: None,
+      transport_compression: None,
+      tus: None,
+      jwt: None,
+      token_minting: None,
+      revoked_tokens: None,
+      hash_policies: Arc::new(HashPolicies::default()),
+      strict_nx_spec: false,
+      json_errors: false,
+      allow_empty_uploads: false,
+      base_path: None,
+      cache_control: cache_control_value(&CacheControlConfig::default()),
+    }
+  }
+
+  /// Serve from another storage router, keeping the runtime trackers
+  pub fn with_storage(mut self, storage: MultiStorageRouter) -> Self {
+    self.storage = Arc::new(storage);
+    self
+  }
+
+  /// Let downloads wait up to `wait` for an in-flight upload of the same object
+  pub fn with_in_flight_wait(mut self, wait: Duration) -> Self {
+    self.uploads = Arc::new(InFlightUploads::new(wait));
+    self
+  }
+
+  /// Enable the read-only compiler cache mirror
+  pub fn with_mirror(mut self, config: MirrorConfig) -> Self {
+    self.mirror = Some(Arc::new(Mirror::new(config)));
+    self
+  }
+
+  /// Enable bulk download manifests with server-signed URLs
+  pub fn with_manifest(mut self, config: &ResolvedManifestConfig) -> Self {
+    self.manifest = Some(Arc::new(ManifestSigner::new(config)));
+    self
+  }
+
+  /// Enable rendezvous hashing hints across the given replicas
+  pub fn with_affinity(mut self, config: &AffinityConfig) -> Self {
+    self.affinity = Some(Arc::new(Affinity::new(config)));
+    self
+  }
+
+  /// Accept task metadata alongside artifacts and serve it to admin queries
+  pub fn with_task_metadata(mut self, config: TaskMetadataConfig) -> Self {
+    self.task_metadata = Some(Arc::new(config));
+    self
+  }
+
+  /// Compress downloads on the fly for clients that accept it
+  pub fn with_transport_compression(mut self, config: TransportCompressionConfig) -> Self {
+    self.transport_compression = Some(Arc::new(config));
+    self
+  }
+
+  /// Enable tus resumable uploads at `/v1/tus`
+  pub fn with_tus(mut self, config: &TusConfig) -> Self {
+    self.tus = Some(Arc::new(TusUploads::new(config)));
+    self
+  }
+
+  /// Accept JWT bearer tokens standing for the service tokens their claims map to
+  pub fn with_jwt(mut self, providers: &[ResolvedJwtConfig]) -> Self {
+    self.jwt = Some(Arc::new(JwtAuth::new(providers)));
+    self
+  }
+
+  /// Mint short-lived derived tokens at `/admin/tokens/{name}/mint`
+  ///
+  /// Minted tokens are only accepted if the JWT verifiers include the minting provider.
+  pub fn with_token_minting(mut self, config: &ResolvedTokenMintingConfig) -> Self {
+    self.token_minting = Some(Arc::new(config.clone()));
+    self
+  }
+
+  /// Reject the tokens in `revoked` and revoke more at `/admin/tokens/{name}/revoke`
+  pub fn with_revoked_tokens(mut self, revoked: RevokedTokens) -> Self {
+    self.revoked_tokens = Some(Arc::new(revoked));
+    self
+  }
+
+  /// Validate keys with the given per-surface policies instead of the built-in rules
+  pub fn with_hash_policies(mut self, policies: HashPolicies) -> Self {
+    self.hash_policies = Arc::new(policies);
+    self
+  }
+
+  /// Fold the Nx cache endpoints' statuses and error bodies into the Nx remote cache spec
+  pub fn with_strict_nx_spec(mut self) -> Self {
+    self.strict_nx_spec = true;
+    self
+  }
+
+  /// Answer errors as `{"error": {"code": ..., "message": ...}}` instead of plain text
+  pub fn with_json_errors(mut self) -> Self {
+    self.json_errors = true;
+    self
+  }
+
+  /// Store zero-byte uploads instead of rejecting them
+  pub fn with_empty_uploads(mut self) -> Self {
+    self.allow_empty_uploads = true;
+    self
+  }
+
+  /// Mount every route under `base_path` (e.g. `/nx-cache`)
+  pub fn with_base_path(mut self, base_path: impl Into<String>) -> Self {
+    self.base_path = Some(base_path.into());
+    self
+  }
+
+  /// Send the configured `Cache-Control` with artifact downloads
+  pub fn with_cache_control(mut self, config: &CacheControlConfig) -> Self {
+    self.cache_control = cache_control_value(config);
+    self
+  }
+}
+
+/// Header value for the configured `Cache-Control`, `None` for a max age of 0
+fn cache_control_value(config: &CacheControlConfig) -> Option<HeaderValue> {
+  if config.max_age_seconds == 0 {
+    return None;
+  }
+  let visibility = if config.public { "public" } else { "private" };
+  HeaderValue::from_str(&format!(
+    "{}, max-age={}, immutable",
+    visibility, config.max_age_seconds
+  ))
+  .ok()
 }
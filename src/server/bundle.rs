@@ -0,0 +1,677 @@
+use crate::domain::config::SpillConfig;
+use crate::domain::storage::{boxed_reader_stream, DynAsyncRead, StorageError};
+use crate::infra::multi_storage::UploadOptions;
+use crate::infra::spill::Spill;
+use crate::server::{error::ServerError, handlers, middleware::AuthenticatedToken, AppState};
+use axum::{
+  body::{Body, Bytes},
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  routing::post,
+  Extension, Json, Router,
+};
+use futures_util::{stream, stream::FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Most hashes a single bundle may request
+pub const MAX_BUNDLE_HASHES: usize = 1000;
+
+/// Tar archives are written in blocks of this many bytes
+const BLOCK_SIZE: usize = 512;
+
+/// Largest size the 11 octal digits of a ustar header can hold; larger entries need PAX
+const MAX_USTAR_SIZE: u64 = 0o77777777777;
+
+/// Chunks buffered between the task reading artifacts and the response body
+const BUNDLE_CHANNEL_CAPACITY: usize = 8;
+
+#[derive(Debug, Deserialize)]
+pub struct BundleRequest {
+  pub hashes: Vec<String>,
+}
+
+/// Token-protected routes moving several artifacts as one tar: `POST /v1/cache/bundle`
+/// downloads, `PUT /v1/cache/bundle` uploads
+pub fn bundle_routes() -> Router<AppState> {
+  Router::new().route("/v1/cache/bundle", post(download_bundle).put(upload_bundle))
+}
+
+/// Write `value` as zero-padded octal followed by a NUL, filling `field`
+fn write_octal(field: &mut [u8], value: u64) {
+  let width = field.len() - 1;
+  let digits = format!("{:0width$o}", value);
+  field[..width].copy_from_slice(&digits.as_bytes()[digits.len() - width..]);
+  field[width] = 0;
+}
+
+/// A single 512-byte ustar header block
+fn ustar_block(name: &str, size: u64, mtime: u64, typeflag: u8) -> [u8; BLOCK_SIZE] {
+  let mut block = [0u8; BLOCK_SIZE];
+  let name = name.as_bytes();
+  let name_len = name.len().min(100);
+  block[..name_len].copy_from_slice(&name[..name_len]);
+  write_octal(&mut block[100..108], 0o644);
+  write_octal(&mut block[108..116], 0);
+  write_octal(&mut block[116..124], 0);
+  write_octal(&mut block[124..136], size.min(MAX_USTAR_SIZE));
+  write_octal(&mut block[136..148], mtime.min(MAX_USTAR_SIZE));
+  block[156] = typeflag;
+  block[257..263].copy_from_slice(b"ustar\0");
+  block[263..265].copy_from_slice(b"00");
+
+  // The checksum is computed with its own field filled with spaces
+  block[148..156].fill(b' ');
+  let checksum: u32 = block.iter().map(|&byte| u32::from(byte)).sum();
+  write_octal(&mut block[148..155], u64::from(checksum));
+  block
+}
+
+/// A PAX record, `"<length> <key>=<value>\n"` where the length counts the whole record
+fn pax_record(key: &str, value: &str) -> String {
+  let body = format!(" {}={}\n", key, value);
+  let mut length = body.len();
+  while length != body.len() + length.to_string().len() {
+    length = body.len() + length.to_string().len();
+  }
+  format!("{}{}", length, body)
+}
+
+/// Zero bytes that pad an entry of `size` bytes to a whole block
+fn padding(size: u64) -> usize {
+  (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE
+}
+
+/// Header blocks of a regular file entry
+///
+/// Names longer than 100 bytes and sizes beyond 8 GiB don't fit a ustar header and are
+/// carried by a preceding PAX extended header.
+fn entry_header(name: &str, size: u64, mtime: u64) -> Vec<u8> {
+  let mut records = String::new();
+  if name.len() > 100 {
+    records.push_str(&pax_record("path", name));
+  }
+  if size > MAX_USTAR_SIZE {
+    records.push_str(&pax_record("size", &size.to_string()));
+  }
+
+  let mut header = Vec::with_capacity(3 * BLOCK_SIZE);
+  if !records.is_empty() {
+    let pax_size = records.len() as u64;
+    header.extend_from_slice(&ustar_block("././@PaxHeader", pax_size, mtime, b'x'));
+    header.extend_from_slice(records.as_bytes());
+    header.resize(header.len() + padding(pax_size), 0);
+  }
+  header.extend_from_slice(&ustar_block(name, size, mtime, b'0'));
+  header
+}
+
+/// Send a chunk of the archive; fails once the client has gone away
+async fn send(sender: &mpsc::Sender<io::Result<Bytes>>, chunk: impl Into<Bytes>) -> Result<(), ()> {
+  sender.send(Ok(chunk.into())).await.map_err(|_| ())
+}
+
+/// Stream the requested artifacts as a tar, one `<hash>` entry each
+///
+/// Hashes that are not in the cache are left out of the archive. A read failing midway
+/// aborts the response, since the entry's size has already been sent.
+pub async fn download_bundle(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Json(request): Json<BundleRequest>,
+) -> Result<Response, ServerError> {
+  if request.hashes.is_empty() || request.hashes.len() > MAX_BUNDLE_HASHES {
+    return Err(ServerError::BadRequest);
+  }
+  for hash in &request.hashes {
+    state.hash_policies.nx.validate(hash)?;
+  }
+  handlers::require_read(&state, &token)?;
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  let token_name = service.name.clone();
+  if state
+    .egress
+    .is_over_limit(&token_name, service.egress_daily_limit_bytes)
+  {
+    tracing::warn!("Daily egress limit exceeded for token: {}", token_name);
+    state.accounting.record_request(&token_name, false);
+    return Err(ServerError::EgressLimitExceeded);
+  }
+
+  let mut seen = HashSet::new();
+  let hashes: Vec<String> = request
+    .hashes
+    .into_iter()
+    .filter(|hash| seen.insert(hash.clone()))
+    .collect();
+
+  let (sender, receiver) = mpsc::channel(BUNDLE_CHANNEL_CAPACITY);
+  tokio::spawn(async move {
+    for hash in hashes {
+      let (reader, stat) = tokio::join!(
+        state.storage.retrieve_with_token(&token.0, &hash),
+        state.storage.stat_with_token(&token.0, &hash),
+      );
+      state.accounting.record_request(&token_name, reader.is_ok());
+      let reader = match reader {
+        Ok(reader) => reader,
+        Err(StorageError::NotFound) => continue,
+        Err(err) => {
+          tracing::warn!("Bundle read failed for {}: {}", hash, err);
+          continue;
+        },
+      };
+      let stat = stat.ok();
+      let mtime = stat
+        .as_ref()
+        .and_then(|stat| stat.last_modified)
+        .unwrap_or(0);
+
+      // The header needs the size up front; objects without a known size are measured first
+      let (data, size, _spilled) = match stat.and_then(|stat| stat.size) {
+        Some(size) => (reader, size, None),
+        None => match Spill::new(&SpillConfig::default())
+          .sized(boxed_reader_stream(reader))
+          .await
+        {
+          Ok(body) => (
+            Box::new(StreamReader::new(body.data)) as DynAsyncRead,
+            body.size,
+            body.spilled,
+          ),
+          Err(err) => {
+            tracing::warn!("Bundle read failed for {}: {}", hash, err);
+            continue;
+          },
+        },
+      };
+
+      if send(&sender, entry_header(&hash, size, mtime))
+        .await
+        .is_err()
+      {
+        return;
+      }
+      let mut data = ReaderStream::new(data.take(size));
+      let mut sent = 0u64;
+      while let Some(chunk) = data.next().await {
+        let chunk = match chunk {
+          Ok(chunk) => chunk,
+          Err(err) => {
+            tracing::error!("Bundle read of {} failed midway: {}", hash, err);
+            let _ = sender.send(Err(err)).await;
+            return;
+          },
+        };
+        sent += chunk.len() as u64;
+        state.egress.record(&token_name, chunk.len() as u64);
+        state
+          .accounting
+          .record_bytes(&token_name, chunk.len() as u64);
+        if send(&sender, chunk).await.is_err() {
+          return;
+        }
+      }
+      if sent != size {
+        tracing::error!(
+          "Bundle entry {} ended after {} of {} bytes",
+          hash,
+          sent,
+          size
+        );
+        let _ = sender
+          .send(Err(io::Error::from(io::ErrorKind::UnexpectedEof)))
+          .await;
+        return;
+      }
+      if send(&sender, vec![0u8; padding(size)]).await.is_err() {
+        return;
+      }
+    }
+    // End of archive: two zero blocks
+    let _ = send(&sender, vec![0u8; 2 * BLOCK_SIZE]).await;
+  });
+
+  let body = stream::unfold(receiver, |mut receiver| async move {
+    receiver.recv().await.map(|chunk| (chunk, receiver))
+  });
+  Ok(
+    (
+      StatusCode::OK,
+      [("content-type", "application/x-tar")],
+      Body::from_stream(body),
+    )
+      .into_response(),
+  )
+}
+
+/// Largest artifact accepted inside an uploaded bundle; entries are buffered in memory
+pub const MAX_BUNDLE_ENTRY_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Stores of bundle entries running at once
+const BUNDLE_STORE_CONCURRENCY: usize = 8;
+
+/// Largest PAX or GNU long name header read for an entry
+const MAX_EXTENDED_HEADER_BYTES: u64 = 64 * 1024;
+
+/// Outcome of each entry of an uploaded bundle
+#[derive(Debug, Default, Serialize)]
+pub struct BundleUploadResult {
+  pub stored: Vec<String>,
+  /// Already in the cache and left unchanged
+  pub existing: Vec<String>,
+  /// Invalid hash, duplicate, empty or larger than [`MAX_BUNDLE_ENTRY_BYTES`]
+  pub rejected: Vec<String>,
+  /// Storing failed; the client may retry these
+  pub failed: Vec<String>,
+}
+
+enum EntryOutcome {
+  Stored,
+  Existing,
+  Failed,
+}
+
+struct TarHeader {
+  name: String,
+  size: u64,
+  typeflag: u8,
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Value of a NUL-terminated header field
+fn header_str(field: &[u8]) -> &[u8] {
+  let end = field
+    .iter()
+    .position(|&byte| byte == 0)
+    .unwrap_or(field.len());
+  &field[..end]
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+  let digits = std::str::from_utf8(header_str(field)).map_err(|_| invalid("bad number"))?;
+  let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+  if digits.is_empty() {
+    return Ok(0);
+  }
+  u64::from_str_radix(digits, 8).map_err(|_| invalid("bad number"))
+}
+
+fn parse_header(block: &[u8; BLOCK_SIZE]) -> io::Result<TarHeader> {
+  let stored = parse_octal(&block[148..156])?;
+  let checksum: u64 = block
+    .iter()
+    .enumerate()
+    .map(|(i, &byte)| {
+      if (148..156).contains(&i) {
+        u64::from(b' ')
+      } else {
+        u64::from(byte)
+      }
+    })
+    .sum();
+  if stored != checksum {
+    return Err(invalid("header checksum mismatch"));
+  }
+
+  let mut name = String::from_utf8_lossy(header_str(&block[..100])).into_owned();
+  // Only POSIX ustar has a name prefix; GNU tar keeps other fields there
+  if &block[257..263] == b"ustar\0" {
+    let prefix = header_str(&block[345..500]);
+    if !prefix.is_empty() {
+      name = format!("{}/{}", String::from_utf8_lossy(prefix), name);
+    }
+  }
+  Ok(TarHeader {
+    name,
+    size: parse_octal(&block[124..136])?,
+    typeflag: block[156],
+  })
+}
+
+/// Read the next header block; `None` at the end of the archive
+///
+/// The stream may only end on a block boundary; a partial header block is an error.
+async fn read_header(
+  reader: &mut (impl AsyncRead + Unpin),
+) -> io::Result<Option<[u8; BLOCK_SIZE]>> {
+  let mut block = [0u8; BLOCK_SIZE];
+  let mut filled = 0;
+  while filled < BLOCK_SIZE {
+    match reader.read(&mut block[filled..]).await? {
+      // Archives cut off after the last entry, without the end marker, are accepted
+      0 if filled == 0 => return Ok(None),
+      0 => return Err(invalid("truncated header")),
+      read => filled += read,
+    }
+  }
+  Ok(block.iter().any(|&byte| byte != 0).then_some(block))
+}
+
+/// Read an entry's data and the padding after it
+async fn read_data(reader: &mut (impl AsyncRead + Unpin), size: u64) -> io::Result<Vec<u8>> {
+  let mut data = Vec::with_capacity(size as usize);
+  (&mut *reader).take(size).read_to_end(&mut data).await?;
+  if data.len() as u64 != size {
+    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+  }
+  let mut pad = [0u8; BLOCK_SIZE];
+  reader.read_exact(&mut pad[..padding(size)]).await?;
+  Ok(data)
+}
+
+/// Discard an entry's data and the padding after it
+async fn skip(reader: &mut (impl AsyncRead + Unpin), size: u64) -> io::Result<()> {
+  let size = size + padding(size) as u64;
+  let skipped = tokio::io::copy(&mut (&mut *reader).take(size), &mut tokio::io::sink()).await?;
+  if skipped != size {
+    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+  }
+  Ok(())
+}
+
+/// `path` from the records of a PAX extended header
+fn pax_path(records: &[u8]) -> Option<String> {
+  let records = std::str::from_utf8(records).ok()?;
+  let mut rest = records;
+  let mut path = None;
+  while !rest.is_empty() {
+    let (length, _) = rest.split_once(' ')?;
+    let record = rest.get(..length.parse().ok()?)?;
+    rest = &rest[record.len()..];
+    let (_, pair) = record.split_once(' ')?;
+    if let Some(value) = pair.strip_prefix("path=") {
+      path = Some(value.trim_end_matches('\n').to_string());
+    }
+  }
+  path
+}
+
+/// Store the regular files of an uploaded tar, each named after the hash it is stored as
+///
+/// Entries are stored as they arrive, several at once. Each entry gets its own outcome; a
+/// malformed archive answers 400 after the entries before the damage have been stored.
+pub async fn upload_bundle(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  body: Body,
+) -> Result<Response, ServerError> {
+  handlers::require_write(&state, &token)?;
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  let overwrite = service.allow_overwrite;
+
+  let stream = body
+    .into_data_stream()
+    .map(|chunk| chunk.map_err(io::Error::other));
+  let mut reader = StreamReader::new(stream);
+  let mut result = BundleUploadResult::default();
+  let mut seen = HashSet::new();
+  let mut stores = FuturesUnordered::new();
+  let mut long_name = None;
+
+  let parsed: io::Result<()> = async {
+    while let Some(block) = read_header(&mut reader).await? {
+      let header = parse_header(&block)?;
+      match header.typeflag {
+        b'x' | b'L' => {
+          if header.size > MAX_EXTENDED_HEADER_BYTES {
+            return Err(invalid("extended header too large"));
+          }
+          let data = read_data(&mut reader, header.size).await?;
+          long_name = if header.typeflag == b'x' {
+            pax_path(&data)
+          } else {
+            Some(String::from_utf8_lossy(header_str(&data)).into_owned())
+          };
+          continue;
+        },
+        b'0' | 0 => {},
+        // Directories, links and global headers carry no artifact
+        _ => {
+          long_name = None;
+          skip(&mut reader, header.size).await?;
+          continue;
+        },
+      }
+
+      let name = long_name.take().unwrap_or(header.name);
+      let hash = name.trim_start_matches("./").to_string();
+      let acceptable = state.hash_policies.nx.validate(&hash).is_ok()
+        && header.size <= MAX_BUNDLE_ENTRY_BYTES
+        && (header.size > 0 || state.allow_empty_uploads)
+        && seen.insert(hash.clone());
+      if !acceptable {
+        skip(&mut reader, header.size).await?;
+        result.rejected.push(hash);
+        continue;
+      }
+      let data = read_data(&mut reader, header.size).await?;
+
+      if stores.len() >= BUNDLE_STORE_CONCURRENCY {
+        if let Some((hash, outcome)) = stores.next().await {
+          result.record(hash, outcome);
+        }
+      }
+      stores.push(store_entry(
+        state.clone(),
+        token.0.clone(),
+        hash,
+        data,
+        overwrite,
+      ));
+    }
+    Ok(())
+  }
+  .await;
+
+  while let Some((hash, outcome)) = stores.next().await {
+    result.record(hash, outcome);
+  }
+  if let Err(err) = parsed {
+    tracing::warn!("Rejected bundle upload: {}", err);
+    return Ok(
+      (
+        StatusCode::BAD_REQUEST,
+        [("Content-Type", "text/plain")],
+        "Invalid tar archive",
+      )
+        .into_response(),
+    );
+  }
+
+  tracing::debug!(
+    "Bundle upload: {} stored, {} existing, {} rejected, {} failed",
+    result.stored.len(),
+    result.existing.len(),
+    result.rejected.len(),
+    result.failed.len()
+  );
+  Ok(Json(result).into_response())
+}
+
+impl BundleUploadResult {
+  fn record(&mut self, hash: String, outcome: EntryOutcome) {
+    match outcome {
+      EntryOutcome::Stored => self.stored.push(hash),
+      EntryOutcome::Existing => self.existing.push(hash),
+      EntryOutcome::Failed => self.failed.push(hash),
+    }
+  }
+}
+
+async fn store_entry(
+  state: AppState,
+  token: String,
+  hash: String,
+  data: Vec<u8>,
+  overwrite: bool,
+) -> (String, EntryOutcome) {
+  if !overwrite {
+    match state.storage.exists_with_token(&token, &hash).await {
+      Ok(true) => return (hash, EntryOutcome::Existing),
+      Ok(false) => {},
+      Err(err) => {
+        tracing::error!("Storage error on exists for bundle entry {}: {}", hash, err);
+        return (hash, EntryOutcome::Failed);
+      },
+    }
+  }
+
+  let size = data.len() as u64;
+  let options = UploadOptions {
+    overwrite,
+    ..Default::default()
+  };
+  let outcome = match state
+    .storage
+    .store_with_options(
+      &token,
+      &hash,
+      boxed_reader_stream(io::Cursor::new(data)),
+      Some(size),
+      options,
+    )
+    .await
+  {
+    Ok(()) => {
+      handlers::record_upload(&state, &token, size);
+      EntryOutcome::Stored
+    },
+    Err(StorageError::AlreadyExists) => EntryOutcome::Existing,
+    Err(err) => {
+      tracing::error!("Failed to store bundle entry {}: {}", hash, err);
+      EntryOutcome::Failed
+    },
+  };
+  (hash, outcome)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Config;
+  use crate::infra::multi_storage::MultiStorageRouter;
+  use axum::http::Request;
+  use tower::ServiceExt;
+
+  fn checksum_ok(block: &[u8]) -> bool {
+    let mut copy = block.to_vec();
+    copy[148..156].fill(b' ');
+    let sum: u32 = copy.iter().map(|&byte| u32::from(byte)).sum();
+    let stored = std::str::from_utf8(&block[148..154]).unwrap();
+    u32::from_str_radix(stored, 8).unwrap() == sum
+  }
+
+  #[test]
+  fn test_ustar_header() {
+    let header = entry_header("abc123", 1000, 1_700_000_000);
+    assert_eq!(header.len(), BLOCK_SIZE);
+    assert_eq!(&header[..6], b"abc123");
+    assert_eq!(&header[124..136], b"00000001750\0");
+    assert_eq!(header[156], b'0');
+    assert_eq!(&header[257..263], b"ustar\0");
+    assert!(checksum_ok(&header));
+    assert_eq!(padding(1000), 24);
+    assert_eq!(padding(1024), 0);
+  }
+
+  #[test]
+  fn test_long_names_use_pax() {
+    let name = "a".repeat(128);
+    let header = entry_header(&name, 5, 0);
+    assert_eq!(header.len(), 3 * BLOCK_SIZE);
+    assert_eq!(header[156], b'x');
+    assert!(checksum_ok(&header[..BLOCK_SIZE]));
+    let record = pax_record("path", &name);
+    assert_eq!(record.len().to_string(), record.split(' ').next().unwrap());
+    assert_eq!(
+      &header[BLOCK_SIZE..BLOCK_SIZE + record.len()],
+      record.as_bytes()
+    );
+    assert_eq!(header[2 * BLOCK_SIZE + 156], b'0');
+  }
+
+  #[tokio::test]
+  async fn test_read_written_archive() {
+    let long_name = "b".repeat(128);
+    let mut archive = entry_header("abc", 3, 0);
+    archive.extend_from_slice(b"xyz");
+    archive.resize(archive.len() + padding(3), 0);
+    archive.extend(entry_header(&long_name, 0, 0));
+    archive.extend([0u8; 2 * BLOCK_SIZE]);
+    let mut reader = archive.as_slice();
+
+    let block = read_header(&mut reader).await.unwrap().unwrap();
+    let header = parse_header(&block).unwrap();
+    assert_eq!((header.name.as_str(), header.size), ("abc", 3));
+    assert_eq!(header.typeflag, b'0');
+    assert_eq!(read_data(&mut reader, 3).await.unwrap(), b"xyz");
+
+    let block = read_header(&mut reader).await.unwrap().unwrap();
+    let pax = parse_header(&block).unwrap();
+    assert_eq!(pax.typeflag, b'x');
+    let records = read_data(&mut reader, pax.size).await.unwrap();
+    assert_eq!(pax_path(&records), Some(long_name));
+    let block = read_header(&mut reader).await.unwrap().unwrap();
+    assert_eq!(parse_header(&block).unwrap().size, 0);
+
+    assert!(read_header(&mut reader).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn test_truncated_upload_is_rejected() {
+    let root = tempfile::tempdir().unwrap();
+    let yaml = format!(
+      "buckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\nserviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessToken: ci\n",
+      root.path().display()
+    );
+    let config = Config::from_yaml_str(&yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let state = AppState::new(MultiStorageRouter::from_config(&config).await.unwrap());
+    let app = crate::server::create_router(&state).with_state(state);
+    let upload = |archive: Vec<u8>| {
+      Request::builder()
+        .method("PUT")
+        .uri("/v1/cache/bundle")
+        .header("authorization", "Bearer ci")
+        .body(Body::from(archive))
+        .unwrap()
+    };
+
+    let mut archive = entry_header("abc", 3, 0);
+    archive.extend_from_slice(b"xyz");
+    archive.resize(archive.len() + padding(3), 0);
+    let complete = archive.clone();
+    // The next header is cut off after a few bytes
+    archive.extend(&entry_header("def", 3, 0)[..100]);
+    let response = app.clone().oneshot(upload(archive)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Without the end marker, but ending on a block boundary
+    let response = app.oneshot(upload(complete)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+  }
+
+  #[test]
+  fn test_checksum_mismatch() {
+    let mut block = ustar_block("abc", 3, 0, b'0');
+    block[0] = b'x';
+    assert!(parse_header(&block).is_err());
+  }
+}
@@ -0,0 +1,337 @@
+use crate::domain::storage::StorageError;
+use crate::infra::multi_storage::UploadOptions;
+use crate::server::integrity::IntegrityCheck;
+use crate::server::validation::HashPolicy;
+use crate::server::{error::ServerError, handlers, middleware::AuthenticatedToken, AppState};
+use axum::{
+  body::Body,
+  extract::{Path, State},
+  http::{HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  routing::get,
+  Extension, Router,
+};
+
+/// Build systems whose HTTP remote cache is a plain keyed GET/PUT store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyedCache {
+  Please,
+  Gradle,
+  /// ccache HTTP storage, keyed by the path of its `subdirs`, `flat` or `bazel` layout
+  Ccache,
+  /// Bazel action cache, keyed by the action digest
+  BazelAc,
+  /// Bazel content-addressable store, keyed by the SHA-256 of the content
+  BazelCas,
+}
+
+impl KeyedCache {
+  /// Storage namespace below the token prefix, keeping protocols from colliding
+  fn namespace(self) -> &'static str {
+    match self {
+      Self::Please => "please",
+      Self::Gradle => "gradle",
+      Self::Ccache => "ccache",
+      Self::BazelAc => "bazel/ac",
+      Self::BazelCas => "bazel/cas",
+    }
+  }
+
+  /// Object key of `key` within the protocol's namespace
+  pub(crate) fn key(self, key: &str) -> String {
+    format!("{}/{}", self.namespace(), key)
+  }
+
+  /// Key policy of the protocol's routes
+  fn policy(self, state: &AppState) -> &HashPolicy {
+    match self {
+      Self::Please => &state.hash_policies.please,
+      Self::Gradle => &state.hash_policies.gradle,
+      Self::Ccache => &state.hash_policies.ccache,
+      Self::BazelAc | Self::BazelCas => &state.hash_policies.bazel,
+    }
+  }
+
+  /// Whether uploads replace an existing entry for tokens allowed to overwrite
+  ///
+  /// ccache rewrites entries on `--recache` and after finding one corrupt.
+  fn overwrites(self) -> bool {
+    self == Self::Ccache
+  }
+}
+
+/// Routes for the Please (`/please/{key}`), Gradle (`/gradle/{key}`) and ccache
+/// (`/ccache/{key}`) HTTP caches
+pub fn keyed_cache_routes() -> Router<AppState> {
+  Router::new()
+    .route("/please/{*key}", get(please_get).put(please_put))
+    .route("/gradle/{key}", get(gradle_get).put(gradle_put))
+    .route(
+      "/ccache/{*key}",
+      get(ccache_get)
+        .head(ccache_head)
+        .put(ccache_put)
+        .delete(ccache_delete),
+    )
+}
+
+/// Routes for the Bazel HTTP remote cache (`/ac/{hash}` and `/cas/{hash}`)
+pub fn bazel_routes() -> Router<AppState> {
+  Router::new()
+    .route("/ac/{hash}", get(bazel_ac_get).put(bazel_ac_put))
+    .route("/cas/{hash}", get(bazel_cas_get).put(bazel_cas_put))
+}
+
+async fn please_get(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+) -> Result<Response, ServerError> {
+  get_object(KeyedCache::Please, state, token, key).await
+}
+
+async fn please_put(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+  headers: HeaderMap,
+  body: Body,
+) -> Response {
+  put_object(KeyedCache::Please, state, token, key, headers, body).await
+}
+
+async fn gradle_get(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+) -> Result<Response, ServerError> {
+  get_object(KeyedCache::Gradle, state, token, key).await
+}
+
+async fn gradle_put(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+  headers: HeaderMap,
+  body: Body,
+) -> Response {
+  put_object(KeyedCache::Gradle, state, token, key, headers, body).await
+}
+
+async fn ccache_get(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+) -> Result<Response, ServerError> {
+  get_object(KeyedCache::Ccache, state, token, key).await
+}
+
+async fn ccache_head(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+) -> Result<Response, ServerError> {
+  head_object(KeyedCache::Ccache, state, token, key).await
+}
+
+async fn ccache_put(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+  headers: HeaderMap,
+  body: Body,
+) -> Response {
+  put_object(KeyedCache::Ccache, state, token, key, headers, body).await
+}
+
+async fn ccache_delete(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  key: Path<String>,
+) -> Result<Response, ServerError> {
+  delete_object(KeyedCache::Ccache, state, token, key).await
+}
+
+async fn bazel_ac_get(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  hash: Path<String>,
+) -> Result<Response, ServerError> {
+  get_object(KeyedCache::BazelAc, state, token, hash).await
+}
+
+async fn bazel_ac_put(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  hash: Path<String>,
+  headers: HeaderMap,
+  body: Body,
+) -> Response {
+  put_object(KeyedCache::BazelAc, state, token, hash, headers, body).await
+}
+
+async fn bazel_cas_get(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  hash: Path<String>,
+) -> Result<Response, ServerError> {
+  get_object(KeyedCache::BazelCas, state, token, hash).await
+}
+
+async fn bazel_cas_put(
+  state: State<AppState>,
+  token: Extension<AuthenticatedToken>,
+  hash: Path<String>,
+  headers: HeaderMap,
+  body: Body,
+) -> Response {
+  put_object(KeyedCache::BazelCas, state, token, hash, headers, body).await
+}
+
+/// GET: 200 with the object, 404 on a miss
+async fn get_object(
+  cache: KeyedCache,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Path(key): Path<String>,
+) -> Result<Response, ServerError> {
+  cache.policy(&state).validate(&key)?;
+  handlers::require_read(&state, &token)?;
+  let object = cache.key(&key);
+  handlers::stream_object(&state, &token, &object, &[]).await
+}
+
+/// HEAD: 200 if the object exists, 404 otherwise
+async fn head_object(
+  cache: KeyedCache,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Path(key): Path<String>,
+) -> Result<Response, ServerError> {
+  cache.policy(&state).validate(&key)?;
+  handlers::require_read(&state, &token)?;
+  if state
+    .storage
+    .exists_with_token(&token.0, &cache.key(&key))
+    .await?
+  {
+    Ok(StatusCode::OK.into_response())
+  } else {
+    Err(ServerError::Storage(StorageError::NotFound))
+  }
+}
+
+/// DELETE: 204 whether or not the object existed, 403 for tokens that may not overwrite
+async fn delete_object(
+  cache: KeyedCache,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Path(key): Path<String>,
+) -> Result<Response, ServerError> {
+  cache.policy(&state).validate(&key)?;
+  handlers::require_write(&state, &token)?;
+  let allowed = state
+    .storage
+    .get_token_config(&token.0)
+    .is_some_and(|service| service.allow_overwrite);
+  if !allowed {
+    return Ok(
+      (
+        StatusCode::FORBIDDEN,
+        [("Content-Type", "text/plain")],
+        "Access token may not delete entries",
+      )
+        .into_response(),
+    );
+  }
+  state
+    .storage
+    .delete_with_token(&token.0, &cache.key(&key))
+    .await?;
+  Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// PUT: 200 when stored. Keys are content-addressed, so an existing entry is also a success
+///
+/// Bazel CAS uploads must hash to their key, so a corrupted blob is never stored. ccache
+/// entries are replaced instead when the token allows overwrites.
+async fn put_object(
+  cache: KeyedCache,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Path(key): Path<String>,
+  headers: HeaderMap,
+  body: Body,
+) -> Response {
+  let invalid_key = || {
+    (
+      StatusCode::BAD_REQUEST,
+      [("Content-Type", "text/plain")],
+      "Invalid key",
+    )
+      .into_response()
+  };
+  if cache.policy(&state).validate(&key).is_err() {
+    return invalid_key();
+  }
+  if let Err(err) = handlers::require_write(&state, &token) {
+    return err.into_response();
+  }
+  let mut check = IntegrityCheck::default();
+  if cache == KeyedCache::BazelCas {
+    let mut digest = [0u8; 32];
+    if hex::decode_to_slice(&key, &mut digest).is_err() {
+      return invalid_key();
+    }
+    check = check.expecting_sha256(digest);
+  }
+
+  let object = cache.key(&key);
+  let content_length = headers
+    .get(axum::http::header::CONTENT_LENGTH)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|s| s.parse::<u64>().ok());
+
+  let overwrite = cache.overwrites()
+    && state
+      .storage
+      .get_token_config(&token.0)
+      .is_some_and(|service| service.allow_overwrite);
+  if !overwrite {
+    match state.storage.exists_with_token(&token.0, &object).await {
+      Ok(true) => return StatusCode::OK.into_response(),
+      Ok(false) => {},
+      Err(err) => {
+        tracing::error!("{:?} cache: storage error on exists: {}", cache, err);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+      },
+    }
+  }
+
+  let options = UploadOptions {
+    overwrite,
+    ..UploadOptions::default()
+  };
+  let check = check.with_content_length(content_length);
+  match handlers::store_body(
+    &state,
+    &token,
+    &object,
+    body,
+    content_length,
+    None,
+    options,
+    &check,
+  )
+  .await
+  {
+    Ok(()) | Err(StorageError::AlreadyExists) => StatusCode::OK.into_response(),
+    Err(err) => match check.failure() {
+      Some(failure) => handlers::integrity_failure_response(&object, failure).into_response(),
+      None => {
+        tracing::error!("{:?} cache: storage error on store: {}", cache, err);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+      },
+    },
+  }
+}
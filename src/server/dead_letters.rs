@@ -0,0 +1,59 @@
+use crate::infra::dead_letter::DeadLetter;
+use crate::server::task_metadata::admin_target;
+use crate::server::{error::ServerError, middleware::AuthenticatedToken, AppState};
+use axum::{
+  extract::{Path, Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+  routing::{delete, get, post},
+  Extension, Json, Router,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterQuery {
+  /// Name of the token whose dead letters are handled (defaults to the caller's)
+  pub token: Option<String>,
+}
+
+/// Admin routes to inspect, retry and discard permanently failed background deliveries
+pub fn dead_letter_routes() -> Router<AppState> {
+  Router::new()
+    .route("/admin/dead-letters", get(list_dead_letters))
+    .route("/admin/dead-letters/{id}", delete(discard_dead_letter))
+    .route("/admin/dead-letters/{id}/retry", post(retry_dead_letter))
+}
+
+/// Dead letters of a token, most recent failure first, for tokens with `admin` access
+pub async fn list_dead_letters(
+  State(state): State<AppState>,
+  Query(query): Query<DeadLetterQuery>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<Vec<DeadLetter>>, ServerError> {
+  let target = admin_target(&state, &token, query.token.as_deref())?;
+  Ok(Json(state.storage.dead_letters(&target).await?))
+}
+
+/// Run a dead-lettered delivery again; the record is removed once it succeeds
+pub async fn retry_dead_letter(
+  Path(id): Path<String>,
+  State(state): State<AppState>,
+  Query(query): Query<DeadLetterQuery>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<impl IntoResponse, ServerError> {
+  let target = admin_target(&state, &token, query.token.as_deref())?;
+  state.storage.retry_dead_letter(&target, &id).await?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Drop a dead letter without retrying it
+pub async fn discard_dead_letter(
+  Path(id): Path<String>,
+  State(state): State<AppState>,
+  Query(query): Query<DeadLetterQuery>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<impl IntoResponse, ServerError> {
+  let target = admin_target(&state, &token, query.token.as_deref())?;
+  state.storage.discard_dead_letter(&target, &id).await?;
+  Ok(StatusCode::NO_CONTENT)
+}
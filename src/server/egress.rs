@@ -0,0 +1,257 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of UTC days kept in the rolling egress window
+pub const EGRESS_WINDOW_DAYS: u64 = 7;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Bytes served to a token on a single UTC day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DailyEgress {
+  /// Days since the Unix epoch (UTC)
+  pub day: u64,
+  pub bytes: u64,
+}
+
+/// Serializable view of a token's egress usage
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EgressStats {
+  pub token: String,
+  pub bytes_today: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub daily_limit_bytes: Option<u64>,
+  pub window_days: u64,
+  pub days: Vec<EgressDayStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EgressDayStats {
+  /// UTC date formatted as YYYY-MM-DD
+  pub date: String,
+  pub bytes: u64,
+}
+
+/// Tracks bytes served per token per UTC day over a rolling window
+#[derive(Debug, Default)]
+pub struct EgressTracker {
+  usage: Mutex<HashMap<String, VecDeque<DailyEgress>>>,
+  /// Last day a quota warning was logged for each token
+  warned: Mutex<HashMap<String, u64>>,
+}
+
+impl EgressTracker {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Record bytes served to the given token
+  pub fn record(&self, token_name: &str, bytes: u64) {
+    self.record_at(token_name, bytes, current_day());
+  }
+
+  fn record_at(&self, token_name: &str, bytes: u64, day: u64) {
+    let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+    let days = usage.entry(token_name.to_string()).or_default();
+
+    match days.back_mut() {
+      Some(last) if last.day == day => last.bytes += bytes,
+      _ => days.push_back(DailyEgress { day, bytes }),
+    }
+
+    Self::evict_expired(days, day);
+  }
+
+  fn evict_expired(days: &mut VecDeque<DailyEgress>, today: u64) {
+    while let Some(first) = days.front() {
+      if first.day + EGRESS_WINDOW_DAYS <= today {
+        days.pop_front();
+      } else {
+        break;
+      }
+    }
+  }
+
+  /// Bytes served to the given token so far today (UTC)
+  pub fn bytes_today(&self, token_name: &str) -> u64 {
+    self.bytes_on(token_name, current_day())
+  }
+
+  fn bytes_on(&self, token_name: &str, day: u64) -> u64 {
+    let usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+    usage
+      .get(token_name)
+      .and_then(|days| days.iter().find(|d| d.day == day))
+      .map(|d| d.bytes)
+      .unwrap_or(0)
+  }
+
+  /// Check whether the token has reached its daily egress limit
+  pub fn is_over_limit(&self, token_name: &str, daily_limit: Option<u64>) -> bool {
+    match daily_limit {
+      Some(limit) => self.bytes_today(token_name) >= limit,
+      None => false,
+    }
+  }
+
+  /// Quota warning header value once today's usage reaches `warning_percent` of the limit
+  ///
+  /// The first warning per token and UTC day is also logged.
+  pub fn quota_warning(
+    &self,
+    token_name: &str,
+    daily_limit: Option<u64>,
+    warning_percent: u8,
+  ) -> Option<String> {
+    self.quota_warning_on(token_name, daily_limit?, warning_percent, current_day())
+  }
+
+  fn quota_warning_on(
+    &self,
+    token_name: &str,
+    limit: u64,
+    warning_percent: u8,
+    day: u64,
+  ) -> Option<String> {
+    let used = self.bytes_on(token_name, day);
+    let percent = (used as u128 * 100 / limit.max(1) as u128) as u64;
+    if percent < warning_percent as u64 {
+      return None;
+    }
+
+    let mut warned = self.warned.lock().unwrap_or_else(|e| e.into_inner());
+    if warned.insert(token_name.to_string(), day) != Some(day) {
+      tracing::warn!(
+        "Token {} reached {}% of its daily egress limit ({} of {} bytes)",
+        token_name,
+        percent,
+        used,
+        limit
+      );
+    }
+
+    Some(format!(
+      "egress={}%; used={}; limit={}",
+      percent, used, limit
+    ))
+  }
+
+  /// Daily usage within the rolling window, oldest first
+  pub fn window(&self, token_name: &str) -> Vec<DailyEgress> {
+    let today = current_day();
+    let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+    match usage.get_mut(token_name) {
+      Some(days) => {
+        Self::evict_expired(days, today);
+        days.iter().copied().collect()
+      },
+      None => Vec::new(),
+    }
+  }
+
+  /// Build the serializable stats for a token
+  pub fn stats(&self, token_name: &str, daily_limit: Option<u64>) -> EgressStats {
+    let days: Vec<EgressDayStats> = self
+      .window(token_name)
+      .into_iter()
+      .map(|d| EgressDayStats {
+        date: format_day(d.day),
+        bytes: d.bytes,
+      })
+      .collect();
+
+    EgressStats {
+      token: token_name.to_string(),
+      bytes_today: self.bytes_today(token_name),
+      daily_limit_bytes: daily_limit,
+      window_days: EGRESS_WINDOW_DAYS,
+      days,
+    }
+  }
+}
+
+/// Days since the Unix epoch (UTC)
+pub fn current_day() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() / SECONDS_PER_DAY)
+    .unwrap_or(0)
+}
+
+/// Seconds until the daily egress limits reset at the next UTC midnight
+pub fn seconds_until_reset() -> u64 {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  SECONDS_PER_DAY - now % SECONDS_PER_DAY
+}
+
+/// Format days since the Unix epoch as a YYYY-MM-DD civil date
+pub fn format_day(day: u64) -> String {
+  // Civil-from-days algorithm (Howard Hinnant), valid for all dates after 1970
+  let z = day as i64 + 719_468;
+  let era = z / 146_097;
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let d = doy - (153 * mp + 2) / 5 + 1;
+  let m = if mp < 10 { mp + 3 } else { mp - 9 };
+  let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+  format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record_accumulates_per_day() {
+    let tracker = EgressTracker::new();
+    tracker.record_at("ci", 100, 10);
+    tracker.record_at("ci", 50, 10);
+    tracker.record_at("ci", 25, 11);
+
+    assert_eq!(tracker.bytes_on("ci", 10), 150);
+    assert_eq!(tracker.bytes_on("ci", 11), 25);
+    assert_eq!(tracker.bytes_on("other", 10), 0);
+  }
+
+  #[test]
+  fn test_rolling_window_evicts_old_days() {
+    let tracker = EgressTracker::new();
+    tracker.record_at("ci", 100, 10);
+    tracker.record_at("ci", 100, 10 + EGRESS_WINDOW_DAYS);
+
+    assert_eq!(tracker.bytes_on("ci", 10), 0);
+    assert_eq!(tracker.bytes_on("ci", 10 + EGRESS_WINDOW_DAYS), 100);
+  }
+
+  #[test]
+  fn test_quota_warning_threshold() {
+    let tracker = EgressTracker::new();
+    tracker.record_at("ci", 79, 10);
+    assert_eq!(tracker.quota_warning_on("ci", 100, 80, 10), None);
+
+    tracker.record_at("ci", 6, 10);
+    assert_eq!(
+      tracker.quota_warning_on("ci", 100, 80, 10).as_deref(),
+      Some("egress=85%; used=85; limit=100")
+    );
+    // A new day starts below the threshold again
+    assert_eq!(tracker.quota_warning_on("ci", 100, 80, 11), None);
+    assert_eq!(tracker.quota_warning("ci", None, 80), None);
+  }
+
+  #[test]
+  fn test_format_day() {
+    assert_eq!(format_day(0), "1970-01-01");
+    assert_eq!(format_day(19_723), "2024-01-01");
+    assert_eq!(format_day(19_782), "2024-02-29");
+  }
+}
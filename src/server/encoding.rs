@@ -0,0 +1,137 @@
+use crate::domain::config::Codec;
+use crate::domain::storage::DynAsyncRead;
+use crate::server::error::ServerError;
+use async_compression::tokio::bufread::{GzipDecoder, GzipEncoder, ZstdDecoder, ZstdEncoder};
+use async_compression::Level;
+use axum::http::{
+  header::{ACCEPT_ENCODING, CONTENT_ENCODING},
+  HeaderMap,
+};
+use tokio::io::BufReader;
+
+/// Codecs a client accepts per its `Accept-Encoding` header, most preferred first
+///
+/// Codecs with `q=0` are refused; `*` and encodings the server does not store are ignored.
+pub fn accepted_codecs(headers: &HeaderMap) -> Vec<Codec> {
+  let mut accepted: Vec<(Codec, f32)> = headers
+    .get_all(ACCEPT_ENCODING)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .filter_map(|item| {
+      let mut parts = item.split(';');
+      let codec = match parts.next()?.trim().to_ascii_lowercase().as_str() {
+        "zstd" => Codec::Zstd,
+        "gzip" | "x-gzip" => Codec::Gzip,
+        _ => return None,
+      };
+      let quality = parts
+        .filter_map(|param| param.trim().strip_prefix("q="))
+        .find_map(|q| q.trim().parse::<f32>().ok())
+        .unwrap_or(1.0);
+      (quality > 0.0).then_some((codec, quality))
+    })
+    .collect();
+
+  // Stable, so equally preferred codecs keep the client's order
+  accepted.sort_by(|a, b| b.1.total_cmp(&a.1));
+  let mut codecs = Vec::new();
+  for (codec, _) in accepted {
+    if !codecs.contains(&codec) {
+      codecs.push(codec);
+    }
+  }
+  codecs
+}
+
+/// Codec an upload was compressed with per its `Content-Encoding` header
+///
+/// `identity` counts as no encoding; other encodings and stacked codecs are rejected.
+pub fn content_encoding(headers: &HeaderMap) -> Result<Option<Codec>, ServerError> {
+  let Some(value) = headers.get(CONTENT_ENCODING) else {
+    return Ok(None);
+  };
+  let value = value.to_str().map_err(|_| ServerError::BadRequest)?;
+  match value.trim().to_ascii_lowercase().as_str() {
+    "" | "identity" => Ok(None),
+    "zstd" => Ok(Some(Codec::Zstd)),
+    "gzip" | "x-gzip" => Ok(Some(Codec::Gzip)),
+    _ => Err(ServerError::BadRequest),
+  }
+}
+
+/// Decompress an upload sent with the given `Content-Encoding`
+pub fn decode(reader: DynAsyncRead, codec: Codec) -> DynAsyncRead {
+  let reader = BufReader::new(reader);
+  match codec {
+    Codec::Zstd => Box::new(ZstdDecoder::new(reader)),
+    Codec::Gzip => Box::new(GzipDecoder::new(reader)),
+  }
+}
+
+/// Compress a download for the transfer with the given codec and level
+pub fn encode(reader: DynAsyncRead, codec: Codec, level: i32) -> DynAsyncRead {
+  let reader = BufReader::new(reader);
+  let level = Level::Precise(level);
+  match codec {
+    Codec::Zstd => Box::new(ZstdEncoder::with_quality(reader, level)),
+    Codec::Gzip => Box::new(GzipEncoder::with_quality(reader, level)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn accepted(value: &str) -> Vec<Codec> {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_ENCODING, value.parse().unwrap());
+    accepted_codecs(&headers)
+  }
+
+  #[test]
+  fn test_accepted_codecs() {
+    assert_eq!(accepted("gzip, deflate, br"), vec![Codec::Gzip]);
+    assert_eq!(accepted("gzip;q=0.5, zstd"), vec![Codec::Zstd, Codec::Gzip]);
+    assert_eq!(accepted("zstd;q=0, gzip"), vec![Codec::Gzip]);
+    assert_eq!(accepted("identity, *"), Vec::<Codec>::new());
+    assert!(accepted_codecs(&HeaderMap::new()).is_empty());
+  }
+
+  #[test]
+  fn test_content_encoding() {
+    let encoding = |value: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(CONTENT_ENCODING, value.parse().unwrap());
+      content_encoding(&headers)
+    };
+    assert_eq!(encoding("gzip").unwrap(), Some(Codec::Gzip));
+    assert_eq!(encoding("ZSTD").unwrap(), Some(Codec::Zstd));
+    assert_eq!(encoding("identity").unwrap(), None);
+    assert!(encoding("br").is_err());
+    assert!(encoding("gzip, zstd").is_err());
+    assert_eq!(content_encoding(&HeaderMap::new()).unwrap(), None);
+  }
+
+  #[tokio::test]
+  async fn test_encode_round_trip() {
+    use tokio::io::AsyncReadExt;
+
+    let data = b"artifact ".repeat(100);
+    for codec in [Codec::Zstd, Codec::Gzip] {
+      let mut encoded = Vec::new();
+      encode(Box::new(std::io::Cursor::new(data.clone())), codec, 3)
+        .read_to_end(&mut encoded)
+        .await
+        .unwrap();
+      assert!(encoded.len() < data.len());
+
+      let mut decoded = Vec::new();
+      decode(Box::new(std::io::Cursor::new(encoded)), codec)
+        .read_to_end(&mut decoded)
+        .await
+        .unwrap();
+      assert_eq!(decoded, data);
+    }
+  }
+}
@@ -1,8 +1,10 @@
 use crate::domain::storage::StorageError;
+use crate::server::egress;
 use axum::{
-  http::StatusCode,
+  http::{header::RETRY_AFTER, HeaderValue, StatusCode},
   response::{IntoResponse, Response},
 };
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,15 +15,70 @@ pub enum ServerError {
   #[error("Unauthorized")]
   Unauthorized,
 
+  /// A valid token without write access attempted an upload (403, as the Nx spec requires)
+  #[error("Access token does not have write access")]
+  WriteForbidden,
+
+  /// A valid token without read access attempted a download (403)
+  #[error("Access token does not have read access")]
+  ReadForbidden,
+
+  /// A valid token without `admin` access called an admin endpoint (403)
+  #[error("Forbidden")]
+  Forbidden,
+
   #[error("Internal server error")]
   InternalError,
 
+  #[error("Daily egress limit exceeded")]
+  EgressLimitExceeded,
+
+  #[error("A profile is already being captured")]
+  ProfilerBusy,
+
+  #[error("Request deadline exceeded")]
+  DeadlineExceeded,
+
   #[error("Storage error: {0}")]
   Storage(#[from] StorageError),
 }
 
+/// Machine-readable code of an error response, attached as a response extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode(pub &'static str);
+
+impl ServerError {
+  /// Stable code naming the error in JSON error bodies
+  pub fn code(&self) -> &'static str {
+    match self {
+      ServerError::BadRequest => "invalid_request",
+      ServerError::Unauthorized => "unauthorized",
+      ServerError::WriteForbidden => "write_forbidden",
+      ServerError::ReadForbidden => "read_forbidden",
+      ServerError::Forbidden => "forbidden",
+      ServerError::InternalError => "internal_error",
+      ServerError::EgressLimitExceeded => "egress_limit_exceeded",
+      ServerError::ProfilerBusy => "profiler_busy",
+      ServerError::DeadlineExceeded => "deadline_exceeded",
+      ServerError::Storage(StorageError::NotFound) => "not_found",
+      ServerError::Storage(StorageError::AlreadyExists) => "already_exists",
+      ServerError::Storage(StorageError::OperationFailed) => "storage_error",
+    }
+  }
+}
+
+/// Tell the client how long to back off before retrying, in whole seconds rounded up
+pub fn with_retry_after(mut response: Response, after: Duration) -> Response {
+  let seconds = after.as_secs() + u64::from(after.subsec_nanos() > 0);
+  response
+    .headers_mut()
+    .insert(RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+  response
+}
+
 impl IntoResponse for ServerError {
   fn into_response(self) -> Response {
+    let code = ErrorCode(self.code());
     let (status, message) = match self {
       // Map domain errors to HTTP responses
       ServerError::Storage(StorageError::NotFound) => {
@@ -37,9 +94,56 @@ impl IntoResponse for ServerError {
       // HTTP-specific errors
       ServerError::BadRequest => (StatusCode::NOT_FOUND, "The record was not found"),
       ServerError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      ServerError::WriteForbidden => (
+        StatusCode::FORBIDDEN,
+        "access token does not have write access",
+      ),
+      ServerError::ReadForbidden => (
+        StatusCode::FORBIDDEN,
+        "access token does not have read access",
+      ),
+      ServerError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
       ServerError::InternalError => (StatusCode::NOT_FOUND, "The record was not found"),
+      ServerError::EgressLimitExceeded => {
+        (StatusCode::TOO_MANY_REQUESTS, "Daily egress limit exceeded")
+      },
+      ServerError::ProfilerBusy => (StatusCode::CONFLICT, "A profile is already being captured"),
+      ServerError::DeadlineExceeded => (StatusCode::GATEWAY_TIMEOUT, "Request deadline exceeded"),
     };
 
-    (status, [("Content-Type", "text/plain")], message).into_response()
+    let mut response = (status, [("Content-Type", "text/plain")], message).into_response();
+    response.extensions_mut().insert(code);
+    if code.0 == "egress_limit_exceeded" {
+      let reset = Duration::from_secs(egress::seconds_until_reset());
+      response = with_retry_after(response, reset);
+    }
+    response
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_retry_after() {
+    let response = with_retry_after(
+      StatusCode::SERVICE_UNAVAILABLE.into_response(),
+      Duration::from_millis(1500),
+    );
+    assert_eq!(response.headers()[RETRY_AFTER], "2");
+    let response = with_retry_after(
+      StatusCode::SERVICE_UNAVAILABLE.into_response(),
+      Duration::ZERO,
+    );
+    assert_eq!(response.headers()[RETRY_AFTER], "1");
+
+    let response = ServerError::EgressLimitExceeded.into_response();
+    let seconds: u64 = response.headers()[RETRY_AFTER]
+      .to_str()
+      .unwrap()
+      .parse()
+      .unwrap();
+    assert!((1..=86_400).contains(&seconds));
   }
 }
@@ -0,0 +1,900 @@
+//! gRPC remote cache for Bazel, Buck2 and Pants clients, following the remote execution API
+//!
+//! Serves the `Capabilities`, `ActionCache` and `ContentAddressableStorage` services and the
+//! ByteStream API on their own listener. Entries share storage with the HTTP `/ac` and
+//! `/cas` routes, so clients of either protocol hit each other's entries.
+
+use crate::domain::storage::StorageError;
+use crate::infra::multi_storage::UploadOptions;
+use crate::server::compat::KeyedCache;
+use crate::server::error::ServerError;
+use crate::server::integrity::{IntegrityCheck, IntegrityFailure};
+use crate::server::middleware::{is_expired, is_revoked, resolve_token, AuthenticatedToken};
+use crate::server::remote_apis::*;
+use crate::server::{handlers, AppState};
+use axum::{
+  body::Body,
+  extract::{Request, State},
+  response::{IntoResponse, Response},
+  routing::post,
+  Router,
+};
+use bytes::Bytes;
+use futures_util::{future, stream, Stream, StreamExt};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::server::Grpc;
+use tonic::{Status, Streaming};
+use tonic_prost::ProstCodec;
+use tower::service_fn;
+
+/// Largest message a client may send, leaving room around a full batch
+const MAX_MESSAGE_BYTES: usize = 8 * 1024 * 1024;
+
+/// Total blob size a client may send or request in one batch call
+const MAX_BATCH_BYTES: i64 = 4 * 1024 * 1024;
+
+/// Storage lookups in flight for one `FindMissingBlobs` call
+const FIND_MISSING_CONCURRENCY: usize = 16;
+
+/// SHA-256 of the empty blob, which is always present
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+type ReadStream = Pin<Box<dyn Stream<Item = Result<ReadResponse, Status>> + Send>>;
+
+/// Serves the remote execution API from the current application state
+#[derive(Clone)]
+pub struct RemoteCache {
+  state: Arc<dyn Fn() -> AppState + Send + Sync>,
+}
+
+impl RemoteCache {
+  /// `state` is asked for the application state on every call, so reloads take effect
+  pub fn new(state: impl Fn() -> AppState + Send + Sync + 'static) -> Self {
+    Self {
+      state: Arc::new(state),
+    }
+  }
+
+  /// Application state and the caller's token, from `authorization: Bearer <token>` metadata
+  async fn authenticate<T>(
+    &self,
+    request: &tonic::Request<T>,
+  ) -> Result<(AppState, AuthenticatedToken), Status> {
+    let state = (self.state)();
+    let bearer = request
+      .metadata()
+      .get("authorization")
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| value.strip_prefix("Bearer "));
+    let found = match bearer {
+      Some(bearer) => resolve_token(&state, bearer)
+        .await
+        .map_err(Status::unauthenticated)?,
+      None => None,
+    };
+    let token = found.ok_or_else(|| {
+      tracing::warn!("gRPC authentication failed: invalid token");
+      Status::unauthenticated("Unauthorized")
+    })?;
+    if is_revoked(&state, &token) {
+      return Err(Status::unauthenticated("Token revoked"));
+    }
+    if is_expired(&state, &token) {
+      return Err(Status::unauthenticated("Token expired"));
+    }
+    Ok((state, AuthenticatedToken(token)))
+  }
+
+  async fn get_capabilities(
+    self,
+    request: tonic::Request<GetCapabilitiesRequest>,
+  ) -> Result<tonic::Response<ServerCapabilities>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    let update_enabled = state
+      .storage
+      .get_token_config(&token.0)
+      .is_some_and(|service| service.permissions.can_write());
+    let version = SemVer { major: 2, minor: 0 };
+    Ok(tonic::Response::new(ServerCapabilities {
+      cache_capabilities: Some(CacheCapabilities {
+        digest_functions: vec![DIGEST_FUNCTION_SHA256],
+        action_cache_update_capabilities: Some(ActionCacheUpdateCapabilities { update_enabled }),
+        max_batch_total_size_bytes: MAX_BATCH_BYTES,
+      }),
+      low_api_version: Some(version.clone()),
+      high_api_version: Some(version),
+    }))
+  }
+
+  async fn get_action_result(
+    self,
+    request: tonic::Request<GetActionResultRequest>,
+  ) -> Result<tonic::Response<ActionResult>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_read(&state, &token)?;
+    let digest = required(request.get_ref().action_digest.as_ref())?;
+    check_digest(&state, digest)?;
+
+    let key = KeyedCache::BazelAc.key(&digest.hash);
+    let data = read_blob(&state, &token, &key).await?;
+    Ok(tonic::Response::new(ActionResult(data)))
+  }
+
+  async fn update_action_result(
+    self,
+    request: tonic::Request<UpdateActionResultRequest>,
+  ) -> Result<tonic::Response<ActionResult>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_write(&state, &token)?;
+    let request = request.into_inner();
+    let digest = required(request.action_digest.as_ref())?;
+    check_digest(&state, digest)?;
+
+    // The first result stored for an action wins, as on the HTTP `/ac` route
+    let key = KeyedCache::BazelAc.key(&digest.hash);
+    let data = request.action_result;
+    let length = data.len() as u64;
+    store(
+      &state,
+      &token,
+      &key,
+      Body::from(data.clone()),
+      length,
+      IntegrityCheck::default(),
+    )
+    .await?;
+    Ok(tonic::Response::new(ActionResult(data)))
+  }
+
+  async fn find_missing_blobs(
+    self,
+    request: tonic::Request<FindMissingBlobsRequest>,
+  ) -> Result<tonic::Response<FindMissingBlobsResponse>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_read(&state, &token)?;
+    let digests = request.into_inner().blob_digests;
+    for digest in &digests {
+      check_digest(&state, digest)?;
+    }
+
+    let lookups = digests
+      .into_iter()
+      .filter(|digest| !is_empty_blob(digest))
+      .map(|digest| {
+        let (state, token) = (&state, &token);
+        async move {
+          let key = KeyedCache::BazelCas.key(&digest.hash);
+          let exists = state.storage.exists_with_token(&token.0, &key).await?;
+          Ok::<_, StorageError>((!exists).then_some(digest))
+        }
+      });
+    let mut missing_blob_digests = Vec::new();
+    let mut lookups = stream::iter(lookups).buffered(FIND_MISSING_CONCURRENCY);
+    while let Some(lookup) = lookups.next().await {
+      missing_blob_digests.extend(lookup.map_err(ServerError::from)?);
+    }
+    Ok(tonic::Response::new(FindMissingBlobsResponse {
+      missing_blob_digests,
+    }))
+  }
+
+  async fn batch_update_blobs(
+    self,
+    request: tonic::Request<BatchUpdateBlobsRequest>,
+  ) -> Result<tonic::Response<BatchUpdateBlobsResponse>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_write(&state, &token)?;
+    let blobs = request.into_inner().requests;
+    let total: usize = blobs.iter().map(|blob| blob.data.len()).sum();
+    if total as i64 > MAX_BATCH_BYTES {
+      return Err(Status::invalid_argument("Batch exceeds the maximum size"));
+    }
+
+    let mut responses = Vec::with_capacity(blobs.len());
+    for blob in blobs {
+      let status = match update_blob(&state, &token, &blob).await {
+        Ok(()) => RpcStatus::default(),
+        Err(status) => RpcStatus::from(status),
+      };
+      responses.push(UpdatedBlob {
+        digest: blob.digest,
+        status: Some(status),
+      });
+    }
+    Ok(tonic::Response::new(BatchUpdateBlobsResponse { responses }))
+  }
+
+  async fn batch_read_blobs(
+    self,
+    request: tonic::Request<BatchReadBlobsRequest>,
+  ) -> Result<tonic::Response<BatchReadBlobsResponse>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_read(&state, &token)?;
+    let digests = request.into_inner().digests;
+    let total: i64 = digests.iter().map(|digest| digest.size_bytes.max(0)).sum();
+    if total > MAX_BATCH_BYTES {
+      return Err(Status::invalid_argument("Batch exceeds the maximum size"));
+    }
+
+    let mut responses = Vec::with_capacity(digests.len());
+    for digest in digests {
+      let read = match check_digest(&state, &digest) {
+        Ok(()) if is_empty_blob(&digest) => Ok(Bytes::new()),
+        Ok(()) => read_blob(&state, &token, &KeyedCache::BazelCas.key(&digest.hash)).await,
+        Err(status) => Err(status),
+      };
+      let (data, status) = match read {
+        Ok(data) => (data, RpcStatus::default()),
+        Err(status) => (Bytes::new(), RpcStatus::from(status)),
+      };
+      responses.push(ReadBlob {
+        digest: Some(digest),
+        data,
+        status: Some(status),
+      });
+    }
+    Ok(tonic::Response::new(BatchReadBlobsResponse { responses }))
+  }
+
+  async fn read(
+    self,
+    request: tonic::Request<ReadRequest>,
+  ) -> Result<tonic::Response<ReadStream>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_read(&state, &token)?;
+    let request = request.into_inner();
+    let digest = blob_digest(&request.resource_name, false)?;
+    check_digest(&state, &digest)?;
+    if request.read_offset < 0 || request.read_limit < 0 {
+      return Err(Status::invalid_argument("Negative read offset or limit"));
+    }
+    if request.read_offset > digest.size_bytes {
+      return Err(Status::out_of_range(
+        "Read offset is past the end of the blob",
+      ));
+    }
+    if is_empty_blob(&digest) {
+      return Ok(tonic::Response::new(Box::pin(stream::empty())));
+    }
+
+    let key = KeyedCache::BazelCas.key(&digest.hash);
+    let body = handlers::stream_object(&state, &token, &key, &[])
+      .await?
+      .into_body();
+    let skip = request.read_offset as u64;
+    let limit = match request.read_limit {
+      0 => u64::MAX,
+      limit => limit as u64,
+    };
+    let chunks = body
+      .into_data_stream()
+      .scan((skip, limit), |(skip, remaining), chunk| {
+        if *remaining == 0 {
+          return future::ready(None);
+        }
+        let message = chunk
+          .map(|mut data| {
+            let skipped = (*skip).min(data.len() as u64);
+            *skip -= skipped;
+            data = data.slice(skipped as usize..);
+            data.truncate((*remaining).min(data.len() as u64) as usize);
+            *remaining -= data.len() as u64;
+            ReadResponse { data }
+          })
+          .map_err(|err| Status::internal(err.to_string()));
+        future::ready(Some(message))
+      })
+      .filter(|message| future::ready(!matches!(message, Ok(m) if m.data.is_empty())));
+    Ok(tonic::Response::new(Box::pin(chunks)))
+  }
+
+  async fn write(
+    self,
+    request: tonic::Request<Streaming<WriteRequest>>,
+  ) -> Result<tonic::Response<WriteResponse>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_write(&state, &token)?;
+    let mut messages = request.into_inner();
+    let first = messages
+      .message()
+      .await?
+      .ok_or_else(|| Status::invalid_argument("Empty write"))?;
+    let digest = blob_digest(&first.resource_name, true)?;
+    check_digest(&state, &digest)?;
+    // Partial uploads are not kept, so a retried write always starts over
+    if first.write_offset != 0 {
+      return Err(Status::invalid_argument("Writes must start at offset 0"));
+    }
+
+    let committed = tonic::Response::new(WriteResponse {
+      committed_size: digest.size_bytes,
+    });
+    let key = KeyedCache::BazelCas.key(&digest.hash);
+    if is_empty_blob(&digest)
+      || state
+        .storage
+        .exists_with_token(&token.0, &key)
+        .await
+        .map_err(ServerError::from)?
+    {
+      return Ok(committed);
+    }
+
+    let data = stream::once(future::ready(Ok::<_, Status>(first.data)))
+      .chain(messages.map(|message| message.map(|message| message.data)));
+    let check = IntegrityCheck::default().expecting_sha256(sha256(&digest)?);
+    store(
+      &state,
+      &token,
+      &key,
+      Body::from_stream(data),
+      digest.size_bytes as u64,
+      check,
+    )
+    .await?;
+    Ok(committed)
+  }
+
+  async fn query_write_status(
+    self,
+    request: tonic::Request<QueryWriteStatusRequest>,
+  ) -> Result<tonic::Response<QueryWriteStatusResponse>, Status> {
+    let (state, token) = self.authenticate(&request).await?;
+    handlers::require_write(&state, &token)?;
+    let digest = blob_digest(&request.get_ref().resource_name, true)?;
+    check_digest(&state, &digest)?;
+
+    let key = KeyedCache::BazelCas.key(&digest.hash);
+    let complete = is_empty_blob(&digest)
+      || state
+        .storage
+        .exists_with_token(&token.0, &key)
+        .await
+        .map_err(ServerError::from)?;
+    Ok(tonic::Response::new(QueryWriteStatusResponse {
+      committed_size: if complete { digest.size_bytes } else { 0 },
+      complete,
+    }))
+  }
+}
+
+impl From<ServerError> for Status {
+  fn from(err: ServerError) -> Self {
+    let message = err.to_string();
+    match err {
+      ServerError::BadRequest => Status::invalid_argument(message),
+      ServerError::Unauthorized => Status::unauthenticated(message),
+      ServerError::WriteForbidden | ServerError::ReadForbidden | ServerError::Forbidden => {
+        Status::permission_denied(message)
+      },
+      ServerError::EgressLimitExceeded => Status::resource_exhausted(message),
+      ServerError::DeadlineExceeded => Status::deadline_exceeded(message),
+      ServerError::Storage(StorageError::NotFound) => Status::not_found(message),
+      ServerError::Storage(StorageError::AlreadyExists) => Status::already_exists(message),
+      ServerError::Storage(StorageError::OperationFailed) => Status::unavailable(message),
+      ServerError::InternalError | ServerError::ProfilerBusy => Status::internal(message),
+    }
+  }
+}
+
+fn required(digest: Option<&Digest>) -> Result<&Digest, Status> {
+  digest.ok_or_else(|| Status::invalid_argument("Missing digest"))
+}
+
+/// Check a digest against the Bazel key policy
+fn check_digest(state: &AppState, digest: &Digest) -> Result<(), Status> {
+  if digest.size_bytes < 0 || state.hash_policies.bazel.validate(&digest.hash).is_err() {
+    return Err(Status::invalid_argument(format!(
+      "Invalid digest {}/{}",
+      digest.hash, digest.size_bytes
+    )));
+  }
+  Ok(())
+}
+
+fn is_empty_blob(digest: &Digest) -> bool {
+  digest.size_bytes == 0 && digest.hash == EMPTY_SHA256
+}
+
+fn sha256(digest: &Digest) -> Result<[u8; 32], Status> {
+  let mut bytes = [0u8; 32];
+  hex::decode_to_slice(&digest.hash, &mut bytes)
+    .map_err(|_| Status::invalid_argument(format!("Invalid digest {}", digest.hash)))?;
+  Ok(bytes)
+}
+
+/// Digest named by a ByteStream resource name
+///
+/// Reads name `[{instance}/]blobs/{hash}/{size}`, writes
+/// `[{instance}/]uploads/{uuid}/blobs/{hash}/{size}[/{metadata}]`. The instance name is
+/// ignored, tokens already keep namespaces apart.
+fn blob_digest(resource_name: &str, upload: bool) -> Result<Digest, Status> {
+  let invalid = || Status::invalid_argument(format!("Invalid resource name {}", resource_name));
+  let segments: Vec<&str> = resource_name.split('/').collect();
+  let index = segments
+    .iter()
+    .position(|segment| matches!(*segment, "blobs" | "compressed-blobs"))
+    .ok_or_else(invalid)?;
+  if segments[index] == "compressed-blobs" {
+    return Err(Status::unimplemented("Compressed blobs are not supported"));
+  }
+  if upload && (index < 2 || segments[index - 2] != "uploads") {
+    return Err(invalid());
+  }
+
+  let (hash, size) = match &segments[index + 1..] {
+    [hash, size] => (hash, size),
+    [hash, size, ..] if upload => (hash, size),
+    _ => return Err(invalid()),
+  };
+  let size_bytes = size
+    .parse()
+    .ok()
+    .filter(|size| *size >= 0)
+    .ok_or_else(invalid)?;
+  Ok(Digest {
+    hash: hash.to_string(),
+    size_bytes,
+  })
+}
+
+/// Read a small object whole, counted against the token's egress like any download
+async fn read_blob(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  key: &str,
+) -> Result<Bytes, Status> {
+  let response = handlers::stream_object(state, token, key, &[]).await?;
+  axum::body::to_bytes(response.into_body(), MAX_MESSAGE_BYTES)
+    .await
+    .map_err(|err| Status::internal(err.to_string()))
+}
+
+/// Store one blob of a batch, checked against its digest
+async fn update_blob(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  blob: &UpdateBlob,
+) -> Result<(), Status> {
+  let digest = required(blob.digest.as_ref())?;
+  check_digest(state, digest)?;
+  if blob.compressor != 0 {
+    return Err(Status::invalid_argument(
+      "Compressed blobs are not supported",
+    ));
+  }
+  if is_empty_blob(digest) {
+    return Ok(());
+  }
+
+  let key = KeyedCache::BazelCas.key(&digest.hash);
+  let check = IntegrityCheck::default().expecting_sha256(sha256(digest)?);
+  let length = digest.size_bytes as u64;
+  store(
+    state,
+    token,
+    &key,
+    Body::from(blob.data.clone()),
+    length,
+    check,
+  )
+  .await
+}
+
+/// Store a body of `length` bytes; an existing entry counts as stored
+async fn store(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  key: &str,
+  body: Body,
+  length: u64,
+  check: IntegrityCheck,
+) -> Result<(), Status> {
+  let check = check.with_content_length(Some(length));
+  match handlers::store_body(
+    state,
+    token,
+    key,
+    body,
+    Some(length),
+    None,
+    UploadOptions::default(),
+    &check,
+  )
+  .await
+  {
+    Ok(()) | Err(StorageError::AlreadyExists) => Ok(()),
+    Err(err) => Err(match check.failure() {
+      Some(IntegrityFailure::Checksum) => {
+        Status::invalid_argument("Blob does not match its digest")
+      },
+      Some(_) => Status::invalid_argument("Blob size does not match its digest"),
+      None => {
+        tracing::error!("gRPC cache: storage error on store of {}: {}", key, err);
+        ServerError::from(err).into()
+      },
+    }),
+  }
+}
+
+/// Answer a unary call with `handle`
+async fn unary<Req, Resp, F, Fut>(request: Request, handle: F) -> Response
+where
+  Req: prost::Message + Default + Send + 'static,
+  Resp: prost::Message + Send + 'static,
+  F: FnMut(tonic::Request<Req>) -> Fut,
+  Fut: Future<Output = Result<tonic::Response<Resp>, Status>>,
+{
+  let mut grpc =
+    Grpc::new(ProstCodec::<Resp, Req>::default()).max_decoding_message_size(MAX_MESSAGE_BYTES);
+  grpc
+    .unary(service_fn(handle), request)
+    .await
+    .into_response()
+}
+
+async fn get_capabilities(State(cache): State<RemoteCache>, request: Request) -> Response {
+  unary(request, |request| cache.clone().get_capabilities(request)).await
+}
+
+async fn get_action_result(State(cache): State<RemoteCache>, request: Request) -> Response {
+  unary(request, |request| cache.clone().get_action_result(request)).await
+}
+
+async fn update_action_result(State(cache): State<RemoteCache>, request: Request) -> Response {
+  unary(request, |request| {
+    cache.clone().update_action_result(request)
+  })
+  .await
+}
+
+async fn find_missing_blobs(State(cache): State<RemoteCache>, request: Request) -> Response {
+  unary(request, |request| cache.clone().find_missing_blobs(request)).await
+}
+
+async fn batch_update_blobs(State(cache): State<RemoteCache>, request: Request) -> Response {
+  unary(request, |request| cache.clone().batch_update_blobs(request)).await
+}
+
+async fn batch_read_blobs(State(cache): State<RemoteCache>, request: Request) -> Response {
+  unary(request, |request| cache.clone().batch_read_blobs(request)).await
+}
+
+async fn query_write_status(State(cache): State<RemoteCache>, request: Request) -> Response {
+  unary(request, |request| cache.clone().query_write_status(request)).await
+}
+
+async fn read(State(cache): State<RemoteCache>, request: Request) -> Response {
+  let mut grpc = Grpc::new(ProstCodec::<ReadResponse, ReadRequest>::default());
+  let handle = |request| cache.clone().read(request);
+  grpc
+    .server_streaming(service_fn(handle), request)
+    .await
+    .into_response()
+}
+
+async fn write(State(cache): State<RemoteCache>, request: Request) -> Response {
+  let mut grpc = Grpc::new(ProstCodec::<WriteResponse, WriteRequest>::default())
+    .max_decoding_message_size(MAX_MESSAGE_BYTES);
+  let handle = |request| cache.clone().write(request);
+  grpc
+    .client_streaming(service_fn(handle), request)
+    .await
+    .into_response()
+}
+
+/// Routes of the gRPC services; unknown methods are answered with `UNIMPLEMENTED`
+pub fn grpc_routes(cache: RemoteCache) -> Router {
+  method_routes()
+    .fallback(|| async { Status::unimplemented("Method not implemented").into_http::<Body>() })
+    .with_state(cache)
+}
+
+/// gRPC service routes for the HTTP port, so Buck2 can use it as its engine address
+///
+/// Unknown methods fall through to the HTTP router's `404`, which gRPC clients read as
+/// `UNIMPLEMENTED`. The HTTP router is rebuilt on reload, so `state` is always current.
+#[cfg(feature = "buck2")]
+pub fn http_routes<S: Clone + Send + Sync + 'static>(state: &AppState) -> Router<S> {
+  let state = state.clone();
+  method_routes().with_state(RemoteCache::new(move || state.clone()))
+}
+
+fn method_routes() -> Router<RemoteCache> {
+  const CAPABILITIES: &str = "/build.bazel.remote.execution.v2.Capabilities";
+  const ACTION_CACHE: &str = "/build.bazel.remote.execution.v2.ActionCache";
+  const CAS: &str = "/build.bazel.remote.execution.v2.ContentAddressableStorage";
+  const BYTE_STREAM: &str = "/google.bytestream.ByteStream";
+
+  Router::new()
+    .route(
+      &format!("{CAPABILITIES}/GetCapabilities"),
+      post(get_capabilities),
+    )
+    .route(
+      &format!("{ACTION_CACHE}/GetActionResult"),
+      post(get_action_result),
+    )
+    .route(
+      &format!("{ACTION_CACHE}/UpdateActionResult"),
+      post(update_action_result),
+    )
+    .route(&format!("{CAS}/FindMissingBlobs"), post(find_missing_blobs))
+    .route(&format!("{CAS}/BatchUpdateBlobs"), post(batch_update_blobs))
+    .route(&format!("{CAS}/BatchReadBlobs"), post(batch_read_blobs))
+    .route(&format!("{BYTE_STREAM}/Read"), post(read))
+    .route(&format!("{BYTE_STREAM}/Write"), post(write))
+    .route(
+      &format!("{BYTE_STREAM}/QueryWriteStatus"),
+      post(query_write_status),
+    )
+}
+
+/// Serve the gRPC remote cache on `listener` until `signal` resolves
+pub async fn serve(
+  listener: TcpListener,
+  cache: RemoteCache,
+  signal: impl Future<Output = ()>,
+) -> io::Result<()> {
+  tonic::transport::Server::builder()
+    .add_routes(tonic::service::Routes::from(grpc_routes(cache)))
+    .serve_with_incoming_shutdown(TcpListenerStream::new(listener), signal)
+    .await
+    .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Config;
+  use crate::infra::multi_storage::MultiStorageRouter;
+  use prost::Message;
+  use sha2::{Digest as _, Sha256};
+  use tower::util::ServiceExt;
+
+  async fn remote_cache(root: &std::path::Path) -> RemoteCache {
+    let yaml = format!(
+      "buckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\nserviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessToken: secret\n",
+      root.display()
+    );
+    let config = Config::from_yaml_str(&yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let state = AppState::new(MultiStorageRouter::from_config(&config).await.unwrap());
+    RemoteCache::new(move || state.clone())
+  }
+
+  /// Make a unary call through the routes, returning the status and the decoded response
+  async fn call<Req: Message, Resp: Message + Default>(
+    cache: &RemoteCache,
+    path: &str,
+    message: Req,
+    token: &str,
+  ) -> (tonic::Code, Option<Resp>) {
+    let encoded = message.encode_to_vec();
+    let mut frame = vec![0u8];
+    frame.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&encoded);
+    let request = Request::builder()
+      .method("POST")
+      .uri(path)
+      .header("content-type", "application/grpc")
+      .header("authorization", format!("Bearer {}", token))
+      .body(Body::from(frame))
+      .unwrap();
+
+    let response = grpc_routes(cache.clone()).oneshot(request).await.unwrap();
+    let header_code = response
+      .headers()
+      .get("grpc-status")
+      .map(|value| tonic::Code::from_bytes(value.as_bytes()));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    match header_code {
+      Some(code) => (code, None),
+      None => (tonic::Code::Ok, Some(Resp::decode(&body[5..]).unwrap())),
+    }
+  }
+
+  fn digest_of(data: &[u8]) -> Digest {
+    Digest {
+      hash: hex::encode(Sha256::digest(data)),
+      size_bytes: data.len() as i64,
+    }
+  }
+
+  #[tokio::test]
+  async fn test_action_cache_round_trip() {
+    let root = tempfile::tempdir().unwrap();
+    let cache = remote_cache(root.path()).await;
+    let digest = digest_of(b"action");
+    let get = GetActionResultRequest {
+      instance_name: String::new(),
+      action_digest: Some(digest.clone()),
+    };
+    const GET: &str = "/build.bazel.remote.execution.v2.ActionCache/GetActionResult";
+    const UPDATE: &str = "/build.bazel.remote.execution.v2.ActionCache/UpdateActionResult";
+
+    let (code, _) = call::<_, ActionResult>(&cache, GET, get.clone(), "secret").await;
+    assert_eq!(code, tonic::Code::NotFound);
+
+    let result = Bytes::from_static(&[0x20, 0x03, 0x2a, 0x02, b'o', b'k']);
+    let update = UpdateActionResultRequest {
+      instance_name: String::new(),
+      action_digest: Some(digest),
+      action_result: result.clone(),
+    };
+    let (code, _) = call::<_, ActionResult>(&cache, UPDATE, update, "secret").await;
+    assert_eq!(code, tonic::Code::Ok);
+
+    let (code, stored) = call::<_, ActionResult>(&cache, GET, get.clone(), "secret").await;
+    assert_eq!(code, tonic::Code::Ok);
+    assert_eq!(stored.unwrap().0, result);
+
+    let (code, _) = call::<_, ActionResult>(&cache, GET, get, "wrong").await;
+    assert_eq!(code, tonic::Code::Unauthenticated);
+  }
+
+  #[tokio::test]
+  async fn test_cas_batches_check_digests() {
+    let root = tempfile::tempdir().unwrap();
+    let cache = remote_cache(root.path()).await;
+    const FIND: &str =
+      "/build.bazel.remote.execution.v2.ContentAddressableStorage/FindMissingBlobs";
+    const UPDATE: &str =
+      "/build.bazel.remote.execution.v2.ContentAddressableStorage/BatchUpdateBlobs";
+    const READ: &str = "/build.bazel.remote.execution.v2.ContentAddressableStorage/BatchReadBlobs";
+
+    let (blob, empty) = (digest_of(b"blob"), digest_of(b""));
+    let find = FindMissingBlobsRequest {
+      instance_name: String::new(),
+      blob_digests: vec![blob.clone(), empty.clone()],
+    };
+    let (_, missing) =
+      call::<_, FindMissingBlobsResponse>(&cache, FIND, find.clone(), "secret").await;
+    assert_eq!(missing.unwrap().missing_blob_digests, vec![blob.clone()]);
+
+    let update = BatchUpdateBlobsRequest {
+      instance_name: String::new(),
+      requests: vec![
+        UpdateBlob {
+          digest: Some(blob.clone()),
+          data: Bytes::from_static(b"blob"),
+          compressor: 0,
+        },
+        UpdateBlob {
+          digest: Some(digest_of(b"other")),
+          data: Bytes::from_static(b"wrong"),
+          compressor: 0,
+        },
+      ],
+    };
+    let (_, updated) = call::<_, BatchUpdateBlobsResponse>(&cache, UPDATE, update, "secret").await;
+    let codes: Vec<i32> = updated
+      .unwrap()
+      .responses
+      .iter()
+      .map(|response| response.status.as_ref().unwrap().code)
+      .collect();
+    assert_eq!(
+      codes,
+      vec![tonic::Code::Ok as i32, tonic::Code::InvalidArgument as i32]
+    );
+
+    let (_, missing) = call::<_, FindMissingBlobsResponse>(&cache, FIND, find, "secret").await;
+    assert!(missing.unwrap().missing_blob_digests.is_empty());
+
+    let read = BatchReadBlobsRequest {
+      instance_name: String::new(),
+      digests: vec![blob, digest_of(b"other")],
+    };
+    let (_, read) = call::<_, BatchReadBlobsResponse>(&cache, READ, read, "secret").await;
+    let read = read.unwrap().responses;
+    assert_eq!(read[0].data, Bytes::from_static(b"blob"));
+    assert_eq!(
+      read[1].status.as_ref().unwrap().code,
+      tonic::Code::NotFound as i32
+    );
+  }
+
+  #[tokio::test]
+  async fn test_byte_stream_write_and_read() {
+    let root = tempfile::tempdir().unwrap();
+    let cache = remote_cache(root.path()).await;
+    const WRITE: &str = "/google.bytestream.ByteStream/Write";
+    const READ: &str = "/google.bytestream.ByteStream/Read";
+    let digest = digest_of(b"hello world");
+    let write = |data: &'static [u8]| WriteRequest {
+      resource_name: format!("uploads/1/blobs/{}/{}", digest.hash, digest.size_bytes),
+      write_offset: 0,
+      finish_write: true,
+      data: Bytes::from_static(data),
+    };
+
+    let (code, _) = call::<_, WriteResponse>(&cache, WRITE, write(b"hello there"), "secret").await;
+    assert_eq!(code, tonic::Code::InvalidArgument);
+    let (_, written) =
+      call::<_, WriteResponse>(&cache, WRITE, write(b"hello world"), "secret").await;
+    assert_eq!(written.unwrap().committed_size, 11);
+
+    let read = ReadRequest {
+      resource_name: format!("blobs/{}/{}", digest.hash, digest.size_bytes),
+      read_offset: 6,
+      read_limit: 3,
+    };
+    let (_, chunk) = call::<_, ReadResponse>(&cache, READ, read, "secret").await;
+    assert_eq!(chunk.unwrap().data, Bytes::from_static(b"wor"));
+  }
+
+  #[cfg(feature = "buck2")]
+  #[tokio::test]
+  async fn test_http_port_serves_remote_apis() {
+    let root = tempfile::tempdir().unwrap();
+    let state = (remote_cache(root.path()).await.state)();
+    let app = crate::server::create_router(&state).with_state(state);
+    let grpc = |path: &str| {
+      // An empty message: uncompressed, zero length
+      let frame = vec![0u8; 5];
+      Request::builder()
+        .method("POST")
+        .uri(path)
+        .header("content-type", "application/grpc")
+        .header("authorization", "Bearer secret")
+        .body(Body::from(frame))
+        .unwrap()
+    };
+
+    let response = app
+      .clone()
+      .oneshot(grpc(
+        "/build.bazel.remote.execution.v2.Capabilities/GetCapabilities",
+      ))
+      .await
+      .unwrap();
+    assert!(response.headers().get("grpc-status").is_none());
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let capabilities = ServerCapabilities::decode(&body[5..]).unwrap();
+    assert_eq!(
+      capabilities.cache_capabilities.unwrap().digest_functions,
+      vec![DIGEST_FUNCTION_SHA256]
+    );
+
+    // Unknown methods reach the HTTP router's 404 instead of a gRPC fallback
+    let response = app
+      .oneshot(grpc("/build.bazel.remote.execution.v2.Execution/Execute"))
+      .await
+      .unwrap();
+    assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+  }
+
+  #[test]
+  fn test_blob_digest_from_resource_name() {
+    let hash = "ab".repeat(32);
+    let digest = blob_digest(&format!("main/blobs/{}/12", hash), false).unwrap();
+    assert_eq!(
+      (digest.hash.as_str(), digest.size_bytes),
+      (hash.as_str(), 12)
+    );
+
+    let upload = format!("uploads/4d0b2b6e/blobs/{}/12/build.tar", hash);
+    assert_eq!(blob_digest(&upload, true).unwrap().size_bytes, 12);
+    assert!(blob_digest(&upload, false).is_err());
+    assert!(blob_digest(&format!("blobs/{}/12", hash), true).is_err());
+    assert!(blob_digest(&format!("blobs/{}/-1", hash), false).is_err());
+    assert_eq!(
+      blob_digest(&format!("compressed-blobs/zstd/{}/12", hash), false)
+        .unwrap_err()
+        .code(),
+      tonic::Code::Unimplemented
+    );
+  }
+}
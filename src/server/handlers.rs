@@ -1,18 +1,221 @@
-use crate::server::{error::ServerError, middleware::AuthenticatedToken, validation, AppState};
+use crate::domain::config::{Codec, ResolvedServiceAccessToken, MINTED_TOKEN_ISSUER};
+use crate::domain::http_date;
+use crate::domain::storage::{DynAsyncRead, StorageError};
+use crate::infra::bucket_status::BucketStatusReport;
+use crate::infra::multi_storage::{BucketReadiness, UploadOptions};
+use crate::infra::repair::RepairCounts;
+use crate::server::accounting::{self, ExportFormat};
+use crate::server::integrity::{IntegrityCheck, IntegrityFailure};
+use crate::server::recent_errors::ErrorRecord;
+use crate::server::revoked_tokens::{self, Revocation};
+use crate::server::task_metadata::admin_target;
+use crate::server::{
+  encoding,
+  error::{self, ServerError},
+  jwt,
+  middleware::AuthenticatedToken,
+  upstream, validation, AppState,
+};
 use axum::{
   body::Body,
-  extract::{Path, Request, State},
-  http::StatusCode,
-  response::IntoResponse,
+  extract::{Path, Query, Request, State},
+  http::{
+    header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, LAST_MODIFIED, VARY},
+    HeaderMap, HeaderValue, StatusCode,
+  },
+  response::{IntoResponse, Response},
+  Extension, Json,
 };
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio_stream::StreamExt;
 
+/// Header selecting a per-platform variant of an artifact, alternative to `?variant=`
+pub const VARIANT_HEADER: &str = "x-nx-cache-variant";
+
+/// Header requesting a retention in seconds for an upload, capped at the token's maximum
+pub const TTL_HEADER: &str = "x-nx-cache-ttl";
+
+/// Header naming the CI run an upload belongs to, so the run can be purged later
+pub const RUN_ID_HEADER: &str = "x-nx-run-id";
+
+/// Header with the HTTP date an artifact was uploaded, on downloads
+pub const ARTIFACT_CREATED_HEADER: &str = "x-artifact-created";
+
+/// Separates the hash from the variant in the storage name
+const VARIANT_SEPARATOR: char = '~';
+
+#[derive(Debug, Deserialize)]
+pub struct VariantQuery {
+  pub variant: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurgeRunQuery {
+  /// Name of the token whose namespace is purged (defaults to the caller's)
+  pub token: Option<String>,
+}
+
+/// Most artifact names returned in one page of `GET /admin/artifacts`
+const MAX_ARTIFACT_PAGE: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ArtifactListQuery {
+  /// Name of the token whose namespace is listed (defaults to the caller's)
+  pub token: Option<String>,
+  /// `nextCursor` of the previous page
+  pub cursor: Option<String>,
+  /// Page size, at most 1000 (the default)
+  pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactList {
+  pub artifacts: Vec<String>,
+  /// Cursor for the next page, absent on the last page
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgeRunResult {
+  pub run_id: String,
+  pub deleted: usize,
+}
+
+/// One line of the progress streamed by `DELETE /admin/namespaces/{name}`
+#[derive(Debug, Serialize)]
+pub struct PurgeProgress {
+  /// Objects deleted so far
+  pub deleted: usize,
+  pub done: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+/// The calling token as the server sees it
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhoAmI {
+  pub name: String,
+  pub bucket: String,
+  pub prefix: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub notice: Option<String>,
+}
+
+/// A configured token as listed for admins, without its secret
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+  pub name: String,
+  pub bucket: String,
+  pub prefix: String,
+  pub scopes: Vec<&'static str>,
+  /// RFC 3339 time from which the token is rejected
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<String>,
+  /// RFC 3339 time until which the previous access token is still accepted
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub previous_token_valid_until: Option<String>,
+  /// RFC 3339 time the token was revoked at
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub revoked_at: Option<String>,
+}
+
+/// Usage of a token as reported by `GET /admin/tokens/{name}/stats`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenStats {
+  pub name: String,
+  #[serde(flatten)]
+  pub usage: accounting::UsageTotals,
+}
+
+/// Body of `POST /admin/tokens/{name}/mint`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MintRequest {
+  /// Lifetime of the minted token, capped at the configured maximum
+  pub ttl_seconds: Option<u64>,
+}
+
+/// Short-lived token derived from a service token
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MintedToken {
+  /// Name of the service token whose namespace and permissions the token carries
+  pub name: String,
+  pub token: String,
+  /// RFC 3339 time from which the token is rejected
+  pub expires_at: String,
+}
+
+/// Token revoked by `POST /admin/tokens/{name}/revoke`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokedToken {
+  pub name: String,
+  pub revoked_at: String,
+  pub revoked_by: String,
+}
+
+/// Objects stored under a token's prefix as reported by `GET /admin/namespaces/{name}/usage`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceUsage {
+  pub name: String,
+  pub prefix: String,
+  pub objects: u64,
+  /// Stored bytes, after compression or encryption
+  pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VariantList {
+  pub hash: String,
+  pub variants: Vec<String>,
+}
+
+/// Storage name for a hash, with the requested variant appended for tokens that allow variants
+fn variant_name(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  hash: &str,
+  headers: &HeaderMap,
+  query: VariantQuery,
+) -> Result<String, ServerError> {
+  let variant = query.variant.or_else(|| {
+    headers
+      .get(VARIANT_HEADER)
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_string)
+  });
+  let Some(variant) = variant else {
+    return Ok(hash.to_string());
+  };
+
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  if !service.variants {
+    return Err(ServerError::BadRequest);
+  }
+  validation::validate_variant(&variant)?;
+  Ok(format!("{}{}{}", hash, VARIANT_SEPARATOR, variant))
+}
+
 pub async fn store_artifact(
   Path(hash): Path<String>,
   State(state): State<AppState>,
+  Query(query): Query<VariantQuery>,
   request: Request,
 ) -> Result<impl IntoResponse, ServerError> {
-  if validation::validate_hash(&hash).is_err() {
+  if state.hash_policies.nx.validate(&hash).is_err() {
     return Ok((
       StatusCode::FORBIDDEN,
       [("Content-Type", "text/plain")],
@@ -26,6 +229,47 @@ pub async fn store_artifact(
     .get::<AuthenticatedToken>()
     .cloned()
     .ok_or(ServerError::Unauthorized)?;
+  require_write(&state, &token)?;
+
+  let hash = match variant_name(&state, &token, &hash, request.headers(), query) {
+    Ok(name) => name,
+    Err(_) => {
+      return Ok((
+        StatusCode::FORBIDDEN,
+        [("Content-Type", "text/plain")],
+        "Access forbidden",
+      ))
+    },
+  };
+
+  let ttl_seconds = match request.headers().get(TTL_HEADER) {
+    None => None,
+    Some(value) => match value.to_str().ok().and_then(|s| s.parse::<u64>().ok()) {
+      Some(ttl) if ttl > 0 => Some(ttl),
+      _ => {
+        return Ok((
+          StatusCode::FORBIDDEN,
+          [("Content-Type", "text/plain")],
+          "Access forbidden",
+        ))
+      },
+    },
+  };
+
+  let run_id = request
+    .headers()
+    .get(RUN_ID_HEADER)
+    .map(|value| value.to_str().unwrap_or_default().to_string());
+  if run_id
+    .as_deref()
+    .is_some_and(|run_id| validation::validate_run_id(run_id).is_err())
+  {
+    return Ok((
+      StatusCode::FORBIDDEN,
+      [("Content-Type", "text/plain")],
+      "Access forbidden",
+    ));
+  }
 
   // Extract Content-Length header before consuming the request
   let content_length = request
@@ -34,15 +278,42 @@ pub async fn store_artifact(
     .and_then(|v| v.to_str().ok())
     .and_then(|s| s.parse::<u64>().ok());
 
-  // Check if artifact already exists
+  let Ok(check) = IntegrityCheck::from_headers(request.headers()) else {
+    return Ok((
+      StatusCode::BAD_REQUEST,
+      [("Content-Type", "text/plain")],
+      "Invalid checksum header",
+    ));
+  };
+  let Ok(content_encoding) = encoding::content_encoding(request.headers()) else {
+    return Ok((
+      StatusCode::BAD_REQUEST,
+      [("Content-Type", "text/plain")],
+      "Unsupported Content-Encoding",
+    ));
+  };
+  let check = check
+    .with_content_length(content_length)
+    .rejecting_empty(!state.allow_empty_uploads);
+  if content_length == Some(0) && !state.allow_empty_uploads {
+    return Ok(integrity_failure_response(&hash, IntegrityFailure::Empty));
+  }
+
+  let overwrite = state
+    .storage
+    .get_token_config(&token.0)
+    .is_some_and(|service| service.allow_overwrite);
+
+  // Check if artifact already exists, unless the token may replace it
   match state.storage.exists_with_token(&token.0, &hash).await {
-    Ok(true) => {
+    Ok(true) if !overwrite => {
       return Ok((
         StatusCode::CONFLICT,
         [("Content-Type", "text/plain")],
         "Cannot override an existing record",
       ));
     },
+    Ok(true) => tracing::info!("Overwriting existing record: {}", hash),
     Ok(false) => {},
     Err(err) => {
       tracing::error!("Storage error on exists: {}", err);
@@ -54,20 +325,27 @@ pub async fn store_artifact(
     },
   }
 
-  // convert body directly to AsyncRead without buffering
-  let body_stream = request.into_body().into_data_stream();
-
-  // Map the stream to convert axum errors to io::Error
-  let io_stream = body_stream.map(|result| result.map_err(std::io::Error::other));
-
-  let body_reader = tokio_util::io::StreamReader::new(io_stream);
-  let reader_stream = tokio_util::io::ReaderStream::new(body_reader);
-
-  if let Err(err) = state
-    .storage
-    .store_with_token(&token.0, &hash, reader_stream, content_length)
-    .await
+  let options = UploadOptions {
+    ttl_seconds,
+    run_id,
+    overwrite,
+  };
+  if let Err(err) = store_body(
+    &state,
+    &token,
+    &hash,
+    request.into_body(),
+    content_length,
+    content_encoding,
+    options,
+    &check,
+  )
+  .await
   {
+    if let Some(failure) = check.failure() {
+      return Ok(integrity_failure_response(&hash, failure));
+    }
+
     if matches!(err, crate::domain::storage::StorageError::AlreadyExists) {
       return Ok((
         StatusCode::CONFLICT,
@@ -90,9 +368,10 @@ pub async fn store_artifact(
 pub async fn retrieve_artifact(
   Path(hash): Path<String>,
   State(state): State<AppState>,
+  Query(query): Query<VariantQuery>,
   request: Request,
 ) -> Result<impl IntoResponse, ServerError> {
-  validation::validate_hash(&hash)?;
+  state.hash_policies.nx.validate(&hash)?;
 
   // Extract the authenticated token from request extensions
   let token = request
@@ -100,18 +379,1063 @@ pub async fn retrieve_artifact(
     .get::<AuthenticatedToken>()
     .cloned()
     .ok_or(ServerError::Unauthorized)?;
+  require_read(&state, &token)?;
+
+  let name = variant_name(&state, &token, &hash, request.headers(), query)?;
+  let accepted = encoding::accepted_codecs(request.headers());
+  let mut response = match stream_object(&state, &token, &name, &accepted).await {
+    Err(ServerError::Storage(StorageError::NotFound)) => {
+      upstream::fetch(&state, &token, &hash, &name)
+        .await
+        .ok_or(ServerError::Storage(StorageError::NotFound))?
+    },
+    response => response?,
+  };
+  // The same URL serves another object depending on the variant header
+  if state
+    .storage
+    .get_token_config(&token.0)
+    .is_some_and(|service| service.variants)
+  {
+    response
+      .headers_mut()
+      .append(VARY, HeaderValue::from_static(VARIANT_HEADER));
+  }
+  Ok(response)
+}
+
+/// List the variants stored for a hash
+pub async fn list_variants(
+  Path(hash): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<VariantList>, ServerError> {
+  state.hash_policies.nx.validate(&hash)?;
+  require_read(&state, &token)?;
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  if !service.variants {
+    return Err(ServerError::BadRequest);
+  }
 
-  let reader = state.storage.retrieve_with_token(&token.0, &hash).await?;
-  let stream = tokio_util::io::ReaderStream::new(reader);
+  let prefix = format!("{}{}", hash, VARIANT_SEPARATOR);
+  let variants = state
+    .storage
+    .list_with_token(&token.0, &prefix)
+    .await?
+    .into_iter()
+    .filter_map(|name| name.strip_prefix(&prefix).map(str::to_string))
+    .collect();
+
+  Ok(Json(VariantList { hash, variants }))
+}
+
+/// Response for an upload whose body did not match what the client declared
+///
+/// The failed body stream already aborted the storage write, so nothing is left behind.
+pub(crate) fn integrity_failure_response(
+  hash: &str,
+  failure: IntegrityFailure,
+) -> (StatusCode, [(&'static str, &'static str); 1], &'static str) {
+  let message = match failure {
+    IntegrityFailure::Checksum => {
+      tracing::warn!("Rejected upload of {} not matching its checksum", hash);
+      "Checksum mismatch"
+    },
+    IntegrityFailure::Length { expected, received } => {
+      tracing::warn!(
+        "Rejected upload of {}: Content-Length was {} but {} bytes were received",
+        hash,
+        expected,
+        received
+      );
+      "Content-Length mismatch"
+    },
+    IntegrityFailure::Empty => {
+      tracing::warn!("Rejected empty upload of {}", hash);
+      "Empty request body"
+    },
+  };
+  (
+    StatusCode::BAD_REQUEST,
+    [("Content-Type", "text/plain")],
+    message,
+  )
+}
+
+/// Stream a request body into storage without buffering it
+///
+/// A body sent with a `Content-Encoding` is checked as sent and stored decompressed.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn store_body(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  hash: &str,
+  body: Body,
+  content_length: Option<u64>,
+  content_encoding: Option<Codec>,
+  options: UploadOptions,
+  check: &IntegrityCheck,
+) -> Result<(), crate::domain::storage::StorageError> {
+  // convert body directly to AsyncRead without buffering
+  let body_stream = body.into_data_stream();
+
+  // Map the stream to convert axum errors to io::Error, counting the bytes received
+  let received = Arc::new(AtomicU64::new(0));
+  let counter = received.clone();
+  let io_stream = body_stream.map(move |result| {
+    let chunk = result.map_err(std::io::Error::other)?;
+    counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    Ok::<_, std::io::Error>(chunk)
+  });
+
+  let body_reader: DynAsyncRead =
+    Box::new(check.wrap(tokio_util::io::StreamReader::new(io_stream)));
+  let (body_reader, content_length) = match content_encoding {
+    // The decoded length is only known once the upload finished
+    Some(codec) => (encoding::decode(body_reader, codec), None),
+    None => (body_reader, content_length),
+  };
+  let reader_stream = tokio_util::io::ReaderStream::new(body_reader);
+
+  // Held until the upload finished so downloads of the same object can wait for it
+  let _upload = upload_key(state, token, hash).and_then(|key| state.uploads.begin(&key));
+  state
+    .storage
+    .store_with_options(&token.0, hash, reader_stream, content_length, options)
+    .await?;
+  record_upload(state, &token.0, received.load(Ordering::Relaxed));
+  Ok(())
+}
+
+/// Count a stored upload in the token's usage accounting
+pub(crate) fn record_upload(state: &AppState, token: &str, bytes: u64) {
+  if let Some(service) = state.storage.get_token_config(token) {
+    state.accounting.record_upload(&service.name, bytes);
+  }
+}
+
+/// Identifies an object across tokens that share a bucket and prefix
+fn upload_key(state: &AppState, token: &AuthenticatedToken, hash: &str) -> Option<String> {
+  let service = state.storage.get_token_config(&token.0)?;
+  Some(format!("{}{}/{}", service.bucket, service.prefix, hash))
+}
+
+/// Reject tokens whose `permissions` do not allow downloads
+pub(crate) fn require_read(
+  state: &AppState,
+  token: &AuthenticatedToken,
+) -> Result<(), ServerError> {
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  if !service.permissions.can_read() {
+    return Err(ServerError::ReadForbidden);
+  }
+  Ok(())
+}
+
+/// Configuration of the request's token, rejecting tokens without `admin` access
+pub(crate) fn require_admin<'a>(
+  state: &'a AppState,
+  token: &AuthenticatedToken,
+) -> Result<&'a ResolvedServiceAccessToken, ServerError> {
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  if !service.admin {
+    return Err(ServerError::Forbidden);
+  }
+  Ok(service)
+}
+
+/// Reject tokens whose `permissions` do not allow uploads
+pub(crate) fn require_write(
+  state: &AppState,
+  token: &AuthenticatedToken,
+) -> Result<(), ServerError> {
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  if !service.permissions.can_write() {
+    return Err(ServerError::WriteForbidden);
+  }
+  Ok(())
+}
+
+/// Stream a stored object to the client, enforcing and recording the token's egress
+pub(crate) async fn stream_object(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  hash: &str,
+  accepted: &[Codec],
+) -> Result<Response, ServerError> {
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  let token_name = service.name.clone();
+  let immutable = !service.allow_overwrite;
+  if state
+    .egress
+    .is_over_limit(&token_name, service.egress_daily_limit_bytes)
+  {
+    tracing::warn!("Daily egress limit exceeded for token: {}", token_name);
+    state.accounting.record_request(&token_name, false);
+    return Err(ServerError::EgressLimitExceeded);
+  }
+
+  // Looked up alongside the read so the headers cost no extra round trip
+  let (mut reader, mut stat) = tokio::join!(
+    state
+      .storage
+      .retrieve_encoded_with_token(&token.0, hash, accepted),
+    state.storage.stat_with_token(&token.0, hash),
+  );
+  if matches!(reader, Err(StorageError::NotFound)) {
+    if let Some(key) = upload_key(state, token, hash) {
+      if state.uploads.wait_for(&key).await {
+        (reader, stat) = tokio::join!(
+          state
+            .storage
+            .retrieve_encoded_with_token(&token.0, hash, accepted),
+          state.storage.stat_with_token(&token.0, hash),
+        );
+      }
+    }
+  }
+  state.accounting.record_request(&token_name, reader.is_ok());
+  let (reader, codec) = reader?;
+
+  // Objects not stored compressed may be encoded for the transfer instead
+  let size = stat.as_ref().ok().and_then(|stat| stat.size);
+  let transport = match (&state.transport_compression, codec) {
+    (Some(transport), None) => accepted
+      .first()
+      .copied()
+      .filter(|_| size.is_none_or(|size| size >= transport.min_size_bytes))
+      .map(|codec| (codec, transport.level(codec))),
+    _ => None,
+  };
+  let reader = match transport {
+    Some((codec, level)) => encoding::encode(reader, codec, level),
+    None => reader,
+  };
+  let encoding = codec.or(transport.map(|(codec, _)| codec));
+
+  let egress = state.egress.clone();
+  let usage = state.accounting.clone();
+  let stream = tokio_util::io::ReaderStream::new(reader).map(move |chunk| {
+    if let Ok(bytes) = &chunk {
+      egress.record(&token_name, bytes.len() as u64);
+      usage.record_bytes(&token_name, bytes.len() as u64);
+    }
+    chunk
+  });
   let body = Body::from_stream(stream);
 
-  Ok((
+  let mut response = (
     StatusCode::OK,
     [("content-type", "application/octet-stream")],
     body,
+  )
+    .into_response();
+  let headers = response.headers_mut();
+  if let Some(codec) = encoding {
+    // Passed through still compressed or encoded for the transfer; the client decodes it
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static(codec.as_str()));
+  }
+  if codec.is_some() || (state.transport_compression.is_some() && !accepted.is_empty()) {
+    headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+  }
+  // Stored objects never change unless the token may overwrite them
+  if let Some(cache_control) = state.cache_control.as_ref().filter(|_| immutable) {
+    headers.insert(CACHE_CONTROL, cache_control.clone());
+  }
+  // A failed lookup only costs the headers, the artifact is still served
+  if let Ok(stat) = stat {
+    // The stat size is the decoded one, which a compressed body doesn't have
+    if let Some(size) = stat.size.filter(|_| encoding.is_none()) {
+      headers.insert(CONTENT_LENGTH, HeaderValue::from(size));
+    }
+    if let Some(modified) = stat.last_modified {
+      if let Ok(value) = HeaderValue::from_str(&http_date::format(modified)) {
+        headers.insert(LAST_MODIFIED, value);
+      }
+    }
+    if let Some(created) = stat.created_at.or(stat.last_modified) {
+      if let Ok(value) = HeaderValue::from_str(&http_date::format(created)) {
+        headers.insert(ARTIFACT_CREATED_HEADER, value);
+      }
+    }
+  }
+  Ok(response)
+}
+
+/// Name, bucket and prefix of the calling token, with the operator's notice for it
+pub async fn whoami(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<WhoAmI>, ServerError> {
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+
+  Ok(Json(WhoAmI {
+    name: service.name.clone(),
+    bucket: service.bucket.clone(),
+    prefix: service.prefix.clone(),
+    notice: service.notice.clone(),
+  }))
+}
+
+/// All configured tokens sorted by name, for tokens with `admin` access
+pub async fn list_tokens(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<Vec<TokenInfo>>, ServerError> {
+  require_admin(&state, &token)?;
+
+  let mut tokens: Vec<TokenInfo> = state
+    .storage
+    .token_configs()
+    .map(|config| TokenInfo {
+      name: config.name.clone(),
+      bucket: config.bucket.clone(),
+      prefix: config.prefix.clone(),
+      scopes: config.scopes(),
+      expires_at: config.expires_at.map(http_date::format_rfc3339),
+      previous_token_valid_until: config
+        .previous_access_token
+        .as_ref()
+        .map(|previous| http_date::format_rfc3339(previous.valid_until)),
+      revoked_at: state
+        .revoked_tokens
+        .as_ref()
+        .and_then(|revoked| revoked.get(&config.name))
+        .filter(|revocation| revocation.applies_to(config))
+        .map(|revocation| revocation.revoked_at),
+    })
+    .collect();
+  tokens.sort_by(|a, b| a.name.cmp(&b.name));
+  Ok(Json(tokens))
+}
+
+/// Requests, hit rate and bytes transferred of a token over the retained days, for tokens
+/// with `admin` access
+pub async fn token_stats(
+  Path(name): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<TokenStats>, ServerError> {
+  require_admin(&state, &token)?;
+  let target = state
+    .storage
+    .find_token_by_name(&name)
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+
+  Ok(Json(TokenStats {
+    usage: state.accounting.totals(&target.name),
+    name: target.name.clone(),
+  }))
+}
+
+/// Mint a short-lived token bound to the namespace of the named token, for tokens with
+/// `admin` access
+///
+/// The minted token expires after the requested lifetime, at most the configured maximum and
+/// never after the named token itself.
+pub async fn mint_token(
+  Path(name): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  request: Option<Json<MintRequest>>,
+) -> Result<Json<MintedToken>, ServerError> {
+  let minting = state
+    .token_minting
+    .as_ref()
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+  let service = require_admin(&state, &token)?;
+  let target = state
+    .storage
+    .find_token_by_name(&name)
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  let ttl = request
+    .and_then(|Json(request)| request.ttl_seconds)
+    .unwrap_or(minting.default_ttl_seconds)
+    .clamp(1, minting.max_ttl_seconds);
+  let expires_at = target
+    .expires_at
+    .map_or(now + ttl, |expires_at| expires_at.min(now + ttl));
+  let claims = serde_json::json!({
+    "iss": MINTED_TOKEN_ISSUER,
+    "sub": target.name,
+    "iat": now,
+    "exp": expires_at,
+  });
+  tracing::info!(
+    "{} minted a token for {} valid until {}",
+    service.name,
+    target.name,
+    http_date::format_rfc3339(expires_at)
+  );
+
+  Ok(Json(MintedToken {
+    name: target.name.clone(),
+    token: jwt::sign_hs256(&minting.signing_key, &claims),
+    expires_at: http_date::format_rfc3339(expires_at),
+  }))
+}
+
+/// Revoke the named token immediately and store the revocation, for tokens with `admin`
+/// access
+///
+/// Revoking a token again keeps its first revocation unless the token's secret was rotated
+/// since, in which case the new secret is revoked in its place.
+pub async fn revoke_token(
+  Path(name): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<RevokedToken>, ServerError> {
+  let revoked = state
+    .revoked_tokens
+    .as_ref()
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+  let service = require_admin(&state, &token)?;
+  let target = state
+    .storage
+    .find_token_by_name(&name)
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  let revocation = Revocation {
+    revoked_at: http_date::format_rfc3339(now),
+    revoked_by: service.name.clone(),
+    credential_sha256: revoked_tokens::credential_sha256(target),
+  };
+  tracing::warn!("{} revoked token {}", service.name, target.name);
+  let revocation = revoked
+    .revoke(&target.name, revocation)
+    .await
+    .map_err(|e| {
+      tracing::error!(
+        "Token {} is revoked in memory only, storing the revocation failed: {}",
+        target.name,
+        e
+      );
+      ServerError::InternalError
+    })?;
+
+  Ok(Json(RevokedToken {
+    name: target.name.clone(),
+    revoked_at: revocation.revoked_at,
+    revoked_by: revocation.revoked_by,
+  }))
+}
+
+pub async fn egress_stats(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<impl IntoResponse, ServerError> {
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+
+  Ok(Json(
+    state
+      .egress
+      .stats(&service.name, service.egress_daily_limit_bytes),
   ))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AccountingQuery {
+  /// `csv` (default) or `json`
+  pub format: Option<String>,
+}
+
+/// Export per token, per day usage; accounting admins get every token, others only their own
+pub async fn accounting_export(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Query(query): Query<AccountingQuery>,
+) -> Result<Response, ServerError> {
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  let format = match query.format.as_deref() {
+    None => ExportFormat::Csv,
+    Some(value) => ExportFormat::parse(value).ok_or(ServerError::BadRequest)?,
+  };
+
+  let scope = (!service.accounting_admin).then_some(service.name.as_str());
+  let rows = state.accounting.rows(scope);
+  Ok(match format {
+    ExportFormat::Csv => (
+      StatusCode::OK,
+      [("content-type", "text/csv; charset=utf-8")],
+      accounting::to_csv(&rows),
+    )
+      .into_response(),
+    ExportFormat::Json => Json(rows).into_response(),
+  })
+}
+
+/// Most recent errors, newest first, for tokens with `admin` access
+pub async fn recent_errors(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<Vec<ErrorRecord>>, ServerError> {
+  require_admin(&state, &token)?;
+
+  Ok(Json(state.errors.snapshot()))
+}
+
+/// Corrupted reads detected and repaired from replicas, for tokens with `admin` access
+pub async fn repair_stats(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<RepairCounts>, ServerError> {
+  require_admin(&state, &token)?;
+
+  Ok(Json(state.storage.repair_counts()))
+}
+
+/// One page of the artifacts in a token's namespace, for tokens with `admin` access
+pub async fn list_artifacts(
+  State(state): State<AppState>,
+  Query(query): Query<ArtifactListQuery>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<ArtifactList>, ServerError> {
+  let target = admin_target(&state, &token, query.token.as_deref())?;
+  let limit = query
+    .limit
+    .unwrap_or(MAX_ARTIFACT_PAGE)
+    .clamp(1, MAX_ARTIFACT_PAGE);
+
+  let page = state
+    .storage
+    .list_artifacts(&target, query.cursor.as_deref(), limit)
+    .await?;
+  Ok(Json(ArtifactList {
+    artifacts: page.keys,
+    next_cursor: page.next,
+  }))
+}
+
+/// Delete all artifacts uploaded with a run ID, for tokens with `admin` access
+pub async fn purge_run(
+  Path(run_id): Path<String>,
+  State(state): State<AppState>,
+  Query(query): Query<PurgeRunQuery>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<PurgeRunResult>, ServerError> {
+  require_admin(&state, &token)?;
+  validation::validate_run_id(&run_id)?;
+
+  let access_token = match &query.token {
+    Some(name) => state
+      .storage
+      .find_token_by_name(name)
+      .map(|target| target.access_token.clone())
+      .ok_or(ServerError::BadRequest)?,
+    None => token.0.clone(),
+  };
+
+  let deleted = state.storage.purge_run(&access_token, &run_id).await?;
+  Ok(Json(PurgeRunResult { run_id, deleted }))
+}
+
+/// Delete everything under another token's prefix, for tokens with `admin` access
+///
+/// Progress is streamed as newline-delimited JSON: a line per page of deleted objects and a
+/// last line with `done` set, or with the `error` that stopped the purge. The purge keeps
+/// going if the client disconnects.
+pub async fn purge_namespace(
+  Path(name): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Response, ServerError> {
+  let service = require_admin(&state, &token)?;
+  let target = state
+    .storage
+    .find_token_by_name(&name)
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+  if target.prefix.trim_matches('/').is_empty() {
+    return Ok(
+      (
+        StatusCode::BAD_REQUEST,
+        [("Content-Type", "text/plain")],
+        "Token has no prefix to purge",
+      )
+        .into_response(),
+    );
+  }
+  tracing::warn!(
+    "Token {} is purging the namespace of {}",
+    service.name,
+    name
+  );
+
+  let line = |progress: PurgeProgress| {
+    let mut line = serde_json::to_vec(&progress).unwrap_or_default();
+    line.push(b'\n');
+    Ok::<_, std::io::Error>(axum::body::Bytes::from(line))
+  };
+  let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+  let storage = state.storage.clone();
+  let access_token = target.access_token.clone();
+  tokio::spawn(async move {
+    let mut deleted = 0;
+    let result = storage
+      .purge_namespace(&access_token, |count| {
+        deleted = count;
+        let _ = sender.send(line(PurgeProgress {
+          deleted: count,
+          done: false,
+          error: None,
+        }));
+      })
+      .await;
+    let last = match result {
+      Ok(deleted) => PurgeProgress {
+        deleted,
+        done: true,
+        error: None,
+      },
+      Err(err) => {
+        tracing::error!("Purge of namespace {} failed: {}", name, err);
+        PurgeProgress {
+          deleted,
+          done: false,
+          error: Some(err.to_string()),
+        }
+      },
+    };
+    let _ = sender.send(line(last));
+  });
+
+  let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+    receiver.recv().await.map(|line| (line, receiver))
+  });
+  Ok(
+    (
+      StatusCode::OK,
+      [("Content-Type", "application/x-ndjson")],
+      Body::from_stream(body),
+    )
+      .into_response(),
+  )
+}
+
+/// Number and stored size of the objects under another token's prefix, for tokens with
+/// `admin` access
+pub async fn namespace_usage(
+  Path(name): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<NamespaceUsage>, ServerError> {
+  require_admin(&state, &token)?;
+  let target = state
+    .storage
+    .find_token_by_name(&name)
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+
+  let usage = state.storage.namespace_usage(&target.access_token).await?;
+  Ok(Json(NamespaceUsage {
+    name: target.name.clone(),
+    prefix: target.prefix.clone(),
+    objects: usage.objects,
+    bytes: usage.bytes,
+  }))
+}
+
+/// Combined health check, answering like [`liveness`] for existing probes
 pub async fn health_check() -> impl IntoResponse {
+  liveness().await
+}
+
+/// Liveness probe: the process is up and serving HTTP
+pub async fn liveness() -> impl IntoResponse {
   (StatusCode::OK, "OK")
 }
+
+#[derive(Debug, Deserialize)]
+pub struct HealthzQuery {
+  pub verbose: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeepHealth {
+  pub version: &'static str,
+  pub tokens: usize,
+  pub buckets: Vec<BucketStatusReport>,
+}
+
+/// Health check answering like [`liveness`], or with `?verbose=1` the cached connectivity,
+/// last operation times and settings of every bucket
+pub async fn healthz(State(state): State<AppState>, Query(query): Query<HealthzQuery>) -> Response {
+  if !matches!(query.verbose.as_deref(), Some("1" | "true")) {
+    return liveness().await.into_response();
+  }
+  Json(DeepHealth {
+    version: env!("CARGO_PKG_VERSION"),
+    tokens: state.storage.token_names().count(),
+    buckets: state.storage.bucket_statuses(),
+  })
+  .into_response()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+  pub ready: bool,
+  pub buckets: Vec<BucketReadiness>,
+}
+
+/// Readiness probe: every bucket a token is bound to can serve requests
+///
+/// Answers 503 otherwise, so load balancers stop routing to an instance whose backend
+/// is down while its liveness probe keeps it from being restarted.
+pub async fn readiness(State(state): State<AppState>) -> impl IntoResponse {
+  let buckets = state.storage.readiness().await;
+  let ready = buckets.iter().all(|bucket| bucket.ready);
+  if ready {
+    return (StatusCode::OK, Json(Readiness { ready, buckets })).into_response();
+  }
+  let response = (
+    StatusCode::SERVICE_UNAVAILABLE,
+    Json(Readiness { ready, buckets }),
+  )
+    .into_response();
+  error::with_retry_after(response, state.storage.retry_after())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Config;
+  use crate::infra::multi_storage::MultiStorageRouter;
+  use crate::server::revoked_tokens::RevokedTokens;
+  use axum::Router;
+  use tower::ServiceExt;
+
+  /// State for a filesystem bucket `local` under `root`, followed by `yaml` with the
+  /// `serviceAccessTokens` and any JWT or minting settings
+  async fn test_state(root: &std::path::Path, yaml: &str) -> AppState {
+    let yaml = format!(
+      "buckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\n{}",
+      root.display(),
+      yaml
+    );
+    let config = Config::from_yaml_str(&yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let mut state = AppState::new(MultiStorageRouter::from_config(&config).await.unwrap());
+    if !config.jwt.is_empty() {
+      state = state.with_jwt(&config.jwt);
+    }
+    if let Some(minting) = &config.token_minting {
+      state = state.with_token_minting(minting);
+    }
+    state
+  }
+
+  async fn test_app(root: &std::path::Path, yaml: &str) -> Router {
+    let state = test_state(root, yaml).await;
+    crate::server::create_router(&state).with_state(state)
+  }
+
+  fn request(method: &str, uri: &str, token: &str) -> axum::http::request::Builder {
+    Request::builder()
+      .method(method)
+      .uri(uri)
+      .header("authorization", format!("Bearer {}", token))
+  }
+
+  /// Status and body of the response to `request`
+  async fn send(app: &Router, request: Request) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+  }
+
+  async fn get(app: &Router, uri: &str, token: &str) -> (StatusCode, String) {
+    send(app, request("GET", uri, token).body(Body::empty()).unwrap()).await
+  }
+
+  #[tokio::test]
+  async fn test_token_permissions() {
+    let root = tempfile::tempdir().unwrap();
+    let app = test_app(
+      root.path(),
+      "serviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessToken: ci\n    permissions: write\n  - name: dev\n    bucket: local\n    accessToken: dev\n    permissions: read\n",
+    )
+    .await;
+    let put = |token| request("PUT", "/v1/cache/1234", token).body(Body::from("artifact"));
+
+    assert_eq!(
+      send(&app, put("dev").unwrap()).await.0,
+      StatusCode::FORBIDDEN
+    );
+    assert_eq!(send(&app, put("ci").unwrap()).await.0, StatusCode::OK);
+    assert_eq!(
+      get(&app, "/v1/cache/1234", "ci").await.0,
+      StatusCode::FORBIDDEN
+    );
+    assert_eq!(get(&app, "/v1/cache/1234", "dev").await.0, StatusCode::OK);
+  }
+
+  #[tokio::test]
+  async fn test_expired_token() {
+    let root = tempfile::tempdir().unwrap();
+    let state = test_state(
+      root.path(),
+      "serviceAccessTokens:\n  - name: old\n    bucket: local\n    accessToken: old\n    expiresAt: \"2020-01-01T00:00:00Z\"\n  - name: new\n    bucket: local\n    accessToken: new\n    expiresAt: \"2999-01-01T00:00:00Z\"\n",
+    )
+    .await;
+    let app = crate::server::create_router(&state).with_state(state.clone());
+
+    assert_eq!(
+      get(&app, "/v1/whoami", "old").await,
+      (StatusCode::UNAUTHORIZED, "Token expired".to_string())
+    );
+    assert_eq!(state.accounting.totals("old").expired_rejections, 1);
+    assert_eq!(get(&app, "/v1/whoami", "new").await.0, StatusCode::OK);
+  }
+
+  #[tokio::test]
+  async fn test_hashed_token() {
+    let root = tempfile::tempdir().unwrap();
+    let app = test_app(
+      root.path(),
+      &format!(
+        "serviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessTokenHash: \"{}\"\n",
+        bcrypt::hash("hashed-secret", 4).unwrap()
+      ),
+    )
+    .await;
+
+    // Second requests are answered from the checked tokens
+    for _ in 0..2 {
+      assert_eq!(
+        get(&app, "/v1/whoami", "ci.hashed-secret").await.0,
+        StatusCode::OK
+      );
+      for token in ["ci.other-secret", "hashed-secret", "other.hashed-secret"] {
+        assert_eq!(
+          get(&app, "/v1/whoami", token).await.0,
+          StatusCode::UNAUTHORIZED
+        );
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_previous_access_token() {
+    let root = tempfile::tempdir().unwrap();
+    let tokens = |valid_until: &str| {
+      format!(
+        "serviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessToken: current\n    previousAccessToken: old\n    previousAccessTokenValidUntil: \"{}\"\n",
+        valid_until
+      )
+    };
+
+    let app = test_app(root.path(), &tokens("2099-01-01T00:00:00Z")).await;
+    for token in ["current", "old"] {
+      assert_eq!(get(&app, "/v1/whoami", token).await.0, StatusCode::OK);
+    }
+    assert_eq!(
+      get(&app, "/v1/whoami", "other").await.0,
+      StatusCode::UNAUTHORIZED
+    );
+
+    // Once the deadline has passed only the current token is accepted
+    let app = test_app(root.path(), &tokens("2020-01-01T00:00:00Z")).await;
+    assert_eq!(
+      get(&app, "/v1/whoami", "old").await.0,
+      StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(get(&app, "/v1/whoami", "current").await.0, StatusCode::OK);
+  }
+
+  #[tokio::test]
+  async fn test_jwt_token() {
+    let root = tempfile::tempdir().unwrap();
+    let app = test_app(
+      root.path(),
+      "serviceAccessTokens:\n  - name: main\n    bucket: local\n    prefix: /main\n  - name: branches\n    bucket: local\n    prefix: /branches\n    permissions: read\njwt:\n  secret: jwt-secret\n  rules:\n    - token: main\n      claims:\n        ref: refs/heads/main\n    - token: branches\n      claims:\n        ref: refs/heads/*\n",
+    )
+    .await;
+    let jwt = |git_ref: &str, exp: u64| {
+      let claims = serde_json::json!({"ref": git_ref, "exp": exp});
+      crate::server::jwt::sign_hs256("jwt-secret", &claims)
+    };
+
+    let (status, body) = get(
+      &app,
+      "/v1/whoami",
+      &jwt("refs/heads/feature", 4_000_000_000),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"branches\""));
+
+    assert_eq!(
+      get(&app, "/v1/whoami", &jwt("refs/heads/main", 1_000_000)).await,
+      (StatusCode::UNAUTHORIZED, "Token expired".to_string())
+    );
+    assert_eq!(
+      get(&app, "/v1/whoami", &jwt("refs/tags/v1", 4_000_000_000)).await,
+      (StatusCode::UNAUTHORIZED, "Invalid token".to_string())
+    );
+  }
+
+  #[tokio::test]
+  async fn test_revoke_token() {
+    let root = tempfile::tempdir().unwrap();
+    let path = root.path().join("revoked.json");
+    let state = test_state(
+      root.path(),
+      "serviceAccessTokens:\n  - name: orchestrator\n    bucket: local\n    accessToken: admin\n    admin: true\n  - name: ci\n    bucket: local\n    prefix: /ci\n    accessToken: ci\n    previousAccessToken: old-ci\n    previousAccessTokenValidUntil: \"2099-01-01T00:00:00Z\"\n",
+    )
+    .await
+    .with_revoked_tokens(RevokedTokens::load(&path).unwrap());
+    let app = crate::server::create_router(&state).with_state(state);
+    let revoke = |name: &str, token: &str| {
+      let uri = format!("/admin/tokens/{}/revoke", name);
+      let app = app.clone();
+      let request = request("POST", &uri, token).body(Body::empty()).unwrap();
+      async move { send(&app, request).await }
+    };
+
+    assert_eq!(revoke("ci", "ci").await.0, StatusCode::FORBIDDEN);
+    assert_eq!(revoke("unknown", "admin").await.0, StatusCode::NOT_FOUND);
+
+    let (status, body) = revoke("ci", "admin").await;
+    assert_eq!(status, StatusCode::OK);
+    let revoked: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(revoked["name"], "ci");
+    assert_eq!(revoked["revokedBy"], "orchestrator");
+    assert!(std::fs::read_to_string(&path).unwrap().contains("\"ci\""));
+
+    // Both the current and the previous access token are rejected from now on
+    for token in ["ci", "old-ci"] {
+      assert_eq!(
+        get(&app, "/v1/whoami", token).await,
+        (StatusCode::UNAUTHORIZED, "Token revoked".to_string())
+      );
+    }
+
+    let (_, body) = get(&app, "/admin/tokens", "admin").await;
+    let tokens: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(tokens[0]["name"], "ci");
+    assert_eq!(tokens[0]["revokedAt"], revoked["revokedAt"]);
+    assert!(tokens[1].get("revokedAt").is_none());
+  }
+
+  #[tokio::test]
+  async fn test_revoke_rotated_token() {
+    let root = tempfile::tempdir().unwrap();
+    let path = root.path().join("revoked.json");
+    let app = |secret: &str| {
+      let yaml = format!(
+        "serviceAccessTokens:\n  - name: orchestrator\n    bucket: local\n    accessToken: admin\n    admin: true\n  - name: ci\n    bucket: local\n    accessToken: {}\n",
+        secret
+      );
+      let path = path.clone();
+      let root = root.path().to_path_buf();
+      async move {
+        let state = test_state(&root, &yaml)
+          .await
+          .with_revoked_tokens(RevokedTokens::load(&path).unwrap());
+        crate::server::create_router(&state).with_state(state)
+      }
+    };
+    let revoke = |app: Router| async move {
+      let request = request("POST", "/admin/tokens/ci/revoke", "admin")
+        .body(Body::empty())
+        .unwrap();
+      send(&app, request).await.0
+    };
+
+    let leaked = app("leaked").await;
+    assert_eq!(revoke(leaked.clone()).await, StatusCode::OK);
+    assert_eq!(
+      get(&leaked, "/v1/whoami", "leaked").await.0,
+      StatusCode::UNAUTHORIZED
+    );
+
+    // The rotated secret is accepted until it is revoked in turn
+    let rotated = app("rotated").await;
+    assert_eq!(
+      get(&rotated, "/v1/whoami", "rotated").await.0,
+      StatusCode::OK
+    );
+    assert_eq!(revoke(rotated.clone()).await, StatusCode::OK);
+    assert_eq!(
+      get(&rotated, "/v1/whoami", "rotated").await,
+      (StatusCode::UNAUTHORIZED, "Token revoked".to_string())
+    );
+
+    // The stored revocation covers the rotated secret after a restart
+    let restarted = app("rotated").await;
+    assert_eq!(
+      get(&restarted, "/v1/whoami", "rotated").await.0,
+      StatusCode::UNAUTHORIZED
+    );
+  }
+
+  #[tokio::test]
+  async fn test_mint_token() {
+    let root = tempfile::tempdir().unwrap();
+    let app = test_app(
+      root.path(),
+      "serviceAccessTokens:\n  - name: orchestrator\n    bucket: local\n    accessToken: admin\n    admin: true\n  - name: ci\n    bucket: local\n    prefix: /ci\n    accessToken: ci\ntokenMinting:\n  signingKey: minting-key\n  maxTtlSeconds: 600\n",
+    )
+    .await;
+    let mint = |token: &str, body: &str| {
+      request("POST", "/admin/tokens/ci/mint", token)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+    };
+
+    assert_eq!(send(&app, mint("ci", "{}")).await.0, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(&app, mint("admin", r#"{"ttlSeconds": 86400}"#)).await;
+    assert_eq!(status, StatusCode::OK);
+    let minted: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(minted["name"], "ci");
+    let expires_at = http_date::parse_rfc3339(minted["expiresAt"].as_str().unwrap()).unwrap();
+    let now = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    assert!(expires_at <= now + 600);
+
+    let (status, body) = get(&app, "/v1/whoami", minted["token"].as_str().unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("\"ci\""));
+  }
+}
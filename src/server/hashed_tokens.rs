@@ -0,0 +1,215 @@
+use crate::domain::token_hash;
+use crate::infra::multi_storage::MultiStorageRouter;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Matching tokens remembered before they are all forgotten
+const MAX_MATCHED: usize = 10_000;
+
+/// Failing tokens remembered, the least recently presented is forgotten first
+const MAX_FAILED: usize = 10_000;
+
+/// Hash verifications running at the same time; further requests wait for a slot
+const MAX_CONCURRENT_CHECKS: usize = 4;
+
+/// How long unknown secrets of a token are rejected without a check after one failed
+const FAILED_CHECK_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Finds the configured token a bearer token hashes to
+///
+/// Hashed tokens are sent as `<name>.<secret>`, so a request verifies at most the one hash
+/// of the named token. Verification is slow and memory-hungry by design, so only a few run at
+/// once and the outcome is remembered by the SHA-256 of the presented token. Matches and
+/// failures are remembered apart, so guessed secrets can never push out a token that
+/// matched. Checks of one token name run one at a time, and after a failed check further
+/// unknown secrets of that name are rejected for [`FAILED_CHECK_BACKOFF`] without a check, so
+/// guessing one name cannot hold up the logins of the others. A remembered outcome no longer
+/// applies once the token's hash changes, e.g. after a reload.
+pub struct HashedTokens {
+  /// SHA-256 of a presented token that matched -> hash it matched
+  matched: Mutex<HashMap<[u8; 32], String>>,
+  failed: Mutex<FailedTokens>,
+  /// Per token name: when a check of it last failed
+  names: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<Instant>>>>>,
+  checks: Semaphore,
+}
+
+impl Default for HashedTokens {
+  fn default() -> Self {
+    Self {
+      matched: Mutex::new(HashMap::new()),
+      failed: Mutex::new(FailedTokens::default()),
+      names: Mutex::new(HashMap::new()),
+      checks: Semaphore::new(MAX_CONCURRENT_CHECKS),
+    }
+  }
+}
+
+impl HashedTokens {
+  /// Internal key of the token whose `accessTokenHash` `token` matches
+  pub async fn find(&self, storage: &MultiStorageRouter, token: &str) -> Option<String> {
+    let (name, secret) = token.split_once('.')?;
+    let config = storage.find_token_by_name(name)?;
+    let hash = config.access_token_hash.clone()?;
+
+    let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+    if let Some(matched) = self.remembered(&digest, &hash) {
+      return matched.then(|| config.access_token.clone());
+    }
+
+    let name_lock = self
+      .names
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .entry(name.to_string())
+      .or_default()
+      .clone();
+    let mut last_failure = name_lock.lock().await;
+    // Another request may have checked the same token while this one waited
+    if let Some(matched) = self.remembered(&digest, &hash) {
+      return matched.then(|| config.access_token.clone());
+    }
+    if last_failure.is_some_and(|failed| failed.elapsed() < FAILED_CHECK_BACKOFF) {
+      tracing::debug!("Hashed token {} is backing off after a failed check", name);
+      return None;
+    }
+
+    let matched = {
+      let _permit = self.checks.acquire().await.ok()?;
+      let (checked_hash, secret) = (hash.clone(), secret.to_string());
+      tokio::task::spawn_blocking(move || token_hash::verify(&checked_hash, &secret))
+        .await
+        .ok()?
+    };
+    if matched {
+      let mut tokens = self.matched.lock().unwrap_or_else(|e| e.into_inner());
+      if tokens.len() >= MAX_MATCHED {
+        tokens.clear();
+      }
+      tokens.insert(digest, hash);
+    } else {
+      *last_failure = Some(Instant::now());
+      self
+        .failed
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(digest, hash);
+    }
+    matched.then(|| config.access_token.clone())
+  }
+
+  /// Remembered outcome of checking the token with SHA-256 `digest` against `hash`
+  fn remembered(&self, digest: &[u8; 32], hash: &str) -> Option<bool> {
+    let matched = self
+      .matched
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .get(digest)
+      .is_some_and(|matched_hash| matched_hash == hash);
+    if matched {
+      return Some(true);
+    }
+    self
+      .failed
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .contains(digest, hash)
+      .then_some(false)
+  }
+}
+
+/// Tokens that did not match, bounded to [`MAX_FAILED`] by forgetting the least recently
+/// presented
+#[derive(Default)]
+struct FailedTokens {
+  /// SHA-256 of a presented token -> hash it failed and when it was last presented
+  tokens: HashMap<[u8; 32], (String, u64)>,
+  /// Last presentation -> SHA-256 of the token
+  order: BTreeMap<u64, [u8; 32]>,
+  clock: u64,
+}
+
+impl FailedTokens {
+  fn contains(&mut self, digest: &[u8; 32], hash: &str) -> bool {
+    self.clock += 1;
+    match self.tokens.get_mut(digest) {
+      Some((failed_hash, used)) if failed_hash == hash => {
+        self.order.remove(used);
+        *used = self.clock;
+        self.order.insert(self.clock, *digest);
+        true
+      },
+      _ => false,
+    }
+  }
+
+  fn insert(&mut self, digest: [u8; 32], hash: String) {
+    self.clock += 1;
+    if let Some((_, used)) = self.tokens.insert(digest, (hash, self.clock)) {
+      self.order.remove(&used);
+    }
+    self.order.insert(self.clock, digest);
+    while self.tokens.len() > MAX_FAILED {
+      let Some((_, oldest)) = self.order.pop_first() else {
+        break;
+      };
+      self.tokens.remove(&oldest);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Config;
+
+  #[test]
+  fn test_failed_tokens_forget_least_recently_presented() {
+    let mut failed = FailedTokens::default();
+    for i in 0..MAX_FAILED {
+      failed.insert(Sha256::digest(i.to_string()).into(), "hash".to_string());
+    }
+    let first: [u8; 32] = Sha256::digest("0").into();
+    let second: [u8; 32] = Sha256::digest("1").into();
+    assert!(failed.contains(&first, "hash"));
+    assert!(!failed.contains(&first, "rotated"));
+
+    failed.insert(Sha256::digest("new").into(), "hash".to_string());
+    assert_eq!(failed.tokens.len(), MAX_FAILED);
+    assert!(failed.contains(&first, "hash"));
+    assert!(!failed.contains(&second, "hash"));
+  }
+
+  #[tokio::test]
+  async fn test_failed_checks_back_off_per_name() {
+    let root = tempfile::tempdir().unwrap();
+    let hash = bcrypt::hash("secret", 4).unwrap();
+    let yaml = format!(
+      "buckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\nserviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessTokenHash: \"{}\"\n  - name: dev\n    bucket: local\n    accessTokenHash: \"{}\"\n",
+      root.path().display(),
+      hash,
+      hash
+    );
+    let config = Config::from_yaml_str(&yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let storage = MultiStorageRouter::from_config(&config).await.unwrap();
+    let tokens = HashedTokens::default();
+
+    assert!(tokens.find(&storage, "ci.guess").await.is_none());
+    // The right secret of the guessed name waits out the backoff, other names do not
+    assert!(tokens.find(&storage, "ci.secret").await.is_none());
+    assert!(tokens.find(&storage, "dev.secret").await.is_some());
+
+    let name_lock = tokens.names.lock().unwrap()["ci"].clone();
+    *name_lock.lock().await = Instant::now().checked_sub(FAILED_CHECK_BACKOFF);
+    assert!(tokens.find(&storage, "ci.secret").await.is_some());
+    // Matches are remembered apart from failures and skip the backoff
+    assert!(tokens.find(&storage, "ci.guess").await.is_none());
+    assert!(tokens.find(&storage, "ci.secret").await.is_some());
+  }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Tracks uploads in progress so downloads of the same object can wait for them
+///
+/// In wide CI fan-outs several agents often ask for an artifact another agent is still
+/// uploading; waiting briefly turns those misses into hits instead of duplicated work.
+#[derive(Debug, Default)]
+pub struct InFlightUploads {
+  uploads: Mutex<HashMap<String, watch::Receiver<()>>>,
+  wait: Duration,
+}
+
+/// Marks an upload as in progress until dropped
+pub struct UploadGuard {
+  uploads: Arc<InFlightUploads>,
+  key: String,
+  // Dropping the sender wakes every waiter
+  _done: watch::Sender<()>,
+}
+
+impl InFlightUploads {
+  /// Track uploads, letting downloads wait up to `wait` for them (zero disables waiting)
+  pub fn new(wait: Duration) -> Self {
+    Self {
+      uploads: Mutex::default(),
+      wait,
+    }
+  }
+
+  /// Register an upload; `None` if waiting is disabled or the key is already being uploaded
+  pub fn begin(self: &Arc<Self>, key: &str) -> Option<UploadGuard> {
+    if self.wait.is_zero() {
+      return None;
+    }
+    let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+    if uploads.contains_key(key) {
+      return None;
+    }
+    let (done, receiver) = watch::channel(());
+    uploads.insert(key.to_string(), receiver);
+    Some(UploadGuard {
+      uploads: self.clone(),
+      key: key.to_string(),
+      _done: done,
+    })
+  }
+
+  /// Wait for an in-flight upload of `key`, returning whether one finished within the wait
+  pub async fn wait_for(&self, key: &str) -> bool {
+    let receiver = {
+      let uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+      uploads.get(key).cloned()
+    };
+    let Some(mut receiver) = receiver else {
+      return false;
+    };
+
+    tracing::debug!("Waiting up to {:?} for in-flight upload {}", self.wait, key);
+    // changed() fails once the sender is dropped, i.e. the upload finished or failed
+    tokio::time::timeout(self.wait, receiver.changed())
+      .await
+      .is_ok()
+  }
+}
+
+impl Drop for UploadGuard {
+  fn drop(&mut self) {
+    let mut uploads = self
+      .uploads
+      .uploads
+      .lock()
+      .unwrap_or_else(|e| e.into_inner());
+    uploads.remove(&self.key);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_waits_for_upload() {
+    let uploads = Arc::new(InFlightUploads::new(Duration::from_secs(5)));
+    let guard = uploads.begin("ci/abc").unwrap();
+    assert!(uploads.begin("ci/abc").is_none());
+
+    let waiter = {
+      let uploads = uploads.clone();
+      tokio::spawn(async move { uploads.wait_for("ci/abc").await })
+    };
+    tokio::task::yield_now().await;
+    drop(guard);
+
+    assert!(waiter.await.unwrap());
+    assert!(!uploads.wait_for("ci/abc").await);
+  }
+
+  #[tokio::test]
+  async fn test_wait_times_out() {
+    let uploads = Arc::new(InFlightUploads::new(Duration::from_millis(10)));
+    let _guard = uploads.begin("ci/abc").unwrap();
+    assert!(!uploads.wait_for("ci/abc").await);
+  }
+
+  #[test]
+  fn test_disabled() {
+    let uploads = Arc::new(InFlightUploads::new(Duration::ZERO));
+    assert!(uploads.begin("ci/abc").is_none());
+  }
+}
@@ -0,0 +1,244 @@
+use axum::http::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::server::error::ServerError;
+
+/// Header carrying the hex encoded SHA-256 of an upload's body
+pub const CHECKSUM_HEADER: &str = "x-content-sha256";
+
+/// Why an upload was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityFailure {
+  /// The body's SHA-256 differs from the declared checksum
+  Checksum,
+  /// The body is shorter or longer than the declared Content-Length
+  Length { expected: u64, received: u64 },
+  /// The body is empty although empty uploads are rejected
+  Empty,
+}
+
+/// Checks a client declared for an upload, verified while the body streams to storage
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityCheck {
+  sha256: Option<[u8; 32]>,
+  content_length: Option<u64>,
+  reject_empty: bool,
+  failure: Arc<OnceLock<IntegrityFailure>>,
+}
+
+impl IntegrityCheck {
+  /// Read the declared checksum, rejecting values that are not 64 hex characters
+  pub fn from_headers(headers: &HeaderMap) -> Result<Self, ServerError> {
+    let sha256 = match headers.get(CHECKSUM_HEADER) {
+      None => None,
+      Some(value) => {
+        let mut digest = [0u8; 32];
+        let value = value.to_str().map_err(|_| ServerError::BadRequest)?;
+        hex::decode_to_slice(value.trim(), &mut digest).map_err(|_| ServerError::BadRequest)?;
+        Some(digest)
+      },
+    };
+    Ok(Self {
+      sha256,
+      ..Default::default()
+    })
+  }
+
+  /// Require the body's SHA-256 to be `digest`, as for content-addressed keys
+  pub fn expecting_sha256(mut self, digest: [u8; 32]) -> Self {
+    self.sha256 = Some(digest);
+    self
+  }
+
+  /// Also require the body to be exactly `content_length` bytes long, if declared
+  pub fn with_content_length(mut self, content_length: Option<u64>) -> Self {
+    self.content_length = content_length;
+    self
+  }
+
+  /// Also require the body to contain at least one byte
+  pub fn rejecting_empty(mut self, reject: bool) -> Self {
+    self.reject_empty = reject;
+    self
+  }
+
+  /// How the body did not match what the client declared, if it did not
+  pub fn failure(&self) -> Option<IntegrityFailure> {
+    self.failure.get().copied()
+  }
+
+  fn fail(&self, failure: IntegrityFailure) -> io::Error {
+    let _ = self.failure.set(failure);
+    let message = match failure {
+      IntegrityFailure::Checksum => "upload does not match its declared checksum".to_string(),
+      IntegrityFailure::Length { expected, received } => format!(
+        "upload length does not match Content-Length (expected {}, received {})",
+        expected, received
+      ),
+      IntegrityFailure::Empty => "upload body is empty".to_string(),
+    };
+    io::Error::new(io::ErrorKind::InvalidData, message)
+  }
+
+  /// Wrap a body reader so it fails at end of stream if the body does not match
+  ///
+  /// Failing the read makes the storage write fail, so a corrupted upload is never committed.
+  pub fn wrap<R>(&self, inner: R) -> IntegrityReader<R> {
+    IntegrityReader {
+      inner,
+      hasher: self.sha256.map(|_| Sha256::new()),
+      received: 0,
+      check: self.clone(),
+    }
+  }
+}
+
+/// Reader verifying an [`IntegrityCheck`] over the bytes it passes through
+pub struct IntegrityReader<R> {
+  inner: R,
+  hasher: Option<Sha256>,
+  received: u64,
+  check: IntegrityCheck,
+}
+
+impl<R> IntegrityReader<R> {
+  fn length_failure(&self, at_end: bool) -> Option<IntegrityFailure> {
+    let expected = self.check.content_length?;
+    let mismatch = self.received > expected || (at_end && self.received != expected);
+    mismatch.then_some(IntegrityFailure::Length {
+      expected,
+      received: self.received,
+    })
+  }
+
+  fn finish(&mut self) -> io::Result<()> {
+    if self.check.reject_empty && self.received == 0 {
+      return Err(self.check.fail(IntegrityFailure::Empty));
+    }
+    if let Some(failure) = self.length_failure(true) {
+      return Err(self.check.fail(failure));
+    }
+    if let (Some(hasher), Some(expected)) = (self.hasher.take(), self.check.sha256) {
+      if hasher.finalize().as_slice() != expected {
+        return Err(self.check.fail(IntegrityFailure::Checksum));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for IntegrityReader<R> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    let before = buf.filled().len();
+    match Pin::new(&mut this.inner).poll_read(cx, buf) {
+      Poll::Ready(Ok(())) => {
+        let read = &buf.filled()[before..];
+        if read.is_empty() && buf.remaining() > 0 {
+          return Poll::Ready(this.finish());
+        }
+        this.received += read.len() as u64;
+        // Stop as soon as the body runs past its declared length
+        if let Some(failure) = this.length_failure(false) {
+          // A failed read must not hand out data
+          buf.set_filled(before);
+          return Poll::Ready(Err(this.check.fail(failure)));
+        }
+        if let Some(hasher) = &mut this.hasher {
+          hasher.update(read);
+        }
+        Poll::Ready(Ok(()))
+      },
+      other => other,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::io::AsyncReadExt;
+
+  fn check(sha256: &str) -> IntegrityCheck {
+    let mut headers = HeaderMap::new();
+    headers.insert(CHECKSUM_HEADER, sha256.parse().unwrap());
+    IntegrityCheck::from_headers(&headers).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_matching_checksum_passes() {
+    let check = check("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+    let mut data = Vec::new();
+    check
+      .wrap(&b"hello"[..])
+      .read_to_end(&mut data)
+      .await
+      .unwrap();
+
+    assert_eq!(data, b"hello");
+    assert_eq!(check.failure(), None);
+  }
+
+  #[tokio::test]
+  async fn test_mismatch_fails_at_end_of_stream() {
+    let check = check("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+    let mut data = Vec::new();
+    let result = check.wrap(&b"hellO"[..]).read_to_end(&mut data).await;
+
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(check.failure(), Some(IntegrityFailure::Checksum));
+  }
+
+  #[tokio::test]
+  async fn test_length_mismatch() {
+    for (body, expected) in [(&b"hell"[..], 5), (&b"hello!"[..], 5)] {
+      let check = IntegrityCheck::default().with_content_length(Some(expected));
+      let mut data = Vec::new();
+      assert!(check.wrap(body).read_to_end(&mut data).await.is_err());
+      assert_eq!(
+        check.failure(),
+        Some(IntegrityFailure::Length {
+          expected,
+          received: body.len() as u64
+        })
+      );
+    }
+
+    let check = IntegrityCheck::default().with_content_length(Some(5));
+    let mut data = Vec::new();
+    check
+      .wrap(&b"hello"[..])
+      .read_to_end(&mut data)
+      .await
+      .unwrap();
+    assert_eq!(check.failure(), None);
+  }
+
+  #[tokio::test]
+  async fn test_empty_body() {
+    let check = IntegrityCheck::default().rejecting_empty(true);
+    let mut data = Vec::new();
+    assert!(check.wrap(&b""[..]).read_to_end(&mut data).await.is_err());
+    assert_eq!(check.failure(), Some(IntegrityFailure::Empty));
+
+    let check = IntegrityCheck::default();
+    check.wrap(&b""[..]).read_to_end(&mut data).await.unwrap();
+    assert_eq!(check.failure(), None);
+  }
+
+  #[test]
+  fn test_invalid_header_is_rejected() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CHECKSUM_HEADER, "not-hex".parse().unwrap());
+    assert!(IntegrityCheck::from_headers(&headers).is_err());
+  }
+}
@@ -0,0 +1,751 @@
+use crate::domain::config::{JwtKeySource, JwtRuleConfig, ResolvedJwtConfig};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Sha256, Sha384, Sha512};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Shortest time between two key set downloads triggered by an unknown `kid`
+const MIN_JWKS_REFRESH: Duration = Duration::from_secs(30);
+
+/// How long a key set download may take
+const JWKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum JwtError {
+  #[error("malformed token: {0}")]
+  Malformed(&'static str),
+  #[error("unsupported algorithm {0}")]
+  UnsupportedAlgorithm(String),
+  #[error("no key matches kid {0:?}")]
+  UnknownKey(Option<String>),
+  #[error("invalid signature")]
+  InvalidSignature,
+  #[error("token expired")]
+  Expired,
+  #[error("token not yet valid")]
+  NotYetValid,
+  #[error("issuer {0:?} not accepted")]
+  Issuer(Option<String>),
+  #[error("audience not accepted")]
+  Audience,
+  #[error("no rule matches the token's claims")]
+  NoMatchingRule,
+  #[error("failed to fetch keys: {0}")]
+  Jwks(String),
+}
+
+/// Verifies JWT bearer tokens and maps their claims to a configured service token
+///
+/// Each configured provider verifies the tokens its issuer signs; providers without an
+/// issuer are tried for every token.
+pub struct JwtAuth {
+  providers: Vec<Provider>,
+}
+
+struct Provider {
+  name: String,
+  keys: Keys,
+  issuer: Option<String>,
+  audience: Option<String>,
+  leeway_seconds: u64,
+  subject_is_token: bool,
+  rules: Vec<JwtRuleConfig>,
+}
+
+enum Keys {
+  Secret(Vec<u8>),
+  Jwks(JwksCache),
+}
+
+#[derive(Deserialize)]
+struct Header {
+  alg: String,
+  kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IssuerClaim {
+  iss: Option<String>,
+}
+
+impl JwtAuth {
+  pub fn new(providers: &[ResolvedJwtConfig]) -> Self {
+    Self {
+      providers: providers.iter().map(Provider::new).collect(),
+    }
+  }
+
+  /// Name of the service token the JWT stands for
+  pub async fn verify(&self, token: &str) -> Result<String, JwtError> {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or(0);
+    // The issuer only selects the providers to try; each of them verifies it again
+    let issuer = token
+      .split('.')
+      .nth(1)
+      .and_then(|payload| URL_SAFE_NO_PAD.decode(payload).ok())
+      .and_then(|payload| serde_json::from_slice::<IssuerClaim>(&payload).ok())
+      .and_then(|claims| claims.iss);
+    let mut result = Err(JwtError::Issuer(issuer.clone()));
+    for provider in &self.providers {
+      if provider.issuer.is_some() && provider.issuer != issuer {
+        continue;
+      }
+      result = provider.verify(token, now).await;
+      match &result {
+        Ok(_) => break,
+        Err(err) => tracing::debug!("{} provider rejected JWT: {}", provider.name, err),
+      }
+    }
+    result
+  }
+}
+
+impl Provider {
+  fn new(config: &ResolvedJwtConfig) -> Self {
+    let max_age = Duration::from_secs(config.jwks_cache_seconds);
+    let keys = match &config.keys {
+      JwtKeySource::Secret(secret) => Keys::Secret(secret.as_bytes().to_vec()),
+      JwtKeySource::JwksUrl(url) => {
+        Keys::Jwks(JwksCache::new(JwksLocation::Url(url.clone()), max_age))
+      },
+      JwtKeySource::Discovery(issuer) => Keys::Jwks(JwksCache::new(
+        JwksLocation::Discovery(issuer.clone()),
+        max_age,
+      )),
+    };
+    Self {
+      name: config.provider.clone(),
+      keys,
+      issuer: config.issuer.clone(),
+      audience: config.audience.clone(),
+      leeway_seconds: config.leeway_seconds,
+      subject_is_token: config.subject_is_token,
+      rules: config.rules.clone(),
+    }
+  }
+
+  /// Name of the service token the JWT's subject names, else of the first rule its claims
+  /// match
+  async fn verify(&self, token: &str, now: u64) -> Result<String, JwtError> {
+    let claims = self.verify_at(token, now).await?;
+    if self.subject_is_token {
+      return claims
+        .get("sub")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or(JwtError::NoMatchingRule);
+    }
+    self
+      .rules
+      .iter()
+      .find(|rule| rule_matches(rule, &claims))
+      .map(|rule| rule.token.clone())
+      .ok_or(JwtError::NoMatchingRule)
+  }
+
+  /// Claims of a JWT whose signature and registered claims are valid at `now`
+  async fn verify_at(&self, token: &str, now: u64) -> Result<Map<String, Value>, JwtError> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+      (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+      return Err(JwtError::Malformed("expected three parts"));
+    };
+    let decode = |part: &str| {
+      URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| JwtError::Malformed("invalid base64url"))
+    };
+    let header: Header = serde_json::from_slice(&decode(header)?)
+      .map_err(|_| JwtError::Malformed("invalid header"))?;
+    let signature = decode(signature)?;
+    let message = &token[..token.len() - signature_len(token)];
+
+    match &self.keys {
+      Keys::Secret(secret) => verify_hmac(&header.alg, secret, message.as_bytes(), &signature)?,
+      Keys::Jwks(cache) => {
+        let jwk = cache.key(header.kid.as_deref()).await?;
+        jwk.verify(&header.alg, message.as_bytes(), &signature)?
+      },
+    }
+
+    let claims: Map<String, Value> = serde_json::from_slice(&decode(payload)?)
+      .map_err(|_| JwtError::Malformed("invalid claims"))?;
+    self.check_claims(&claims, now)?;
+    Ok(claims)
+  }
+
+  /// Check `exp`, `nbf`, `iss` and `aud`; `exp` is required so tokens are short-lived
+  fn check_claims(&self, claims: &Map<String, Value>, now: u64) -> Result<(), JwtError> {
+    let time = |name: &str| claims.get(name).and_then(Value::as_u64);
+    let exp = time("exp").ok_or(JwtError::Malformed("missing exp claim"))?;
+    if exp.saturating_add(self.leeway_seconds) <= now {
+      return Err(JwtError::Expired);
+    }
+    if time("nbf").is_some_and(|nbf| nbf > now.saturating_add(self.leeway_seconds)) {
+      return Err(JwtError::NotYetValid);
+    }
+    if let Some(issuer) = &self.issuer {
+      let iss = claims.get("iss").and_then(Value::as_str);
+      if iss != Some(issuer.as_str()) {
+        return Err(JwtError::Issuer(iss.map(str::to_string)));
+      }
+    }
+    if let Some(audience) = &self.audience {
+      let accepted = match claims.get("aud") {
+        Some(Value::String(aud)) => aud == audience,
+        Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+        _ => false,
+      };
+      if !accepted {
+        return Err(JwtError::Audience);
+      }
+    }
+    Ok(())
+  }
+}
+
+/// HS256 token with the given claims, signed with `secret`
+pub fn sign_hs256(secret: &str, claims: &Value) -> String {
+  let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+  let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+  let message = format!("{}.{}", header, payload);
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+  mac.update(message.as_bytes());
+  let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+  format!("{}.{}", message, signature)
+}
+
+/// Whether `token` has the shape of a JWT rather than a static token
+pub fn is_jwt(token: &str) -> bool {
+  token.split('.').count() == 3 && token.starts_with("eyJ")
+}
+
+/// Length of the signature part including its leading dot
+fn signature_len(token: &str) -> usize {
+  token.rsplit('.').next().map_or(0, |s| s.len() + 1)
+}
+
+/// Whether the JWT carries every claim the rule requires
+fn rule_matches(rule: &JwtRuleConfig, claims: &Map<String, Value>) -> bool {
+  rule
+    .claims
+    .iter()
+    .all(|(name, pattern)| match claims.get(name) {
+      Some(Value::Array(values)) => values.iter().any(|value| claim_matches(pattern, value)),
+      Some(value) => claim_matches(pattern, value),
+      None => false,
+    })
+}
+
+fn claim_matches(pattern: &str, value: &Value) -> bool {
+  match value {
+    Value::String(value) => glob_matches(pattern, value),
+    Value::Number(_) | Value::Bool(_) => glob_matches(pattern, &value.to_string()),
+    _ => false,
+  }
+}
+
+/// Match `value` against a pattern in which `*` stands for any run of characters
+pub(crate) fn glob_matches(pattern: &str, value: &str) -> bool {
+  let mut pieces = pattern.split('*');
+  let first = pieces.next().unwrap_or_default();
+  let Some(mut rest) = value.strip_prefix(first) else {
+    return false;
+  };
+  let pieces: Vec<&str> = pieces.collect();
+  let Some((last, middle)) = pieces.split_last() else {
+    return rest.is_empty();
+  };
+  for piece in middle {
+    match rest.find(piece) {
+      Some(index) => rest = &rest[index + piece.len()..],
+      None => return false,
+    }
+  }
+  rest.len() >= last.len() && rest.ends_with(last)
+}
+
+fn verify_hmac(alg: &str, secret: &[u8], message: &[u8], signature: &[u8]) -> Result<(), JwtError> {
+  fn verify<M: Mac + hmac::digest::KeyInit>(
+    secret: &[u8],
+    message: &[u8],
+    signature: &[u8],
+  ) -> Result<(), JwtError> {
+    let mut mac = <M as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    mac
+      .verify_slice(signature)
+      .map_err(|_| JwtError::InvalidSignature)
+  }
+  match alg {
+    "HS256" => verify::<Hmac<Sha256>>(secret, message, signature),
+    "HS384" => verify::<Hmac<Sha384>>(secret, message, signature),
+    "HS512" => verify::<Hmac<Sha512>>(secret, message, signature),
+    other => Err(JwtError::UnsupportedAlgorithm(other.to_string())),
+  }
+}
+
+/// Public key of a JSON Web Key Set
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+  kty: String,
+  kid: Option<String>,
+  alg: Option<String>,
+  n: Option<String>,
+  e: Option<String>,
+  crv: Option<String>,
+  x: Option<String>,
+  y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+  keys: Vec<Jwk>,
+}
+
+impl Jwk {
+  /// Verify with this key, refusing algorithms that do not fit its type
+  fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> Result<(), JwtError> {
+    if self.alg.as_deref().is_some_and(|key_alg| key_alg != alg) {
+      return Err(JwtError::UnsupportedAlgorithm(alg.to_string()));
+    }
+    let param = |value: &Option<String>| {
+      value
+        .as_deref()
+        .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        .ok_or(JwtError::Jwks(format!("incomplete {} key", self.kty)))
+    };
+    match (self.kty.as_str(), alg) {
+      ("RSA", "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512") => {
+        let params: &'static signature::RsaParameters = match alg {
+          "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+          "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+          "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+          "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+          "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+          _ => &signature::RSA_PSS_2048_8192_SHA512,
+        };
+        let (n, e) = (param(&self.n)?, param(&self.e)?);
+        RsaPublicKeyComponents { n: &n, e: &e }
+          .verify(params, message, signature)
+          .map_err(|_| JwtError::InvalidSignature)
+      },
+      ("EC", "ES256" | "ES384") => {
+        let (curve, params): (_, &'static signature::EcdsaVerificationAlgorithm) = match alg {
+          "ES256" => ("P-256", &signature::ECDSA_P256_SHA256_FIXED),
+          _ => ("P-384", &signature::ECDSA_P384_SHA384_FIXED),
+        };
+        if self.crv.as_deref() != Some(curve) {
+          return Err(JwtError::UnsupportedAlgorithm(alg.to_string()));
+        }
+        let mut point = vec![0x04];
+        point.extend(param(&self.x)?);
+        point.extend(param(&self.y)?);
+        UnparsedPublicKey::new(params, point)
+          .verify(message, signature)
+          .map_err(|_| JwtError::InvalidSignature)
+      },
+      _ => Err(JwtError::UnsupportedAlgorithm(alg.to_string())),
+    }
+  }
+}
+
+/// Where a key set is downloaded from
+enum JwksLocation {
+  Url(String),
+  /// The `jwks_uri` of the issuer's OpenID provider configuration
+  Discovery(String),
+}
+
+#[derive(Deserialize)]
+struct ProviderMetadata {
+  issuer: String,
+  jwks_uri: String,
+}
+
+/// Key set downloaded from a URL, refreshed when stale or when a token names an unknown key
+///
+/// Downloads are at least [`MIN_JWKS_REFRESH`] apart, whether they succeed or not, and only
+/// one runs at a time; requests arriving meanwhile use the keys already cached.
+struct JwksCache {
+  location: JwksLocation,
+  max_age: Duration,
+  cached: RwLock<Option<(Instant, Vec<Jwk>)>>,
+  /// Start of the last download, successful or not
+  attempted: Mutex<Option<Instant>>,
+  refreshing: tokio::sync::Mutex<()>,
+}
+
+impl JwksCache {
+  fn new(location: JwksLocation, max_age: Duration) -> Self {
+    Self {
+      location,
+      max_age,
+      cached: RwLock::new(None),
+      attempted: Mutex::new(None),
+      refreshing: tokio::sync::Mutex::new(()),
+    }
+  }
+
+  fn lookup(&self, kid: Option<&str>) -> (Option<Jwk>, Option<Instant>) {
+    let cached = self.cached.read().unwrap_or_else(|e| e.into_inner());
+    let Some((fetched, keys)) = cached.as_ref() else {
+      return (None, None);
+    };
+    let key = keys
+      .iter()
+      .find(|key| kid.is_none() || key.kid.as_deref() == kid)
+      .cloned();
+    (key, Some(*fetched))
+  }
+
+  fn may_refresh(&self) -> bool {
+    self
+      .attempted
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .is_none_or(|attempted| attempted.elapsed() >= MIN_JWKS_REFRESH)
+  }
+
+  async fn key(&self, kid: Option<&str>) -> Result<Jwk, JwtError> {
+    let (key, fetched) = self.lookup(kid);
+    let stale = fetched.is_none_or(|fetched| fetched.elapsed() >= self.max_age);
+    if (key.is_some() && !stale) || !self.may_refresh() {
+      return self.cached_key(key, fetched, kid);
+    }
+
+    let _refreshing = self.refreshing.lock().await;
+    // Another request may have refreshed the keys while this one waited
+    if self.may_refresh() {
+      *self.attempted.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+      match self.fetch().await {
+        Ok(keys) => {
+          *self.cached.write().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), keys));
+        },
+        // Keep verifying with the keys we have while the key set is unreachable
+        Err(err) if key.is_some() => tracing::warn!("Keeping cached JWT keys: {}", err),
+        Err(err) => return Err(err),
+      }
+    }
+    let (key, fetched) = self.lookup(kid);
+    self.cached_key(key, fetched, kid)
+  }
+
+  /// `key` as looked up in the cache, or why there is none
+  fn cached_key(
+    &self,
+    key: Option<Jwk>,
+    fetched: Option<Instant>,
+    kid: Option<&str>,
+  ) -> Result<Jwk, JwtError> {
+    match (key, fetched) {
+      (Some(key), _) => Ok(key),
+      (None, Some(_)) => Err(JwtError::UnknownKey(kid.map(str::to_string))),
+      (None, None) => Err(JwtError::Jwks(
+        "key set unavailable, retrying shortly".to_string(),
+      )),
+    }
+  }
+
+  async fn fetch(&self) -> Result<Vec<Jwk>, JwtError> {
+    let url = match &self.location {
+      JwksLocation::Url(url) => url.clone(),
+      JwksLocation::Discovery(issuer) => {
+        let metadata: ProviderMetadata =
+          get_json(&format!("{}/.well-known/openid-configuration", issuer)).await?;
+        if metadata.issuer.trim_end_matches('/') != issuer {
+          return Err(JwtError::Jwks(format!(
+            "discovery document names issuer {}",
+            metadata.issuer
+          )));
+        }
+        metadata.jwks_uri
+      },
+    };
+    let set: JwkSet = get_json(&url).await?;
+    tracing::debug!("Fetched {} JWT key(s) from {}", set.keys.len(), url);
+    Ok(set.keys)
+  }
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, JwtError> {
+  static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+  let client = CLIENT.get_or_init(reqwest::Client::new);
+  let response = client
+    .get(url)
+    .timeout(JWKS_TIMEOUT)
+    .send()
+    .await
+    .and_then(reqwest::Response::error_for_status)
+    .map_err(|e| JwtError::Jwks(format!("{}: {}", url, e)))?;
+  let body = response
+    .bytes()
+    .await
+    .map_err(|e| JwtError::Jwks(format!("{}: {}", url, e)))?;
+  serde_json::from_slice(&body).map_err(|e| JwtError::Jwks(format!("{}: {}", url, e)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::BTreeMap;
+
+  fn config(rules: Vec<JwtRuleConfig>) -> ResolvedJwtConfig {
+    ResolvedJwtConfig {
+      provider: "jwt".to_string(),
+      keys: JwtKeySource::Secret("secret".to_string()),
+      issuer: Some("https://ci.example.com".to_string()),
+      audience: Some("nx-cache".to_string()),
+      leeway_seconds: 60,
+      jwks_cache_seconds: 3600,
+      subject_is_token: false,
+      rules,
+    }
+  }
+
+  fn rule(token: &str, claims: &[(&str, &str)]) -> JwtRuleConfig {
+    JwtRuleConfig {
+      token: token.to_string(),
+      claims: claims
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<BTreeMap<_, _>>(),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_verify_maps_claims_to_token() {
+    let auth = JwtAuth::new(&[config(vec![
+      rule(
+        "main",
+        &[("repository", "acme/web"), ("ref", "refs/heads/main")],
+      ),
+      rule("branches", &[("repository", "acme/*")]),
+    ])]);
+    let claims = |git_ref: &str, repository: &str| {
+      serde_json::json!({
+        "iss": "https://ci.example.com",
+        "aud": ["nx-cache", "other"],
+        "exp": 4_000_000_000u64,
+        "repository": repository,
+        "ref": git_ref,
+      })
+    };
+
+    let token = sign_hs256("secret", &claims("refs/heads/main", "acme/web"));
+    assert!(is_jwt(&token));
+    assert_eq!(auth.verify(&token).await, Ok("main".to_string()));
+    let token = sign_hs256("secret", &claims("refs/heads/feature", "acme/web"));
+    assert_eq!(auth.verify(&token).await, Ok("branches".to_string()));
+    let token = sign_hs256("secret", &claims("refs/heads/main", "other/web"));
+    assert_eq!(auth.verify(&token).await, Err(JwtError::NoMatchingRule));
+  }
+
+  #[tokio::test]
+  async fn test_verify_takes_token_from_subject() {
+    let auth = JwtAuth::new(&[ResolvedJwtConfig {
+      subject_is_token: true,
+      ..config(Vec::new())
+    }]);
+    let claims = |sub: Option<&str>| {
+      serde_json::json!({
+        "iss": "https://ci.example.com",
+        "aud": "nx-cache",
+        "exp": 4_000_000_000u64,
+        "sub": sub,
+      })
+    };
+
+    // The subject is returned as is, for an exact lookup rather than as a pattern
+    let token = sign_hs256("secret", &claims(Some("ci*")));
+    assert_eq!(auth.verify(&token).await, Ok("ci*".to_string()));
+    let token = sign_hs256("secret", &claims(None));
+    assert_eq!(auth.verify(&token).await, Err(JwtError::NoMatchingRule));
+  }
+
+  #[tokio::test]
+  async fn test_verify_rejects_invalid_tokens() {
+    let auth = Provider::new(&config(vec![rule("ci", &[])]));
+    let claims = serde_json::json!({
+      "iss": "https://ci.example.com",
+      "aud": "nx-cache",
+      "exp": 1_000_000u64,
+    });
+    let token = sign_hs256("secret", &claims);
+    assert!(auth.verify_at(&token, 1_000_000).await.is_ok());
+    assert_eq!(
+      auth.verify_at(&token, 1_000_060).await,
+      Err(JwtError::Expired)
+    );
+
+    let forged = sign_hs256("other", &claims);
+    assert_eq!(
+      auth.verify_at(&forged, 0).await,
+      Err(JwtError::InvalidSignature)
+    );
+
+    let unsigned = format!(
+      "{}.{}.",
+      URL_SAFE_NO_PAD.encode(br#"{"alg":"none"}"#),
+      URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    assert_eq!(
+      auth.verify_at(&unsigned, 0).await,
+      Err(JwtError::UnsupportedAlgorithm("none".to_string()))
+    );
+
+    let wrong_audience = sign_hs256(
+      "secret",
+      &serde_json::json!({"iss": "https://ci.example.com", "aud": "x", "exp": 1_000_000u64}),
+    );
+    assert_eq!(
+      auth.verify_at(&wrong_audience, 0).await,
+      Err(JwtError::Audience)
+    );
+    let no_expiry = sign_hs256(
+      "secret",
+      &serde_json::json!({"iss": "https://ci.example.com", "aud": "nx-cache"}),
+    );
+    assert!(matches!(
+      auth.verify_at(&no_expiry, 0).await,
+      Err(JwtError::Malformed(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_verify_with_discovered_keys() {
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+    let key =
+      EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+    let point = key.public_key().as_ref();
+    let jwks = serde_json::json!({"keys": [{
+      "kty": "EC",
+      "kid": "k1",
+      "crv": "P-256",
+      "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+      "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+    }]});
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let metadata = serde_json::json!({"issuer": issuer, "jwks_uri": format!("{}/keys", issuer)});
+    let app = axum::Router::new()
+      .route(
+        "/.well-known/openid-configuration",
+        axum::routing::get(move || async move { axum::Json(metadata) }),
+      )
+      .route(
+        "/keys",
+        axum::routing::get(move || async move { axum::Json(jwks) }),
+      );
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let auth = JwtAuth::new(&[
+      config(vec![rule("static", &[])]),
+      ResolvedJwtConfig {
+        provider: "oidc".to_string(),
+        keys: JwtKeySource::Discovery(issuer.clone()),
+        issuer: Some(issuer.clone()),
+        audience: Some("nx-cache".to_string()),
+        leeway_seconds: 60,
+        jwks_cache_seconds: 3600,
+        subject_is_token: false,
+        rules: vec![rule("developers", &[("sub", "*@acme.com")])],
+      },
+    ]);
+    let sign = |kid: &str, sub: &str| {
+      let header = URL_SAFE_NO_PAD.encode(format!(r#"{{"alg":"ES256","kid":"{}"}}"#, kid));
+      let claims =
+        serde_json::json!({"iss": issuer, "aud": "nx-cache", "sub": sub, "exp": 4_000_000_000u64});
+      let message = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+      let signature = key.sign(&rng, message.as_bytes()).unwrap();
+      format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    };
+
+    assert_eq!(
+      auth.verify(&sign("k1", "jane@acme.com")).await,
+      Ok("developers".to_string())
+    );
+    assert_eq!(
+      auth.verify(&sign("k1", "jane@example.com")).await,
+      Err(JwtError::NoMatchingRule)
+    );
+    assert_eq!(
+      auth.verify(&sign("k2", "jane@acme.com")).await,
+      Err(JwtError::UnknownKey(Some("k2".to_string())))
+    );
+    server.abort();
+  }
+
+  #[tokio::test]
+  async fn test_jwks_refresh_is_throttled_and_shared() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let failing = Arc::new(AtomicBool::new(false));
+    let (counter, outage) = (requests.clone(), failing.clone());
+    let app = axum::Router::new().route(
+      "/keys",
+      axum::routing::get(move || async move {
+        counter.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if outage.load(Ordering::SeqCst) {
+          Err(axum::http::StatusCode::SERVICE_UNAVAILABLE)
+        } else {
+          Ok(axum::Json(
+            serde_json::json!({"keys": [{"kty": "oct", "kid": "k1"}]}),
+          ))
+        }
+      }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/keys", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    // Every key set is stale at once, so only the refresh throttle limits downloads
+    let cache = JwksCache::new(JwksLocation::Url(url), Duration::ZERO);
+    assert!(cache.key(Some("k1")).await.is_ok());
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // During an outage concurrent requests share one failed download and keep the old keys
+    failing.store(true, Ordering::SeqCst);
+    *cache.attempted.lock().unwrap() = Instant::now().checked_sub(MIN_JWKS_REFRESH);
+    let keys = futures_util::future::join_all((0..5).map(|_| cache.key(Some("k1")))).await;
+    assert!(keys.iter().all(Result::is_ok));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // The failed download is not retried before the minimum refresh interval
+    assert!(cache.key(Some("k1")).await.is_ok());
+    assert!(matches!(
+      cache.key(Some("k2")).await,
+      Err(JwtError::UnknownKey(_))
+    ));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    server.abort();
+  }
+
+  #[test]
+  fn test_glob_matches() {
+    assert!(glob_matches("acme/web", "acme/web"));
+    assert!(!glob_matches("acme/web", "acme/web2"));
+    assert!(glob_matches("acme/*", "acme/web"));
+    assert!(glob_matches("*", ""));
+    assert!(glob_matches("refs/*/release-*", "refs/heads/release-1.2"));
+    assert!(!glob_matches("refs/*/release-*", "refs/heads/main"));
+    assert!(!glob_matches("a*a", "a"));
+  }
+}
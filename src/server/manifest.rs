@@ -0,0 +1,279 @@
+use crate::domain::config::ResolvedManifestConfig;
+use crate::domain::storage::StorageError;
+use crate::server::{error::ServerError, handlers, middleware::AuthenticatedToken, AppState};
+use axum::{
+  extract::{Path, Query, State},
+  response::Response,
+  routing::{get, post},
+  Extension, Json, Router,
+};
+use futures_util::stream::{self, StreamExt};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Size lookups running at the same time while building one manifest
+const SIZE_LOOKUP_CONCURRENCY: usize = 16;
+
+/// Signs and verifies download URLs handed out in manifests
+pub struct ManifestSigner {
+  key: Vec<u8>,
+  url_ttl_seconds: u64,
+  max_hashes: usize,
+  public_url: Option<String>,
+}
+
+impl ManifestSigner {
+  pub fn new(config: &ResolvedManifestConfig) -> Self {
+    Self {
+      key: config.signing_key.as_bytes().to_vec(),
+      url_ttl_seconds: config.url_ttl_seconds,
+      max_hashes: config.max_hashes,
+      public_url: config.public_url.clone(),
+    }
+  }
+
+  fn mac(&self, token_name: &str, hash: &str, expires: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}\n{}\n{}", token_name, hash, expires).as_bytes());
+    mac
+  }
+
+  /// Hex encoded signature binding a token, a hash and an expiry time
+  pub fn sign(&self, token_name: &str, hash: &str, expires: u64) -> String {
+    hex::encode(self.mac(token_name, hash, expires).finalize().into_bytes())
+  }
+
+  /// Check a signature in constant time, rejecting it once `expires` has passed
+  pub fn verify(
+    &self,
+    token_name: &str,
+    hash: &str,
+    expires: u64,
+    signature: &str,
+    now: u64,
+  ) -> bool {
+    if expires < now {
+      return false;
+    }
+    let Ok(signature) = hex::decode(signature) else {
+      return false;
+    };
+    self
+      .mac(token_name, hash, expires)
+      .verify_slice(&signature)
+      .is_ok()
+  }
+
+  /// Signed download URL, absolute when a public URL is configured
+  pub fn url(&self, token_name: &str, hash: &str, expires: u64) -> String {
+    format!(
+      "{}/v1/signed/{}?token={}&expires={}&signature={}",
+      self.public_url.as_deref().unwrap_or(""),
+      hash,
+      encode_query_value(token_name),
+      expires,
+      self.sign(token_name, hash, expires)
+    )
+  }
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters
+fn encode_query_value(value: &str) -> String {
+  let mut encoded = String::with_capacity(value.len());
+  for byte in value.bytes() {
+    if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+      encoded.push(byte as char);
+    } else {
+      encoded.push_str(&format!("%{:02X}", byte));
+    }
+  }
+  encoded
+}
+
+fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ManifestRequest {
+  pub hashes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+  /// Unix time (seconds) after which the URLs stop working
+  pub expires_at: u64,
+  pub objects: Vec<ManifestEntry>,
+  /// Requested hashes that are not in the cache
+  pub missing: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+  pub hash: String,
+  pub url: String,
+  pub size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignedQuery {
+  pub token: String,
+  pub expires: u64,
+  pub signature: String,
+}
+
+/// Token-protected route that builds manifests (`POST /v1/manifest`)
+pub fn manifest_routes() -> Router<AppState> {
+  Router::new().route("/v1/manifest", post(create_manifest))
+}
+
+/// Public route serving objects through signed URLs (`GET /v1/signed/{hash}`)
+pub fn signed_routes() -> Router<AppState> {
+  Router::new().route("/v1/signed/{hash}", get(signed_download))
+}
+
+/// Build a manifest of signed URLs and sizes for the requested hashes
+pub async fn create_manifest(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Json(request): Json<ManifestRequest>,
+) -> Result<Json<Manifest>, ServerError> {
+  let signer = state
+    .manifest
+    .as_ref()
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+  if request.hashes.len() > signer.max_hashes {
+    return Err(ServerError::BadRequest);
+  }
+  for hash in &request.hashes {
+    state.hash_policies.nx.validate(hash)?;
+  }
+  handlers::require_read(&state, &token)?;
+  let token_name = state
+    .storage
+    .get_token_config(&token.0)
+    .map(|config| config.name.clone())
+    .ok_or(ServerError::Unauthorized)?;
+
+  let mut seen = HashSet::new();
+  let hashes: Vec<String> = request
+    .hashes
+    .into_iter()
+    .filter(|hash| seen.insert(hash.clone()))
+    .collect();
+
+  let sizes: Vec<(String, Result<u64, StorageError>)> = stream::iter(hashes)
+    .map(|hash| {
+      let storage = state.storage.clone();
+      let token = token.0.clone();
+      async move {
+        let size = storage.size_with_token(&token, &hash).await;
+        (hash, size)
+      }
+    })
+    .buffered(SIZE_LOOKUP_CONCURRENCY)
+    .collect()
+    .await;
+
+  let expires_at = unix_now() + signer.url_ttl_seconds;
+  let mut manifest = Manifest {
+    expires_at,
+    objects: Vec::new(),
+    missing: Vec::new(),
+  };
+  for (hash, size) in sizes {
+    match size {
+      Ok(size) => manifest.objects.push(ManifestEntry {
+        url: signer.url(&token_name, &hash, expires_at),
+        hash,
+        size,
+      }),
+      Err(StorageError::NotFound) => manifest.missing.push(hash),
+      Err(err) => {
+        tracing::warn!("Manifest size lookup failed for {}: {}", hash, err);
+        manifest.missing.push(hash);
+      },
+    }
+  }
+
+  tracing::debug!(
+    "Manifest for {}: {} object(s), {} missing",
+    token_name,
+    manifest.objects.len(),
+    manifest.missing.len()
+  );
+  Ok(Json(manifest))
+}
+
+/// Serve an object for a valid, unexpired signature, counting egress against the signing token
+pub async fn signed_download(
+  State(state): State<AppState>,
+  Path(hash): Path<String>,
+  Query(query): Query<SignedQuery>,
+) -> Result<Response, ServerError> {
+  let signer = state
+    .manifest
+    .as_ref()
+    .ok_or(ServerError::Storage(StorageError::NotFound))?;
+  state.hash_policies.nx.validate(&hash)?;
+  if !signer.verify(
+    &query.token,
+    &hash,
+    query.expires,
+    &query.signature,
+    unix_now(),
+  ) {
+    return Err(ServerError::Unauthorized);
+  }
+
+  let access_token = state
+    .storage
+    .find_token_by_name(&query.token)
+    .map(|config| config.access_token.clone())
+    .ok_or(ServerError::Unauthorized)?;
+  handlers::stream_object(&state, &AuthenticatedToken(access_token), &hash, &[]).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn signer() -> ManifestSigner {
+    ManifestSigner::new(&ResolvedManifestConfig {
+      signing_key: "secret".to_string(),
+      url_ttl_seconds: 60,
+      max_hashes: 10,
+      public_url: Some("https://cache.example.com".to_string()),
+    })
+  }
+
+  #[test]
+  fn test_signature_roundtrip() {
+    let signer = signer();
+    let signature = signer.sign("ci", "abc", 100);
+
+    assert!(signer.verify("ci", "abc", 100, &signature, 50));
+    assert!(!signer.verify("ci", "abc", 100, &signature, 101));
+    assert!(!signer.verify("ci", "abd", 100, &signature, 50));
+    assert!(!signer.verify("other", "abc", 100, &signature, 50));
+    assert!(!signer.verify("ci", "abc", 200, &signature, 50));
+    assert!(!signer.verify("ci", "abc", 100, "not-hex", 50));
+  }
+
+  #[test]
+  fn test_url_encodes_token_name() {
+    let url = signer().url("team a/ci", "abc", 100);
+    assert!(url.starts_with(
+      "https://cache.example.com/v1/signed/abc?token=team%20a%2Fci&expires=100&signature="
+    ));
+  }
+}
@@ -1,81 +1,800 @@
-use crate::server::AppState;
+use crate::domain::http_date;
+use crate::server::{
+  error::{ErrorCode, ServerError},
+  jwt::{self, JwtError},
+  request_id::RequestId,
+  AppState,
+};
 use axum::{
+  body::Body,
   extract::{Request, State},
-  http::StatusCode,
+  http::{
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
+    HeaderMap, HeaderValue, Method, StatusCode,
+  },
   middleware::Next,
   response::{IntoResponse, Response},
 };
+use base64::engine::general_purpose;
+use base64::Engine as _;
+use futures_util::{stream, StreamExt};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use subtle::ConstantTimeEq;
-use tracing;
+use tokio::time::Instant;
+use tracing::{self, Instrument};
 
 /// Extension type to carry the authenticated token through the request
 #[derive(Clone)]
 pub struct AuthenticatedToken(pub String);
 
+/// The configured token equal to `token`, compared in constant time
+pub(crate) fn find_token(state: &AppState, token: &str) -> Option<String> {
+  state
+    .storage
+    .tokens()
+    .find(|candidate| bool::from(token.as_bytes().ct_eq(candidate.as_bytes())))
+    .cloned()
+}
+
+/// The configured token whose previous access token is `token`, until its deadline passes
+/// unless that secret was revoked
+pub(crate) fn find_previous_token(state: &AppState, token: &str) -> Option<String> {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  let config = state.storage.token_configs().find(|config| {
+    config
+      .previous_access_token
+      .as_ref()
+      .is_some_and(|previous| {
+        bool::from(token.as_bytes().ct_eq(previous.access_token.as_bytes()))
+          && now < previous.valid_until
+      })
+  })?;
+  // A revoked secret stays rejected after it was rotated into the previous access token
+  let revoked = state
+    .revoked_tokens
+    .as_ref()
+    .and_then(|revoked| revoked.get(&config.name))
+    .is_some_and(|revocation| revocation.revokes_secret(token));
+  if revoked {
+    tracing::warn!(
+      "Authentication failed: previous access token of {} was revoked",
+      config.name
+    );
+    return None;
+  }
+  tracing::debug!(
+    "Token {} authenticated with its previous access token",
+    config.name
+  );
+  Some(config.access_token.clone())
+}
+
+/// The configured token a JWT bearer token stands for
+///
+/// `None` unless JWTs are enabled and `token` looks like one; a rejected JWT is logged with
+/// the reason and answered with a message that does not reveal it.
+pub(crate) async fn find_jwt_token(
+  state: &AppState,
+  token: &str,
+) -> Option<Result<String, &'static str>> {
+  let jwt = state.jwt.as_ref()?;
+  if !jwt::is_jwt(token) {
+    return None;
+  }
+  Some(match jwt.verify(token).await {
+    Ok(name) => state
+      .storage
+      .find_token_by_name(&name)
+      .map(|config| config.access_token.clone())
+      .ok_or("Invalid token"),
+    Err(JwtError::Expired) => Err("Token expired"),
+    Err(err) => {
+      tracing::warn!("Authentication failed: JWT rejected: {}", err);
+      Err("Invalid token")
+    },
+  })
+}
+
+/// The configured token a bearer token authenticates as
+///
+/// Plain access tokens are tried first, then previous access tokens before their deadline,
+/// then JWTs, then the configured token hashes. `Err` carries the message a rejected JWT is
+/// answered with.
+pub(crate) async fn resolve_token(
+  state: &AppState,
+  token: &str,
+) -> Result<Option<String>, &'static str> {
+  if let Some(found) = find_token(state, token).or_else(|| find_previous_token(state, token)) {
+    return Ok(Some(found));
+  }
+  if let Some(result) = find_jwt_token(state, token).await {
+    return result.map(Some);
+  }
+  Ok(state.hashed_tokens.find(&state.storage, token).await)
+}
+
+/// Whether the configured token `token` has expired, logging and counting the rejection
+pub(crate) fn is_expired(state: &AppState, token: &str) -> bool {
+  let Some(config) = state.storage.get_token_config(token) else {
+    return false;
+  };
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  let Some(expires_at) = config.expires_at.filter(|_| config.is_expired_at(now)) else {
+    return false;
+  };
+  tracing::warn!(
+    "Authentication failed: token {} expired at {}",
+    config.name,
+    http_date::format_rfc3339(expires_at)
+  );
+  state.accounting.record_expired(&config.name);
+  true
+}
+
+/// Whether the configured token `token` has been revoked, logging the rejection
+pub(crate) fn is_revoked(state: &AppState, token: &str) -> bool {
+  let (Some(revoked), Some(config)) =
+    (&state.revoked_tokens, state.storage.get_token_config(token))
+  else {
+    return false;
+  };
+  let Some(revocation) = revoked
+    .get(&config.name)
+    .filter(|revocation| revocation.applies_to(config))
+  else {
+    return false;
+  };
+  tracing::warn!(
+    "Authentication failed: token {} was revoked at {} by {}",
+    config.name,
+    revocation.revoked_at,
+    revocation.revoked_by
+  );
+  true
+}
+
+/// Longest authorization scheme echoed back in an error message
+const MAX_ECHOED_SCHEME_LEN: usize = 32;
+
+/// Credentials a request carries, or why it carries none that can be checked
+#[derive(Debug, PartialEq, Eq)]
+enum Credentials {
+  /// A token, with the custom header it was sent in if it did not come from `Authorization`
+  Token {
+    token: String,
+    header: Option<String>,
+  },
+  /// `Authorization` with a scheme other than Bearer or Basic
+  UnsupportedScheme(String),
+  /// `Basic` credentials that are not base64 of `user:password`
+  MalformedBasic,
+  Missing,
+}
+
+/// Token of an `Authorization` value: `Bearer <token>`, the password of `Basic` credentials
+/// for clients such as Gradle that only send basic auth, or a bare token as Nx Cloud clients
+/// send it
+fn authorization_credentials(value: &str) -> Credentials {
+  let value = value.trim();
+  let Some((scheme, rest)) = value.split_once(' ') else {
+    return match value {
+      "" => Credentials::Missing,
+      token => Credentials::Token {
+        token: token.to_string(),
+        header: None,
+      },
+    };
+  };
+  let token = if scheme.eq_ignore_ascii_case("bearer") {
+    rest.trim().to_string()
+  } else if scheme.eq_ignore_ascii_case("basic") {
+    let password = general_purpose::STANDARD
+      .decode(rest.trim())
+      .ok()
+      .and_then(|credentials| String::from_utf8(credentials).ok())
+      .and_then(|credentials| Some(credentials.split_once(':')?.1.to_string()));
+    match password {
+      Some(password) => password,
+      None => return Credentials::MalformedBasic,
+    }
+  } else {
+    let scheme = scheme.chars().take(MAX_ECHOED_SCHEME_LEN).collect();
+    return Credentials::UnsupportedScheme(scheme);
+  };
+  Credentials::Token {
+    token,
+    header: None,
+  }
+}
+
+/// Credentials from `Authorization`, else from the first custom token header present
+///
+/// Custom headers hold the bare token, optionally prefixed with `Bearer `.
+fn request_credentials<'a>(
+  headers: &HeaderMap,
+  custom_headers: impl IntoIterator<Item = &'a str>,
+) -> Credentials {
+  if let Some(value) = headers.get("authorization") {
+    return match value.to_str() {
+      Ok(value) => authorization_credentials(value),
+      Err(_) => Credentials::Missing,
+    };
+  }
+  for name in custom_headers {
+    let Some(value) = headers.get(name).and_then(|value| value.to_str().ok()) else {
+      continue;
+    };
+    let value = value.trim();
+    let token = match value.split_once(' ') {
+      Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+      _ => value,
+    };
+    if !token.is_empty() {
+      return Credentials::Token {
+        token: token.to_string(),
+        header: Some(name.to_string()),
+      };
+    }
+  }
+  Credentials::Missing
+}
+
+/// Custom headers configured for any token, in token order without duplicates
+fn custom_token_headers(state: &AppState) -> Vec<String> {
+  let mut headers: Vec<String> = Vec::new();
+  for token in state.storage.tokens() {
+    let Some(header) = state
+      .storage
+      .get_token_config(token)
+      .and_then(|config| config.auth_header.clone())
+    else {
+      continue;
+    };
+    if !headers.contains(&header) {
+      headers.push(header);
+    }
+  }
+  headers
+}
+
+/// 401 naming what is wrong with the request's credentials
+fn unauthorized(message: String) -> Response {
+  (
+    StatusCode::UNAUTHORIZED,
+    [
+      ("Content-Type", "text/plain"),
+      ("WWW-Authenticate", "Bearer"),
+    ],
+    message,
+  )
+    .into_response()
+}
+
 pub async fn auth_middleware(
   State(state): State<AppState>,
   mut request: Request,
   next: Next,
 ) -> Result<Response, Response> {
-  // Extract Bearer token from Authorization header
-  let token = request
-    .headers()
-    .get("authorization")
-    .and_then(|header| header.to_str().ok())
-    .and_then(|auth_value| auth_value.strip_prefix("Bearer "));
-
-  let token = match token {
-    Some(t) => t,
-    None => {
-      return Err(
-        (
-          StatusCode::UNAUTHORIZED,
-          [("Content-Type", "text/plain")],
-          "Unauthorized",
-        )
-          .into_response(),
-      )
+  let custom_headers = custom_token_headers(&state);
+  let credentials =
+    request_credentials(request.headers(), custom_headers.iter().map(String::as_str));
+  let (token, header) = match credentials {
+    Credentials::Token { token, header } => (token, header),
+    Credentials::UnsupportedScheme(scheme) => {
+      tracing::warn!("Authentication failed: unsupported scheme {}", scheme);
+      return Err(unauthorized(format!(
+        "Unsupported authorization scheme '{}': send 'Authorization: Bearer <token>'",
+        scheme
+      )));
+    },
+    Credentials::MalformedBasic => {
+      tracing::warn!("Authentication failed: malformed basic credentials");
+      return Err(unauthorized(
+        "Malformed Basic credentials: expected base64 of 'user:token'".to_string(),
+      ));
+    },
+    Credentials::Missing => {
+      return Err(unauthorized(
+        "Missing credentials: send 'Authorization: Bearer <token>'".to_string(),
+      ))
     },
   };
 
-  // Check token against all configured tokens using constant-time comparison
-  let mut matched_token: Option<String> = None;
-
-  for token_value in state.storage.tokens() {
-    if bool::from(token.as_bytes().ct_eq(token_value.as_bytes())) {
-      matched_token = Some(token_value.clone());
-      break;
+  let found = resolve_token(&state, &token)
+    .await
+    .map_err(|message| unauthorized(message.to_string()))?;
+  // A custom header only carries the tokens it is configured for
+  if let (Some(token_value), Some(header)) = (&found, &header) {
+    let configured = state
+      .storage
+      .get_token_config(token_value)
+      .and_then(|config| config.auth_header.as_deref());
+    if configured != Some(header.as_str()) {
+      tracing::warn!(
+        "Authentication failed: token sent in unconfigured header {}",
+        header
+      );
+      return Err(unauthorized(format!(
+        "Token is not accepted in the '{}' header: send 'Authorization: Bearer <token>'",
+        header
+      )));
     }
   }
 
-  match matched_token {
+  match found {
+    Some(token_value) if is_revoked(&state, &token_value) => {
+      Err(unauthorized("Token revoked".to_string()))
+    },
+    Some(token_value) if is_expired(&state, &token_value) => {
+      Err(unauthorized("Token expired".to_string()))
+    },
     Some(token_value) => {
       // Get the token configuration to log the name
-      if let Some(config) = state.storage.get_token_config(&token_value) {
-        tracing::info!(
-          "Authenticated request from: {} (bucket: {}, prefix: {})",
-          config.name,
-          config.bucket,
-          config.prefix
-        );
-      }
+      let token_name = match state.storage.get_token_config(&token_value) {
+        Some(config) => {
+          tracing::info!(
+            "Authenticated request from: {} (bucket: {}, prefix: {})",
+            config.name,
+            config.bucket,
+            config.prefix
+          );
+          config.name.clone()
+        },
+        None => String::new(),
+      };
+
+      // Errors logged while handling the request are attributed to this token and operation
+      let span = tracing::info_span!(
+        "request",
+        token = %token_name,
+        operation = %format!("{} {}", request.method(), request.uri().path())
+      );
 
       // Store the token in request extensions for handlers to use
       request
         .extensions_mut()
         .insert(AuthenticatedToken(token_value));
-      Ok(next.run(request).await)
+      Ok(next.run(request).instrument(span).await)
     },
     None => {
       tracing::warn!("Authentication failed: invalid token");
-      Err(
-        (
-          StatusCode::UNAUTHORIZED,
-          [("Content-Type", "text/plain")],
-          "Unauthorized",
-        )
-          .into_response(),
-      )
+      Err(unauthorized("Invalid token".to_string()))
+    },
+  }
+}
+
+/// Response header carrying a soft quota warning
+pub const QUOTA_WARNING_HEADER: &str = "x-nx-cache-quota-warning";
+
+/// Add a soft quota warning header once a token nears its daily egress limit
+///
+/// Must run after [`auth_middleware`], which provides the [`AuthenticatedToken`] extension.
+pub async fn quota_warning_middleware(
+  State(state): State<AppState>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let token = request.extensions().get::<AuthenticatedToken>().cloned();
+  let mut response = next.run(request).await;
+
+  let Some(service) = token.and_then(|token| state.storage.get_token_config(&token.0)) else {
+    return response;
+  };
+  let warning = state.egress.quota_warning(
+    &service.name,
+    service.egress_daily_limit_bytes,
+    service.quota_warning_percent,
+  );
+  if let Some(value) = warning.and_then(|w| HeaderValue::from_str(&w).ok()) {
+    response.headers_mut().insert(QUOTA_WARNING_HEADER, value);
+  }
+  response
+}
+
+/// Response header carrying the operator's notice for the token
+pub const NOTICE_HEADER: &str = "x-nx-cache-notice";
+
+/// Add the token's notice, if any, to every response
+///
+/// Must run after [`auth_middleware`], which provides the [`AuthenticatedToken`] extension.
+pub async fn notice_middleware(
+  State(state): State<AppState>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let token = request.extensions().get::<AuthenticatedToken>().cloned();
+  let mut response = next.run(request).await;
+
+  let notice = token
+    .and_then(|token| state.storage.get_token_config(&token.0))
+    .and_then(|service| service.notice.as_deref())
+    .and_then(|notice| HeaderValue::from_str(notice).ok());
+  if let Some(value) = notice {
+    response.headers_mut().insert(NOTICE_HEADER, value);
+  }
+  response
+}
+
+/// Request header carrying the client's deadline as Unix time in milliseconds
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+
+/// Request header carrying the client's timeout in seconds, counted from arrival
+pub const REQUEST_TIMEOUT_HEADER: &str = "request-timeout";
+
+/// Deadline the client set for a request, the earlier one if both headers are sent
+///
+/// Unparseable values are ignored; deadlines in the past resolve to now.
+pub fn request_deadline(headers: &HeaderMap) -> Option<Instant> {
+  let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+  let now = Instant::now();
+
+  let absolute = header(DEADLINE_HEADER)
+    .and_then(|value| value.trim().parse::<u64>().ok())
+    .map(|deadline_ms| {
+      let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+      now + Duration::from_millis(deadline_ms.saturating_sub(now_ms))
+    });
+  let relative = header(REQUEST_TIMEOUT_HEADER)
+    .and_then(|value| value.trim().parse::<f64>().ok())
+    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+    .map(|timeout| now + timeout);
+
+  absolute.into_iter().chain(relative).min()
+}
+
+/// Cancel a request once the client's deadline passes
+///
+/// The handler, and with it every storage operation it awaits, is dropped at the deadline.
+/// A response body still streaming at that point is ended with an error.
+pub async fn deadline_middleware(request: Request, next: Next) -> Response {
+  let Some(deadline) = request_deadline(request.headers()) else {
+    return next.run(request).await;
+  };
+  let operation = format!("{} {}", request.method(), request.uri().path());
+
+  let response = match tokio::time::timeout_at(deadline, next.run(request)).await {
+    Ok(response) => response,
+    Err(_) => {
+      tracing::debug!("Deadline passed before {} responded, cancelled", operation);
+      return ServerError::DeadlineExceeded.into_response();
+    },
+  };
+
+  response.map(|body| {
+    let state = (
+      body.into_data_stream(),
+      Box::pin(tokio::time::sleep_until(deadline)),
+    );
+    let chunks = stream::unfold(Some(state), |state| async move {
+      let (mut data, mut expired) = state?;
+      tokio::select! {
+        chunk = data.next() => chunk.map(|chunk| (chunk, Some((data, expired)))),
+        _ = &mut expired => {
+          let err = io::Error::new(io::ErrorKind::TimedOut, "request deadline exceeded");
+          Some((Err(axum::Error::new(err)), None))
+        },
+      }
+    });
+    Body::from_stream(chunks)
+  })
+}
+
+/// Whether a path is one of the Nx remote cache endpoints, `/v1/cache/{hash}`
+fn is_nx_cache_path(path: &str) -> bool {
+  path
+    .strip_prefix("/v1/cache/")
+    .is_some_and(|hash| !hash.is_empty() && !hash.contains('/'))
+}
+
+/// Fold a response of the Nx cache endpoints into what the Nx remote cache spec allows
+///
+/// Successes become a plain `200`. Uploads may fail with `401`, `403` or `409` and downloads
+/// with `401`, `403` or `404`; any other error becomes `403` on uploads, and on downloads
+/// `403` for client errors and `404` (a miss the client rebuilds) for server errors. Every
+/// error carries the spec's plain-text body.
+pub(crate) fn nx_spec_response(upload: bool, response: Response) -> Response {
+  let status = response.status();
+  if status.is_success() {
+    if !upload {
+      let (mut parts, body) = response.into_parts();
+      parts.status = StatusCode::OK;
+      return Response::from_parts(parts, body);
+    }
+    return (StatusCode::OK, [("Content-Type", "text/plain")], "").into_response();
+  }
+
+  let status = match (upload, status) {
+    (_, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => status,
+    (true, StatusCode::CONFLICT) | (false, StatusCode::NOT_FOUND) => status,
+    (false, status) if status.is_server_error() => StatusCode::NOT_FOUND,
+    _ => StatusCode::FORBIDDEN,
+  };
+  let message = match status {
+    StatusCode::UNAUTHORIZED => "Unauthorized",
+    StatusCode::FORBIDDEN => "Access forbidden",
+    StatusCode::CONFLICT => "Cannot override an existing record",
+    _ => "The record was not found",
+  };
+  let (mut parts, _) = response.into_parts();
+  parts.status = status;
+  parts.headers.remove(CONTENT_LENGTH);
+  parts.headers.remove(CONTENT_ENCODING);
+  parts
+    .headers
+    .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+  Response::from_parts(parts, Body::from(message))
+}
+
+/// Answer the Nx cache endpoints strictly as the Nx remote cache spec describes them
+///
+/// Applied around the whole router so it also covers authentication failures; every other
+/// route passes through unchanged.
+pub async fn nx_spec_middleware(request: Request, next: Next) -> Response {
+  if !is_nx_cache_path(request.uri().path()) {
+    return next.run(request).await;
+  }
+  let upload = request.method() == Method::PUT;
+  nx_spec_response(upload, next.run(request).await)
+}
+
+/// Longest plain-text error body rewritten as JSON; larger bodies pass through unchanged
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// Stable code for a plain-text error response that carries no [`ErrorCode`]
+fn plain_error_code(status: StatusCode, message: &str) -> &'static str {
+  match message {
+    "The record was not found" => "not_found",
+    "Cannot override an existing record" => "already_exists",
+    "Unauthorized" => "unauthorized",
+    "Access forbidden" => "forbidden",
+    "Invalid checksum header" => "invalid_checksum_header",
+    "Checksum mismatch" => "checksum_mismatch",
+    "Content-Length mismatch" => "content_length_mismatch",
+    "Empty request body" => "empty_body",
+    "Unsupported Content-Encoding" => "unsupported_encoding",
+    "Invalid key" => "invalid_key",
+    "Invalid tar archive" => "invalid_archive",
+    "Invalid task metadata" => "invalid_task_metadata",
+    "Task metadata too large" => "task_metadata_too_large",
+    "Token has no prefix to purge" => "no_prefix",
+    _ => match status {
+      StatusCode::BAD_REQUEST => "bad_request",
+      StatusCode::UNAUTHORIZED => "unauthorized",
+      StatusCode::FORBIDDEN => "forbidden",
+      StatusCode::NOT_FOUND => "not_found",
+      StatusCode::CONFLICT => "conflict",
+      StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+      StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+      StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+      StatusCode::GATEWAY_TIMEOUT => "timeout",
+      status if status.is_server_error() => "internal_error",
+      _ => "client_error",
     },
   }
 }
+
+/// Rewrite a plain-text error response as `{"error": {"code": ..., "message": ...}}`
+///
+/// The request id, when known, is added as `requestId`. Successes and error responses of
+/// other content types are returned unchanged.
+pub(crate) async fn json_error_response(response: Response, request_id: Option<&str>) -> Response {
+  let is_plain = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.starts_with("text/plain"));
+  let status = response.status();
+  if !(status.is_client_error() || status.is_server_error()) || !is_plain {
+    return response;
+  }
+
+  let (mut parts, body) = response.into_parts();
+  let message = match axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+    Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+    Err(_) => String::new(),
+  };
+  let code = match parts.extensions.get::<ErrorCode>() {
+    Some(code) => code.0,
+    None => plain_error_code(parts.status, &message),
+  };
+  let mut body = serde_json::json!({ "error": { "code": code, "message": message } });
+  if let Some(request_id) = request_id {
+    body["error"]["requestId"] = request_id.into();
+  }
+  parts.headers.remove(CONTENT_LENGTH);
+  parts.headers.remove(CONTENT_ENCODING);
+  parts
+    .headers
+    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+  Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+/// Answer every plain-text error as JSON with a stable machine-readable code
+pub async fn json_error_middleware(request: Request, next: Next) -> Response {
+  let request_id = request.extensions().get::<RequestId>().cloned();
+  let response = next.run(request).await;
+  json_error_response(response, request_id.as_ref().map(|id| id.0.as_str())).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_request_credentials() {
+    let token = |token: &str, header: Option<&str>| Credentials::Token {
+      token: token.to_string(),
+      header: header.map(str::to_string),
+    };
+    let credentials = |name: &str, value: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(
+        axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+        HeaderValue::from_str(value).unwrap(),
+      );
+      request_credentials(&headers, ["x-nx-token"])
+    };
+    assert_eq!(
+      credentials("authorization", "Bearer secret"),
+      token("secret", None)
+    );
+    assert_eq!(
+      credentials("authorization", "bearer secret"),
+      token("secret", None)
+    );
+    // Nx Cloud clients send the bare token
+    assert_eq!(
+      credentials("authorization", "secret"),
+      token("secret", None)
+    );
+    // Gradle sends the token as the password; the user name is ignored
+    let basic = general_purpose::STANDARD.encode("gradle:secret");
+    assert_eq!(
+      credentials("authorization", &format!("Basic {}", basic)),
+      token("secret", None)
+    );
+    assert_eq!(
+      credentials("authorization", "Basic not-base64!"),
+      Credentials::MalformedBasic
+    );
+    assert_eq!(
+      credentials("authorization", "Token secret"),
+      Credentials::UnsupportedScheme("Token".to_string())
+    );
+    assert_eq!(
+      credentials("x-nx-token", "Bearer secret"),
+      token("secret", Some("x-nx-token"))
+    );
+    assert_eq!(credentials("x-other", "secret"), Credentials::Missing);
+    assert_eq!(
+      request_credentials(&HeaderMap::new(), []),
+      Credentials::Missing
+    );
+  }
+
+  #[tokio::test]
+  async fn test_request_deadline_headers() {
+    let mut headers = HeaderMap::new();
+    assert!(request_deadline(&headers).is_none());
+
+    headers.insert(REQUEST_TIMEOUT_HEADER, HeaderValue::from_static("30"));
+    let relative = request_deadline(&headers).unwrap();
+    assert!(relative > Instant::now() + Duration::from_secs(29));
+
+    // The earlier deadline wins, and one in the past has already expired
+    headers.insert(DEADLINE_HEADER, HeaderValue::from_static("1000"));
+    assert!(request_deadline(&headers).unwrap() <= Instant::now());
+
+    headers.insert(DEADLINE_HEADER, HeaderValue::from_static("soon"));
+    headers.insert(REQUEST_TIMEOUT_HEADER, HeaderValue::from_static("-1"));
+    assert!(request_deadline(&headers).is_none());
+  }
+
+  #[tokio::test]
+  async fn test_nx_spec_response() {
+    async fn fold(upload: bool, status: StatusCode, body: &'static str) -> (StatusCode, String) {
+      let response = nx_spec_response(upload, (status, body).into_response());
+      let status = response.status();
+      let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+      (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    assert_eq!(
+      fold(true, StatusCode::ACCEPTED, "queued").await,
+      (StatusCode::OK, String::new())
+    );
+    assert_eq!(
+      fold(true, StatusCode::BAD_REQUEST, "Checksum mismatch").await,
+      (StatusCode::FORBIDDEN, "Access forbidden".to_string())
+    );
+    assert_eq!(
+      fold(
+        false,
+        StatusCode::GATEWAY_TIMEOUT,
+        "Request deadline exceeded"
+      )
+      .await,
+      (
+        StatusCode::NOT_FOUND,
+        "The record was not found".to_string()
+      )
+    );
+    assert_eq!(
+      fold(
+        false,
+        StatusCode::TOO_MANY_REQUESTS,
+        "Daily egress limit exceeded"
+      )
+      .await,
+      (StatusCode::FORBIDDEN, "Access forbidden".to_string())
+    );
+    assert_eq!(
+      fold(true, StatusCode::CONFLICT, "exists").await,
+      (
+        StatusCode::CONFLICT,
+        "Cannot override an existing record".to_string()
+      )
+    );
+    assert!(is_nx_cache_path("/v1/cache/abc"));
+    assert!(!is_nx_cache_path("/v1/cache/abc/variants"));
+  }
+
+  #[tokio::test]
+  async fn test_json_error_response() {
+    async fn rewrite(response: Response) -> (StatusCode, serde_json::Value) {
+      let response = json_error_response(response, None).await;
+      let status = response.status();
+      let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+      (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    let (status, body) = rewrite(
+      (
+        StatusCode::BAD_REQUEST,
+        [("Content-Type", "text/plain")],
+        "Checksum mismatch",
+      )
+        .into_response(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+      body,
+      serde_json::json!({ "error": { "code": "checksum_mismatch", "message": "Checksum mismatch" } })
+    );
+
+    // Server errors keep their status but name the cause, not the folded Nx message
+    let (status, body) = rewrite(ServerError::BadRequest.into_response()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "invalid_request");
+
+    let (_, body) = rewrite((StatusCode::SERVICE_UNAVAILABLE, "bucket down").into_response()).await;
+    assert_eq!(body["error"]["code"], "unavailable");
+
+    let response = (StatusCode::NOT_FOUND, "The record was not found").into_response();
+    let response = json_error_response(response, Some("ci-run-42")).await;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["requestId"], "ci-run-42");
+
+    // Successes and JSON errors pass through
+    let (_, body) = rewrite((StatusCode::OK, "fine").into_response()).await;
+    assert_eq!(body, serde_json::Value::Null);
+  }
+}
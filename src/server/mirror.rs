@@ -0,0 +1,203 @@
+use crate::domain::config::MirrorConfig;
+use crate::domain::storage::StorageError;
+use crate::server::{error::ServerError, AppState};
+use axum::{
+  body::Body,
+  extract::{Path, State},
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  routing::get,
+  Router,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Entries are content-addressed, so hits can be cached by clients and proxies indefinitely
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Read-only view of a bucket for compiler caches (ccache, sccache)
+pub struct Mirror {
+  config: MirrorConfig,
+  misses: NegativeCache,
+}
+
+impl Mirror {
+  pub fn new(config: MirrorConfig) -> Self {
+    let misses = NegativeCache::new(
+      Duration::from_secs(config.negative_cache_ttl_seconds),
+      config.negative_cache_max_entries,
+    );
+    Self { config, misses }
+  }
+
+  /// Whether mirror routes are placed behind the token auth middleware
+  pub fn require_auth(&self) -> bool {
+    self.config.require_auth
+  }
+}
+
+/// Bounded set of recently missed keys, so repeated lookups skip the bucket
+///
+/// Compiler caches probe far more keys than they hit; remembering misses for a short
+/// TTL turns most of those probes into a map lookup.
+struct NegativeCache {
+  entries: Mutex<HashMap<String, Instant>>,
+  ttl: Duration,
+  max_entries: usize,
+}
+
+impl NegativeCache {
+  fn new(ttl: Duration, max_entries: usize) -> Self {
+    Self {
+      entries: Mutex::new(HashMap::new()),
+      ttl,
+      max_entries: max_entries.max(1),
+    }
+  }
+
+  fn contains(&self, key: &str) -> bool {
+    self.contains_at(key, Instant::now())
+  }
+
+  fn contains_at(&self, key: &str, now: Instant) -> bool {
+    let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+    match entries.get(key) {
+      Some(expires) if *expires > now => true,
+      Some(_) => {
+        entries.remove(key);
+        false
+      },
+      None => false,
+    }
+  }
+
+  fn insert(&self, key: &str) {
+    self.insert_at(key, Instant::now());
+  }
+
+  fn insert_at(&self, key: &str, now: Instant) {
+    let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+    if entries.len() >= self.max_entries && !entries.contains_key(key) {
+      entries.retain(|_, expires| *expires > now);
+      if entries.len() >= self.max_entries {
+        // Still full of live entries: start over rather than track recency per key
+        entries.clear();
+      }
+    }
+    entries.insert(key.to_string(), now + self.ttl);
+  }
+
+  #[cfg(test)]
+  fn len(&self) -> usize {
+    self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+  }
+}
+
+/// GET/HEAD routes for the read-only mirror (`/mirror/{key}`)
+pub fn mirror_routes() -> Router<AppState> {
+  Router::new().route("/mirror/{*key}", get(get_object).head(head_object))
+}
+
+fn mirror(state: &AppState) -> Result<&Arc<Mirror>, ServerError> {
+  state
+    .mirror
+    .as_ref()
+    .ok_or(ServerError::Storage(StorageError::NotFound))
+}
+
+/// GET: 200 with the object, 404 on a miss
+async fn get_object(
+  State(state): State<AppState>,
+  Path(key): Path<String>,
+) -> Result<Response, ServerError> {
+  let mirror = mirror(&state)?;
+  state.hash_policies.mirror.validate(&key)?;
+  if mirror.misses.contains(&key) {
+    return Err(ServerError::Storage(StorageError::NotFound));
+  }
+
+  let config = &mirror.config;
+  match state
+    .storage
+    .retrieve_from_bucket(&config.bucket, &config.prefix, &key)
+    .await
+  {
+    Ok(reader) => {
+      let body = Body::from_stream(tokio_util::io::ReaderStream::new(reader));
+      Ok(
+        (
+          StatusCode::OK,
+          [
+            ("content-type", "application/octet-stream"),
+            ("cache-control", IMMUTABLE_CACHE_CONTROL),
+          ],
+          body,
+        )
+          .into_response(),
+      )
+    },
+    Err(StorageError::NotFound) => {
+      mirror.misses.insert(&key);
+      Err(ServerError::Storage(StorageError::NotFound))
+    },
+    Err(err) => Err(err.into()),
+  }
+}
+
+/// HEAD: existence check without downloading the object
+async fn head_object(
+  State(state): State<AppState>,
+  Path(key): Path<String>,
+) -> Result<Response, ServerError> {
+  let mirror = mirror(&state)?;
+  state.hash_policies.mirror.validate(&key)?;
+  if mirror.misses.contains(&key) {
+    return Err(ServerError::Storage(StorageError::NotFound));
+  }
+
+  let config = &mirror.config;
+  if state
+    .storage
+    .exists_in_bucket(&config.bucket, &config.prefix, &key)
+    .await?
+  {
+    Ok((StatusCode::OK, [("cache-control", IMMUTABLE_CACHE_CONTROL)]).into_response())
+  } else {
+    mirror.misses.insert(&key);
+    Err(ServerError::Storage(StorageError::NotFound))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_negative_cache_expires() {
+    let cache = NegativeCache::new(Duration::from_secs(10), 100);
+    let now = Instant::now();
+    cache.insert_at("a/b", now);
+
+    assert!(cache.contains_at("a/b", now + Duration::from_secs(5)));
+    assert!(!cache.contains_at("a/b", now + Duration::from_secs(10)));
+    assert!(!cache.contains_at("other", now));
+  }
+
+  #[test]
+  fn test_negative_cache_is_bounded() {
+    let cache = NegativeCache::new(Duration::from_secs(10), 2);
+    let now = Instant::now();
+    cache.insert_at("a", now);
+    cache.insert_at("b", now + Duration::from_secs(5));
+
+    // "a" has expired and is evicted to make room
+    cache.insert_at("c", now + Duration::from_secs(11));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.contains_at("b", now + Duration::from_secs(11)));
+
+    // All live: the cache starts over
+    cache.insert_at("d", now + Duration::from_secs(12));
+    assert_eq!(cache.len(), 1);
+  }
+}
@@ -1,11 +1,41 @@
+pub mod accounting;
+pub mod affinity;
 pub mod app_state;
+pub mod bundle;
+pub mod compat;
+pub mod dead_letters;
+pub mod egress;
+pub mod encoding;
 pub mod error;
+pub mod grpc;
 pub mod handlers;
+pub mod hashed_tokens;
+pub mod in_flight;
+pub mod integrity;
+pub mod jwt;
+pub mod manifest;
 pub mod middleware;
+pub mod mirror;
+pub mod normalize;
+pub mod openapi;
+#[cfg(feature = "pprof")]
+pub mod pprof;
+pub mod recent_errors;
+pub mod reload;
+pub mod remote_apis;
+pub mod request_id;
+pub mod revoked_tokens;
 pub mod router;
 pub mod runtime;
+pub mod shutdown;
+pub mod task_metadata;
+pub mod turbo;
+pub mod tus;
+pub mod upstream;
 pub mod validation;
+pub mod version;
 
 pub use app_state::AppState;
-pub use router::create_router;
-pub use runtime::run_server;
+pub use middleware::AuthenticatedToken;
+pub use router::{create_router, protected_routes, public_routes, with_auth};
+pub use runtime::{run_server, ServerBuilder};
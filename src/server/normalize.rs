@@ -0,0 +1,90 @@
+use axum::{
+  extract::Request,
+  http::{uri::PathAndQuery, Uri},
+  middleware::map_request,
+  Router,
+};
+
+/// Collapse repeated slashes and strip a trailing slash from a request path
+///
+/// Returns `None` when the path is already normalized.
+pub fn normalize_path(path: &str) -> Option<String> {
+  let mut normalized = String::with_capacity(path.len());
+  for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+    normalized.push('/');
+    normalized.push_str(segment);
+  }
+  if normalized.is_empty() {
+    normalized.push('/');
+  }
+
+  (normalized != path).then_some(normalized)
+}
+
+/// Rewrite the request URI to its normalized path, keeping the query string
+pub async fn normalize_request(mut request: Request) -> Request {
+  let uri = request.uri();
+  let Some(path) = normalize_path(uri.path()) else {
+    return request;
+  };
+
+  let path_and_query = match uri.query() {
+    Some(query) => format!("{}?{}", path, query),
+    None => path,
+  };
+
+  let mut parts = uri.clone().into_parts();
+  parts.path_and_query = match path_and_query.parse::<PathAndQuery>() {
+    Ok(path_and_query) => Some(path_and_query),
+    Err(_) => return request,
+  };
+
+  if let Ok(normalized) = Uri::from_parts(parts) {
+    tracing::info!(
+      "Normalized request path '{}' to '{}'",
+      request.uri().path(),
+      normalized.path()
+    );
+    *request.uri_mut() = normalized;
+  }
+
+  request
+}
+
+/// Wrap a router so paths are normalized before routing
+///
+/// Layers added with `Router::layer` run after a route was matched, so the app is
+/// nested as the fallback of an outer router that carries the rewriting layer.
+pub fn with_path_normalization(app: Router) -> Router {
+  Router::new()
+    .fallback_service(app)
+    .layer(map_request(normalize_request))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_already_normalized() {
+    assert_eq!(normalize_path("/v1/cache/abc"), None);
+    assert_eq!(normalize_path("/"), None);
+  }
+
+  #[test]
+  fn test_trailing_slash() {
+    assert_eq!(
+      normalize_path("/v1/cache/abc/"),
+      Some("/v1/cache/abc".to_string())
+    );
+  }
+
+  #[test]
+  fn test_duplicate_slashes() {
+    assert_eq!(
+      normalize_path("//v1//cache///abc"),
+      Some("/v1/cache/abc".to_string())
+    );
+    assert_eq!(normalize_path("//"), Some("/".to_string()));
+  }
+}
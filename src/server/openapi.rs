@@ -0,0 +1,752 @@
+use axum::{extract::State, Json};
+use serde_json::{json, Map, Value};
+
+use crate::server::AppState;
+
+/// Router feature an operation belongs to, mirroring how [`crate::server::create_router`]
+/// assembles the routes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Group {
+  Public,
+  Protected,
+  Mirror,
+  Manifest,
+  Signed,
+  TaskMetadata,
+  Affinity,
+  Tus,
+  TokenMinting,
+  TokenRevocation,
+  Pprof,
+}
+
+/// What an operation answers with on success
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Body {
+  Binary,
+  Json,
+  Text,
+  Empty,
+}
+
+struct Operation {
+  method: &'static str,
+  /// Route path in axum syntax (`{param}`, `{*wildcard}`)
+  path: &'static str,
+  summary: &'static str,
+  tag: &'static str,
+  group: Group,
+  response: Body,
+  /// Whether the operation takes an artifact as request body
+  upload: bool,
+}
+
+const fn op(
+  method: &'static str,
+  path: &'static str,
+  summary: &'static str,
+  tag: &'static str,
+  group: Group,
+  response: Body,
+) -> Operation {
+  Operation {
+    method,
+    path,
+    summary,
+    tag,
+    group,
+    response,
+    upload: false,
+  }
+}
+
+const fn upload(
+  path: &'static str,
+  summary: &'static str,
+  tag: &'static str,
+  group: Group,
+) -> Operation {
+  Operation {
+    method: "put",
+    path,
+    summary,
+    tag,
+    group,
+    response: Body::Empty,
+    upload: true,
+  }
+}
+
+/// Every route the server can serve; a test keeps it in sync with the router modules
+const OPERATIONS: &[Operation] = &[
+  op(
+    "get",
+    "/health",
+    "Liveness probe (alias of /livez)",
+    "health",
+    Group::Public,
+    Body::Text,
+  ),
+  op(
+    "get",
+    "/livez",
+    "Liveness probe",
+    "health",
+    Group::Public,
+    Body::Text,
+  ),
+  op(
+    "get",
+    "/readyz",
+    "Readiness probe with per-bucket status",
+    "health",
+    Group::Public,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/healthz",
+    "Health check, with ?verbose=1 per-bucket status",
+    "health",
+    Group::Public,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/version",
+    "Build information",
+    "health",
+    Group::Public,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/openapi.json",
+    "This OpenAPI document",
+    "health",
+    Group::Public,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/v1/cache/{hash}",
+    "Download a task output artifact",
+    "nx",
+    Group::Protected,
+    Body::Binary,
+  ),
+  upload(
+    "/v1/cache/{hash}",
+    "Upload a task output artifact",
+    "nx",
+    Group::Protected,
+  ),
+  op(
+    "get",
+    "/v1/cache/{hash}/variants",
+    "List the variants stored for a hash",
+    "nx",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "post",
+    "/v1/cache/bundle",
+    "Download several artifacts as one tar",
+    "nx",
+    Group::Protected,
+    Body::Binary,
+  ),
+  op(
+    "put",
+    "/v1/cache/bundle",
+    "Upload several artifacts as one tar",
+    "nx",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/v1/whoami",
+    "Describe the calling token",
+    "nx",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/v1/stats/egress",
+    "Egress used by the calling token today",
+    "stats",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/v1/stats/accounting",
+    "Usage accounting export",
+    "stats",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/errors",
+    "Recent storage errors",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/repairs",
+    "Corruption repair counters",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/artifacts",
+    "List artifacts of a namespace page by page",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "delete",
+    "/admin/runs/{run_id}",
+    "Purge the artifacts of a CI run",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/tokens",
+    "List configured tokens without their secrets",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/tokens/{name}/stats",
+    "Requests, hit rate and bytes transferred of a token",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "post",
+    "/admin/reload",
+    "Reload buckets and tokens from the configuration file",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "delete",
+    "/admin/namespaces/{name}",
+    "Purge everything under a token's prefix",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/namespaces/{name}/usage",
+    "Number and stored size of the objects under a token's prefix",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/dead-letters",
+    "List failed asynchronous deliveries",
+    "admin",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "delete",
+    "/admin/dead-letters/{id}",
+    "Discard a dead letter",
+    "admin",
+    Group::Protected,
+    Body::Empty,
+  ),
+  op(
+    "post",
+    "/admin/dead-letters/{id}/retry",
+    "Retry a dead letter",
+    "admin",
+    Group::Protected,
+    Body::Empty,
+  ),
+  op(
+    "get",
+    "/ac/{hash}",
+    "Download a Bazel action cache entry",
+    "compat",
+    Group::Protected,
+    Body::Binary,
+  ),
+  upload(
+    "/ac/{hash}",
+    "Upload a Bazel action cache entry",
+    "compat",
+    Group::Protected,
+  ),
+  op(
+    "get",
+    "/cas/{hash}",
+    "Download a Bazel content-addressable store blob",
+    "compat",
+    Group::Protected,
+    Body::Binary,
+  ),
+  upload(
+    "/cas/{hash}",
+    "Upload a Bazel content-addressable store blob",
+    "compat",
+    Group::Protected,
+  ),
+  op(
+    "get",
+    "/gradle/{key}",
+    "Download a Gradle build cache entry",
+    "compat",
+    Group::Protected,
+    Body::Binary,
+  ),
+  upload(
+    "/gradle/{key}",
+    "Upload a Gradle build cache entry",
+    "compat",
+    Group::Protected,
+  ),
+  op(
+    "get",
+    "/please/{*key}",
+    "Download a Please remote cache entry",
+    "compat",
+    Group::Protected,
+    Body::Binary,
+  ),
+  upload(
+    "/please/{*key}",
+    "Upload a Please remote cache entry",
+    "compat",
+    Group::Protected,
+  ),
+  op(
+    "get",
+    "/ccache/{*key}",
+    "Download a ccache entry",
+    "compat",
+    Group::Protected,
+    Body::Binary,
+  ),
+  op(
+    "head",
+    "/ccache/{*key}",
+    "Check for a ccache entry",
+    "compat",
+    Group::Protected,
+    Body::Empty,
+  ),
+  upload(
+    "/ccache/{*key}",
+    "Upload a ccache entry",
+    "compat",
+    Group::Protected,
+  ),
+  op(
+    "delete",
+    "/ccache/{*key}",
+    "Delete a ccache entry",
+    "compat",
+    Group::Protected,
+    Body::Empty,
+  ),
+  op(
+    "get",
+    "/v8/artifacts/status",
+    "Turborepo remote caching status",
+    "compat",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "post",
+    "/v8/artifacts",
+    "Query Turborepo artifacts",
+    "compat",
+    Group::Protected,
+    Body::Json,
+  ),
+  op(
+    "post",
+    "/v8/artifacts/events",
+    "Record Turborepo cache usage events",
+    "compat",
+    Group::Protected,
+    Body::Empty,
+  ),
+  op(
+    "get",
+    "/v8/artifacts/{hash}",
+    "Download a Turborepo artifact",
+    "compat",
+    Group::Protected,
+    Body::Binary,
+  ),
+  op(
+    "head",
+    "/v8/artifacts/{hash}",
+    "Check for a Turborepo artifact",
+    "compat",
+    Group::Protected,
+    Body::Empty,
+  ),
+  upload(
+    "/v8/artifacts/{hash}",
+    "Upload a Turborepo artifact",
+    "compat",
+    Group::Protected,
+  ),
+  op(
+    "get",
+    "/mirror/{*key}",
+    "Download a compiler cache object",
+    "mirror",
+    Group::Mirror,
+    Body::Binary,
+  ),
+  op(
+    "head",
+    "/mirror/{*key}",
+    "Check for a compiler cache object",
+    "mirror",
+    Group::Mirror,
+    Body::Empty,
+  ),
+  op(
+    "post",
+    "/v1/manifest",
+    "Create a download manifest with signed URLs",
+    "manifest",
+    Group::Manifest,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/v1/signed/{hash}",
+    "Download an artifact through a signed URL",
+    "manifest",
+    Group::Signed,
+    Body::Binary,
+  ),
+  upload(
+    "/v1/cache/{hash}/task",
+    "Attach task metadata to an artifact",
+    "tasks",
+    Group::TaskMetadata,
+  ),
+  op(
+    "get",
+    "/admin/tasks",
+    "List task metadata",
+    "tasks",
+    Group::TaskMetadata,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/admin/tasks/{hash}",
+    "Task metadata of an artifact",
+    "tasks",
+    Group::TaskMetadata,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/v1/affinity/{hash}",
+    "Replica that should serve a hash",
+    "affinity",
+    Group::Affinity,
+    Body::Json,
+  ),
+  op(
+    "options",
+    "/v1/tus",
+    "Supported tus protocol version and extensions",
+    "tus",
+    Group::Tus,
+    Body::Empty,
+  ),
+  op(
+    "post",
+    "/v1/tus",
+    "Start a resumable upload",
+    "tus",
+    Group::Tus,
+    Body::Empty,
+  ),
+  op(
+    "head",
+    "/v1/tus/{id}",
+    "Offset of a resumable upload",
+    "tus",
+    Group::Tus,
+    Body::Empty,
+  ),
+  op(
+    "patch",
+    "/v1/tus/{id}",
+    "Append to a resumable upload",
+    "tus",
+    Group::Tus,
+    Body::Empty,
+  ),
+  op(
+    "delete",
+    "/v1/tus/{id}",
+    "Abandon a resumable upload",
+    "tus",
+    Group::Tus,
+    Body::Empty,
+  ),
+  op(
+    "post",
+    "/admin/tokens/{name}/mint",
+    "Mint a short-lived token bound to a token's namespace",
+    "admin",
+    Group::TokenMinting,
+    Body::Json,
+  ),
+  op(
+    "post",
+    "/admin/tokens/{name}/revoke",
+    "Revoke a token immediately",
+    "admin",
+    Group::TokenRevocation,
+    Body::Json,
+  ),
+  op(
+    "get",
+    "/debug/pprof/profile",
+    "Capture a CPU profile",
+    "debug",
+    Group::Pprof,
+    Body::Binary,
+  ),
+  op(
+    "get",
+    "/debug/pprof/flamegraph",
+    "Capture a CPU flamegraph",
+    "debug",
+    Group::Pprof,
+    Body::Binary,
+  ),
+];
+
+impl Group {
+  fn enabled(self, state: &AppState) -> bool {
+    match self {
+      Group::Public | Group::Protected => true,
+      Group::Mirror => state.mirror.is_some(),
+      Group::Manifest | Group::Signed => state.manifest.is_some(),
+      Group::TaskMetadata => state.task_metadata.is_some(),
+      Group::Affinity => state.affinity.is_some(),
+      Group::Tus => state.tus.is_some(),
+      Group::TokenMinting => state.token_minting.is_some(),
+      Group::TokenRevocation => state.revoked_tokens.is_some(),
+      Group::Pprof => cfg!(feature = "pprof"),
+    }
+  }
+
+  fn requires_token(self, state: &AppState) -> bool {
+    match self {
+      Group::Public | Group::Signed => false,
+      Group::Mirror => state
+        .mirror
+        .as_ref()
+        .is_some_and(|mirror| mirror.require_auth()),
+      _ => true,
+    }
+  }
+}
+
+/// OpenAPI path template and parameter names of an axum route path
+fn openapi_path(path: &str) -> (String, Vec<String>) {
+  let mut params = Vec::new();
+  let segments: Vec<String> = path
+    .split('/')
+    .map(|segment| match segment.strip_prefix('{') {
+      Some(rest) => {
+        let name = rest.trim_end_matches('}').trim_start_matches('*');
+        params.push(name.to_string());
+        format!("{{{}}}", name)
+      },
+      None => segment.to_string(),
+    })
+    .collect();
+  (segments.join("/"), params)
+}
+
+fn responses(operation: &Operation, secured: bool) -> Value {
+  let ok = match operation.response {
+    Body::Binary => json!({
+      "description": "OK",
+      "content": {"application/octet-stream": {"schema": {"type": "string", "format": "binary"}}},
+    }),
+    Body::Json => json!({
+      "description": "OK",
+      "content": {"application/json": {"schema": {"type": "object"}}},
+    }),
+    Body::Text => json!({
+      "description": "OK",
+      "content": {"text/plain": {"schema": {"type": "string"}}},
+    }),
+    Body::Empty => json!({"description": "OK"}),
+  };
+  let mut responses = Map::new();
+  responses.insert("200".to_string(), ok);
+  if secured {
+    responses.insert(
+      "401".to_string(),
+      json!({"description": "Missing or invalid access token"}),
+    );
+    responses.insert(
+      "403".to_string(),
+      json!({"description": "The access token is not allowed to perform this operation"}),
+    );
+  }
+  if operation.upload {
+    responses.insert(
+      "409".to_string(),
+      json!({"description": "Cannot override an existing record"}),
+    );
+  }
+  if operation.path.contains('{') {
+    responses.insert(
+      "404".to_string(),
+      json!({"description": "The record was not found"}),
+    );
+  }
+  Value::Object(responses)
+}
+
+/// OpenAPI 3.0 document of the routes served with the given state
+///
+/// The Nx remote cache operations (`/v1/cache/{hash}`) follow Nx's published spec; all
+/// other routes are extensions of this server.
+pub fn document(state: &AppState) -> Value {
+  let mut paths = Map::new();
+  for operation in OPERATIONS
+    .iter()
+    .filter(|operation| operation.group.enabled(state))
+  {
+    let (path, params) = openapi_path(operation.path);
+    let secured = operation.group.requires_token(state);
+    let mut item = json!({
+      "summary": operation.summary,
+      "tags": [operation.tag],
+      "responses": responses(operation, secured),
+    });
+    if !params.is_empty() {
+      item["parameters"] = params
+        .iter()
+        .map(|name| json!({"name": name, "in": "path", "required": true, "schema": {"type": "string"}}))
+        .collect();
+    }
+    if operation.upload {
+      item["requestBody"] = json!({
+        "required": true,
+        "content": {"application/octet-stream": {"schema": {"type": "string", "format": "binary"}}},
+      });
+    }
+    if secured {
+      item["security"] = json!([{"bearerAuth": []}]);
+    }
+    let entry = paths
+      .entry(path)
+      .or_insert_with(|| Value::Object(Map::new()));
+    entry[operation.method] = item;
+  }
+
+  json!({
+    "openapi": "3.0.3",
+    "info": {
+      "title": "Nx Cache Server",
+      "version": env!("CARGO_PKG_VERSION"),
+    },
+    "servers": [{"url": state.base_path.as_deref().unwrap_or("/")}],
+    "paths": paths,
+    "components": {
+      "securitySchemes": {
+        "bearerAuth": {"type": "http", "scheme": "bearer"},
+      },
+    },
+  })
+}
+
+pub async fn openapi_json(State(state): State<AppState>) -> Json<Value> {
+  Json(document(&state))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use regex::Regex;
+  use std::collections::BTreeSet;
+
+  /// Route paths registered in the router modules' source
+  fn routed_paths() -> BTreeSet<String> {
+    let sources = [
+      include_str!("router.rs"),
+      include_str!("affinity.rs"),
+      include_str!("bundle.rs"),
+      include_str!("compat.rs"),
+      include_str!("dead_letters.rs"),
+      include_str!("manifest.rs"),
+      include_str!("mirror.rs"),
+      include_str!("task_metadata.rs"),
+      include_str!("turbo.rs"),
+      include_str!("tus.rs"),
+    ];
+    let route = Regex::new(r#"\.route\(\s*"([^"]+)""#).unwrap();
+    sources
+      .iter()
+      .flat_map(|source| route.captures_iter(source))
+      .map(|captures| captures[1].to_string())
+      .collect()
+  }
+
+  #[test]
+  fn test_operations_cover_every_route() {
+    let documented: BTreeSet<String> = OPERATIONS
+      .iter()
+      .map(|operation| operation.path.to_string())
+      .collect();
+    assert_eq!(documented, routed_paths());
+  }
+
+  #[test]
+  fn test_openapi_path() {
+    assert_eq!(
+      openapi_path("/v1/cache/{hash}/task"),
+      (
+        "/v1/cache/{hash}/task".to_string(),
+        vec!["hash".to_string()]
+      )
+    );
+    assert_eq!(
+      openapi_path("/please/{*key}"),
+      ("/please/{key}".to_string(), vec!["key".to_string()])
+    );
+  }
+}
@@ -0,0 +1,109 @@
+//! CPU profiling endpoints, compiled only with the `pprof` feature.
+//!
+//! - `GET /debug/pprof/profile?seconds=N` returns a protobuf profile for `go tool pprof`
+//! - `GET /debug/pprof/flamegraph?seconds=N` returns an SVG flamegraph
+
+use crate::server::error::ServerError;
+use axum::{
+  extract::Query,
+  http::{header, StatusCode},
+  response::{IntoResponse, Response},
+};
+use pprof::protos::Message;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const DEFAULT_SECONDS: u64 = 10;
+const MAX_SECONDS: u64 = 120;
+const SAMPLING_FREQUENCY: i32 = 99;
+
+/// Only one profile may run at a time, profiling is process-wide
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileParams {
+  seconds: Option<u64>,
+}
+
+enum ProfileFormat {
+  Protobuf,
+  Flamegraph,
+}
+
+pub async fn cpu_profile(Query(params): Query<ProfileParams>) -> Result<Response, ServerError> {
+  let body = capture(params.seconds, ProfileFormat::Protobuf).await?;
+  Ok(
+    (
+      StatusCode::OK,
+      [(header::CONTENT_TYPE, "application/octet-stream")],
+      body,
+    )
+      .into_response(),
+  )
+}
+
+pub async fn flamegraph(Query(params): Query<ProfileParams>) -> Result<Response, ServerError> {
+  let body = capture(params.seconds, ProfileFormat::Flamegraph).await?;
+  Ok(
+    (
+      StatusCode::OK,
+      [(header::CONTENT_TYPE, "image/svg+xml")],
+      body,
+    )
+      .into_response(),
+  )
+}
+
+async fn capture(seconds: Option<u64>, format: ProfileFormat) -> Result<Vec<u8>, ServerError> {
+  let seconds = seconds.unwrap_or(DEFAULT_SECONDS).clamp(1, MAX_SECONDS);
+
+  if PROFILING
+    .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+    .is_err()
+  {
+    tracing::warn!("CPU profile requested while another profile is running");
+    return Err(ServerError::ProfilerBusy);
+  }
+
+  tracing::info!("Capturing {}s CPU profile", seconds);
+
+  // The profiler guard is not Send, so the whole capture runs on a blocking thread
+  let result = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, String> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+      .frequency(SAMPLING_FREQUENCY)
+      .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+      .build()
+      .map_err(|e| e.to_string())?;
+
+    std::thread::sleep(Duration::from_secs(seconds));
+
+    let report = guard.report().build().map_err(|e| e.to_string())?;
+    let mut body = Vec::new();
+    match format {
+      ProfileFormat::Protobuf => {
+        let profile = report.pprof().map_err(|e| e.to_string())?;
+        profile.encode(&mut body).map_err(|e| e.to_string())?;
+      },
+      ProfileFormat::Flamegraph => {
+        report.flamegraph(&mut body).map_err(|e| e.to_string())?;
+      },
+    }
+    Ok(body)
+  })
+  .await;
+
+  PROFILING.store(false, Ordering::Release);
+
+  match result {
+    Ok(Ok(body)) => Ok(body),
+    Ok(Err(err)) => {
+      tracing::error!("CPU profiling failed: {}", err);
+      Err(ServerError::InternalError)
+    },
+    Err(err) => {
+      tracing::error!("CPU profiling task panicked: {}", err);
+      Err(ServerError::InternalError)
+    },
+  }
+}
@@ -0,0 +1,254 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Number of errors kept unless configured otherwise
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// An error logged while serving requests
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorRecord {
+  /// Unix time in milliseconds
+  pub timestamp_ms: u64,
+  /// Module that logged the error
+  pub target: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub token: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub operation: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub request_id: Option<String>,
+  pub message: String,
+}
+
+/// Ring buffer of the most recent errors
+#[derive(Debug)]
+pub struct RecentErrors {
+  entries: Mutex<VecDeque<ErrorRecord>>,
+  capacity: AtomicUsize,
+}
+
+impl RecentErrors {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      entries: Mutex::new(VecDeque::with_capacity(capacity)),
+      capacity: AtomicUsize::new(capacity),
+    }
+  }
+
+  /// Buffer shared by the tracing layer and the server
+  pub fn global() -> &'static Arc<RecentErrors> {
+    static GLOBAL: OnceLock<Arc<RecentErrors>> = OnceLock::new();
+    GLOBAL.get_or_init(|| Arc::new(RecentErrors::new(DEFAULT_CAPACITY)))
+  }
+
+  /// Change how many errors are kept, dropping the oldest ones if needed
+  pub fn set_capacity(&self, capacity: usize) {
+    self.capacity.store(capacity, Ordering::Relaxed);
+    let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+    while entries.len() > capacity {
+      entries.pop_front();
+    }
+  }
+
+  pub fn record(&self, record: ErrorRecord) {
+    let capacity = self.capacity.load(Ordering::Relaxed);
+    if capacity == 0 {
+      return;
+    }
+    let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+    while entries.len() >= capacity {
+      entries.pop_front();
+    }
+    entries.push_back(record);
+  }
+
+  /// Recorded errors, newest first
+  pub fn snapshot(&self) -> Vec<ErrorRecord> {
+    let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+    entries.iter().rev().cloned().collect()
+  }
+}
+
+/// Request context stored on spans that carry `token`, `operation` or `request_id` fields
+#[derive(Debug, Default, Clone)]
+struct SpanContext {
+  token: Option<String>,
+  operation: Option<String>,
+  request_id: Option<String>,
+}
+
+impl SpanContext {
+  fn is_empty(&self) -> bool {
+    self.token.is_none() && self.operation.is_none() && self.request_id.is_none()
+  }
+
+  /// Fill the fields still missing from an enclosing span's context
+  fn or(self, outer: &SpanContext) -> SpanContext {
+    SpanContext {
+      token: self.token.or_else(|| outer.token.clone()),
+      operation: self.operation.or_else(|| outer.operation.clone()),
+      request_id: self.request_id.or_else(|| outer.request_id.clone()),
+    }
+  }
+}
+
+impl Visit for SpanContext {
+  fn record_str(&mut self, field: &Field, value: &str) {
+    match field.name() {
+      "token" => self.token = Some(value.to_string()),
+      "operation" => self.operation = Some(value.to_string()),
+      "request_id" => self.request_id = Some(value.to_string()),
+      _ => {},
+    }
+  }
+
+  fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+    self.record_str(field, &format!("{:?}", value));
+  }
+}
+
+/// Collects an event's message followed by its other fields
+#[derive(Default)]
+struct MessageVisitor {
+  message: String,
+  fields: String,
+}
+
+impl Visit for MessageVisitor {
+  fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+    if field.name() == "message" {
+      let _ = write!(self.message, "{:?}", value);
+    } else {
+      let _ = write!(self.fields, " {}={:?}", field.name(), value);
+    }
+  }
+}
+
+/// Tracing layer feeding ERROR events into [`RecentErrors`]
+///
+/// Token, operation and request id are each taken from the closest enclosing span that
+/// records them.
+pub struct RecentErrorsLayer {
+  errors: Arc<RecentErrors>,
+}
+
+impl RecentErrorsLayer {
+  pub fn new(errors: Arc<RecentErrors>) -> Self {
+    Self { errors }
+  }
+}
+
+impl<S> Layer<S> for RecentErrorsLayer
+where
+  S: Subscriber + for<'a> LookupSpan<'a>,
+{
+  fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+    let mut context = SpanContext::default();
+    attrs.record(&mut context);
+    if !context.is_empty() {
+      if let Some(span) = ctx.span(id) {
+        span.extensions_mut().insert(context);
+      }
+    }
+  }
+
+  fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+    if *event.metadata().level() != Level::ERROR {
+      return;
+    }
+
+    let mut visitor = MessageVisitor::default();
+    event.record(&mut visitor);
+    let context = ctx
+      .event_scope(event)
+      .map(|scope| {
+        scope
+          .into_iter()
+          .fold(SpanContext::default(), |context, span| {
+            match span.extensions().get::<SpanContext>() {
+              Some(outer) => context.or(outer),
+              None => context,
+            }
+          })
+      })
+      .unwrap_or_default();
+
+    self.errors.record(ErrorRecord {
+      timestamp_ms: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0),
+      target: event.metadata().target().to_string(),
+      token: context.token,
+      operation: context.operation,
+      request_id: context.request_id,
+      message: visitor.message + &visitor.fields,
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tracing_subscriber::prelude::*;
+
+  #[test]
+  fn test_ring_buffer_keeps_newest() {
+    let errors = RecentErrors::new(2);
+    for message in ["a", "b", "c"] {
+      errors.record(ErrorRecord {
+        timestamp_ms: 0,
+        target: "test".to_string(),
+        token: None,
+        operation: None,
+        request_id: None,
+        message: message.to_string(),
+      });
+    }
+
+    let messages: Vec<String> = errors.snapshot().into_iter().map(|e| e.message).collect();
+    assert_eq!(messages, vec!["c", "b"]);
+
+    errors.set_capacity(1);
+    assert_eq!(errors.snapshot().len(), 1);
+  }
+
+  #[test]
+  fn test_layer_records_errors_with_span_context() {
+    let errors = Arc::new(RecentErrors::new(10));
+    let subscriber = tracing_subscriber::registry().with(RecentErrorsLayer::new(errors.clone()));
+
+    tracing::subscriber::with_default(subscriber, || {
+      let http = tracing::info_span!("http", request_id = "abc123");
+      let _http = http.enter();
+      let span = tracing::info_span!("request", token = "ci", operation = "GET /v1/cache/{hash}");
+      let _entered = span.enter();
+      tracing::warn!("ignored");
+      tracing::error!(bucket = "main", "MinIO stat_object failed: {}", "timeout");
+    });
+
+    let recorded = errors.snapshot();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].target, module_path!());
+    assert_eq!(recorded[0].token.as_deref(), Some("ci"));
+    assert_eq!(
+      recorded[0].operation.as_deref(),
+      Some("GET /v1/cache/{hash}")
+    );
+    assert_eq!(recorded[0].request_id.as_deref(), Some("abc123"));
+    assert_eq!(
+      recorded[0].message,
+      "MinIO stat_object failed: timeout bucket=\"main\""
+    );
+  }
+}
@@ -0,0 +1,367 @@
+use crate::domain::config::{Config, ConfigError};
+use crate::domain::storage::StorageError;
+use crate::infra::failover::DEFAULT_RECOVERY_INTERVAL;
+use crate::infra::multi_storage::MultiStorageRouter;
+use crate::server::runtime::serve_app;
+use crate::server::{
+  error::{self, ServerError},
+  handlers::require_admin,
+  middleware::AuthenticatedToken,
+  AppState,
+};
+use axum::{
+  extract::{Request, State},
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  Extension, Json, Router,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tower::util::ServiceExt;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReloadError {
+  #[error("{0}")]
+  Config(#[from] ConfigError),
+  #[error("Failed to initialize storage: {0}")]
+  Storage(StorageError),
+  #[error("Bucket connectivity test failed: {0}")]
+  Connectivity(StorageError),
+  #[error("Failed to read revoked tokens: {0}")]
+  RevokedTokens(std::io::Error),
+}
+
+/// Buckets and tokens in effect after a reload
+#[derive(Debug, Serialize)]
+pub struct ReloadResult {
+  pub buckets: usize,
+  pub tokens: usize,
+}
+
+/// Serves requests with the current application state and swaps in storages and tokens
+/// re-read from the configuration file
+///
+/// Runtime trackers such as egress counters and recent errors are carried over, and every
+/// setting besides buckets and tokens keeps the value it had at startup. In-flight requests
+/// finish on the router they started on.
+pub struct Reloader {
+  config_file: PathBuf,
+  normalize_paths: bool,
+  current: RwLock<(AppState, Router)>,
+  /// Serializes reloads, so two of them never build storages side by side
+  reloading: tokio::sync::Mutex<()>,
+}
+
+impl Reloader {
+  pub fn new(config_file: impl Into<PathBuf>, state: AppState, normalize_paths: bool) -> Self {
+    let router = serve_app(&state, normalize_paths);
+    Self {
+      config_file: config_file.into(),
+      normalize_paths,
+      current: RwLock::new((state, router)),
+      reloading: tokio::sync::Mutex::new(()),
+    }
+  }
+
+  /// Application state requests are currently served with
+  pub fn state(&self) -> AppState {
+    self
+      .current
+      .read()
+      .unwrap_or_else(|e| e.into_inner())
+      .0
+      .clone()
+  }
+
+  fn router(&self) -> Router {
+    self
+      .current
+      .read()
+      .unwrap_or_else(|e| e.into_inner())
+      .1
+      .clone()
+  }
+
+  /// Router handing each request to the router current when it arrives
+  pub fn into_router(self: Arc<Self>) -> Router {
+    Router::new().fallback(dispatch).with_state(self)
+  }
+
+  /// Re-read the configuration file and swap in the storages and tokens it describes
+  ///
+  /// Revoked tokens are read again from their file, so entries removed from it are lifted.
+  /// Nothing changes unless the new configuration is valid, all of its buckets are reachable
+  /// and the revoked tokens file can be read.
+  pub async fn reload(&self) -> Result<ReloadResult, ReloadError> {
+    let _reloading = self.reloading.lock().await;
+    tracing::info!(
+      "Reloading configuration from: {}",
+      self.config_file.display()
+    );
+
+    let config = Config::from_file(&self.config_file)?.resolve_env_vars()?;
+    let storage = MultiStorageRouter::from_config(&config)
+      .await
+      .map_err(ReloadError::Storage)?;
+    storage
+      .test_all_buckets()
+      .await
+      .map_err(ReloadError::Connectivity)?;
+    storage.probe_buckets().await;
+    if let Some(revoked) = &self.state().revoked_tokens {
+      let count = revoked.reload().await.map_err(ReloadError::RevokedTokens)?;
+      tracing::info!("{} revoked token(s) read again", count);
+    }
+
+    let result = ReloadResult {
+      buckets: config.buckets.len(),
+      tokens: storage.token_names().count(),
+    };
+    let state = self.state().with_storage(storage);
+    let router = serve_app(&state, self.normalize_paths);
+    *self.current.write().unwrap_or_else(|e| e.into_inner()) = (state, router);
+
+    tracing::info!(
+      "Configuration reloaded with {} bucket(s) and {} token(s)",
+      result.buckets,
+      result.tokens
+    );
+    Ok(result)
+  }
+}
+
+async fn dispatch(State(reloader): State<Arc<Reloader>>, mut request: Request) -> Response {
+  let router = reloader.router();
+  request.extensions_mut().insert(reloader);
+  match router.oneshot(request).await {
+    Ok(response) => response,
+    Err(infallible) => match infallible {},
+  }
+}
+
+/// Reload buckets and tokens from the configuration file, for tokens with `admin` access
+pub async fn reload_config(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  reloader: Option<Extension<Arc<Reloader>>>,
+) -> Result<Response, ServerError> {
+  let service = require_admin(&state, &token)?;
+  let Some(Extension(reloader)) = reloader else {
+    return Err(ServerError::Storage(StorageError::NotFound));
+  };
+  tracing::info!("Token {} requested a configuration reload", service.name);
+
+  match reloader.reload().await {
+    Ok(result) => Ok(Json(result).into_response()),
+    Err(err) => {
+      tracing::error!("Configuration reload failed: {}", err);
+      let status = match err {
+        ReloadError::Config(_) | ReloadError::RevokedTokens(_) => StatusCode::BAD_REQUEST,
+        ReloadError::Storage(_) | ReloadError::Connectivity(_) => StatusCode::SERVICE_UNAVAILABLE,
+      };
+      let response = (status, [("Content-Type", "text/plain")], err.to_string()).into_response();
+      if status == StatusCode::SERVICE_UNAVAILABLE {
+        return Ok(error::with_retry_after(response, DEFAULT_RECOVERY_INTERVAL));
+      }
+      Ok(response)
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::server::revoked_tokens::RevokedTokens;
+  use axum::body::Body;
+  use axum::http::header;
+
+  fn config_yaml(root: &std::path::Path, tokens: &[(&str, &str)]) -> String {
+    let mut yaml = format!(
+      "port: 3000\nbuckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\nserviceAccessTokens:\n",
+      root.display()
+    );
+    for (name, secret) in tokens {
+      yaml.push_str(&format!(
+        "  - name: {}\n    bucket: local\n    prefix: /{}\n    accessToken: {}\n    admin: true\n",
+        name, name, secret
+      ));
+    }
+    yaml
+  }
+
+  async fn whoami(app: &Router, secret: &str) -> StatusCode {
+    let request = Request::builder()
+      .uri("/v1/whoami")
+      .header(header::AUTHORIZATION, format!("Bearer {}", secret))
+      .body(Body::empty())
+      .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+  }
+
+  #[tokio::test]
+  async fn test_reload_swaps_tokens() {
+    let root = tempfile::tempdir().unwrap();
+    let config_file = root.path().join("config.yaml");
+    std::fs::write(&config_file, config_yaml(root.path(), &[("ci", "first")])).unwrap();
+    let config = Config::from_file(&config_file)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let storage = MultiStorageRouter::from_config(&config).await.unwrap();
+    let app = Arc::new(Reloader::new(&config_file, AppState::new(storage), false)).into_router();
+    assert_eq!(whoami(&app, "second").await, StatusCode::UNAUTHORIZED);
+
+    let yaml = config_yaml(root.path(), &[("ci", "first"), ("dev", "second")]);
+    std::fs::write(&config_file, yaml).unwrap();
+    let request = Request::builder()
+      .method("POST")
+      .uri("/admin/reload")
+      .header(header::AUTHORIZATION, "Bearer first")
+      .body(Body::empty())
+      .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(whoami(&app, "second").await, StatusCode::OK);
+
+    // An invalid file leaves the running configuration in place
+    std::fs::write(&config_file, "buckets: [").unwrap();
+    let request = Request::builder()
+      .method("POST")
+      .uri("/admin/reload")
+      .header(header::AUTHORIZATION, "Bearer first")
+      .body(Body::empty())
+      .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(whoami(&app, "second").await, StatusCode::OK);
+  }
+
+  #[tokio::test]
+  async fn test_reload_keeps_previous_token_deadline() {
+    let root = tempfile::tempdir().unwrap();
+    let config_file = root.path().join("config.yaml");
+    let now = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    let yaml = format!(
+      "{}    previousAccessToken: old\n    previousAccessTokenValidUntil: \"{}\"\n",
+      config_yaml(root.path(), &[("ci", "current")]),
+      crate::domain::http_date::format_rfc3339(now + 2)
+    );
+    std::fs::write(&config_file, yaml).unwrap();
+    let config = Config::from_file(&config_file)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let storage = MultiStorageRouter::from_config(&config).await.unwrap();
+    let app = Arc::new(Reloader::new(&config_file, AppState::new(storage), false)).into_router();
+    assert_eq!(whoami(&app, "old").await, StatusCode::OK);
+
+    // A reload after the deadline does not accept the previous token again
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    let request = Request::builder()
+      .method("POST")
+      .uri("/admin/reload")
+      .header(header::AUTHORIZATION, "Bearer current")
+      .body(Body::empty())
+      .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(whoami(&app, "old").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(whoami(&app, "current").await, StatusCode::OK);
+  }
+
+  #[tokio::test]
+  async fn test_reload_lifts_revocation_of_rotated_token() {
+    let root = tempfile::tempdir().unwrap();
+    let config_file = root.path().join("config.yaml");
+    let tokens = [("admin", "admin"), ("ci", "leaked")];
+    std::fs::write(&config_file, config_yaml(root.path(), &tokens)).unwrap();
+    let config = Config::from_file(&config_file)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let storage = MultiStorageRouter::from_config(&config).await.unwrap();
+    let revoked = RevokedTokens::load(root.path().join("revoked.json")).unwrap();
+    let state = AppState::new(storage).with_revoked_tokens(revoked);
+    let app = Arc::new(Reloader::new(&config_file, state, false)).into_router();
+    let request = |uri: &str| {
+      Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(header::AUTHORIZATION, "Bearer admin")
+        .body(Body::empty())
+        .unwrap()
+    };
+    let response = app
+      .clone()
+      .oneshot(request("/admin/tokens/ci/revoke"))
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(whoami(&app, "leaked").await, StatusCode::UNAUTHORIZED);
+
+    // A reload with the same secret keeps the revocation
+    let response = app.clone().oneshot(request("/admin/reload")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(whoami(&app, "leaked").await, StatusCode::UNAUTHORIZED);
+
+    // A new secret is accepted, while the revoked one stays rejected as the previous token
+    let yaml = format!(
+      "{}    previousAccessToken: leaked\n    previousAccessTokenValidUntil: \"2099-01-01T00:00:00Z\"\n",
+      config_yaml(root.path(), &[("admin", "admin"), ("ci", "rotated")])
+    );
+    std::fs::write(&config_file, yaml).unwrap();
+    let response = app.clone().oneshot(request("/admin/reload")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(whoami(&app, "rotated").await, StatusCode::OK);
+    assert_eq!(whoami(&app, "leaked").await, StatusCode::UNAUTHORIZED);
+  }
+
+  #[tokio::test]
+  async fn test_reload_accepts_minted_tokens_of_added_tokens() {
+    let root = tempfile::tempdir().unwrap();
+    let config_file = root.path().join("config.yaml");
+    let minting = "tokenMinting:\n  signingKey: minting-key\n";
+    let yaml = |tokens: &[(&str, &str)]| format!("{}{}", config_yaml(root.path(), tokens), minting);
+    std::fs::write(&config_file, yaml(&[("admin", "admin")])).unwrap();
+    let config = Config::from_file(&config_file)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let storage = MultiStorageRouter::from_config(&config).await.unwrap();
+    let state = AppState::new(storage)
+      .with_jwt(&config.jwt)
+      .with_token_minting(config.token_minting.as_ref().unwrap());
+    let app = Arc::new(Reloader::new(&config_file, state, false)).into_router();
+    let request = |uri: &str| {
+      Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(header::AUTHORIZATION, "Bearer admin")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{}"))
+        .unwrap()
+    };
+
+    std::fs::write(&config_file, yaml(&[("admin", "admin"), ("dev", "dev")])).unwrap();
+    let response = app.clone().oneshot(request("/admin/reload")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+      .clone()
+      .oneshot(request("/admin/tokens/dev/mint"))
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    let minted: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(
+      whoami(&app, minted["token"].as_str().unwrap()).await,
+      StatusCode::OK
+    );
+  }
+}
@@ -0,0 +1,315 @@
+//! Messages of the Bazel remote execution API (`build.bazel.remote.execution.v2`) and the
+//! ByteStream API (`google.bytestream`) used by the gRPC remote cache
+//!
+//! Only the fields the cache reads or writes are declared; prost skips the others. Field tags
+//! follow the upstream `.proto` definitions.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use prost::encoding::{
+  encode_key, encode_varint, fixed32, fixed64, skip_field, uint64, DecodeContext, WireType,
+};
+use prost::DecodeError;
+
+/// `DigestFunction.Value.SHA256`
+pub const DIGEST_FUNCTION_SHA256: i32 = 1;
+
+/// Content digest: lowercase hex SHA-256 and size of a blob
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Digest {
+  #[prost(string, tag = "1")]
+  pub hash: String,
+  #[prost(int64, tag = "2")]
+  pub size_bytes: i64,
+}
+
+/// Encoded `ActionResult`, kept as sent so no field is lost on its way through the cache
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActionResult(pub Bytes);
+
+impl prost::Message for ActionResult {
+  fn encode_raw(&self, buf: &mut impl BufMut) {
+    buf.put_slice(&self.0);
+  }
+
+  fn merge_field(
+    &mut self,
+    tag: u32,
+    wire_type: WireType,
+    buf: &mut impl Buf,
+    ctx: DecodeContext,
+  ) -> Result<(), DecodeError> {
+    let mut field = BytesMut::from(&self.0[..]);
+    encode_key(tag, wire_type, &mut field);
+    match wire_type {
+      WireType::Varint => {
+        let mut value = 0;
+        uint64::merge(wire_type, &mut value, buf, ctx)?;
+        encode_varint(value, &mut field);
+      },
+      WireType::SixtyFourBit => {
+        let mut value = 0;
+        fixed64::merge(wire_type, &mut value, buf, ctx)?;
+        field.put_u64_le(value);
+      },
+      WireType::ThirtyTwoBit => {
+        let mut value = 0;
+        fixed32::merge(wire_type, &mut value, buf, ctx)?;
+        field.put_u32_le(value);
+      },
+      WireType::LengthDelimited => {
+        let mut value = Bytes::new();
+        prost::encoding::bytes::merge(wire_type, &mut value, buf, ctx)?;
+        encode_varint(value.len() as u64, &mut field);
+        field.put(value);
+      },
+      // Groups are not used by the remote execution API
+      WireType::StartGroup | WireType::EndGroup => return skip_field(wire_type, tag, buf, ctx),
+    }
+    self.0 = field.freeze();
+    Ok(())
+  }
+
+  fn encoded_len(&self) -> usize {
+    self.0.len()
+  }
+
+  fn clear(&mut self) {
+    self.0 = Bytes::new();
+  }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetActionResultRequest {
+  #[prost(string, tag = "1")]
+  pub instance_name: String,
+  #[prost(message, optional, tag = "2")]
+  pub action_digest: Option<Digest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UpdateActionResultRequest {
+  #[prost(string, tag = "1")]
+  pub instance_name: String,
+  #[prost(message, optional, tag = "2")]
+  pub action_digest: Option<Digest>,
+  /// The `ActionResult` message, read as its encoded bytes
+  #[prost(bytes = "bytes", tag = "3")]
+  pub action_result: Bytes,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FindMissingBlobsRequest {
+  #[prost(string, tag = "1")]
+  pub instance_name: String,
+  #[prost(message, repeated, tag = "2")]
+  pub blob_digests: Vec<Digest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FindMissingBlobsResponse {
+  #[prost(message, repeated, tag = "2")]
+  pub missing_blob_digests: Vec<Digest>,
+}
+
+/// `google.rpc.Status`, without details
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RpcStatus {
+  #[prost(int32, tag = "1")]
+  pub code: i32,
+  #[prost(string, tag = "2")]
+  pub message: String,
+}
+
+impl From<tonic::Status> for RpcStatus {
+  fn from(status: tonic::Status) -> Self {
+    Self {
+      code: status.code() as i32,
+      message: status.message().to_string(),
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BatchUpdateBlobsRequest {
+  #[prost(string, tag = "1")]
+  pub instance_name: String,
+  #[prost(message, repeated, tag = "2")]
+  pub requests: Vec<UpdateBlob>,
+}
+
+/// `BatchUpdateBlobsRequest.Request`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UpdateBlob {
+  #[prost(message, optional, tag = "1")]
+  pub digest: Option<Digest>,
+  #[prost(bytes = "bytes", tag = "2")]
+  pub data: Bytes,
+  /// `Compressor.Value`; only `IDENTITY` (0) is supported
+  #[prost(int32, tag = "3")]
+  pub compressor: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BatchUpdateBlobsResponse {
+  #[prost(message, repeated, tag = "1")]
+  pub responses: Vec<UpdatedBlob>,
+}
+
+/// `BatchUpdateBlobsResponse.Response`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UpdatedBlob {
+  #[prost(message, optional, tag = "1")]
+  pub digest: Option<Digest>,
+  #[prost(message, optional, tag = "2")]
+  pub status: Option<RpcStatus>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BatchReadBlobsRequest {
+  #[prost(string, tag = "1")]
+  pub instance_name: String,
+  #[prost(message, repeated, tag = "2")]
+  pub digests: Vec<Digest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BatchReadBlobsResponse {
+  #[prost(message, repeated, tag = "1")]
+  pub responses: Vec<ReadBlob>,
+}
+
+/// `BatchReadBlobsResponse.Response`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReadBlob {
+  #[prost(message, optional, tag = "1")]
+  pub digest: Option<Digest>,
+  #[prost(bytes = "bytes", tag = "2")]
+  pub data: Bytes,
+  #[prost(message, optional, tag = "3")]
+  pub status: Option<RpcStatus>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetCapabilitiesRequest {
+  #[prost(string, tag = "1")]
+  pub instance_name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerCapabilities {
+  #[prost(message, optional, tag = "1")]
+  pub cache_capabilities: Option<CacheCapabilities>,
+  #[prost(message, optional, tag = "4")]
+  pub low_api_version: Option<SemVer>,
+  #[prost(message, optional, tag = "5")]
+  pub high_api_version: Option<SemVer>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CacheCapabilities {
+  /// `DigestFunction.Value`s
+  #[prost(int32, repeated, tag = "1")]
+  pub digest_functions: Vec<i32>,
+  #[prost(message, optional, tag = "2")]
+  pub action_cache_update_capabilities: Option<ActionCacheUpdateCapabilities>,
+  #[prost(int64, tag = "4")]
+  pub max_batch_total_size_bytes: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ActionCacheUpdateCapabilities {
+  #[prost(bool, tag = "1")]
+  pub update_enabled: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SemVer {
+  #[prost(int32, tag = "1")]
+  pub major: i32,
+  #[prost(int32, tag = "2")]
+  pub minor: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReadRequest {
+  #[prost(string, tag = "1")]
+  pub resource_name: String,
+  #[prost(int64, tag = "2")]
+  pub read_offset: i64,
+  #[prost(int64, tag = "3")]
+  pub read_limit: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReadResponse {
+  #[prost(bytes = "bytes", tag = "10")]
+  pub data: Bytes,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WriteRequest {
+  #[prost(string, tag = "1")]
+  pub resource_name: String,
+  #[prost(int64, tag = "2")]
+  pub write_offset: i64,
+  #[prost(bool, tag = "3")]
+  pub finish_write: bool,
+  #[prost(bytes = "bytes", tag = "10")]
+  pub data: Bytes,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WriteResponse {
+  #[prost(int64, tag = "1")]
+  pub committed_size: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryWriteStatusRequest {
+  #[prost(string, tag = "1")]
+  pub resource_name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryWriteStatusResponse {
+  #[prost(int64, tag = "1")]
+  pub committed_size: i64,
+  #[prost(bool, tag = "2")]
+  pub complete: bool,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use prost::Message;
+
+  #[test]
+  fn test_action_result_round_trips_unknown_fields() {
+    // exit_code = 3, stdout_raw = "ok", a fixed64 and a fixed32 field
+    let mut encoded = Vec::new();
+    encode_key(4, WireType::Varint, &mut encoded);
+    encode_varint(3, &mut encoded);
+    encode_key(5, WireType::LengthDelimited, &mut encoded);
+    encode_varint(2, &mut encoded);
+    encoded.extend_from_slice(b"ok");
+    encode_key(100, WireType::SixtyFourBit, &mut encoded);
+    encoded.extend_from_slice(&7u64.to_le_bytes());
+    encode_key(101, WireType::ThirtyTwoBit, &mut encoded);
+    encoded.extend_from_slice(&9u32.to_le_bytes());
+
+    let result = ActionResult::decode(encoded.as_slice()).unwrap();
+    assert_eq!(result.0, encoded);
+    assert_eq!(result.encode_to_vec(), encoded);
+
+    // Embedded in an update, the same bytes come back out
+    let request = UpdateActionResultRequest {
+      instance_name: String::new(),
+      action_digest: None,
+      action_result: Bytes::from(encoded.clone()),
+    };
+    let decoded = UpdateActionResultRequest::decode(request.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded.action_result, encoded);
+
+    assert!(ActionResult::decode(&encoded[..encoded.len() - 1]).is_err());
+  }
+}
@@ -0,0 +1,117 @@
+use axum::{
+  extract::Request,
+  http::{HeaderName, HeaderValue},
+  middleware::Next,
+  response::Response,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+
+/// Header carrying the id that correlates a request with the server's log lines
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied request id that is propagated; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Id of the current request, available as a request extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Whether a client-supplied id is safe to echo in headers and log lines
+fn is_valid(id: &str) -> bool {
+  !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// A new random request id of 32 hex characters
+fn generate() -> String {
+  let mut bytes = [0u8; 16];
+  if SystemRandom::new().fill(&mut bytes).is_err() {
+    // Only has to be unique enough to find the request in the logs
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_nanos() as u64)
+      .unwrap_or(0);
+    bytes[..8].copy_from_slice(&nanos.to_be_bytes());
+    bytes[8..].copy_from_slice(&COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+  }
+  hex::encode(bytes)
+}
+
+/// Take the client's `x-request-id` or generate one, and tag the request's logs and response
+///
+/// Everything logged while handling the request runs inside a span carrying the id, and the
+/// response, errors included, echoes it in `x-request-id`.
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+  let id = request
+    .headers()
+    .get(&REQUEST_ID_HEADER)
+    .and_then(|value| value.to_str().ok())
+    .filter(|id| is_valid(id))
+    .map(str::to_string)
+    .unwrap_or_else(generate);
+  let Ok(value) = HeaderValue::from_str(&id) else {
+    return next.run(request).await;
+  };
+
+  request
+    .headers_mut()
+    .insert(REQUEST_ID_HEADER, value.clone());
+  request.extensions_mut().insert(RequestId(id.clone()));
+  let span = tracing::info_span!("http", request_id = %id);
+  let mut response = next.run(request).instrument(span).await;
+  response.headers_mut().insert(REQUEST_ID_HEADER, value);
+  response
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use axum::{body::Body, middleware::from_fn, routing::get, Extension, Router};
+  use tower::util::ServiceExt;
+
+  async fn echo(Extension(id): Extension<RequestId>) -> String {
+    id.0
+  }
+
+  async fn call(header: Option<&str>) -> (String, String) {
+    let app = Router::new()
+      .route("/", get(echo))
+      .layer(from_fn(request_id_middleware));
+    let mut request = Request::builder().uri("/");
+    if let Some(header) = header {
+      request = request.header(REQUEST_ID_HEADER, header);
+    }
+    let response = app
+      .oneshot(request.body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    let header = response.headers()[REQUEST_ID_HEADER]
+      .to_str()
+      .unwrap()
+      .to_string();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    (header, String::from_utf8(body.to_vec()).unwrap())
+  }
+
+  #[tokio::test]
+  async fn test_propagates_client_id() {
+    let (header, seen) = call(Some("ci-run-42")).await;
+    assert_eq!(header, "ci-run-42");
+    assert_eq!(seen, "ci-run-42");
+  }
+
+  #[tokio::test]
+  async fn test_generates_missing_or_invalid_id() {
+    let (header, seen) = call(None).await;
+    assert_eq!(header.len(), 32);
+    assert_eq!(header, seen);
+
+    let (header, _) = call(Some(&"x".repeat(MAX_REQUEST_ID_LEN + 1))).await;
+    assert_eq!(header.len(), 32);
+  }
+}
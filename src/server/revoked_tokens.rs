@@ -0,0 +1,201 @@
+use crate::domain::config::ResolvedServiceAccessToken;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Why and when a token was revoked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revocation {
+  /// RFC 3339 time of the revocation
+  pub revoked_at: String,
+  /// Name of the admin token that revoked it
+  pub revoked_by: String,
+  /// Hex SHA-256 of the token's configured secret when it was revoked, see
+  /// [`credential_sha256`]; none revokes the name whatever its secret
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub credential_sha256: Option<String>,
+}
+
+impl Revocation {
+  /// Whether the revocation still applies to `config`, i.e. its secret has not been rotated
+  pub fn applies_to(&self, config: &ResolvedServiceAccessToken) -> bool {
+    self.credential_sha256.is_none() || self.credential_sha256 == credential_sha256(config)
+  }
+
+  /// Whether `secret` is the secret that was revoked
+  pub fn revokes_secret(&self, secret: &str) -> bool {
+    self.credential_sha256.as_deref() == Some(&sha256_hex(secret))
+  }
+}
+
+/// Hex SHA-256 of the token's `accessTokenHash`, else of its `accessToken`; none for tokens
+/// reached through JWTs only, whose internal key is random
+pub fn credential_sha256(config: &ResolvedServiceAccessToken) -> Option<String> {
+  match &config.access_token_hash {
+    Some(hash) => Some(sha256_hex(hash)),
+    None if config.jwt_only => None,
+    None => Some(sha256_hex(&config.access_token)),
+  }
+}
+
+fn sha256_hex(value: &str) -> String {
+  hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+/// Names of revoked tokens, kept in memory and in a JSON file
+///
+/// A revoked name rejects every credential that maps to it: its access token, a previous
+/// access token, a matching hash and JWTs or minted tokens standing for it. The revocation
+/// lapses once the token is configured with a new secret, though the revoked secret stays
+/// rejected as its previous access token. The file is written on every revocation and read
+/// again on reload, so removing an entry from it restores the token.
+pub struct RevokedTokens {
+  path: PathBuf,
+  revoked: RwLock<BTreeMap<String, Revocation>>,
+  /// Serializes writes of the file
+  writing: tokio::sync::Mutex<()>,
+}
+
+impl RevokedTokens {
+  /// Read the revocations stored at `path`, none if the file does not exist yet
+  pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+    let path = path.into();
+    let revoked = read(&path, std::fs::read(&path))?;
+    Ok(Self {
+      path,
+      revoked: RwLock::new(revoked),
+      writing: tokio::sync::Mutex::new(()),
+    })
+  }
+
+  /// Replace the revocations in memory with those in the file
+  pub async fn reload(&self) -> io::Result<usize> {
+    let _writing = self.writing.lock().await;
+    let revoked = read(&self.path, tokio::fs::read(&self.path).await)?;
+    let count = revoked.len();
+    *self.revoked.write().unwrap_or_else(|e| e.into_inner()) = revoked;
+    Ok(count)
+  }
+
+  pub fn get(&self, name: &str) -> Option<Revocation> {
+    self
+      .revoked
+      .read()
+      .unwrap_or_else(|e| e.into_inner())
+      .get(name)
+      .cloned()
+  }
+
+  /// Revoke `name` and store the revocation
+  ///
+  /// An earlier revocation of the same name is kept while it still covers the secret being
+  /// revoked. One left behind by a secret that has since been rotated is replaced, so the
+  /// new secret is rejected too. The token is rejected from this call on, even if storing the
+  /// revocation fails.
+  pub async fn revoke(&self, name: &str, revocation: Revocation) -> io::Result<Revocation> {
+    let _writing = self.writing.lock().await;
+    let (revocation, contents) = {
+      let mut revoked = self.revoked.write().unwrap_or_else(|e| e.into_inner());
+      let revocation = match revoked.get(name) {
+        Some(earlier)
+          if earlier.credential_sha256.is_none()
+            || earlier.credential_sha256 == revocation.credential_sha256 =>
+        {
+          earlier.clone()
+        },
+        _ => {
+          revoked.insert(name.to_string(), revocation.clone());
+          revocation
+        },
+      };
+      (revocation, serde_json::to_vec_pretty(&*revoked)?)
+    };
+
+    // Written next to the file and renamed, so a crash never leaves it half-written
+    let temp = self.path.with_extension("tmp");
+    tokio::fs::write(&temp, contents).await?;
+    tokio::fs::rename(&temp, &self.path).await?;
+    Ok(revocation)
+  }
+}
+
+fn read(path: &Path, contents: io::Result<Vec<u8>>) -> io::Result<BTreeMap<String, Revocation>> {
+  match contents {
+    Ok(contents) => serde_json::from_slice(&contents).map_err(|e| {
+      io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid revoked tokens file {}: {}", path.display(), e),
+      )
+    }),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+    Err(e) => Err(e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_revoke() {
+    let root = tempfile::tempdir().unwrap();
+    let path = root.path().join("revoked.json");
+    let revoked = RevokedTokens::load(&path).unwrap();
+    assert_eq!(revoked.get("ci"), None);
+
+    let revocation = |by: &str, secret: Option<&str>| Revocation {
+      revoked_at: "2026-10-16T12:00:00Z".to_string(),
+      revoked_by: by.to_string(),
+      credential_sha256: secret.map(sha256_hex),
+    };
+    assert_eq!(
+      revoked
+        .revoke("ci", revocation("admin", Some("old")))
+        .await
+        .unwrap(),
+      revocation("admin", Some("old"))
+    );
+    // Revoking the same secret again keeps the first revocation
+    assert_eq!(
+      revoked
+        .revoke("ci", revocation("other", Some("old")))
+        .await
+        .unwrap(),
+      revocation("admin", Some("old"))
+    );
+    // Revoking a rotated secret replaces the revocation of the old one
+    assert_eq!(
+      revoked
+        .revoke("ci", revocation("other", Some("new")))
+        .await
+        .unwrap(),
+      revocation("other", Some("new"))
+    );
+    // A revocation without a secret covers every secret
+    revoked
+      .revoke("dev", revocation("admin", None))
+      .await
+      .unwrap();
+    assert_eq!(
+      revoked
+        .revoke("dev", revocation("other", Some("new")))
+        .await
+        .unwrap(),
+      revocation("admin", None)
+    );
+
+    // The revocation survives a restart, and removing it from the file lifts it on reload
+    let restarted = RevokedTokens::load(&path).unwrap();
+    assert_eq!(restarted.get("ci"), Some(revocation("other", Some("new"))));
+    std::fs::write(&path, "{}").unwrap();
+    assert_eq!(restarted.reload().await.unwrap(), 0);
+    assert_eq!(restarted.get("ci"), None);
+
+    std::fs::write(&path, "not json").unwrap();
+    assert!(RevokedTokens::load(&path).is_err());
+  }
+}
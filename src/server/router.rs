@@ -1,20 +1,166 @@
-use crate::server::{app_state::AppState, handlers, middleware};
+use crate::server::{
+  affinity, app_state::AppState, bundle, compat, dead_letters, handlers, manifest, middleware,
+  mirror, openapi, reload, request_id, task_metadata, turbo, tus, version,
+};
 use axum::{
-  middleware::from_fn_with_state,
-  routing::{get, put},
+  middleware::{from_fn, from_fn_with_state},
+  routing::{delete, get, post, put},
   Router,
 };
 
+/// Build the complete router: public routes plus the token-protected cache API
+///
+/// Mirror routes are added when the mirror is enabled, without auth if it is configured so.
+/// Manifest routes are added when manifests are enabled; signed downloads carry their own auth.
+/// Task metadata routes are added when task metadata is enabled.
+/// The token minting and revocation routes are added when they are enabled.
+/// Resumable upload routes are added when tus is enabled.
+/// With affinity hints enabled, cache responses name the replica that should serve the hash.
+/// With JSON errors enabled, plain-text error responses are rewritten as JSON.
+/// In strict Nx spec mode the cache endpoints' responses are folded into the Nx spec.
+/// With a base path, all of the above is mounted under it.
+/// With the `buck2` feature, the gRPC remote cache is also served at its fixed service paths.
+/// Every request, including unmatched ones, carries an `x-request-id`.
 pub fn create_router(app_state: &AppState) -> Router<AppState> {
-  let protected_routes = Router::new()
-    .route("/v1/cache/{hash}", get(handlers::retrieve_artifact))
-    .route("/v1/cache/{hash}", put(handlers::store_artifact))
-    .route_layer(from_fn_with_state(
-      app_state.clone(),
-      middleware::auth_middleware,
-    ));
+  let mut public = public_routes();
+  let mut protected = protected_routes();
+  match &app_state.mirror {
+    Some(state) if state.require_auth() => protected = protected.merge(mirror::mirror_routes()),
+    Some(_) => public = public.merge(mirror::mirror_routes()),
+    None => {},
+  }
+  if app_state.manifest.is_some() {
+    protected = protected.merge(manifest::manifest_routes());
+    public = public.merge(manifest::signed_routes());
+  }
+  if app_state.task_metadata.is_some() {
+    protected = protected.merge(task_metadata::task_metadata_routes());
+  }
+  if app_state.token_minting.is_some() {
+    protected = protected.route("/admin/tokens/{name}/mint", post(handlers::mint_token));
+  }
+  if app_state.revoked_tokens.is_some() {
+    protected = protected.route("/admin/tokens/{name}/revoke", post(handlers::revoke_token));
+  }
+  if app_state.tus.is_some() {
+    protected = protected.merge(tus::tus_routes());
+  }
+  if app_state.affinity.is_some() {
+    protected = protected
+      .merge(affinity::affinity_routes())
+      .layer(from_fn_with_state(
+        app_state.clone(),
+        affinity::affinity_middleware,
+      ));
+  }
 
+  let mut router = public.merge(with_auth(protected, app_state));
+  // Inside the strict layer, so the Nx cache endpoints still answer with the spec's bodies
+  if app_state.json_errors {
+    router = router.layer(from_fn(middleware::json_error_middleware));
+  }
+  if app_state.strict_nx_spec {
+    router = router.layer(from_fn(middleware::nx_spec_middleware));
+  }
+  let router = match &app_state.base_path {
+    Some(base_path) => Router::new().nest(base_path, router),
+    None => router,
+  };
+  with_remote_apis(router, app_state).layer(from_fn(request_id::request_id_middleware))
+}
+
+/// Routes that are served without authentication
+pub fn public_routes() -> Router<AppState> {
   Router::new()
     .route("/health", get(handlers::health_check))
-    .merge(protected_routes)
+    .route("/livez", get(handlers::liveness))
+    .route("/healthz", get(handlers::healthz))
+    .route("/readyz", get(handlers::readiness))
+    .route("/version", get(version::version))
+    .route("/openapi.json", get(openapi::openapi_json))
+}
+
+/// Routes that require a service access token, without the auth layer applied
+///
+/// Embedders can add their own routes to this router before passing it to [`with_auth`];
+/// handlers receive the caller's token as `Extension<middleware::AuthenticatedToken>`.
+pub fn protected_routes() -> Router<AppState> {
+  with_profiling(
+    Router::new()
+      .route("/v1/cache/{hash}", get(handlers::retrieve_artifact))
+      .route("/v1/cache/{hash}", put(handlers::store_artifact))
+      .route("/v1/cache/{hash}/variants", get(handlers::list_variants))
+      .route("/v1/whoami", get(handlers::whoami))
+      .route("/v1/stats/egress", get(handlers::egress_stats))
+      .route("/v1/stats/accounting", get(handlers::accounting_export))
+      .route("/admin/errors", get(handlers::recent_errors))
+      .route("/admin/repairs", get(handlers::repair_stats))
+      .route("/admin/artifacts", get(handlers::list_artifacts))
+      .route("/admin/runs/{run_id}", delete(handlers::purge_run))
+      .route(
+        "/admin/namespaces/{name}",
+        delete(handlers::purge_namespace),
+      )
+      .route(
+        "/admin/namespaces/{name}/usage",
+        get(handlers::namespace_usage),
+      )
+      .route("/admin/reload", post(reload::reload_config))
+      .route("/admin/tokens", get(handlers::list_tokens))
+      .route("/admin/tokens/{name}/stats", get(handlers::token_stats))
+      .merge(bundle::bundle_routes())
+      .merge(compat::keyed_cache_routes())
+      .merge(compat::bazel_routes())
+      .merge(turbo::turbo_routes())
+      .merge(dead_letters::dead_letter_routes()),
+  )
+}
+
+/// Profiling endpoints are only compiled in with the `pprof` feature and still require a token
+#[cfg(feature = "pprof")]
+fn with_profiling(routes: Router<AppState>) -> Router<AppState> {
+  routes
+    .route(
+      "/debug/pprof/profile",
+      get(crate::server::pprof::cpu_profile),
+    )
+    .route(
+      "/debug/pprof/flamegraph",
+      get(crate::server::pprof::flamegraph),
+    )
+}
+
+#[cfg(not(feature = "pprof"))]
+fn with_profiling(routes: Router<AppState>) -> Router<AppState> {
+  routes
+}
+
+/// gRPC clients cannot be given a base path, so the services stay at the root
+#[cfg(feature = "buck2")]
+fn with_remote_apis(router: Router<AppState>, app_state: &AppState) -> Router<AppState> {
+  router.merge(crate::server::grpc::http_routes(app_state))
+}
+
+#[cfg(not(feature = "buck2"))]
+fn with_remote_apis(router: Router<AppState>, _app_state: &AppState) -> Router<AppState> {
+  router
+}
+
+/// Apply the bearer token auth, quota warning, notice and client deadline middleware to every
+/// route in `routes`
+pub fn with_auth(routes: Router<AppState>, app_state: &AppState) -> Router<AppState> {
+  routes
+    .route_layer(from_fn(middleware::deadline_middleware))
+    .route_layer(from_fn_with_state(
+      app_state.clone(),
+      middleware::notice_middleware,
+    ))
+    .route_layer(from_fn_with_state(
+      app_state.clone(),
+      middleware::quota_warning_middleware,
+    ))
+    .route_layer(from_fn_with_state(
+      app_state.clone(),
+      middleware::auth_middleware,
+    ))
 }
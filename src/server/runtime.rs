@@ -1,30 +1,424 @@
 use crate::domain::config::ResolvedConfig;
+use crate::infra::bucket_status::BUCKET_STATUS_INTERVAL;
 use crate::infra::multi_storage::MultiStorageRouter;
 use crate::server::app_state::AppState;
+use crate::server::grpc::{self, RemoteCache};
+use crate::server::normalize::with_path_normalization;
+use crate::server::reload::Reloader;
+use crate::server::revoked_tokens::RevokedTokens;
 use crate::server::router::create_router;
+use crate::server::shutdown::{shutdown, shutdown_signal};
+use crate::server::validation::HashPolicies;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
 
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+type StartupHook = Box<dyn FnOnce(AppState) -> BoxFuture<io::Result<()>> + Send>;
+type ReadyHook = Box<dyn FnOnce(SocketAddr) -> BoxFuture<()> + Send>;
+type ShutdownHook = Box<dyn FnOnce() -> BoxFuture<()> + Send>;
+
+/// Router serving the application state, with path normalization if enabled
+pub(crate) fn serve_app(state: &AppState, normalize_paths: bool) -> axum::Router {
+  let app = create_router(state).with_state(state.clone());
+  if normalize_paths {
+    with_path_normalization(app)
+  } else {
+    app
+  }
+}
+
+/// Run the server from a resolved configuration until SIGINT or SIGTERM
 pub async fn run_server(
   storage: MultiStorageRouter,
   config: &ResolvedConfig,
 ) -> Result<(), std::io::Error> {
-  tracing::info!(
-    "Server starting with {} configured token(s)",
-    storage.token_names().count()
-  );
-  for name in storage.token_names() {
-    tracing::info!("  - Token configured: {}", name);
+  ServerBuilder::new(storage, config).run().await
+}
+
+/// Server with lifecycle hooks for embedding applications
+///
+/// Hooks of the same kind run one after another: startup and ready hooks in registration
+/// order, shutdown hooks in reverse, so resources are released in the opposite order they
+/// were acquired.
+pub struct ServerBuilder<'a> {
+  storage: MultiStorageRouter,
+  config: &'a ResolvedConfig,
+  config_file: Option<PathBuf>,
+  on_startup: Vec<StartupHook>,
+  on_ready: Vec<ReadyHook>,
+  on_shutdown: Vec<ShutdownHook>,
+  shutdown_signal: Option<BoxFuture<()>>,
+}
+
+impl<'a> ServerBuilder<'a> {
+  pub fn new(storage: MultiStorageRouter, config: &'a ResolvedConfig) -> Self {
+    Self {
+      storage,
+      config,
+      config_file: None,
+      on_startup: Vec::new(),
+      on_ready: Vec::new(),
+      on_shutdown: Vec::new(),
+      shutdown_signal: None,
+    }
+  }
+
+  /// Enable `POST /admin/reload`, which re-reads buckets and tokens from `path`
+  pub fn with_config_file(mut self, path: impl Into<PathBuf>) -> Self {
+    self.config_file = Some(path.into());
+    self
+  }
+
+  /// Run a hook with the application state before the listener is bound
+  ///
+  /// An error stops the server from starting and is returned from [`ServerBuilder::run`].
+  pub fn on_startup<F, Fut>(mut self, hook: F) -> Self
+  where
+    F: FnOnce(AppState) -> Fut + Send + 'static,
+    Fut: Future<Output = io::Result<()>> + Send + 'static,
+  {
+    self
+      .on_startup
+      .push(Box::new(move |state| Box::pin(hook(state))));
+    self
   }
 
-  let app_state = AppState {
-    storage: Arc::new(storage),
-  };
+  /// Run a hook with the bound address once the server accepts connections
+  pub fn on_ready<F, Fut>(mut self, hook: F) -> Self
+  where
+    F: FnOnce(SocketAddr) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+  {
+    self
+      .on_ready
+      .push(Box::new(move |addr| Box::pin(hook(addr))));
+    self
+  }
+
+  /// Run a hook after in-flight requests and background storage work have finished
+  pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+  where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+  {
+    self.on_shutdown.push(Box::new(move || Box::pin(hook())));
+    self
+  }
+
+  /// Shut down when `signal` resolves instead of on SIGINT or SIGTERM
+  pub fn with_shutdown_signal<F>(mut self, signal: F) -> Self
+  where
+    F: Future<Output = ()> + Send + 'static,
+  {
+    self.shutdown_signal = Some(Box::pin(signal));
+    self
+  }
+
+  pub async fn run(self) -> Result<(), std::io::Error> {
+    let Self {
+      storage,
+      config,
+      config_file,
+      on_startup,
+      on_ready,
+      on_shutdown,
+      shutdown_signal: signal,
+    } = self;
+
+    tracing::info!(
+      "Server starting with {} configured token(s)",
+      storage.token_names().count()
+    );
+    for name in storage.token_names() {
+      tracing::info!("  - Token configured: {}", name);
+    }
+
+    if config.audit_namespaces {
+      let storage = storage.clone();
+      let config = config.clone();
+      tokio::spawn(async move {
+        let findings = storage.audit_namespaces(&config).await;
+        for finding in &findings {
+          tracing::warn!("Namespace audit: {}", finding);
+        }
+        tracing::info!(
+          "Namespace audit finished with {} finding(s)",
+          findings.len()
+        );
+      });
+    }
+
+    let mut app_state = AppState::new(storage);
+    app_state.errors.set_capacity(config.recent_errors_capacity);
+    if config.in_flight_wait_seconds > 0 {
+      tracing::info!(
+        "Downloads wait up to {}s for in-flight uploads",
+        config.in_flight_wait_seconds
+      );
+      app_state = app_state.with_in_flight_wait(Duration::from_secs(config.in_flight_wait_seconds));
+    }
+    if let Some(mirror) = &config.mirror {
+      tracing::info!(
+        "Compiler cache mirror enabled on bucket {} (requireAuth: {})",
+        mirror.bucket,
+        mirror.require_auth
+      );
+      app_state = app_state.with_mirror(mirror.clone());
+    }
+    if let Some(manifest) = &config.manifest {
+      tracing::info!(
+        "Download manifests enabled (URLs valid for {}s)",
+        manifest.url_ttl_seconds
+      );
+      app_state = app_state.with_manifest(manifest);
+    }
+    if let Some(affinity) = &config.affinity {
+      tracing::info!(
+        "Affinity hints enabled across {} peers",
+        affinity.peers.len()
+      );
+      app_state = app_state.with_affinity(affinity);
+    }
+    if let Some(task_metadata) = &config.task_metadata {
+      tracing::info!("Task metadata capture enabled");
+      app_state = app_state.with_task_metadata(task_metadata.clone());
+    }
+
+    if let Some(transport) = &config.transport_compression {
+      tracing::info!("Transport compression of downloads enabled");
+      app_state = app_state.with_transport_compression(transport.clone());
+    }
+
+    if let Some(tus) = &config.tus {
+      tracing::info!("tus resumable uploads enabled");
+      app_state = app_state.with_tus(tus);
+    }
+
+    if !config.jwt.is_empty() {
+      for jwt in &config.jwt {
+        tracing::info!(
+          "{} bearer tokens accepted ({} rule(s))",
+          jwt.provider,
+          jwt.rules.len()
+        );
+      }
+      app_state = app_state.with_jwt(&config.jwt);
+    }
 
-  let app = create_router(&app_state).with_state(app_state);
-  let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.port)).await?;
+    if let Some(minting) = &config.token_minting {
+      tracing::info!(
+        "Token minting enabled (tokens valid for up to {}s)",
+        minting.max_ttl_seconds
+      );
+      app_state = app_state.with_token_minting(minting);
+    }
 
-  tracing::info!("Server running on port {}", config.port);
-  axum::serve(listener, app).await?;
+    if let Some(revocation) = &config.token_revocation {
+      let revoked = RevokedTokens::load(&revocation.path)?;
+      tracing::info!(
+        "Token revocation enabled (revocations stored in {})",
+        revocation.path
+      );
+      app_state = app_state.with_revoked_tokens(revoked);
+    }
 
-  Ok(())
+    if config.hash_validation != Default::default() {
+      let policies = HashPolicies::from_config(&config.hash_validation)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+      tracing::info!("Custom hash validation policies enabled");
+      app_state = app_state.with_hash_policies(policies);
+    }
+
+    if config.strict_nx_spec {
+      tracing::info!("Strict Nx spec compliance enabled");
+      app_state = app_state.with_strict_nx_spec();
+    }
+
+    if config.json_errors {
+      tracing::info!("JSON error responses enabled");
+      app_state = app_state.with_json_errors();
+    }
+
+    if config.allow_empty_uploads {
+      tracing::info!("Empty uploads accepted");
+      app_state = app_state.with_empty_uploads();
+    }
+
+    if config.cache_control.max_age_seconds == 0 {
+      tracing::info!("Cache-Control on downloads disabled");
+    } else if config.cache_control.public {
+      tracing::warn!("Downloads are marked public and may be stored by shared caches");
+    }
+    app_state = app_state.with_cache_control(&config.cache_control);
+
+    if let Some(base_path) = &config.base_path {
+      tracing::info!("Routes mounted under {}", base_path);
+      app_state = app_state.with_base_path(base_path);
+    }
+
+    for hook in on_startup {
+      hook(app_state.clone()).await?;
+    }
+
+    if config.normalize_paths {
+      tracing::info!("Request path normalization enabled");
+    }
+    let reloader = config_file.map(|path| {
+      tracing::info!("Configuration reload enabled at /admin/reload");
+      Arc::new(Reloader::new(
+        path,
+        app_state.clone(),
+        config.normalize_paths,
+      ))
+    });
+    let app = match &reloader {
+      Some(reloader) => reloader.clone().into_router(),
+      None => serve_app(&app_state, config.normalize_paths),
+    };
+    let current_state = move || match &reloader {
+      Some(reloader) => reloader.state(),
+      None => app_state.clone(),
+    };
+
+    // Keep the connectivity reported by `/healthz?verbose=1` current
+    let probed = current_state.clone();
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(BUCKET_STATUS_INTERVAL);
+      interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+      loop {
+        interval.tick().await;
+        probed().storage.probe_buckets().await;
+      }
+    });
+
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.port)).await?;
+    let addr = listener.local_addr()?;
+
+    tracing::info!("Server running on port {}", addr.port());
+    let grpc_server = match &config.grpc {
+      Some(grpc) => {
+        let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", grpc.port)).await?;
+        tracing::info!(
+          "gRPC remote cache running on port {}",
+          listener.local_addr()?.port()
+        );
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let cache = RemoteCache::new(current_state.clone());
+        let server = tokio::spawn(grpc::serve(listener, cache, async move {
+          let _ = stopped.await;
+        }));
+        Some((stop, server))
+      },
+      None => None,
+    };
+    // Spawned so ready hooks can make requests to the server they are waiting on
+    tokio::spawn(async move {
+      for hook in on_ready {
+        hook(addr).await;
+      }
+    });
+    // Stop accepting connections on signal and let in-flight requests finish
+    let signal = signal.unwrap_or_else(|| Box::pin(shutdown_signal()));
+    axum::serve(listener, app)
+      .with_graceful_shutdown(signal)
+      .await?;
+    // gRPC calls are drained after the HTTP server stopped, before storage shuts down
+    if let Some((stop, server)) = grpc_server {
+      let _ = stop.send(());
+      match server.await {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => tracing::error!("gRPC server failed: {}", e),
+        Err(e) => tracing::error!("gRPC server task failed: {}", e),
+      }
+    }
+
+    tracing::info!("HTTP server stopped, running shutdown stages");
+    // Background work of storages replaced by a reload is not waited for
+    shutdown(current_state(), &config.shutdown).await;
+    for hook in on_shutdown.into_iter().rev() {
+      hook().await;
+    }
+    tracing::info!("Shutdown complete");
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Config;
+  use std::sync::{Arc, Mutex};
+
+  async fn storage_and_config(root: &std::path::Path) -> (MultiStorageRouter, ResolvedConfig) {
+    let yaml = format!(
+      "port: 0\nbuckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\nserviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessToken: secret\n",
+      root.display()
+    );
+    let config = Config::from_yaml_str(&yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    let storage = MultiStorageRouter::from_config(&config).await.unwrap();
+    (storage, config)
+  }
+
+  #[tokio::test]
+  async fn test_hooks_run_in_lifecycle_order() {
+    let root = tempfile::tempdir().unwrap();
+    let (storage, config) = storage_and_config(root.path()).await;
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let record = |event: &'static str| {
+      let events = events.clone();
+      move || events.lock().unwrap().push(event)
+    };
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+    let (startup, ready) = (record("startup"), record("ready"));
+    let (first, second) = (record("shutdown first"), record("shutdown second"));
+    ServerBuilder::new(storage, &config)
+      .on_startup(|_| async move {
+        startup();
+        Ok(())
+      })
+      .on_ready(|addr| async move {
+        assert_ne!(addr.port(), 0);
+        ready();
+        let _ = stop.send(());
+      })
+      .on_shutdown(|| async move { first() })
+      .on_shutdown(|| async move { second() })
+      .with_shutdown_signal(async move {
+        let _ = stopped.await;
+      })
+      .run()
+      .await
+      .unwrap();
+
+    assert_eq!(
+      *events.lock().unwrap(),
+      vec!["startup", "ready", "shutdown second", "shutdown first"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_failing_startup_hook_stops_the_server() {
+    let root = tempfile::tempdir().unwrap();
+    let (storage, config) = storage_and_config(root.path()).await;
+
+    let result = ServerBuilder::new(storage, &config)
+      .on_startup(|_| async { Err(io::Error::other("database unavailable")) })
+      .on_ready(|_| async {
+        panic!("server must not start");
+      })
+      .run()
+      .await;
+    assert_eq!(result.unwrap_err().to_string(), "database unavailable");
+  }
 }
@@ -0,0 +1,103 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::domain::config::ShutdownConfig;
+use crate::server::app_state::AppState;
+
+/// Resolve once SIGINT (Ctrl+C) or, on Unix, SIGTERM is received
+pub async fn shutdown_signal() {
+  let ctrl_c = async {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+      tracing::error!("Failed to listen for Ctrl+C: {}", e);
+      std::future::pending::<()>().await;
+    }
+  };
+
+  #[cfg(unix)]
+  let terminate = async {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+      Ok(mut signal) => {
+        signal.recv().await;
+      },
+      Err(e) => {
+        tracing::error!("Failed to listen for SIGTERM: {}", e);
+        std::future::pending::<()>().await;
+      },
+    }
+  };
+
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    _ = ctrl_c => tracing::info!("Received Ctrl+C, shutting down"),
+    _ = terminate => tracing::info!("Received SIGTERM, shutting down"),
+  }
+}
+
+/// Run a single shutdown stage with a timeout, logging how it went.
+///
+/// Returns `false` if the stage timed out.
+async fn run_stage<F>(name: &str, timeout: Duration, stage: F) -> bool
+where
+  F: Future<Output = ()>,
+{
+  tracing::info!("Shutdown stage '{}' started (timeout {:?})", name, timeout);
+  let started = Instant::now();
+
+  match tokio::time::timeout(timeout, stage).await {
+    Ok(()) => {
+      tracing::info!(
+        "Shutdown stage '{}' finished in {:?}",
+        name,
+        started.elapsed()
+      );
+      true
+    },
+    Err(_) => {
+      tracing::warn!("Shutdown stage '{}' timed out after {:?}", name, timeout);
+      false
+    },
+  }
+}
+
+/// Tear down the application after the HTTP server stopped accepting requests.
+///
+/// Stages run in a fixed order:
+/// 1. drain pending background storage work (write-behind uploads, async replication)
+/// 2. flush in-memory stats to the log
+/// 3. drop the storage clients
+pub async fn shutdown(state: AppState, config: &ShutdownConfig) {
+  let background = state.storage.background_tasks();
+
+  let drained = run_stage(
+    "drain background work",
+    Duration::from_secs(config.drain_timeout_seconds),
+    background.wait_idle(),
+  )
+  .await;
+  if !drained {
+    tracing::warn!(
+      "{} background storage task(s) still pending; their data may not have reached the bucket",
+      background.pending()
+    );
+  }
+
+  run_stage(
+    "flush stats",
+    Duration::from_secs(config.flush_timeout_seconds),
+    async {
+      for name in state.storage.token_names() {
+        let bytes = state.egress.bytes_today(name);
+        if bytes > 0 {
+          tracing::info!("Egress today for token '{}': {} bytes", name, bytes);
+        }
+      }
+    },
+  )
+  .await;
+
+  tracing::info!("Shutdown stage 'close storage clients' started");
+  drop(state);
+  tracing::info!("Shutdown stage 'close storage clients' finished");
+}
@@ -0,0 +1,276 @@
+use crate::domain::config::TaskMetadataConfig;
+use crate::domain::storage::{boxed_reader_stream, StorageError};
+use crate::server::{error::ServerError, handlers, middleware::AuthenticatedToken, AppState};
+use axum::{
+  body::Bytes,
+  extract::{Path, Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+  routing::{get, put},
+  Extension, Json, Router,
+};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+
+/// Key prefix below a token's prefix holding one JSON record per hash
+const TASKS_DIR: &str = ".tasks";
+
+/// Records read at the same time while answering one admin query
+const READ_CONCURRENCY: usize = 16;
+
+/// Task metadata a client sends alongside an artifact
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskMetadata {
+  pub project: String,
+  pub target: String,
+  /// Wall-clock duration of the task run that produced the artifact
+  pub duration_ms: u64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub configuration: Option<String>,
+}
+
+/// Stored task metadata with the hash it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRecord {
+  pub hash: String,
+  /// Unix time (seconds) the metadata was recorded
+  pub recorded_at: u64,
+  #[serde(flatten)]
+  pub task: TaskMetadata,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskQuery {
+  /// Name of the token whose records are queried, defaults to the caller's
+  pub token: Option<String>,
+  pub project: Option<String>,
+  pub target: Option<String>,
+  pub limit: Option<usize>,
+}
+
+impl TaskQuery {
+  fn matches(&self, record: &TaskRecord) -> bool {
+    self
+      .project
+      .as_ref()
+      .is_none_or(|project| *project == record.task.project)
+      && self
+        .target
+        .as_ref()
+        .is_none_or(|target| *target == record.task.target)
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskTokenQuery {
+  pub token: Option<String>,
+}
+
+fn task_key(hash: &str) -> String {
+  format!("{}/{}.json", TASKS_DIR, hash)
+}
+
+fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+/// Token-protected routes recording task metadata and querying it as an admin
+pub fn task_metadata_routes() -> Router<AppState> {
+  Router::new()
+    .route("/v1/cache/{hash}/task", put(store_task_metadata))
+    .route("/admin/tasks", get(list_task_metadata))
+    .route("/admin/tasks/{hash}", get(get_task_metadata))
+}
+
+fn config(state: &AppState) -> Result<&TaskMetadataConfig, ServerError> {
+  state
+    .task_metadata
+    .as_deref()
+    .ok_or(ServerError::Storage(StorageError::NotFound))
+}
+
+/// Access token of the queried token, for tokens with `admin` access
+pub(crate) fn admin_target(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  name: Option<&str>,
+) -> Result<String, ServerError> {
+  handlers::require_admin(state, token)?;
+  match name {
+    Some(name) => state
+      .storage
+      .find_token_by_name(name)
+      .map(|target| target.access_token.clone())
+      .ok_or(ServerError::BadRequest),
+    None => Ok(token.0.clone()),
+  }
+}
+
+/// Record task metadata for a hash; the first record for a hash wins
+pub async fn store_task_metadata(
+  Path(hash): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  body: Bytes,
+) -> Result<impl IntoResponse, ServerError> {
+  state.hash_policies.nx.validate(&hash)?;
+  handlers::require_write(&state, &token)?;
+  let config = config(&state)?;
+  if body.len() > config.max_bytes {
+    return Ok((
+      StatusCode::PAYLOAD_TOO_LARGE,
+      [("Content-Type", "text/plain")],
+      "Task metadata too large",
+    ));
+  }
+  let Ok(task) = serde_json::from_slice::<TaskMetadata>(&body) else {
+    return Ok((
+      StatusCode::BAD_REQUEST,
+      [("Content-Type", "text/plain")],
+      "Invalid task metadata",
+    ));
+  };
+
+  let record = TaskRecord {
+    hash: hash.clone(),
+    recorded_at: unix_now(),
+    task,
+  };
+  let data = serde_json::to_vec(&record).map_err(|_| ServerError::InternalError)?;
+  let length = data.len() as u64;
+  state
+    .storage
+    .store_with_token(
+      &token.0,
+      &task_key(&hash),
+      boxed_reader_stream(std::io::Cursor::new(data)),
+      Some(length),
+    )
+    .await?;
+
+  Ok((StatusCode::OK, [("Content-Type", "text/plain")], ""))
+}
+
+async fn read_record(state: &AppState, token: &str, hash: &str) -> Result<TaskRecord, ServerError> {
+  let mut reader = state
+    .storage
+    .retrieve_with_token(token, &task_key(hash))
+    .await?;
+  let mut data = Vec::new();
+  reader
+    .read_to_end(&mut data)
+    .await
+    .map_err(|_| ServerError::InternalError)?;
+  serde_json::from_slice(&data).map_err(|_| ServerError::InternalError)
+}
+
+/// Task metadata recorded for a hash, for tokens with `admin` access
+pub async fn get_task_metadata(
+  Path(hash): Path<String>,
+  State(state): State<AppState>,
+  Query(query): Query<TaskTokenQuery>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<TaskRecord>, ServerError> {
+  config(&state)?;
+  let target = admin_target(&state, &token, query.token.as_deref())?;
+  state.hash_policies.nx.validate(&hash)?;
+
+  Ok(Json(read_record(&state, &target, &hash).await?))
+}
+
+/// Recorded task metadata filtered by project and target, newest first, for `admin` tokens
+pub async fn list_task_metadata(
+  State(state): State<AppState>,
+  Query(query): Query<TaskQuery>,
+  Extension(token): Extension<AuthenticatedToken>,
+) -> Result<Json<Vec<TaskRecord>>, ServerError> {
+  let max_results = config(&state)?.max_results;
+  let target = admin_target(&state, &token, query.token.as_deref())?;
+  let limit = query.limit.unwrap_or(max_results).min(max_results);
+
+  let prefix = format!("{}/", TASKS_DIR);
+  let hashes: Vec<String> = state
+    .storage
+    .list_with_token(&target, &prefix)
+    .await?
+    .into_iter()
+    .filter_map(|name| {
+      name
+        .strip_prefix(&prefix)
+        .and_then(|name| name.strip_suffix(".json"))
+        .map(str::to_string)
+    })
+    .collect();
+
+  let mut records: Vec<TaskRecord> = stream::iter(hashes)
+    .map(|hash| {
+      let state = state.clone();
+      let target = target.clone();
+      async move { read_record(&state, &target, &hash).await }
+    })
+    .buffer_unordered(READ_CONCURRENCY)
+    .filter_map(|record| async move { record.ok() })
+    .filter(|record| std::future::ready(query.matches(record)))
+    .collect()
+    .await;
+
+  records.sort_by_key(|record| std::cmp::Reverse(record.recorded_at));
+  records.truncate(limit);
+  Ok(Json(records))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record_flattens_task_fields() {
+    let task: TaskMetadata = serde_json::from_str(
+      r#"{"project":"app","target":"build","durationMs":1200,"configuration":"production"}"#,
+    )
+    .unwrap();
+    let record = TaskRecord {
+      hash: "abc123".to_string(),
+      recorded_at: 1_700_000_000,
+      task,
+    };
+
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(json["project"], "app");
+    assert_eq!(json["durationMs"], 1200);
+    assert_eq!(json["recordedAt"], 1_700_000_000);
+    assert_eq!(serde_json::from_value::<TaskRecord>(json).unwrap(), record);
+  }
+
+  #[test]
+  fn test_query_filters_by_project_and_target() {
+    let record = TaskRecord {
+      hash: "abc123".to_string(),
+      recorded_at: 0,
+      task: TaskMetadata {
+        project: "app".to_string(),
+        target: "build".to_string(),
+        duration_ms: 10,
+        configuration: None,
+      },
+    };
+    let query = |project: Option<&str>, target: Option<&str>| TaskQuery {
+      token: None,
+      project: project.map(str::to_string),
+      target: target.map(str::to_string),
+      limit: None,
+    };
+
+    assert!(query(None, None).matches(&record));
+    assert!(query(Some("app"), Some("build")).matches(&record));
+    assert!(!query(Some("lib"), None).matches(&record));
+    assert!(!query(None, Some("test")).matches(&record));
+  }
+}
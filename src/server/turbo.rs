@@ -0,0 +1,370 @@
+use crate::domain::storage::{boxed_reader_stream, StorageError};
+use crate::infra::multi_storage::UploadOptions;
+use crate::server::integrity::IntegrityCheck;
+use crate::server::validation::validate_variant;
+use crate::server::{error::ServerError, handlers, middleware::AuthenticatedToken, AppState};
+use axum::{
+  body::Body,
+  extract::{Path, Query, State},
+  http::{HeaderMap, HeaderValue, StatusCode},
+  response::{IntoResponse, Response},
+  routing::{get, post},
+  Extension, Json, Router,
+};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::AsyncReadExt;
+
+/// Storage namespace below the token prefix holding Turborepo artifacts
+const TURBO_DIR: &str = "turbo";
+
+/// Task duration in milliseconds Turborepo sends and expects back with an artifact
+pub const DURATION_HEADER: &str = "x-artifact-duration";
+
+/// Signature of a signed artifact, passed through unchecked
+pub const TAG_HEADER: &str = "x-artifact-tag";
+
+/// Artifacts looked up at the same time while answering one query
+const QUERY_CONCURRENCY: usize = 16;
+
+/// Most hashes accepted in one artifact query
+const MAX_QUERY_HASHES: usize = 1000;
+
+/// Team an artifact request is scoped to, as Turborepo sends it
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamQuery {
+  pub team_id: Option<String>,
+  pub slug: Option<String>,
+}
+
+impl TeamQuery {
+  /// Key prefix of the team's artifacts; a team id takes precedence over a slug
+  fn dir(&self) -> Result<String, ServerError> {
+    match self.team_id.as_deref().or(self.slug.as_deref()) {
+      Some(team) => {
+        validate_variant(team)?;
+        Ok(format!("{}/{}", TURBO_DIR, team))
+      },
+      None => Ok(TURBO_DIR.to_string()),
+    }
+  }
+}
+
+/// Duration and signature tag stored next to an artifact
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactInfo {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  duration_ms: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  tag: Option<String>,
+}
+
+impl ArtifactInfo {
+  /// Info sent with an upload; a malformed duration is dropped rather than failing the upload
+  fn from_headers(headers: &HeaderMap) -> Self {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    Self {
+      duration_ms: header(DURATION_HEADER).and_then(|value| value.trim().parse().ok()),
+      tag: header(TAG_HEADER).map(str::to_string),
+    }
+  }
+
+  fn apply(&self, headers: &mut HeaderMap) {
+    if let Some(duration) = self.duration_ms {
+      headers.insert(DURATION_HEADER, HeaderValue::from(duration));
+    }
+    if let Some(tag) = self
+      .tag
+      .as_ref()
+      .and_then(|t| HeaderValue::from_str(t).ok())
+    {
+      headers.insert(TAG_HEADER, tag);
+    }
+  }
+}
+
+fn info_key(dir: &str, hash: &str) -> String {
+  format!("{}/{}.json", dir, hash)
+}
+
+/// Stored info of an artifact; artifacts uploaded without duration or tag have none
+async fn read_info(state: &AppState, token: &str, dir: &str, hash: &str) -> ArtifactInfo {
+  let read = async {
+    let mut reader = state
+      .storage
+      .retrieve_with_token(token, &info_key(dir, hash))
+      .await
+      .ok()?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await.ok()?;
+    serde_json::from_slice(&data).ok()
+  };
+  read.await.unwrap_or_default()
+}
+
+/// Routes of the Turborepo remote cache API, so `turbo` can use this server instead of Vercel
+pub fn turbo_routes() -> Router<AppState> {
+  Router::new()
+    .route("/v8/artifacts/status", get(status))
+    .route("/v8/artifacts", post(query_artifacts))
+    .route("/v8/artifacts/events", post(record_events))
+    .route(
+      "/v8/artifacts/{hash}",
+      get(download_artifact)
+        .head(artifact_exists)
+        .put(upload_artifact),
+    )
+}
+
+/// Remote caching is always enabled for tokens of this server
+async fn status() -> Json<Value> {
+  Json(json!({ "status": "enabled" }))
+}
+
+/// GET: 200 with the artifact and its duration and tag headers, 404 on a miss
+async fn download_artifact(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Path(hash): Path<String>,
+  Query(team): Query<TeamQuery>,
+) -> Result<Response, ServerError> {
+  state.hash_policies.turbo.validate(&hash)?;
+  handlers::require_read(&state, &token)?;
+  let dir = team.dir()?;
+
+  let key = format!("{}/{}", dir, hash);
+  let (response, info) = tokio::join!(
+    handlers::stream_object(&state, &token, &key, &[]),
+    read_info(&state, &token.0, &dir, &hash),
+  );
+  let mut response = response?;
+  info.apply(response.headers_mut());
+  Ok(response)
+}
+
+/// HEAD: 200 with the duration and tag headers if the artifact exists, 404 otherwise
+async fn artifact_exists(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Path(hash): Path<String>,
+  Query(team): Query<TeamQuery>,
+) -> Result<Response, ServerError> {
+  state.hash_policies.turbo.validate(&hash)?;
+  handlers::require_read(&state, &token)?;
+  let dir = team.dir()?;
+
+  let key = format!("{}/{}", dir, hash);
+  if !state.storage.exists_with_token(&token.0, &key).await? {
+    return Err(ServerError::Storage(StorageError::NotFound));
+  }
+  let mut response = StatusCode::OK.into_response();
+  read_info(&state, &token.0, &dir, &hash)
+    .await
+    .apply(response.headers_mut());
+  Ok(response)
+}
+
+/// PUT: 202 when stored. Artifacts are content-addressed, so an existing one is also a success
+async fn upload_artifact(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Path(hash): Path<String>,
+  Query(team): Query<TeamQuery>,
+  headers: HeaderMap,
+  body: Body,
+) -> Result<Response, ServerError> {
+  state.hash_policies.turbo.validate(&hash)?;
+  handlers::require_write(&state, &token)?;
+  let dir = team.dir()?;
+  let info = ArtifactInfo::from_headers(&headers);
+  let accepted = (
+    StatusCode::ACCEPTED,
+    Json(json!({ "urls": [format!("/v8/artifacts/{}", hash)] })),
+  )
+    .into_response();
+
+  let key = format!("{}/{}", dir, hash);
+  if state.storage.exists_with_token(&token.0, &key).await? {
+    return Ok(accepted);
+  }
+
+  let content_length = headers
+    .get(axum::http::header::CONTENT_LENGTH)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|s| s.parse::<u64>().ok());
+  let check = IntegrityCheck::default().with_content_length(content_length);
+  match handlers::store_body(
+    &state,
+    &token,
+    &key,
+    body,
+    content_length,
+    None,
+    UploadOptions::default(),
+    &check,
+  )
+  .await
+  {
+    Ok(()) => {},
+    Err(StorageError::AlreadyExists) => return Ok(accepted),
+    Err(err) => {
+      return match check.failure() {
+        Some(failure) => Ok(handlers::integrity_failure_response(&key, failure).into_response()),
+        None => Err(err.into()),
+      }
+    },
+  }
+
+  // The first upload of an artifact decides its duration and tag, like the artifact itself
+  if info != ArtifactInfo::default() {
+    let data = serde_json::to_vec(&info).map_err(|_| ServerError::InternalError)?;
+    let length = data.len() as u64;
+    match state
+      .storage
+      .store_with_token(
+        &token.0,
+        &info_key(&dir, &hash),
+        boxed_reader_stream(std::io::Cursor::new(data)),
+        Some(length),
+      )
+      .await
+    {
+      Ok(()) | Err(StorageError::AlreadyExists) => {},
+      Err(err) => tracing::warn!(
+        "Failed to store Turborepo artifact info for {}: {}",
+        key,
+        err
+      ),
+    }
+  }
+  Ok(accepted)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArtifactQuery {
+  pub hashes: Vec<String>,
+}
+
+/// Size, duration and tag of each queried artifact; `null` for missing ones
+async fn query_artifacts(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Query(team): Query<TeamQuery>,
+  Json(query): Json<ArtifactQuery>,
+) -> Result<Json<serde_json::Map<String, Value>>, ServerError> {
+  handlers::require_read(&state, &token)?;
+  let dir = team.dir()?;
+  if query.hashes.len() > MAX_QUERY_HASHES {
+    return Err(ServerError::BadRequest);
+  }
+
+  let lookups = query.hashes.into_iter().map(|hash| {
+    let (state, token, dir) = (&state, &token, &dir);
+    async move {
+      if state.hash_policies.turbo.validate(&hash).is_err() {
+        return (hash, json!({ "error": { "message": "Invalid hash" } }));
+      }
+      let key = format!("{}/{}", dir, hash);
+      let (stat, info) = tokio::join!(
+        state.storage.stat_with_token(&token.0, &key),
+        read_info(state, &token.0, dir, &hash),
+      );
+      let value = match stat {
+        Ok(stat) => json!({
+          "size": stat.size,
+          "taskDurationMs": info.duration_ms,
+          "tag": info.tag,
+        }),
+        Err(StorageError::NotFound) => Value::Null,
+        Err(err) => json!({ "error": { "message": err.to_string() } }),
+      };
+      (hash, value)
+    }
+  });
+  let artifacts = stream::iter(lookups)
+    .buffered(QUERY_CONCURRENCY)
+    .collect()
+    .await;
+  Ok(Json(artifacts))
+}
+
+/// Cache usage event a Turborepo client reports after a run
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactEvent {
+  /// `LOCAL` or `REMOTE`
+  pub source: String,
+  /// `HIT` or `MISS`
+  pub event: String,
+}
+
+/// Accept the events clients report; they are only logged
+async fn record_events(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  Json(events): Json<Vec<ArtifactEvent>>,
+) -> StatusCode {
+  let name = state
+    .storage
+    .get_token_config(&token.0)
+    .map(|service| service.name.as_str())
+    .unwrap_or_default();
+  let hits = events.iter().filter(|event| event.event == "HIT");
+  let remote_hits = hits
+    .clone()
+    .filter(|event| event.source == "REMOTE")
+    .count();
+  tracing::debug!(
+    "Turborepo client of {} reported {} cache events, {} hits ({} remote)",
+    name,
+    events.len(),
+    hits.count(),
+    remote_hits
+  );
+  StatusCode::OK
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_team_dir() {
+    let team = |team_id: Option<&str>, slug: Option<&str>| TeamQuery {
+      team_id: team_id.map(str::to_string),
+      slug: slug.map(str::to_string),
+    };
+    assert_eq!(team(None, None).dir().unwrap(), "turbo");
+    assert_eq!(team(None, Some("web")).dir().unwrap(), "turbo/web");
+    assert_eq!(
+      team(Some("team_123"), Some("web")).dir().unwrap(),
+      "turbo/team_123"
+    );
+    assert!(team(Some("../other"), None).dir().is_err());
+  }
+
+  #[test]
+  fn test_artifact_info_headers() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(
+      ArtifactInfo::from_headers(&headers),
+      ArtifactInfo::default()
+    );
+
+    headers.insert(DURATION_HEADER, HeaderValue::from_static("1500"));
+    headers.insert(TAG_HEADER, HeaderValue::from_static("c2lnbmF0dXJl"));
+    let info = ArtifactInfo::from_headers(&headers);
+    assert_eq!(info.duration_ms, Some(1500));
+
+    let mut response = HeaderMap::new();
+    info.apply(&mut response);
+    assert_eq!(response[DURATION_HEADER], "1500");
+    assert_eq!(response[TAG_HEADER], "c2lnbmF0dXJl");
+
+    headers.insert(DURATION_HEADER, HeaderValue::from_static("fast"));
+    assert_eq!(ArtifactInfo::from_headers(&headers).duration_ms, None);
+  }
+}
@@ -0,0 +1,749 @@
+use crate::domain::config::TusConfig;
+use crate::domain::http_date;
+use crate::domain::storage::{boxed_reader_stream, StorageError};
+use crate::infra::multi_storage::UploadOptions;
+use crate::server::{
+  error::{self, ServerError},
+  handlers,
+  middleware::AuthenticatedToken,
+  validation, AppState,
+};
+use axum::{
+  body::Body,
+  extract::{Path, Request, State},
+  http::{header, HeaderMap, HeaderValue, StatusCode},
+  response::{IntoResponse, Response},
+  routing::{head, post},
+  Extension, Router,
+};
+use base64::engine::general_purpose;
+use base64::Engine as _;
+use futures_util::StreamExt;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+/// Protocol version this server speaks
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// Supported protocol extensions, announced on `OPTIONS /v1/tus`
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+
+/// Content type every `PATCH` must declare
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+const TUS_RESUMABLE: &str = "tus-resumable";
+const UPLOAD_LENGTH: &str = "upload-length";
+const UPLOAD_OFFSET: &str = "upload-offset";
+const UPLOAD_METADATA: &str = "upload-metadata";
+const UPLOAD_EXPIRES: &str = "upload-expires";
+
+fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+/// What a client declared when creating an upload
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct UploadInfo {
+  /// Name of the token that created the upload; only it may continue the upload
+  token: String,
+  hash: String,
+  length: u64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  run_id: Option<String>,
+  /// Unix time after which the unfinished upload is discarded
+  expires_at: u64,
+}
+
+/// Why appending a request body to an upload stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AppendError {
+  /// The body runs past the declared `Upload-Length`
+  TooLong,
+  /// The client stopped sending, e.g. because its connection dropped
+  Interrupted,
+  Io,
+}
+
+/// Partial uploads kept on local disk until they are complete and moved to the bucket
+///
+/// Each upload is a `{id}.part` file holding the bytes received so far, whose size is the
+/// upload offset, and a `{id}.json` file with the [`UploadInfo`].
+pub struct TusUploads {
+  dir: PathBuf,
+  max_size_bytes: u64,
+  expiration_seconds: u64,
+  /// Uploads a request is currently appending to, finishing or terminating
+  busy: Mutex<HashSet<String>>,
+  rng: SystemRandom,
+}
+
+/// Marks an upload busy until dropped
+struct BusyGuard<'a> {
+  uploads: &'a TusUploads,
+  id: String,
+}
+
+impl Drop for BusyGuard<'_> {
+  fn drop(&mut self) {
+    self
+      .uploads
+      .busy
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .remove(&self.id);
+  }
+}
+
+fn io_error(context: &str, e: std::io::Error) -> StorageError {
+  tracing::error!("tus: {}: {}", context, e);
+  StorageError::OperationFailed
+}
+
+impl TusUploads {
+  pub fn new(config: &TusConfig) -> Self {
+    let dir = config
+      .path
+      .as_deref()
+      .map(PathBuf::from)
+      .unwrap_or_else(|| std::env::temp_dir().join("nx-cache-tus"));
+    Self {
+      dir,
+      max_size_bytes: config.max_size_bytes,
+      expiration_seconds: config.expiration_seconds,
+      busy: Mutex::new(HashSet::new()),
+      rng: SystemRandom::new(),
+    }
+  }
+
+  fn info_path(&self, id: &str) -> PathBuf {
+    self.dir.join(format!("{}.json", id))
+  }
+
+  fn data_path(&self, id: &str) -> PathBuf {
+    self.dir.join(format!("{}.part", id))
+  }
+
+  /// Upload IDs are 32 lowercase hex characters, so they are safe to use as file names
+  fn is_valid_id(id: &str) -> bool {
+    id.len() == 32 && id.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+  }
+
+  fn lock(&self, id: &str) -> Option<BusyGuard<'_>> {
+    let mut busy = self.busy.lock().unwrap_or_else(|e| e.into_inner());
+    busy.insert(id.to_string()).then(|| BusyGuard {
+      uploads: self,
+      id: id.to_string(),
+    })
+  }
+
+  fn expires_at(&self) -> u64 {
+    unix_now().saturating_add(self.expiration_seconds)
+  }
+
+  /// Start an upload, returning its ID
+  async fn create(&self, info: &UploadInfo) -> Result<String, StorageError> {
+    fs::create_dir_all(&self.dir)
+      .await
+      .map_err(|e| io_error("failed to create upload directory", e))?;
+    self.sweep().await;
+
+    let mut bytes = [0u8; 16];
+    self
+      .rng
+      .fill(&mut bytes)
+      .map_err(|_| StorageError::OperationFailed)?;
+    let id = hex::encode(bytes);
+    File::create(self.data_path(&id))
+      .await
+      .map_err(|e| io_error("failed to create upload", e))?;
+    self.save(&id, info).await?;
+    Ok(id)
+  }
+
+  async fn save(&self, id: &str, info: &UploadInfo) -> Result<(), StorageError> {
+    let json = serde_json::to_vec(info).map_err(|_| StorageError::OperationFailed)?;
+    fs::write(self.info_path(id), json)
+      .await
+      .map_err(|e| io_error("failed to write upload info", e))
+  }
+
+  /// Info and offset of an unfinished upload; expired uploads are removed and not found
+  async fn load(&self, id: &str) -> Result<(UploadInfo, u64), StorageError> {
+    if !Self::is_valid_id(id) {
+      return Err(StorageError::NotFound);
+    }
+    let json = fs::read(self.info_path(id))
+      .await
+      .map_err(|_| StorageError::NotFound)?;
+    let info: UploadInfo =
+      serde_json::from_slice(&json).map_err(|_| StorageError::OperationFailed)?;
+    if info.expires_at < unix_now() {
+      self.remove(id).await;
+      return Err(StorageError::NotFound);
+    }
+    let offset = fs::metadata(self.data_path(id))
+      .await
+      .map_err(|_| StorageError::NotFound)?
+      .len();
+    Ok((info, offset))
+  }
+
+  async fn remove(&self, id: &str) {
+    let _ = fs::remove_file(self.data_path(id)).await;
+    let _ = fs::remove_file(self.info_path(id)).await;
+  }
+
+  /// Remove expired uploads that no request is working on
+  async fn sweep(&self) {
+    let Ok(mut entries) = fs::read_dir(&self.dir).await else {
+      return;
+    };
+    let now = unix_now();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+      let name = entry.file_name();
+      let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".json")) else {
+        continue;
+      };
+      let expired = match fs::read(entry.path()).await {
+        Ok(json) => serde_json::from_slice::<UploadInfo>(&json)
+          .map(|info| info.expires_at < now)
+          .unwrap_or(true),
+        Err(_) => false,
+      };
+      if expired {
+        if let Some(_guard) = self.lock(id) {
+          tracing::info!("tus: removing expired upload {}", id);
+          self.remove(id).await;
+        }
+      }
+    }
+  }
+
+  /// Append a request body to an upload, accepting at most `limit` bytes
+  ///
+  /// Returns how many bytes were appended. Bytes received before the body failed stay
+  /// appended, so the client can resume right after them.
+  async fn append(&self, id: &str, body: Body, limit: u64) -> (u64, Result<(), AppendError>) {
+    let path = self.data_path(id);
+    let mut file = match OpenOptions::new().append(true).open(&path).await {
+      Ok(file) => file,
+      Err(e) => {
+        tracing::error!("tus: failed to open upload {}: {}", id, e);
+        return (0, Err(AppendError::Io));
+      },
+    };
+    let start = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+
+    let mut stream = body.into_data_stream();
+    let mut written = 0u64;
+    let mut result = Ok(());
+    while let Some(chunk) = stream.next().await {
+      let Ok(chunk) = chunk else {
+        result = Err(AppendError::Interrupted);
+        break;
+      };
+      if written + chunk.len() as u64 > limit {
+        result = Err(AppendError::TooLong);
+        break;
+      }
+      if let Err(e) = file.write_all(&chunk).await {
+        tracing::error!("tus: failed to append to upload {}: {}", id, e);
+        result = Err(AppendError::Io);
+        break;
+      }
+      written += chunk.len() as u64;
+    }
+
+    // Drop a partially written chunk so the offset only counts whole chunks
+    if result == Err(AppendError::Io) {
+      let _ = file.set_len(start + written).await;
+    }
+    if let Err(e) = file.sync_data().await {
+      tracing::error!("tus: failed to sync upload {}: {}", id, e);
+      return (written, Err(AppendError::Io));
+    }
+    (written, result)
+  }
+}
+
+/// Token-protected routes of the tus resumable upload protocol
+pub fn tus_routes() -> Router<AppState> {
+  Router::new()
+    .route("/v1/tus", post(create_upload).options(discover))
+    .route(
+      "/v1/tus/{id}",
+      head(upload_offset)
+        .patch(append_upload)
+        .delete(terminate_upload),
+    )
+}
+
+fn uploads(state: &AppState) -> Result<&TusUploads, ServerError> {
+  state
+    .tus
+    .as_deref()
+    .ok_or(ServerError::Storage(StorageError::NotFound))
+}
+
+/// Response carrying the `Tus-Resumable` header every tus response needs
+fn tus_response(status: StatusCode, message: &'static str) -> Response {
+  let mut response = if message.is_empty() {
+    status.into_response()
+  } else {
+    (status, [("Content-Type", "text/plain")], message).into_response()
+  };
+  response
+    .headers_mut()
+    .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+  response
+}
+
+/// Whether a request speaks the protocol version this server implements
+fn supported_version(headers: &HeaderMap) -> bool {
+  headers.get(TUS_RESUMABLE).and_then(|v| v.to_str().ok()) == Some(TUS_VERSION)
+}
+
+/// Rejection of a request for a protocol version other than the one this server speaks
+fn unsupported_version() -> Response {
+  let mut response = tus_response(StatusCode::PRECONDITION_FAILED, "Unsupported tus version");
+  response
+    .headers_mut()
+    .insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+  response
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+  headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+fn insert_header(response: &mut Response, name: &'static str, value: impl ToString) {
+  if let Ok(value) = HeaderValue::from_str(&value.to_string()) {
+    response.headers_mut().insert(name, value);
+  }
+}
+
+/// Decode `Upload-Metadata`: comma separated `key base64(value)` pairs, the value optional
+fn parse_metadata(value: &str) -> Option<HashMap<String, String>> {
+  let mut metadata = HashMap::new();
+  if value.trim().is_empty() {
+    return Some(metadata);
+  }
+  for pair in value.split(',') {
+    let mut parts = pair.trim().splitn(2, ' ');
+    let key = parts.next().filter(|key| !key.is_empty())?;
+    let value = match parts.next() {
+      Some(encoded) => {
+        String::from_utf8(general_purpose::STANDARD.decode(encoded.trim()).ok()?).ok()?
+      },
+      None => String::new(),
+    };
+    metadata.insert(key.to_string(), value);
+  }
+  Some(metadata)
+}
+
+/// Name of the calling token, which an upload is bound to
+fn token_name(state: &AppState, token: &AuthenticatedToken) -> Result<String, ServerError> {
+  state
+    .storage
+    .get_token_config(&token.0)
+    .map(|service| service.name.clone())
+    .ok_or(ServerError::Unauthorized)
+}
+
+/// Load an upload of the calling token; other tokens' uploads are not found
+async fn load_own(
+  uploads: &TusUploads,
+  id: &str,
+  name: &str,
+) -> Result<(UploadInfo, u64), ServerError> {
+  let (info, offset) = uploads.load(id).await?;
+  if info.token != name {
+    return Err(ServerError::Storage(StorageError::NotFound));
+  }
+  Ok((info, offset))
+}
+
+/// `OPTIONS /v1/tus`: the protocol version, extensions and size limit
+pub async fn discover(State(state): State<AppState>) -> Result<Response, ServerError> {
+  let uploads = uploads(&state)?;
+  let mut response = tus_response(StatusCode::NO_CONTENT, "");
+  insert_header(&mut response, "tus-version", TUS_VERSION);
+  insert_header(&mut response, "tus-extension", TUS_EXTENSIONS);
+  insert_header(&mut response, "tus-max-size", uploads.max_size_bytes);
+  Ok(response)
+}
+
+/// `POST /v1/tus`: start an upload of the hash named in `Upload-Metadata`
+pub async fn create_upload(
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  headers: HeaderMap,
+) -> Result<Response, ServerError> {
+  if !supported_version(&headers) {
+    return Ok(unsupported_version());
+  }
+  let uploads = uploads(&state)?;
+  handlers::require_write(&state, &token)?;
+
+  let Some(length) = header_u64(&headers, UPLOAD_LENGTH) else {
+    return Ok(tus_response(
+      StatusCode::BAD_REQUEST,
+      "Missing Upload-Length",
+    ));
+  };
+  if length > uploads.max_size_bytes {
+    return Ok(tus_response(
+      StatusCode::PAYLOAD_TOO_LARGE,
+      "Upload exceeds Tus-Max-Size",
+    ));
+  }
+  if length == 0 && !state.allow_empty_uploads {
+    return Ok(tus_response(StatusCode::BAD_REQUEST, "Empty request body"));
+  }
+
+  let metadata = headers
+    .get(UPLOAD_METADATA)
+    .and_then(|value| value.to_str().ok())
+    .map_or(Some(HashMap::new()), parse_metadata);
+  let Some(mut metadata) = metadata else {
+    return Ok(tus_response(
+      StatusCode::BAD_REQUEST,
+      "Invalid Upload-Metadata",
+    ));
+  };
+  let Some(hash) = metadata
+    .remove("hash")
+    .filter(|hash| state.hash_policies.nx.validate(hash).is_ok())
+  else {
+    return Ok(tus_response(
+      StatusCode::BAD_REQUEST,
+      "Upload-Metadata must name a valid hash",
+    ));
+  };
+  let run_id = metadata.remove("runId");
+  if run_id
+    .as_deref()
+    .is_some_and(|run_id| validation::validate_run_id(run_id).is_err())
+  {
+    return Ok(tus_response(StatusCode::BAD_REQUEST, "Invalid runId"));
+  }
+
+  // Refuse early instead of after the client sent the whole artifact
+  let service = state
+    .storage
+    .get_token_config(&token.0)
+    .ok_or(ServerError::Unauthorized)?;
+  if !service.allow_overwrite && state.storage.exists_with_token(&token.0, &hash).await? {
+    return Ok(tus_response(
+      StatusCode::CONFLICT,
+      "Cannot override an existing record",
+    ));
+  }
+
+  let info = UploadInfo {
+    token: service.name.clone(),
+    hash,
+    length,
+    run_id,
+    expires_at: uploads.expires_at(),
+  };
+  let id = uploads.create(&info).await?;
+  tracing::info!(
+    "tus: created upload {} of {} for {}",
+    id,
+    info.hash,
+    info.token
+  );
+
+  if length == 0 {
+    let _guard = uploads.lock(&id);
+    return Ok(finish(&state, &token, uploads, &id, &info).await);
+  }
+
+  let mut response = tus_response(StatusCode::CREATED, "");
+  let location = format!("{}/v1/tus/{}", state.base_path.as_deref().unwrap_or(""), id);
+  insert_header(&mut response, "location", location);
+  insert_header(
+    &mut response,
+    UPLOAD_EXPIRES,
+    http_date::format(info.expires_at),
+  );
+  Ok(response)
+}
+
+/// `HEAD /v1/tus/{id}`: how many bytes of the upload the server has
+pub async fn upload_offset(
+  Path(id): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  headers: HeaderMap,
+) -> Result<Response, ServerError> {
+  if !supported_version(&headers) {
+    return Ok(unsupported_version());
+  }
+  let uploads = uploads(&state)?;
+  let name = token_name(&state, &token)?;
+  let (info, offset) = load_own(uploads, &id, &name).await?;
+
+  let mut response = tus_response(StatusCode::OK, "");
+  insert_header(&mut response, UPLOAD_OFFSET, offset);
+  insert_header(&mut response, UPLOAD_LENGTH, info.length);
+  insert_header(
+    &mut response,
+    UPLOAD_EXPIRES,
+    http_date::format(info.expires_at),
+  );
+  response
+    .headers_mut()
+    .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+  Ok(response)
+}
+
+/// `PATCH /v1/tus/{id}`: append bytes at `Upload-Offset`; the last bytes store the artifact
+pub async fn append_upload(
+  Path(id): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  request: Request,
+) -> Result<Response, ServerError> {
+  if !supported_version(request.headers()) {
+    return Ok(unsupported_version());
+  }
+  handlers::require_write(&state, &token)?;
+  let content_type = request
+    .headers()
+    .get(header::CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok());
+  if content_type != Some(OFFSET_CONTENT_TYPE) {
+    return Ok(tus_response(
+      StatusCode::UNSUPPORTED_MEDIA_TYPE,
+      "Content-Type must be application/offset+octet-stream",
+    ));
+  }
+  let Some(client_offset) = header_u64(request.headers(), UPLOAD_OFFSET) else {
+    return Ok(tus_response(
+      StatusCode::BAD_REQUEST,
+      "Missing Upload-Offset",
+    ));
+  };
+
+  let uploads = uploads(&state)?;
+  let name = token_name(&state, &token)?;
+  let Some(_guard) = uploads.lock(&id) else {
+    return Ok(tus_response(
+      StatusCode::CONFLICT,
+      "Upload is in use by another request",
+    ));
+  };
+  let (mut info, offset) = load_own(uploads, &id, &name).await?;
+  if client_offset != offset {
+    return Ok(tus_response(
+      StatusCode::CONFLICT,
+      "Upload-Offset does not match the upload",
+    ));
+  }
+
+  let (written, result) = uploads
+    .append(&id, request.into_body(), info.length - offset)
+    .await;
+  info.expires_at = uploads.expires_at();
+  uploads.save(&id, &info).await?;
+  match result {
+    Ok(()) => {},
+    Err(AppendError::TooLong) => {
+      return Ok(tus_response(
+        StatusCode::BAD_REQUEST,
+        "Upload exceeds Upload-Length",
+      ));
+    },
+    Err(AppendError::Interrupted) => {
+      tracing::warn!(
+        "tus: upload {} interrupted after {} bytes",
+        id,
+        offset + written
+      );
+      return Ok(tus_response(StatusCode::BAD_REQUEST, "Upload interrupted"));
+    },
+    Err(AppendError::Io) => return Err(ServerError::InternalError),
+  }
+
+  let offset = offset + written;
+  if offset == info.length {
+    return Ok(finish(&state, &token, uploads, &id, &info).await);
+  }
+  let mut response = tus_response(StatusCode::NO_CONTENT, "");
+  insert_header(&mut response, UPLOAD_OFFSET, offset);
+  insert_header(
+    &mut response,
+    UPLOAD_EXPIRES,
+    http_date::format(info.expires_at),
+  );
+  Ok(response)
+}
+
+/// Store a complete upload as the artifact and remove it from disk
+///
+/// A failed store keeps the upload, so the client's retry of the final `PATCH` (with an
+/// empty body at the full offset) stores it again.
+async fn finish(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  uploads: &TusUploads,
+  id: &str,
+  info: &UploadInfo,
+) -> Response {
+  let overwrite = state
+    .storage
+    .get_token_config(&token.0)
+    .is_some_and(|service| service.allow_overwrite);
+  let options = UploadOptions {
+    run_id: info.run_id.clone(),
+    overwrite,
+    ..Default::default()
+  };
+
+  let result = match File::open(uploads.data_path(id)).await {
+    Ok(file) => {
+      state
+        .storage
+        .store_with_options(
+          &token.0,
+          &info.hash,
+          boxed_reader_stream(file),
+          Some(info.length),
+          options,
+        )
+        .await
+    },
+    Err(e) => Err(io_error("failed to open complete upload", e)),
+  };
+
+  let mut response = match result {
+    Ok(()) => {
+      tracing::info!("tus: stored upload {} as {}", id, info.hash);
+      handlers::record_upload(state, &token.0, info.length);
+      uploads.remove(id).await;
+      tus_response(StatusCode::NO_CONTENT, "")
+    },
+    Err(StorageError::AlreadyExists) => {
+      uploads.remove(id).await;
+      return tus_response(StatusCode::CONFLICT, "Cannot override an existing record");
+    },
+    Err(err) => {
+      tracing::error!("tus: failed to store upload {}: {}", id, err);
+      // A server error makes tus clients retry
+      let response = tus_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Failed to store the upload, retry the request",
+      );
+      return error::with_retry_after(response, state.storage.retry_after());
+    },
+  };
+  insert_header(&mut response, UPLOAD_OFFSET, info.length);
+  response
+}
+
+/// `DELETE /v1/tus/{id}`: abandon an upload
+pub async fn terminate_upload(
+  Path(id): Path<String>,
+  State(state): State<AppState>,
+  Extension(token): Extension<AuthenticatedToken>,
+  headers: HeaderMap,
+) -> Result<Response, ServerError> {
+  if !supported_version(&headers) {
+    return Ok(unsupported_version());
+  }
+  let uploads = uploads(&state)?;
+  let name = token_name(&state, &token)?;
+  let Some(_guard) = uploads.lock(&id) else {
+    return Ok(tus_response(
+      StatusCode::CONFLICT,
+      "Upload is in use by another request",
+    ));
+  };
+  load_own(uploads, &id, &name).await?;
+  uploads.remove(&id).await;
+  Ok(tus_response(StatusCode::NO_CONTENT, ""))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn info(expires_at: u64) -> UploadInfo {
+    UploadInfo {
+      token: "ci".to_string(),
+      hash: "abc".to_string(),
+      length: 11,
+      run_id: None,
+      expires_at,
+    }
+  }
+
+  #[test]
+  fn test_parse_metadata() {
+    let metadata = parse_metadata("hash YWJj,runId cnVuLTE=, empty").unwrap();
+    assert_eq!(metadata["hash"], "abc");
+    assert_eq!(metadata["runId"], "run-1");
+    assert_eq!(metadata["empty"], "");
+    assert!(parse_metadata("").unwrap().is_empty());
+    assert!(parse_metadata("hash not-base64!").is_none());
+  }
+
+  #[tokio::test]
+  async fn test_append_and_resume() {
+    let dir = tempfile::tempdir().unwrap();
+    let uploads = TusUploads::new(&TusConfig {
+      path: Some(dir.path().display().to_string()),
+      max_size_bytes: 1024,
+      expiration_seconds: 60,
+    });
+    let id = uploads.create(&info(uploads.expires_at())).await.unwrap();
+    assert!(TusUploads::is_valid_id(&id));
+
+    let (written, result) = uploads.append(&id, Body::from("hello"), 11).await;
+    assert_eq!((written, result), (5, Ok(())));
+    let (written, result) = uploads.append(&id, Body::from(" world!"), 6).await;
+    assert_eq!((written, result), (0, Err(AppendError::TooLong)));
+    let (written, result) = uploads.append(&id, Body::from(" world"), 6).await;
+    assert_eq!((written, result), (6, Ok(())));
+
+    let (_, offset) = uploads.load(&id).await.unwrap();
+    assert_eq!(offset, 11);
+    assert_eq!(
+      std::fs::read(uploads.data_path(&id)).unwrap(),
+      b"hello world"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_expired_uploads_are_swept() {
+    let dir = tempfile::tempdir().unwrap();
+    let uploads = TusUploads::new(&TusConfig {
+      path: Some(dir.path().display().to_string()),
+      max_size_bytes: 1024,
+      expiration_seconds: 60,
+    });
+    let expired = uploads.create(&info(1)).await.unwrap();
+    assert!(matches!(
+      uploads.load(&expired).await,
+      Err(StorageError::NotFound)
+    ));
+
+    let stale = uploads.create(&info(1)).await.unwrap();
+    let fresh = uploads.create(&info(uploads.expires_at())).await.unwrap();
+    assert!(!uploads.info_path(&stale).exists());
+    assert!(uploads.load(&fresh).await.is_ok());
+  }
+}
@@ -0,0 +1,271 @@
+use crate::domain::storage::{boxed_reader_stream, StorageError};
+use crate::server::handlers::VARIANT_HEADER;
+use crate::server::{middleware::AuthenticatedToken, AppState};
+use axum::{
+  body::Body,
+  http::{header::CONTENT_LENGTH, HeaderValue, StatusCode},
+  response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use futures_util::stream::StreamExt;
+use std::io;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::StreamReader;
+
+/// Chunks buffered between the upstream download and each of its consumers
+const CHANNEL_CHUNKS: usize = 16;
+
+/// Client shared by the upstreams of all tokens, so connections are pooled per upstream host
+fn client() -> &'static reqwest::Client {
+  static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+  CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Fetch an artifact missing locally from the token's upstream and stream it to the client
+///
+/// `key` is the local storage name of `hash`, carrying the requested variant if any. An
+/// upstream that misses, fails or does not answer in time is logged and treated as a miss.
+/// With `persist`, the artifact is stored under `key` as it streams, even if the client
+/// goes away before the download finished.
+pub(crate) async fn fetch(
+  state: &AppState,
+  token: &AuthenticatedToken,
+  hash: &str,
+  key: &str,
+) -> Option<Response> {
+  let service = state.storage.get_token_config(&token.0)?;
+  let upstream = service.upstream.as_ref()?;
+
+  let mut request = client()
+    .get(format!("{}/v1/cache/{}", upstream.url, hash))
+    .bearer_auth(&upstream.access_token);
+  if let Some(variant) = key.strip_prefix(hash).and_then(|rest| rest.get(1..)) {
+    request = request.header(VARIANT_HEADER, variant);
+  }
+  let timeout = Duration::from_secs(upstream.timeout_seconds);
+  let response = match tokio::time::timeout(timeout, request.send()).await {
+    Ok(Ok(response)) => response,
+    Ok(Err(err)) => {
+      tracing::warn!("Upstream {} failed for {}: {}", upstream.url, hash, err);
+      return None;
+    },
+    Err(_) => {
+      tracing::warn!("Upstream {} timed out for {}", upstream.url, hash);
+      return None;
+    },
+  };
+  match response.status() {
+    reqwest::StatusCode::OK => {},
+    reqwest::StatusCode::NOT_FOUND => return None,
+    status => {
+      tracing::warn!("Upstream {} answered {} for {}", upstream.url, status, hash);
+      return None;
+    },
+  }
+  tracing::debug!("Serving {} from upstream {}", hash, upstream.url);
+
+  let size = response.content_length();
+  let chunks = response
+    .bytes_stream()
+    .map(|chunk| chunk.map_err(io::Error::other));
+  let body = if upstream.persist {
+    let (client_sender, client_receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    let (store_sender, store_receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    tokio::spawn(tee(chunks, client_sender, store_sender));
+    let (state, token, key) = (state.clone(), token.0.clone(), key.to_string());
+    tokio::spawn(async move {
+      let reader = StreamReader::new(ReceiverStream::new(store_receiver));
+      match state
+        .storage
+        .store_with_token(&token, &key, boxed_reader_stream(reader), size)
+        .await
+      {
+        Ok(()) | Err(StorageError::AlreadyExists) => {},
+        Err(err) => tracing::warn!("Failed to persist {} from upstream: {}", key, err),
+      }
+    });
+    ReceiverStream::new(client_receiver).boxed()
+  } else {
+    chunks.boxed()
+  };
+
+  let token_name = service.name.clone();
+  let egress = state.egress.clone();
+  let usage = state.accounting.clone();
+  let body = body.map(move |chunk| {
+    if let Ok(bytes) = &chunk {
+      egress.record(&token_name, bytes.len() as u64);
+      usage.record_bytes(&token_name, bytes.len() as u64);
+    }
+    chunk
+  });
+
+  let mut response = (
+    StatusCode::OK,
+    [("content-type", "application/octet-stream")],
+    Body::from_stream(body),
+  )
+    .into_response();
+  if let Some(size) = size {
+    response
+      .headers_mut()
+      .insert(CONTENT_LENGTH, HeaderValue::from(size));
+  }
+  Some(response)
+}
+
+/// Copy the upstream download to the client and to local storage
+///
+/// A failed download is passed to both, so the local copy is aborted rather than truncated.
+/// The copy stops early only once both consumers are gone.
+async fn tee(
+  chunks: impl futures_util::Stream<Item = io::Result<Bytes>>,
+  client: mpsc::Sender<io::Result<Bytes>>,
+  store: mpsc::Sender<io::Result<Bytes>>,
+) {
+  let mut chunks = std::pin::pin!(chunks);
+  let (mut client, mut store) = (Some(client), Some(store));
+  while let Some(chunk) = chunks.next().await {
+    let copy = match &chunk {
+      Ok(bytes) => Ok(bytes.clone()),
+      Err(err) => Err(io::Error::new(err.kind(), err.to_string())),
+    };
+    if let Some(sender) = &store {
+      if sender.send(copy).await.is_err() {
+        store = None;
+      }
+    }
+    if let Some(sender) = &client {
+      if sender.send(chunk).await.is_err() {
+        client = None;
+      }
+    }
+    if client.is_none() && store.is_none() {
+      return;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::domain::config::Config;
+  use crate::infra::multi_storage::MultiStorageRouter;
+  use axum::http::Request;
+  use futures_util::stream;
+  use tower::ServiceExt;
+
+  async fn state(root: &std::path::Path, token: &str, upstream: &str) -> AppState {
+    let yaml = format!(
+      "buckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\nserviceAccessTokens:\n  - name: ci\n    bucket: local\n    accessToken: {}\n{}",
+      root.display(),
+      token,
+      upstream
+    );
+    let config = Config::from_yaml_str(&yaml)
+      .unwrap()
+      .resolve_env_vars()
+      .unwrap();
+    AppState::new(MultiStorageRouter::from_config(&config).await.unwrap())
+  }
+
+  #[tokio::test]
+  async fn test_pull_through_serves_and_persists_upstream_artifacts() {
+    let central_root = tempfile::tempdir().unwrap();
+    let central = state(central_root.path(), "central", "").await;
+    central
+      .storage
+      .store_with_token(
+        "central",
+        "1234",
+        boxed_reader_stream(io::Cursor::new(b"artifact".to_vec())),
+        Some(8),
+      )
+      .await
+      .unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let central_app = crate::server::create_router(&central).with_state(central.clone());
+    let server = tokio::spawn(async move { axum::serve(listener, central_app).await });
+
+    let edge_root = tempfile::tempdir().unwrap();
+    let upstream = format!(
+      "    upstream:\n      url: http://127.0.0.1:{}/\n      accessToken: central\n",
+      port
+    );
+    let edge = state(edge_root.path(), "edge", &upstream).await;
+    let app = crate::server::create_router(&edge).with_state(edge.clone());
+    let get = |hash: &str| {
+      Request::builder()
+        .uri(format!("/v1/cache/{}", hash))
+        .header("authorization", "Bearer edge")
+        .body(Body::empty())
+        .unwrap()
+    };
+
+    let response = app.clone().oneshot(get("1234")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_LENGTH], "8");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    assert_eq!(&body[..], b"artifact");
+
+    // The local copy is written in the background
+    let mut persisted = false;
+    for _ in 0..100 {
+      persisted = edge
+        .storage
+        .exists_with_token("edge", "1234")
+        .await
+        .unwrap();
+      if persisted {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(persisted);
+
+    let response = app.oneshot(get("5678")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    server.abort();
+  }
+
+  #[tokio::test]
+  async fn test_tee_keeps_storing_after_client_is_gone() {
+    let chunks = stream::iter(vec![
+      Ok(Bytes::from_static(b"ab")),
+      Ok(Bytes::from_static(b"cd")),
+    ]);
+    let (client, client_receiver) = mpsc::channel(1);
+    let (store, store_receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    drop(client_receiver);
+    tee(chunks, client, store).await;
+
+    let stored: Vec<_> = ReceiverStream::new(store_receiver)
+      .map(|chunk| chunk.unwrap())
+      .collect()
+      .await;
+    assert_eq!(stored.concat(), b"abcd");
+  }
+
+  #[tokio::test]
+  async fn test_tee_passes_failures_to_both() {
+    let chunks = stream::iter(vec![
+      Ok(Bytes::from_static(b"ab")),
+      Err(io::Error::other("connection reset")),
+    ]);
+    let (client, client_receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    let (store, store_receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    tee(chunks, client, store).await;
+
+    for receiver in [client_receiver, store_receiver] {
+      let chunks: Vec<_> = ReceiverStream::new(receiver).collect().await;
+      assert_eq!(chunks.len(), 2);
+      assert!(chunks[1].is_err());
+    }
+  }
+}
@@ -1,20 +1,230 @@
+use crate::domain::config::{HashCharset, HashPolicyConfig, HashValidationConfig};
 use crate::server::error::ServerError;
+use regex::Regex;
 
+/// Key format accepted on one protocol surface
+#[derive(Debug, Clone)]
+pub struct HashPolicy {
+  charset: HashCharset,
+  min_length: usize,
+  max_length: usize,
+  pattern: Option<Regex>,
+}
+
+impl HashPolicy {
+  /// Built-in rule for Nx hashes: up to 128 letters, digits, `-` and `_`
+  pub fn nx() -> Self {
+    Self {
+      charset: HashCharset::Alphanumeric,
+      min_length: 1,
+      max_length: 128,
+      pattern: None,
+    }
+  }
+
+  /// Built-in rule for keyed caches: path-like keys of up to 512 characters
+  pub fn keyed() -> Self {
+    Self {
+      charset: HashCharset::Path,
+      min_length: 1,
+      max_length: 512,
+      pattern: None,
+    }
+  }
+
+  /// Built-in rule for Bazel digests: 64 hex characters of SHA-256
+  pub fn sha256() -> Self {
+    Self {
+      charset: HashCharset::Hex,
+      min_length: 64,
+      max_length: 64,
+      pattern: None,
+    }
+  }
+
+  /// Override the fields the config sets; the pattern has to match the whole key
+  pub fn with_config(self, config: &HashPolicyConfig) -> Result<Self, regex::Error> {
+    let pattern = match &config.pattern {
+      Some(pattern) => Some(Regex::new(&format!("^(?:{})$", pattern))?),
+      None => self.pattern,
+    };
+    Ok(Self {
+      charset: config.charset.unwrap_or(self.charset),
+      min_length: config.min_length.unwrap_or(self.min_length),
+      max_length: config.max_length.unwrap_or(self.max_length),
+      pattern,
+    })
+  }
+
+  pub fn validate(&self, hash: &str) -> Result<(), ServerError> {
+    if hash.len() < self.min_length || hash.len() > self.max_length {
+      return Err(ServerError::BadRequest);
+    }
+
+    let valid_chars = match self.charset {
+      HashCharset::Alphanumeric => hash
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_'),
+      HashCharset::Hex => hash.chars().all(|c| c.is_ascii_hexdigit()),
+      HashCharset::Path => {
+        hash
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+          && hash
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+      },
+    };
+    if !valid_chars {
+      return Err(ServerError::BadRequest);
+    }
+
+    match &self.pattern {
+      Some(pattern) if !pattern.is_match(hash) => Err(ServerError::BadRequest),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Key policies of every protocol surface
+#[derive(Debug, Clone)]
+pub struct HashPolicies {
+  pub nx: HashPolicy,
+  pub please: HashPolicy,
+  pub bazel: HashPolicy,
+  pub gradle: HashPolicy,
+  pub ccache: HashPolicy,
+  pub turbo: HashPolicy,
+  pub mirror: HashPolicy,
+}
+
+impl Default for HashPolicies {
+  fn default() -> Self {
+    Self {
+      nx: HashPolicy::nx(),
+      please: HashPolicy::keyed(),
+      bazel: HashPolicy::sha256(),
+      gradle: HashPolicy::keyed(),
+      ccache: HashPolicy::keyed(),
+      turbo: HashPolicy::nx(),
+      mirror: HashPolicy::keyed(),
+    }
+  }
+}
+
+impl HashPolicies {
+  /// Built-in policies with the configured overrides applied
+  pub fn from_config(config: &HashValidationConfig) -> Result<Self, regex::Error> {
+    let apply = |policy: HashPolicy, config: &Option<HashPolicyConfig>| match config {
+      Some(config) => policy.with_config(config),
+      None => Ok(policy),
+    };
+    Ok(Self {
+      nx: apply(HashPolicy::nx(), &config.nx)?,
+      please: apply(HashPolicy::keyed(), &config.please)?,
+      bazel: apply(HashPolicy::sha256(), &config.bazel)?,
+      gradle: apply(HashPolicy::keyed(), &config.gradle)?,
+      ccache: apply(HashPolicy::keyed(), &config.ccache)?,
+      turbo: apply(HashPolicy::nx(), &config.turbo)?,
+      mirror: apply(HashPolicy::keyed(), &config.mirror)?,
+    })
+  }
+}
+
+/// Check a hash against the built-in Nx rule, for routes without access to the app state
 pub fn validate_hash(hash: &str) -> Result<(), ServerError> {
-  if hash.is_empty() {
+  HashPolicy::nx().validate(hash)
+}
+
+/// Variants name a platform such as `linux-x64`; they become part of the storage key
+pub fn validate_variant(variant: &str) -> Result<(), ServerError> {
+  if variant.is_empty() || variant.len() > 64 {
     return Err(ServerError::BadRequest);
   }
 
-  if !hash
+  if !variant
     .chars()
-    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
   {
     return Err(ServerError::BadRequest);
   }
 
-  if hash.len() > 128 {
+  Ok(())
+}
+
+/// Run IDs come from CI systems (e.g. `1234567-2`) and become a storage key segment
+pub fn validate_run_id(run_id: &str) -> Result<(), ServerError> {
+  if run_id.is_empty() || run_id.len() > 128 || run_id == "." || run_id == ".." {
+    return Err(ServerError::BadRequest);
+  }
+
+  if !run_id
+    .chars()
+    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+  {
     return Err(ServerError::BadRequest);
   }
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_built_in_policies() {
+    let policies = HashPolicies::default();
+    assert!(policies.nx.validate("abc-123_def").is_ok());
+    assert!(policies.nx.validate("").is_err());
+    assert!(policies.nx.validate("a/b").is_err());
+    assert!(policies.nx.validate(&"a".repeat(129)).is_err());
+
+    assert!(policies
+      .please
+      .validate("linux_amd64/src/core/abc.tar")
+      .is_ok());
+    assert!(policies.please.validate("a/../b").is_err());
+    assert!(policies.please.validate("a//b").is_err());
+    assert!(policies.please.validate("a b").is_err());
+
+    assert!(policies.bazel.validate(&"0f".repeat(32)).is_ok());
+    assert!(policies.bazel.validate(&"0f".repeat(16)).is_err());
+    assert!(policies.bazel.validate(&"xy".repeat(32)).is_err());
+  }
+
+  #[test]
+  fn test_configured_policy() {
+    let config = HashValidationConfig {
+      nx: Some(HashPolicyConfig {
+        charset: Some(HashCharset::Hex),
+        min_length: Some(64),
+        max_length: Some(64),
+        pattern: None,
+      }),
+      please: Some(HashPolicyConfig {
+        pattern: Some("[a-z0-9_]+/[0-9a-f]+".to_string()),
+        ..Default::default()
+      }),
+      mirror: Some(HashPolicyConfig {
+        pattern: Some(".*".to_string()),
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+    let policies = HashPolicies::from_config(&config).unwrap();
+
+    assert!(policies.nx.validate(&"ab".repeat(32)).is_ok());
+    assert!(policies.nx.validate("abc123").is_err());
+    assert!(policies.nx.validate(&"xy".repeat(32)).is_err());
+
+    assert!(policies.please.validate("linux_amd64/abc123").is_ok());
+    // The pattern must match the whole key and never widens the charset
+    assert!(policies
+      .please
+      .validate("linux_amd64/abc123/extra")
+      .is_err());
+    assert!(policies.mirror.validate("any/key.tar").is_ok());
+    assert!(policies.mirror.validate("a/../b").is_err());
+  }
+}
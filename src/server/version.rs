@@ -0,0 +1,57 @@
+use axum::Json;
+use serde::Serialize;
+
+use crate::server::egress::format_day;
+
+/// Git commit the binary was built from, `unknown` outside a checkout without `GIT_SHA`
+pub const GIT_SHA: &str = env!("NX_CACHE_GIT_SHA");
+
+/// Unix time of the build, embedded by the build script
+pub const BUILD_EPOCH: &str = env!("NX_CACHE_BUILD_EPOCH");
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+  pub version: &'static str,
+  pub git_sha: &'static str,
+  /// RFC 3339 build time in UTC
+  pub build_timestamp: String,
+}
+
+impl BuildInfo {
+  pub fn current() -> Self {
+    let epoch: u64 = BUILD_EPOCH.parse().unwrap_or(0);
+    Self {
+      version: env!("CARGO_PKG_VERSION"),
+      git_sha: GIT_SHA,
+      build_timestamp: rfc3339(epoch),
+    }
+  }
+}
+
+fn rfc3339(unix_secs: u64) -> String {
+  let seconds = unix_secs % 86_400;
+  format!(
+    "{}T{:02}:{:02}:{:02}Z",
+    format_day(unix_secs / 86_400),
+    seconds / 3600,
+    seconds / 60 % 60,
+    seconds % 60
+  )
+}
+
+/// Unauthenticated build information, so fleets can verify which build each instance runs
+pub async fn version() -> Json<BuildInfo> {
+  Json(BuildInfo::current())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_rfc3339() {
+    assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+    assert_eq!(rfc3339(1_760_601_612), "2025-10-16T08:00:12Z");
+  }
+}
@@ -20,15 +20,22 @@ use axum::{
 };
 use common::{unique_bucket_name, MinioTestContainer};
 use nx_cache_server::domain::config::{
-  ResolvedBucketConfig, ResolvedConfig, ResolvedServiceAccessToken,
+  CacheControlConfig, ResolvedBucketConfig, ResolvedConfig, ResolvedServiceAccessToken,
+  ShutdownConfig, TransportCompressionConfig, TusConfig,
 };
 use nx_cache_server::infra::multi_storage::MultiStorageRouter;
 use nx_cache_server::server::{create_router, AppState};
-use std::sync::Arc;
 use tower::util::ServiceExt; // for `oneshot` and `ready`
 
 /// Helper to create a test app with MinIO backend
 async fn create_test_app(minio: &MinioTestContainer) -> (Router, String) {
+  let (app_state, bucket_name) = create_test_state(minio).await;
+  let app = create_router(&app_state).with_state(app_state);
+  (app, bucket_name)
+}
+
+/// Helper to create the app state of a test app, for tests that configure it further
+async fn create_test_state(minio: &MinioTestContainer) -> (AppState, String) {
   let bucket_name = unique_bucket_name("api-test");
 
   // Create bucket in MinIO
@@ -44,14 +51,11 @@ async fn create_test_app(minio: &MinioTestContainer) -> (Router, String) {
       bucket_name: bucket_name.clone(),
       access_key_id: Some(minio.access_key.clone()),
       secret_access_key: Some(minio.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(minio.endpoint_url()),
-      tls_ca_file: None,
-      insecure_tls: None,
       force_path_style: true,
-      sse: None,
       timeout: 60,
+      ..Default::default()
     }],
     service_access_tokens: vec![
       ResolvedServiceAccessToken {
@@ -59,16 +63,39 @@ async fn create_test_app(minio: &MinioTestContainer) -> (Router, String) {
         bucket: bucket_name.clone(),
         prefix: "/test".to_string(),
         access_token: "test-token-rw".to_string(),
+        ..Default::default()
       },
       ResolvedServiceAccessToken {
         name: "another-namespace".to_string(),
         bucket: bucket_name.clone(),
         prefix: "/other".to_string(),
         access_token: "test-token-other".to_string(),
+        ..Default::default()
       },
     ],
     port: 3000,
     debug: true,
+    shutdown: ShutdownConfig::default(),
+    cache_control: CacheControlConfig::default(),
+    normalize_paths: false,
+    in_flight_wait_seconds: 0,
+    recent_errors_capacity: 100,
+    mirror: None,
+    manifest: None,
+    affinity: None,
+    task_metadata: None,
+    jwt: Vec::new(),
+    token_minting: None,
+    token_revocation: None,
+    hash_validation: Default::default(),
+    audit_namespaces: false,
+    strict_nx_spec: false,
+    json_errors: false,
+    allow_empty_uploads: false,
+    base_path: None,
+    transport_compression: None,
+    tus: None,
+    grpc: None,
   };
 
   // Create storage router
@@ -76,14 +103,7 @@ async fn create_test_app(minio: &MinioTestContainer) -> (Router, String) {
     .await
     .expect("Failed to create MultiStorageRouter");
 
-  // Create app state and router
-  let app_state = AppState {
-    storage: Arc::new(storage),
-  };
-
-  let app = create_router(&app_state).with_state(app_state);
-
-  (app, bucket_name)
+  (AppState::new(storage), bucket_name)
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -160,6 +180,16 @@ async fn test_get_artifact_success() {
     response.headers().get(header::CONTENT_TYPE).unwrap(),
     "application/octet-stream"
   );
+  assert_eq!(
+    response.headers().get(header::CACHE_CONTROL).unwrap(),
+    "private, max-age=31536000, immutable"
+  );
+  assert_eq!(
+    response.headers().get(header::CONTENT_LENGTH).unwrap(),
+    &data.len().to_string()
+  );
+  assert!(response.headers().contains_key(header::LAST_MODIFIED));
+  assert!(response.headers().contains_key("x-artifact-created"));
 
   // Verify body content
   let body = axum::body::to_bytes(response.into_body(), usize::MAX)
@@ -192,6 +222,27 @@ async fn test_get_artifact_not_found() {
   println!("✓ GET nonexistent artifact returned 404");
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_artifact_past_deadline() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/v1/cache/abandoned-hash")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header("x-request-deadline", "1000")
+    .body(Body::empty())
+    .unwrap();
+
+  let response = app.oneshot(request).await.unwrap();
+
+  // The client gave up long ago, so no storage work is done for it
+  assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+  println!("✓ GET past its deadline returned 504");
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_put_artifact_missing_auth() {
   let minio = MinioTestContainer::start().await;
@@ -312,6 +363,39 @@ async fn test_put_artifact_conflict() {
   println!("✓ PUT duplicate artifact returned 409 Conflict");
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_put_empty_artifact_rejected() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let hash = "empty-hash";
+
+  let request = Request::builder()
+    .method("PUT")
+    .uri(format!("/v1/cache/{}", hash))
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_TYPE, "application/octet-stream")
+    .header(header::CONTENT_LENGTH, 0)
+    .body(Body::empty())
+    .unwrap();
+
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  // Nothing was stored for the hash
+  let request = Request::builder()
+    .method("GET")
+    .uri(format!("/v1/cache/{}", hash))
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .body(Body::empty())
+    .unwrap();
+
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+  println!("✓ Empty PUT returned 400 and stored nothing");
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_namespace_isolation() {
   let _ = tracing_subscriber::fmt()
@@ -435,6 +519,528 @@ async fn test_health_check_endpoint() {
   println!("✓ Health check endpoint working");
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_liveness_and_readiness_endpoints() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/livez")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/readyz")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(json["ready"], true);
+  assert_eq!(json["buckets"][0]["reachable"], true);
+
+  println!("✓ Liveness and readiness endpoints working");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_version_endpoint() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/version")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+  assert!(json["gitSha"].is_string());
+  assert!(json["buildTimestamp"].as_str().unwrap().ends_with('Z'));
+
+  println!("✓ Version endpoint working");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_openapi_document() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/openapi.json")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let cache = &json["paths"]["/v1/cache/{hash}"];
+  assert!(cache["get"].is_object());
+  assert_eq!(
+    cache["put"]["security"][0]["bearerAuth"],
+    serde_json::json!([])
+  );
+  assert!(json["paths"]["/health"]["get"]["security"].is_null());
+  // Optional routes only appear when their feature is enabled
+  assert!(json["paths"]["/mirror/{key}"].is_null());
+
+  println!("✓ OpenAPI document served");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_put_compressed_artifact() {
+  use async_compression::tokio::bufread::ZstdEncoder;
+  use tokio::io::AsyncReadExt;
+
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let data = b"compressed upload ".repeat(200);
+  let mut compressed = Vec::new();
+  ZstdEncoder::new(&data[..])
+    .read_to_end(&mut compressed)
+    .await
+    .unwrap();
+
+  let request = Request::builder()
+    .method("PUT")
+    .uri("/v1/cache/compressed-upload")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_ENCODING, "zstd")
+    .header(header::CONTENT_LENGTH, compressed.len())
+    .body(Body::from(compressed))
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  // Stored decompressed, so every client can download it
+  let request = Request::builder()
+    .method("GET")
+    .uri("/v1/cache/compressed-upload")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  assert_eq!(body.as_ref(), data);
+
+  let request = Request::builder()
+    .method("PUT")
+    .uri("/v1/cache/brotli-upload")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_ENCODING, "br")
+    .body(Body::from(b"not supported".to_vec()))
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  println!("✓ Compressed uploads stored decompressed");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_transport_compression() {
+  use async_compression::tokio::bufread::GzipDecoder;
+  use tokio::io::AsyncReadExt;
+
+  let minio = MinioTestContainer::start().await;
+  let (app_state, _bucket) = create_test_state(&minio).await;
+  let app_state = app_state.with_transport_compression(TransportCompressionConfig {
+    min_size_bytes: 1024,
+    zstd_level: 3,
+    gzip_level: 6,
+  });
+  let app = create_router(&app_state).with_state(app_state);
+
+  let data = b"compressible artifact ".repeat(200);
+  let request = Request::builder()
+    .method("PUT")
+    .uri("/v1/cache/transport-hash")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_LENGTH, data.len())
+    .body(Body::from(data.clone()))
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/v1/cache/transport-hash")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::ACCEPT_ENCODING, "gzip")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+  assert_eq!(response.headers()[header::VARY], "accept-encoding");
+  assert!(!response.headers().contains_key(header::CONTENT_LENGTH));
+
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  assert!(body.len() < data.len());
+  let mut decoded = Vec::new();
+  GzipDecoder::new(body.as_ref())
+    .read_to_end(&mut decoded)
+    .await
+    .unwrap();
+  assert_eq!(decoded, data);
+
+  // Clients that do not ask for an encoding get the plain artifact
+  let request = Request::builder()
+    .method("GET")
+    .uri("/v1/cache/transport-hash")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  assert_eq!(body.as_ref(), data);
+
+  println!("✓ Downloads compressed for the transfer");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_tus_resumable_upload() {
+  let minio = MinioTestContainer::start().await;
+  let (app_state, _bucket) = create_test_state(&minio).await;
+  let dir = tempfile::tempdir().unwrap();
+  let app_state = app_state.with_tus(&TusConfig {
+    path: Some(dir.path().display().to_string()),
+    max_size_bytes: 1024,
+    expiration_seconds: 3600,
+  });
+  let app = create_router(&app_state).with_state(app_state);
+
+  let request = Request::builder()
+    .method("POST")
+    .uri("/v1/tus")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header("Tus-Resumable", "1.0.0")
+    .header("Upload-Length", "11")
+    .header("Upload-Metadata", "hash dHVzLWhhc2g=")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  assert_eq!(response.headers()["tus-resumable"], "1.0.0");
+  let location = response.headers()[header::LOCATION]
+    .to_str()
+    .unwrap()
+    .to_string();
+  assert!(location.starts_with("/v1/tus/"));
+
+  for (offset, chunk) in [(0, "hello"), (5, " world")] {
+    let request = Request::builder()
+      .method("PATCH")
+      .uri(&location)
+      .header(header::AUTHORIZATION, "Bearer test-token-rw")
+      .header("Tus-Resumable", "1.0.0")
+      .header(header::CONTENT_TYPE, "application/offset+octet-stream")
+      .header("Upload-Offset", offset.to_string())
+      .body(Body::from(chunk))
+      .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+      response.headers()["upload-offset"],
+      (offset + chunk.len()).to_string()
+    );
+
+    if offset == 0 {
+      let request = Request::builder()
+        .method("HEAD")
+        .uri(&location)
+        .header(header::AUTHORIZATION, "Bearer test-token-rw")
+        .header("Tus-Resumable", "1.0.0")
+        .body(Body::empty())
+        .unwrap();
+      let response = app.clone().oneshot(request).await.unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      assert_eq!(response.headers()["upload-offset"], "5");
+      assert_eq!(response.headers()["upload-length"], "11");
+    }
+  }
+
+  // The completed upload is an ordinary artifact and no longer a tus upload
+  let request = Request::builder()
+    .method("GET")
+    .uri("/v1/cache/tus-hash")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  assert_eq!(body.as_ref(), b"hello world");
+
+  let request = Request::builder()
+    .method("HEAD")
+    .uri(&location)
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header("Tus-Resumable", "1.0.0")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+  println!("✓ tus upload stored once complete");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_bundle_download() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  for (hash, data) in [("bundle-a", "first artifact"), ("bundle-b", "second")] {
+    let request = Request::builder()
+      .method("PUT")
+      .uri(format!("/v1/cache/{}", hash))
+      .header(header::AUTHORIZATION, "Bearer test-token-rw")
+      .header(header::CONTENT_LENGTH, data.len())
+      .body(Body::from(data))
+      .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+  }
+
+  let request = Request::builder()
+    .method("POST")
+    .uri("/v1/cache/bundle")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_TYPE, "application/json")
+    .body(Body::from(
+      r#"{"hashes": ["bundle-a", "bundle-missing", "bundle-b"]}"#,
+    ))
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(
+    response.headers()[header::CONTENT_TYPE],
+    "application/x-tar"
+  );
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+
+  // Walk the ustar entries: name, octal size, data padded to 512-byte blocks
+  let mut entries = Vec::new();
+  let mut offset = 0;
+  while body[offset..offset + 512].iter().any(|&byte| byte != 0) {
+    let header = &body[offset..offset + 512];
+    let name = String::from_utf8(header[..100].to_vec()).unwrap();
+    let name = name.trim_end_matches('\0').to_string();
+    let size = std::str::from_utf8(&header[124..135]).unwrap();
+    let size = usize::from_str_radix(size, 8).unwrap();
+    let data = body[offset + 512..offset + 512 + size].to_vec();
+    entries.push((name, String::from_utf8(data).unwrap()));
+    offset += 512 + size.div_ceil(512) * 512;
+  }
+  assert_eq!(body.len(), offset + 1024);
+  assert_eq!(
+    entries,
+    vec![
+      ("bundle-a".to_string(), "first artifact".to_string()),
+      ("bundle-b".to_string(), "second".to_string()),
+    ]
+  );
+
+  println!("✓ Bundle streamed as tar without missing hashes");
+}
+
+/// A ustar entry for `name`, padded to whole 512-byte blocks
+fn tar_entry(name: &str, data: &[u8]) -> Vec<u8> {
+  let mut header = [0u8; 512];
+  header[..name.len()].copy_from_slice(name.as_bytes());
+  header[100..108].copy_from_slice(b"0000644\0");
+  header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+  header[136..148].copy_from_slice(b"00000000000\0");
+  header[156] = b'0';
+  header[257..265].copy_from_slice(b"ustar\x0000");
+  header[148..156].fill(b' ');
+  let checksum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+  header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+
+  let mut entry = header.to_vec();
+  entry.extend_from_slice(data);
+  entry.resize(entry.len().div_ceil(512) * 512, 0);
+  entry
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_bundle_upload() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let request = Request::builder()
+    .method("PUT")
+    .uri("/v1/cache/bundle-existing")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_LENGTH, 3)
+    .body(Body::from("old"))
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let mut archive = tar_entry("bundle-up-a", b"first artifact");
+  archive.extend(tar_entry("./bundle-up-b", b"second"));
+  archive.extend(tar_entry("bundle-existing", b"new"));
+  archive.extend(tar_entry("bundle-up-a", b"repeated"));
+  archive.extend([0u8; 1024]);
+  let request = Request::builder()
+    .method("PUT")
+    .uri("/v1/cache/bundle")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_TYPE, "application/x-tar")
+    .body(Body::from(archive))
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let mut result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  result["stored"]
+    .as_array_mut()
+    .unwrap()
+    .sort_by_key(|hash| hash.to_string());
+  assert_eq!(
+    result,
+    serde_json::json!({
+      "stored": ["bundle-up-a", "bundle-up-b"],
+      "existing": ["bundle-existing"],
+      "rejected": ["bundle-up-a"],
+      "failed": [],
+    })
+  );
+
+  for (hash, expected) in [("bundle-up-b", "second"), ("bundle-existing", "old")] {
+    let request = Request::builder()
+      .method("GET")
+      .uri(format!("/v1/cache/{}", hash))
+      .header(header::AUTHORIZATION, "Bearer test-token-rw")
+      .body(Body::empty())
+      .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    assert_eq!(body.as_ref(), expected.as_bytes());
+  }
+
+  println!("✓ Bundle entries stored individually");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_base_path() {
+  let minio = MinioTestContainer::start().await;
+  let (app_state, _bucket) = create_test_state(&minio).await;
+  let app_state = app_state.with_base_path("/nx-cache");
+  let app = create_router(&app_state).with_state(app_state);
+
+  let data = b"prefixed artifact";
+  let request = Request::builder()
+    .method("PUT")
+    .uri("/nx-cache/v1/cache/prefixed-hash")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .header(header::CONTENT_LENGTH, data.len())
+    .body(Body::from(data.to_vec()))
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/nx-cache/v1/cache/prefixed-hash")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  // Routes are no longer served without the prefix
+  let request = Request::builder()
+    .method("GET")
+    .uri("/health")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/nx-cache/openapi.json")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(json["servers"][0]["url"], "/nx-cache");
+
+  println!("✓ Routes served under the base path");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_verbose_healthz_reports_buckets() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/readyz")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/healthz?verbose=1")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let bucket = &json["buckets"][0];
+  assert_eq!(bucket["probe"]["reachable"], true);
+  assert_eq!(bucket["config"]["backend"], "s3");
+  assert!(bucket["config"].get("secretAccessKey").is_none());
+
+  println!("✓ Verbose health endpoint reports bucket status");
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_large_artifact_streaming() {
   let minio = MinioTestContainer::start().await;
@@ -478,3 +1084,41 @@ async fn test_large_artifact_streaming() {
 
   println!("✓ Large artifact (5MB) streamed successfully");
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_chunked_upload_without_content_length() {
+  let minio = MinioTestContainer::start().await;
+  let (app, _bucket) = create_test_app(&minio).await;
+
+  // Larger than the default spill threshold, so the body goes through a temporary file
+  let data: Vec<u8> = (0..9 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+  let chunks: Vec<Result<Vec<u8>, std::io::Error>> = data
+    .chunks(64 * 1024)
+    .map(|chunk| Ok(chunk.to_vec()))
+    .collect();
+
+  let request = Request::builder()
+    .method("PUT")
+    .uri("/v1/cache/chunked-artifact")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .body(Body::from_stream(tokio_stream::iter(chunks)))
+    .unwrap();
+  assert!(!request.headers().contains_key(header::CONTENT_LENGTH));
+  let response = app.clone().oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let request = Request::builder()
+    .method("GET")
+    .uri("/v1/cache/chunked-artifact")
+    .header(header::AUTHORIZATION, "Bearer test-token-rw")
+    .body(Body::empty())
+    .unwrap();
+  let response = app.oneshot(request).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  assert_eq!(body.as_ref(), data.as_slice());
+
+  println!("✓ Chunked upload without Content-Length stored");
+}
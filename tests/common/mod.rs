@@ -26,18 +26,22 @@ use tracing::{debug, info, warn};
 use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, SanType};
 use tempfile::TempDir;
 
-use testcontainers::runners::AsyncRunner;
-use testcontainers::{core::ContainerPort, core::ExecCommand, core::Mount, GenericImage, ImageExt};
+use testcontainers::runners::{AsyncBuilder, AsyncRunner};
+use testcontainers::{
+  core::BuildImageOptions, core::ContainerPort, core::ExecCommand, core::Host, core::Mount,
+  GenericBuildableImage, GenericImage, ImageExt,
+};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 
 use nx_cache_server::domain::config::ResolvedBucketConfig;
-use nx_cache_server::domain::storage::{StorageError, StorageProvider};
+use nx_cache_server::domain::storage::{boxed_reader_stream, StorageError, StorageProvider};
 use nx_cache_server::infra::nx_cache_store::NxCacheStorage;
 
 #[derive(Debug, Deserialize)]
 struct TestcontainersConfig {
   images: TestcontainersImages,
+  pants: PantsConfig,
 }
 
 #[derive(Debug, Deserialize)]
@@ -46,6 +50,13 @@ struct TestcontainersImages {
   garage: ImageConfig,
   seaweedfs: ImageConfig,
   rustfs: ImageConfig,
+  pants: ImageConfig,
+}
+
+/// Pants release installed into the Pants client image
+#[derive(Debug, Deserialize)]
+struct PantsConfig {
+  version: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -68,6 +79,10 @@ fn load_testcontainers_config() -> &'static TestcontainersConfig {
     validate_image_config(&config.images.garage, "garage");
     validate_image_config(&config.images.seaweedfs, "seaweedfs");
     validate_image_config(&config.images.rustfs, "rustfs");
+    validate_image_config(&config.images.pants, "pants");
+    if config.pants.version.trim().is_empty() {
+      panic!("testcontainers.toml pants version must be set");
+    }
     config
   })
 }
@@ -445,14 +460,12 @@ impl MinioTestContainer {
       bucket_name,
       access_key_id: Some(self.access_key.clone()),
       secret_access_key: Some(self.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(self.endpoint_url()),
       tls_ca_file: self._tls.as_ref().map(|tls| tls.cert_path_string()),
       insecure_tls: if self.use_https { Some(true) } else { None },
       force_path_style: true,
-      sse: None,
-      timeout: 30,
+      ..Default::default()
     }
   }
 
@@ -679,14 +692,12 @@ impl RustfsTestContainer {
       bucket_name,
       access_key_id: Some(self.access_key.clone()),
       secret_access_key: Some(self.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(self.endpoint_url()),
       tls_ca_file: self._tls.as_ref().map(|tls| tls.cert_path_string()),
       insecure_tls: if self.use_https { Some(true) } else { None },
       force_path_style: true,
-      sse: None,
-      timeout: 30,
+      ..Default::default()
     }
   }
 
@@ -942,14 +953,12 @@ impl SeaweedfsTestContainer {
       bucket_name,
       access_key_id: Some(self.access_key.clone()),
       secret_access_key: Some(self.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(self.endpoint_url()),
       tls_ca_file: self._tls.as_ref().map(|tls| tls.cert_path_string()),
       insecure_tls: if self.use_https { Some(true) } else { None },
       force_path_style: true,
-      sse: None,
-      timeout: 30,
+      ..Default::default()
     }
   }
 
@@ -1080,7 +1089,7 @@ impl SeaweedfsTestContainer {
 
     for attempt in 0..retry.retries {
       let cursor = std::io::Cursor::new(data.clone());
-      let reader_stream = tokio_util::io::ReaderStream::new(cursor);
+      let reader_stream = boxed_reader_stream(cursor);
 
       match storage
         .store(object_name, reader_stream, Some(data_len))
@@ -1210,14 +1219,10 @@ impl LocalstackTestContainer {
       bucket_name,
       access_key_id: Some(self.access_key.clone()),
       secret_access_key: Some(self.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(self.endpoint_url()),
-      tls_ca_file: None,
-      insecure_tls: None,
       force_path_style: true,
-      sse: None,
-      timeout: 30,
+      ..Default::default()
     }
   }
 
@@ -1428,14 +1433,10 @@ impl S3MockTestContainer {
       bucket_name,
       access_key_id: Some(self.access_key.clone()),
       secret_access_key: Some(self.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(self.endpoint_url()),
-      tls_ca_file: None,
-      insecure_tls: None,
       force_path_style: true,
-      sse: None,
-      timeout: 30,
+      ..Default::default()
     }
   }
 
@@ -1647,14 +1648,10 @@ impl GoFakeS3TestContainer {
       bucket_name,
       access_key_id: Some(self.access_key.clone()),
       secret_access_key: Some(self.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(self.endpoint_url()),
-      tls_ca_file: None,
-      insecure_tls: None,
       force_path_style: true,
-      sse: None,
-      timeout: 30,
+      ..Default::default()
     }
   }
 
@@ -1927,14 +1924,10 @@ metrics_token = "test-metrics-token"
       bucket_name,
       access_key_id: Some(self.access_key.clone()),
       secret_access_key: Some(self.secret_key.clone()),
-      session_token: None,
       region: Some("garage".to_string()),
       endpoint_url: Some(self.endpoint_url()),
-      tls_ca_file: None,
-      insecure_tls: None,
       force_path_style: true,
-      sse: None,
-      timeout: 30,
+      ..Default::default()
     }
   }
 
@@ -2282,6 +2275,115 @@ metrics_token = "test-metrics-token"
   }
 }
 
+/// Pants release installed into the Pants client container
+#[allow(dead_code)]
+pub fn pants_version() -> &'static str {
+  &load_testcontainers_config().pants.version
+}
+
+/// Python container with a Pants client, for conformance tests of the remote cache
+///
+/// The image is built from `tests/pants/Dockerfile` with the versions pinned in
+/// `testcontainers.toml` the first time it is needed and reused afterwards. The workspace is bind-mounted read-only at `/src` and copied to a fresh `/repo` for every
+/// run, so runs share nothing but the remote cache. `host.docker.internal` resolves to the
+/// host running the server under test.
+#[allow(dead_code)]
+pub struct PantsTestContainer {
+  pub container: testcontainers::ContainerAsync<GenericImage>,
+}
+
+/// Exit code and combined output of a command run in a test container
+#[allow(dead_code)]
+pub struct ExecOutput {
+  pub exit_code: Option<i64>,
+  pub output: String,
+}
+
+impl PantsTestContainer {
+  #[allow(dead_code)]
+  pub async fn start(workspace: &Path) -> Self {
+    Self::start_result(workspace)
+      .await
+      .expect("Failed to start Pants container")
+  }
+
+  #[allow(dead_code)]
+  pub async fn start_result(workspace: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+    let config = load_testcontainers_config();
+    let base = &config.images.pants;
+    let version = pants_version();
+
+    let dockerfile = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/pants/Dockerfile");
+    let image = GenericBuildableImage::new(
+      "nx-cache-server-pants",
+      format!("{}-python{}", version, base.tag),
+    )
+    .with_dockerfile(dockerfile)
+    .build_image_with(
+      BuildImageOptions::new()
+        .with_skip_if_exists(true)
+        .with_build_arg("PYTHON_IMAGE", format!("{}:{}", base.repository, base.tag))
+        .with_build_arg("PANTS_VERSION", version),
+    )
+    .await
+    .map_err(|e| box_err(format!("Failed to build Pants {} image: {}", version, e)))?;
+
+    let container = image
+      .with_cmd(["sleep", "infinity"])
+      .with_host("host.docker.internal", Host::HostGateway)
+      .with_mount(Mount::bind_mount(
+        workspace.to_string_lossy().to_string(),
+        "/src",
+      ))
+      .start()
+      .await
+      .map_err(|e| box_err(format!("Failed to start Pants container: {}", e)))?;
+
+    info!(service = "pants", version, "Pants container ready");
+    Ok(Self { container })
+  }
+
+  /// Run a shell command in the container, collecting stdout and stderr
+  pub async fn exec(&self, command: &str) -> TestResult<ExecOutput> {
+    let mut result = self
+      .container
+      .exec(ExecCommand::new(["sh", "-c", command]))
+      .await
+      .map_err(|e| box_err(format!("Failed to exec in Pants container: {}", e)))?;
+    let stdout = result
+      .stdout_to_vec()
+      .await
+      .map_err(|e| box_err(format!("Failed to read Pants stdout: {}", e)))?;
+    let stderr = result
+      .stderr_to_vec()
+      .await
+      .map_err(|e| box_err(format!("Failed to read Pants stderr: {}", e)))?;
+    let exit_code = result
+      .exit_code()
+      .await
+      .map_err(|e| box_err(format!("Failed to read Pants exit code: {}", e)))?;
+    Ok(ExecOutput {
+      exit_code,
+      output: format!(
+        "{}{}",
+        String::from_utf8_lossy(&stdout),
+        String::from_utf8_lossy(&stderr)
+      ),
+    })
+  }
+
+  /// Run `pants` with `args` in a fresh copy of the workspace
+  #[allow(dead_code)]
+  pub async fn pants(&self, args: &str) -> TestResult<ExecOutput> {
+    self
+      .exec(&format!(
+        "rm -rf /repo && cp -r /src /repo && cd /repo && pants {}",
+        args
+      ))
+      .await
+  }
+}
+
 /// Helper to generate unique bucket names for tests
 pub fn unique_bucket_name(prefix: &str) -> String {
   let timestamp = SystemTime::now()
@@ -8,9 +8,8 @@ use std::io::Cursor;
 
 use tokio::io::AsyncReadExt;
 use tokio::time::Duration;
-use tokio_util::io::ReaderStream;
 
-use nx_cache_server::domain::storage::{StorageError, StorageProvider};
+use nx_cache_server::domain::storage::{boxed_reader_stream, StorageError, StorageProvider};
 
 async fn store_with_retry<S: StorageProvider>(
   storage: &S,
@@ -22,7 +21,7 @@ async fn store_with_retry<S: StorageProvider>(
 ) -> Result<(), StorageError> {
   for attempt in 0..retries {
     let cursor = Cursor::new(data.to_vec());
-    let reader_stream = ReaderStream::new(cursor);
+    let reader_stream = boxed_reader_stream(cursor);
 
     match storage
       .store(hash, reader_stream, Some(content_length))
@@ -133,7 +132,7 @@ where
   }
 
   let cursor = Cursor::new(test_data.to_vec());
-  let reader_stream = ReaderStream::new(cursor);
+  let reader_stream = boxed_reader_stream(cursor);
   let result = storage
     .store(test_hash, reader_stream, Some(test_data.len() as u64))
     .await;
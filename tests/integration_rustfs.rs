@@ -7,13 +7,11 @@ use common::{RustfsTestContainer, SSE_C_KEY};
 use minio::s3::response_traits::HasS3Fields;
 use minio::s3::types::S3Api;
 use nx_cache_server::domain::config::ResolvedSseConfig;
-use nx_cache_server::domain::storage::StorageProvider;
+use nx_cache_server::domain::storage::{boxed_reader_stream, StorageProvider};
 use nx_cache_server::infra::nx_cache_store::NxCacheStorage;
 use std::io::Cursor;
 use tokio::io::AsyncReadExt;
 
-use tokio_util::io::ReaderStream;
-
 /// Integration test that verifies NxCacheStorage works with RustFS (S3-compatible)
 #[tokio::test(flavor = "multi_thread")]
 async fn test_rustfs_integration_store_and_retrieve() {
@@ -129,7 +127,7 @@ async fn test_rustfs_sse_kms_store_and_retrieve() {
   let test_data_len = test_data.len() as u64;
 
   let cursor = Cursor::new(test_data.clone());
-  let reader_stream = ReaderStream::new(cursor);
+  let reader_stream = boxed_reader_stream(cursor);
 
   storage
     .store(test_hash, reader_stream, Some(test_data_len))
@@ -22,13 +22,13 @@ mod common;
 
 use std::io::Cursor;
 use tokio::io::AsyncReadExt;
-use tokio_util::io::ReaderStream;
 
 use common::{unique_bucket_name, MinioTestContainer};
 use nx_cache_server::domain::config::{
-  ResolvedBucketConfig, ResolvedConfig, ResolvedServiceAccessToken,
+  CacheControlConfig, ResolvedBucketConfig, ResolvedConfig, ResolvedServiceAccessToken,
+  ShutdownConfig,
 };
-use nx_cache_server::domain::storage::StorageProvider;
+use nx_cache_server::domain::storage::{boxed_reader_stream, StorageError, StorageProvider};
 use nx_cache_server::infra::multi_storage::MultiStorageRouter;
 
 #[tokio::test(flavor = "multi_thread")]
@@ -65,7 +65,7 @@ async fn test_basic_store_and_retrieve() {
   // Store data
   println!("Storing object...");
   let cursor = Cursor::new(data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   match storage.store(hash, stream, Some(data.len() as u64)).await {
     Ok(_) => println!("Store succeeded"),
     Err(e) => {
@@ -105,7 +105,7 @@ async fn test_multiple_objects() {
 
   for (hash, data) in &objects {
     let cursor = Cursor::new(data.as_bytes().to_vec());
-    let stream = ReaderStream::new(cursor);
+    let stream = boxed_reader_stream(cursor);
     storage
       .store(hash, stream, Some(data.len() as u64))
       .await
@@ -158,7 +158,7 @@ async fn test_storage_errors() {
 
   // First store should succeed
   let cursor = Cursor::new(data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   storage
     .store(hash, stream, Some(data.len() as u64))
     .await
@@ -166,7 +166,7 @@ async fn test_storage_errors() {
 
   // Second store should fail
   let cursor = Cursor::new(data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   let result = storage.store(hash, stream, Some(data.len() as u64)).await;
 
   assert!(result.is_err());
@@ -178,6 +178,37 @@ async fn test_storage_errors() {
   }
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_store_same_hash() {
+  let minio = MinioTestContainer::start().await;
+
+  let bucket_name = unique_bucket_name("race-test");
+  let storage = minio.create_storage(&bucket_name).await.unwrap();
+
+  let hash = "concurrent";
+  let data = vec![7u8; 64 * 1024];
+
+  let store = |payload: Vec<u8>| {
+    let storage = &storage;
+    async move {
+      let len = payload.len() as u64;
+      storage
+        .store(hash, boxed_reader_stream(Cursor::new(payload)), Some(len))
+        .await
+    }
+  };
+
+  let (first, second) = tokio::join!(store(data.clone()), store(data.clone()));
+  let successes = [&first, &second].iter().filter(|r| r.is_ok()).count();
+
+  // Conditional writes guarantee exactly one winner, the other sees AlreadyExists
+  assert_eq!(successes, 1, "results: {:?} / {:?}", first, second);
+  assert!(matches!(
+    (&first, &second),
+    (Err(StorageError::AlreadyExists), Ok(())) | (Ok(()), Err(StorageError::AlreadyExists))
+  ));
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_streaming_large_file() {
   let minio = MinioTestContainer::start().await;
@@ -193,7 +224,7 @@ async fn test_streaming_large_file() {
   println!("Storing {}MB file...", size / 1024 / 1024);
 
   let cursor = Cursor::new(data.clone());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   storage
     .store(hash, stream, Some(size as u64))
     .await
@@ -256,14 +287,11 @@ async fn test_multiple_namespaces_in_one_bucket() {
       bucket_name: bucket_name.clone(),
       access_key_id: Some(minio.access_key.clone()),
       secret_access_key: Some(minio.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(minio.endpoint_url()),
-      tls_ca_file: None,
-      insecure_tls: None,
       force_path_style: true,
-      sse: None,
       timeout: 60,
+      ..Default::default()
     }],
     service_access_tokens: vec![
       ResolvedServiceAccessToken {
@@ -271,28 +299,53 @@ async fn test_multiple_namespaces_in_one_bucket() {
         bucket: bucket_name.clone(),
         prefix: "/ci".to_string(),
         access_token: "token-ci".to_string(),
+        ..Default::default()
       },
       ResolvedServiceAccessToken {
         name: "dev-team".to_string(),
         bucket: bucket_name.clone(),
         prefix: "/dev".to_string(),
         access_token: "token-dev".to_string(),
+        ..Default::default()
       },
       ResolvedServiceAccessToken {
         name: "prod-team".to_string(),
         bucket: bucket_name.clone(),
         prefix: "/prod".to_string(),
         access_token: "token-prod".to_string(),
+        ..Default::default()
       },
       ResolvedServiceAccessToken {
         name: "no-prefix-team".to_string(),
         bucket: bucket_name.clone(),
         prefix: "".to_string(),
         access_token: "token-root".to_string(),
+        ..Default::default()
       },
     ],
     port: 3000,
     debug: true,
+    shutdown: ShutdownConfig::default(),
+    cache_control: CacheControlConfig::default(),
+    normalize_paths: false,
+    in_flight_wait_seconds: 0,
+    recent_errors_capacity: 100,
+    mirror: None,
+    manifest: None,
+    affinity: None,
+    task_metadata: None,
+    jwt: Vec::new(),
+    token_minting: None,
+    token_revocation: None,
+    hash_validation: Default::default(),
+    audit_namespaces: false,
+    strict_nx_spec: false,
+    json_errors: false,
+    allow_empty_uploads: false,
+    base_path: None,
+    transport_compression: None,
+    tus: None,
+    grpc: None,
   };
 
   // Create MultiStorageRouter from config
@@ -318,7 +371,7 @@ async fn test_multiple_namespaces_in_one_bucket() {
   // CI namespace
   println!("Storing in /ci namespace...");
   let cursor = Cursor::new(ci_data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   router
     .store_with_token("token-ci", hash, stream, Some(ci_data.len() as u64))
     .await
@@ -327,7 +380,7 @@ async fn test_multiple_namespaces_in_one_bucket() {
   // Dev namespace
   println!("Storing in /dev namespace...");
   let cursor = Cursor::new(dev_data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   router
     .store_with_token("token-dev", hash, stream, Some(dev_data.len() as u64))
     .await
@@ -336,7 +389,7 @@ async fn test_multiple_namespaces_in_one_bucket() {
   // Prod namespace
   println!("Storing in /prod namespace...");
   let cursor = Cursor::new(prod_data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   router
     .store_with_token("token-prod", hash, stream, Some(prod_data.len() as u64))
     .await
@@ -345,7 +398,7 @@ async fn test_multiple_namespaces_in_one_bucket() {
   // Root namespace (no prefix)
   println!("Storing in root namespace (no prefix)...");
   let cursor = Cursor::new(root_data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   router
     .store_with_token("token-root", hash, stream, Some(root_data.len() as u64))
     .await
@@ -422,7 +475,7 @@ async fn test_multiple_namespaces_in_one_bucket() {
   let ci_exclusive_data = b"Only in CI";
 
   let cursor = Cursor::new(ci_exclusive_data.to_vec());
-  let stream = ReaderStream::new(cursor);
+  let stream = boxed_reader_stream(cursor);
   router
     .store_with_token(
       "token-ci",
@@ -18,11 +18,11 @@ use axum::{
 };
 use common::{unique_bucket_name, MinioTestContainer};
 use nx_cache_server::domain::config::{
-  ResolvedBucketConfig, ResolvedConfig, ResolvedServiceAccessToken,
+  CacheControlConfig, ResolvedBucketConfig, ResolvedConfig, ResolvedServiceAccessToken,
+  ShutdownConfig,
 };
 use nx_cache_server::infra::multi_storage::MultiStorageRouter;
 use nx_cache_server::server::{create_router, AppState};
-use std::sync::Arc;
 use tower::util::ServiceExt;
 
 /// Helper to create a test app with MinIO backend
@@ -40,32 +40,49 @@ async fn create_test_app(minio: &MinioTestContainer) -> (Router, String) {
       bucket_name: bucket_name.clone(),
       access_key_id: Some(minio.access_key.clone()),
       secret_access_key: Some(minio.secret_key.clone()),
-      session_token: None,
       region: Some("us-east-1".to_string()),
       endpoint_url: Some(minio.endpoint_url()),
-      tls_ca_file: None,
-      insecure_tls: None,
       force_path_style: true,
-      sse: None,
       timeout: 60,
+      ..Default::default()
     }],
     service_access_tokens: vec![ResolvedServiceAccessToken {
       name: "test-token".to_string(),
       bucket: bucket_name.clone(),
       prefix: "/test".to_string(),
       access_token: "valid-test-token".to_string(),
+      ..Default::default()
     }],
     port: 3000,
     debug: true,
+    shutdown: ShutdownConfig::default(),
+    cache_control: CacheControlConfig::default(),
+    normalize_paths: false,
+    in_flight_wait_seconds: 0,
+    recent_errors_capacity: 100,
+    mirror: None,
+    manifest: None,
+    affinity: None,
+    task_metadata: None,
+    jwt: Vec::new(),
+    token_minting: None,
+    token_revocation: None,
+    hash_validation: Default::default(),
+    audit_namespaces: false,
+    strict_nx_spec: false,
+    json_errors: false,
+    allow_empty_uploads: false,
+    base_path: None,
+    transport_compression: None,
+    tus: None,
+    grpc: None,
   };
 
   let storage = MultiStorageRouter::from_config(&resolved_config)
     .await
     .expect("Failed to create MultiStorageRouter");
 
-  let app_state = AppState {
-    storage: Arc::new(storage),
-  };
+  let app_state = AppState::new(storage);
 
   let app = create_router(&app_state).with_state(app_state);
 
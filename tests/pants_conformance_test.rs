@@ -0,0 +1,160 @@
+//! Conformance tests of the gRPC remote cache against a real Pants client
+//!
+//! Pants talks to remote caches through the remote execution API only. These tests run
+//! Pants in a container against the gRPC listener and check that:
+//! - a first run writes its process results to the cache
+//! - a second run in a fresh workspace, without a local cache, is served from it
+//! - a wrong token is rejected without failing the build
+
+mod common;
+
+use common::{pants_version, PantsTestContainer};
+use nx_cache_server::domain::config::Config;
+use nx_cache_server::infra::multi_storage::MultiStorageRouter;
+use nx_cache_server::server::grpc::{self, RemoteCache};
+use nx_cache_server::server::AppState;
+use std::path::Path;
+use tokio::net::TcpListener;
+
+const TOKEN: &str = "pants-conformance-token";
+
+/// Start the gRPC listener on a free port reachable from containers, backed by `root`
+async fn start_server(root: &Path) -> (u16, tokio::task::JoinHandle<()>) {
+  let yaml = format!(
+    "buckets:\n  - name: local\n    type: fs\n    bucketName: local\n    path: {}\nserviceAccessTokens:\n  - name: pants\n    bucket: local\n    prefix: /pants-ci\n    accessToken: {}\n",
+    root.display(),
+    TOKEN
+  );
+  let config = Config::from_yaml_str(&yaml)
+    .expect("Failed to parse config")
+    .resolve_env_vars()
+    .expect("Failed to resolve config");
+  let storage = MultiStorageRouter::from_config(&config)
+    .await
+    .expect("Failed to create MultiStorageRouter");
+  let state = AppState::new(storage);
+
+  let listener = TcpListener::bind("0.0.0.0:0")
+    .await
+    .expect("Failed to bind gRPC listener");
+  let port = listener.local_addr().unwrap().port();
+  let cache = RemoteCache::new(move || state.clone());
+  let server = tokio::spawn(async move {
+    grpc::serve(listener, cache, std::future::pending())
+      .await
+      .expect("gRPC server failed");
+  });
+  (port, server)
+}
+
+/// Workspace with one cacheable process, pointed at the server on `port`
+fn write_workspace(dir: &Path, port: u16) {
+  let version = pants_version();
+  std::fs::write(
+    dir.join("pants.toml"),
+    format!(
+      r#"[GLOBAL]
+pants_version = "{version}"
+backend_packages = ["pants.backend.shell"]
+pantsd = false
+local_cache = false
+remote_cache_read = true
+remote_cache_write = true
+remote_store_address = "grpc://host.docker.internal:{port}"
+"#
+    ),
+  )
+  .unwrap();
+  std::fs::write(
+    dir.join("BUILD"),
+    r#"shell_command(
+    name="hello",
+    command="echo conformance > hello.txt",
+    tools=["echo"],
+    output_files=["hello.txt"],
+)
+"#,
+  )
+  .unwrap();
+}
+
+/// Pants arguments for one build, authenticating with `token`
+fn build_args(token: &str) -> String {
+  format!(
+    "--remote-store-headers=\"{{'authorization': 'Bearer {}'}}\" --stats-log export-codegen //:hello",
+    token
+  )
+}
+
+/// Value of a counter in the output of `--stats-log`, 0 if it was not reported
+fn counter(output: &str, name: &str) -> u64 {
+  output
+    .lines()
+    .filter_map(|line| line.trim().strip_prefix(name)?.strip_prefix(':'))
+    .filter_map(|value| value.trim().parse().ok())
+    .next()
+    .unwrap_or(0)
+}
+
+/// Number of action cache entries the server stored
+fn action_cache_entries(root: &Path) -> usize {
+  std::fs::read_dir(root.join("pants-ci/bazel/ac"))
+    .map(|entries| entries.count())
+    .unwrap_or(0)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_pants_reads_and_writes_remote_cache() {
+  let _ = tracing_subscriber::fmt()
+    .with_max_level(tracing::Level::INFO)
+    .with_test_writer()
+    .try_init();
+
+  let storage = tempfile::tempdir().unwrap();
+  let workspace = tempfile::tempdir().unwrap();
+  let (port, server) = start_server(storage.path()).await;
+  write_workspace(workspace.path(), port);
+  let pants = PantsTestContainer::start(workspace.path()).await;
+
+  // First run: a miss that is written back
+  let first = pants.pants(&build_args(TOKEN)).await.unwrap();
+  assert_eq!(first.exit_code, Some(0), "{}", first.output);
+  assert!(
+    counter(&first.output, "remote_cache_write_successes") >= 1,
+    "{}",
+    first.output
+  );
+  assert!(action_cache_entries(storage.path()) >= 1);
+
+  // Second run in a fresh workspace: served from the remote cache
+  let second = pants.pants(&build_args(TOKEN)).await.unwrap();
+  assert_eq!(second.exit_code, Some(0), "{}", second.output);
+  assert!(
+    counter(&second.output, "remote_cache_requests_cached") >= 1,
+    "{}",
+    second.output
+  );
+  assert_eq!(
+    counter(&second.output, "remote_cache_read_errors"),
+    0,
+    "{}",
+    second.output
+  );
+
+  // A wrong token never hits, but remote cache errors do not fail the build
+  let rejected = pants.pants(&build_args("wrong-token")).await.unwrap();
+  assert_eq!(rejected.exit_code, Some(0), "{}", rejected.output);
+  assert_eq!(
+    counter(&rejected.output, "remote_cache_requests_cached"),
+    0,
+    "{}",
+    rejected.output
+  );
+  assert!(
+    counter(&rejected.output, "remote_cache_read_errors") >= 1,
+    "{}",
+    rejected.output
+  );
+
+  server.abort();
+}